@@ -0,0 +1,187 @@
+use crate::config::{AbrConfig, AbrRendition, StreamConfig, StreamMode};
+use serde::Serialize;
+use std::path::Path;
+
+/// 实际生成的编码布局，随流详情返回
+#[derive(Debug, Clone, Serialize)]
+pub struct Layout {
+    /// 源视频的解码次数：各档共用一次解码
+    pub decodes: u32,
+    /// 进程中的视频编码路数 (各档之外，transcode 模式的主输出与水印版本也各占一路)
+    pub video_encodes: usize,
+    pub encoder: String,
+    /// 传给 FFmpeg 的 `-filter_complex`
+    pub filter_complex: String,
+    pub renditions: Vec<RenditionLayout>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RenditionLayout {
+    pub name: String,
+    pub height: u32,
+    pub bitrate_kbps: u32,
+    pub playlist: String,
+}
+
+/// 一档输出的播放列表文件名
+pub fn playlist(rendition: &AbrRendition) -> String {
+    format!("abr_{}.m3u8", rendition.name)
+}
+
+/// 解码后的画面经 `split` 复制为 N 路，分别缩放到各档高度 (输出标签 `[abr_N]`)
+pub fn filter(abr: &AbrConfig) -> String {
+    let mut graph = format!("[0:v:0]split={}", abr.renditions.len());
+    for i in 0..abr.renditions.len() {
+        graph.push_str(&format!("[abr_in{}]", i));
+    }
+    for (i, rendition) in abr.renditions.iter().enumerate() {
+        graph.push_str(&format!(
+            ";[abr_in{}]scale=-2:{}[abr_{}]",
+            i, rendition.height, i
+        ));
+    }
+    graph
+}
+
+/// 各档的 HLS 输出 (`-filter_complex` 由调用方放在所有输出之前)
+///
+/// 各档强制在相同时间点插入关键帧，切片边界一致，播放器可在切片之间切换档位；
+/// 配置了多语言音轨时各档只含视频，音频由 master 播放列表中的音轨组提供
+pub fn output_args(
+    cfg: &StreamConfig,
+    abr: &AbrConfig,
+    output_dir: &Path,
+    key_args: &[String],
+    hls_flags: &str,
+    loudnorm: Option<&String>,
+) -> Vec<String> {
+    let duration = cfg.hls.segment_duration_sec;
+    let mut args = Vec::new();
+    for (i, rendition) in abr.renditions.iter().enumerate() {
+        args.extend(key_args.iter().cloned());
+        args.extend([
+            "-map".to_string(),
+            format!("[abr_{}]", i),
+            "-c:v".to_string(),
+            abr.encoder.clone(),
+        ]);
+        if !abr.preset.is_empty() {
+            args.extend(["-preset".to_string(), abr.preset.clone()]);
+        }
+        args.extend([
+            "-b:v".to_string(),
+            format!("{}k", rendition.bitrate_kbps),
+            "-maxrate".to_string(),
+            format!("{}k", rendition.bitrate_kbps),
+            "-bufsize".to_string(),
+            format!("{}k", rendition.bitrate_kbps * 2),
+            "-force_key_frames".to_string(),
+            format!("expr:gte(t,n_forced*{})", duration),
+        ]);
+        if cfg.audio_tracks.is_empty() {
+            args.extend([
+                "-map".to_string(),
+                "0:a:0?".to_string(),
+                "-c:a".to_string(),
+                "aac".to_string(),
+                "-b:a".to_string(),
+                format!("{}k", abr.audio_bitrate_kbps),
+            ]);
+            if let Some(filter) = loudnorm {
+                args.extend(["-af".to_string(), filter.clone()]);
+            }
+        }
+        args.extend([
+            "-f".to_string(),
+            "hls".to_string(),
+            "-hls_time".to_string(),
+            duration.to_string(),
+            "-hls_list_size".to_string(),
+            cfg.hls.list_size.to_string(),
+            "-hls_flags".to_string(),
+            hls_flags.to_string(),
+            "-hls_segment_filename".to_string(),
+            output_dir
+                .join(format!("abr_{}_%d.ts", rendition.name))
+                .to_string_lossy()
+                .to_string(),
+            output_dir
+                .join(playlist(rendition))
+                .to_string_lossy()
+                .to_string(),
+        ]);
+    }
+    args
+}
+
+/// 流的 ABR 编码布局 (未配置 ABR 时为 None)
+pub fn layout(cfg: &StreamConfig) -> Option<Layout> {
+    let abr = cfg.abr.as_ref()?;
+    let main = usize::from(cfg.mode == StreamMode::Transcode);
+    let watermark = if cfg.watermark.is_some() { 2 } else { 0 };
+    Some(Layout {
+        decodes: 1,
+        video_encodes: abr.renditions.len() + main + watermark,
+        encoder: abr.encoder.clone(),
+        filter_complex: filter(abr),
+        renditions: abr
+            .renditions
+            .iter()
+            .map(|r| RenditionLayout {
+                name: r.name.clone(),
+                height: r.height,
+                bitrate_kbps: r.bitrate_kbps,
+                playlist: playlist(r),
+            })
+            .collect(),
+    })
+}
+
+/// 校验 ABR 配置
+pub fn validate(stream: &StreamConfig, abr: &AbrConfig) -> anyhow::Result<()> {
+    if stream.is_proxied() {
+        anyhow::bail!("Stream [{}] cannot publish abr in proxy mode", stream.name);
+    }
+    if abr.renditions.is_empty() {
+        anyhow::bail!("Stream [{}] abr needs at least one rendition", stream.name);
+    }
+    if abr.encoder.is_empty() || abr.encoder.contains(char::is_whitespace) {
+        anyhow::bail!("Stream [{}] has an invalid abr.encoder", stream.name);
+    }
+    if stream.hls.segment_duration_sec == 0 {
+        anyhow::bail!(
+            "Stream [{}] has a zero hls.segment_duration_sec",
+            stream.name
+        );
+    }
+    for (i, rendition) in abr.renditions.iter().enumerate() {
+        let safe = !rendition.name.is_empty()
+            && rendition
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+        if !safe {
+            anyhow::bail!(
+                "Stream [{}] abr rendition name [{}] contains unsupported characters",
+                stream.name,
+                rendition.name
+            );
+        }
+        if abr.renditions[..i].iter().any(|r| r.name == rendition.name) {
+            anyhow::bail!(
+                "Stream [{}] has duplicate abr rendition [{}]",
+                stream.name,
+                rendition.name
+            );
+        }
+        // 4:2:0 编码要求偶数尺寸
+        if rendition.height == 0 || rendition.height % 2 != 0 || rendition.bitrate_kbps == 0 {
+            anyhow::bail!(
+                "Stream [{}] abr rendition [{}] needs an even, nonzero height and a nonzero bitrate_kbps",
+                stream.name,
+                rendition.name
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use crate::config::{AgentConfig, AppConfig, StreamConfig};
+use crate::drain;
+use crate::http_client;
+use crate::snapshot::{self, ImportResult};
+use crate::state::SharedState;
+use crate::system::SystemStats;
+use crate::updater;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// 控制器下发的指令
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Command {
+    /// 下发完整的期望流列表，替换本地流配置
+    AssignStreams {
+        #[serde(default)]
+        revision: u64,
+        streams: Vec<StreamConfig>,
+    },
+    /// 自更新 (地址可省略，默认取本地 update 配置)
+    Update(updater::Source),
+}
+
+/// 启动集群代理
+///
+/// # 任务流程：
+/// - 向控制器注册节点，失败时按指数退避重试
+/// - 后台定期发送心跳 (节点资源与流状态)
+/// - 长轮询控制器指令通道，应用下发的流分配
+pub async fn start_agent(state: SharedState, cfg: AgentConfig) {
+    let node_id = cfg
+        .node_id
+        .clone()
+        .or_else(|| sys_info::hostname().ok())
+        .unwrap_or_else(|| "vtx-link".to_string());
+    let base = cfg.controller_url.trim_end_matches('/').to_string();
+    let revision = Arc::new(AtomicU64::new(0));
+
+    register(&state, &cfg, &base, &node_id).await;
+
+    tokio::spawn(heartbeat_loop(
+        state.clone(),
+        cfg.clone(),
+        base.clone(),
+        node_id.clone(),
+        revision.clone(),
+    ));
+
+    command_loop(state, cfg, base, node_id, revision).await;
+}
+
+/// 注册节点，直到控制器接受为止
+async fn register(state: &SharedState, cfg: &AgentConfig, base: &str, node_id: &str) {
+    let url = format!("{}/api/nodes/register", base);
+    let mut backoff = 2;
+
+    loop {
+        let config = state.config();
+        let payload = serde_json::json!({
+            "node_id": node_id,
+            "version": env!("CARGO_PKG_VERSION"),
+            "listen": config.server.listen,
+            "streams": config.streams.iter().map(|s| &s.name).collect::<Vec<_>>(),
+        });
+
+        match http_client::send_json(
+            "POST",
+            &url,
+            &payload,
+            cfg.token.as_deref(),
+            Duration::from_secs(10),
+        )
+        .await
+        {
+            Ok(res) if res.is_success() => {
+                info!("Agent: registered with controller as [{}]", node_id);
+                return;
+            }
+            Ok(res) => warn!("Agent: registration rejected with status {}", res.status),
+            Err(e) => warn!("Agent: registration failed: {}", e),
+        }
+
+        tokio::time::sleep(Duration::from_secs(backoff)).await;
+        backoff = std::cmp::min(backoff * 2, 60);
+    }
+}
+
+/// 周期性上报节点状态
+async fn heartbeat_loop(
+    state: SharedState,
+    cfg: AgentConfig,
+    base: String,
+    node_id: String,
+    revision: Arc<AtomicU64>,
+) {
+    let url = format!("{}/api/nodes/{}/heartbeat", base, node_id);
+    let started_at = Instant::now();
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(cfg.heartbeat_interval_sec.max(1)));
+
+    loop {
+        interval.tick().await;
+        let payload = serde_json::json!({
+            "node_id": node_id,
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_seconds": started_at.elapsed().as_secs(),
+            "assignment_revision": revision.load(Ordering::Relaxed),
+            "draining": drain::is_draining(&state),
+            "system": SystemStats::collect(),
+            "streams": state.stream_statuses(),
+        });
+
+        match http_client::send_json(
+            "POST",
+            &url,
+            &payload,
+            cfg.token.as_deref(),
+            Duration::from_secs(10),
+        )
+        .await
+        {
+            Ok(res) if res.is_success() => {}
+            Ok(res) => warn!("Agent: heartbeat rejected with status {}", res.status),
+            Err(e) => warn!("Agent: heartbeat failed: {}", e),
+        }
+    }
+}
+
+/// 长轮询指令通道
+async fn command_loop(
+    state: SharedState,
+    cfg: AgentConfig,
+    base: String,
+    node_id: String,
+    revision: Arc<AtomicU64>,
+) {
+    let url = format!(
+        "{}/api/nodes/{}/commands?wait={}",
+        base, node_id, cfg.poll_timeout_sec
+    );
+    let auth = cfg.token.as_ref().map(|t| format!("Bearer {}", t));
+    let headers: Vec<(&str, &str)> = auth
+        .as_deref()
+        .map(|a| vec![("Authorization", a)])
+        .unwrap_or_default();
+    let timeout = Duration::from_secs(cfg.poll_timeout_sec + 10);
+
+    loop {
+        let res = match http_client::request("GET", &url, &headers, None, timeout).await {
+            Ok(res) => res,
+            Err(e) => {
+                warn!("Agent: command poll failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        // 204 表示等待期内没有新指令
+        if res.status == 204 {
+            continue;
+        }
+        if !res.is_success() {
+            warn!("Agent: command poll rejected with status {}", res.status);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+
+        // 兼容单条指令与指令数组
+        let commands: Vec<Command> = match serde_json::from_slice::<Vec<Command>>(&res.body) {
+            Ok(list) => list,
+            Err(_) => match serde_json::from_slice::<Command>(&res.body) {
+                Ok(cmd) => vec![cmd],
+                Err(e) => {
+                    error!("Agent: invalid command payload: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        for cmd in commands {
+            match cmd {
+                Command::AssignStreams {
+                    revision: rev,
+                    streams,
+                } => match apply_assignment(&state, streams).await {
+                    Ok(result) => {
+                        revision.store(rev, Ordering::Relaxed);
+                        info!(
+                            "Agent: applied stream assignment (revision {}, {} stream(s) stopped)",
+                            rev,
+                            result.map_or(0, |r| r.stopped_streams.len())
+                        );
+                    }
+                    Err(e) => error!("Agent: rejected stream assignment: {}", e),
+                },
+                Command::Update(source) => match updater::update(&state, source).await {
+                    Ok(res) => info!(
+                        "Agent: update to version {} installed ({} bytes)",
+                        res.version, res.size
+                    ),
+                    Err(e) => error!("Agent: update failed: {}", e),
+                },
+            }
+        }
+    }
+}
+
+/// 应用流分配：校验新配置后写回配置文件并整体替换，停止被移除或被修改的流；
+/// 与当前流列表相同时不做任何修改 (返回 None)
+///
+/// 分配写入配置文件，重启后继续生效。被修改的流若开启 auto_start，会在下一轮监控中以新配置重新拉起
+async fn apply_assignment(
+    state: &SharedState,
+    streams: Vec<StreamConfig>,
+) -> anyhow::Result<Option<ImportResult>> {
+    let _guard = state.config_lock.lock().await;
+    let current = state.config();
+    if current.streams == streams {
+        return Ok(None);
+    }
+    let mut next: AppConfig = (*current).clone();
+    next.streams = streams;
+    next.validate()?;
+    Ok(Some(
+        snapshot::apply(state, &state.config_path, next).await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use std::path::PathBuf;
+
+    const STREAMS: &str = "
+  - name: cam1
+    source: rtsp://127.0.0.1:1/cam1
+  - name: cam2
+    source: rtsp://127.0.0.1:1/cam2
+";
+
+    fn test_state(tag: &str) -> (SharedState, PathBuf) {
+        let root = std::env::temp_dir().join(format!("vtx-agent-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let yaml = format!(
+            "server:\n  listen: 127.0.0.1:0\n  ffmpeg_binary: ffmpeg\n  supervisor_interval_ms: 1000\n  hls_root: {root}/hls\n  state_root: {root}/state\nstreams:{STREAMS}",
+            root = root.display()
+        );
+        let path = root.join("config.yaml");
+        std::fs::write(&path, &yaml).unwrap();
+        let config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+        (Arc::new(AppState::new(config, path).unwrap()), root)
+    }
+
+    fn streams(yaml: &str) -> Vec<StreamConfig> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn invalid_assignment_leaves_config_untouched() {
+        let (state, root) = test_state("invalid");
+        let before = std::fs::read_to_string(&state.config_path).unwrap();
+        let duplicate = streams(
+            "
+  - name: cam1
+    source: rtsp://127.0.0.1:1/a
+  - name: cam1
+    source: rtsp://127.0.0.1:1/b
+",
+        );
+        assert!(apply_assignment(&state, duplicate).await.is_err());
+        assert_eq!(state.config().streams, streams(STREAMS));
+        assert_eq!(std::fs::read_to_string(&state.config_path).unwrap(), before);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn assignment_is_persisted_and_stops_only_changed_streams() {
+        let (state, root) = test_state("assign");
+        let assigned = streams(
+            "
+  - name: cam1
+    source: rtsp://127.0.0.1:1/cam1
+  - name: cam2
+    source: rtsp://127.0.0.1:1/moved
+  - name: cam3
+    source: rtsp://127.0.0.1:1/cam3
+",
+        );
+        let result = apply_assignment(&state, assigned.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.stopped_streams, ["cam2"]);
+        assert_eq!(state.config().streams, assigned);
+        let saved = AppConfig::load(state.config_path.to_str().unwrap()).unwrap();
+        assert_eq!(saved.streams, assigned);
+
+        // 相同的分配不再写回配置文件
+        assert!(apply_assignment(&state, assigned).await.unwrap().is_none());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn controller_must_be_local() {
+        let config = |url: &str| -> AppConfig {
+            serde_yaml::from_str(&format!(
+                "server:\n  listen: 127.0.0.1:0\n  ffmpeg_binary: ffmpeg\n  supervisor_interval_ms: 1000\nagent:\n  controller_url: {}\n",
+                url
+            ))
+            .unwrap()
+        };
+        assert!(config("http://127.0.0.1:9000").validate().is_ok());
+        assert!(config("http://localhost:9000/").validate().is_ok());
+        assert!(config("http://10.0.0.5:9000").validate().is_err());
+        assert!(config("https://controller.example.com").validate().is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use crate::clock;
+use crate::config::{AlertAction, AlertCondition, AlertRule};
+use crate::engine::Engine;
+use crate::hash;
+use crate::http_client;
+use crate::maintenance::{self, Mute};
+use crate::metrics;
+use crate::notify;
+use crate::runtime_state;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+/// 告警规则的评估间隔
+const EVAL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 告警回调超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 指标序列的一个样本
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub metric: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+/// 已触发的告警 (GET /alerts 与 GET /sys/status 中的 `alerts`)
+#[derive(Debug, Clone, Serialize)]
+pub struct FiringAlert {
+    /// 由规则名称与标签得出的稳定标识，用于确认告警
+    pub id: String,
+    pub rule: String,
+    pub metric: String,
+    pub labels: BTreeMap<String, String>,
+    /// 最近一次评估的指标值
+    pub value: f64,
+    pub action: AlertAction,
+    /// 条件开始满足的时间
+    pub since: String,
+    /// 确认后直到告警解除都不再发送通知
+    pub acknowledged: Option<AlertAck>,
+    /// 所属流的通知静默
+    pub mute: Option<Mute>,
+}
+
+/// 运维人员对告警的确认
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertAck {
+    /// 确认时间 (RFC 3339)
+    pub at: String,
+    pub by: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// 告警标识：规则名称与标签的 SHA-1 前 6 字节 (十六进制)
+pub fn alert_id(rule: &str, labels: &BTreeMap<String, String>) -> String {
+    let mut key = rule.to_string();
+    for (name, value) in labels {
+        key.push_str(&format!("\0{}={}", name, value));
+    }
+    hash::sha1(key.as_bytes())[..6]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 确认已触发的告警，返回确认后的告警 (告警不存在或已解除时为 None)
+pub fn acknowledge(
+    state: &AppState,
+    id: &str,
+    by: Option<String>,
+    comment: Option<String>,
+) -> Option<FiringAlert> {
+    let mut alerts = state.alerts.lock_or_recover();
+    let alert = alerts.iter_mut().find(|a| a.id == id)?;
+    let ack = AlertAck {
+        at: clock::rfc3339(SystemTime::now()),
+        by,
+        comment,
+    };
+    info!(
+        "Alert [{}] {:?} acknowledged{}",
+        alert.rule,
+        alert.labels,
+        ack.by
+            .as_deref()
+            .map(|b| format!(" by {}", b))
+            .unwrap_or_default()
+    );
+    state
+        .alert_acks
+        .lock_or_recover()
+        .insert(id.to_string(), ack.clone());
+    alert.acknowledged = Some(ack);
+    Some(alert.clone())
+}
+
+/// 满足条件的序列
+struct Pending {
+    since: Instant,
+    since_wall: SystemTime,
+    value: f64,
+    fired: bool,
+}
+
+/// 解析 Prometheus 文本格式 (仅支持本程序导出的格式)
+pub fn parse(text: &str) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let Ok(value) = value.parse::<f64>() else {
+            continue;
+        };
+        let (metric, labels) = match series.split_once('{') {
+            Some((metric, rest)) => (metric, parse_labels(rest.trim_end_matches('}'))),
+            None => (series, BTreeMap::new()),
+        };
+        samples.push(Sample {
+            metric: metric.to_string(),
+            labels,
+            value,
+        });
+    }
+    samples
+}
+
+fn parse_labels(s: &str) -> BTreeMap<String, String> {
+    s.split("\",")
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once("=\"")?;
+            Some((
+                key.trim().to_string(),
+                value.trim_end_matches('"').to_string(),
+            ))
+        })
+        .collect()
+}
+
+fn matches(rule: &AlertRule, sample: &Sample) -> bool {
+    sample.metric == rule.metric
+        && rule
+            .labels
+            .iter()
+            .all(|(k, v)| sample.labels.get(k) == Some(v))
+        && match rule.condition {
+            AlertCondition::Above => sample.value > rule.threshold,
+            AlertCondition::Below => sample.value < rule.threshold,
+        }
+}
+
+/// 定期按指标评估告警规则
+///
+/// 每个序列单独计时，条件持续满足 `for_sec` 后执行一次动作，条件解除后恢复
+pub async fn start_evaluator(state: Arc<AppState>) {
+    let mut pending: HashMap<(String, BTreeMap<String, String>), Pending> = HashMap::new();
+    let mut ticker = tokio::time::interval(EVAL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let config = state.config();
+        if config.alert_rules.is_empty() {
+            pending.clear();
+            state.alerts.lock_or_recover().clear();
+            continue;
+        }
+        let samples = parse(&metrics::render(&state, |_| true));
+        let now = Instant::now();
+
+        let mut seen = Vec::new();
+        for rule in &config.alert_rules {
+            for sample in samples.iter().filter(|s| matches(rule, s)) {
+                let key = (rule.name.clone(), sample.labels.clone());
+                let entry = pending.entry(key.clone()).or_insert_with(|| Pending {
+                    since: now,
+                    since_wall: SystemTime::now(),
+                    value: sample.value,
+                    fired: false,
+                });
+                entry.value = sample.value;
+                if !entry.fired && now.duration_since(entry.since).as_secs() >= rule.for_sec {
+                    entry.fired = true;
+                    fire(&state, rule, sample).await;
+                }
+                seen.push(key);
+            }
+        }
+
+        // 条件不再满足 (或序列消失、规则被删除) 的告警解除
+        pending.retain(|key, entry| {
+            if seen.contains(key) {
+                return true;
+            }
+            if entry.fired {
+                info!("Alert [{}] resolved {:?}", key.0, key.1);
+                if let Some(rule) = config.alert_rules.iter().find(|r| r.name == key.0) {
+                    notify(&state, rule, &key.1, entry.value, "alert_resolved");
+                }
+                state
+                    .alert_acks
+                    .lock_or_recover()
+                    .remove(&alert_id(&key.0, &key.1));
+            }
+            false
+        });
+
+        let acks = state.alert_acks.lock_or_recover().clone();
+        let firing = pending
+            .iter()
+            .filter(|(_, p)| p.fired)
+            .filter_map(|((name, labels), p)| {
+                let rule = config.alert_rules.iter().find(|r| &r.name == name)?;
+                let id = alert_id(name, labels);
+                Some(FiringAlert {
+                    acknowledged: acks.get(&id).cloned(),
+                    mute: labels
+                        .get("stream")
+                        .and_then(|s| maintenance::muted(&state, s)),
+                    id,
+                    rule: name.clone(),
+                    metric: rule.metric.clone(),
+                    labels: labels.clone(),
+                    value: p.value,
+                    action: rule.action,
+                    since: clock::rfc3339(p.since_wall),
+                })
+            })
+            .collect();
+        *state.alerts.lock_or_recover() = firing;
+    }
+}
+
+/// 执行告警动作
+async fn fire(state: &Arc<AppState>, rule: &AlertRule, sample: &Sample) {
+    warn!(
+        "Alert [{}] firing: {} {:?} = {} ({:?} {})",
+        rule.name, rule.metric, sample.labels, sample.value, rule.condition, rule.threshold
+    );
+    notify(state, rule, &sample.labels, sample.value, "alert_firing");
+
+    let stream = sample.labels.get("stream");
+    match (rule.action, stream) {
+        (AlertAction::Notify, _) => {}
+        (_, None) => warn!(
+            "Alert [{}] cannot {:?}: the series has no stream label",
+            rule.name, rule.action
+        ),
+        (AlertAction::Restart, Some(name)) => {
+            if !state.active_streams.lock_or_recover().contains_key(name) {
+                return;
+            }
+            warn!("Restarting stream [{}] (alert [{}])", name, rule.name);
+            let _ = Engine::stop_stream(state, name).await;
+            if let Err(e) = Engine::start_stream(state, name).await {
+                error!("Restart failed [{}]: {}", name, e);
+            }
+        }
+        (AlertAction::Quarantine, Some(name)) => {
+            error!(
+                "Stream [{}] quarantined by alert [{}]. Force-start it to release.",
+                name, rule.name
+            );
+            state
+                .recovery_states
+                .lock_or_recover()
+                .entry(name.clone())
+                .or_default()
+                .quarantined = Some(format!("alert rule {}", rule.name));
+            runtime_state::save_or_warn(state);
+            let _ = Engine::stop_stream(state, name).await;
+        }
+    }
+}
+
+/// 向规则的回调地址与通知渠道发送事件 (后台执行，失败仅记录日志)
+///
+/// 所属流处于静默或告警已被确认时不发送
+fn notify(
+    state: &AppState,
+    rule: &AlertRule,
+    labels: &BTreeMap<String, String>,
+    value: f64,
+    event: &'static str,
+) {
+    if let Some(mute) = labels
+        .get("stream")
+        .and_then(|s| maintenance::muted(state, s))
+    {
+        info!(
+            "Suppressed {} for [{}] {:?}: stream muted until {}",
+            event, rule.name, labels, mute.mute_until
+        );
+        return;
+    }
+    if state
+        .alert_acks
+        .lock_or_recover()
+        .contains_key(&alert_id(&rule.name, labels))
+    {
+        info!(
+            "Suppressed {} for [{}] {:?}: acknowledged",
+            event, rule.name, labels
+        );
+        return;
+    }
+    notify::dispatch(state, rule, labels, value, event);
+    let Some(url) = rule.webhook.clone() else {
+        return;
+    };
+    let body = serde_json::json!({
+        "event": event,
+        "rule": rule.name,
+        "metric": rule.metric,
+        "labels": labels,
+        "id": alert_id(&rule.name, labels),
+        "value": value,
+        "threshold": rule.threshold,
+        "action": rule.action,
+        "severity": rule.severity,
+        "time": clock::rfc3339(SystemTime::now()),
+    });
+    let name = rule.name.clone();
+    tokio::spawn(async move {
+        if let Err(e) = http_client::send_json("POST", &url, &body, None, WEBHOOK_TIMEOUT).await {
+            warn!("Alert webhook failed [{}]: {}", name, e);
+        }
+    });
+}
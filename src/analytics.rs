@@ -0,0 +1,360 @@
+use crate::clock;
+use crate::config::AnalyticsConfig;
+use crate::http_client;
+use crate::state::{AppState, LockExt};
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// 导出请求的超时
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 长连接传输期间刷新会话的间隔
+const TOUCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 导出失败时最多保留的批数，超出后丢弃最早的记录
+const MAX_PENDING_BATCHES: usize = 10;
+
+const CSV_HEADER: &str = "stream,tenant,protocol,client_ip,user_agent,start,end,duration_sec,bytes,segments,mean_segment_fetch_ms\n";
+
+/// 一个已结束的观看会话
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionRecord {
+    pub stream: String,
+    pub tenant: Option<String>,
+    /// `hls` 或 `ts`
+    pub protocol: &'static str,
+    pub client_ip: String,
+    pub user_agent: String,
+    /// 首个与最后一个请求的时间 (RFC 3339)
+    pub start: String,
+    pub end: String,
+    pub duration_sec: u64,
+    /// 下发的字节数 (含播放列表)
+    pub bytes: u64,
+    /// 请求的切片数
+    pub segments: u64,
+    /// 切片从收到请求到发送完毕的平均耗时，未请求切片 (如 MPEG-TS 长连接) 时为 None
+    pub mean_segment_fetch_ms: Option<f64>,
+}
+
+/// 进行中的会话
+struct OpenSession {
+    record: SessionRecord,
+    started: SystemTime,
+    last_seen: Instant,
+    last_seen_at: SystemTime,
+    fetch_ms_total: f64,
+    /// 仍在传输的响应数，不为零时会话不会因超时结束
+    transfers: usize,
+}
+
+/// 会话记录与待导出的队列
+#[derive(Default)]
+pub struct Analytics {
+    /// (流名称, 观看者标识) -> 进行中的会话
+    open: HashMap<(String, String), OpenSession>,
+    /// 已结束、等待导出的会话 (由旧到新)
+    finished: VecDeque<SessionRecord>,
+    last_export: Option<Instant>,
+    /// 正在导出，避免批次并发写入同一文件或乱序到达
+    exporting: bool,
+}
+
+/// 一次请求的观看者信息
+pub struct Request<'a> {
+    pub stream: &'a str,
+    pub tenant: Option<&'a str>,
+    pub protocol: &'static str,
+    pub viewer: &'a str,
+    pub client_ip: &'a str,
+    pub user_agent: &'a str,
+}
+
+/// 一次响应的传输，响应体结束或被丢弃 (客户端断开) 时计入会话
+pub struct Fetch {
+    state: Arc<AppState>,
+    key: (String, String),
+    started: Instant,
+    segment: bool,
+    /// 尚未计入会话的字节数
+    bytes: u64,
+    touched: Instant,
+}
+
+impl Fetch {
+    /// 将已下发的字节数计入会话并刷新其活跃时间 (长连接在传输期间保持会话)
+    fn commit(&mut self) -> Option<()> {
+        let mut analytics = self.state.analytics.lock_or_recover();
+        let session = analytics.open.get_mut(&self.key)?;
+        session.record.bytes += std::mem::take(&mut self.bytes);
+        session.last_seen = Instant::now();
+        session.last_seen_at = SystemTime::now();
+        self.touched = Instant::now();
+        Some(())
+    }
+}
+
+impl Drop for Fetch {
+    fn drop(&mut self) {
+        if self.commit().is_none() {
+            return;
+        }
+        let fetch_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        let mut analytics = self.state.analytics.lock_or_recover();
+        if let Some(session) = analytics.open.get_mut(&self.key) {
+            session.transfers = session.transfers.saturating_sub(1);
+            if self.segment {
+                session.record.segments += 1;
+                session.fetch_ms_total += fetch_ms;
+            }
+        }
+    }
+}
+
+/// 记录一次请求并返回其传输的计量 (未配置分析时为 None)
+///
+/// `segment` 为 true 时传输耗时计入平均切片获取耗时
+pub fn begin(state: &Arc<AppState>, req: Request, segment: bool) -> Option<Fetch> {
+    state.config().analytics.as_ref()?;
+    let key = (req.stream.to_string(), req.viewer.to_string());
+    let now = SystemTime::now();
+    let mut analytics = state.analytics.lock_or_recover();
+    let session = analytics
+        .open
+        .entry(key.clone())
+        .or_insert_with(|| OpenSession {
+            record: SessionRecord {
+                stream: req.stream.to_string(),
+                tenant: req.tenant.map(str::to_string),
+                protocol: req.protocol,
+                client_ip: req.client_ip.to_string(),
+                user_agent: req.user_agent.to_string(),
+                start: String::new(),
+                end: String::new(),
+                duration_sec: 0,
+                bytes: 0,
+                segments: 0,
+                mean_segment_fetch_ms: None,
+            },
+            started: now,
+            last_seen: Instant::now(),
+            last_seen_at: now,
+            fetch_ms_total: 0.0,
+            transfers: 0,
+        });
+    session.last_seen = Instant::now();
+    session.last_seen_at = now;
+    session.transfers += 1;
+    Some(Fetch {
+        state: state.clone(),
+        key,
+        started: Instant::now(),
+        segment,
+        bytes: 0,
+        touched: Instant::now(),
+    })
+}
+
+/// 为响应体计量下发的字节数，响应体被丢弃时结束本次传输
+pub fn tracked<S, E>(stream: S, fetch: Option<Fetch>) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let mut fetch = fetch;
+    stream.inspect(move |item| {
+        if let (Some(fetch), Ok(chunk)) = (fetch.as_mut(), item) {
+            fetch.bytes += chunk.len() as u64;
+            if fetch.touched.elapsed() >= TOUCH_INTERVAL {
+                fetch.commit();
+            }
+        }
+    })
+}
+
+/// Supervisor 每轮调用：没有进行中的传输且超过会话超时未再请求的会话视为结束，
+/// 累计满一批或距上次导出超过 `flush_interval_sec` 时在后台导出
+pub fn tick(state: &Arc<AppState>, session_timeout: Duration) {
+    let config = state.config();
+    let Some(cfg) = config.analytics.clone() else {
+        let mut analytics = state.analytics.lock_or_recover();
+        analytics.open.clear();
+        analytics.finished.clear();
+        return;
+    };
+    let batch = {
+        let mut analytics = state.analytics.lock_or_recover();
+        let ended: Vec<(String, String)> = analytics
+            .open
+            .iter()
+            .filter(|(_, s)| s.transfers == 0 && s.last_seen.elapsed() >= session_timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in ended {
+            if let Some(session) = analytics.open.remove(&key) {
+                analytics.finished.push_back(close(session));
+            }
+        }
+        let due = analytics
+            .last_export
+            .is_none_or(|t| t.elapsed() >= Duration::from_secs(cfg.flush_interval_sec));
+        if analytics.exporting
+            || analytics.finished.is_empty()
+            || (analytics.finished.len() < cfg.batch_size && !due)
+        {
+            return;
+        }
+        analytics.exporting = true;
+        analytics.last_export = Some(Instant::now());
+        let n = analytics.finished.len().min(cfg.batch_size);
+        analytics.finished.drain(..n).collect::<Vec<_>>()
+    };
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        let result = export(&cfg, &batch).await;
+        let mut analytics = state.analytics.lock_or_recover();
+        analytics.exporting = false;
+        if let Err(e) = result {
+            warn!(
+                "Analytics export of {} session(s) failed: {}",
+                batch.len(),
+                e
+            );
+            // 放回队首，下一轮重试
+            for record in batch.into_iter().rev() {
+                analytics.finished.push_front(record);
+            }
+            let limit = cfg.batch_size * MAX_PENDING_BATCHES;
+            if analytics.finished.len() > limit {
+                let dropped = analytics.finished.len() - limit;
+                analytics.finished.drain(..dropped);
+                warn!("Analytics queue full, dropped {} session(s)", dropped);
+            }
+        }
+    });
+}
+
+/// 网关关闭时结束所有会话并导出剩余记录
+pub async fn flush_on_shutdown(state: &AppState) {
+    let Some(cfg) = state.config().analytics.clone() else {
+        return;
+    };
+    let records: Vec<SessionRecord> = {
+        let mut analytics = state.analytics.lock_or_recover();
+        let open: Vec<OpenSession> = analytics.open.drain().map(|(_, s)| s).collect();
+        analytics.finished.extend(open.into_iter().map(close));
+        analytics.finished.drain(..).collect()
+    };
+    if records.is_empty() {
+        return;
+    }
+    for batch in records.chunks(cfg.batch_size) {
+        if let Err(e) = export(&cfg, batch).await {
+            warn!(
+                "Analytics export of {} session(s) failed: {}",
+                batch.len(),
+                e
+            );
+            return;
+        }
+    }
+    info!("Exported {} viewer session(s)", records.len());
+}
+
+fn close(session: OpenSession) -> SessionRecord {
+    let mut record = session.record;
+    record.start = clock::rfc3339(session.started);
+    record.end = clock::rfc3339(session.last_seen_at);
+    record.duration_sec = session
+        .last_seen_at
+        .duration_since(session.started)
+        .map_or(0, |d| d.as_secs());
+    record.mean_segment_fetch_ms = (record.segments > 0)
+        .then(|| (session.fetch_ms_total / record.segments as f64 * 10.0).round() / 10.0);
+    record
+}
+
+/// 导出一批会话：写入 CSV 与 POST 到接口 (均已配置时须都成功)
+async fn export(cfg: &AnalyticsConfig, batch: &[SessionRecord]) -> anyhow::Result<()> {
+    if let Some(dir) = &cfg.csv_dir {
+        write_csv(Path::new(dir), batch).await?;
+    }
+    if let Some(url) = &cfg.url {
+        let body = serde_json::json!({ "sessions": batch });
+        let resp = http_client::send_json(
+            "POST",
+            url,
+            &body,
+            cfg.bearer_token.as_deref(),
+            EXPORT_TIMEOUT,
+        )
+        .await?;
+        if !resp.is_success() {
+            anyhow::bail!("{} returned HTTP {}", url, resp.status);
+        }
+    }
+    Ok(())
+}
+
+/// 追加到会话结束日期的 CSV 文件，新文件先写入表头
+async fn write_csv(dir: &Path, batch: &[SessionRecord]) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let mut by_day: Vec<(&str, String)> = Vec::new();
+    for record in batch {
+        let day = record.end.get(..10).unwrap_or("unknown");
+        let line = csv_line(record);
+        match by_day.iter_mut().find(|(d, _)| *d == day) {
+            Some((_, text)) => text.push_str(&line),
+            None => by_day.push((day, line)),
+        }
+    }
+    for (day, text) in by_day {
+        let path = dir.join(format!("sessions-{}.csv", day));
+        let new = !tokio::fs::try_exists(&path).await?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        if new {
+            file.write_all(CSV_HEADER.as_bytes()).await?;
+        }
+        file.write_all(text.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+fn csv_line(r: &SessionRecord) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{}\n",
+        csv_field(&r.stream),
+        csv_field(r.tenant.as_deref().unwrap_or_default()),
+        r.protocol,
+        csv_field(&r.client_ip),
+        csv_field(&r.user_agent),
+        r.start,
+        r.end,
+        r.duration_sec,
+        r.bytes,
+        r.segments,
+        r.mean_segment_fetch_ms
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    )
+}
+
+/// 含逗号、引号或换行的字段加引号，引号双写 (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,204 @@
+use crate::agent;
+use crate::alerts;
+use crate::analytics;
+use crate::audit;
+use crate::config::ServerConfig;
+use crate::counters;
+use crate::engine::Engine;
+use crate::gc;
+use crate::gpu;
+use crate::heartbeat;
+use crate::ntp;
+use crate::playout;
+use crate::rtsp;
+use crate::runtime_state;
+use crate::state::{LockExt, SharedState};
+use crate::supervisor;
+use crate::tokens;
+use crate::web;
+use axum::{
+    middleware,
+    routing::{delete, get, patch, post, put},
+    Router,
+};
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// 按 `server.worker_threads` 与 `server.max_blocking_threads` 构建异步运行时
+pub fn runtime(server: &ServerConfig) -> std::io::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(threads) = server.worker_threads {
+        builder.worker_threads(threads);
+    }
+    if let Some(threads) = server.max_blocking_threads {
+        builder.max_blocking_threads(threads);
+    }
+    builder.build()
+}
+
+/// 网关的全部 HTTP 路由 (管理 API、HLS 与 TS 下发)
+///
+/// 客户端地址用于观看会话统计，服务时需提供 `ConnectInfo<SocketAddr>` (见 `listener::serve`)
+pub fn router(state: SharedState) -> Router {
+    let router = Router::new()
+        .route("/", get(web::admin::index_handler)) // 首页
+        .route("/healthz", get(web::admin::healthz)) // 存活探测 (无需令牌)
+        .route("/readyz", get(web::admin::readyz)) // 就绪探测 (无需令牌)
+        .route("/sys/status", get(web::admin::sys_status)) // 系统状态
+        .route("/sys/drain", post(web::admin::handle_drain)) // 进入维护排空
+        .route("/sys/undrain", post(web::admin::handle_undrain)) // 恢复服务
+        .route("/sys/gc", post(web::admin::handle_gc)) // 清理残留的流目录
+        .route("/sys/update", post(web::admin::handle_update)) // 自更新并重启
+        .route(
+            "/sys/nettest",
+            get(web::admin::nettest).post(web::admin::nettest_upload),
+        ) // 网络测速
+        .route("/sys/config/export", get(web::admin::export_config)) // 导出配置
+        .route("/sys/config/import", post(web::admin::import_config)) // 导入配置
+        .route("/streams", get(web::admin::list_streams)) // 获取流列表
+        .route(
+            "/streams/:name",
+            get(web::admin::stream_detail)
+                .put(web::admin::put_stream)
+                .delete(web::admin::delete_stream),
+        ) // 获取流详情 / 声明式更新 / 删除流
+        .route("/config/streams", put(web::admin::put_streams)) // 声明式替换流集合
+        .route("/metrics", get(web::admin::metrics)) // Prometheus 指标
+        .route("/tenants", get(web::admin::list_tenants)) // 获取租户列表
+        .route("/stats/summary", get(web::admin::stats_summary)) // 统计概览
+        .route("/alerts", get(web::admin::list_alerts)) // 已触发的告警
+        .route("/alerts/:id/ack", post(web::admin::handle_ack)) // 确认告警
+        .route("/audit", get(web::admin::list_audit)) // 审计日志
+        .route(
+            "/auth/tokens",
+            get(web::admin::list_tokens).post(web::admin::issue_token),
+        ) // 令牌列表 / 签发令牌
+        .route("/auth/tokens/:id", delete(web::admin::revoke_token)) // 吊销令牌
+        .route("/streams/:name/start", post(web::admin::handle_start)) // 启动流
+        .route("/streams/:name/stop", post(web::admin::handle_stop)) // 停止流
+        .route("/streams/:name/logs", get(web::admin::stream_logs)) // 获取流日志
+        .route(
+            "/streams/:name/availability",
+            get(web::admin::stream_availability), // 获取流可用性
+        )
+        .route("/streams/:name/disable", post(web::admin::handle_disable)) // 运维禁用流
+        .route("/streams/:name/enable", post(web::admin::handle_enable)) // 恢复流
+        .route(
+            "/streams/:name/mute",
+            post(web::admin::handle_mute).delete(web::admin::handle_unmute),
+        ) // 静默 / 恢复流的告警通知
+        .route(
+            "/streams/:name/timelapse",
+            post(web::admin::handle_timelapse), // 生成延时视频
+        )
+        .route("/streams/:name/markers", post(web::admin::handle_marker)) // 插入广告标记
+        .route(
+            "/streams/:name/watermarks",
+            get(web::admin::list_watermarks), // 查询水印码分配
+        )
+        .route("/streams/:name/clone", post(web::admin::clone_stream)) // 复制流
+        .route(
+            "/streams/:name/apply-profile",
+            post(web::admin::apply_profile), // 无中断修改流配置
+        )
+        .route("/streams/:name/hls", patch(web::admin::patch_hls)) // 修改切片时长与播放列表长度
+        .route(
+            "/streams/:name/schedule",
+            get(web::admin::get_schedule).put(web::admin::put_schedule),
+        ) // 查询 / 修改节目表
+        .route("/templates", get(web::admin::list_templates)) // 获取流模板列表
+        .route(
+            "/templates/:name/instantiate",
+            post(web::admin::instantiate_template), // 按模板创建流
+        )
+        .route("/discovery/scan", post(web::admin::discovery_scan)) // 扫描 ONVIF 设备
+        .route("/hls/:stream_name/key", get(web::hls::serve_hls_key)) // 获取解密密钥
+        .route(
+            "/hls/:stream_name/:file_name",
+            get(web::hls::serve_hls_file), // 获取HLS文件
+        )
+        .route(
+            "/hls/:tenant/:stream_name/key",
+            get(web::hls::serve_tenant_hls_key), // 获取租户流的解密密钥
+        )
+        .route(
+            "/hls/:tenant/:stream_name/:file_name",
+            get(web::hls::serve_tenant_hls_file), // 获取租户流的HLS文件
+        )
+        .route("/ts/:stream_name", get(web::ts::serve_ts)) // 获取 MPEG-TS 直播流
+        .route("/ts/:tenant/:stream_name", get(web::ts::serve_tenant_ts)); // 获取租户流的 MPEG-TS 直播流
+                                                                           // 故障演练接口 (仅 debug 特性)
+    #[cfg(feature = "debug")]
+    let router = web::debug::routes(router);
+    router
+        // 记录每个改变状态的请求
+        .layer(middleware::from_fn_with_state(state.clone(), audit::record))
+        .with_state(state)
+}
+
+/// 启动后台任务：Supervisor、手动启动流的恢复、时钟核对、告警、节目表、GPU 监控、
+/// 内置 RTSP 服务 (使用 `rtsp::bind_servers` 预先监听的端口) 与集群代理
+pub fn spawn_tasks(state: &SharedState, rtsp_listeners: Vec<(u16, TcpListener)>) {
+    let config = state.config();
+
+    // 启动后台监控程序
+    tokio::spawn(supervisor::start_watchdog(
+        state.clone(),
+        config.server.supervisor_interval_ms,
+    ));
+
+    // 重新启动上次手动启动的流
+    tokio::spawn(runtime_state::resume_started(state.clone()));
+
+    // 定期核对系统时钟 (如已配置 NTP 服务器)
+    if let Some(server) = config.server.ntp_server.clone() {
+        tokio::spawn(ntp::start_monitor(state.clone(), server));
+    }
+
+    // 评估本地告警规则
+    tokio::spawn(alerts::start_evaluator(state.clone()));
+    tokio::spawn(counters::start_flusher(state.clone()));
+    tokio::spawn(tokens::start_flusher(state.clone()));
+
+    // 向外部监控推送节点与流的心跳 (配置了 heartbeat.url 或流的 heartbeat_url 时)
+    tokio::spawn(heartbeat::start_pusher(state.clone()));
+
+    // 按节目表切换循环文件频道的节目
+    tokio::spawn(playout::start_scheduler(state.clone()));
+
+    // 采集 GPU/VPU 占用 (未发现设备时自动退出)
+    tokio::spawn(gpu::start_monitor(state.clone()));
+
+    // 清理 hls_root 下已删除、改名或长期闲置的流留下的目录
+    tokio::spawn(gc::start(state.clone()));
+
+    // 启动内置 RTSP 服务 (如有流配置了 rtsp_output)
+    rtsp::start_servers(state.clone(), rtsp_listeners);
+
+    // 启动集群代理 (如已配置控制器)
+    if let Some(agent_cfg) = config.agent.clone() {
+        info!(
+            "Agent mode enabled. Controller: {}",
+            agent_cfg.controller_url
+        );
+        tokio::spawn(agent::start_agent(state.clone(), agent_cfg));
+    }
+}
+
+/// 停止所有 FFmpeg 进程，避免退出后留下无人管理的子进程
+pub async fn shutdown(state: &SharedState) {
+    info!("Shutting down: stopping all streams");
+    let running: Vec<String> = state
+        .active_streams
+        .lock_or_recover()
+        .keys()
+        .cloned()
+        .collect();
+    for name in running {
+        let _ = Engine::stop_stream(state, &name).await;
+    }
+    counters::flush_on_shutdown(state);
+    tokens::flush_on_shutdown(state);
+    analytics::flush_on_shutdown(state).await;
+}
@@ -0,0 +1,265 @@
+use crate::auth::{self, Principal};
+use crate::clock;
+use crate::config::AppConfig;
+use crate::sessions;
+use crate::snapshot;
+use crate::state::{AppState, LockExt, SharedState};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use tracing::warn;
+
+/// 审计日志文件 (位于 server.state_root，每行一条 JSON)
+const FILE_NAME: &str = "audit.jsonl";
+
+/// 超过该大小时轮转为 `audit.jsonl.1` (只保留一份旧文件)
+const MAX_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// 记录其内容的请求体上限；更大的请求体 (配置导入、升级包等) 只记录长度
+const MAX_BODY_BYTES: usize = 8 * 1024;
+
+/// 调用方自报的操作人 (如 CLI 发送的登录用户名)，仅用于记录，权限仍以令牌为准
+pub const USER_HEADER: &str = "X-Vtx-User";
+
+/// 串行化写入与轮转
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 一次改变状态的 API 调用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// 请求到达的时间 (RFC 3339，毫秒)
+    pub at: String,
+    /// 令牌 ID (见 `GET /auth/tokens`)，未携带或无法识别令牌时为 `anonymous`
+    pub actor: String,
+    /// 令牌所属租户
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// 调用方自报的操作人 (`X-Vtx-User`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub client_ip: String,
+    pub method: String,
+    pub path: String,
+    /// 操作的流 (路径为 `/streams/:name/...` 时)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    /// 查询参数与 JSON 请求体，令牌与密码已屏蔽
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+    /// 应答状态
+    pub status: u16,
+    /// `ok` (2xx/3xx)、`denied` (401/403) 或 `failed`
+    pub outcome: String,
+    pub duration_ms: u64,
+}
+
+/// 审计日志查询条件
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub stream: Option<String>,
+    pub actor: Option<String>,
+    /// Unix 秒，含
+    pub since: Option<u64>,
+    /// Unix 秒，不含
+    pub until: Option<u64>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let at = clock::parse_rfc3339(&entry.at).unwrap_or(0);
+        self.stream
+            .as_ref()
+            .is_none_or(|s| entry.stream.as_ref() == Some(s))
+            && self
+                .actor
+                .as_ref()
+                .is_none_or(|a| entry.actor == *a || entry.user.as_ref() == Some(a))
+            && self.since.is_none_or(|t| at >= t)
+            && self.until.is_none_or(|t| at < t)
+    }
+}
+
+/// 路由中间件：记录除 GET/HEAD/OPTIONS 以外的每个请求 (启停、配置变更等)
+pub async fn record(State(state): State<SharedState>, req: Request, next: Next) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+    let started = Instant::now();
+    let at = clock::rfc3339_millis(SystemTime::now());
+
+    let query: BTreeMap<String, String> = Query::try_from_uri(req.uri())
+        .map(|Query(q)| q)
+        .unwrap_or_default();
+    let token = auth::extract_token(req.headers(), query.get("token").map(String::as_str));
+    let (actor, tenant) = match token.and_then(|t| auth::identify(&state, &t)) {
+        Some((id, Principal::Tenant(tenant))) => (id, Some(tenant)),
+        Some((id, Principal::Admin)) => (id, None),
+        None => ("anonymous".to_string(), None),
+    };
+    let user = req
+        .headers()
+        .get(USER_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| *peer);
+    let client_ip = match peer {
+        Some(peer) => sessions::client_ip(&state, req.headers(), peer),
+        None => String::new(),
+    };
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let stream = stream_of(&path);
+
+    let (req, body) = capture_body(req).await;
+    let mut params = serde_json::Map::new();
+    let query: serde_json::Map<String, Value> = query
+        .into_iter()
+        .filter(|(k, _)| k != "token")
+        .map(|(k, v)| (k, Value::String(v)))
+        .collect();
+    if !query.is_empty() {
+        params.insert("query".to_string(), Value::Object(query));
+    }
+    if let Some(body) = body {
+        params.insert("body".to_string(), body);
+    }
+    let mut params = (!params.is_empty()).then_some(Value::Object(params));
+    if let Some(params) = &mut params {
+        snapshot::mask_secrets(params);
+    }
+
+    let res = next.run(req).await;
+    let status = res.status();
+    let outcome = match status.as_u16() {
+        401 | 403 => "denied",
+        _ if status.is_success() || status.is_redirection() => "ok",
+        _ => "failed",
+    };
+    let entry = AuditEntry {
+        at,
+        actor,
+        tenant,
+        user,
+        client_ip,
+        method,
+        path,
+        stream,
+        params,
+        status: status.as_u16(),
+        outcome: outcome.to_string(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    if let Err(e) = append(&state, &entry) {
+        warn!("Failed to write audit log entry: {}", e);
+    }
+    res
+}
+
+/// `/streams/:name/...` 中的流名称
+fn stream_of(path: &str) -> Option<String> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    (segments.next() == Some("streams"))
+        .then(|| segments.next())
+        .flatten()
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// 读取不超过上限的 JSON 请求体并放回请求中；其他请求体只记录声明的长度
+async fn capture_body(req: Request) -> (Request, Option<Value>) {
+    let length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    let is_json = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("json"));
+    match length {
+        Some(0) | None => (req, None),
+        Some(len) if !is_json || len > MAX_BODY_BYTES => {
+            (req, Some(serde_json::json!({ "bytes": len })))
+        }
+        Some(len) => {
+            let (parts, body) = req.into_parts();
+            match axum::body::to_bytes(body, MAX_BODY_BYTES).await {
+                Ok(bytes) => {
+                    let value = serde_json::from_slice(&bytes)
+                        .unwrap_or_else(|_| serde_json::json!({ "bytes": len }));
+                    (Request::from_parts(parts, Body::from(bytes)), Some(value))
+                }
+                // 读取失败时请求体已不可用，交给处理函数按空请求体报错
+                Err(_) => (
+                    Request::from_parts(parts, Body::empty()),
+                    Some(serde_json::json!({ "bytes": len })),
+                ),
+            }
+        }
+    }
+}
+
+/// 追加一条记录，文件过大时先轮转
+pub fn append(state: &AppState, entry: &AuditEntry) -> anyhow::Result<()> {
+    let _guard = WRITE_LOCK.lock_or_recover();
+    let path = file_path(&state.config());
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if std::fs::metadata(&path).is_ok_and(|m| m.len() >= MAX_FILE_BYTES) {
+        std::fs::rename(&path, path.with_extension("jsonl.1"))?;
+    }
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?
+        .write_all(&line)?;
+    Ok(())
+}
+
+/// 按时间顺序返回匹配条件的最近 `limit` 条记录 (含轮转出的旧文件)
+pub fn query(config: &AppConfig, filter: &Filter, limit: usize) -> anyhow::Result<Vec<AuditEntry>> {
+    let _guard = WRITE_LOCK.lock_or_recover();
+    let path = file_path(config);
+    let mut entries = Vec::new();
+    for file in [path.with_extension("jsonl.1"), path] {
+        let file = match std::fs::File::open(&file) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for line in std::io::BufReader::new(file).lines() {
+            // 跳过被截断的行 (如写入时断电)
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) else {
+                continue;
+            };
+            if filter.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+    }
+    let skip = entries.len().saturating_sub(limit);
+    Ok(entries.split_off(skip))
+}
+
+fn file_path(config: &AppConfig) -> PathBuf {
+    PathBuf::from(&config.server.state_root).join(FILE_NAME)
+}
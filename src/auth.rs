@@ -0,0 +1,108 @@
+use crate::error::VtxError;
+use crate::state::{AppState, SharedState};
+use crate::tokens;
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{header, request::Parts, HeaderMap},
+};
+use serde::Deserialize;
+
+/// 已认证的调用方身份
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// 管理员，可访问全部租户
+    Admin,
+    /// 限定到单个租户
+    Tenant(String),
+}
+
+impl Principal {
+    /// 是否可访问指定租户 (None 表示默认命名空间) 的资源
+    pub fn can_access(&self, tenant: Option<&str>) -> bool {
+        match self {
+            Self::Admin => true,
+            Self::Tenant(own) => tenant == Some(own.as_str()),
+        }
+    }
+}
+
+/// 从请求中提取访问令牌
+///
+/// 优先读取 `Authorization: Bearer <token>`，其次使用查询参数 `token`
+pub fn extract_token(headers: &HeaderMap, query_token: Option<&str>) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string());
+
+    bearer.or_else(|| query_token.map(|t| t.to_string()))
+}
+
+/// 根据令牌 (配置中的或通过 API 签发的) 确定调用方身份并记录使用，
+/// 令牌无效、已过期或已吊销时返回 None
+pub fn authenticate(state: &AppState, token: Option<&str>) -> Option<Principal> {
+    let (id, principal) = identify(state, token?)?;
+    tokens::record_use(state, &id).then_some(principal)
+}
+
+/// 查找令牌的 ID 与身份，不检查吊销、不记录使用 (用于审计日志中的调用方)
+pub fn identify(state: &AppState, token: &str) -> Option<(String, Principal)> {
+    let configured = state
+        .config()
+        .auth
+        .tokens
+        .iter()
+        .find(|t| constant_time_eq(t.token(), token))
+        .map(|t| (t.id(), t.tenant().map(str::to_string)));
+    let (id, tenant) = configured.or_else(|| tokens::lookup(state, token))?;
+    let principal = match tenant {
+        Some(tenant) => Principal::Tenant(tenant),
+        None => Principal::Admin,
+    };
+    Some((id, principal))
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// 管理 API 调用方
+///
+/// 未开启 `auth.protect_api` 时所有请求均视为管理员
+pub struct ApiPrincipal(pub Principal);
+
+#[async_trait]
+impl FromRequestParts<SharedState> for ApiPrincipal {
+    type Rejection = VtxError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &SharedState,
+    ) -> Result<Self, Self::Rejection> {
+        let config = state.config();
+        if !config.auth.protect_api {
+            return Ok(Self(Principal::Admin));
+        }
+
+        let query_token = Query::<TokenQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|q| q.0.token);
+        let token = extract_token(&parts.headers, query_token.as_deref());
+
+        authenticate(state, token.as_deref())
+            .map(Self)
+            .ok_or(VtxError::Unauthorized)
+    }
+}
+
+/// 定长比较，避免通过响应时间猜测令牌
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,231 @@
+use crate::config::AvSyncConfig;
+use crate::engine::Engine;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tracing::{error, warn};
+
+/// 每个切片最多读取的字节数 (足以包含首个视频与音频 PES)
+const MAX_SCAN_BYTES: usize = 1024 * 1024;
+
+/// 相邻切片时间戳与切片时长不符超过该值时记为时间戳跳变 (毫秒)
+const DISCONTINUITY_MS: f64 = 1000.0;
+
+/// FFmpeg 在时间戳不连续时输出的日志片段
+const DISCONTINUITY_LOGS: &[&str] = &[
+    "Non-monotonous DTS",
+    "Non-monotonic DTS",
+    "DTS discontinuity",
+    "timestamp discontinuity",
+];
+
+/// 流的音画同步统计 (随进程重启清零)
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SyncStats {
+    /// 最近一个切片中首个音频与首个视频时间戳之差 (毫秒，正值表示音频滞后)
+    pub av_offset_ms: Option<f64>,
+    /// 相对进程启动后首个切片的偏移变化 (毫秒)
+    pub drift_ms: Option<f64>,
+    /// 时间戳跳变次数 (FFmpeg 日志与相邻切片的时间戳)
+    pub discontinuities: u64,
+}
+
+/// 运行中流的同步监测状态
+#[derive(Debug, Default)]
+pub struct SyncTracker {
+    pub stats: SyncStats,
+    /// 首个切片的音画偏移，作为漂移的基准
+    baseline_ms: Option<f64>,
+    /// 上一个已分析的切片
+    last_segment: Option<String>,
+    /// 上一个切片的首个视频时间戳与时长 (秒)
+    previous: Option<(u64, f64)>,
+    /// 连续超出阈值的切片数
+    over_threshold: u32,
+}
+
+/// 检查一行 FFmpeg 日志，统计时间戳不连续的告警
+pub fn on_stderr(state: &AppState, name: &str, line: &str) {
+    if DISCONTINUITY_LOGS.iter().any(|p| line.contains(p)) {
+        if let Some(running) = state.active_streams.lock_or_recover().get_mut(name) {
+            running.sync.stats.discontinuities += 1;
+        }
+    }
+}
+
+/// 主播放列表写出后分析刚完成的切片
+///
+/// FFmpeg 先输出 `Opening` 日志再写入播放列表，稍作等待后读取
+pub fn on_playlist_write(state: &Arc<AppState>, name: &str, line: &str, cfg: &AvSyncConfig) {
+    if !line.contains("index.m3u8") {
+        return;
+    }
+    let state = state.clone();
+    let name = name.to_string();
+    let cfg = cfg.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        if let Err(e) = analyze(&state, &name, &cfg).await {
+            warn!("A/V sync check failed [{}]: {}", name, e);
+        }
+    });
+}
+
+async fn analyze(state: &Arc<AppState>, name: &str, cfg: &AvSyncConfig) -> anyhow::Result<()> {
+    let Some(stream) = state.config().stream(name).cloned() else {
+        return Ok(());
+    };
+    let output_dir = Engine::output_dir(state, &stream);
+    let playlist = tokio::fs::read_to_string(output_dir.join("index.m3u8")).await?;
+    let Some((segment, duration)) = last_segment(&playlist) else {
+        return Ok(());
+    };
+    let already_seen = state
+        .active_streams
+        .lock_or_recover()
+        .get(name)
+        .is_none_or(|r| r.sync.last_segment.as_deref() == Some(segment.as_str()));
+    if already_seen {
+        return Ok(());
+    }
+
+    let mut data = Vec::new();
+    tokio::fs::File::open(output_dir.join(&segment))
+        .await?
+        .take(MAX_SCAN_BYTES as u64)
+        .read_to_end(&mut data)
+        .await?;
+    let (video, audio) = first_pts(&data);
+
+    let restart = {
+        let mut streams = state.active_streams.lock_or_recover();
+        let Some(running) = streams.get_mut(name) else {
+            return Ok(());
+        };
+        let tracker = &mut running.sync;
+        tracker.last_segment = Some(segment);
+        let Some(video) = video else {
+            return Ok(());
+        };
+
+        if let Some((prev, prev_duration)) = tracker.previous {
+            let gap = pts_diff_ms(video, prev) - prev_duration * 1000.0;
+            if gap.abs() > DISCONTINUITY_MS {
+                tracker.stats.discontinuities += 1;
+            }
+        }
+        tracker.previous = Some((video, duration));
+
+        let Some(audio) = audio else {
+            return Ok(());
+        };
+        let offset = pts_diff_ms(audio, video);
+        let baseline = *tracker.baseline_ms.get_or_insert(offset);
+        let drift = offset - baseline;
+        tracker.stats.av_offset_ms = Some(offset);
+        tracker.stats.drift_ms = Some(drift);
+
+        if drift.abs() <= cfg.max_drift_ms as f64 {
+            tracker.over_threshold = 0;
+            false
+        } else {
+            tracker.over_threshold += 1;
+            // 每次达到连续切片数时只处理一次
+            let fire = tracker.over_threshold == cfg.segments;
+            if fire {
+                warn!(
+                    "Stream [{}] A/V drift {:.0} ms exceeds {} ms for {} segments",
+                    name, drift, cfg.max_drift_ms, cfg.segments
+                );
+            }
+            fire && cfg.restart
+        }
+    };
+
+    if restart {
+        warn!(
+            "Restarting stream [{}] to resynchronize audio and video",
+            name
+        );
+        let _ = Engine::stop_stream(state, name).await;
+        if let Err(e) = Engine::start_stream(state, name).await {
+            error!("Restart failed [{}]: {}", name, e);
+        }
+    }
+    Ok(())
+}
+
+/// 播放列表中最后一个切片的地址与时长
+fn last_segment(playlist: &str) -> Option<(String, f64)> {
+    let mut duration = None;
+    let mut last = None;
+    for line in playlist.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            duration = rest.split(',').next().and_then(|d| d.parse::<f64>().ok());
+        } else if !line.is_empty() && !line.starts_with('#') {
+            // 只接受输出目录内的相对地址
+            if !line.contains(['/', '\\']) && !line.contains("..") {
+                last = duration.map(|d| (line.to_string(), d));
+            }
+            duration = None;
+        }
+    }
+    last
+}
+
+/// 切片中首个视频与首个音频 PES 的 PTS (90kHz)，按 PES stream_id 区分音视频
+fn first_pts(data: &[u8]) -> (Option<u64>, Option<u64>) {
+    let (mut video, mut audio) = (None, None);
+    for packet in data.chunks_exact(188) {
+        if video.is_some() && audio.is_some() {
+            break;
+        }
+        // 同步字节与 payload_unit_start_indicator
+        if packet[0] != 0x47 || packet[1] & 0x40 == 0 {
+            continue;
+        }
+        let adaptation = (packet[3] >> 4) & 0x3;
+        if adaptation & 0x1 == 0 {
+            continue;
+        }
+        let start = if adaptation & 0x2 != 0 {
+            5 + packet[4] as usize
+        } else {
+            4
+        };
+        let Some(pes) = packet.get(start..) else {
+            continue;
+        };
+        if pes.len() < 14 || pes[..3] != [0, 0, 1] || pes[7] & 0x80 == 0 {
+            continue;
+        }
+        let pts = ((pes[9] as u64 >> 1) & 0x7) << 30
+            | (pes[10] as u64) << 22
+            | (pes[11] as u64 >> 1) << 15
+            | (pes[12] as u64) << 7
+            | pes[13] as u64 >> 1;
+        match pes[3] {
+            0xE0..=0xEF => {
+                video.get_or_insert(pts);
+            }
+            // MPEG 音频或 private_stream_1 (AC-3 等)
+            0xC0..=0xDF | 0xBD => {
+                audio.get_or_insert(pts);
+            }
+            _ => {}
+        }
+    }
+    (video, audio)
+}
+
+/// 两个 33 位 PTS 之差 (毫秒)，处理回绕
+fn pts_diff_ms(a: u64, b: u64) -> f64 {
+    const WRAP: i64 = 1 << 33;
+    let mut diff = (a as i64 - b as i64).rem_euclid(WRAP);
+    if diff >= WRAP / 2 {
+        diff -= WRAP;
+    }
+    diff as f64 / 90.0
+}
@@ -0,0 +1,242 @@
+use crate::clock;
+use crate::config::AppConfig;
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// 事件日志文件 (位于 server.state_root，每行一条 JSON)
+const FILE_NAME: &str = "availability.jsonl";
+
+/// 事件日志的保留时间 (秒)，也是可查询的最长区间
+pub const MAX_PERIOD_SEC: u64 = 90 * 86400;
+
+/// 串行化追加与裁剪
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+/// 流的运行状态变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transition {
+    /// 进程启动
+    Up,
+    /// 进程意外退出，直到下一次启动 (或被有意停止) 之前计为停机
+    Down,
+    /// 进程被有意停止 (手动、空闲回收、网关关闭)，不计为停机
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Event {
+    /// Unix 秒数
+    time: u64,
+    stream: String,
+    transition: Transition,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// 一次停机
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    /// 开始时间 (RFC 3339，早于统计区间时截取到区间起点)
+    pub start: String,
+    /// 结束时间，仍在停机时为 None
+    pub end: Option<String>,
+    pub duration_sec: u64,
+    pub reason: Option<String>,
+}
+
+/// 一个流在统计区间内的可用性
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub stream: String,
+    /// 统计区间 (RFC 3339)；起点不早于日志中该流的第一条事件
+    pub from: String,
+    pub to: String,
+    pub period_sec: u64,
+    pub downtime_sec: u64,
+    /// 日志中没有该流的事件时为 None
+    pub availability_percent: Option<f64>,
+    pub incidents: Vec<Incident>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn format_secs(secs: u64) -> String {
+    clock::rfc3339(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn file_path(config: &AppConfig) -> PathBuf {
+    PathBuf::from(&config.server.state_root).join(FILE_NAME)
+}
+
+/// 追加一条状态变化，写入失败时仅记录日志
+pub fn record(state: &AppState, name: &str, transition: Transition, reason: Option<String>) {
+    let event = Event {
+        time: now_secs(),
+        stream: name.to_string(),
+        transition,
+        reason,
+    };
+    let path = file_path(&state.config());
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let result = (|| -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(&line)
+    })();
+    if let Err(e) = result {
+        warn!("Failed to write availability event to {:?}: {}", path, e);
+    }
+}
+
+fn load(config: &AppConfig) -> Vec<Event> {
+    let Ok(text) = std::fs::read_to_string(file_path(config)) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// 启动时丢弃超过保留时间的事件；上次运行意外中止时为仍在运行的流补记停止
+pub fn prune(config: &AppConfig) {
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let events = load(config);
+    if events.is_empty() {
+        return;
+    }
+    let now = now_secs();
+    let cutoff = now.saturating_sub(MAX_PERIOD_SEC);
+
+    // 网关未正常关闭时最后一条为 up，此后的状态未知，按停止处理
+    let mut last: Vec<(&str, &Event)> = Vec::new();
+    for event in &events {
+        match last.iter_mut().find(|(name, _)| *name == event.stream) {
+            Some(entry) => entry.1 = event,
+            None => last.push((&event.stream, event)),
+        }
+    }
+    let unterminated: Vec<Event> = last
+        .iter()
+        .filter(|(_, e)| e.transition == Transition::Up)
+        .map(|(name, e)| Event {
+            time: e.time,
+            stream: name.to_string(),
+            transition: Transition::Stopped,
+            reason: Some("gateway exited without stopping the stream".to_string()),
+        })
+        .collect();
+    if !unterminated.is_empty() {
+        info!(
+            "Closing {} stream(s) left running by the previous gateway run",
+            unterminated.len()
+        );
+    }
+
+    let mut text = String::new();
+    for event in events
+        .iter()
+        .filter(|e| e.time >= cutoff)
+        .chain(&unterminated)
+    {
+        if let Ok(line) = serde_json::to_string(event) {
+            text.push_str(&line);
+            text.push('\n');
+        }
+    }
+    let path = file_path(config);
+    let tmp = path.with_extension("jsonl.tmp");
+    if let Err(e) = std::fs::write(&tmp, text).and_then(|_| std::fs::rename(&tmp, &path)) {
+        warn!("Failed to prune {:?}: {}", path, e);
+    }
+}
+
+/// 解析统计区间，如 `24h`、`7d` (不超过 MAX_PERIOD_SEC)
+pub fn parse_period(s: &str) -> Option<u64> {
+    let (value, unit) = s.split_at(s.len().checked_sub(1)?);
+    let value: u64 = value.parse().ok()?;
+    let sec = match unit {
+        "h" => value * 3600,
+        "d" => value * 86400,
+        _ => return None,
+    };
+    (sec > 0 && sec <= MAX_PERIOD_SEC).then_some(sec)
+}
+
+/// 统计流在最近 `period_sec` 秒内的可用性
+pub fn report(config: &AppConfig, name: &str, period_sec: u64) -> Report {
+    let to = now_secs();
+    let events: Vec<Event> = load(config)
+        .into_iter()
+        .filter(|e| e.stream == name && e.time <= to)
+        .collect();
+    let from = to
+        .saturating_sub(period_sec)
+        .max(events.first().map_or(to, |e| e.time));
+
+    let mut incidents = Vec::new();
+    // 当前停机的开始时间与原因
+    let mut down: Option<(u64, Option<String>)> = None;
+    let mut close = |down: &mut Option<(u64, Option<String>)>, end: Option<u64>| {
+        if let Some((start, reason)) = down.take() {
+            let start = start.max(from);
+            let end_time = end.unwrap_or(to);
+            if end_time > start || end.is_none() {
+                incidents.push(Incident {
+                    start: format_secs(start),
+                    end: end.map(format_secs),
+                    duration_sec: end_time.saturating_sub(start),
+                    reason,
+                });
+            }
+        }
+    };
+    for event in &events {
+        match event.transition {
+            // 连续的崩溃属于同一次停机
+            Transition::Down if down.is_none() => down = Some((event.time, event.reason.clone())),
+            Transition::Down => {}
+            Transition::Up | Transition::Stopped => {
+                if event.time < from {
+                    down = None;
+                } else {
+                    close(&mut down, Some(event.time));
+                }
+            }
+        }
+    }
+    close(&mut down, None);
+
+    let downtime_sec: u64 = incidents.iter().map(|i| i.duration_sec).sum();
+    let span = to - from;
+    let availability_percent = (span > 0).then(|| {
+        let up = span.saturating_sub(downtime_sec) as f64;
+        (up * 100_000.0 / span as f64).round() / 1000.0
+    });
+    Report {
+        stream: name.to_string(),
+        from: format_secs(from),
+        to: format_secs(to),
+        period_sec,
+        downtime_sec,
+        availability_percent,
+        incidents,
+    }
+}
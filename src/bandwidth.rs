@@ -0,0 +1,197 @@
+use crate::config::StreamConfig;
+use crate::state::{AppState, LockExt};
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 令牌桶限速器
+///
+/// 允许令牌余额透支，透支部分换算为调用方需要等待的时间，
+/// 从而让并发的下载按请求顺序公平地分享带宽
+pub struct RateLimiter {
+    /// 每秒补充的字节数
+    rate: f64,
+    inner: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// 当前可用字节数 (可为负)
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            inner: Mutex::new(Bucket {
+                tokens: bytes_per_sec as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// 由 Kbps 配置构建限速器，0 表示不限速
+    pub fn from_kbps(kbps: u64) -> Option<Arc<Self>> {
+        (kbps > 0).then(|| Arc::new(Self::new(kbps * 1000 / 8)))
+    }
+
+    /// 预留 n 字节，返回发送前需要等待的时长
+    pub fn reserve(&self, n: usize) -> Duration {
+        let mut bucket = self.inner.lock_or_recover();
+        let now = Instant::now();
+
+        // 补充令牌，桶容量为 1 秒的流量
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.rate);
+        bucket.last_refill = now;
+
+        bucket.tokens -= n as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        }
+    }
+}
+
+/// 计算出口速率的时间窗 (秒)
+const METER_WINDOW_SEC: u64 = 10;
+
+/// 出口流量计：按秒累计下发的字节数，给出最近时间窗内的平均速率
+pub struct EgressMeter {
+    epoch: Instant,
+    /// (自 epoch 起的秒数, 该秒内的字节数)，由旧到新
+    buckets: Mutex<VecDeque<(u64, u64)>>,
+    /// 创建以来下发的总字节数
+    total: AtomicU64,
+}
+
+impl Default for EgressMeter {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            buckets: Mutex::new(VecDeque::new()),
+            total: AtomicU64::new(0),
+        }
+    }
+}
+
+impl EgressMeter {
+    pub fn record(&self, bytes: usize) {
+        self.total.fetch_add(bytes as u64, Ordering::Relaxed);
+        let second = self.epoch.elapsed().as_secs();
+        let mut buckets = self.buckets.lock_or_recover();
+        match buckets.back_mut() {
+            Some((s, n)) if *s == second => *n += bytes as u64,
+            _ => buckets.push_back((second, bytes as u64)),
+        }
+        while buckets
+            .front()
+            .is_some_and(|(s, _)| s + METER_WINDOW_SEC <= second)
+        {
+            buckets.pop_front();
+        }
+    }
+
+    /// 最近时间窗内的平均速率 (bps)
+    pub fn rate_bps(&self) -> u64 {
+        let second = self.epoch.elapsed().as_secs();
+        let bytes: u64 = self
+            .buckets
+            .lock_or_recover()
+            .iter()
+            .filter(|(s, _)| s + METER_WINDOW_SEC > second)
+            .map(|(_, n)| n)
+            .sum();
+        bytes * 8 / METER_WINDOW_SEC
+    }
+
+    /// 创建以来 (即本次网关运行期间) 下发的总字节数
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+/// 流的出口流量计，首次下发时创建
+pub fn meter_for(state: &AppState, name: &str) -> Arc<EgressMeter> {
+    state
+        .egress_meters
+        .lock_or_recover()
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+/// 为数据流计量下发的字节数
+pub fn metered<S, E>(stream: S, meter: Arc<EgressMeter>) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    stream.inspect(move |item| {
+        if let Ok(chunk) = item {
+            meter.record(chunk.len());
+        }
+    })
+}
+
+/// 下发某个流的数据时需同时满足的限速器：租户配额、流配额与网关总带宽
+///
+/// 每个响应按数据块依次向共享的令牌桶预留额度，并发的观看者因此按块轮流获得带宽
+pub fn limiters_for(state: &AppState, cfg: &StreamConfig) -> Vec<Arc<RateLimiter>> {
+    let mut limiters = Vec::new();
+    if let Some(l) = cfg
+        .tenant
+        .as_ref()
+        .and_then(|t| state.tenant_limiters.get(t))
+    {
+        limiters.push(l.clone());
+    }
+
+    let mut streams = state.stream_limiters.lock_or_recover();
+    let kbps = cfg.max_bandwidth_kbps;
+    match streams.get(&cfg.name) {
+        Some((rate, limiter)) if *rate == kbps => limiters.push(limiter.clone()),
+        _ => match RateLimiter::from_kbps(kbps) {
+            Some(limiter) => {
+                streams.insert(cfg.name.clone(), (kbps, limiter.clone()));
+                limiters.push(limiter);
+            }
+            None => {
+                streams.remove(&cfg.name);
+            }
+        },
+    }
+    drop(streams);
+
+    limiters.extend(state.egress_limiter.clone());
+    limiters
+}
+
+/// 为数据流套上一组限速器，每个数据块需同时满足全部限速
+pub fn throttle<S, E>(
+    stream: S,
+    limiters: Vec<Arc<RateLimiter>>,
+) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: Send + 'static,
+{
+    futures_util::stream::unfold((stream, limiters), |(mut stream, limiters)| async move {
+        let item = stream.next().await?;
+        if let Ok(chunk) = &item {
+            let wait = limiters
+                .iter()
+                .map(|l| l.reserve(chunk.len()))
+                .max()
+                .unwrap_or(Duration::ZERO);
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        Some((item, (stream, limiters)))
+    })
+}
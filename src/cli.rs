@@ -0,0 +1,465 @@
+use crate::audit;
+use crate::completion::{self, Shell};
+use crate::config::{AppConfig, Backend, TokenConfig};
+use crate::credentials::{self, CredentialsArgs};
+use crate::http_client::{self, HttpResponse};
+use crate::import::{self, ImportArgs};
+use crate::mock::{self, MockArgs};
+use clap::{Args, Subcommand, ValueEnum};
+use serde_json::Value;
+use std::time::Duration;
+
+/// 管理 API 请求超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `logs -f` 的轮询间隔
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 管理子命令 (通过 HTTP API 操作运行中的实例)
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 查看与操作流
+    Streams {
+        #[command(subcommand)]
+        action: StreamsAction,
+    },
+    /// 查看流的 FFmpeg 日志
+    Logs {
+        name: String,
+        /// 持续输出新日志
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// 校验配置文件并检查 FFmpeg 是否可用 (不需要运行中的实例)
+    Check,
+    /// 将 MediaMTX 或 nginx-rtmp 配置转换为流配置 (如 `vtx-link import --from mediamtx.yml`)，
+    /// 无法转换的选项列在输出开头，不需要运行中的实例
+    Import(ImportArgs),
+    /// 管理凭据库的密钥与加密值 (如 `echo -n secret | vtx-link credentials encrypt`)，不需要运行中的实例
+    Credentials(CredentialsArgs),
+    /// 输出 Shell 补全脚本 (如 `vtx-link completions bash > /etc/bash_completion.d/vtx-link`)
+    Completions { shell: Shell },
+    /// 模拟 FFmpeg 进程 (server.backend 为 mock 时由网关启动)
+    #[command(hide = true)]
+    MockEngine(MockArgs),
+}
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// 面向终端的表格与文本
+    #[default]
+    Table,
+    /// 原样输出 API 返回的 JSON (供脚本与 Ansible 解析)
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StreamsAction {
+    /// 列出所有流及其状态
+    List,
+    /// 启动流
+    Start {
+        name: String,
+        /// 释放隔离并跳过崩溃退避
+        #[arg(long)]
+        force: bool,
+        /// 后台启动，立即返回任务 ID
+        #[arg(long = "async")]
+        background: bool,
+    },
+    /// 停止流
+    Stop { name: String },
+    /// 运维禁用流
+    Disable {
+        name: String,
+        /// 禁用原因
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// 恢复运维禁用的流
+    Enable { name: String },
+    /// 静默流的告警通知 (计划内维护)，流照常运行与监控
+    Mute {
+        name: String,
+        /// 静默时长 (如 `90m`、`2h`、`1d`)
+        #[arg(long = "for", value_parser = parse_duration)]
+        duration: u64,
+        /// 静默原因
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// 结束告警通知静默
+    Unmute { name: String },
+}
+
+/// 解析带单位 (s、m、h、d) 的时长，返回秒数
+fn parse_duration(s: &str) -> Result<u64, String> {
+    let err = || format!("invalid duration {:?} (expected e.g. 90m, 2h, 1d)", s);
+    let (value, unit) = s.split_at(s.len().checked_sub(1).ok_or_else(err)?);
+    let value: u64 = value.parse().map_err(|_| err())?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(err()),
+    };
+    value
+        .checked_mul(scale)
+        .filter(|sec| *sec > 0)
+        .ok_or_else(err)
+}
+
+/// 连接运行中实例的参数，缺省时从配置文件推断
+#[derive(Args, Debug)]
+pub struct ClientArgs {
+    /// 实例地址，默认取配置中的 server.listen
+    #[arg(long, global = true)]
+    pub url: Option<String>,
+    /// 管理令牌，默认取配置中的第一个管理员令牌
+    #[arg(long, global = true)]
+    pub token: Option<String>,
+    /// 输出格式
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table, global = true)]
+    pub output: OutputFormat,
+}
+
+/// 执行管理子命令
+///
+/// `root` 为完整的命令行定义，用于生成补全脚本
+pub async fn run(
+    command: Command,
+    config_path: &str,
+    args: ClientArgs,
+    root: clap::Command,
+) -> anyhow::Result<()> {
+    match command {
+        Command::Check => check(config_path, args.output),
+        Command::Import(import) => import_config(&import, args.output),
+        Command::Credentials(credentials) => credentials::run(&credentials, config_path),
+        Command::Completions { shell } => {
+            print!("{}", completion::generate(shell, root));
+            Ok(())
+        }
+        Command::Streams { action } => streams(&Client::new(config_path, args)?, action).await,
+        Command::Logs { name, follow } => {
+            logs(&Client::new(config_path, args)?, &name, follow).await
+        }
+        Command::MockEngine(args) => mock::run(args).await,
+    }
+}
+
+struct Client {
+    base: String,
+    token: Option<String>,
+    output: OutputFormat,
+}
+
+impl Client {
+    fn new(config_path: &str, args: ClientArgs) -> anyhow::Result<Self> {
+        let config = match (&args.url, &args.token) {
+            (Some(_), Some(_)) => None,
+            _ => Some(AppConfig::load(config_path).map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to load {} ({}); pass --url and --token instead",
+                    config_path,
+                    e
+                )
+            })?),
+        };
+        let base = match args.url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => local_url(&config.as_ref().unwrap().server.listen),
+        };
+        let token = args.token.or_else(|| {
+            config.as_ref().and_then(|c| {
+                c.auth.tokens.iter().find_map(|t| match t {
+                    TokenConfig::Plain(token) => Some(token.clone()),
+                    TokenConfig::Scoped { .. } => None,
+                })
+            })
+        });
+        Ok(Self {
+            base,
+            token,
+            output: args.output,
+        })
+    }
+
+    async fn send(&self, method: &str, path: &str, body: Option<&Value>) -> anyhow::Result<Value> {
+        let res = self.request(method, path, body).await?;
+        if !res.is_success() {
+            anyhow::bail!(
+                "{} {} failed with status {}: {}",
+                method,
+                path,
+                res.status,
+                String::from_utf8_lossy(&res.body).trim()
+            );
+        }
+        // 部分接口返回纯文本
+        Ok(serde_json::from_slice(&res.body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&res.body).into_owned())))
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> anyhow::Result<HttpResponse> {
+        let url = format!("{}{}", self.base, path);
+        let auth = self.token.as_ref().map(|t| format!("Bearer {}", t));
+        // 供审计日志记录操作人 (自报，令牌仍是权限依据)
+        let user = std::env::var("USER").ok();
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(auth) = &auth {
+            headers.push(("Authorization", auth));
+        }
+        if let Some(user) = &user {
+            headers.push((audit::USER_HEADER, user));
+        }
+        let body = body.map(serde_json::to_vec).transpose()?;
+        http_client::request(method, &url, &headers, body.as_deref(), REQUEST_TIMEOUT)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to reach {}: {}", self.base, e))
+    }
+
+    /// 输出操作结果：JSON 格式原样输出 (纯文本结果包装为 `{"message": ...}`)，否则输出文本或单行 JSON
+    fn print(&self, value: &Value) {
+        match (value, self.output) {
+            (Value::String(text), OutputFormat::Table) => println!("{}", text),
+            (Value::String(text), OutputFormat::Json) => {
+                println!("{}", serde_json::json!({ "message": text }))
+            }
+            (_, OutputFormat::Json) => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(value).unwrap_or_default()
+                )
+            }
+            _ => println!("{}", value),
+        }
+    }
+}
+
+/// 监听地址转换为本机可访问的 URL (通配地址改为回环地址)
+fn local_url(listen: &str) -> String {
+    let addr = listen
+        .replacen("0.0.0.0:", "127.0.0.1:", 1)
+        .replacen("[::]:", "[::1]:", 1);
+    format!("http://{}", addr)
+}
+
+async fn streams(client: &Client, action: StreamsAction) -> anyhow::Result<()> {
+    let value = match action {
+        StreamsAction::List => {
+            let value = client.send("GET", "/streams", None).await?;
+            if client.output == OutputFormat::Table {
+                print_streams(&value);
+                return Ok(());
+            }
+            value
+        }
+        StreamsAction::Start {
+            name,
+            force,
+            background,
+        } => {
+            let query: Vec<&str> = [(force, "force=true"), (background, "async=true")]
+                .into_iter()
+                .filter_map(|(set, param)| set.then_some(param))
+                .collect();
+            let mut path = format!("/streams/{}/start", name);
+            if !query.is_empty() {
+                path = format!("{}?{}", path, query.join("&"));
+            }
+            client.send("POST", &path, None).await?
+        }
+        StreamsAction::Stop { name } => {
+            client
+                .send("POST", &format!("/streams/{}/stop", name), None)
+                .await?
+        }
+        StreamsAction::Disable { name, reason } => {
+            let body = serde_json::json!({ "reason": reason });
+            client
+                .send("POST", &format!("/streams/{}/disable", name), Some(&body))
+                .await?
+        }
+        StreamsAction::Enable { name } => {
+            client
+                .send("POST", &format!("/streams/{}/enable", name), None)
+                .await?
+        }
+        StreamsAction::Mute {
+            name,
+            duration,
+            reason,
+        } => {
+            let body = serde_json::json!({ "duration_sec": duration, "reason": reason });
+            client
+                .send("POST", &format!("/streams/{}/mute", name), Some(&body))
+                .await?
+        }
+        StreamsAction::Unmute { name } => {
+            client
+                .send("DELETE", &format!("/streams/{}/mute", name), None)
+                .await?
+        }
+    };
+    client.print(&value);
+    Ok(())
+}
+
+fn print_streams(value: &Value) {
+    let streams = value["streams"].as_array().cloned().unwrap_or_default();
+    let mut rows = vec![[
+        "NAME".to_string(),
+        "STATUS".to_string(),
+        "VIEWERS".to_string(),
+        "UPTIME".to_string(),
+        "CRASHES".to_string(),
+        "TENANT".to_string(),
+    ]];
+    for s in &streams {
+        rows.push([
+            s["name"].as_str().unwrap_or_default().to_string(),
+            s["status"].as_str().unwrap_or_default().to_string(),
+            s["viewers"].as_u64().unwrap_or(0).to_string(),
+            format_duration(s["uptime_seconds"].as_u64().unwrap_or(0)),
+            s["crash_count"].as_u64().unwrap_or(0).to_string(),
+            s["tenant"].as_str().unwrap_or("-").to_string(),
+        ]);
+    }
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, w)| format!("{:<w$}", cell, w = *w))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// 秒数格式化为 `1d2h`、`3h4m`、`5m6s` 或 `7s`
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0 => "-".to_string(),
+        s if s >= 86_400 => format!("{}d{}h", s / 86_400, s % 86_400 / 3600),
+        s if s >= 3600 => format!("{}h{}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m{}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+async fn logs(client: &Client, name: &str, follow: bool) -> anyhow::Result<()> {
+    let mut after = 0;
+    let mut waiting = false;
+    loop {
+        let path = format!("/streams/{}/logs?after={}", name, after);
+        let res = client.request("GET", &path, None).await?;
+        // 跟随模式下等待流启动 (或重启)
+        if res.status == 409 && follow {
+            if !waiting {
+                eprintln!("Stream [{}] is not running, waiting...", name);
+                waiting = true;
+            }
+            after = 0;
+            tokio::time::sleep(FOLLOW_INTERVAL).await;
+            continue;
+        }
+        if !res.is_success() {
+            anyhow::bail!(
+                "Failed to read logs: {} {}",
+                res.status,
+                String::from_utf8_lossy(&res.body).trim()
+            );
+        }
+        waiting = false;
+        let value: Value = serde_json::from_slice(&res.body)?;
+        if value["dropped"].as_u64().unwrap_or(0) > 0 && after > 0 {
+            eprintln!("... {} line(s) skipped", value["dropped"]);
+        }
+        // JSON 格式每行输出一个对象 (NDJSON)，便于逐行解析
+        for line in value["lines"].as_array().into_iter().flatten() {
+            let text = line.as_str().unwrap_or_default();
+            match client.output {
+                OutputFormat::Table => println!("{}", text),
+                OutputFormat::Json => {
+                    println!("{}", serde_json::json!({ "stream": name, "line": text }))
+                }
+            }
+        }
+        if !follow {
+            return Ok(());
+        }
+        after = value["next"].as_u64().unwrap_or(after);
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+    }
+}
+
+/// 转换其他软件的配置并输出 (YAML 或 JSON)，转换摘要输出到 stderr
+fn import_config(args: &ImportArgs, output: OutputFormat) -> anyhow::Result<()> {
+    let import = import::convert(args)?;
+    match output {
+        OutputFormat::Table => print!("{}", import.to_yaml(&args.from)?),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&import)?),
+    }
+    eprintln!(
+        "{} stream(s) converted, {} item(s) need review",
+        import.streams.len(),
+        import.warnings.len()
+    );
+    Ok(())
+}
+
+/// 校验配置文件并检查 FFmpeg 可执行 (模拟后端不需要 FFmpeg)
+fn check(config_path: &str, output: OutputFormat) -> anyhow::Result<()> {
+    let config = AppConfig::load(config_path)
+        .map_err(|e| anyhow::anyhow!("{} is invalid: {}", config_path, e))?;
+    let ffmpeg = &config.server.ffmpeg_binary;
+    let version = if config.server.backend == Backend::Mock {
+        "not used (server.backend: mock)".to_string()
+    } else {
+        let res = std::process::Command::new(ffmpeg)
+            .arg("-version")
+            .output()
+            .map_err(|e| anyhow::anyhow!("FFmpeg binary {} cannot be executed: {}", ffmpeg, e))?;
+        String::from_utf8_lossy(&res.stdout)
+            .lines()
+            .next()
+            .unwrap_or("(no version output)")
+            .to_string()
+    };
+
+    match output {
+        OutputFormat::Table => {
+            println!(
+                "{}: OK ({} stream(s), {} tenant(s), {} template(s))",
+                config_path,
+                config.streams.len(),
+                config.tenants.len(),
+                config.stream_templates.len()
+            );
+            println!("{}: {}", ffmpeg, version);
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "config": config_path,
+                "valid": true,
+                "streams": config.streams.len(),
+                "tenants": config.tenants.len(),
+                "templates": config.stream_templates.len(),
+                "ffmpeg": { "binary": ffmpeg, "version": version },
+            }))
+            .unwrap_or_default()
+        ),
+    }
+    Ok(())
+}
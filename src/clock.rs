@@ -0,0 +1,118 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 将系统时间格式化为 RFC 3339 UTC 时间 (如 `2024-01-31T08:00:00Z`)
+pub fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// 将系统时间格式化为带毫秒的 RFC 3339 UTC 时间 (如 `2024-01-31T08:00:00.250Z`)
+pub fn rfc3339_millis(time: SystemTime) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_millis())
+        .unwrap_or(0);
+    format!("{}.{:03}Z", rfc3339(time).trim_end_matches('Z'), millis)
+}
+
+/// 将系统时间格式化为 RFC 2822 时间 (如 `Wed, 31 Jan 2024 08:00:00 +0000`)，用于邮件头
+pub fn rfc2822(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{}, {} {} {:04} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// 将系统时间格式化为紧凑的 UTC 时间 (如 `20240131T080000Z`)，可安全用作文件名
+pub fn compact(time: SystemTime) -> String {
+    rfc3339(time).replace(['-', ':'], "")
+}
+
+/// 解析 RFC 3339 时间 (如 `2024-01-31T08:00:00Z` 或 `2024-01-31T16:00:00+08:00`)，返回 Unix 秒数
+pub fn parse_rfc3339(s: &str) -> Option<u64> {
+    let (date, time) = s.split_once(['T', 't', ' '])?;
+    let mut d = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (d.next()??, d.next()??, d.next()??);
+
+    // 时区：Z 或 ±HH:MM
+    let (clock, offset) = if let Some(c) = time.strip_suffix(['Z', 'z']) {
+        (c, 0)
+    } else {
+        let at = time.rfind(['+', '-'])?;
+        let (h, m) = time[at + 1..].split_once(':')?;
+        let minutes = h.parse::<i64>().ok()? * 60 + m.parse::<i64>().ok()?;
+        let sign = if time.as_bytes()[at] == b'-' { -1 } else { 1 };
+        (&time[..at], sign * minutes * 60)
+    };
+    let mut t = clock.split(':');
+    let (hour, minute) = (
+        t.next()?.parse::<i64>().ok()?,
+        t.next()?.parse::<i64>().ok()?,
+    );
+    // 忽略小数秒
+    let second = t.next()?.split('.').next()?.parse::<i64>().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    let secs = days_from_civil(year, month as u32, day as u32) * 86400
+        + hour * 3600
+        + minute * 60
+        + second
+        - offset;
+    u64::try_from(secs).ok()
+}
+
+/// 由公历日期计算 Unix 纪元以来的天数 (Howard Hinnant 算法)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// 由 Unix 纪元以来的天数计算公历日期 (Howard Hinnant 算法)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
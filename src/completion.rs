@@ -0,0 +1,300 @@
+use clap::{Command, ValueEnum};
+use std::collections::BTreeSet;
+
+/// 支持生成补全脚本的 Shell
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    /// 通过 bashcompinit 复用 bash 补全
+    Zsh,
+    Fish,
+}
+
+/// 根据命令行定义生成补全脚本
+///
+/// 子命令、参数与可选值全部取自传入的 clap 定义 (`Args::command()`)，不单独维护，
+/// 隐藏的子命令与参数 (如 `mock-engine`) 不出现在补全中
+pub fn generate(shell: Shell, mut root: Command) -> String {
+    // 展开 global 参数与自动生成的 help / version
+    root.build();
+    match shell {
+        Shell::Bash => bash(&root),
+        Shell::Zsh => format!(
+            "autoload -U +X bashcompinit && bashcompinit\n{}",
+            bash(&root)
+        ),
+        Shell::Fish => fish(&root),
+    }
+}
+
+/// 依次访问每一级子命令 (状态名为以 `__` 连接的命令路径)，自动生成的 help 子命令不展开
+fn visit<'a>(cmd: &'a Command, state: String, f: &mut impl FnMut(&str, &'a Command)) {
+    f(&state, cmd);
+    for sub in subcommands(cmd).filter(|s| s.get_name() != "help") {
+        visit(sub, format!("{}__{}", state, sub.get_name()), f);
+    }
+}
+
+fn subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands().filter(|s| !s.is_hide_set())
+}
+
+fn arguments(cmd: &Command) -> impl Iterator<Item = &clap::Arg> {
+    cmd.get_arguments().filter(|a| !a.is_hide_set())
+}
+
+/// 位置参数的可选值 (如 `completions` 的 Shell 名称)
+fn positional_values(cmd: &Command) -> impl Iterator<Item = String> + '_ {
+    arguments(cmd)
+        .filter(|arg| arg.is_positional())
+        .flat_map(|arg| arg.get_possible_values())
+        .map(|v| v.get_name().to_string())
+}
+
+fn flags(cmd: &Command) -> impl Iterator<Item = String> + '_ {
+    arguments(cmd).flat_map(|arg| {
+        let long = arg.get_long().map(|l| format!("--{}", l));
+        let short = arg.get_short().map(|s| format!("-{}", s));
+        long.into_iter().chain(short)
+    })
+}
+
+fn takes_value(arg: &clap::Arg) -> bool {
+    !arg.is_positional() && arg.get_action().takes_values()
+}
+
+fn bash(root: &Command) -> String {
+    let name = root.get_name();
+    let func = format!("_{}", name.replace('-', "_"));
+    let mut transitions = String::new();
+    let mut options = String::new();
+    let mut value_flags = BTreeSet::new();
+    let mut value_cases = String::new();
+
+    visit(root, func.clone(), &mut |state, cmd| {
+        for sub in subcommands(cmd).filter(|s| s.get_name() != "help") {
+            transitions.push_str(&format!(
+                "            {state},{sub}) state={state}__{sub} ;;\n",
+                sub = sub.get_name()
+            ));
+        }
+        let words: Vec<String> = subcommands(cmd)
+            .map(|s| s.get_name().to_string())
+            .chain(positional_values(cmd))
+            .chain(flags(cmd))
+            .collect();
+        options.push_str(&format!(
+            "        {}) opts=\"{}\" ;;\n",
+            state,
+            words.join(" ")
+        ));
+        for arg in arguments(cmd).filter(|a| takes_value(a)) {
+            let names: Vec<String> = arg
+                .get_long()
+                .map(|l| format!("--{}", l))
+                .into_iter()
+                .chain(arg.get_short().map(|s| format!("-{}", s)))
+                .filter(|n| value_flags.insert(n.clone()))
+                .collect();
+            if names.is_empty() {
+                continue;
+            }
+            let values: Vec<String> = arg
+                .get_possible_values()
+                .iter()
+                .map(|v| v.get_name().to_string())
+                .collect();
+            let reply = if values.is_empty() {
+                "compgen -f -- \"$cur\"".to_string()
+            } else {
+                format!("compgen -W \"{}\" -- \"$cur\"", values.join(" "))
+            };
+            value_cases.push_str(&format!(
+                "        {}) COMPREPLY=($({})); return ;;\n",
+                names.join("|"),
+                reply
+            ));
+        }
+    });
+    let value_flags = value_flags.into_iter().collect::<Vec<_>>().join("|");
+
+    format!(
+        r#"{func}() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local state={func} opts="" skip=0 i w
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        w="${{COMP_WORDS[i]}}"
+        if ((skip)); then
+            skip=0
+            continue
+        fi
+        case "$w" in
+            {value_flags}) skip=1; continue ;;
+        esac
+        case "$state,$w" in
+{transitions}        esac
+    done
+    case "$prev" in
+{value_cases}    esac
+    case "$state" in
+{options}    esac
+    COMPREPLY=($(compgen -W "$opts" -- "$cur"))
+}}
+complete -F {func} {name}
+"#
+    )
+}
+
+fn fish(root: &Command) -> String {
+    let name = root.get_name();
+    let mut out = format!("complete -c {} -f\n", name);
+    let escape = |text: String| text.replace('\\', "\\\\").replace('\'', "\\'");
+    let about = |cmd: &Command| {
+        cmd.get_about()
+            .map(|a| format!(" -d '{}'", escape(a.to_string())))
+            .unwrap_or_default()
+    };
+
+    visit(root, String::new(), &mut |state, cmd| {
+        let path: Vec<&str> = state.split("__").filter(|s| !s.is_empty()).collect();
+        let children: Vec<&str> = subcommands(cmd).map(|s| s.get_name()).collect();
+        // 当前命令已输入、其子命令尚未输入时补全子命令
+        let (here, pending) = match path.last() {
+            Some(last) => {
+                let here = format!(" -n '__fish_seen_subcommand_from {}'", last);
+                let pending = format!(
+                    " -n '__fish_seen_subcommand_from {}; and not __fish_seen_subcommand_from {}'",
+                    last,
+                    children.join(" ")
+                );
+                (here, pending)
+            }
+            None => (String::new(), " -n '__fish_use_subcommand'".to_string()),
+        };
+        for sub in subcommands(cmd) {
+            out.push_str(&format!(
+                "complete -c {}{} -a {}{}\n",
+                name,
+                pending,
+                sub.get_name(),
+                about(sub)
+            ));
+        }
+        let values: Vec<String> = positional_values(cmd).collect();
+        if !values.is_empty() {
+            out.push_str(&format!(
+                "complete -c {}{} -a '{}'\n",
+                name,
+                here,
+                values.join(" ")
+            ));
+        }
+        for arg in arguments(cmd).filter(|a| !a.is_positional()) {
+            let mut line = format!("complete -c {}{}", name, here);
+            if let Some(long) = arg.get_long() {
+                line.push_str(&format!(" -l {}", long));
+            }
+            if let Some(short) = arg.get_short() {
+                line.push_str(&format!(" -s {}", short));
+            }
+            if takes_value(arg) {
+                let values: Vec<String> = arg
+                    .get_possible_values()
+                    .iter()
+                    .map(|v| v.get_name().to_string())
+                    .collect();
+                if values.is_empty() {
+                    line.push_str(" -r -F");
+                } else {
+                    line.push_str(&format!(" -x -a '{}'", values.join(" ")));
+                }
+            }
+            if let Some(help) = arg.get_help() {
+                line.push_str(&format!(" -d '{}'", escape(help.to_string())));
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{ClientArgs, Command as CliCommand};
+    use clap::{Args, Subcommand};
+    use std::process::Command as Process;
+
+    /// 与 main.rs 的 `Args` 相同的子命令与全局参数
+    fn root() -> Command {
+        let root = Command::new("vtx-link").arg(
+            clap::Arg::new("config")
+                .short('c')
+                .long("config")
+                .global(true),
+        );
+        CliCommand::augment_subcommands(ClientArgs::augment_args(root))
+    }
+
+    /// 每个可见的子命令与长参数都出现在生成的脚本中，隐藏的子命令不出现
+    #[test]
+    fn scripts_follow_cli_definition() {
+        let bash = generate(Shell::Bash, root());
+        let fish = generate(Shell::Fish, root());
+        let mut built = root();
+        built.build();
+        let mut checked = 0;
+        visit(&built, String::new(), &mut |_, cmd| {
+            for sub in subcommands(cmd) {
+                let name = sub.get_name();
+                assert!(bash.contains(&format!(",{}) state=", name)) || name == "help");
+                assert!(fish.contains(&format!(" -a {}", name)), "{}", name);
+                checked += 1;
+            }
+            for long in arguments(cmd).filter_map(|a| a.get_long()) {
+                assert!(bash.contains(&format!("--{}", long)), "{}", long);
+                assert!(fish.contains(&format!(" -l {}", long)), "{}", long);
+                checked += 1;
+            }
+        });
+        assert!(checked > 20);
+        assert!(!bash.contains("mock-engine"));
+        assert!(!fish.contains("mock-engine"));
+    }
+
+    /// 在 bash 中加载脚本并模拟补全
+    #[test]
+    fn bash_completes_subcommands_and_values() {
+        let script = generate(Shell::Bash, root());
+        let complete = |words: &str| {
+            let program = format!(
+                "{}\nCOMP_WORDS=({}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1)); _vtx_link; echo \"${{COMPREPLY[*]}}\"",
+                script, words
+            );
+            let Ok(out) = Process::new("bash").arg("-c").arg(program).output() else {
+                return None;
+            };
+            assert!(
+                out.status.success(),
+                "{}",
+                String::from_utf8_lossy(&out.stderr)
+            );
+            Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+        };
+        let Some(top) = complete("vtx-link ''") else {
+            return;
+        };
+        assert!(top.split(' ').any(|w| w == "streams"), "{}", top);
+        assert!(!top.contains("mock-engine"));
+        assert_eq!(complete("vtx-link streams st").unwrap(), "start stop");
+        assert_eq!(
+            complete("vtx-link -c x.yaml completions f").unwrap(),
+            "fish"
+        );
+        assert_eq!(
+            complete("vtx-link streams list --output j").unwrap(),
+            "json"
+        );
+    }
+}
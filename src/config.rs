@@ -1,41 +1,1608 @@
-use serde::Deserialize;
-use std::path::Path;
+use crate::abr;
+use crate::credentials;
+use crate::dependency;
+use crate::http_client::{self, Url};
+use crate::mosaic;
+use crate::nettest;
+use crate::pattern::Pattern;
+use crate::playout;
+use crate::sessions;
+use crate::templates;
+use crate::test_source;
+use crate::updater;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
     pub server: ServerConfig,
     #[serde(default)]
     pub streams: Vec<StreamConfig>,
+
+    /// 访问控制配置
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    /// 将播放请求交给外部鉴权服务判断，未配置时不启用
+    #[serde(default)]
+    pub forward_auth: Option<ForwardAuthConfig>,
+
+    /// 媒体请求的 JWT 播放令牌校验，未配置时不启用
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+
+    /// 租户列表
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+
+    /// 集群代理模式，配置后向中央控制器注册并接收流分配
+    #[serde(default)]
+    pub agent: Option<AgentConfig>,
+
+    /// ONVIF 设备发现
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+
+    /// 对所有流生效的 FFmpeg 日志匹配规则
+    #[serde(default)]
+    pub stderr_matchers: Vec<StderrMatcher>,
+
+    /// 本地告警规则：按内部指标判断，离线时也能告警与自愈
+    #[serde(default)]
+    pub alert_rules: Vec<AlertRule>,
+
+    /// 告警的邮件 / Telegram 通知渠道
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// 硬件编码 (NVENC / VAAPI / QSV 等) 资源管理
+    #[serde(default)]
+    pub hwaccel: HwAccelConfig,
+
+    /// 向外部监控 (healthchecks.io 等 dead man's switch) 定期推送心跳
+    #[serde(default)]
+    pub heartbeat: HeartbeatConfig,
+
+    /// 自更新 (POST /sys/update 或控制器指令触发)，未配置时不可用
+    #[serde(default)]
+    pub update: Option<UpdateConfig>,
+
+    /// 以非 root 用户运行 (先监听端口再切换用户)，未配置时不切换
+    #[serde(default)]
+    pub privileges: Option<PrivilegeConfig>,
+
+    /// FFmpeg 子进程沙箱 (Linux)，未配置时不启用
+    #[serde(default)]
+    pub sandbox: Option<SandboxConfig>,
+
+    /// 网络测速 (GET /sys/nettest) 使用的对端地址，未配置时只能以生成数据的方式测速
+    #[serde(default)]
+    pub nettest: Option<NetTestConfig>,
+
+    /// 流模板，通过 POST /templates/:name/instantiate 按参数生成新流
+    #[serde(default)]
+    pub stream_templates: Vec<StreamTemplate>,
+
+    /// 负载卸载：内存或 CPU 压力过高时按优先级停止流，未配置时不启用
+    #[serde(default)]
+    pub load_shedding: Option<LoadSheddingConfig>,
+
+    /// 观看会话分析：记录每个观看会话并分批导出到 HTTP 接口或本地 CSV 文件，未配置时不记录
+    #[serde(default)]
+    pub analytics: Option<AnalyticsConfig>,
+
+    /// 分批应用配置：导入或声明式更新改动了运行中的流时分批重启并检查其能否就绪，
+    /// 失败过多时自动回滚；未配置时一次性停止受影响的流
+    #[serde(default)]
+    pub rollout: Option<RolloutConfig>,
+
+    /// 凭据库 (名称 -> 凭据)，流的源地址以 `{cred:名称}` 引用，避免在配置各处重复明文密码
+    #[serde(default)]
+    pub credentials: BTreeMap<String, Credential>,
+
+    /// 解密 `enc:` 前缀凭据的密钥文件 (由 `vtx-link credentials keygen` 生成)
+    #[serde(default)]
+    pub credentials_key_file: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServerConfig {
     pub listen: String,
     pub ffmpeg_binary: String,
     pub supervisor_interval_ms: u64,
 
     /// HLS 切片存储根目录
-    /// 建议配置为 /dev/shm/vtx-hls 以保护闪存寿命
+    /// 建议配置为内存盘 (Linux 的 /dev/shm/vtx-hls，Windows 的 RAM 盘) 以保护闪存寿命
     #[serde(default = "default_hls_root")]
     pub hls_root: String,
+
+    /// 流输出目录模板，可用 `{hls_root}`、`{tenant}`、`{name}`、`{id}`
+    /// (默认命名空间的流 `{tenant}` 为空，对应的路径层级被省略)
+    #[serde(default = "default_hls_layout")]
+    pub hls_layout: String,
+
+    /// 清理 hls_root 下残留目录的间隔 (秒，0 表示只能通过 `POST /sys/gc` 手动清理)，启动时先清理一次
+    /// 不属于任何已配置流的目录 (流被删除或改名) 总会被清理
+    #[serde(default = "default_hls_gc_interval")]
+    pub hls_gc_interval_sec: u64,
+
+    /// 未运行的流的输出目录超过该时长 (小时) 未更新时同样清理 (0 表示保留)
+    #[serde(default)]
+    pub hls_gc_idle_hours: u64,
+
+    /// 加密密钥存储目录
+    /// 必须位于 hls_root 之外，避免密钥文件被当作切片直接下发
+    #[serde(default = "default_key_root")]
+    pub key_root: String,
+
+    /// 观看会话超时 (秒)：超过该时间未请求播放列表的观看者视为离开
+    #[serde(default = "default_session_timeout")]
+    pub session_timeout_sec: u64,
+
+    /// 录像存储目录 (运动检测等)
+    #[serde(default = "default_record_root")]
+    pub record_root: String,
+
+    /// 运行状态存储目录 (运维禁用的流等需跨重启保留的状态)
+    #[serde(default = "default_state_root")]
+    pub state_root: String,
+
+    /// 网关下发总带宽上限 (Mbps，0 表示不限)，所有流与观看者公平分享
+    #[serde(default)]
+    pub max_egress_mbps: f64,
+
+    /// 用于核对系统时钟的 NTP 服务器 (`host` 或 `host:port`)，未配置时不核对
+    #[serde(default)]
+    pub ntp_server: Option<String>,
+
+    /// 允许的系统时钟偏差 (毫秒)，超出时告警
+    #[serde(default = "default_max_clock_skew")]
+    pub max_clock_skew_ms: u64,
+
+    /// 排空期间建议观看者改用的节点基础 URL (如 `https://node-b.example.com`)，
+    /// 被拒绝请求的路径会附加其后
+    #[serde(default)]
+    pub drain_alternate_url: Option<String>,
+
+    /// 排空期间返回的 Retry-After (秒)
+    #[serde(default = "default_drain_retry_after")]
+    pub drain_retry_after_sec: u64,
+
+    /// 启动宽限期 (秒)：期间 /readyz 不要求 auto_start 流已运行
+    #[serde(default = "default_ready_grace")]
+    pub ready_grace_sec: u64,
+
+    /// 就绪所需运行中的 auto_start 流数量，缺省要求全部 (运维禁用或手动停止的流除外)
+    #[serde(default)]
+    pub ready_min_auto_start: Option<usize>,
+
+    /// 流进程的后端，`mock` 时无需 FFmpeg 与真实的源
+    #[serde(default)]
+    pub backend: Backend,
+
+    /// 不在 FFmpeg 命令行中传递带凭据的源地址：改为写入 key_root 下仅属主可读的
+    /// concat 列表 (附按协议生成的 `-protocol_whitelist`)，`ps` 中只能看到列表路径
+    #[serde(default)]
+    pub hide_source_credentials: bool,
+
+    /// 多个流使用同一源地址时只由第一个流 (配置顺序) 拉取：其 FFmpeg 额外输出源的 MPEG-TS 副本，
+    /// 其余流经网关的本地回环读取，适用于只允许一个客户端连接的摄像机
+    #[serde(default)]
+    pub share_sources: bool,
+
+    /// 运行状态 (运维禁用、手动操作、故障恢复) 的持久化方式
+    #[serde(default)]
+    pub state_store: StateStore,
+
+    /// 是否保持 HTTP/1.1 长连接，播放器可在同一个连接上连续请求播放列表与切片，
+    /// 高延迟链路上省去每次请求的 TCP 握手
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: bool,
+
+    /// 长连接的空闲超时 (秒)：等待下一个请求头的最长时间，同时限制慢速客户端发送请求头，
+    /// 0 表示不限 (仅在启动时生效)
+    #[serde(default = "default_keep_alive_timeout")]
+    pub keep_alive_timeout_sec: u64,
+
+    /// MaxMind GeoIP2 / GeoLite2 (Country 或 City) 数据库路径，供流的 `geo` 限制与观看者地区统计使用
+    #[serde(default)]
+    pub geoip_db: Option<String>,
+
+    /// 可信的反向代理 (IP 地址或 CIDR 网段，如 `10.0.0.0/8`、`::1`)：只有来自这些地址的请求
+    /// 才采用 `X-Forwarded-For`，并取其中自右向左第一个不属于可信代理的地址作为客户端地址。
+    /// 为空时一律使用连接的对端地址
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+
+    /// 附加到 HLS 播放列表与切片响应的 HTTP 头 (如 `Timing-Allow-Origin`、CDN 缓存键)，
+    /// 可被流的 `hls_headers` 覆盖
+    #[serde(default)]
+    pub hls_headers: BTreeMap<String, String>,
+
+    /// 同时保持的 HTTP 连接上限，达到上限后新连接在内核队列中等待 (0 表示不限，仅在启动时生效)
+    #[serde(default)]
+    pub max_connections: usize,
+
+    /// 异步运行时的工作线程数，缺省为 CPU 核数 (仅在启动时生效)
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+
+    /// 阻塞操作 (文件读写、DNS 解析等) 线程池的上限，缺省为 512 (仅在启动时生效)
+    #[serde(default)]
+    pub max_blocking_threads: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// 运行状态的持久化方式，均保存在 server.state_root 下
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StateStore {
+    /// 每次变化时整体写入 runtime_state.json
+    #[default]
+    Json,
+    /// 写入 runtime_state.db (SQLite，需以 `sqlite` 特性编译)
+    Sqlite,
+}
+
+/// 流进程后端
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// 启动 server.ffmpeg_binary
+    #[default]
+    Ffmpeg,
+    /// 由网关自身模拟 FFmpeg：写出合成的播放列表与切片，并按流的 `mock` 脚本崩溃或卡住，
+    /// 供 CI 中的集成测试使用
+    Mock,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AuthConfig {
+    /// 允许访问受保护资源 (如解密密钥) 的令牌列表
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// 是否要求管理 API 携带令牌
+    #[serde(default)]
+    pub protect_api: bool,
+}
+
+/// 访问令牌，可直接写为字符串 (管理员令牌) 或限定到某个租户
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum TokenConfig {
+    Plain(String),
+    Scoped {
+        token: String,
+        /// 令牌所属租户，缺省表示管理员令牌
+        #[serde(default)]
+        tenant: Option<String>,
+        /// 令牌标识 (用于统计与吊销)，缺省由令牌摘要生成
+        #[serde(default)]
+        id: Option<String>,
+    },
+}
+
+impl TokenConfig {
+    pub fn token(&self) -> &str {
+        match self {
+            Self::Plain(token) => token,
+            Self::Scoped { token, .. } => token,
+        }
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Self::Plain(_) => None,
+            Self::Scoped { tenant, .. } => tenant.as_deref(),
+        }
+    }
+
+    /// 令牌标识：配置的 `id`，缺省为 `cfg-` 加令牌摘要的前 12 位 (不泄露令牌本身)
+    pub fn id(&self) -> String {
+        match self {
+            Self::Scoped { id: Some(id), .. } => id.clone(),
+            _ => format!("cfg-{}", &crate::tokens::digest(self.token())[..12]),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AgentConfig {
+    /// 控制器地址 (仅支持 http://，须为本机地址，如 HTTPS 转发代理)
+    pub controller_url: String,
+    /// 节点标识，缺省使用主机名
+    #[serde(default)]
+    pub node_id: Option<String>,
+    /// 访问控制器使用的 Bearer 令牌
+    #[serde(default)]
+    pub token: Option<String>,
+    /// 心跳间隔 (秒)
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval_sec: u64,
+    /// 指令长轮询等待时间 (秒)
+    #[serde(default = "default_poll_timeout")]
+    pub poll_timeout_sec: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DiscoveryConfig {
+    /// 访问摄像机使用的默认账号
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// 自动添加流时使用的模板 (部分流配置，name/source 由发现结果填充)
+    #[serde(default)]
+    pub template: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpdateConfig {
+    /// 新版本二进制的下载地址 (http)
+    pub url: String,
+    /// 发布清单的下载地址，默认为 `{url}.manifest`
+    /// 清单为 JSON `{version, target, sha256}`，见 `updater::Manifest`
+    #[serde(default)]
+    pub manifest_url: Option<String>,
+    /// 签名下载地址，默认为 `{manifest_url}.sig`
+    /// 签名为清单文件的 Ed25519 签名，64 字节原始数据或其十六进制文本
+    #[serde(default)]
+    pub signature_url: Option<String>,
+    /// 发布者的 Ed25519 公钥 (64 位十六进制)
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NetTestConfig {
+    /// 下行测速的下载地址 (http)，如另一台网关的 `/sys/nettest?mode=junk`
+    #[serde(default)]
+    pub download_url: Option<String>,
+    /// 上行测速的上传地址 (http)，网关以 POST 发送生成的数据
+    #[serde(default)]
+    pub upload_url: Option<String>,
+    /// 访问对端时携带的 Bearer 令牌
+    #[serde(default)]
+    pub token: Option<String>,
+    /// 每个方向默认传输的字节数
+    #[serde(default = "default_nettest_bytes")]
+    pub bytes: u64,
+    /// 每个方向的超时 (秒)，超时后按已传输的数据计算速率
+    #[serde(default = "default_nettest_timeout")]
+    pub timeout_sec: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrivilegeConfig {
+    /// 监听端口后网关切换到的用户 (用户名或数字 ID)
+    #[serde(default)]
+    pub user: Option<String>,
+    /// 网关切换到的组，缺省为用户的主组
+    #[serde(default)]
+    pub group: Option<String>,
+    /// FFmpeg 子进程使用的用户；与 user 不同时网关须保留 root (不配置 user)
+    #[serde(default)]
+    pub ffmpeg_user: Option<String>,
+    /// FFmpeg 子进程使用的组，缺省为 ffmpeg_user 的主组
+    #[serde(default)]
+    pub ffmpeg_group: Option<String>,
+    /// 启动时将 hls_root、key_root、record_root (以及 state_root) 的属主改为对应用户
+    #[serde(default = "default_chown_dirs")]
+    pub chown_dirs: bool,
+}
+
+/// 沙箱始终设置 no_new_privs (子进程无法通过 setuid 程序提权)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SandboxConfig {
+    /// 通过 Landlock (Linux 5.13+) 限制 FFmpeg 只能写入本流的输出目录
+    #[serde(default = "default_landlock")]
+    pub landlock: bool,
+    /// 额外允许写入的目录
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+    /// 通过 seccomp 拦截挂载、ptrace、内核模块等系统调用 (返回 EPERM)
+    #[serde(default)]
+    pub seccomp: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamTemplate {
+    pub name: String,
+    /// 实例化时必须提供的参数，字符串中的 `{参数}` 被替换为对应的值
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// 部分流配置 (name 在实例化时指定)
+    pub stream: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HwAccelConfig {
+    /// 硬件编码会话上限 (0 表示不限)，如消费级 NVIDIA 显卡的 NVENC 并发限制
+    /// 流的会话数按 output_args 中的硬件编码器 (如 `h264_nvenc`、`h264_vaapi`) 计算
+    #[serde(default)]
+    pub max_encode_sessions: u32,
+    /// GPU/VPU 占用采集间隔 (秒)
+    #[serde(default = "default_gpu_poll_interval")]
+    pub poll_interval_sec: u64,
+}
+
+impl Default for HwAccelConfig {
+    fn default() -> Self {
+        Self {
+            max_encode_sessions: 0,
+            poll_interval_sec: default_gpu_poll_interval(),
+        }
+    }
+}
+
+/// 防盗链规则：请求的 Origin (没有时取 Referer) 的主机名需匹配其中任一正则
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RefererPolicy {
+    /// 允许的主机名 (如 `^(www\.)?partner\.com$`)，主机名转为小写后匹配；加载配置时编译，
+    /// 语法错误使配置无效
+    #[serde(default)]
+    pub allow: Vec<Pattern>,
+    /// 是否允许既没有 Origin 也没有 Referer 的请求 (原生播放器、直接打开链接)
+    #[serde(default)]
+    pub allow_empty: bool,
+}
+
+/// 按国家/地区的播放限制，代码为 ISO 3166-1 两位字母 (如 `DE`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GeoRestriction {
+    /// 只允许这些国家/地区，为空时不限
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// 拒绝这些国家/地区 (优先于 allow)
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// 无法判断国家/地区 (内网地址或库中没有) 时是否允许；未配置 allow 时总是允许
+    #[serde(default)]
+    pub allow_unknown: bool,
+}
+
+/// 外部鉴权：每个 HLS / MPEG-TS 播放请求先询问鉴权服务，决定短暂缓存
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForwardAuthConfig {
+    /// 鉴权服务地址 (仅支持 http://)，以 POST 发送 JSON：
+    /// `{"stream", "tenant", "client_ip", "token", "path", "protocol"}`；
+    /// 2xx 表示允许，401/403 原样返回给播放器，其他状态视为鉴权服务故障
+    pub url: String,
+    /// 调用鉴权服务时携带的 Bearer 令牌
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    /// 只对名称匹配其中任一正则的流鉴权，为空时对所有流鉴权
+    #[serde(default)]
+    pub streams: Vec<String>,
+    /// 单次鉴权请求的超时 (毫秒)
+    #[serde(default = "default_forward_auth_timeout")]
+    pub timeout_ms: u64,
+    /// 同一流、客户端地址与令牌的决定缓存的时间 (秒，0 表示不缓存)，播放列表与切片共用
+    #[serde(default = "default_forward_auth_cache_ttl")]
+    pub cache_ttl_sec: u64,
+    /// 鉴权服务不可用时是否放行 (缺省拒绝，返回 503)
+    #[serde(default)]
+    pub fail_open: bool,
+}
+
+/// JWT 播放令牌：HLS / MPEG-TS 请求须携带有效的 JWT (Bearer 或 `token` 查询参数)，
+/// 支持 HS256 (共享密钥) 与 RS256 (JWKS 公钥)，`exp` 必需
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JwtConfig {
+    /// HS256 共享密钥，未配置时不接受 HS256 令牌
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// RS256 公钥集合 (JWKS) 地址 (仅支持本机的 http://，远端 JWKS 经 HTTPS 转发代理获取)，
+    /// 未配置时不接受 RS256 令牌
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// JWKS 刷新间隔 (秒)；令牌指定的 kid 不在已获取的公钥中时提前刷新
+    #[serde(default = "default_jwks_refresh")]
+    pub jwks_refresh_sec: u64,
+    /// 要求的签发者 (`iss`)
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// 要求的受众 (`aud` 为该值或包含该值的数组)
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// 列出可访问流名称的声明 (字符串或字符串数组)，令牌不含该声明时可访问全部流
+    #[serde(default = "default_jwt_streams_claim")]
+    pub streams_claim: String,
+    /// 只对名称匹配其中任一正则的流要求 JWT，为空时对所有流要求
+    #[serde(default)]
+    pub streams: Vec<String>,
+    /// 校验 `exp` 与 `nbf` 时容许的时钟偏差 (秒)
+    #[serde(default = "default_jwt_leeway")]
+    pub leeway_sec: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HeartbeatConfig {
+    /// 节点心跳地址 (仅支持 http://)，未配置时只推送流的心跳
+    #[serde(default)]
+    pub url: Option<String>,
+    /// 请求方法，`post` 时附带状态摘要 (JSON)
+    #[serde(default)]
+    pub method: HeartbeatMethod,
+    /// 推送间隔 (秒)，节点与流共用
+    #[serde(default = "default_heartbeat_push_interval")]
+    pub interval_sec: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            method: HeartbeatMethod::default(),
+            interval_sec: default_heartbeat_push_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HeartbeatMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+/// 负载卸载策略 (阈值为 0 表示不检查该项)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoadSheddingConfig {
+    /// 系统可用内存低于该值 (MB) 时视为压力过高
+    #[serde(default)]
+    pub min_mem_avail_mb: u64,
+    /// 1 分钟平均负载除以 CPU 核数超过该值时视为压力过高
+    #[serde(default)]
+    pub max_load_per_cpu: f64,
+    /// 压力解除的余量 (%)：可用内存需高于阈值、负载需低于阈值该比例后才算解除，避免在阈值附近反复启停
+    #[serde(default = "default_shed_resume_margin")]
+    pub resume_margin_percent: u64,
+    /// 两次卸载 (或恢复) 之间的最短间隔 (秒)，等待上一次停止的效果体现在内存与负载上
+    #[serde(default = "default_shed_interval")]
+    pub interval_sec: u64,
+    /// 压力解除后需保持的时间 (秒)，之后按优先级由高到低逐个恢复被卸载的流
+    #[serde(default = "default_shed_recover")]
+    pub recover_sec: u64,
+    /// `priority` 不低于该值的流不会被卸载
+    #[serde(default)]
+    pub protect_priority: Option<i32>,
+    /// 卸载与恢复事件的回调地址 (POST JSON)，未配置时仅记录日志
+    #[serde(default)]
+    pub webhook: Option<String>,
+}
+
+/// 观看会话分析的导出方式 (可同时配置 HTTP 与 CSV)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnalyticsConfig {
+    /// 接收会话记录的地址，每批以 `{"sessions": [...]}` POST JSON
+    #[serde(default)]
+    pub url: Option<String>,
+    /// 请求携带的 Bearer 令牌
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    /// 写入 CSV 文件的目录，按 UTC 日期每天一个文件 (`sessions-YYYY-MM-DD.csv`)
+    #[serde(default)]
+    pub csv_dir: Option<String>,
+    /// 累计到该数量的已结束会话时立即导出
+    #[serde(default = "default_analytics_batch")]
+    pub batch_size: usize,
+    /// 未达到批量时的最长导出间隔 (秒)
+    #[serde(default = "default_analytics_flush")]
+    pub flush_interval_sec: u64,
+}
+
+/// 分批应用配置的策略 (回滚判断使用应用前的配置中的策略)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RolloutConfig {
+    /// 每批重启的流数量
+    #[serde(default = "default_rollout_batch")]
+    pub batch_size: usize,
+    /// 每批等待流产出有效播放列表的时间 (秒)
+    #[serde(default = "default_rollout_window")]
+    pub health_window_sec: u64,
+    /// 未能就绪的流占改动的运行中流的比例超过该值 (0~1) 时停止应用并回滚到原配置
+    #[serde(default = "default_rollout_failure_ratio")]
+    pub max_failure_ratio: f64,
+}
+
+/// 一条凭据：用户名/密码或令牌
+///
+/// 密码与令牌可写为 `enc:...` (由 `vtx-link credentials encrypt` 生成)，使用时以密钥文件解密
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Credential {
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantConfig {
+    pub name: String,
+    /// 租户资源配额
+    #[serde(default)]
+    pub quota: TenantQuota,
+}
+
+/// 租户配额 (各项为 0 表示不限制)
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TenantQuota {
+    /// 同时运行的最大流数量
+    #[serde(default)]
+    pub max_streams: usize,
+    /// HLS 下发总带宽上限 (Kbps)
+    #[serde(default)]
+    pub max_bandwidth_kbps: u64,
+    /// 切片存储总量上限 (MB)
+    #[serde(default)]
+    pub max_storage_mb: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct StreamConfig {
     pub name: String,
+    /// 稳定的外部标识，与显示名称无关，改名后播放地址保持不变
+    #[serde(default)]
+    pub id: Option<String>,
+    /// 别名 (如改名前的旧名称)，HLS 路由与管理 API 均可使用
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// 所属租户，缺省表示默认命名空间
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// 源地址 (mosaic 模式下不使用)
+    #[serde(default)]
     pub source: String,
+    /// 运行模式
+    #[serde(default)]
+    pub mode: StreamMode,
+    /// 源类型
+    #[serde(default)]
+    pub source_type: SourceType,
+    /// 对端节点中继方式 (仅 source_type: vtx 时有效)
+    #[serde(default)]
+    pub relay: RelayMode,
+    /// 读取源的超时 (秒)，超时后 FFmpeg 退出并由守护进程重启
+    #[serde(default)]
+    pub input_timeout_sec: Option<u64>,
+    /// HTTP 源 (HLS、FLV) 断线后由 FFmpeg 自动重连
+    #[serde(default)]
+    pub reconnect: bool,
+    /// RTSP 源的传输协议，缺省由 FFmpeg 协商
+    #[serde(default)]
+    pub rtsp_transport: Option<RtspTransport>,
+    /// 探测源格式的最长时长 (微秒，对应 `-analyzeduration`)
+    #[serde(default)]
+    pub analyzeduration: Option<u64>,
+    /// 探测源格式读取的最大字节数 (对应 `-probesize`)
+    #[serde(default)]
+    pub probesize: Option<u64>,
+    /// FFmpeg 输出参数
+    /// relay 模式下仅作为附加的封装参数，不允许包含编码/缩放相关选项
+    #[serde(default)]
     pub output_args: Vec<String>,
+    /// HLS 封装参数 (用于 relay 模式)
+    #[serde(default)]
+    pub hls: HlsConfig,
     #[serde(default)]
     pub auto_start: bool,
+    /// 最后一个观看会话结束后保留的时间 (秒，0 表示不回收)
     #[serde(default)]
     pub idle_timeout: u64,
+    /// 始终保持运行，不因空闲而停止
+    #[serde(default)]
+    pub keep_warm: bool,
+    /// 防盗链：按请求的 Origin / Referer 限制 HLS 与 MPEG-TS 播放
+    #[serde(default)]
+    pub referer: Option<RefererPolicy>,
+    /// 按观看者所在国家/地区限制 HLS 与 MPEG-TS 播放 (需配置 server.geoip_db)
+    #[serde(default)]
+    pub geo: Option<GeoRestriction>,
+    /// 附加到本流 HLS 响应的 HTTP 头，与 server.hls_headers 同名时以此为准
+    #[serde(default)]
+    pub hls_headers: BTreeMap<String, String>,
+    /// 两次启动之间的最短间隔 (秒，0 表示不限)，手动启动、按需启动与崩溃重启均受限，
+    /// 保护频繁重连会卡死的摄像头
+    #[serde(default)]
+    pub min_start_interval_sec: u64,
+    /// 优先级 (越大越重要)，负载卸载时先停止优先级最低的流
+    #[serde(default)]
+    pub priority: i32,
+    /// 依赖的流 (如合成画面引用的摄像头流)：先于本流启动，重启时本流随之重启
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// 合成画面布局 (仅 mosaic 模式)，成员流自动视为依赖
+    #[serde(default)]
+    pub mosaic: Option<MosaicConfig>,
+    /// 循环播放的本地文件 (仅 source_type: file_loop)
+    #[serde(default)]
+    pub file_loop: Option<FileLoopConfig>,
+    /// 覆盖输出目录 (模板同 server.hls_layout)，如将长时间保留的流放在磁盘上
+    /// 该目录由网关独占，每次启动时会被清空
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    /// 本流下发带宽上限 (Kbps，0 表示不限)，所有观看者共享
+    #[serde(default)]
+    pub max_bandwidth_kbps: u64,
+    /// 下发播放列表时的切片地址改写 (CDN 回源、令牌注入)
+    #[serde(default)]
+    pub playlist: PlaylistRewrite,
+    /// 通过 API 插入的广告标记在播放列表中的格式
+    #[serde(default)]
+    pub cue_format: CueFormat,
+    /// 下发的播放列表为每个切片携带 `#EXT-X-PROGRAM-DATE-TIME` (墙上时间)
+    #[serde(default)]
+    pub program_date_time: bool,
+    /// 空闲后的处理方式
+    #[serde(default)]
+    pub idle_action: IdleAction,
+    /// 热备状态的最长保留时间 (秒，0 表示一直保留)
+    #[serde(default)]
+    pub standby_timeout: u64,
+    /// 冷启动时播放列表请求的处理方式
+    #[serde(default)]
+    pub cold_start: ColdStart,
+    /// `cold_start: wait` 时等待播放列表生成的最长时间 (毫秒)
+    #[serde(default = "default_cold_start_wait")]
+    pub cold_start_wait_ms: u64,
 
     /// 故障重试策略
     #[serde(default)]
     pub retry: RetryPolicy,
+    /// Supervisor 托管策略，可让流只接受手动启停
+    #[serde(default)]
+    pub supervise: SupervisePolicy,
+    /// 模拟进程的行为脚本 (仅 server.backend 为 mock 时生效)
+    #[serde(default)]
+    pub mock: MockScript,
+
+    /// 切片加密方式
+    #[serde(default)]
+    pub encryption: Encryption,
+    /// 密钥轮换周期 (秒，0 表示不轮换)
+    #[serde(default)]
+    pub key_rotation_sec: u64,
+
+    /// 额外以 RTSP 方式对外提供 (供 NVR / VMS 拉流)
+    #[serde(default)]
+    pub rtsp_output: Option<RtspOutputConfig>,
+    /// 将 HLS 输出同步推送到远端源站 (HTTP PUT / DELETE)，本地照常提供播放
+    #[serde(default)]
+    pub origin_push: Option<OriginPushConfig>,
+    /// 额外提供连续的 MPEG-TS over HTTP 输出 (`/ts/:name`)
+    #[serde(default)]
+    pub ts_output: bool,
+    /// 额外发布的 HLS 变体
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+    /// 自适应码率 (ABR)：源只解码一次，缩放后按各档分别编码并登记到 `master.m3u8`
+    #[serde(default)]
+    pub abr: Option<AbrConfig>,
+    /// 多语言音轨，各自输出播放列表并登记到 `master.m3u8` (仅 relay 模式)
+    #[serde(default)]
+    pub audio_tracks: Vec<AudioTrack>,
+    /// WebVTT 字幕，各自输出播放列表并登记到 `master.m3u8` (仅 relay 模式)
+    #[serde(default)]
+    pub subtitles: Vec<SubtitleTrack>,
+    /// 音频处理 (响度归一化)，作用于主输出、纯音频变体与多语言音轨
+    #[serde(default)]
+    pub audio: AudioConfig,
+    /// 音画同步监测：分析每个切片的音视频时间戳，漂移超出阈值时告警或重启
+    #[serde(default)]
+    pub av_sync: Option<AvSyncConfig>,
+    /// HLS 输出质量监测：检查切片时长、不连续标签与媒体序号，给出质量评分，低于阈值时告警或重启
+    #[serde(default)]
+    pub quality_watch: Option<QualityWatchConfig>,
+    /// 画面叠加 (时间戳、文字、logo)，仅 transcode 模式可用
+    #[serde(default)]
+    pub overlay: Option<OverlayConfig>,
+    /// 运动检测录像
+    #[serde(default)]
+    pub motion: Option<MotionConfig>,
+    /// 观看事件：首个观看者加入与最后一个观看者离开时发送事件
+    #[serde(default)]
+    pub viewer_events: Option<ViewerEventsConfig>,
+    /// 流心跳地址 (仅支持 http://)，流正常运行 (running) 时按 heartbeat.interval_sec 推送
+    #[serde(default)]
+    pub heartbeat_url: Option<String>,
+    /// 取证水印：额外提供按观看者混排 A/B 版本切片的 `secure.m3u8`
+    #[serde(default)]
+    pub watermark: Option<WatermarkConfig>,
+    /// 定时截帧 (用于生成延时视频)
+    #[serde(default)]
+    pub timelapse: Option<TimelapseConfig>,
+    /// 备用源地址，由 `switch_source` 规则依次切换
+    #[serde(default)]
+    pub fallback_sources: Vec<String>,
+    /// 本流的 FFmpeg 日志匹配规则 (与全局规则同时生效)
+    #[serde(default)]
+    pub stderr_matchers: Vec<StderrMatcher>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct PlaylistRewrite {
+    /// 切片地址前缀，相对地址被拼接为绝对地址 (如 `https://cdn.example.com/hls/{name}/`)
+    /// 可用 `{tenant}`、`{name}`、`{id}`
+    #[serde(default)]
+    pub segment_base_url: Option<String>,
+    /// 追加到切片地址的查询参数，`{token}` 替换为观看者请求播放列表时使用的令牌
+    #[serde(default)]
+    pub segment_query: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AudioTrack {
+    /// 语言 (RFC 5646，如 `en`、`zh`)
+    pub language: String,
+    /// 播放器中显示的名称，缺省使用语言
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 源中的音轨序号 (`0:a:N`)，缺省按列表顺序
+    #[serde(default)]
+    pub index: Option<usize>,
+    /// AAC 码率 (Kbps)
+    #[serde(default = "default_audio_bitrate")]
+    pub bitrate_kbps: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SubtitleTrack {
+    /// 语言 (RFC 5646)
+    pub language: String,
+    /// 播放器中显示的名称，缺省使用语言
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 独立的字幕输入 (如 WebVTT 地址)，缺省使用源中的字幕轨
+    #[serde(default)]
+    pub source: Option<String>,
+    /// 字幕轨序号：独立输入中缺省为 0，源中缺省按列表顺序
+    #[serde(default)]
+    pub index: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CueFormat {
+    /// `#EXT-X-CUE-OUT` / `#EXT-X-CUE-IN`
+    #[default]
+    CueOut,
+    /// `#EXT-X-DATERANGE` (可携带 SCTE35-OUT / SCTE35-IN)
+    DateRange,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TimelapseConfig {
+    /// 截帧间隔 (秒)
+    pub interval_sec: u64,
+    /// 截帧保留时长 (小时，0 表示不清理)
+    #[serde(default)]
+    pub retention_hours: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StderrMatcher {
+    /// 正则表达式 (常用子集，`(?i)` 前缀忽略大小写)
+    pub pattern: String,
+    /// 触发后执行的动作
+    pub action: MatchAction,
+    /// 在 window_sec 内匹配达到该次数才触发 (用于识别刷屏)
+    #[serde(default = "default_match_count")]
+    pub count: u32,
+    #[serde(default = "default_match_window")]
+    pub window_sec: u64,
+    /// 触发后的冷却时间 (秒)，同时也是 mark_degraded 的保持时间
+    #[serde(default = "default_match_cooldown")]
+    pub cooldown_sec: u64,
+    /// notify 动作的回调地址 (POST JSON)，未配置时仅记录日志
+    #[serde(default)]
+    pub webhook: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AlertRule {
+    /// 规则名称 (用于日志与告警)
+    pub name: String,
+    /// 指标名称，与 /metrics 中的一致 (如 `vtx_process_cpu_percent`)
+    pub metric: String,
+    /// 标签过滤，只评估标签全部相同的序列 (如 `{stream: cam01}`)
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub condition: AlertCondition,
+    pub threshold: f64,
+    /// 条件需持续的时间 (秒)
+    #[serde(default)]
+    pub for_sec: u64,
+    pub action: AlertAction,
+    /// 告警回调地址 (POST JSON)，未配置时仅记录日志
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// 告警级别，通知渠道按 min_severity 过滤
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub channels: Vec<NotificationChannel>,
+    /// 同一告警 (规则、标签与事件相同) 在此时间内只通知一次 (秒)，抑制反复触发
+    #[serde(default = "default_notify_dedup")]
+    pub dedup_window_sec: u64,
+    /// 每个渠道每小时最多发送的通知数 (0 表示不限)
+    #[serde(default = "default_notify_max_per_hour")]
+    pub max_per_hour: u32,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            dedup_window_sec: default_notify_dedup(),
+            max_per_hour: default_notify_max_per_hour(),
+        }
+    }
+}
+
+/// 通知渠道，smtp 与 telegram 二选一
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationChannel {
+    pub name: String,
+    #[serde(default)]
+    pub smtp: Option<SmtpChannel>,
+    #[serde(default)]
+    pub telegram: Option<TelegramChannel>,
+    /// 只发送不低于该级别的告警
+    #[serde(default)]
+    pub min_severity: Severity,
+    /// 只发送 `stream` 标签匹配其中任一正则的告警，为空时不按流过滤
+    #[serde(default)]
+    pub streams: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmtpChannel {
+    /// SMTP 服务器 (`host`、`host:port` 或 `[v6]:port`，缺省端口 25)；不支持 TLS，建议经本机中继
+    pub server: String,
+    pub from: String,
+    pub to: Vec<String>,
+    /// AUTH PLAIN 认证 (明文传输)：只允许 server 为本机中继，连接的对端不是环回地址时拒绝发送
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl SmtpChannel {
+    /// 服务器的主机部分 (IPv6 地址不带方括号)
+    pub fn host(&self) -> &str {
+        let server = self.server.as_str();
+        if let Some(v6) = server.strip_prefix('[') {
+            return v6.split(']').next().unwrap_or_default();
+        }
+        match server.rsplit_once(':') {
+            // 不带方括号的 IPv6 地址没有端口
+            Some((host, _)) if !host.contains(':') => host,
+            _ => server,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TelegramChannel {
+    /// Bot API 地址 (仅支持 http://)：必须是本机的 telegram-bot-api 服务或 HTTPS 转发代理，
+    /// 令牌不以明文经过网络
+    pub api_url: String,
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// 指标高于阈值
+    #[default]
+    Above,
+    /// 指标低于阈值
+    Below,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertAction {
+    /// 仅告警
+    Notify,
+    /// 重启序列 `stream` 标签对应的流
+    Restart,
+    /// 隔离序列 `stream` 标签对应的流 (停止且不再自动重启，手动启动后解除)
+    Quarantine,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchAction {
+    /// 重启 FFmpeg 进程
+    Restart,
+    /// 将流标记为降级 (status `degraded`)
+    MarkDegraded,
+    /// 发送告警
+    Notify,
+    /// 切换到下一个备用源并重启
+    SwitchSource,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MotionConfig {
+    /// 场景变化分数阈值 (0~1)，越小越灵敏
+    #[serde(default = "default_motion_threshold")]
+    pub threshold: f64,
+    /// 事件开始前保留的录像时长 (秒)
+    #[serde(default = "default_pre_roll")]
+    pub pre_roll_sec: u64,
+    /// 最后一次运动之后继续录像的时长 (秒)
+    #[serde(default = "default_post_roll")]
+    pub post_roll_sec: u64,
+    /// 事件告警回调地址 (POST JSON)
+    #[serde(default)]
+    pub webhook: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ViewerEventsConfig {
+    /// 事件回调地址 (POST JSON)，未配置时仅记录日志
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// 观看状态需保持的时间 (秒)，避免观看者短暂断开或刷新时反复触发
+    #[serde(default = "default_viewer_debounce")]
+    pub debounce_sec: u64,
+}
+
+/// 自适应码率输出
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AbrConfig {
+    /// 各档输出 (由高到低)
+    pub renditions: Vec<AbrRendition>,
+    /// 视频编码器
+    #[serde(default = "default_abr_encoder")]
+    pub encoder: String,
+    /// 编码预设，为空时不传 `-preset`
+    #[serde(default = "default_abr_preset")]
+    pub preset: String,
+    /// 各档的音频码率 (Kbps)
+    #[serde(default = "default_abr_audio_bitrate")]
+    pub audio_bitrate_kbps: u32,
+}
+
+/// ABR 的一档输出
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AbrRendition {
+    /// 名称，用于播放列表文件名 (`abr_{name}.m3u8`)
+    pub name: String,
+    /// 输出高度 (像素)，宽度按源宽高比缩放
+    pub height: u32,
+    /// 视频码率 (Kbps)
+    pub bitrate_kbps: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WatermarkConfig {
+    /// 水印版本的视频码率 (Kbps)
+    #[serde(default = "default_watermark_bitrate")]
+    pub bitrate_kbps: u32,
+    /// 画面标记的不透明度 (0~1)
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f64,
+    /// 只允许通过 `secure.m3u8` 观看，其余播放列表返回 403
+    #[serde(default)]
+    pub exclusive: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AudioConfig {
+    /// 响度归一化算法，缺省不处理
+    #[serde(default)]
+    pub normalize: Option<Normalize>,
+    /// 目标综合响度 (LUFS)
+    #[serde(default = "default_loudness_target")]
+    pub target_lufs: f64,
+    /// 目标响度范围 (LU)
+    #[serde(default = "default_loudness_range")]
+    pub loudness_range: f64,
+    /// 真峰值上限 (dBTP)
+    #[serde(default = "default_true_peak")]
+    pub true_peak_db: f64,
+    /// relay 模式下主输出音频重新编码为 AAC 的码率 (Kbps)
+    #[serde(default = "default_audio_bitrate")]
+    pub bitrate_kbps: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            normalize: None,
+            target_lufs: default_loudness_target(),
+            loudness_range: default_loudness_range(),
+            true_peak_db: default_true_peak(),
+            bitrate_kbps: default_audio_bitrate(),
+        }
+    }
+}
+
+impl AudioConfig {
+    /// 音频滤镜 (`-af` 参数)，未启用归一化时为 None
+    ///
+    /// loudnorm 内部以 192kHz 处理，输出前重采样回 48kHz
+    pub fn filter(&self) -> Option<String> {
+        match self.normalize? {
+            Normalize::EbuR128 => Some(format!(
+                "loudnorm=I={}:LRA={}:TP={},aresample=48000",
+                self.target_lufs, self.loudness_range, self.true_peak_db
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileLoopConfig {
+    /// 按顺序循环播放的本地媒体文件 (没有节目时段生效时的垫片内容)；
+    /// relay 模式下所有文件的编码参数须一致
+    pub files: Vec<String>,
+    /// 节目表起点 (Unix 时间，秒)，播放位置按当前时间对总时长取模计算
+    #[serde(default)]
+    pub epoch: u64,
+    /// 按时段播放的节目，时段重叠时取列表中靠前的一项
+    #[serde(default)]
+    pub schedule: Vec<PlayoutSlot>,
+    /// 节目时段使用的时区 (相对 UTC 的分钟数，如 480 表示 UTC+8)
+    #[serde(default)]
+    pub utc_offset_min: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlayoutSlot {
+    /// 节目名称 (仅用于展示)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 开始时间 `HH:MM`
+    pub start: String,
+    /// 结束时间 `HH:MM`，不晚于开始时间表示跨越午夜
+    pub end: String,
+    /// 生效的星期 (按开始时间所在的日期计算)，为空表示每天
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// 时段内从头开始循环播放的文件
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AvSyncConfig {
+    /// 允许的音画偏移变化 (毫秒，相对进程启动后的首个切片)
+    #[serde(default = "default_max_drift_ms")]
+    pub max_drift_ms: u64,
+    /// 连续超出阈值的切片数达到该值才处理，排除单个切片的抖动
+    #[serde(default = "default_drift_segments")]
+    pub segments: u32,
+    /// 超出阈值时重启流 (否则仅告警)
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QualityWatchConfig {
+    /// 期望的切片时长 (秒)，缺省为 relay / mosaic 模式的 hls.segment_duration_sec，
+    /// transcode 模式为评分窗口内切片时长的中位数
+    #[serde(default)]
+    pub target_duration_sec: Option<f64>,
+    /// 切片时长偏离期望值超过该比例 (%) 时记为过短或过长
+    #[serde(default = "default_quality_tolerance")]
+    pub tolerance_percent: u64,
+    /// 参与评分的最近切片数，窗口填满后才判断阈值
+    #[serde(default = "default_quality_window")]
+    pub window_segments: u32,
+    /// 评分窗口内允许的 `#EXT-X-DISCONTINUITY` 数量，超出视为不连续标签泛滥
+    #[serde(default = "default_quality_discontinuities")]
+    pub max_discontinuities: u32,
+    /// 质量评分 (0~100，窗口内正常切片的比例) 低于该值时处理
+    #[serde(default = "default_quality_min_score")]
+    pub min_score: f64,
+    /// 评分过低或不连续标签泛滥时重启流 (否则仅告警)
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalize {
+    /// EBU R128 响度归一化 (FFmpeg loudnorm 滤镜)
+    EbuR128,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OverlayConfig {
+    /// 时间戳格式 (strftime)，如 `%Y-%m-%d %H:%M:%S`
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    /// 是否显示流名称
+    #[serde(default)]
+    pub show_name: bool,
+    /// 自定义文字
+    #[serde(default)]
+    pub text: Option<String>,
+    /// 文字位置
+    #[serde(default)]
+    pub position: OverlayPosition,
+    /// 字体文件 (FFmpeg 未编译 fontconfig 时必填)
+    #[serde(default)]
+    pub font_file: Option<String>,
+    #[serde(default = "default_font_size")]
+    pub font_size: u32,
+    /// logo 图片路径
+    #[serde(default)]
+    pub logo: Option<String>,
+    /// logo 位置
+    #[serde(default = "default_logo_position")]
+    pub logo_position: OverlayPosition,
+}
+
+impl OverlayConfig {
+    /// 是否包含文字元素
+    pub fn has_text(&self) -> bool {
+        self.timestamp.is_some() || self.show_name || self.text.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    /// 纯音频 HLS (`audio.m3u8`)，音轨转为 AAC
+    AudioOnly,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleAction {
+    /// 停止进程
+    #[default]
+    Stop,
+    /// 保留进程与源连接，只清理旧切片，下一个观看者可立即播放
+    Standby,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColdStart {
+    /// 等待播放列表生成 (最长 cold_start_wait_ms)，超时返回 404
+    #[default]
+    Wait,
+    /// 立即返回不含切片的占位播放列表，播放器按目标时长继续轮询
+    Priming,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MosaicConfig {
+    /// 成员流，按从左到右、从上到下的顺序排列
+    pub members: Vec<String>,
+    /// 列数，缺省取成员数的平方根 (向上取整)
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// 每格宽度 (像素，偶数)
+    #[serde(default = "default_tile_width")]
+    pub tile_width: u32,
+    /// 每格高度 (像素，偶数)
+    #[serde(default = "default_tile_height")]
+    pub tile_height: u32,
+    #[serde(default = "default_mosaic_fps")]
+    pub fps: u32,
+    #[serde(default = "default_mosaic_bitrate")]
+    pub bitrate_kbps: u32,
+    /// 视频编码器
+    #[serde(default = "default_mosaic_encoder")]
+    pub encoder: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OriginPushConfig {
+    /// 远端目录地址 (仅支持 http://)，播放列表与切片以本地文件名 PUT 到其下，
+    /// 可用 `{tenant}`、`{name}`、`{id}`，如 `http://origin.example.com/live/{name}/`
+    pub url: String,
+    /// 附加的请求头 (如 `Authorization`)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// 切片从本地播放列表中移除后在远端删除 (DELETE)
+    #[serde(default = "default_push_delete")]
+    pub delete: bool,
+    /// 单个文件上传失败后的重试次数，仍失败时本轮不推送引用它的播放列表，下一轮重新尝试
+    #[serde(default = "default_push_retries")]
+    pub retries: u32,
+    /// 单次请求的超时 (秒)
+    #[serde(default = "default_push_timeout")]
+    pub timeout_sec: u64,
+}
+
+impl OriginPushConfig {
+    /// 展开后的远端目录地址 (以 `/` 结尾)
+    pub fn base_url(&self, cfg: &StreamConfig) -> String {
+        let url = self
+            .url
+            .replace("{tenant}", cfg.tenant.as_deref().unwrap_or_default())
+            .replace("{name}", &cfg.name)
+            .replace("{id}", cfg.id.as_deref().unwrap_or(&cfg.name));
+        if url.ends_with('/') {
+            url
+        } else {
+            format!("{}/", url)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RtspOutputConfig {
+    /// 内置 RTSP 服务监听端口，多个流可共用同一端口
+    pub port: u16,
+    /// 拉流路径，缺省为 `/{name}`
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl RtspOutputConfig {
+    /// 实际生效的拉流路径
+    pub fn path(&self, stream_name: &str) -> String {
+        match &self.path {
+            Some(path) => path.trim_end_matches('/').to_string(),
+            None => format!("/{}", stream_name),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamMode {
+    /// 由 output_args 决定的 FFmpeg 处理流程
+    #[default]
+    Transcode,
+    /// 直接复制音视频编码 (-c copy)，HLS 封装参数由 hls 配置生成
+    Relay,
+    /// 按需拉取上游 HLS，缓存切片并改写播放列表，不启动 FFmpeg
+    Proxy,
+    /// 将多路成员流按网格拼接为一路画面 (由 mosaic 配置生成滤镜)
+    Mosaic,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HlsConfig {
+    /// 切片时长 (秒)
+    #[serde(default = "default_segment_duration")]
+    pub segment_duration_sec: u32,
+    /// 播放列表保留的切片数量
+    #[serde(default = "default_list_size")]
+    pub list_size: u32,
+    /// 切片文件名模板 (relay 与 mosaic 模式)，缺省为 FFmpeg 默认的 `index%d.ts`；
+    /// `{sequence}` (或补零到 N 位的 `{sequence:N}`) 为切片序号，`{epoch}` 为切片开始的 Unix 秒，
+    /// 其余 `%` 字段按 strftime 展开 (网关所在时区)，如 `cam_%Y%m%d-%H%M%S_{sequence}.ts`
+    #[serde(default)]
+    pub segment_filename: Option<String>,
+}
+
+impl Default for HlsConfig {
+    fn default() -> Self {
+        Self {
+            segment_duration_sec: default_segment_duration(),
+            list_size: default_list_size(),
+            segment_filename: None,
+        }
+    }
+}
+
+/// 由 `hls.segment_filename` 生成的 FFmpeg 切片命名参数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentNaming {
+    /// `-hls_segment_filename` 的文件名部分
+    pub pattern: String,
+    /// 是否需要 `-strftime 1`
+    pub strftime: bool,
+    /// strftime 模式下需要追加的 hls_flags (序号须由 `second_level_segment_index` 填入)
+    pub flags: Vec<&'static str>,
+}
+
+/// 其他输出使用的切片文件名前缀 (音轨、字幕与水印)
+const RESERVED_SEGMENT_PREFIXES: &[&str] = &["audio", "subs_", "wm_"];
+
+impl HlsConfig {
+    /// 展开切片文件名模板，未配置时为 None
+    pub fn segment_naming(&self) -> Option<SegmentNaming> {
+        let template = self.segment_filename.as_deref()?.replace("{epoch}", "%s");
+        let strftime = template.contains('%');
+        // strftime 先于序号展开，序号须转义为 %%d
+        let escape = if strftime { "%%" } else { "%" };
+        let mut pattern = String::new();
+        let mut sequence = false;
+        let mut rest = template.as_str();
+        while let Some(at) = rest.find("{sequence") {
+            let Some(end) = rest[at..].find('}') else {
+                break;
+            };
+            pattern.push_str(&rest[..at]);
+            match rest[at + "{sequence".len()..at + end].strip_prefix(':') {
+                Some(width) => {
+                    let width = width.parse::<u8>().unwrap_or(0);
+                    pattern.push_str(&format!("{}0{}d", escape, width));
+                }
+                None => pattern.push_str(&format!("{}d", escape)),
+            }
+            sequence = true;
+            rest = &rest[at + end + 1..];
+        }
+        pattern.push_str(rest);
+        let flags = if strftime && sequence {
+            vec!["second_level_segment_index"]
+        } else {
+            Vec::new()
+        };
+        Some(SegmentNaming {
+            pattern,
+            strftime,
+            flags,
+        })
+    }
+
+    fn validate_segment_filename(&self, stream: &str) -> anyhow::Result<()> {
+        let Some(template) = &self.segment_filename else {
+            return Ok(());
+        };
+        if template.contains(['/', '\\']) || !template.ends_with(".ts") {
+            anyhow::bail!(
+                "Stream [{}] hls.segment_filename must be a file name ending in .ts",
+                stream
+            );
+        }
+        if RESERVED_SEGMENT_PREFIXES
+            .iter()
+            .any(|p| template.starts_with(p))
+        {
+            anyhow::bail!(
+                "Stream [{}] hls.segment_filename must not start with {}",
+                stream,
+                RESERVED_SEGMENT_PREFIXES.join(", ")
+            );
+        }
+        // 只允许 {sequence}、{sequence:N} 与 {epoch}
+        let mut rest = template.as_str();
+        while let Some(at) = rest.find('{') {
+            let end = rest[at..].find('}').ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Stream [{}] hls.segment_filename has an unclosed {{",
+                    stream
+                )
+            })?;
+            let name = &rest[at + 1..at + end];
+            let valid = name == "sequence"
+                || name == "epoch"
+                || name
+                    .strip_prefix("sequence:")
+                    .is_some_and(|w| matches!(w.parse::<u8>(), Ok(1..=20)));
+            if !valid {
+                anyhow::bail!(
+                    "Stream [{}] hls.segment_filename has unknown variable {{{}}}",
+                    stream,
+                    name
+                );
+            }
+            rest = &rest[at + end + 1..];
+        }
+        // 没有序号时须有精确到秒的时间，否则切片会互相覆盖
+        let unique = ["{sequence", "{epoch}", "%s", "%S", "%T"]
+            .iter()
+            .any(|v| template.contains(v));
+        if !unique {
+            anyhow::bail!(
+                "Stream [{}] hls.segment_filename needs {{sequence}}, {{epoch}} or a seconds field",
+                stream
+            );
+        }
+        Ok(())
+    }
+}
+
+/// relay 模式下禁止出现的 FFmpeg 选项 (编码、缩放、码率、滤镜及封装格式)
+const RELAY_FORBIDDEN_ARGS: &[&str] = &[
+    "-c",
+    "-codec",
+    "-c:v",
+    "-c:a",
+    "-vcodec",
+    "-acodec",
+    "-vf",
+    "-af",
+    "-filter:v",
+    "-filter:a",
+    "-filter_complex",
+    "-s",
+    "-r",
+    "-b:v",
+    "-b:a",
+    "-maxrate",
+    "-bufsize",
+    "-crf",
+    "-preset",
+    "-g",
+    "-f",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceType {
+    /// 任意 FFmpeg 可读取的输入
+    #[default]
+    Url,
+    /// 另一个 vtx-link 节点的 HLS 播放列表地址
+    Vtx,
+    /// 循环播放本地文件列表 (见 `file_loop`)，source 可省略
+    #[serde(rename = "file_loop")]
+    FileLoop,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayMode {
+    /// 直接转发对端的播放列表与切片，不启动 FFmpeg
+    #[default]
+    Proxy,
+    /// 以对端 HLS 为输入，由本地 FFmpeg 重新封装
+    Repackage,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RtspTransport {
+    Tcp,
+    Udp,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Encryption {
+    /// 不加密
+    #[default]
+    None,
+    /// HLS AES-128 整片加密
+    Aes128,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct RetryPolicy {
     /// 最大重试次数 (0 表示无限重试)
     pub max_attempts: u32,
@@ -43,6 +1610,113 @@ pub struct RetryPolicy {
     pub initial_backoff_sec: u64,
     /// 最大退避时间 (秒)
     pub max_backoff_sec: u64,
+    /// 参数或源地址错误 (如 401/404、Invalid argument) 时的退避时间 (秒)
+    #[serde(default = "default_config_backoff")]
+    pub config_backoff_sec: u64,
+    /// 滚动窗口内允许的最大崩溃次数 (0 表示不检测)，超过后视为崩溃循环并隔离
+    /// 与 max_attempts 不同，窗口外的零星崩溃不会累积
+    #[serde(default)]
+    pub max_crashes_per_window: u32,
+    /// 崩溃循环检测窗口 (秒)
+    #[serde(default = "default_crash_window")]
+    pub window_sec: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SupervisePolicy {
+    /// 进程意外退出后自动重启 (按 retry 退避)，关闭后只记录崩溃
+    #[serde(default = "default_supervise")]
+    pub auto_restart: bool,
+    /// 空闲超时后停止 (或进入热备)，关闭后与 keep_warm 相同
+    #[serde(default = "default_supervise")]
+    pub idle_stop: bool,
+}
+
+impl Default for SupervisePolicy {
+    fn default() -> Self {
+        Self {
+            auto_restart: true,
+            idle_stop: true,
+        }
+    }
+}
+
+/// 模拟进程的行为脚本，时间均从进程启动起计算
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct MockScript {
+    /// 写出第一个切片前的延迟 (毫秒)，模拟连接源的耗时
+    #[serde(default)]
+    pub startup_delay_ms: u64,
+    /// 运行指定秒数后退出
+    #[serde(default)]
+    pub crash_after_sec: Option<u64>,
+    /// 退出码 (缺省为 1)
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// 退出前写入 stderr 的一行 (如 `Connection refused`)，用于测试失败分类
+    #[serde(default)]
+    pub error: Option<String>,
+    /// 运行指定秒数后不再写出切片，但进程保持运行
+    #[serde(default)]
+    pub stall_after_sec: Option<u64>,
+}
+
+impl StreamConfig {
+    /// 名称之外用于寻址该流的键 (稳定标识与别名)
+    pub fn extra_keys(&self) -> impl Iterator<Item = &String> {
+        self.id.iter().chain(&self.aliases)
+    }
+
+    /// 按序号取源地址：0 为主源，之后依次为备用源 (循环)
+    pub fn source_at(&self, index: usize) -> &str {
+        std::iter::once(&self.source)
+            .chain(&self.fallback_sources)
+            .nth(index % (1 + self.fallback_sources.len()))
+            .unwrap()
+    }
+
+    /// 是否配置了需要 master 播放列表的音轨或字幕
+    pub fn has_media_tracks(&self) -> bool {
+        !self.audio_tracks.is_empty() || !self.subtitles.is_empty()
+    }
+
+    /// 是否由网关生成 master 播放列表 (多音轨、字幕或 ABR)
+    pub fn has_master_playlist(&self) -> bool {
+        self.has_media_tracks() || self.abr.is_some()
+    }
+
+    /// 启动前需运行的流：depends_on 与合成画面的成员
+    pub fn dependencies(&self) -> impl Iterator<Item = &String> {
+        self.depends_on
+            .iter()
+            .chain(self.mosaic.iter().flat_map(|m| &m.members))
+    }
+
+    /// 是否以代理方式直接转发上游 HLS (不启动 FFmpeg)
+    pub fn is_proxied(&self) -> bool {
+        self.mode == StreamMode::Proxy
+            || (self.source_type == SourceType::Vtx && self.relay == RelayMode::Proxy)
+    }
+}
+
+impl PlaylistRewrite {
+    /// 是否未配置任何改写
+    pub fn is_empty(&self) -> bool {
+        self.segment_base_url.is_none() && self.segment_query.is_none()
+    }
+}
+
+impl StderrMatcher {
+    fn validate(&self) -> anyhow::Result<()> {
+        Pattern::new(&self.pattern)?;
+        if self.count == 0 {
+            anyhow::bail!("Stderr matcher {:?} has a zero count", self.pattern);
+        }
+        if let Some(url) = &self.webhook {
+            Url::parse(url)?;
+        }
+        Ok(())
+    }
 }
 
 impl Default for RetryPolicy {
@@ -51,18 +1725,1162 @@ impl Default for RetryPolicy {
             max_attempts: 10,
             initial_backoff_sec: 2,
             max_backoff_sec: 60,
+            config_backoff_sec: default_config_backoff(),
+            max_crashes_per_window: 0,
+            window_sec: default_crash_window(),
         }
     }
 }
 
+fn default_match_count() -> u32 {
+    1
+}
+
+fn default_match_window() -> u64 {
+    10
+}
+
+fn default_match_cooldown() -> u64 {
+    60
+}
+
+fn default_cold_start_wait() -> u64 {
+    3000
+}
+
+fn default_config_backoff() -> u64 {
+    300
+}
+
+fn default_crash_window() -> u64 {
+    300
+}
+
+fn default_abr_encoder() -> String {
+    "libx264".to_string()
+}
+
+fn default_abr_preset() -> String {
+    "veryfast".to_string()
+}
+
+fn default_abr_audio_bitrate() -> u32 {
+    128
+}
+
+fn default_watermark_bitrate() -> u32 {
+    1500
+}
+
+fn default_watermark_opacity() -> f64 {
+    0.3
+}
+
+fn default_audio_bitrate() -> u32 {
+    128
+}
+
+fn default_nettest_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_nettest_timeout() -> u64 {
+    30
+}
+
+fn default_max_drift_ms() -> u64 {
+    200
+}
+
+fn default_drift_segments() -> u32 {
+    3
+}
+
+fn default_push_delete() -> bool {
+    true
+}
+
+fn default_push_retries() -> u32 {
+    3
+}
+
+fn default_push_timeout() -> u64 {
+    10
+}
+
+fn default_quality_tolerance() -> u64 {
+    50
+}
+
+fn default_quality_window() -> u32 {
+    20
+}
+
+fn default_quality_discontinuities() -> u32 {
+    2
+}
+
+fn default_quality_min_score() -> f64 {
+    80.0
+}
+
+fn default_loudness_target() -> f64 {
+    -23.0
+}
+
+fn default_loudness_range() -> f64 {
+    7.0
+}
+
+fn default_true_peak() -> f64 {
+    -2.0
+}
+
+fn default_max_clock_skew() -> u64 {
+    1000
+}
+
+fn default_drain_retry_after() -> u64 {
+    30
+}
+
+fn default_gpu_poll_interval() -> u64 {
+    10
+}
+
 fn default_hls_root() -> String {
     "./static/hls".to_string()
 }
 
+fn default_hls_gc_interval() -> u64 {
+    3600
+}
+
+fn default_hls_layout() -> String {
+    "{hls_root}/{tenant}/{name}".to_string()
+}
+
+fn default_key_root() -> String {
+    "./keys".to_string()
+}
+
+fn default_session_timeout() -> u64 {
+    30
+}
+
+fn default_record_root() -> String {
+    "./recordings".to_string()
+}
+
+fn default_state_root() -> String {
+    "./state".to_string()
+}
+
+fn default_ready_grace() -> u64 {
+    60
+}
+
+fn default_tile_width() -> u32 {
+    640
+}
+
+fn default_tile_height() -> u32 {
+    360
+}
+
+fn default_mosaic_fps() -> u32 {
+    15
+}
+
+fn default_mosaic_bitrate() -> u32 {
+    2000
+}
+
+fn default_mosaic_encoder() -> String {
+    "libx264".to_string()
+}
+
+fn default_keep_alive() -> bool {
+    true
+}
+
+fn default_keep_alive_timeout() -> u64 {
+    75
+}
+
+fn default_supervise() -> bool {
+    true
+}
+
+fn default_chown_dirs() -> bool {
+    true
+}
+
+fn default_landlock() -> bool {
+    true
+}
+
+fn default_segment_duration() -> u32 {
+    4
+}
+
+fn default_list_size() -> u32 {
+    5
+}
+
+fn default_motion_threshold() -> f64 {
+    0.3
+}
+
+fn default_viewer_debounce() -> u64 {
+    10
+}
+
+fn default_pre_roll() -> u64 {
+    8
+}
+
+fn default_post_roll() -> u64 {
+    10
+}
+
+fn default_font_size() -> u32 {
+    24
+}
+
+fn default_logo_position() -> OverlayPosition {
+    OverlayPosition::TopRight
+}
+
+fn default_heartbeat_interval() -> u64 {
+    15
+}
+
+fn default_notify_dedup() -> u64 {
+    600
+}
+
+fn default_notify_max_per_hour() -> u32 {
+    30
+}
+
+fn default_forward_auth_timeout() -> u64 {
+    2000
+}
+
+fn default_forward_auth_cache_ttl() -> u64 {
+    30
+}
+
+fn default_jwks_refresh() -> u64 {
+    3600
+}
+
+fn default_jwt_streams_claim() -> String {
+    "streams".to_string()
+}
+
+fn default_jwt_leeway() -> u64 {
+    60
+}
+
+fn default_shed_resume_margin() -> u64 {
+    20
+}
+
+fn default_shed_interval() -> u64 {
+    30
+}
+
+fn default_shed_recover() -> u64 {
+    120
+}
+
+fn default_analytics_batch() -> usize {
+    500
+}
+
+fn default_analytics_flush() -> u64 {
+    60
+}
+
+fn default_rollout_batch() -> usize {
+    4
+}
+
+fn default_rollout_window() -> u64 {
+    30
+}
+
+fn default_rollout_failure_ratio() -> f64 {
+    0.25
+}
+
+fn default_heartbeat_push_interval() -> u64 {
+    60
+}
+
+fn default_poll_timeout() -> u64 {
+    30
+}
+
+/// 由网关或 HTTP 连接本身决定、不允许配置的响应头
+const RESERVED_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "content-type",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// 检查配置的响应头名称与取值是否合法
+fn validate_headers(field: &str, headers: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    for (name, value) in headers {
+        let parsed = axum::http::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow::anyhow!("{} has an invalid header name [{}]", field, name))?;
+        if RESERVED_HEADERS.contains(&parsed.as_str()) {
+            anyhow::bail!("{} must not set {}", field, name);
+        }
+        if axum::http::HeaderValue::from_str(value).is_err() {
+            anyhow::bail!("{} has an invalid value for {}", field, name);
+        }
+    }
+    Ok(())
+}
+
 impl AppConfig {
     pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let config = serde_yaml::from_str(&content)?;
+        let config: Self = serde_yaml::from_str(&content)?;
+        config.validate()?;
         Ok(config)
     }
+
+    /// 校验配置中相互依赖的字段
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let hls_root = Path::new(&self.server.hls_root);
+        if Path::new(&self.server.key_root).starts_with(hls_root) {
+            anyhow::bail!("server.key_root must not be located inside server.hls_root");
+        }
+        if Path::new(&self.server.record_root).starts_with(hls_root) {
+            anyhow::bail!("server.record_root must not be located inside server.hls_root");
+        }
+        if Path::new(&self.server.state_root).starts_with(hls_root) {
+            anyhow::bail!("server.state_root must not be located inside server.hls_root");
+        }
+        if !(self.server.max_egress_mbps >= 0.0 && self.server.max_egress_mbps.is_finite()) {
+            anyhow::bail!("server.max_egress_mbps must be a non-negative number");
+        }
+        validate_headers("server.hls_headers", &self.server.hls_headers)?;
+        if let Some(net) = self
+            .server
+            .trusted_proxies
+            .iter()
+            .find(|net| sessions::parse_net(net).is_none())
+        {
+            anyhow::bail!(
+                "server.trusted_proxies has an invalid address or CIDR [{}]",
+                net
+            );
+        }
+        if self.server.worker_threads == Some(0) {
+            anyhow::bail!("server.worker_threads must be at least 1");
+        }
+        if self.server.max_blocking_threads == Some(0) {
+            anyhow::bail!("server.max_blocking_threads must be at least 1");
+        }
+        if self.server.state_store == StateStore::Sqlite && !cfg!(feature = "sqlite") {
+            anyhow::bail!(
+                "server.state_store: sqlite requires a build with the sqlite feature (cargo build --features sqlite)"
+            );
+        }
+        if let Some(update) = &self.update {
+            updater::parse_public_key(&update.public_key)?;
+            for url in std::iter::once(&update.url)
+                .chain(&update.manifest_url)
+                .chain(&update.signature_url)
+            {
+                Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("update URL {} is invalid: {}", url, e))?;
+            }
+        }
+        if let Some(nettest) = &self.nettest {
+            for url in nettest.download_url.iter().chain(&nettest.upload_url) {
+                Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("nettest URL {} is invalid: {}", url, e))?;
+            }
+            if nettest.bytes == 0 || nettest.bytes > nettest::MAX_BYTES || nettest.timeout_sec == 0
+            {
+                anyhow::bail!(
+                    "nettest.bytes must be between 1 and {} and nettest.timeout_sec non-zero",
+                    nettest::MAX_BYTES
+                );
+            }
+        }
+        if let Some(privileges) = &self.privileges {
+            if let (Some(user), Some(ffmpeg_user)) = (&privileges.user, &privileges.ffmpeg_user) {
+                if user != ffmpeg_user {
+                    anyhow::bail!(
+                        "privileges.ffmpeg_user differs from privileges.user; an unprivileged gateway cannot start processes as another user"
+                    );
+                }
+            }
+            if privileges.group.is_some() && privileges.user.is_none() {
+                anyhow::bail!("privileges.group requires privileges.user");
+            }
+            if privileges.ffmpeg_group.is_some() && privileges.ffmpeg_user.is_none() {
+                anyhow::bail!("privileges.ffmpeg_group requires privileges.ffmpeg_user");
+            }
+        }
+        dependency::validate(self)?;
+        for (i, template) in self.stream_templates.iter().enumerate() {
+            if self.stream_templates[..i]
+                .iter()
+                .any(|t| t.name == template.name)
+            {
+                anyhow::bail!("Duplicate stream template [{}]", template.name);
+            }
+            templates::validate_template(template)?;
+        }
+        if let Some(url) = &self.server.drain_alternate_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                anyhow::bail!("server.drain_alternate_url must be an http(s) URL");
+            }
+        }
+        if let Some(server) = &self.server.ntp_server {
+            if server.is_empty() || server.contains(char::is_whitespace) {
+                anyhow::bail!("server.ntp_server is invalid");
+            }
+        }
+
+        for tenant in &self.tenants {
+            let safe = !tenant.name.is_empty()
+                && !tenant.name.starts_with('.')
+                && tenant
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+            if !safe {
+                anyhow::bail!(
+                    "Tenant name [{}] contains unsupported characters",
+                    tenant.name
+                );
+            }
+            if self
+                .streams
+                .iter()
+                .any(|s| s.tenant.is_none() && s.name == tenant.name)
+            {
+                anyhow::bail!(
+                    "Tenant [{}] collides with a stream of the default namespace",
+                    tenant.name
+                );
+            }
+        }
+
+        for matcher in &self.stderr_matchers {
+            matcher.validate()?;
+        }
+        for (i, rule) in self.alert_rules.iter().enumerate() {
+            if rule.name.is_empty() || self.alert_rules[..i].iter().any(|r| r.name == rule.name) {
+                anyhow::bail!("Alert rules need unique, non-empty names");
+            }
+            if rule.metric.is_empty() || !rule.threshold.is_finite() {
+                anyhow::bail!(
+                    "Alert rule [{}] needs a metric and a finite threshold",
+                    rule.name
+                );
+            }
+            if let Some(url) = &rule.webhook {
+                Url::parse(url)?;
+            }
+        }
+        for (i, channel) in self.notifications.channels.iter().enumerate() {
+            if channel.name.is_empty()
+                || self.notifications.channels[..i]
+                    .iter()
+                    .any(|c| c.name == channel.name)
+            {
+                anyhow::bail!("Notification channels need unique, non-empty names");
+            }
+            if channel.smtp.is_some() == channel.telegram.is_some() {
+                anyhow::bail!(
+                    "Notification channel [{}] needs exactly one of smtp or telegram",
+                    channel.name
+                );
+            }
+            if let Some(smtp) = &channel.smtp {
+                if smtp.to.is_empty() || smtp.username.is_some() != smtp.password.is_some() {
+                    anyhow::bail!(
+                        "Notification channel [{}] needs recipients, and username and password together",
+                        channel.name
+                    );
+                }
+                // 不支持 TLS，AUTH PLAIN 的口令只发往本机中继
+                if smtp.username.is_some() && !http_client::is_loopback_host(smtp.host()) {
+                    anyhow::bail!(
+                        "Notification channel [{}] sends SMTP credentials without TLS; point smtp.server at a local relay (localhost or 127.0.0.1)",
+                        channel.name
+                    );
+                }
+            }
+            if let Some(telegram) = &channel.telegram {
+                // Bot 令牌位于请求路径中，明文 HTTP 只发往本机的 Bot API 服务或 HTTPS 转发代理
+                if !Url::parse(&telegram.api_url)?.is_loopback() {
+                    anyhow::bail!(
+                        "Notification channel [{}] would send the bot token over plain HTTP; telegram.api_url must be a local Bot API server or HTTPS forwarding proxy",
+                        channel.name
+                    );
+                }
+            }
+            for pattern in &channel.streams {
+                Pattern::new(pattern)?;
+            }
+        }
+        if let Some(fa) = &self.forward_auth {
+            Url::parse(&fa.url)?;
+            for pattern in &fa.streams {
+                Pattern::new(pattern)?;
+            }
+            if fa.timeout_ms == 0 {
+                anyhow::bail!("forward_auth.timeout_ms must be non-zero");
+            }
+        }
+        if let Some(jwt) = &self.jwt {
+            if jwt.secret.as_deref().unwrap_or_default().is_empty() && jwt.jwks_url.is_none() {
+                anyhow::bail!("jwt requires a non-empty secret or a jwks_url");
+            }
+            if let Some(url) = &jwt.jwks_url {
+                // 不支持 https，明文获取的公钥可被中间人替换，只从本机获取 (如 HTTPS 转发代理)
+                if !Url::parse(url)?.is_loopback() {
+                    anyhow::bail!(
+                        "jwt.jwks_url must point at localhost (e.g. an HTTPS forwarding proxy): keys fetched over plain HTTP could be replaced in transit"
+                    );
+                }
+            }
+            for pattern in &jwt.streams {
+                Pattern::new(pattern)?;
+            }
+            if jwt.jwks_refresh_sec == 0 {
+                anyhow::bail!("jwt.jwks_refresh_sec must be non-zero");
+            }
+        }
+        if let Some(url) = &self.heartbeat.url {
+            Url::parse(url)?;
+        }
+        if self.heartbeat.interval_sec == 0 {
+            anyhow::bail!("heartbeat.interval_sec must be non-zero");
+        }
+        if let Some(shed) = &self.load_shedding {
+            if !(shed.max_load_per_cpu >= 0.0 && shed.max_load_per_cpu.is_finite()) {
+                anyhow::bail!("load_shedding.max_load_per_cpu must be a non-negative number");
+            }
+            if shed.min_mem_avail_mb == 0 && shed.max_load_per_cpu == 0.0 {
+                anyhow::bail!("load_shedding needs min_mem_avail_mb or max_load_per_cpu to be set");
+            }
+            if shed.resume_margin_percent >= 100 {
+                anyhow::bail!("load_shedding.resume_margin_percent must be below 100");
+            }
+            if let Some(url) = &shed.webhook {
+                Url::parse(url)?;
+            }
+        }
+        if let Some(analytics) = &self.analytics {
+            if analytics.url.is_none() && analytics.csv_dir.is_none() {
+                anyhow::bail!("analytics needs url or csv_dir to be set");
+            }
+            if analytics.batch_size == 0 || analytics.flush_interval_sec == 0 {
+                anyhow::bail!(
+                    "analytics.batch_size and analytics.flush_interval_sec must be non-zero"
+                );
+            }
+            if let Some(url) = &analytics.url {
+                Url::parse(url)?;
+            }
+        }
+        if let Some(rollout) = &self.rollout {
+            if rollout.batch_size == 0 || rollout.health_window_sec == 0 {
+                anyhow::bail!("rollout.batch_size and rollout.health_window_sec must be non-zero");
+            }
+            if !(0.0..=1.0).contains(&rollout.max_failure_ratio) {
+                anyhow::bail!("rollout.max_failure_ratio must be between 0 and 1");
+            }
+        }
+        credentials::validate(self)?;
+
+        let output_dirs: Vec<PathBuf> = self.streams.iter().map(|s| self.output_dir(s)).collect();
+        for (i, stream) in self.streams.iter().enumerate() {
+            if self.streams[..i].iter().any(|s| s.name == stream.name) {
+                anyhow::bail!("Duplicate stream name [{}]", stream.name);
+            }
+            validate_headers(
+                &format!("Stream [{}] hls_headers", stream.name),
+                &stream.hls_headers,
+            )?;
+            if let Some(geo) = &stream.geo {
+                if self.server.geoip_db.is_none() {
+                    anyhow::bail!("Stream [{}] sets geo without server.geoip_db", stream.name);
+                }
+                let valid = |c: &String| c.len() == 2 && c.bytes().all(|b| b.is_ascii_uppercase());
+                if let Some(code) = geo.allow.iter().chain(&geo.deny).find(|c| !valid(c)) {
+                    anyhow::bail!(
+                        "Stream [{}] geo has an invalid country code [{}] (expected e.g. DE)",
+                        stream.name,
+                        code
+                    );
+                }
+            }
+            if stream.retry.max_crashes_per_window > 0 && stream.retry.window_sec == 0 {
+                anyhow::bail!(
+                    "Stream [{}] sets retry.max_crashes_per_window without retry.window_sec",
+                    stream.name
+                );
+            }
+
+            // 输出目录启动时会被清空：禁止路径穿越，且不得与其他目录重叠
+            let dir = &output_dirs[i];
+            let depth = dir
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .count();
+            if depth < 2 || dir.components().any(|c| c == Component::ParentDir) {
+                anyhow::bail!(
+                    "Stream [{}] has an unsafe output directory {:?}",
+                    stream.name,
+                    dir
+                );
+            }
+            let overlaps = |other: &Path| dir.starts_with(other) || other.starts_with(dir);
+            if overlaps(Path::new(&self.server.key_root))
+                || overlaps(Path::new(&self.server.record_root))
+            {
+                anyhow::bail!(
+                    "Stream [{}] output directory {:?} overlaps server.key_root or server.record_root",
+                    stream.name,
+                    dir
+                );
+            }
+            if let Some(j) = output_dirs[..i].iter().position(|d| overlaps(d)) {
+                anyhow::bail!(
+                    "Streams [{}] and [{}] have overlapping output directories",
+                    self.streams[j].name,
+                    stream.name
+                );
+            }
+
+            // 稳定标识与别名和所有流的名称共用一个命名空间
+            for key in stream.extra_keys() {
+                let safe = !key.is_empty()
+                    && !key.starts_with('.')
+                    && !key.contains(['/', '\\', '?', '#']);
+                if !safe {
+                    anyhow::bail!(
+                        "Stream [{}] has an invalid id or alias [{}]",
+                        stream.name,
+                        key
+                    );
+                }
+                let taken = self.streams.iter().enumerate().any(|(j, s)| {
+                    (j != i && s.name == *key) || (j < i && s.extra_keys().any(|k| k == key))
+                });
+                if taken {
+                    anyhow::bail!(
+                        "Stream [{}] uses id or alias [{}], which is already taken",
+                        stream.name,
+                        key
+                    );
+                }
+            }
+
+            if stream.is_proxied()
+                && !(stream.stderr_matchers.is_empty() && stream.fallback_sources.is_empty())
+            {
+                anyhow::bail!(
+                    "Stream [{}] has no FFmpeg process in proxy mode for stderr matchers or fallback sources",
+                    stream.name
+                );
+            }
+            for matcher in &stream.stderr_matchers {
+                matcher.validate()?;
+                if matcher.action == MatchAction::SwitchSource && stream.fallback_sources.is_empty()
+                {
+                    anyhow::bail!(
+                        "Stream [{}] uses switch_source without fallback_sources",
+                        stream.name
+                    );
+                }
+            }
+
+            if let Some(tenant) = &stream.tenant {
+                if !self.tenants.iter().any(|t| t.name == *tenant) {
+                    anyhow::bail!(
+                        "Stream [{}] references unknown tenant [{}]",
+                        stream.name,
+                        tenant
+                    );
+                }
+            }
+
+            if stream.mode == StreamMode::Mosaic {
+                mosaic::validate(stream, &self.streams)?;
+            } else if stream.mosaic.is_some() {
+                anyhow::bail!(
+                    "Stream [{}] has a mosaic block but is not in mosaic mode",
+                    stream.name
+                );
+            } else if stream.source.is_empty() && stream.source_type != SourceType::FileLoop {
+                anyhow::bail!("Stream [{}] has no source", stream.name);
+            }
+            match (
+                &stream.file_loop,
+                stream.source_type == SourceType::FileLoop,
+            ) {
+                (Some(fl), true) => {
+                    playout::validate(stream, fl)?;
+                    if !matches!(stream.mode, StreamMode::Relay | StreamMode::Transcode) {
+                        anyhow::bail!(
+                            "Stream [{}] can only loop files in relay or transcode mode",
+                            stream.name
+                        );
+                    }
+                }
+                (None, true) => anyhow::bail!(
+                    "Stream [{}] uses source_type file_loop without a file_loop block",
+                    stream.name
+                ),
+                (Some(_), false) => anyhow::bail!(
+                    "Stream [{}] has a file_loop block but source_type is not file_loop",
+                    stream.name
+                ),
+                (None, false) => {}
+            }
+            if stream.source_type == SourceType::Vtx || stream.is_proxied() {
+                Url::parse(&stream.source)?;
+            }
+            for source in std::iter::once(&stream.source).chain(&stream.fallback_sources) {
+                if test_source::is_test(source) {
+                    test_source::parse(source)?;
+                }
+            }
+            if stream.mode == StreamMode::Relay {
+                if let Some(arg) = stream
+                    .output_args
+                    .iter()
+                    .find(|a| RELAY_FORBIDDEN_ARGS.contains(&a.as_str()))
+                {
+                    anyhow::bail!(
+                        "Stream [{}] uses relay mode, which does not allow output option {}",
+                        stream.name,
+                        arg
+                    );
+                }
+                if stream.hls.segment_duration_sec == 0 {
+                    anyhow::bail!(
+                        "Stream [{}] has a zero hls.segment_duration_sec",
+                        stream.name
+                    );
+                }
+            }
+            if stream.hls.segment_filename.is_some()
+                && !matches!(stream.mode, StreamMode::Relay | StreamMode::Mosaic)
+            {
+                anyhow::bail!(
+                    "Stream [{}] sets hls.segment_filename, which only applies to relay and mosaic modes",
+                    stream.name
+                );
+            }
+            stream.hls.validate_segment_filename(&stream.name)?;
+
+            if let Some(base) = &stream.playlist.segment_base_url {
+                let absolute = (base.starts_with("http://") || base.starts_with("https://"))
+                    && base.len() > "https://".len();
+                if !absolute || base.contains(char::is_whitespace) {
+                    anyhow::bail!(
+                        "Stream [{}] has an invalid playlist.segment_base_url",
+                        stream.name
+                    );
+                }
+            }
+            if let Some(query) = &stream.playlist.segment_query {
+                if query.is_empty() || query.contains(|c: char| c.is_whitespace() || c == '#') {
+                    anyhow::bail!(
+                        "Stream [{}] has an invalid playlist.segment_query",
+                        stream.name
+                    );
+                }
+            }
+
+            if stream.is_proxied() && stream.encryption != Encryption::None {
+                anyhow::bail!(
+                    "Stream [{}] cannot encrypt segments in proxy mode",
+                    stream.name
+                );
+            }
+
+            if stream.program_date_time && stream.is_proxied() {
+                anyhow::bail!(
+                    "Stream [{}] cannot add PROGRAM-DATE-TIME in proxy mode",
+                    stream.name
+                );
+            }
+
+            if stream.ts_output && stream.is_proxied() {
+                anyhow::bail!(
+                    "Stream [{}] cannot provide MPEG-TS output in proxy mode",
+                    stream.name
+                );
+            }
+            if !stream.variants.is_empty() && stream.is_proxied() {
+                anyhow::bail!(
+                    "Stream [{}] cannot publish variants in proxy mode",
+                    stream.name
+                );
+            }
+
+            if stream.has_media_tracks() {
+                if stream.mode != StreamMode::Relay || stream.is_proxied() {
+                    anyhow::bail!(
+                        "Stream [{}] can only use audio_tracks and subtitles in relay mode",
+                        stream.name
+                    );
+                }
+                let labels: Vec<(&str, &str)> = stream
+                    .audio_tracks
+                    .iter()
+                    .map(|t| ("audio", t.name.as_deref().unwrap_or(&t.language)))
+                    .chain(
+                        stream
+                            .subtitles
+                            .iter()
+                            .map(|t| ("subtitles", t.name.as_deref().unwrap_or(&t.language))),
+                    )
+                    .collect();
+                let languages = stream
+                    .audio_tracks
+                    .iter()
+                    .map(|t| &t.language)
+                    .chain(stream.subtitles.iter().map(|t| &t.language));
+                for language in languages {
+                    if language.is_empty() || language.contains(['"', ',', '\n']) {
+                        anyhow::bail!(
+                            "Stream [{}] has an invalid track language {:?}",
+                            stream.name,
+                            language
+                        );
+                    }
+                }
+                for (i, (group, label)) in labels.iter().enumerate() {
+                    if label.is_empty() || label.contains(['"', '\n']) {
+                        anyhow::bail!(
+                            "Stream [{}] has an invalid track name {:?}",
+                            stream.name,
+                            label
+                        );
+                    }
+                    if labels[..i].contains(&(*group, *label)) {
+                        anyhow::bail!(
+                            "Stream [{}] has duplicate {} track name {:?}",
+                            stream.name,
+                            group,
+                            label
+                        );
+                    }
+                }
+                if stream.audio_tracks.iter().any(|t| t.bitrate_kbps == 0) {
+                    anyhow::bail!("Stream [{}] has a zero audio bitrate", stream.name);
+                }
+            }
+
+            if let Some(overlay) = &stream.overlay {
+                if stream.mode != StreamMode::Transcode {
+                    anyhow::bail!(
+                        "Stream [{}] can only use overlay in transcode mode",
+                        stream.name
+                    );
+                }
+                if !overlay.has_text() && overlay.logo.is_none() {
+                    anyhow::bail!("Stream [{}] has an empty overlay", stream.name);
+                }
+                // 叠加需要重新编码视频，且与手写的视频滤镜冲突
+                let conflict = stream.output_args.windows(2).find(|w| {
+                    matches!(w[0].as_str(), "-vf" | "-filter:v" | "-filter_complex")
+                        || (matches!(w[0].as_str(), "-c" | "-c:v" | "-vcodec") && w[1] == "copy")
+                });
+                if let Some(args) = conflict {
+                    anyhow::bail!(
+                        "Stream [{}] uses overlay, which conflicts with output option {} {}",
+                        stream.name,
+                        args[0],
+                        args[1]
+                    );
+                }
+            }
+
+            if stream.input_timeout_sec == Some(0) {
+                anyhow::bail!("Stream [{}] has a zero input_timeout_sec", stream.name);
+            }
+            // FFmpeg 要求 probesize 不小于 32
+            if stream.probesize.is_some_and(|p| p < 32) {
+                anyhow::bail!("Stream [{}] probesize must be at least 32", stream.name);
+            }
+
+            if let Some(sync) = &stream.av_sync {
+                if stream.is_proxied() || stream.encryption != Encryption::None {
+                    anyhow::bail!(
+                        "Stream [{}] can only monitor A/V sync on unencrypted local output",
+                        stream.name
+                    );
+                }
+                if sync.max_drift_ms == 0 || sync.segments == 0 {
+                    anyhow::bail!(
+                        "Stream [{}] has a zero av_sync.max_drift_ms or av_sync.segments",
+                        stream.name
+                    );
+                }
+            }
+
+            if let Some(push) = &stream.origin_push {
+                if stream.is_proxied() {
+                    anyhow::bail!(
+                        "Stream [{}] is proxied and has no local output to push",
+                        stream.name
+                    );
+                }
+                Url::parse(&push.base_url(stream)).map_err(|e| {
+                    anyhow::anyhow!("Stream [{}] origin_push.url: {}", stream.name, e)
+                })?;
+                validate_headers(
+                    &format!("Stream [{}] origin_push.headers", stream.name),
+                    &push.headers,
+                )?;
+                if push.timeout_sec == 0 {
+                    anyhow::bail!(
+                        "Stream [{}] has a zero origin_push.timeout_sec",
+                        stream.name
+                    );
+                }
+            }
+
+            if let Some(watch) = &stream.quality_watch {
+                if stream.is_proxied() {
+                    anyhow::bail!(
+                        "Stream [{}] is proxied and has no local playlist to watch",
+                        stream.name
+                    );
+                }
+                if watch.window_segments == 0 || watch.tolerance_percent == 0 {
+                    anyhow::bail!(
+                        "Stream [{}] has a zero quality_watch.window_segments or quality_watch.tolerance_percent",
+                        stream.name
+                    );
+                }
+                if !(0.0..=100.0).contains(&watch.min_score)
+                    || watch
+                        .target_duration_sec
+                        .is_some_and(|d| !(d > 0.0 && d.is_finite()))
+                {
+                    anyhow::bail!(
+                        "Stream [{}] quality_watch.min_score must be within 0-100 and target_duration_sec positive",
+                        stream.name
+                    );
+                }
+            }
+
+            if stream.audio.normalize.is_some() {
+                let audio = &stream.audio;
+                if stream.is_proxied() {
+                    anyhow::bail!(
+                        "Stream [{}] is proxied and cannot normalize audio",
+                        stream.name
+                    );
+                }
+                if !(-70.0..=-5.0).contains(&audio.target_lufs)
+                    || !(1.0..=50.0).contains(&audio.loudness_range)
+                    || !(-9.0..=0.0).contains(&audio.true_peak_db)
+                {
+                    anyhow::bail!(
+                        "Stream [{}] audio targets out of range (target_lufs -70..-5, loudness_range 1..50, true_peak_db -9..0)",
+                        stream.name
+                    );
+                }
+                if audio.bitrate_kbps == 0 {
+                    anyhow::bail!("Stream [{}] has a zero audio.bitrate_kbps", stream.name);
+                }
+                // 归一化需要重新编码音频，且与手写的音频滤镜冲突
+                let conflict = stream.output_args.windows(2).find(|w| {
+                    matches!(w[0].as_str(), "-af" | "-filter:a" | "-filter_complex")
+                        || (matches!(w[0].as_str(), "-c" | "-c:a" | "-acodec") && w[1] == "copy")
+                });
+                if let Some(args) = conflict {
+                    anyhow::bail!(
+                        "Stream [{}] normalizes audio, which conflicts with output option {} {}",
+                        stream.name,
+                        args[0],
+                        args[1]
+                    );
+                }
+            }
+
+            if let Some(motion) = &stream.motion {
+                if stream.is_proxied() || stream.encryption != Encryption::None {
+                    anyhow::bail!(
+                        "Stream [{}] cannot record motion in proxy mode or with encryption",
+                        stream.name
+                    );
+                }
+                if !(motion.threshold > 0.0 && motion.threshold < 1.0) {
+                    anyhow::bail!(
+                        "Stream [{}] has a motion threshold outside (0, 1)",
+                        stream.name
+                    );
+                }
+                if let Some(url) = &motion.webhook {
+                    Url::parse(url)?;
+                }
+            }
+
+            if let Some(url) = stream
+                .viewer_events
+                .as_ref()
+                .and_then(|v| v.webhook.as_ref())
+            {
+                Url::parse(url)?;
+            }
+
+            if let Some(url) = &stream.heartbeat_url {
+                Url::parse(url)?;
+            }
+
+            if let Some(wm) = &stream.watermark {
+                if stream.is_proxied() {
+                    anyhow::bail!(
+                        "Stream [{}] cannot be watermarked in proxy mode",
+                        stream.name
+                    );
+                }
+                if wm.bitrate_kbps == 0 || !(wm.opacity > 0.0 && wm.opacity <= 1.0) {
+                    anyhow::bail!(
+                        "Stream [{}] needs a nonzero watermark.bitrate_kbps and an opacity in (0, 1]",
+                        stream.name
+                    );
+                }
+                if stream.hls.segment_duration_sec == 0 {
+                    anyhow::bail!(
+                        "Stream [{}] has a zero hls.segment_duration_sec",
+                        stream.name
+                    );
+                }
+            }
+
+            if let Some(abr) = &stream.abr {
+                abr::validate(stream, abr)?;
+            }
+
+            if let Some(tl) = &stream.timelapse {
+                if stream.is_proxied() || tl.interval_sec == 0 {
+                    anyhow::bail!(
+                        "Stream [{}] needs a local process and a nonzero timelapse.interval_sec",
+                        stream.name
+                    );
+                }
+            }
+
+            if let Some(rtsp) = &stream.rtsp_output {
+                if stream.is_proxied() {
+                    anyhow::bail!(
+                        "Stream [{}] cannot provide RTSP output in proxy mode",
+                        stream.name
+                    );
+                }
+                let path = rtsp.path(&stream.name);
+                if rtsp.port == 0
+                    || !path.starts_with('/')
+                    || path.len() < 2
+                    || path.contains(|c: char| c.is_whitespace() || c == '?')
+                {
+                    anyhow::bail!("Stream [{}] has an invalid rtsp_output", stream.name);
+                }
+                let taken = self.streams[..i].iter().any(|s| {
+                    s.rtsp_output
+                        .as_ref()
+                        .is_some_and(|o| o.port == rtsp.port && o.path(&s.name) == path)
+                });
+                if taken {
+                    anyhow::bail!(
+                        "Stream [{}] reuses RTSP path {} on port {}",
+                        stream.name,
+                        path,
+                        rtsp.port
+                    );
+                }
+            }
+
+            if stream.encryption != Encryption::None && self.auth.tokens.is_empty() {
+                anyhow::bail!(
+                    "Stream [{}] enables encryption but auth.tokens is empty",
+                    stream.name
+                );
+            }
+        }
+
+        if let Some(agent) = &self.agent {
+            // 控制器下发的流配置原样交给 FFmpeg，明文通道上的指令与令牌可被篡改或窃取
+            if !Url::parse(&agent.controller_url)?.is_loopback() {
+                anyhow::bail!(
+                    "agent.controller_url must point at localhost (e.g. an HTTPS forwarding proxy): stream assignments and the agent token would otherwise travel over plain HTTP"
+                );
+            }
+        }
+
+        let mut token_ids = HashSet::new();
+        for token in &self.auth.tokens {
+            if let Some(tenant) = token.tenant() {
+                if !self.tenants.iter().any(|t| t.name == tenant) {
+                    anyhow::bail!("Token references unknown tenant [{}]", tenant);
+                }
+            }
+            let id = token.id();
+            // tok- 前缀留给通过 API 签发的令牌
+            if id.is_empty() || id.starts_with(crate::tokens::ISSUED_PREFIX) {
+                anyhow::bail!("Invalid token id [{}]", id);
+            }
+            if !token_ids.insert(id.clone()) {
+                anyhow::bail!("Duplicate token id [{}]", id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 查找流配置
+    pub fn stream(&self, name: &str) -> Option<&StreamConfig> {
+        self.streams.iter().find(|s| s.name == name)
+    }
+
+    /// 流的 HLS 输出目录 (按 output_dir 或 server.hls_layout 模板展开)
+    pub fn output_dir(&self, cfg: &StreamConfig) -> PathBuf {
+        let template = cfg.output_dir.as_deref().unwrap_or(&self.server.hls_layout);
+        let rendered = template
+            .replace("{hls_root}", &self.server.hls_root)
+            .replace("{tenant}", cfg.tenant.as_deref().unwrap_or_default())
+            .replace("{name}", &cfg.name)
+            .replace("{id}", cfg.id.as_deref().unwrap_or(&cfg.name));
+        // 重新组装路径以去掉空层级 (如默认命名空间的 `{tenant}`)
+        Path::new(&rendered).components().collect()
+    }
+
+    /// 按名称、稳定标识或别名查找流配置 (用于外部请求)
+    pub fn lookup(&self, key: &str) -> Option<&StreamConfig> {
+        self.stream(key).or_else(|| {
+            self.streams
+                .iter()
+                .find(|s| s.id.as_deref() == Some(key) || s.aliases.iter().any(|a| a == key))
+        })
+    }
+
+    /// 查找租户配置
+    pub fn tenant(&self, name: &str) -> Option<&TenantConfig> {
+        self.tenants.iter().find(|t| t.name == name)
+    }
 }
@@ -0,0 +1,130 @@
+use crate::config::{AppConfig, StreamConfig};
+use crate::rollout::RolloutReport;
+use crate::snapshot;
+use crate::state::AppState;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::info;
+
+/// 收敛结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConvergeResult {
+    /// 配置是否发生 (dry_run 时为将要发生) 变化；无变化时不写配置文件、不停止任何流
+    pub changed: bool,
+    /// 只计算差异，未应用
+    pub dry_run: bool,
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    /// 因配置变化或被删除而停止的流 (auto_start 流随后由 Supervisor 重新拉起)
+    pub stopped_streams: Vec<String>,
+    /// 配置了 rollout 时运行中流的分批重启结果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<RolloutReport>,
+}
+
+/// 解析单个流的期望配置 (JSON 或 YAML)，名称取自路径，请求体中的名称须与之一致
+pub fn parse_stream(name: &str, body: &[u8]) -> anyhow::Result<StreamConfig> {
+    let mut value = parse_body(body)?;
+    let obj = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("Stream spec must be a mapping"))?;
+    match obj.get("name").and_then(Value::as_str) {
+        Some(other) if other != name => {
+            anyhow::bail!("Stream spec name [{}] does not match [{}]", other, name)
+        }
+        _ => {
+            obj.insert("name".to_string(), name.into());
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// 解析期望的流集合：`{"streams": [...]}` 或直接为列表
+pub fn parse_streams(body: &[u8]) -> anyhow::Result<Vec<StreamConfig>> {
+    let value = match parse_body(body)? {
+        Value::Object(mut obj) => obj
+            .remove("streams")
+            .ok_or_else(|| anyhow::anyhow!("Missing streams"))?,
+        value => value,
+    };
+    Ok(serde_json::from_value(value)?)
+}
+
+/// 解析 JSON 或 YAML 请求体
+pub fn parse_body(body: &[u8]) -> anyhow::Result<Value> {
+    let yaml: serde_yaml::Value = serde_yaml::from_slice(body)?;
+    Ok(serde_json::to_value(yaml)?)
+}
+
+/// 将 `in_scope` 选出的现有流收敛为 `desired`：不在其中的被删除，其余按名称新建或整体替换
+/// (缺省字段取默认值，与现有配置完全一致时视为未变化)；有变化时校验并写回配置文件，
+/// 停止配置变化或被删除的运行中流
+///
+/// 期望的流与范围之外的流重名时拒绝，避免跨租户覆盖
+pub async fn converge(
+    state: &Arc<AppState>,
+    desired: Vec<StreamConfig>,
+    in_scope: impl Fn(&StreamConfig) -> bool,
+    dry_run: bool,
+) -> anyhow::Result<ConvergeResult> {
+    let _guard = state.config_lock.lock().await;
+    let current = state.config();
+    let mut next: AppConfig = (*current).clone();
+    let mut result = ConvergeResult {
+        dry_run,
+        ..Default::default()
+    };
+
+    next.streams.retain(|s| {
+        let keep = !in_scope(s) || desired.iter().any(|d| d.name == s.name);
+        if !keep {
+            result.deleted.push(s.name.clone());
+        }
+        keep
+    });
+    for stream in desired {
+        match next.streams.iter_mut().find(|s| s.name == stream.name) {
+            Some(existing) if !in_scope(existing) => {
+                anyhow::bail!("Stream [{}] is outside the converged scope", stream.name)
+            }
+            Some(existing) if *existing == stream => {}
+            Some(existing) => {
+                result.updated.push(stream.name.clone());
+                *existing = stream;
+            }
+            None => {
+                if let Some(other) = current.lookup(&stream.name) {
+                    anyhow::bail!(
+                        "[{}] is already an id or alias of stream [{}]",
+                        stream.name,
+                        other.name
+                    );
+                }
+                result.created.push(stream.name.clone());
+                next.streams.push(stream);
+            }
+        }
+    }
+    result.changed =
+        !(result.created.is_empty() && result.updated.is_empty() && result.deleted.is_empty());
+    if !result.changed {
+        return Ok(result);
+    }
+    next.validate()?;
+    if dry_run {
+        return Ok(result);
+    }
+
+    let applied = snapshot::apply(state, &state.config_path, next).await?;
+    result.stopped_streams = applied.stopped_streams;
+    result.rollout = applied.rollout;
+    info!(
+        "Streams converged ({} created, {} updated, {} deleted)",
+        result.created.len(),
+        result.updated.len(),
+        result.deleted.len()
+    );
+    Ok(result)
+}
@@ -0,0 +1,142 @@
+use crate::clock;
+use crate::config::AppConfig;
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// 计数器文件 (位于 server.state_root)
+const FILE_NAME: &str = "stream_counters.json";
+
+/// 写入间隔；网关被强制结束时最多丢失这段时间内的运行时长与流量
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 串行化写入
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 流的累计计数，跨网关重启与升级保留
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LifetimeCounters {
+    /// 开始计数的时间 (RFC 3339)
+    pub since: String,
+    /// 进程启动次数 (含手动启动与自动重启)
+    pub starts: u64,
+    /// 进程意外退出次数
+    pub crashes: u64,
+    /// 进程累计运行时长 (秒)
+    pub uptime_seconds: u64,
+    /// 累计下发给观众的字节数
+    pub bytes_served: u64,
+}
+
+/// 读取上次运行保存的计数 (不存在或无法读取时为空)
+pub fn load(config: &AppConfig) -> HashMap<String, LifetimeCounters> {
+    let path = file_path(config);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!("Failed to read {:?}: {}", path, e);
+            return HashMap::new();
+        }
+    };
+    match serde_json::from_slice(&data) {
+        Ok(counters) => counters,
+        Err(e) => {
+            warn!("Ignoring invalid {:?}: {}", path, e);
+            HashMap::new()
+        }
+    }
+}
+
+fn update(state: &AppState, name: &str, f: impl FnOnce(&mut LifetimeCounters)) {
+    let mut counters = state.lifetime_counters.lock_or_recover();
+    let entry = counters
+        .entry(name.to_string())
+        .or_insert_with(|| LifetimeCounters {
+            since: clock::rfc3339(SystemTime::now()),
+            ..Default::default()
+        });
+    f(entry);
+}
+
+/// 记录一次进程启动
+pub fn record_start(state: &AppState, name: &str) {
+    update(state, name, |c| c.starts += 1);
+}
+
+/// 记录一次进程退出及其运行时长
+pub fn record_exit(state: &AppState, name: &str, uptime_seconds: u64, crashed: bool) {
+    update(state, name, |c| {
+        c.uptime_seconds += uptime_seconds;
+        c.crashes += crashed as u64;
+    });
+}
+
+/// 流的累计计数，包括运行中进程的时长与本次网关运行期间的流量
+pub fn snapshot(state: &AppState, name: &str) -> Option<LifetimeCounters> {
+    let mut counters = state
+        .lifetime_counters
+        .lock_or_recover()
+        .get(name)
+        .cloned()?;
+    if let Some(running) = state.active_streams.lock_or_recover().get(name) {
+        counters.uptime_seconds += running.started_at.elapsed().as_secs();
+    }
+    if let Some(meter) = state.egress_meters.lock_or_recover().get(name) {
+        counters.bytes_served += meter.total();
+    }
+    Some(counters)
+}
+
+/// 写入全部计数：先写临时文件再重命名，只保留配置中仍存在的流
+pub fn save(state: &AppState) -> anyhow::Result<()> {
+    let _guard = SAVE_LOCK.lock_or_recover();
+    let config = state.config();
+    let names: Vec<String> = state
+        .lifetime_counters
+        .lock_or_recover()
+        .keys()
+        .filter(|name| config.stream(name).is_some())
+        .cloned()
+        .collect();
+    let snapshot: HashMap<String, LifetimeCounters> = names
+        .into_iter()
+        .filter_map(|name| Some((name.clone(), snapshot(state, &name)?)))
+        .collect();
+
+    let path = file_path(&config);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&snapshot)?)?;
+    std::fs::rename(&tmp, &path).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))
+}
+
+/// 定期写入计数
+pub async fn start_flusher(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if let Err(e) = save(&state) {
+            warn!("Failed to save stream counters: {}", e);
+        }
+    }
+}
+
+/// 网关关闭时 (所有流停止后) 写入最终计数
+pub fn flush_on_shutdown(state: &AppState) {
+    match save(state) {
+        Ok(()) => info!("Saved lifetime stream counters"),
+        Err(e) => warn!("Failed to save stream counters: {}", e),
+    }
+}
+
+fn file_path(config: &AppConfig) -> PathBuf {
+    PathBuf::from(&config.server.state_root).join(FILE_NAME)
+}
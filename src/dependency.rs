@@ -0,0 +1,179 @@
+use crate::config::{AppConfig, StreamConfig};
+use crate::engine::Engine;
+use crate::share;
+use crate::state::{AppState, LockExt};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// 等待依赖流生成播放列表的最长时间
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 校验依赖 (depends_on 与合成画面成员)：引用的流必须存在，且不能形成环
+pub fn validate(config: &AppConfig) -> anyhow::Result<()> {
+    for stream in &config.streams {
+        for dep in dependencies(config, stream) {
+            if dep == &stream.name {
+                anyhow::bail!("Stream [{}] depends on itself", stream.name);
+            }
+            if config.streams.iter().all(|s| &s.name != dep) {
+                anyhow::bail!(
+                    "Stream [{}] depends on unknown stream [{}]",
+                    stream.name,
+                    dep
+                );
+            }
+        }
+    }
+    for stream in &config.streams {
+        let mut path = Vec::new();
+        if let Some(cycle) = find_cycle(config, &stream.name, &mut path) {
+            anyhow::bail!("Circular stream dependency: {}", cycle.join(" -> "));
+        }
+    }
+    Ok(())
+}
+
+/// 流的直接依赖：配置的依赖 (`StreamConfig::dependencies`) 与共享源的拉取者
+fn dependencies<'a>(
+    config: &'a AppConfig,
+    stream: &'a StreamConfig,
+) -> impl Iterator<Item = &'a String> {
+    stream
+        .dependencies()
+        .chain(share::owner(config, stream).map(|o| &o.name))
+}
+
+fn find_cycle(config: &AppConfig, name: &str, path: &mut Vec<String>) -> Option<Vec<String>> {
+    if let Some(pos) = path.iter().position(|n| n == name) {
+        let mut cycle = path[pos..].to_vec();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    }
+    path.push(name.to_string());
+    let deps: Vec<String> = config
+        .stream(name)
+        .map(|s| dependencies(config, s).cloned().collect())
+        .unwrap_or_default();
+    for dep in &deps {
+        if let Some(cycle) = find_cycle(config, dep, path) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    None
+}
+
+/// 流的全部 (间接) 依赖，按启动顺序排列 (被依赖者在前)，不含流本身
+pub fn start_order(config: &AppConfig, name: &str) -> Vec<String> {
+    fn visit(config: &AppConfig, name: &str, order: &mut Vec<String>) {
+        let deps: Vec<String> = config
+            .stream(name)
+            .map(|s| dependencies(config, s).cloned().collect())
+            .unwrap_or_default();
+        for dep in deps {
+            if !order.contains(&dep) {
+                visit(config, &dep, order);
+                order.push(dep);
+            }
+        }
+    }
+    let mut order = Vec::new();
+    visit(config, name, &mut order);
+    order
+}
+
+/// 运行中的流所依赖的流 (这些流不因空闲被回收)
+pub fn running_upstreams(config: &AppConfig, running: &[&String]) -> HashSet<String> {
+    running
+        .iter()
+        .flat_map(|name| start_order(config, name))
+        .collect()
+}
+
+/// 按依赖顺序启动尚未运行的依赖流，并等待其生成播放列表
+///
+/// 启动依赖会再次进入 `Engine::start_stream`，返回装箱的 Future 以打断递归
+pub fn start_dependencies<'a>(
+    state: &'a Arc<AppState>,
+    name: &'a str,
+) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+        let config = state.config();
+        let mut started = Vec::new();
+        for dep in start_order(&config, name) {
+            let Some(cfg) = config.stream(&dep) else {
+                continue;
+            };
+            let running = state.active_streams.lock_or_recover().contains_key(&dep);
+            if running || cfg.is_proxied() {
+                continue;
+            }
+            info!("Starting stream [{}] required by [{}]", dep, name);
+            Engine::start_stream(state, &dep).await.map_err(|e| {
+                anyhow::anyhow!(
+                    "Dependency [{}] of stream [{}] failed to start: {}",
+                    dep,
+                    name,
+                    e
+                )
+            })?;
+            started.push(Engine::output_dir(state, cfg).join("index.m3u8"));
+        }
+        wait_ready(&started).await;
+        Ok(())
+    })
+}
+
+async fn wait_ready(playlists: &[PathBuf]) {
+    let deadline = tokio::time::Instant::now() + READY_TIMEOUT;
+    for playlist in playlists {
+        while !playlist.exists() {
+            if tokio::time::Instant::now() >= deadline {
+                warn!("Timed out waiting for dependency playlist {:?}", playlist);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+}
+
+/// 上游流 (重新) 启动后重启正在运行的直接依赖者，其下游由各自的启动过程继续联动
+pub fn restart_dependents(
+    state: &Arc<AppState>,
+    name: &str,
+) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+    let state = state.clone();
+    let name = name.to_string();
+    Box::pin(async move {
+        let config = state.config();
+        let dependents: Vec<String> = {
+            let active = state.active_streams.lock_or_recover();
+            config
+                .streams
+                .iter()
+                .filter(|s| {
+                    dependencies(&config, s).any(|d| d == &name) && active.contains_key(&s.name)
+                })
+                .map(|s| s.name.clone())
+                .collect()
+        };
+        for dependent in dependents {
+            info!(
+                "Restarting stream [{}] after its dependency [{}] restarted",
+                dependent, name
+            );
+            if let Err(e) = Engine::stop_stream(&state, &dependent).await {
+                warn!("Failed to stop dependent stream [{}]: {}", dependent, e);
+                continue;
+            }
+            if let Err(e) = Engine::start_stream(&state, &dependent).await {
+                warn!("Failed to restart dependent stream [{}]: {}", dependent, e);
+            }
+        }
+    })
+}
@@ -0,0 +1,448 @@
+use crate::clock;
+use crate::config::{AppConfig, StreamConfig};
+use crate::hash;
+use crate::http_client;
+use crate::keys;
+use crate::snapshot;
+use crate::state::AppState;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+/// WS-Discovery 组播地址
+const WS_DISCOVERY_ADDR: &str = "239.255.255.250:3702";
+
+/// 单次 SOAP 调用超时
+const SOAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 设备访问凭据
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// 发现的 ONVIF 设备
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredDevice {
+    /// 设备服务地址
+    pub xaddr: String,
+    /// 设备在 Scopes 中声明的名称
+    pub name: Option<String>,
+    pub profiles: Vec<DiscoveredProfile>,
+    /// 查询媒体配置失败时的错误信息
+    pub error: Option<String>,
+}
+
+/// 设备的媒体配置及对应的 RTSP 地址
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredProfile {
+    pub token: String,
+    pub name: Option<String>,
+    pub uri: String,
+}
+
+/// 扫描本地网段的 ONVIF 设备并查询其 RTSP 地址
+///
+/// # 流程
+/// - 发送 WS-Discovery Probe 组播，在超时时间内收集 ProbeMatches
+/// - 对每个设备依次调用 GetCapabilities / GetProfiles / GetStreamUri
+pub async fn scan(timeout: Duration, creds: &Credentials) -> anyhow::Result<Vec<DiscoveredDevice>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let probe = probe_message()?;
+    socket.send_to(probe.as_bytes(), WS_DISCOVERY_ADDR).await?;
+
+    // 1. 收集探测应答
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let Ok(Ok((n, from))) = tokio::time::timeout(remaining, socket.recv_from(&mut buf)).await
+        else {
+            break;
+        };
+        let xml = String::from_utf8_lossy(&buf[..n]);
+
+        let name = elements(&xml, "Scopes")
+            .first()
+            .and_then(|scopes| {
+                scopes
+                    .split_whitespace()
+                    .find_map(|s| s.strip_prefix("onvif://www.onvif.org/name/"))
+            })
+            .map(|s| s.replace("%20", " "));
+        let xaddr = elements(&xml, "XAddrs")
+            .first()
+            .and_then(|x| x.split_whitespace().find(|a| a.starts_with("http://")))
+            .map(|x| x.to_string());
+
+        match xaddr {
+            Some(xaddr) if seen.insert(xaddr.clone()) => found.push((xaddr, name)),
+            Some(_) => {}
+            None => warn!("ONVIF device {} returned no usable XAddrs", from),
+        }
+    }
+    info!("ONVIF discovery found {} device(s)", found.len());
+
+    // 2. 查询媒体配置
+    let mut devices = Vec::new();
+    for (xaddr, name) in found {
+        let (profiles, error) = match query_profiles(&xaddr, creds).await {
+            Ok(profiles) => (profiles, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        devices.push(DiscoveredDevice {
+            xaddr,
+            name,
+            profiles,
+            error,
+        });
+    }
+
+    Ok(devices)
+}
+
+/// 根据发现结果生成候选流配置
+///
+/// 模板为部分流配置，缺省时使用 `mode: relay`；凭据会写入 RTSP 地址
+pub fn candidates(
+    devices: &[DiscoveredDevice],
+    template: Option<&serde_json::Value>,
+    creds: &Credentials,
+    prefix: &str,
+) -> anyhow::Result<Vec<StreamConfig>> {
+    let mut out = Vec::new();
+    for device in devices {
+        let host = http_client::Url::parse(&device.xaddr)
+            .map(|u| u.host)
+            .unwrap_or_default();
+        let host_label: String = host
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        for (i, profile) in device.profiles.iter().enumerate() {
+            let mut spec = template
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({ "mode": "relay" }));
+            let obj = spec
+                .as_object_mut()
+                .ok_or_else(|| anyhow::anyhow!("discovery.template must be a mapping"))?;
+            obj.insert(
+                "name".to_string(),
+                format!("{}{}_{}", prefix, host_label, i + 1).into(),
+            );
+            obj.insert(
+                "source".to_string(),
+                with_credentials(&profile.uri, creds).into(),
+            );
+            out.push(serde_json::from_value(spec)?);
+        }
+    }
+    Ok(out)
+}
+
+/// 将候选流加入运行配置并写回配置文件，返回加入的流名称
+///
+/// 跳过名称 (含标识与别名) 或源地址已存在的流。持配置锁后基于最新配置合并，
+/// 扫描期间的其他配置修改不会被覆盖
+pub async fn add(
+    state: &Arc<AppState>,
+    candidates: &[StreamConfig],
+) -> anyhow::Result<Vec<String>> {
+    let _guard = state.config_lock.lock().await;
+    let mut next: AppConfig = (*state.config()).clone();
+    let mut added = Vec::new();
+    for candidate in candidates {
+        let exists = next.lookup(&candidate.name).is_some()
+            || next.streams.iter().any(|s| s.source == candidate.source);
+        if !exists {
+            added.push(candidate.name.clone());
+            next.streams.push(candidate.clone());
+        }
+    }
+    if !added.is_empty() {
+        next.validate()?;
+        snapshot::apply(state, &state.config_path, next).await?;
+        info!("Discovery added {} stream(s)", added.len());
+    }
+    Ok(added)
+}
+
+async fn query_profiles(
+    xaddr: &str,
+    creds: &Credentials,
+) -> anyhow::Result<Vec<DiscoveredProfile>> {
+    // 媒体服务地址，设备未声明时退化为设备服务地址
+    let caps = soap_call(
+        xaddr,
+        r#"<tds:GetCapabilities xmlns:tds="http://www.onvif.org/ver10/device/wsdl"><tds:Category>Media</tds:Category></tds:GetCapabilities>"#,
+        creds,
+    )
+    .await?;
+    let media_xaddr = elements(&caps, "Media")
+        .first()
+        .and_then(|media| {
+            elements(media, "XAddr")
+                .first()
+                .map(|x| x.trim().to_string())
+        })
+        .unwrap_or_else(|| xaddr.to_string());
+
+    let profiles_xml = soap_call(
+        &media_xaddr,
+        r#"<trt:GetProfiles xmlns:trt="http://www.onvif.org/ver10/media/wsdl"/>"#,
+        creds,
+    )
+    .await?;
+
+    let mut profiles = Vec::new();
+    for (start_tag, body) in elements_with_tags(&profiles_xml, "Profiles") {
+        let Some(token) = attribute(start_tag, "token") else {
+            continue;
+        };
+        let name = elements(body, "Name").first().map(|n| n.trim().to_string());
+
+        let request = format!(
+            r#"<trt:GetStreamUri xmlns:trt="http://www.onvif.org/ver10/media/wsdl" xmlns:tt="http://www.onvif.org/ver10/schema"><trt:StreamSetup><tt:Stream>RTP-Unicast</tt:Stream><tt:Transport><tt:Protocol>RTSP</tt:Protocol></tt:Transport></trt:StreamSetup><trt:ProfileToken>{}</trt:ProfileToken></trt:GetStreamUri>"#,
+            xml_escape(&token)
+        );
+        let uri_xml = soap_call(&media_xaddr, &request, creds).await?;
+        if let Some(uri) = elements(&uri_xml, "Uri").first() {
+            profiles.push(DiscoveredProfile {
+                token,
+                name,
+                uri: xml_unescape(uri.trim()),
+            });
+        }
+    }
+
+    Ok(profiles)
+}
+
+async fn soap_call(url: &str, body: &str, creds: &Credentials) -> anyhow::Result<String> {
+    let envelope = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope"><s:Header>{}</s:Header><s:Body>{}</s:Body></s:Envelope>"#,
+        security_header(creds)?,
+        body
+    );
+    let res = http_client::request(
+        "POST",
+        url,
+        &[("Content-Type", "application/soap+xml; charset=utf-8")],
+        Some(envelope.as_bytes()),
+        SOAP_TIMEOUT,
+    )
+    .await?;
+
+    let text = String::from_utf8_lossy(&res.body).to_string();
+    if !res.is_success() {
+        let reason = elements(&text, "Text")
+            .first()
+            .map(|t| t.trim().to_string())
+            .unwrap_or_else(|| format!("status {}", res.status));
+        anyhow::bail!("SOAP call to {} failed: {}", url, reason);
+    }
+    Ok(text)
+}
+
+/// WS-Security UsernameToken (PasswordDigest = Base64(SHA1(nonce + created + password)))
+fn security_header(creds: &Credentials) -> std::io::Result<String> {
+    let (Some(user), Some(pass)) = (&creds.username, &creds.password) else {
+        return Ok(String::new());
+    };
+
+    let mut nonce = [0u8; 16];
+    keys::random_bytes(&mut nonce)?;
+    let created = clock::rfc3339(SystemTime::now());
+
+    let mut material = nonce.to_vec();
+    material.extend_from_slice(created.as_bytes());
+    material.extend_from_slice(pass.as_bytes());
+    let digest = hash::base64_encode(&hash::sha1(&material));
+
+    Ok(format!(
+        r#"<Security s:mustUnderstand="1" xmlns="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd"><UsernameToken><Username>{}</Username><Password Type="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-username-token-profile-1.0#PasswordDigest">{}</Password><Nonce EncodingType="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-soap-message-security-1.0#Base64Binary">{}</Nonce><Created xmlns="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-utility-1.0.xsd">{}</Created></UsernameToken></Security>"#,
+        xml_escape(user),
+        digest,
+        hash::base64_encode(&nonce),
+        created
+    ))
+}
+
+fn probe_message() -> std::io::Result<String> {
+    let mut id = [0u8; 16];
+    keys::random_bytes(&mut id)?;
+    let hex: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+    let uuid = format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    );
+
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><e:Envelope xmlns:e="http://www.w3.org/2003/05/soap-envelope" xmlns:w="http://schemas.xmlsoap.org/ws/2004/08/addressing" xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery" xmlns:dn="http://www.onvif.org/ver10/network/wsdl"><e:Header><w:MessageID>uuid:{}</w:MessageID><w:To e:mustUnderstand="true">urn:schemas-xmlsoap-org:ws:2005:04:discovery</w:To><w:Action e:mustUnderstand="true">http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</w:Action></e:Header><e:Body><d:Probe><d:Types>dn:NetworkVideoTransmitter</d:Types></d:Probe></e:Body></e:Envelope>"#,
+        uuid
+    ))
+}
+
+/// 将凭据写入 RTSP 地址的 userinfo 部分 (地址中已有凭据时保持不变)
+fn with_credentials(uri: &str, creds: &Credentials) -> String {
+    let (Some(user), Some(pass)) = (&creds.username, &creds.password) else {
+        return uri.to_string();
+    };
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return uri.to_string();
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    if authority.contains('@') {
+        return uri.to_string();
+    }
+    format!(
+        "{}://{}:{}@{}",
+        scheme,
+        percent_encode(user),
+        percent_encode(pass),
+        rest
+    )
+}
+
+/// 按 RFC 3986 编码非保留字符之外的所有字节
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+/// 查找所有本地名为 `local` 的元素内容 (忽略命名空间前缀)
+fn elements<'a>(xml: &'a str, local: &str) -> Vec<&'a str> {
+    elements_with_tags(xml, local)
+        .into_iter()
+        .map(|(_, body)| body)
+        .collect()
+}
+
+/// 查找元素，返回 (起始标签, 元素内容)
+fn elements_with_tags<'a>(xml: &'a str, local: &str) -> Vec<(&'a str, &'a str)> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = xml[pos..].find('<') {
+        let start = pos + offset;
+        pos = start + 1;
+        let Some(tag_len) = xml[start..].find('>') else {
+            break;
+        };
+        let tag = &xml[start + 1..start + tag_len];
+        if tag.starts_with('/') || tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        let full_name = tag
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let name = full_name.rsplit(':').next().unwrap_or(full_name);
+        if name != local {
+            continue;
+        }
+
+        let body_start = start + tag_len + 1;
+        if tag.ends_with('/') {
+            out.push((tag, ""));
+            continue;
+        }
+        let close = format!("</{}>", full_name);
+        if let Some(end) = xml[body_start..].find(&close) {
+            out.push((tag, &xml[body_start..body_start + end]));
+            pos = body_start + end + close.len();
+        }
+    }
+    out
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let needle = format!("{}=\"", name);
+    let start = tag
+        .match_indices(&needle)
+        .find(|(i, _)| tag[..*i].ends_with(char::is_whitespace))?
+        .0
+        + needle.len();
+    let end = tag[start..].find('"')?;
+    Some(xml_unescape(&tag[start..start + end]))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn add_merges_into_latest_config_and_persists() {
+        let root = std::env::temp_dir().join(format!("vtx-discovery-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let yaml = format!(
+            "server:\n  listen: 127.0.0.1:0\n  ffmpeg_binary: ffmpeg\n  supervisor_interval_ms: 1000\n  hls_root: {root}/hls\n  state_root: {root}/state\nstreams:\n  - name: cam1\n    source: rtsp://10.0.0.9/live\n",
+            root = root.display()
+        );
+        let path = root.join("config.yaml");
+        std::fs::write(&path, &yaml).unwrap();
+        let config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+        let state = Arc::new(AppState::new(config, path.clone()).unwrap());
+        let stream = |name: &str, source: &str| -> StreamConfig {
+            serde_yaml::from_str(&format!("name: {}\nsource: {}\n", name, source)).unwrap()
+        };
+
+        // 扫描期间加入的流
+        crate::templates::add(&state, stream("cam2", "rtsp://10.0.0.10/live"), true)
+            .await
+            .unwrap();
+        let candidates = [
+            stream("onvif_10_0_0_9_1", "rtsp://10.0.0.9/live"),
+            stream("cam2", "rtsp://10.0.0.11/live"),
+            stream("onvif_10_0_0_12_1", "rtsp://10.0.0.12/live"),
+        ];
+        let added = add(&state, &candidates).await.unwrap();
+        assert_eq!(added, ["onvif_10_0_0_12_1"]);
+
+        let names = |c: &AppConfig| c.streams.iter().map(|s| s.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            names(&state.config()),
+            ["cam1", "cam2", "onvif_10_0_0_12_1"]
+        );
+        let saved = AppConfig::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(names(&saved), names(&state.config()));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::clock;
+use crate::state::{AppState, LockExt};
+use axum::body::Body;
+use axum::http::{header, Response, StatusCode, Uri};
+use serde::Serialize;
+use std::time::SystemTime;
+use tracing::info;
+
+/// 维护排空状态
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainState {
+    /// 开始排空的时间 (RFC 3339)
+    pub since: String,
+    /// 建议观看者改用的节点 (基础 URL，原请求路径附加其后)
+    pub alternate_url: Option<String>,
+    /// 返回给观看者的 Retry-After (秒)
+    pub retry_after_sec: u64,
+}
+
+/// 进入排空状态：拒绝新的播放列表与 MPEG-TS 请求，切片与密钥照常提供，
+/// 现有观看者播完缓冲后自然离开
+pub fn start(
+    state: &AppState,
+    alternate_url: Option<String>,
+    retry_after_sec: Option<u64>,
+) -> DrainState {
+    let config = state.config();
+    let drain = DrainState {
+        since: clock::rfc3339(SystemTime::now()),
+        alternate_url: alternate_url.or_else(|| config.server.drain_alternate_url.clone()),
+        retry_after_sec: retry_after_sec.unwrap_or(config.server.drain_retry_after_sec),
+    };
+    info!(
+        "Draining: new playlist requests are refused (alternate: {})",
+        drain.alternate_url.as_deref().unwrap_or("none")
+    );
+    *state.drain.lock_or_recover() = Some(drain.clone());
+    drain
+}
+
+/// 退出排空状态，恢复接受请求
+pub fn stop(state: &AppState) -> bool {
+    let was_draining = state.drain.lock_or_recover().take().is_some();
+    if was_draining {
+        info!("Drain ended: accepting new playlist requests.");
+    }
+    was_draining
+}
+
+/// 是否处于排空状态
+pub fn is_draining(state: &AppState) -> bool {
+    state.drain.lock_or_recover().is_some()
+}
+
+/// 排空期间对新请求的 503 响应 (未排空时返回 None)
+///
+/// 配置了备用节点时通过 `Link: <url>; rel="alternate"` 及响应正文告知完整的备用地址
+pub fn rejection(state: &AppState, uri: &Uri) -> Option<Response<Body>> {
+    let drain = state.drain.lock_or_recover().clone()?;
+    let alternate = drain.alternate_url.as_ref().map(|base| {
+        let path = uri.path_and_query().map_or("/", |p| p.as_str());
+        format!("{}{}", base.trim_end_matches('/'), path)
+    });
+
+    let mut builder = Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::RETRY_AFTER, drain.retry_after_sec)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    let body = match &alternate {
+        Some(url) => {
+            builder = builder.header(header::LINK, format!("<{}>; rel=\"alternate\"", url));
+            format!("Node is draining for maintenance, use {}", url)
+        }
+        None => "Node is draining for maintenance".to_string(),
+    };
+    Some(builder.body(Body::from(body)).unwrap())
+}
@@ -0,0 +1,445 @@
+//! Ed25519 签名校验 (RFC 8032)，仅用于校验更新包，不涉及私钥运算，无需常数时间实现
+
+use crate::hash;
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// 群阶 L = 2^252 + 27742317777372353535851937790883648493 (小端 64 位字)
+const L: [u64; 4] = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0,
+    0x1000000000000000,
+];
+
+/// 基点 B 的压缩编码 (y = 4/5)
+const BASE_POINT: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+/// 指数 p - 2 (求逆)
+const EXP_INVERT: [u8; 32] = exponent(0xeb, 0x7f);
+/// 指数 (p - 5) / 8 (开平方)
+const EXP_SQRT: [u8; 32] = exponent(0xfd, 0x0f);
+/// 指数 (p - 1) / 4 (2 的该次幂为 sqrt(-1))
+const EXP_SQRT_M1: [u8; 32] = exponent(0xfb, 0x1f);
+
+/// 构造形如 `first, 0xff × 30, last` 的小端指数
+const fn exponent(first: u8, last: u8) -> [u8; 32] {
+    let mut e = [0xff; 32];
+    e[0] = first;
+    e[31] = last;
+    e
+}
+
+/// 校验 Ed25519 签名
+pub fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let Some(a) = Point::decompress(public_key) else {
+        return false;
+    };
+    let Some(b) = Point::decompress(&BASE_POINT) else {
+        return false;
+    };
+    let r: [u8; 32] = signature[..32].try_into().unwrap();
+    let s: [u8; 32] = signature[32..].try_into().unwrap();
+    if !scalar_is_canonical(&s) {
+        return false;
+    }
+
+    let mut input = Vec::with_capacity(64 + message.len());
+    input.extend_from_slice(&r);
+    input.extend_from_slice(public_key);
+    input.extend_from_slice(message);
+    let k = reduce_scalar(&hash::sha512(&input));
+
+    // [S]B - [k]A 应等于 R
+    let check = b.mul(&s).add(&a.neg().mul(&k));
+    check.compress() == r
+}
+
+/// GF(2^255 - 19) 元素，5 个 51 位分量
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_u64(v: u64) -> Fe {
+        Fe([v & MASK, v >> 51, 0, 0, 0])
+    }
+
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let w = |i: usize| u64::from_le_bytes(b[i * 8..i * 8 + 8].try_into().unwrap());
+        let (w0, w1, w2, w3) = (w(0), w(1), w(2), w(3));
+        Fe([
+            w0 & MASK,
+            ((w0 >> 51) | (w1 << 13)) & MASK,
+            ((w1 >> 38) | (w2 << 26)) & MASK,
+            ((w2 >> 25) | (w3 << 39)) & MASK,
+            (w3 >> 12) & MASK,
+        ])
+    }
+
+    /// 完全约简后的 32 字节小端编码
+    fn to_bytes(self) -> [u8; 32] {
+        let mut l = self.carry().0;
+        // l 已小于 2^255 + 很小的量，判断是否 >= p 并减去
+        let mut q = (l[0] + 19) >> 51;
+        for limb in &l[1..] {
+            q = (limb + q) >> 51;
+        }
+        l[0] += 19 * q;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[4] &= MASK;
+
+        let mut out = [0u8; 32];
+        out[0..8].copy_from_slice(&(l[0] | (l[1] << 51)).to_le_bytes());
+        out[8..16].copy_from_slice(&((l[1] >> 13) | (l[2] << 38)).to_le_bytes());
+        out[16..24].copy_from_slice(&((l[2] >> 26) | (l[3] << 25)).to_le_bytes());
+        out[24..32].copy_from_slice(&((l[3] >> 39) | (l[4] << 12)).to_le_bytes());
+        out
+    }
+
+    /// 进位使各分量回到约 51 位
+    fn carry(self) -> Fe {
+        let mut l = self.0;
+        let c: [u64; 5] = std::array::from_fn(|i| l[i] >> 51);
+        for limb in &mut l {
+            *limb &= MASK;
+        }
+        l[0] += c[4] * 19;
+        for i in 1..5 {
+            l[i] += c[i - 1];
+        }
+        Fe(l)
+    }
+
+    fn add(self, rhs: Fe) -> Fe {
+        Fe(std::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+
+    fn sub(self, rhs: Fe) -> Fe {
+        // 先加上 16p 避免下溢
+        const P16: [u64; 5] = [
+            36028797018963664,
+            36028797018963952,
+            36028797018963952,
+            36028797018963952,
+            36028797018963952,
+        ];
+        Fe(std::array::from_fn(|i| (self.0[i] + P16[i]) - rhs.0[i])).carry()
+    }
+
+    fn neg(self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(self, rhs: Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = rhs.0.map(u128::from);
+        let b19: [u128; 5] = std::array::from_fn(|i| b[i] * 19);
+
+        let mut c = [
+            a[0] * b[0] + a[4] * b19[1] + a[3] * b19[2] + a[2] * b19[3] + a[1] * b19[4],
+            a[1] * b[0] + a[0] * b[1] + a[4] * b19[2] + a[3] * b19[3] + a[2] * b19[4],
+            a[2] * b[0] + a[1] * b[1] + a[0] * b[2] + a[4] * b19[3] + a[3] * b19[4],
+            a[3] * b[0] + a[2] * b[1] + a[1] * b[2] + a[0] * b[3] + a[4] * b19[4],
+            a[4] * b[0] + a[3] * b[1] + a[2] * b[2] + a[1] * b[3] + a[0] * b[4],
+        ];
+        for i in 0..4 {
+            c[i + 1] += c[i] >> 51;
+            c[i] &= u128::from(MASK);
+        }
+        let carry = (c[4] >> 51) * 19;
+        c[4] &= u128::from(MASK);
+        c[0] += carry;
+        c[1] += c[0] >> 51;
+        c[0] &= u128::from(MASK);
+        Fe(c.map(|v| v as u64))
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// 按小端指数求幂
+    fn pow(self, exp: &[u8; 32]) -> Fe {
+        let mut result = Fe::ONE;
+        for bit in (0..256).rev() {
+            result = result.square();
+            if (exp[bit / 8] >> (bit % 8)) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn invert(self) -> Fe {
+        self.pow(&EXP_INVERT)
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(self, rhs: Fe) -> bool {
+        self.to_bytes() == rhs.to_bytes()
+    }
+}
+
+/// 曲线常数 d = -121665 / 121666
+const D: Fe = Fe([
+    0x34dca135978a3,
+    0x1a8283b156ebd,
+    0x5e7a26001c029,
+    0x739c663a03cbb,
+    0x52036cee2b6ff,
+]);
+
+/// 2d
+const D2: Fe = Fe([
+    0x69b9426b2f159,
+    0x35050762add7a,
+    0x3cf44c0038052,
+    0x6738cc7407977,
+    0x2406d9dc56dff,
+]);
+
+/// 扩展坐标下的曲线点 (X : Y : Z : T)，x = X/Z, y = Y/Z, xy = T/Z
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    /// 解压缩点 (RFC 8032 5.1.3)，拒绝非规范编码
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        let sign = bytes[31] >> 7 == 1;
+        let mut y_bytes = *bytes;
+        y_bytes[31] &= 0x7f;
+        let y = Fe::from_bytes(&y_bytes);
+        if y.to_bytes() != y_bytes {
+            return None;
+        }
+
+        let y2 = y.square();
+        let u = y2.sub(Fe::ONE);
+        let v = D.mul(y2).add(Fe::ONE);
+        let v3 = v.square().mul(v);
+        let v7 = v3.square().mul(v);
+        let mut x = u.mul(v3).mul(u.mul(v7).pow(&EXP_SQRT));
+
+        let vx2 = v.mul(x.square());
+        if !vx2.equals(u) {
+            if vx2.equals(u.neg()) {
+                x = x.mul(Fe::from_u64(2).pow(&EXP_SQRT_M1));
+            } else {
+                return None;
+            }
+        }
+        if x.equals(Fe::ZERO) && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(y),
+        })
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let zinv = self.z.invert();
+        let x = self.x.mul(zinv);
+        let mut out = self.y.mul(zinv).to_bytes();
+        out[31] |= u8::from(x.is_negative()) << 7;
+        out
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: self.x.neg(),
+            y: self.y,
+            z: self.z,
+            t: self.t.neg(),
+        }
+    }
+
+    /// 统一加法公式 (add-2008-hwcd-3，a = -1)，同样适用于倍点
+    fn add(&self, rhs: &Point) -> Point {
+        let a = self.y.sub(self.x).mul(rhs.y.sub(rhs.x));
+        let b = self.y.add(self.x).mul(rhs.y.add(rhs.x));
+        let c = self.t.mul(D2).mul(rhs.t);
+        let d = self.z.add(self.z).mul(rhs.z);
+        let e = b.sub(a);
+        let f = d.sub(c);
+        let g = d.add(c);
+        let h = b.add(a);
+        Point {
+            x: e.mul(f),
+            y: g.mul(h),
+            z: f.mul(g),
+            t: e.mul(h),
+        }
+    }
+
+    /// 标量乘 (小端 32 字节标量)
+    fn mul(&self, scalar: &[u8; 32]) -> Point {
+        let mut result = Point::IDENTITY;
+        for bit in (0..256).rev() {
+            result = result.add(&result);
+            if (scalar[bit / 8] >> (bit % 8)) & 1 == 1 {
+                result = result.add(self);
+            }
+        }
+        result
+    }
+}
+
+/// S 必须小于 L
+fn scalar_is_canonical(s: &[u8; 32]) -> bool {
+    let words: [u64; 4] =
+        std::array::from_fn(|i| u64::from_le_bytes(s[i * 8..i * 8 + 8].try_into().unwrap()));
+    for i in (0..4).rev() {
+        if words[i] != L[i] {
+            return words[i] < L[i];
+        }
+    }
+    false
+}
+
+/// 将 64 字节小端整数约简到模 L (逐位长除法)
+fn reduce_scalar(input: &[u8; 64]) -> [u8; 32] {
+    let mut r = [0u64; 4];
+    for bit in (0..512).rev() {
+        // r = 2r + bit，r < L < 2^253，不会溢出
+        for i in (1..4).rev() {
+            r[i] = (r[i] << 1) | (r[i - 1] >> 63);
+        }
+        r[0] = (r[0] << 1) | u64::from((input[bit / 8] >> (bit % 8)) & 1);
+
+        if !less_than(&r, &L) {
+            let mut borrow = 0u64;
+            for i in 0..4 {
+                let (v, b1) = r[i].overflowing_sub(L[i]);
+                let (v, b2) = v.overflowing_sub(borrow);
+                r[i] = v;
+                borrow = u64::from(b1 || b2);
+            }
+        }
+    }
+
+    let mut out = [0u8; 32];
+    for (chunk, word) in out.chunks_mut(8).zip(r) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn less_than(a: &[u64; 4], b: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex<const N: usize>(text: &str) -> [u8; N] {
+        let bytes: Vec<u8> = (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect();
+        bytes.try_into().unwrap()
+    }
+
+    /// RFC 8032 第 7.1 节 TEST 1-3 (公钥, 消息, 签名)
+    const VECTORS: [(&str, &[u8], &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            &[0x72],
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            &[0xaf, 0x82],
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn rfc8032_vectors_verify() {
+        for (public_key, message, signature) in VECTORS {
+            assert!(verify(&unhex(public_key), message, &unhex(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_modified_inputs() {
+        for (public_key, message, signature) in VECTORS {
+            let public_key: [u8; 32] = unhex(public_key);
+            let signature: [u8; 64] = unhex(signature);
+
+            let mut longer = message.to_vec();
+            longer.push(0);
+            assert!(!verify(&public_key, &longer, &signature));
+
+            for at in [0, 31, 32, 63] {
+                let mut bad = signature;
+                bad[at] ^= 0x01;
+                assert!(!verify(&public_key, message, &bad));
+            }
+
+            let mut other_key = public_key;
+            other_key[0] ^= 0x01;
+            assert!(!verify(&other_key, message, &signature));
+        }
+        // 公钥与签名对不上
+        let (key, _, _) = VECTORS[0];
+        let (_, message, signature) = VECTORS[1];
+        assert!(!verify(&unhex(key), message, &unhex(signature)));
+    }
+
+    /// S 不小于群阶 L 的签名不可接受 (防止签名延展)
+    #[test]
+    fn rejects_non_canonical_scalar() {
+        let (public_key, message, signature) = VECTORS[0];
+        let mut bad: [u8; 64] = unhex(signature);
+        // S + L
+        let mut carry = 0u16;
+        let l: [u8; 32] = unhex("edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010");
+        for i in 0..32 {
+            let sum = bad[32 + i] as u16 + l[i] as u16 + carry;
+            bad[32 + i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!verify(&unhex(public_key), message, &bad));
+    }
+}
@@ -1,13 +1,93 @@
-use crate::state::{AppState, StreamRuntime};
+use crate::abr;
+use crate::av_sync;
+use crate::availability::{self, Transition};
+use crate::config::{
+    AppConfig, AvSyncConfig, Backend, Encryption, QualityWatchConfig, StreamConfig, StreamMode,
+    Variant,
+};
+use crate::counters;
+use crate::credentials;
+use crate::dependency;
+use crate::error::VtxError;
+use crate::failure::{self, ExitRecord, StderrTail};
+use crate::file_loop;
+use crate::gpu;
+use crate::input;
+use crate::keys::{self, StreamKeyring};
+use crate::loadshed;
+use crate::maintenance;
+use crate::markers;
+use crate::matchers::{self, ActiveMatcher};
+use crate::metrics::{self, Milestone};
+use crate::mock;
+use crate::mosaic;
+use crate::motion;
+use crate::origin_push;
+use crate::overlay;
+use crate::platform;
+use crate::playlist;
+use crate::privilege;
+use crate::proxy;
+use crate::quality;
+use crate::sandbox;
+use crate::segment_index::{self, DirIndex};
+use crate::share;
+use crate::state::{AppState, LockExt, StreamRuntime};
+use crate::tenant;
+use crate::test_source::{self, TestSource};
+use crate::timelapse;
+use crate::ts;
+use crate::watermark;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 use tokio::fs;
-use tokio::process::Command;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, ChildStderr, Command};
 use tracing::{error, info, warn};
 
 pub struct Engine;
 
+/// 启动请求的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartOutcome {
+    /// 新启动了 FFmpeg 进程
+    Started,
+    /// 流已在运行 (或从热备恢复)，只刷新了最后访问时间
+    AlreadyRunning,
+}
+
+/// 已启动、尚未登记到活动流的 FFmpeg 进程
+pub struct Launched {
+    pub child: Child,
+    pub group: Option<platform::ProcessGroup>,
+    pub stderr_tail: StderrTail,
+}
+
+impl Launched {
+    /// 登记为运行中的流实例
+    pub fn into_runtime(self, requested_at: Instant) -> StreamRuntime {
+        StreamRuntime {
+            process: self.child,
+            group: self.group,
+            last_accessed: Instant::now(),
+            started_at: Instant::now(),
+            standby_since: None,
+            requested_at,
+            first_segment: None,
+            first_playlist: None,
+            stderr_tail: self.stderr_tail,
+            degraded_until: None,
+            usage: None,
+            cpu_sample: None,
+            sync: Default::default(),
+            quality: Default::default(),
+            latency: Default::default(),
+        }
+    }
+}
+
 impl Engine {
     /// 启动指定名称的流任务
     ///
@@ -18,16 +98,27 @@ impl Engine {
     /// # 错误处理
     /// - 内存不足时返回错误
     /// - 配置未找到时返回错误
+    /// - 被隔离、仍在崩溃退避期内或被负载卸载时返回错误
     /// - FFmpeg 启动失败时返回错误
-    pub async fn start_stream(state: &Arc<AppState>, name: &str) -> anyhow::Result<()> {
+    pub async fn start_stream(state: &Arc<AppState>, name: &str) -> Result<StartOutcome, VtxError> {
+        let requested_at = Instant::now();
+
         // 1. 检查流任务是否已经在运行
-        {
-            let mut streams = state.active_streams.lock().unwrap();
-            if let Some(running) = streams.get_mut(name) {
-                // 如果流已在运行，则更新最后访问时间并直接返回
-                running.last_accessed = Instant::now();
-                return Ok(());
-            }
+        if Self::touch_running(state, name) {
+            return Ok(StartOutcome::AlreadyRunning);
+        }
+
+        // 同一流的启动串行执行，从启动检查到登记进程与启动时间期间持有：
+        // 并发的冷启动请求在此等待，第一个请求启动后其余请求看到运行中的流直接返回
+        let start_lock = state
+            .start_locks
+            .lock_or_recover()
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        let _starting = start_lock.lock().await;
+        if Self::touch_running(state, name) {
+            return Ok(StartOutcome::AlreadyRunning);
         }
 
         // 2. 检查系统内存是否足够
@@ -35,10 +126,10 @@ impl Engine {
             Ok(mem) => {
                 // 如果系统可用内存小于 5MB，则返回内存不足错误
                 if mem.avail < 5120 {
-                    return Err(anyhow::anyhow!(
+                    return Err(VtxError::InsufficientResources(format!(
                         "Insufficient system memory ({} KB available)",
                         mem.avail
-                    ));
+                    )));
                 }
             }
             Err(e) => {
@@ -48,69 +139,307 @@ impl Engine {
         }
 
         // 3. 查找配置文件中的流配置
-        let cfg = state
-            .config
-            .streams
-            .iter()
-            .find(|s| s.name == name)
-            .ok_or_else(|| anyhow::anyhow!("Stream configuration not found"))?;
+        let config = state.config();
+        let cfg = config
+            .stream(name)
+            .ok_or_else(|| VtxError::ConfigNotFound(name.to_string()))?;
+
+        // 被隔离的流需人工处理 (强制启动) 后才能再次启动，崩溃后的退避期内也不启动
+        if let Some(rec) = state.recovery_states.lock_or_recover().get(name) {
+            if let Some(reason) = rec.quarantined.clone() {
+                return Err(VtxError::StreamQuarantined {
+                    name: name.to_string(),
+                    reason,
+                });
+            }
+            if let Some(wait) = rec
+                .next_retry_at
+                .and_then(|t| t.checked_duration_since(Instant::now()))
+                .filter(|wait| !wait.is_zero())
+            {
+                return Err(VtxError::BackingOff {
+                    name: name.to_string(),
+                    crash_count: rec.crash_count,
+                    retry_at: SystemTime::now() + wait,
+                });
+            }
+            if let Some(wait) = rec.start_wait(cfg.min_start_interval_sec) {
+                return Err(VtxError::StartRateLimited {
+                    name: name.to_string(),
+                    min_interval_sec: cfg.min_start_interval_sec,
+                    retry_at: SystemTime::now() + wait,
+                });
+            }
+        }
+
+        // 运维禁用的流需先恢复 (POST /streams/:name/enable)
+        if maintenance::is_disabled(state, name) {
+            return Err(VtxError::Disabled(name.to_string()));
+        }
+
+        // 被负载卸载的流在压力解除前不启动
+        if let Some(reason) = loadshed::shed_reason(state, name) {
+            return Err(VtxError::LoadShed {
+                name: name.to_string(),
+                reason,
+            });
+        }
+
+        // 代理中继的流由 HLS 接口直接转发，没有本地进程
+        if cfg.is_proxied() {
+            return Err(VtxError::NoLocalProcess(name.to_string()));
+        }
+
+        // 检查租户配额
+        tenant::check_start_quota(state, cfg)
+            .map_err(|e| VtxError::InsufficientResources(e.to_string()))?;
+
+        // 检查硬件编码会话上限
+        gpu::check_start_sessions(state, cfg)
+            .map_err(|e| VtxError::InsufficientResources(e.to_string()))?;
+
+        // 先启动依赖的流
+        dependency::start_dependencies(state, name).await?;
+
+        // 4. 准备 HLS 输出目录并启动 FFmpeg 子进程 (迁移时切换过的目录回到配置的位置)
+        let migrated = state.output_dirs.lock_or_recover().remove(name);
+        if let Some(dir) = migrated {
+            let _ = fs::remove_dir_all(dir).await;
+        }
+        let output_dir = Self::output_dir(state, cfg);
+        let launched = Self::launch(state, &config, cfg, &output_dir).await?;
+        Self::serve_output(state, cfg, &output_dir);
+
+        // 5. 更新活动流状态
+        state
+            .active_streams
+            .lock_or_recover()
+            .insert(name.to_string(), launched.into_runtime(requested_at));
+
+        // 6. 恢复状态 (崩溃计数) 由 Supervisor 在进程稳定运行后重置，
+        //    否则启动即退出的流会在每次重启时清零计数，退避永远不会增长
+        if cfg.min_start_interval_sec > 0 {
+            state
+                .recovery_states
+                .lock_or_recover()
+                .entry(name.to_string())
+                .or_default()
+                .last_start = Some(Instant::now());
+        }
+
+        // 7. 正在运行的下游流读取的是旧进程的输出，随之重启
+        tokio::spawn(dependency::restart_dependents(state, name));
+
+        availability::record(state, name, Transition::Up, None);
+        counters::record_start(state, name);
+        Ok(StartOutcome::Started)
+    }
 
-        // 4. 准备 HLS 输出目录，适配 RAMDisk
-        let output_dir = std::path::Path::new(&state.config.server.hls_root).join(name);
+    /// 流已在运行 (或处于热备) 时更新最后访问时间并返回 true
+    fn touch_running(state: &AppState, name: &str) -> bool {
+        let mut streams = state.active_streams.lock_or_recover();
+        let Some(running) = streams.get_mut(name) else {
+            return false;
+        };
+        running.last_accessed = Instant::now();
+        if running.standby_since.take().is_some() {
+            info!("Stream [{}] resumed from standby.", name);
+        }
+        true
+    }
 
-        // 如果目录已存在，则删除并重新创建
+    /// 清理并准备输出目录，构建 FFmpeg 命令并启动子进程 (尚未登记为运行中)
+    ///
+    /// 输出目录在调用 `serve_output` 后才建立索引并推送到源站，
+    /// 配置迁移时新进程先写入暂存目录，确认产出有效的播放列表后再切换
+    pub async fn launch(
+        state: &Arc<AppState>,
+        config: &AppConfig,
+        cfg: &StreamConfig,
+        output_dir: &Path,
+    ) -> Result<Launched, VtxError> {
+        let name = cfg.name.as_str();
+
+        // 如果目录已存在，则删除并重新创建 (媒体序号从头开始，旧的广告标记随之失效)
+        markers::clear(state, name);
         if output_dir.exists() {
-            let _ = fs::remove_dir_all(&output_dir).await;
+            let _ = fs::remove_dir_all(output_dir).await;
+        }
+        fs::create_dir_all(output_dir).await?;
+        privilege::chown_media(output_dir)?;
+
+        // 多音轨/字幕/ABR 由网关生成 master 播放列表
+        if cfg.has_master_playlist() {
+            fs::write(
+                output_dir.join(playlist::MASTER_PLAYLIST),
+                playlist::master(cfg),
+            )
+            .await?;
+        }
+
+        // 叠加文字由 drawtext 从文件读取，避免多层转义
+        if let Some(ov) = &cfg.overlay {
+            fs::write(
+                output_dir.join(overlay::TEXT_FILE),
+                overlay::text(ov, &cfg.name),
+            )
+            .await?;
         }
-        fs::create_dir_all(&output_dir).await?;
 
         info!("Starting stream [{}]. HLS Output: {:?}", name, output_dir);
 
-        // 5. 构建 FFmpeg 命令并启动子进程
-        let mut cmd = Command::new(&state.config.server.ffmpeg_binary);
+        // 构建 FFmpeg 命令并启动子进程
+        let source_index = state
+            .recovery_states
+            .lock_or_recover()
+            .get(name)
+            .map_or(0, |r| r.source_index);
+        let source = cfg.source_at(source_index);
+        if source != cfg.source {
+            info!(
+                "Stream [{}] using fallback source {}",
+                name,
+                credentials::mask_urls(source)
+            );
+        }
+        let test = test_source::is_test(source)
+            .then(|| test_source::parse(source))
+            .transpose()?;
+
+        let mut cmd = match config.server.backend {
+            Backend::Ffmpeg => Command::new(&config.server.ffmpeg_binary),
+            Backend::Mock => mock::command(&cfg.mock)?,
+        };
         cmd.arg("-hide_banner").arg("-y");
-        cmd.arg("-i").arg(&cfg.source);
+        match &cfg.mosaic {
+            Some(mosaic) => {
+                cmd.args(mosaic::input_args(state, mosaic));
+            }
+            None => match (&cfg.file_loop, &test) {
+                (Some(fl), _) => {
+                    cmd.args(file_loop::input_args(state, cfg, fl).await?);
+                }
+                (None, Some(test)) => {
+                    cmd.args(test_source::input_args(test));
+                }
+                // 与其他流共用主源时从拉取者的本地回环读取，备用源仍自行拉取
+                (None, None) => match share::owner(config, cfg).filter(|_| source_index == 0) {
+                    Some(owner) => {
+                        let url = share::input_url(state, &owner.name).await?;
+                        cmd.args(["-f", "mpegts", "-i", url.as_str()]);
+                    }
+                    None => {
+                        cmd.args(
+                            Self::input(state, name, 0, source, input::args(cfg, source)).await?,
+                        );
+                    }
+                },
+            },
+        }
+        // 独立的字幕输入依次作为第 1、2… 路输入 (见 output_args)
+        for (i, src) in cfg
+            .subtitles
+            .iter()
+            .filter_map(|s| s.source.as_ref())
+            .enumerate()
+        {
+            cmd.args(Self::input(state, name, i + 1, src, Vec::new()).await?);
+        }
 
-        // 替换输出路径变量
-        let dir_str = output_dir.to_string_lossy();
-        for arg in &cfg.output_args {
-            let final_arg = arg.replace("{output_dir}", &dir_str);
-            cmd.arg(final_arg);
+        // 启用加密时为 FFmpeg 提供 key info 文件，密钥由网关生成并托管
+        let mut key_info = None;
+        if cfg.encryption == Encryption::Aes128 {
+            // 旧进程仍在运行 (配置迁移) 时沿用现有密钥，已发布的切片仍可解密
+            let running = state.active_streams.lock_or_recover().contains_key(name);
+            let (id, key) = {
+                let mut key_map = state.stream_keys.lock_or_recover();
+                match key_map.get(name).filter(|_| running) {
+                    Some(keyring) => keyring.current(),
+                    None => {
+                        let keyring = StreamKeyring::new()?;
+                        let current = keyring.current();
+                        key_map.insert(name.to_string(), keyring);
+                        current
+                    }
+                }
+            };
+            let info_path = keys::write_key_files(&Self::key_dir(state, name), id, &key).await?;
+            key_info = Some(info_path);
         }
 
-        cmd.stdout(Stdio::null());
+        let ts_pipe = cfg.ts_output || share::is_owner(config, cfg);
+        cmd.args(Self::output_args(
+            cfg,
+            output_dir,
+            key_info.as_deref(),
+            test.as_ref(),
+            ts_pipe,
+        ));
+
+        // 定时截帧写入录像目录，跨重启保留
+        let mut writable = vec![output_dir.to_path_buf()];
+        if let Some(tl) = &cfg.timelapse {
+            let frame_dir = timelapse::frame_dir(state, name);
+            fs::create_dir_all(&frame_dir).await?;
+            privilege::chown_media(&frame_dir)?;
+            cmd.args(timelapse::capture_args(tl, &frame_dir));
+            writable.push(frame_dir);
+        }
+        cmd.stdout(if ts_pipe {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        });
         cmd.stderr(Stdio::piped());
+        platform::prepare_child(&mut cmd);
+        privilege::apply_to_child(&mut cmd);
+        if let Some(sandbox) = &config.sandbox {
+            let writable: Vec<&Path> = writable.iter().map(PathBuf::as_path).collect();
+            sandbox::apply(&mut cmd, sandbox, &writable)?;
+        }
 
         // 启动 FFmpeg 子进程
-        let child = cmd.spawn().map_err(|e| {
+        let mut child = cmd.spawn().map_err(|e| {
             error!("Failed to spawn FFmpeg process: {}", e);
-            e
+            VtxError::SpawnFailed(e)
         })?;
-
-        // 6. 更新活动流状态
-        {
-            let mut streams = state.active_streams.lock().unwrap();
-            streams.insert(
+        let group = platform::ProcessGroup::adopt(&child);
+        if group.is_none() {
+            warn!(
+                "Stream [{}]: could not create a process group; helper processes may outlive FFmpeg",
+                name
+            );
+        }
+        if let Some(stdout) = child.stdout.take() {
+            ts::spawn_feed(state.clone(), name.to_string(), stdout);
+        }
+        let stderr_tail = StderrTail::default();
+        if let Some(stderr) = child.stderr.take() {
+            let matchers = matchers::compile(config, cfg);
+            Self::watch_stderr(
+                state.clone(),
                 name.to_string(),
-                StreamRuntime {
-                    process: child,
-                    last_accessed: Instant::now(),
-                    started_at: Instant::now(),
-                },
+                stderr,
+                stderr_tail.clone(),
+                matchers,
+                cfg.av_sync.clone(),
+                cfg.quality_watch.clone(),
             );
         }
 
-        // 7. 重置恢复状态（如果有的话）
-        {
-            let mut recovery = state.recovery_states.lock().unwrap();
-            if let Some(rec) = recovery.get_mut(name) {
-                // 重置流的崩溃计数和下次重试时间
-                rec.crash_count = 0;
-                rec.next_retry_at = None;
-            }
-        }
+        Ok(Launched {
+            child,
+            group,
+            stderr_tail,
+        })
+    }
 
-        Ok(())
+    /// 开始对外提供输出目录：建立切片索引，配置了源站推送时开始推送
+    pub fn serve_output(state: &Arc<AppState>, cfg: &StreamConfig, output_dir: &Path) {
+        segment_index::watch(state, &cfg.name, output_dir);
+        if let Some(push) = &cfg.origin_push {
+            origin_push::spawn(state, &cfg.name, push, push.base_url(cfg));
+        }
     }
 
     /// 停止指定名称的流任务
@@ -118,17 +447,641 @@ impl Engine {
     /// # 错误处理
     /// - 若流未找到，则返回空结果
     pub async fn stop_stream(state: &Arc<AppState>, name: &str) -> anyhow::Result<()> {
+        markers::clear(state, name);
+
+        // 代理流没有进程，仅关闭会话并清理缓存
+        if let Some(cfg) = state.config().stream(name).filter(|c| c.is_proxied()) {
+            proxy::close(state, cfg).await;
+            return Ok(());
+        }
+
         let running_stream = {
-            let mut streams = state.active_streams.lock().unwrap();
+            let mut streams = state.active_streams.lock_or_recover();
             streams.remove(name)
         };
 
         // 如果流正在运行，则尝试停止进程
         if let Some(mut running) = running_stream {
-            let _ = running.process.kill().await;
-            info!("Stream [{}] stopped.", name);
+            let status = platform::terminate(&mut running.process).await;
+            // 结束 FFmpeg 派生的辅助进程 (崩溃的流在 Supervisor 移除运行状态时清理)
+            drop(running.group.take());
+            let uptime = running.started_at.elapsed().as_secs();
+            state
+                .recovery_states
+                .lock_or_recover()
+                .entry(name.to_string())
+                .or_default()
+                .record_exit(ExitRecord::new("stopped", status, uptime));
+            availability::record(state, name, Transition::Stopped, None);
+            counters::record_exit(state, name, uptime, false);
+            match status {
+                Some(status) => info!("Stream [{}] stopped ({}).", name, status),
+                None => info!("Stream [{}] stopped.", name),
+            }
+        }
+
+        segment_index::unwatch(state, name);
+
+        // 丢弃密钥，下次启动时重新生成
+        let had_keys = state.stream_keys.lock_or_recover().remove(name).is_some();
+        if had_keys {
+            let _ = fs::remove_dir_all(Self::key_dir(state, name)).await;
+        } else {
+            credentials::remove_input_files(&Self::key_dir(state, name)).await;
         }
 
         Ok(())
     }
+
+    /// 轮换指定流的加密密钥
+    ///
+    /// 新密钥写入后由 FFmpeg 在下一个切片开始时读取 (periodic_rekey)
+    pub async fn rotate_key(state: &Arc<AppState>, name: &str) -> anyhow::Result<()> {
+        let (id, key) = {
+            let mut key_map = state.stream_keys.lock_or_recover();
+            let keyring = key_map
+                .get_mut(name)
+                .ok_or_else(|| anyhow::anyhow!("Stream has no keyring"))?;
+            keyring.rotate()?
+        };
+
+        keys::write_key_files(&Self::key_dir(state, name), id, &key).await?;
+        info!("Stream [{}] rotated encryption key (id {}).", name, id);
+        Ok(())
+    }
+
+    /// 生成 FFmpeg 的输出参数
+    ///
+    /// - transcode 模式：使用 output_args 并替换 `{output_dir}` 变量，配置了 overlay 时加上 `-vf`
+    /// - relay 模式：`-c copy` 加上由 hls 配置生成的封装参数
+    /// - mosaic 模式：拼接成员画面并重新编码 (见 `mosaic::output_args`)
+    /// - 配置了 rtsp_output 时追加推送到内置 RTSP 服务的第二路输出
+    /// - 配置了 ts_output 或为其他流拉取共享源 (`ts_pipe`) 时追加输出到标准输出的 MPEG-TS
+    /// - 配置了 audio_only 变体时追加纯音频 HLS 输出 (`audio.m3u8`)
+    /// - 配置了 abr 时源视频只解码一次，经 `-filter_complex split` 缩放后每档追加一路输出
+    ///   (`abr_{name}.m3u8`)
+    /// - 配置了 audio_tracks / subtitles 时每条音轨与字幕各追加一路输出
+    ///   (`audio_N.m3u8` / `subs_N.m3u8`)，主输出只保留视频
+    /// - 配置了 watermark 时追加 A/B 两路水印转码输出
+    /// - 配置了 motion 时追加场景变化检测输出
+    fn output_args(
+        cfg: &StreamConfig,
+        output_dir: &Path,
+        key_info: Option<&Path>,
+        test: Option<&TestSource>,
+        ts_pipe: bool,
+    ) -> Vec<String> {
+        let dir_str = output_dir.to_string_lossy();
+        let mut args: Vec<String> = Vec::new();
+
+        // 加密参数对每一路 HLS 输出分别生效
+        let mut key_args = Vec::new();
+        let mut hls_flags = Vec::new();
+        if let Some(info) = key_info {
+            key_args = vec![
+                "-hls_key_info_file".to_string(),
+                info.to_string_lossy().to_string(),
+            ];
+            if cfg.key_rotation_sec > 0 {
+                hls_flags.push("periodic_rekey");
+            }
+        }
+        // 合成画面只有一路 HLS 输出
+        if let Some(mosaic) = &cfg.mosaic {
+            return mosaic::output_args(cfg, mosaic, output_dir, &key_args, &hls_flags);
+        }
+        // 全局的滤镜图放在所有输出之前
+        if let Some(abr) = &cfg.abr {
+            args.extend(["-filter_complex".to_string(), abr::filter(abr)]);
+        }
+        args.extend(key_args.iter().cloned());
+
+        // 画面叠加作用于主输出 (transcode 模式)
+        if let Some(ov) = &cfg.overlay {
+            args.push("-vf".to_string());
+            args.push(overlay::filter(ov, &output_dir.join(overlay::TEXT_FILE)));
+        }
+        let loudnorm = cfg.audio.filter();
+        // 响度归一化作用于主输出的音频 (用户参数含 -an 时主输出无音频)
+        if let Some(filter) = &loudnorm {
+            if cfg.mode == StreamMode::Transcode && !cfg.output_args.iter().any(|a| a == "-an") {
+                args.extend(["-af".to_string(), filter.clone()]);
+            }
+        }
+        let prefix_len = args.len();
+        // 测试源没有可直接复制的压缩数据，复制输出改为编码
+        let copy = match test {
+            Some(test) => test_source::encode_args(cfg, test),
+            None => vec!["-c".to_string(), "copy".to_string()],
+        };
+
+        if cfg.mode == StreamMode::Relay {
+            args.extend(copy.iter().cloned());
+            // 视频仍直接复制，音频须重新编码才能应用滤镜；多语言音轨另行输出时主输出无音频
+            if let Some(filter) = loudnorm.as_ref().filter(|_| cfg.audio_tracks.is_empty()) {
+                args.extend([
+                    "-c:a".to_string(),
+                    "aac".to_string(),
+                    "-b:a".to_string(),
+                    format!("{}k", cfg.audio.bitrate_kbps),
+                    "-af".to_string(),
+                    filter.clone(),
+                ]);
+            }
+            if cfg.has_media_tracks() {
+                args.extend(["-map".to_string(), "0:v:0".to_string()]);
+                // 只配置了字幕时音频仍随主输出
+                if cfg.audio_tracks.is_empty() {
+                    args.extend(["-map".to_string(), "0:a:0?".to_string()]);
+                }
+            }
+        }
+
+        // 替换输出路径变量
+        args.extend(
+            cfg.output_args
+                .iter()
+                .map(|arg| arg.replace("{output_dir}", &dir_str)),
+        );
+
+        let base_flags = hls_flags.clone();
+        if cfg.mode == StreamMode::Relay {
+            // 切片先写入 .tmp 再重命名，下发时不会读到未写完的切片
+            hls_flags.extend(["temp_file", "delete_segments"]);
+            if cfg.program_date_time {
+                hls_flags.push("program_date_time");
+            }
+            args.extend([
+                "-f".to_string(),
+                "hls".to_string(),
+                "-hls_time".to_string(),
+                cfg.hls.segment_duration_sec.to_string(),
+                "-hls_list_size".to_string(),
+                cfg.hls.list_size.to_string(),
+            ]);
+            if let Some(naming) = cfg.hls.segment_naming() {
+                hls_flags.extend(naming.flags);
+                if naming.strftime {
+                    args.extend(["-strftime".to_string(), "1".to_string()]);
+                }
+                args.extend([
+                    "-hls_segment_filename".to_string(),
+                    output_dir
+                        .join(&naming.pattern)
+                        .to_string_lossy()
+                        .to_string(),
+                ]);
+            }
+        }
+
+        if !hls_flags.is_empty() {
+            // transcode 模式下放在 output_args 之前，保持输出路径在最后
+            let at = if cfg.mode == StreamMode::Relay {
+                args.len()
+            } else {
+                prefix_len
+            };
+            args.splice(at..at, ["-hls_flags".to_string(), hls_flags.join("+")]);
+        }
+
+        if cfg.mode == StreamMode::Relay {
+            args.push(output_dir.join("index.m3u8").to_string_lossy().to_string());
+        }
+
+        // 纯音频变体：只保留第一路音轨并转为 AAC，供低带宽链路收听
+        let mut track_flags = base_flags;
+        track_flags.extend(["temp_file", "delete_segments"]);
+        if cfg.program_date_time {
+            track_flags.push("program_date_time");
+        }
+        if cfg.variants.contains(&Variant::AudioOnly) {
+            args.extend(key_args.iter().cloned());
+            args.extend(
+                [
+                    "-map", "0:a:0", "-vn", "-c:a", "aac", "-b:a", "64k", "-f", "hls",
+                ]
+                .map(String::from),
+            );
+            if let Some(filter) = &loudnorm {
+                args.extend(["-af".to_string(), filter.clone()]);
+            }
+            args.extend([
+                "-hls_time".to_string(),
+                cfg.hls.segment_duration_sec.to_string(),
+                "-hls_list_size".to_string(),
+                cfg.hls.list_size.to_string(),
+                "-hls_flags".to_string(),
+                track_flags.join("+"),
+                "-hls_segment_filename".to_string(),
+                output_dir.join("audio%d.ts").to_string_lossy().to_string(),
+                output_dir.join("audio.m3u8").to_string_lossy().to_string(),
+            ]);
+        }
+
+        if let Some(abr) = &cfg.abr {
+            args.extend(abr::output_args(
+                cfg,
+                abr,
+                output_dir,
+                &key_args,
+                &track_flags.join("+"),
+                loudnorm.as_ref(),
+            ));
+        }
+
+        // 多语言音轨：逐条转为 AAC 单独切片
+        for (i, track) in cfg.audio_tracks.iter().enumerate() {
+            args.extend(key_args.iter().cloned());
+            args.extend([
+                "-map".to_string(),
+                format!("0:a:{}", track.index.unwrap_or(i)),
+                "-vn".to_string(),
+                "-c:a".to_string(),
+                "aac".to_string(),
+                "-b:a".to_string(),
+                format!("{}k", track.bitrate_kbps),
+            ]);
+            if let Some(filter) = &loudnorm {
+                args.extend(["-af".to_string(), filter.clone()]);
+            }
+            args.extend([
+                "-f".to_string(),
+                "hls".to_string(),
+                "-hls_time".to_string(),
+                cfg.hls.segment_duration_sec.to_string(),
+                "-hls_list_size".to_string(),
+                cfg.hls.list_size.to_string(),
+                "-hls_flags".to_string(),
+                track_flags.join("+"),
+                "-hls_segment_filename".to_string(),
+                output_dir
+                    .join(format!("audio_{}_%d.ts", i))
+                    .to_string_lossy()
+                    .to_string(),
+                output_dir
+                    .join(playlist::audio_playlist(i))
+                    .to_string_lossy()
+                    .to_string(),
+            ]);
+        }
+
+        // 字幕：转为 WebVTT 并按切片时长分段，循环复用文件名
+        let mut next_input = 1;
+        let mut embedded = 0;
+        for (i, sub) in cfg.subtitles.iter().enumerate() {
+            let stream = if sub.source.is_some() {
+                next_input += 1;
+                format!("{}:s:{}", next_input - 1, sub.index.unwrap_or(0))
+            } else {
+                embedded += 1;
+                format!("0:s:{}", sub.index.unwrap_or(embedded - 1))
+            };
+            args.extend([
+                "-map".to_string(),
+                stream,
+                "-c:s".to_string(),
+                "webvtt".to_string(),
+                "-f".to_string(),
+                "segment".to_string(),
+                "-segment_format".to_string(),
+                "webvtt".to_string(),
+                "-segment_time".to_string(),
+                cfg.hls.segment_duration_sec.to_string(),
+                "-segment_list".to_string(),
+                output_dir
+                    .join(playlist::subtitle_playlist(i))
+                    .to_string_lossy()
+                    .to_string(),
+                "-segment_list_type".to_string(),
+                "m3u8".to_string(),
+                "-segment_list_size".to_string(),
+                cfg.hls.list_size.to_string(),
+                "-segment_list_flags".to_string(),
+                "+live".to_string(),
+                "-segment_wrap".to_string(),
+                (cfg.hls.list_size * 2 + 2).to_string(),
+                output_dir
+                    .join(format!("subs_{}_%d.vtt", i))
+                    .to_string_lossy()
+                    .to_string(),
+            ]);
+        }
+        // 取证水印的 A/B 两路转码输出
+        if let Some(wm) = &cfg.watermark {
+            args.extend(watermark::output_args(
+                cfg,
+                wm,
+                output_dir,
+                &key_args,
+                &track_flags.join("+"),
+            ));
+        }
+
+        // 独立字幕输入不应被其余未指定 -map 的输出自动选中
+        let no_subtitles = cfg.subtitles.iter().any(|s| s.source.is_some());
+
+        // 运动检测输出，结果从 stderr 读取
+        if let Some(m) = &cfg.motion {
+            args.extend(motion::detection_args(m));
+        }
+
+        // 额外推送到内置 RTSP 服务
+        if let Some(rtsp) = &cfg.rtsp_output {
+            if no_subtitles {
+                args.push("-sn".to_string());
+            }
+            args.extend(copy.iter().cloned());
+            args.extend(["-f", "rtsp", "-rtsp_transport", "tcp"].map(String::from));
+            args.push(format!(
+                "rtsp://127.0.0.1:{}{}",
+                rtsp.port,
+                rtsp.path(&cfg.name)
+            ));
+        }
+
+        // 额外输出到标准输出，由网关分发给 MPEG-TS 订阅者与共享源的读取者
+        if ts_pipe {
+            if no_subtitles {
+                args.push("-sn".to_string());
+            }
+            args.extend(copy.iter().cloned());
+            args.extend(["-f", "mpegts", "pipe:1"].map(String::from));
+        }
+
+        args
+    }
+
+    /// 删除输出目录中未被任何播放列表引用的旧切片
+    ///
+    /// 仅删除早于播放列表最后更新时间的文件，避免误删 FFmpeg 正在写入的切片
+    pub async fn trim_segments(index: &DirIndex) {
+        let output_dir = index.dir();
+        let mut referenced = std::collections::HashSet::new();
+        let mut playlist_mtime = None;
+        let mut segments = Vec::new();
+        for (name, info) in index.files() {
+            let modified = Some(info.modified);
+            if name.ends_with(".m3u8") {
+                let text = match index.playlist(&name) {
+                    Some(text) => Some(text),
+                    None => fs::read_to_string(output_dir.join(&name)).await.ok(),
+                };
+                if let Some(text) = text {
+                    referenced.extend(
+                        text.lines()
+                            .map(str::trim)
+                            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                            .map(String::from),
+                    );
+                }
+                playlist_mtime = playlist_mtime.max(modified);
+            } else if !name.starts_with('.') {
+                segments.push((name, modified));
+            }
+        }
+
+        let Some(playlist_mtime) = playlist_mtime else {
+            return;
+        };
+        for (name, modified) in segments {
+            if !referenced.contains(&name) && modified.is_some_and(|m| m < playlist_mtime) {
+                let _ = fs::remove_file(output_dir.join(&name)).await;
+            }
+        }
+    }
+
+    /// 持续读取 FFmpeg 的 stderr，避免管道写满阻塞进程，并提取运动检测事件
+    ///
+    /// 末尾若干行保存在 `tail` 中，进程退出后用于判断失败类型；
+    /// 每行同时交给日志匹配规则检查，启用音画同步或质量监测时每次写出播放列表后分析新切片
+    fn watch_stderr(
+        state: Arc<AppState>,
+        name: String,
+        stderr: ChildStderr,
+        tail: StderrTail,
+        mut matchers: Vec<ActiveMatcher>,
+        sync: Option<AvSyncConfig>,
+        quality: Option<QualityWatchConfig>,
+    ) {
+        tokio::spawn(async move {
+            let secrets = credentials::secrets(&state.config());
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // FFmpeg 会在日志中回显输入地址，保存与匹配前先屏蔽凭据
+                let line = credentials::redact(&secrets, &line);
+                failure::push_line(&tail, &line);
+                matchers::check(&state, &name, &mut matchers, &line);
+                av_sync::on_stderr(&state, &name, &line);
+                if let Some(score) = motion::scene_score(&line) {
+                    motion::on_motion(&state, &name, score);
+                } else if is_playlist_write(&line) {
+                    // 首次写出播放列表即首个切片完成
+                    metrics::record(&state, &name, Milestone::FirstSegment);
+                    if let Some(sync) = &sync {
+                        av_sync::on_playlist_write(&state, &name, &line, sync);
+                    }
+                    if let Some(quality) = &quality {
+                        quality::on_playlist_write(&state, &name, &line, quality);
+                    }
+                }
+            }
+        });
+    }
+
+    /// 流的 HLS 输出目录
+    ///
+    /// 默认租户流位于 `{hls_root}/{tenant}/{name}`，默认命名空间位于 `{hls_root}/{name}`，
+    /// 可由 `server.hls_layout` 或流的 `output_dir` 调整；
+    /// 经配置迁移切换过的流在下次启动前使用其暂存目录 (见 `migrate`)
+    pub fn output_dir(state: &AppState, cfg: &StreamConfig) -> std::path::PathBuf {
+        if let Some(dir) = state.output_dirs.lock_or_recover().get(&cfg.name) {
+            return dir.clone();
+        }
+        state.config().output_dir(cfg)
+    }
+
+    /// 一路输入的参数 (`<options> -i <url>`)，源地址中的凭据引用在此展开
+    ///
+    /// 开启 `server.hide_source_credentials` 时，含凭据的地址经 key_root 下的输入列表传给 FFmpeg
+    async fn input(
+        state: &AppState,
+        name: &str,
+        index: usize,
+        source: &str,
+        options: Vec<String>,
+    ) -> anyhow::Result<Vec<String>> {
+        let config = state.config();
+        let url = credentials::expand(&config, source)?;
+        if config.server.hide_source_credentials && credentials::has_secrets(source, &url) {
+            return credentials::input_file_args(
+                &Self::key_dir(state, name),
+                index,
+                &url,
+                &options,
+            )
+            .await;
+        }
+        let mut args = options;
+        args.extend(["-i".to_string(), url]);
+        Ok(args)
+    }
+
+    /// 流的密钥存储目录
+    fn key_dir(state: &AppState, name: &str) -> std::path::PathBuf {
+        std::path::Path::new(&state.config().server.key_root).join(name)
+    }
+}
+
+/// FFmpeg 的 HLS 封装器在 info 日志级别下每次写出播放列表时输出
+/// `Opening '.../index.m3u8.tmp' for writing`
+fn is_playlist_write(line: &str) -> bool {
+    line.contains("Opening '") && line.contains(".m3u8") && line.ends_with("for writing")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    /// 临时目录下的配置：模拟后端 (本测试进程以 `mock-engine` 参数启动，随即退出)
+    fn test_state(tag: &str, min_start_interval_sec: u64) -> (Arc<AppState>, PathBuf) {
+        let root = std::env::temp_dir().join(format!("vtx-engine-{}-{}", tag, std::process::id()));
+        let yaml = format!(
+            r#"
+server:
+  listen: 127.0.0.1:0
+  ffmpeg_binary: ffmpeg
+  supervisor_interval_ms: 1000
+  hls_root: {root}/hls
+  key_root: {root}/keys
+  record_root: {root}/rec
+  state_root: {root}/state
+  backend: mock
+streams:
+  - name: cam
+    source: rtsp://127.0.0.1:1/cam
+    min_start_interval_sec: {min_start_interval_sec}
+"#,
+            root = root.display(),
+        );
+        let config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+        let state = AppState::new(config, root.join("config.yaml")).unwrap();
+        (Arc::new(state), root)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_cold_starts_launch_once() {
+        let (state, root) = test_state("race", 60);
+        let starts: Vec<_> = (0..20)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move { Engine::start_stream(&state, "cam").await })
+            })
+            .collect();
+
+        let mut launched = 0;
+        for start in starts {
+            match start.await.unwrap() {
+                Ok(StartOutcome::Started) => launched += 1,
+                Ok(StartOutcome::AlreadyRunning) => {}
+                Err(e) => panic!("start failed: {}", e),
+            }
+        }
+        assert_eq!(launched, 1);
+
+        // 启动时间与进程在同一把锁内登记，停止后立即重启受启动间隔限制
+        Engine::stop_stream(&state, "cam").await.unwrap();
+        assert!(matches!(
+            Engine::start_stream(&state, "cam").await,
+            Err(VtxError::StartRateLimited { .. })
+        ));
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    /// 本进程的子进程中名为 `comm` 且已退出未回收 (状态 Z) 的进程号
+    #[cfg(target_os = "linux")]
+    fn zombies(comm: &str) -> Vec<u32> {
+        let mut found = Vec::new();
+        for task in std::fs::read_dir("/proc/self/task").unwrap().flatten() {
+            let children =
+                std::fs::read_to_string(task.path().join("children")).unwrap_or_default();
+            for pid in children.split_whitespace() {
+                let stat =
+                    std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+                // "pid (comm) state ..."
+                let Some((head, rest)) = stat.rsplit_once(") ") else {
+                    continue;
+                };
+                if head.ends_with(&format!("({}", comm)) && rest.starts_with('Z') {
+                    found.push(pid.parse().unwrap());
+                }
+            }
+        }
+        found
+    }
+
+    /// 反复启动立即退出 (由 Supervisor 回收) 与持续运行 (由 stop_stream 回收) 的进程，
+    /// 之后不应留下僵尸进程
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn reaps_every_child() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::Duration;
+
+        const COMM: &str = "vtx-zombie-ff";
+        let root = std::env::temp_dir().join(format!("vtx-engine-reap-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let script = root.join(COMM);
+        // 输出路径含流名：crash 流以非零状态退出，其余持续运行
+        std::fs::write(
+            &script,
+            "#!/bin/sh\ncase \"$*\" in *crash*) exit 3 ;; esac\nsleep 30\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let yaml = format!(
+            r#"
+server:
+  listen: 127.0.0.1:0
+  ffmpeg_binary: {script}
+  supervisor_interval_ms: 20
+  hls_root: {root}/hls
+  key_root: {root}/keys
+  record_root: {root}/rec
+  state_root: {root}/state
+streams:
+  - name: crash
+    source: rtsp://127.0.0.1:1/crash
+    supervise: {{auto_restart: false}}
+  - name: run
+    source: rtsp://127.0.0.1:1/run
+"#,
+            script = script.display(),
+            root = root.display(),
+        );
+        let config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+        let state = Arc::new(AppState::new(config, root.join("config.yaml")).unwrap());
+        let supervisor = tokio::spawn(crate::supervisor::start_supervisor(state.clone(), 20));
+
+        for _ in 0..20 {
+            // 崩溃退避只影响下一次启动的时间，测试中清除
+            state.recovery_states.lock_or_recover().remove("crash");
+            Engine::start_stream(&state, "crash").await.unwrap();
+            Engine::start_stream(&state, "run").await.unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while state.active_streams.lock_or_recover().contains_key("crash") {
+                assert!(Instant::now() < deadline, "crashed process was not reaped");
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Engine::stop_stream(&state, "run").await.unwrap();
+        }
+
+        supervisor.abort();
+        assert_eq!(zombies(COMM), Vec::<u32>::new());
+        let _ = std::fs::remove_dir_all(root);
+    }
 }
@@ -0,0 +1,197 @@
+use crate::clock;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use std::fmt;
+use std::time::SystemTime;
+
+/// 流操作的错误类型
+///
+/// Web 层据此返回对应的 HTTP 状态码与机器可读的错误码 (`{"error": code, "message": ...}`)；
+/// 其余内部错误仍使用 anyhow，转换后归为 `Internal`
+#[derive(Debug)]
+pub enum VtxError {
+    /// 配置中没有该流 (或调用方无权访问)
+    ConfigNotFound(String),
+    /// 流被运维禁用
+    Disabled(String),
+    /// 代理模式的流没有本地进程
+    NoLocalProcess(String),
+    /// 流因无法恢复的错误或崩溃循环被隔离，需手动启动释放
+    StreamQuarantined {
+        name: String,
+        reason: String,
+    },
+    /// 流崩溃后仍在退避期内，到 `retry_at` 后由监控循环重启
+    BackingOff {
+        name: String,
+        crash_count: u32,
+        retry_at: SystemTime,
+    },
+    /// 距上次启动不足 `min_start_interval_sec`，到 `retry_at` 后才允许再次启动
+    StartRateLimited {
+        name: String,
+        min_interval_sec: u64,
+        retry_at: SystemTime,
+    },
+    /// 流因系统压力过高被负载卸载，压力解除后放行
+    LoadShed {
+        name: String,
+        reason: String,
+    },
+    /// 系统内存、租户配额或硬件编码会话不足
+    InsufficientResources(String),
+    /// FFmpeg 进程无法启动
+    SpawnFailed(std::io::Error),
+    /// 输出或状态目录所在磁盘已满
+    StorageFull(String),
+    /// 缺少或无效的访问令牌
+    Unauthorized,
+    Internal(anyhow::Error),
+}
+
+impl VtxError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::ConfigNotFound(_) => StatusCode::NOT_FOUND,
+            Self::Disabled(_) | Self::NoLocalProcess(_) | Self::StreamQuarantined { .. } => {
+                StatusCode::CONFLICT
+            }
+            Self::StartRateLimited { .. } | Self::InsufficientResources(_) => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::BackingOff { .. } | Self::LoadShed { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::StorageFull(_) => StatusCode::INSUFFICIENT_STORAGE,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::SpawnFailed(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// 机器可读的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ConfigNotFound(_) => "config_not_found",
+            Self::Disabled(_) => "disabled",
+            Self::NoLocalProcess(_) => "no_local_process",
+            Self::StreamQuarantined { .. } => "quarantined",
+            Self::BackingOff { .. } => "backing_off",
+            Self::StartRateLimited { .. } => "start_rate_limited",
+            Self::LoadShed { .. } => "load_shed",
+            Self::InsufficientResources(_) => "resource_rejected",
+            Self::SpawnFailed(_) => "spawn_failed",
+            Self::StorageFull(_) => "storage_full",
+            Self::Unauthorized => "unauthorized",
+            Self::Internal(_) => "internal",
+        }
+    }
+}
+
+impl fmt::Display for VtxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConfigNotFound(name) => write!(f, "Stream [{}] not found", name),
+            Self::Disabled(name) => write!(f, "Stream [{}] is disabled for maintenance", name),
+            Self::NoLocalProcess(name) => write!(
+                f,
+                "Stream [{}] is relayed in proxy mode and has no local process",
+                name
+            ),
+            Self::StreamQuarantined { name, reason } => {
+                write!(f, "Stream [{}] is quarantined: {}", name, reason)
+            }
+            Self::BackingOff {
+                name,
+                crash_count,
+                retry_at,
+            } => write!(
+                f,
+                "Stream [{}] is backing off after {} crash(es), next retry at {}",
+                name,
+                crash_count,
+                clock::rfc3339(*retry_at)
+            ),
+            Self::StartRateLimited {
+                name,
+                min_interval_sec,
+                retry_at,
+            } => write!(
+                f,
+                "Stream [{}] may start at most once every {}s, next start allowed at {}",
+                name,
+                min_interval_sec,
+                clock::rfc3339(*retry_at)
+            ),
+            Self::LoadShed { name, reason } => {
+                write!(
+                    f,
+                    "Stream [{}] is shed under system pressure: {}",
+                    name, reason
+                )
+            }
+            Self::InsufficientResources(msg) | Self::StorageFull(msg) => f.write_str(msg),
+            Self::SpawnFailed(e) => write!(f, "Failed to spawn FFmpeg process: {}", e),
+            Self::Unauthorized => f.write_str("Unauthorized"),
+            Self::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for VtxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SpawnFailed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// 保留嵌套的类型化错误 (如依赖流启动失败)，磁盘已满的 IO 错误单独归类
+impl From<anyhow::Error> for VtxError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<VtxError>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        match e.downcast::<std::io::Error>() {
+            Ok(io) => io.into(),
+            Err(e) => Self::Internal(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for VtxError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::StorageFull {
+            Self::StorageFull(e.to_string())
+        } else {
+            Self::Internal(e.into())
+        }
+    }
+}
+
+impl IntoResponse for VtxError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::json!({
+            "error": self.code(),
+            "message": self.to_string(),
+        });
+        let mut headers = HeaderMap::new();
+        if let Self::BackingOff { retry_at, .. } | Self::StartRateLimited { retry_at, .. } = &self {
+            let wait = retry_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+                .as_secs_f64()
+                .ceil() as u64;
+            body["retry_at"] = clock::rfc3339(*retry_at).into();
+            body["retry_after_sec"] = wait.into();
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(wait));
+        }
+        (self.status(), headers, Json(body)).into_response()
+    }
+}
+
+/// 供仍以 `(StatusCode, String)` 作为错误类型的处理函数使用 `?`
+impl From<VtxError> for (StatusCode, String) {
+    fn from(e: VtxError) -> Self {
+        (e.status(), e.to_string())
+    }
+}
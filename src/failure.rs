@@ -0,0 +1,162 @@
+use crate::clock;
+use crate::platform;
+use crate::state::LockExt;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// 保留的 stderr 末尾行数 (用于判断退出原因)
+const TAIL_LINES: usize = 20;
+
+/// 每个流保留的退出记录条数
+pub const EXIT_HISTORY: usize = 20;
+
+/// FFmpeg stderr 的末尾若干行，由 stderr 读取任务写入
+pub type StderrTail = Arc<Mutex<TailBuffer>>;
+
+#[derive(Debug, Default)]
+pub struct TailBuffer {
+    lines: VecDeque<String>,
+    /// 累计写入的行数 (供日志接口增量读取)
+    total: u64,
+}
+
+/// 追加一行到 stderr 末尾缓存
+pub fn push_line(tail: &StderrTail, line: &str) {
+    let mut tail = tail.lock_or_recover();
+    if tail.lines.len() == TAIL_LINES {
+        tail.lines.pop_front();
+    }
+    tail.lines.push_back(line.to_string());
+    tail.total += 1;
+}
+
+/// 读取序号 `after` 之后的日志行，返回 (日志行, 下一次读取的序号, 已被覆盖而丢失的行数)
+pub fn lines_after(tail: &StderrTail, after: u64) -> (Vec<String>, u64, u64) {
+    let tail = tail.lock_or_recover();
+    // 序号大于累计行数说明进程已重启，从新进程的第一行开始
+    let after = if after > tail.total { 0 } else { after };
+    let first = tail.total - tail.lines.len() as u64;
+    let skip = after.saturating_sub(first) as usize;
+    let lines = tail.lines.iter().skip(skip).cloned().collect();
+    (lines, tail.total, first.saturating_sub(after))
+}
+
+/// 进程退出原因分类，决定重试策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// 网络抖动、源断开等，立即重试
+    Transient,
+    /// 参数或源地址错误，长时间退避后重试
+    Config,
+    /// 缺少编码器等无法自行恢复的错误，隔离直至人工处理
+    Fatal,
+    /// 无法识别，按常规指数退避
+    Unknown,
+}
+
+/// FFmpeg 进程的一次退出 (已被回收)
+#[derive(Debug, Clone, Serialize)]
+pub struct ExitRecord {
+    pub time: String,
+    /// `crashed` (自行退出) 或 `stopped` (由网关结束)
+    pub cause: &'static str,
+    /// 退出码，被信号结束时为 None
+    pub code: Option<i32>,
+    /// 结束进程的信号 (仅 Unix)
+    pub signal: Option<i32>,
+    /// 崩溃的失败类型与依据的日志行
+    pub kind: Option<FailureKind>,
+    pub reason: Option<String>,
+    pub uptime_seconds: u64,
+}
+
+impl ExitRecord {
+    pub fn new(cause: &'static str, status: Option<ExitStatus>, uptime_seconds: u64) -> Self {
+        Self {
+            time: clock::rfc3339(SystemTime::now()),
+            cause,
+            code: status.and_then(|s| s.code()),
+            signal: status.as_ref().and_then(platform::exit_signal),
+            kind: None,
+            reason: None,
+            uptime_seconds,
+        }
+    }
+}
+
+/// 无法恢复的错误 (需要修改配置或安装组件)
+const FATAL_PATTERNS: &[&str] = &[
+    "unknown encoder",
+    "encoder not found",
+    "unknown decoder",
+    "decoder not found",
+    "unrecognized option",
+    "option not found",
+    "no such filter",
+    "requested output format",
+    "unknown input format",
+];
+
+/// 配置或源访问错误
+const CONFIG_PATTERNS: &[&str] = &[
+    "invalid argument",
+    "invalid data found when processing input",
+    "error opening filters",
+    "error initializing",
+    "401 unauthorized",
+    "403 forbidden",
+    "404 not found",
+    "permission denied",
+    "no such file or directory",
+];
+
+/// 网络层错误
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "connection timed out",
+    "connection refused",
+    "connection reset",
+    "network is unreachable",
+    "no route to host",
+    "broken pipe",
+    "timed out",
+    "end of file",
+    "server returned 5",
+];
+
+/// FFmpeg 收到 SIGINT/SIGTERM 后的退出码
+const EXIT_INTERRUPTED: i32 = 255;
+
+/// 根据退出状态与 stderr 末尾内容判断失败类型，同时返回作为依据的日志行
+///
+/// 最后一条匹配的日志优先，FFmpeg 的致命错误通常在退出前最后输出
+pub fn classify(status: ExitStatus, tail: &StderrTail) -> (FailureKind, Option<String>) {
+    let tail = tail.lock_or_recover();
+    for line in tail.lines.iter().rev() {
+        let lower = line.to_ascii_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|p| lower.contains(p));
+        let kind = if matches(FATAL_PATTERNS) {
+            FailureKind::Fatal
+        } else if matches(CONFIG_PATTERNS) {
+            FailureKind::Config
+        } else if matches(TRANSIENT_PATTERNS) {
+            FailureKind::Transient
+        } else {
+            continue;
+        };
+        return (kind, Some(line.clone()));
+    }
+
+    // 被信号杀死 (如 OOM) 的原因未知；正常退出或被外部中断说明输入已结束
+    let kind = if platform::exit_signal(&status).is_some() {
+        FailureKind::Unknown
+    } else if status.success() || status.code() == Some(EXIT_INTERRUPTED) {
+        FailureKind::Transient
+    } else {
+        FailureKind::Unknown
+    };
+    (kind, None)
+}
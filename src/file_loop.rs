@@ -0,0 +1,144 @@
+use crate::config::{FileLoopConfig, StreamConfig};
+use crate::playout;
+use crate::privilege;
+use crate::state::{AppState, LockExt};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// 探测单个文件时长的超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 循环播放列表 (ffconcat) 的存放位置，不放在对外提供的输出目录中
+pub fn list_path(state: &AppState, name: &str) -> PathBuf {
+    Path::new(&state.config().server.state_root)
+        .join("file_loop")
+        .join(format!("{}.ffconcat", name))
+}
+
+/// 生成循环播放的输入参数
+///
+/// 按节目表选择当前节目 (节目时段或垫片)，再计算应播放的文件与位置：
+/// (当前时间 - 起点) 对总时长取模，进程重启后从节目表位置继续，而不是从头播放。
+/// 节目时段的文件缺失时改播垫片；无法探测时长时从第一个文件开始
+pub async fn input_args(
+    state: &AppState,
+    cfg: &StreamConfig,
+    fl: &FileLoopConfig,
+) -> anyhow::Result<Vec<String>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let program = playout::current(fl, now);
+    // 记录节目时段 (即使改播垫片)，调度器据此判断何时切换
+    state
+        .playout_slots
+        .lock_or_recover()
+        .insert(cfg.name.clone(), program.slot);
+
+    let missing = |files: &[String]| files.iter().find(|f| !Path::new(f).is_file()).cloned();
+    let (files, origin) = match missing(program.files) {
+        None => (program.files, program.origin),
+        Some(file) if program.slot.is_some() => {
+            warn!(
+                "Stream [{}] schedule file {} does not exist; playing filler content",
+                cfg.name, file
+            );
+            (&fl.files[..], fl.epoch)
+        }
+        Some(file) => anyhow::bail!("Stream [{}] loop file {} does not exist", cfg.name, file),
+    };
+    if let Some(file) = missing(files) {
+        anyhow::bail!("Stream [{}] loop file {} does not exist", cfg.name, file);
+    }
+
+    let ffmpeg = &state.config().server.ffmpeg_binary;
+    let mut durations = Vec::new();
+    for file in files {
+        durations.push(probe_duration(ffmpeg, file).await);
+    }
+    let (start, offset) = match durations.iter().copied().collect::<Option<Vec<f64>>>() {
+        Some(durations) => position(&durations, now, origin),
+        None => {
+            warn!(
+                "Stream [{}]: could not read the duration of every loop file; starting from the first file",
+                cfg.name
+            );
+            (0, 0.0)
+        }
+    };
+
+    // 列表从当前文件开始轮转，循环播放时顺序不变
+    let mut list = String::from("ffconcat version 1.0\n");
+    for file in files[start..].iter().chain(&files[..start]) {
+        list.push_str(&format!("file '{}'\n", file.replace('\'', "'\\''")));
+    }
+    let path = list_path(state, &cfg.name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    fs::write(&path, list).await?;
+    info!(
+        "Stream [{}] loop starts at file {} ({:.1}s in)",
+        cfg.name, files[start], offset
+    );
+
+    let mut args: Vec<String> = ["-re", "-stream_loop", "-1"].map(String::from).to_vec();
+    // 仅首轮从节目表位置开始，之后每轮从列表开头播放
+    if offset >= 1.0 {
+        args.extend(["-ss".to_string(), format!("{:.3}", offset)]);
+    }
+    args.extend(
+        ["-f", "concat", "-safe", "0", "-i"]
+            .map(String::from)
+            .to_vec(),
+    );
+    args.push(path.to_string_lossy().to_string());
+    Ok(args)
+}
+
+/// 当前的文件序号与文件内的播放位置 (秒)
+fn position(durations: &[f64], now: u64, origin: u64) -> (usize, f64) {
+    let total: f64 = durations.iter().sum();
+    if total <= 0.0 {
+        return (0, 0.0);
+    }
+    let mut position = (now as f64 - origin as f64).rem_euclid(total);
+    for (i, duration) in durations.iter().enumerate() {
+        if position < *duration {
+            return (i, position);
+        }
+        position -= duration;
+    }
+    (0, 0.0)
+}
+
+/// 由 FFmpeg 读取文件头获得时长 (stderr 中的 `Duration: HH:MM:SS.xx`)
+async fn probe_duration(ffmpeg: &str, file: &str) -> Option<f64> {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-i", file])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    privilege::apply_to_child(&mut cmd);
+    let output = tokio::time::timeout(PROBE_TIMEOUT, cmd.output())
+        .await
+        .ok()?
+        .ok()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let value = stderr
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Duration: "))?
+        .split(',')
+        .next()?;
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    (seconds > 0.0).then_some(seconds)
+}
@@ -0,0 +1,138 @@
+use crate::config::{ForwardAuthConfig, StreamConfig};
+use crate::http_client;
+use crate::pattern::Pattern;
+use crate::state::{AppState, LockExt};
+use axum::http::StatusCode;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// 缓存的决定超过该数量时清理已过期的条目
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// 缓存键：(流, 客户端地址, 令牌)
+type CacheKey = (String, String, Option<String>);
+
+/// 鉴权服务的决定缓存
+#[derive(Debug, Default)]
+pub struct AuthCache {
+    /// 缓存键 -> (决定, 过期时间)
+    decisions: HashMap<CacheKey, (Decision, Instant)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Decision {
+    Allow,
+    /// 原样返回给播放器的状态 (401 或 403)
+    Deny(StatusCode),
+}
+
+/// 待鉴权的播放请求
+pub struct PlaybackRequest<'a> {
+    pub client_ip: &'a str,
+    pub token: Option<&'a str>,
+    /// 请求路径 (不含查询参数)
+    pub path: &'a str,
+    /// `hls`、`ts` 或 `rtsp`
+    pub protocol: &'static str,
+}
+
+/// 配置了 forward_auth 且流在其范围内时询问鉴权服务 (优先使用缓存的决定)，
+/// 拒绝时返回应答给播放器的状态与说明
+pub async fn check(
+    state: &AppState,
+    cfg: &StreamConfig,
+    req: PlaybackRequest<'_>,
+) -> Result<(), (StatusCode, String)> {
+    let config = state.config();
+    let Some(fa) = &config.forward_auth else {
+        return Ok(());
+    };
+    if !applies(fa, &cfg.name) {
+        return Ok(());
+    }
+
+    let key = (
+        cfg.name.clone(),
+        req.client_ip.to_string(),
+        req.token.map(str::to_string),
+    );
+    let now = Instant::now();
+    let cached = state
+        .forward_auth_cache
+        .lock_or_recover()
+        .decisions
+        .get(&key)
+        .filter(|(_, expires)| *expires > now)
+        .map(|(decision, _)| *decision);
+
+    let decision = match cached {
+        Some(decision) => decision,
+        None => match ask(fa, cfg, &req).await {
+            Ok(decision) => {
+                if fa.cache_ttl_sec > 0 {
+                    let mut cache = state.forward_auth_cache.lock_or_recover();
+                    if cache.decisions.len() >= PRUNE_THRESHOLD {
+                        cache.decisions.retain(|_, (_, expires)| *expires > now);
+                    }
+                    let expires = now + Duration::from_secs(fa.cache_ttl_sec);
+                    cache.decisions.insert(key, (decision, expires));
+                }
+                decision
+            }
+            // 故障时不缓存，下一个请求重新询问
+            Err(e) => {
+                warn!("Forward auth for [{}] failed: {}", cfg.name, e);
+                if !fa.fail_open {
+                    return Err((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "Authorization service unavailable".to_string(),
+                    ));
+                }
+                Decision::Allow
+            }
+        },
+    };
+    match decision {
+        Decision::Allow => Ok(()),
+        Decision::Deny(status) => Err((status, "Playback not authorized".to_string())),
+    }
+}
+
+fn applies(fa: &ForwardAuthConfig, stream: &str) -> bool {
+    fa.streams.is_empty()
+        || fa
+            .streams
+            .iter()
+            .filter_map(|p| Pattern::new(p).ok())
+            .any(|p| p.is_match(stream))
+}
+
+async fn ask(
+    fa: &ForwardAuthConfig,
+    cfg: &StreamConfig,
+    req: &PlaybackRequest<'_>,
+) -> anyhow::Result<Decision> {
+    let body = serde_json::json!({
+        "stream": cfg.name,
+        "tenant": cfg.tenant,
+        "client_ip": req.client_ip,
+        "token": req.token,
+        "path": req.path,
+        "protocol": req.protocol,
+    });
+    let res = http_client::send_json(
+        "POST",
+        &fa.url,
+        &body,
+        fa.bearer_token.as_deref(),
+        Duration::from_millis(fa.timeout_ms),
+    )
+    .await?;
+    match res.status {
+        _ if res.is_success() => Ok(Decision::Allow),
+        401 => Ok(Decision::Deny(StatusCode::UNAUTHORIZED)),
+        403 => Ok(Decision::Deny(StatusCode::FORBIDDEN)),
+        status => anyhow::bail!("authorization service returned status {}", status),
+    }
+}
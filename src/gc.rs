@@ -0,0 +1,229 @@
+use crate::migrate;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// 不属于任何流的目录在最后一次修改后至少保留的时间，避免与刚创建的流竞争
+const ORPHAN_GRACE: Duration = Duration::from_secs(300);
+
+/// HLS 输出文件的扩展名；直接包含这类文件的目录才会被当作残留的流目录删除，
+/// 其余目录 (如改名前的租户目录) 只清理其中的流目录，清空后再删除
+const MEDIA_EXTENSIONS: &[&str] = &["m3u8", "ts", "m4s", "mp4", "vtt", "tmp"];
+
+/// 被清理的目录
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovedDir {
+    pub path: PathBuf,
+    /// `orphaned` (不属于任何已配置的流) 或 `idle` (流未运行且长时间未更新)
+    pub reason: &'static str,
+    pub bytes: u64,
+}
+
+/// 一次清理的结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    /// 只列出将被清理的目录，未实际删除
+    pub dry_run: bool,
+    pub removed: Vec<RemovedDir>,
+    pub bytes_reclaimed: u64,
+}
+
+/// 扫描出的待清理目录
+struct Candidate {
+    dir: RemovedDir,
+    /// 目录所属的流 (idle)，删除前再确认其未在运行
+    stream: Option<String>,
+}
+
+/// 扫描 hls_root 所需的配置快照
+struct Scan {
+    /// 已配置流的输出目录 -> 流名称
+    streams: HashMap<PathBuf, String>,
+    idle: Option<Duration>,
+    candidates: Vec<Candidate>,
+    /// 不含切片的残留目录，由深到浅排列，清理后若为空则删除
+    containers: Vec<PathBuf>,
+}
+
+/// 清理 hls_root 下不属于任何已配置流的目录，以及 (配置了 `hls_gc_idle_hours` 时)
+/// 未运行且长时间未更新的流目录；输出目录位于 hls_root 之外的流不受影响
+pub async fn run(state: &AppState, dry_run: bool) -> anyhow::Result<GcReport> {
+    let config = state.config();
+    let root: PathBuf = Path::new(&config.server.hls_root).components().collect();
+    let mut scan = Scan {
+        streams: config
+            .streams
+            .iter()
+            .flat_map(|s| {
+                // 配置迁移在两个目录间交替，暂存目录同样属于该流
+                let dir = config.output_dir(s);
+                [
+                    (migrate::staging_dir(&dir), s.name.clone()),
+                    (dir, s.name.clone()),
+                ]
+            })
+            .collect(),
+        idle: (config.server.hls_gc_idle_hours > 0)
+            .then(|| Duration::from_secs(config.server.hls_gc_idle_hours * 3600)),
+        candidates: Vec::new(),
+        containers: Vec::new(),
+    };
+    // hls_root 本身即为某个流的输出目录时无从区分残留
+    if !root.is_dir() || scan.streams.contains_key(&root) {
+        return Ok(GcReport {
+            dry_run,
+            ..Default::default()
+        });
+    }
+    let scan = tokio::task::spawn_blocking(move || {
+        scan.visit(&root);
+        scan
+    })
+    .await?;
+
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+    for candidate in scan.candidates {
+        if let Some(name) = &candidate.stream {
+            if state.active_streams.lock_or_recover().contains_key(name) {
+                continue;
+            }
+        }
+        let dir = candidate.dir;
+        if !dry_run {
+            if let Err(e) = tokio::fs::remove_dir_all(&dir.path).await {
+                warn!("Failed to remove stale HLS directory {:?}: {}", dir.path, e);
+                continue;
+            }
+            info!(
+                "Removed {} HLS directory {:?} ({} bytes)",
+                dir.reason, dir.path, dir.bytes
+            );
+        }
+        report.bytes_reclaimed += dir.bytes;
+        report.removed.push(dir);
+    }
+    if !dry_run {
+        for dir in &scan.containers {
+            // 仍有其他内容时删除失败，保留该目录
+            let _ = tokio::fs::remove_dir(dir).await;
+        }
+    }
+    Ok(report)
+}
+
+impl Scan {
+    fn visit(&mut self, dir: &Path) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            // 不跟随符号链接
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let path = entry.path();
+            if let Some(name) = self.streams.get(&path) {
+                if let Some(idle) = self.idle {
+                    let (bytes, modified) = usage(&path);
+                    if age(modified) >= idle {
+                        self.candidates.push(Candidate {
+                            dir: RemovedDir {
+                                path,
+                                reason: "idle",
+                                bytes,
+                            },
+                            stream: Some(name.clone()),
+                        });
+                    }
+                }
+            } else if self.streams.keys().any(|p| p.starts_with(&path)) {
+                // 租户目录等上级目录
+                self.visit(&path);
+            } else if has_media(&path) {
+                let (bytes, modified) = usage(&path);
+                if age(modified) >= ORPHAN_GRACE {
+                    self.candidates.push(Candidate {
+                        dir: RemovedDir {
+                            path,
+                            reason: "orphaned",
+                            bytes,
+                        },
+                        stream: None,
+                    });
+                }
+            } else {
+                self.visit(&path);
+                self.containers.push(path);
+            }
+        }
+    }
+}
+
+fn has_media(dir: &Path) -> bool {
+    std::fs::read_dir(dir).is_ok_and(|entries| {
+        entries.flatten().any(|e| {
+            e.path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| MEDIA_EXTENSIONS.contains(&ext))
+        })
+    })
+}
+
+/// 目录下全部文件的总大小与最近的修改时间 (含目录本身)
+fn usage(dir: &Path) -> (u64, SystemTime) {
+    let mut bytes = 0;
+    let mut newest = std::fs::metadata(dir)
+        .and_then(|m| m.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                let (b, m) = usage(&entry.path());
+                bytes += b;
+                newest = newest.max(m);
+            } else {
+                bytes += meta.len();
+                newest = newest.max(meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+            }
+        }
+    }
+    (bytes, newest)
+}
+
+fn age(modified: SystemTime) -> Duration {
+    SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default()
+}
+
+/// 启动时清理一次，之后按 `server.hls_gc_interval_sec` 定期清理
+pub async fn start(state: Arc<AppState>) {
+    let interval = state.config().server.hls_gc_interval_sec;
+    if interval == 0 {
+        return;
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    loop {
+        ticker.tick().await;
+        match run(&state, false).await {
+            Ok(report) if !report.removed.is_empty() => info!(
+                "Reclaimed {} bytes from {} stale HLS directories",
+                report.bytes_reclaimed,
+                report.removed.len()
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to clean up stale HLS directories: {}", e),
+        }
+    }
+}
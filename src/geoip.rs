@@ -0,0 +1,369 @@
+use crate::config::StreamConfig;
+use crate::state::{AppState, LockExt};
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// 元数据段的起始标记，位于文件末尾附近
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+/// 搜索树与数据段之间的 16 字节分隔
+const DATA_SEPARATOR: usize = 16;
+
+/// 解码嵌套的最大深度，防止损坏的数据库导致无限递归
+const MAX_DEPTH: usize = 32;
+
+/// 观看者所在地
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Location {
+    /// ISO 3166-1 两位代码
+    pub country: Option<String>,
+    /// 城市名 (英文，仅 City 数据库)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+}
+
+/// 已加载的数据库，以 server.geoip_db 的路径区分 (配置变化时重新加载)
+pub struct LoadedDb {
+    path: String,
+    /// 加载失败时为 None，直到路径变化前不再重试
+    db: Option<Arc<GeoDb>>,
+}
+
+/// MaxMind DB (MMDB) 格式的只读数据库，整个文件读入内存
+pub struct GeoDb {
+    data: Bytes,
+    node_count: usize,
+    record_size: usize,
+    /// 搜索树的字节数
+    tree_size: usize,
+    /// IPv6 库中 IPv4 地址 (`::a.b.c.d`) 子树的起点
+    ipv4_start: usize,
+    ip_version: u64,
+}
+
+/// 解码后的数据段取值
+#[derive(Debug)]
+enum Value {
+    Str(String),
+    Uint(u64),
+    Map(Vec<(String, Value)>),
+    /// 字节串、浮点数、数组等不需要的类型
+    Other,
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn path(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |v, key| v.get(key))
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_uint(&self) -> Option<u64> {
+        match self {
+            Value::Uint(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl GeoDb {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let data = Bytes::from(std::fs::read(path)?);
+        let start = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a MaxMind DB file", path))?
+            + METADATA_MARKER.len();
+        let (metadata, _) = Decoder {
+            section: &data[start..],
+        }
+        .decode(0, 0)
+        .ok_or_else(|| anyhow::anyhow!("{} has corrupt metadata", path))?;
+        let field = |name: &str| metadata.get(name).and_then(Value::as_uint);
+        let (Some(node_count), Some(record_size), Some(ip_version)) = (
+            field("node_count"),
+            field("record_size"),
+            field("ip_version"),
+        ) else {
+            anyhow::bail!(
+                "{} metadata lacks node_count, record_size or ip_version",
+                path
+            );
+        };
+        if !matches!(record_size, 24 | 28 | 32) {
+            anyhow::bail!("{} uses unsupported record size {}", path, record_size);
+        }
+        let node_count = node_count as usize;
+        let record_size = record_size as usize;
+        let tree_size = node_count * record_size / 4;
+        if tree_size + DATA_SEPARATOR > start {
+            anyhow::bail!("{} is truncated", path);
+        }
+
+        let mut db = Self {
+            data,
+            node_count,
+            record_size,
+            tree_size,
+            ipv4_start: 0,
+            ip_version,
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = db.record(node, 0).unwrap_or(node_count);
+            }
+            db.ipv4_start = node;
+        }
+        Ok(db)
+    }
+
+    /// 查询地址所在地，库中没有该地址时返回 None
+    pub fn lookup(&self, ip: IpAddr) -> Option<Location> {
+        let (bytes, mut node): (Vec<u8>, usize) = match ip {
+            IpAddr::V4(v4) if self.ip_version == 6 => (v4.octets().to_vec(), self.ipv4_start),
+            IpAddr::V4(v4) => (v4.octets().to_vec(), 0),
+            IpAddr::V6(v6) if self.ip_version == 6 => (v6.octets().to_vec(), 0),
+            // IPv4 库只能查询映射的 IPv4 地址
+            IpAddr::V6(v6) => (v6.to_ipv4_mapped()?.octets().to_vec(), 0),
+        };
+        for bit in 0..bytes.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            let side = (bytes[bit / 8] >> (7 - bit % 8)) & 1;
+            node = self.record(node, side as usize)?;
+        }
+        if node <= self.node_count {
+            return None;
+        }
+        let offset = node - self.node_count - DATA_SEPARATOR;
+        let section = self.data.get(self.tree_size + DATA_SEPARATOR..)?;
+        let (record, _) = Decoder { section }.decode(offset, 0)?;
+
+        let country = record
+            .path(&["country", "iso_code"])
+            .or_else(|| record.path(&["registered_country", "iso_code"]))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let city = record
+            .path(&["city", "names", "en"])
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        Some(Location { country, city })
+    }
+
+    /// 节点的左 (0) 或右 (1) 记录
+    fn record(&self, node: usize, side: usize) -> Option<usize> {
+        let base = node * self.record_size / 4;
+        let b = self.data.get(base..base + self.record_size / 4)?;
+        let be = |s: &[u8]| s.iter().fold(0usize, |acc, &x| acc << 8 | x as usize);
+        Some(match (self.record_size, side) {
+            (24, 0) => be(&b[0..3]),
+            (24, _) => be(&b[3..6]),
+            (28, 0) => (b[3] as usize & 0xF0) << 20 | be(&b[0..3]),
+            (28, _) => (b[3] as usize & 0x0F) << 24 | be(&b[4..7]),
+            (_, 0) => be(&b[0..4]),
+            (_, _) => be(&b[4..8]),
+        })
+    }
+}
+
+/// 数据段解码器 (MaxMind DB 规范 2.0)，指针相对于 `section` 起点
+struct Decoder<'a> {
+    section: &'a [u8],
+}
+
+impl Decoder<'_> {
+    /// 解码 `offset` 处的值，返回值与其后的位置
+    fn decode(&self, offset: usize, depth: usize) -> Option<(Value, usize)> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let ctrl = *self.section.get(offset)?;
+        let mut pos = offset + 1;
+        let mut kind = ctrl >> 5;
+
+        // 指针：跳转解码，但位置停在指针之后
+        if kind == 1 {
+            let size = ((ctrl >> 3) & 0x3) as usize;
+            let low = (ctrl & 0x7) as usize;
+            let bytes = self.bytes(pos, size + 1)?;
+            pos += size + 1;
+            let be = bytes.iter().fold(0usize, |acc, &x| acc << 8 | x as usize);
+            let target = match size {
+                0 => low << 8 | be,
+                1 => (low << 16 | be) + 2048,
+                2 => (low << 24 | be) + 526_336,
+                _ => be,
+            };
+            let (value, _) = self.decode(target, depth + 1)?;
+            return Some((value, pos));
+        }
+        if kind == 0 {
+            kind = self.section.get(pos)?.checked_add(7)?;
+            pos += 1;
+        }
+
+        let mut size = (ctrl & 0x1F) as usize;
+        if size >= 29 {
+            let extra = size - 28;
+            let bytes = self.bytes(pos, extra)?;
+            pos += extra;
+            let be = bytes.iter().fold(0usize, |acc, &x| acc << 8 | x as usize);
+            size = match extra {
+                1 => 29 + be,
+                2 => 285 + be,
+                _ => 65_821 + be,
+            };
+        }
+
+        match kind {
+            // utf8_string
+            2 => {
+                let s = std::str::from_utf8(self.bytes(pos, size)?).ok()?;
+                Some((Value::Str(s.to_string()), pos + size))
+            }
+            // uint16 / uint32 / uint64 / uint128 (超过 64 位的部分丢弃)
+            5 | 6 | 9 | 10 => {
+                let n = self
+                    .bytes(pos, size)?
+                    .iter()
+                    .fold(0u64, |acc, &x| acc.wrapping_shl(8) | x as u64);
+                Some((Value::Uint(n), pos + size))
+            }
+            // map
+            7 => {
+                let mut entries = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (key, next) = self.decode(pos, depth + 1)?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    pos = next;
+                    if let Value::Str(key) = key {
+                        entries.push((key, value));
+                    }
+                }
+                Some((Value::Map(entries), pos))
+            }
+            // array
+            11 => {
+                for _ in 0..size {
+                    pos = self.decode(pos, depth + 1)?.1;
+                }
+                Some((Value::Other, pos))
+            }
+            // double
+            3 => Some((Value::Other, pos + 8)),
+            // float
+            15 => Some((Value::Other, pos + 4)),
+            // boolean：取值就是 size，没有数据
+            14 => Some((Value::Other, pos)),
+            // bytes / int32
+            4 | 8 => Some((Value::Other, pos + size)),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self, pos: usize, len: usize) -> Option<&[u8]> {
+        self.section.get(pos..pos.checked_add(len)?)
+    }
+}
+
+/// 当前配置的数据库，首次使用或路径变化时加载
+fn database(state: &AppState) -> Option<Arc<GeoDb>> {
+    let path = state.config().server.geoip_db.clone()?;
+    let mut loaded = state.geoip.lock_or_recover();
+    if let Some(loaded) = loaded.as_ref().filter(|l| l.path == path) {
+        return loaded.db.clone();
+    }
+    let db = match GeoDb::open(&path) {
+        Ok(db) => {
+            info!("Loaded GeoIP database {} ({} nodes)", path, db.node_count);
+            Some(Arc::new(db))
+        }
+        Err(e) => {
+            warn!("Failed to load GeoIP database {}: {}", path, e);
+            None
+        }
+    };
+    *loaded = Some(LoadedDb {
+        path,
+        db: db.clone(),
+    });
+    db
+}
+
+/// 查询客户端地址 (`sessions::client_ip`) 所在地，未配置数据库或无法解析时返回 None
+pub fn lookup(state: &AppState, client_ip: &str) -> Option<Location> {
+    let ip: IpAddr = client_ip.parse().ok()?;
+    database(state)?.lookup(ip)
+}
+
+/// 按流的 `geo` 限制检查观看者，拒绝时返回应答给播放器的状态与说明
+///
+/// 数据库无法加载时拒绝所有受限流的播放 (合同要求的限制不能因故障失效)
+pub fn check(
+    state: &AppState,
+    cfg: &StreamConfig,
+    client_ip: &str,
+) -> Result<(), (StatusCode, String)> {
+    let Some(geo) = &cfg.geo else {
+        return Ok(());
+    };
+    if database(state).is_none() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Geo lookup unavailable".to_string(),
+        ));
+    }
+    let country = lookup(state, client_ip).and_then(|l| l.country);
+    let allowed = match &country {
+        Some(code) if geo.deny.contains(code) => false,
+        Some(code) => geo.allow.is_empty() || geo.allow.contains(code),
+        None => geo.allow.is_empty() || geo.allow_unknown,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::FORBIDDEN,
+            "Stream is not available in your region".to_string(),
+        ))
+    }
+}
+
+/// 按国家/地区统计流当前的观看者，无法判断的计入 `unknown`；未配置数据库时返回 None
+pub fn viewer_countries(state: &AppState, stream: &str) -> Option<BTreeMap<String, usize>> {
+    let db = database(state)?;
+    let mut counts = BTreeMap::new();
+    for ip in crate::sessions::active_addresses(state, stream) {
+        let country = ip
+            .parse()
+            .ok()
+            .and_then(|ip| db.lookup(ip))
+            .and_then(|l| l.country)
+            .unwrap_or_else(|| "unknown".to_string());
+        *counts.entry(country).or_default() += 1;
+    }
+    Some(counts)
+}
@@ -0,0 +1,199 @@
+use crate::config::StreamConfig;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// 硬件编码器名称后缀 (FFmpeg 编码器命名约定)
+const HW_ENCODER_SUFFIXES: [&str; 6] = [
+    "_nvenc",
+    "_vaapi",
+    "_qsv",
+    "_v4l2m2m",
+    "_rkmpp",
+    "_videotoolbox",
+];
+
+/// 单次 nvidia-smi 查询超时
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 硬件加速设备状态
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuDevice {
+    /// 设备标识 (如 `nvidia0`、`card0`)
+    pub id: String,
+    pub name: String,
+    /// 整体占用率 (%)
+    pub utilization_percent: Option<f64>,
+    /// 编码单元占用率 (%)，仅 NVIDIA
+    pub encoder_percent: Option<f64>,
+    /// 驱动报告的活跃编码会话数 (含其他进程)，仅 NVIDIA
+    pub encoder_sessions: Option<u32>,
+    /// 显存占用 (MB)
+    pub memory_used_mb: Option<u64>,
+    pub memory_total_mb: Option<u64>,
+}
+
+/// 流的 FFmpeg 进程占用的硬件编码会话数 (输出参数中每个硬件编码器计一个)
+pub fn encode_sessions(cfg: &StreamConfig) -> u32 {
+    cfg.output_args
+        .iter()
+        .filter(|arg| is_hw_encoder(arg))
+        .count() as u32
+}
+
+/// 运行中的流占用的硬件编码会话总数
+pub fn active_sessions(state: &AppState) -> u32 {
+    let config = state.config();
+    let streams = state.active_streams.lock_or_recover();
+    config
+        .streams
+        .iter()
+        .filter(|s| streams.contains_key(&s.name))
+        .map(encode_sessions)
+        .sum()
+}
+
+/// 启动前检查硬件编码会话上限，超出时拒绝启动 (而不是让 FFmpeg 以难以理解的错误退出)
+pub fn check_start_sessions(state: &AppState, cfg: &StreamConfig) -> anyhow::Result<()> {
+    let needed = encode_sessions(cfg);
+    let limit = state.config().hwaccel.max_encode_sessions;
+    if needed == 0 || limit == 0 {
+        return Ok(());
+    }
+    let active = active_sessions(state);
+    if active + needed > limit {
+        anyhow::bail!(
+            "Stream [{}] needs {} hardware encode session(s) but {} of {} are in use (hwaccel.max_encode_sessions)",
+            cfg.name,
+            needed,
+            active,
+            limit
+        );
+    }
+    Ok(())
+}
+
+/// 定期采集 GPU/VPU 占用，未发现任何设备时退出
+pub async fn start_monitor(state: Arc<AppState>) {
+    let mut nvidia = true;
+    let mut found_any = false;
+    loop {
+        let mut devices = Vec::new();
+        if nvidia {
+            match query_nvidia().await {
+                Ok(list) => devices.extend(list),
+                Err(e) => {
+                    // 未安装驱动或没有 NVIDIA 设备，此后不再尝试
+                    if !found_any {
+                        info!("NVIDIA GPU monitoring unavailable: {}", e);
+                    } else {
+                        warn!("nvidia-smi query failed: {}", e);
+                    }
+                    nvidia = found_any;
+                }
+            }
+        }
+        devices.extend(query_drm());
+
+        if devices.is_empty() && !found_any {
+            info!("No GPU/VPU utilization source found; GPU monitoring disabled.");
+            return;
+        }
+        found_any = true;
+        *state.gpu_devices.lock_or_recover() = devices;
+
+        let interval = state.config().hwaccel.poll_interval_sec.max(1);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+fn is_hw_encoder(arg: &str) -> bool {
+    HW_ENCODER_SUFFIXES.iter().any(|s| arg.ends_with(s))
+}
+
+/// 通过 nvidia-smi 查询 NVIDIA 设备
+async fn query_nvidia() -> anyhow::Result<Vec<GpuDevice>> {
+    let output = tokio::time::timeout(
+        QUERY_TIMEOUT,
+        Command::new("nvidia-smi")
+            .arg("--query-gpu=index,name,utilization.gpu,utilization.encoder,encoder.stats.sessionCount,memory.used,memory.total")
+            .arg("--format=csv,noheader,nounits")
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("Timed out"))??;
+    if !output.status.success() {
+        anyhow::bail!("nvidia-smi exited with {}", output.status);
+    }
+
+    let devices: Vec<_> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() < 7 {
+                return None;
+            }
+            Some(GpuDevice {
+                id: format!("nvidia{}", fields[0]),
+                name: fields[1].to_string(),
+                utilization_percent: fields[2].parse().ok(),
+                encoder_percent: fields[3].parse().ok(),
+                encoder_sessions: fields[4].parse().ok(),
+                memory_used_mb: fields[5].parse().ok(),
+                memory_total_mb: fields[6].parse().ok(),
+            })
+        })
+        .collect();
+    if devices.is_empty() {
+        anyhow::bail!("No devices reported");
+    }
+    Ok(devices)
+}
+
+/// 从 DRM sysfs 读取 GPU 占用 (amdgpu 等驱动提供 gpu_busy_percent)
+fn query_drm() -> Vec<GpuDevice> {
+    let Ok(entries) = std::fs::read_dir("/sys/class/drm") else {
+        return Vec::new();
+    };
+    let mut devices: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_string_lossy().to_string();
+            // 只取 card0 这类设备节点，跳过 card0-HDMI-A-1 等连接器
+            if !id.strip_prefix("card")?.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let device = entry.path().join("device");
+            let busy = std::fs::read_to_string(device.join("gpu_busy_percent")).ok()?;
+            let read_mb = |file: &str| {
+                std::fs::read_to_string(device.join(file))
+                    .ok()
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(|bytes| bytes / 1024 / 1024)
+            };
+            let name = std::fs::read_to_string(device.join("uevent"))
+                .ok()
+                .and_then(|u| {
+                    u.lines()
+                        .find_map(|l| l.strip_prefix("DRIVER="))
+                        .map(str::to_string)
+                })
+                .unwrap_or_default();
+            Some(GpuDevice {
+                id,
+                name,
+                utilization_percent: busy.trim().parse().ok(),
+                encoder_percent: None,
+                encoder_sessions: None,
+                memory_used_mb: read_mb("mem_info_vram_used"),
+                memory_total_mb: read_mb("mem_info_vram_total"),
+            })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    devices
+}
@@ -0,0 +1,153 @@
+use crate::drain;
+use crate::maintenance;
+use crate::runtime_state;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// 单项就绪检查结果
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// 就绪检查结果 (供 /readyz 使用)
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+/// 执行全部就绪检查：配置、切片目录可写、FFmpeg 可执行、auto_start 流已启动、未处于排空
+pub async fn readiness(state: &AppState) -> Readiness {
+    let config = state.config();
+    let mut checks = vec![Check {
+        name: "config",
+        ok: true,
+        detail: format!(
+            "{} stream(s) from {}",
+            config.streams.len(),
+            state.config_path.display()
+        ),
+    }];
+
+    let hls_root = PathBuf::from(&config.server.hls_root);
+    checks.push(match probe_writable(&hls_root).await {
+        Ok(()) => Check {
+            name: "hls_root",
+            ok: true,
+            detail: format!("{} is writable", hls_root.display()),
+        },
+        Err(e) => Check {
+            name: "hls_root",
+            ok: false,
+            detail: format!("{} is not writable: {}", hls_root.display(), e),
+        },
+    });
+
+    let ffmpeg = &config.server.ffmpeg_binary;
+    checks.push(match find_executable(ffmpeg) {
+        Some(path) => Check {
+            name: "ffmpeg",
+            ok: true,
+            detail: path.display().to_string(),
+        },
+        None => Check {
+            name: "ffmpeg",
+            ok: false,
+            detail: format!("{} is not an executable file", ffmpeg),
+        },
+    });
+
+    checks.push(auto_start_check(state));
+
+    let draining = drain::is_draining(state);
+    checks.push(Check {
+        name: "drain",
+        ok: !draining,
+        detail: if draining { "draining" } else { "serving" }.to_string(),
+    });
+
+    Readiness {
+        ready: checks.iter().all(|c| c.ok),
+        checks,
+    }
+}
+
+/// auto_start 流是否已达到要求的运行数量 (启动宽限期内总是通过)
+///
+/// 运维禁用或手动停止的流不计入
+fn auto_start_check(state: &AppState) -> Check {
+    let config = state.config();
+    let expected: Vec<&str> = config
+        .streams
+        .iter()
+        .filter(|s| s.auto_start && !s.is_proxied())
+        .map(|s| s.name.as_str())
+        .filter(|name| {
+            !maintenance::is_disabled(state, name) && !runtime_state::is_stopped(state, name)
+        })
+        .collect();
+    let running = {
+        let active = state.active_streams.lock_or_recover();
+        expected.iter().filter(|n| active.contains_key(**n)).count()
+    };
+    let required = config
+        .server
+        .ready_min_auto_start
+        .unwrap_or(expected.len())
+        .min(expected.len());
+    let grace = config.server.ready_grace_sec;
+    let uptime = Instant::now().duration_since(state.started_at).as_secs();
+    let in_grace = uptime < grace;
+
+    let mut detail = format!(
+        "{}/{} auto_start stream(s) running, {} required",
+        running,
+        expected.len(),
+        required
+    );
+    if running < required && in_grace {
+        detail.push_str(&format!(" (within {}s startup grace period)", grace));
+    }
+    Check {
+        name: "auto_start",
+        ok: running >= required || in_grace,
+        detail,
+    }
+}
+
+/// 在目录中创建并删除一个探测文件
+async fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let probe = dir.join(".readyz");
+    tokio::fs::write(&probe, b"").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+/// 解析可执行文件路径：含路径分隔符时直接检查，否则在 PATH 中查找
+pub fn find_executable(binary: &str) -> Option<PathBuf> {
+    let path = Path::new(binary);
+    if path.components().count() > 1 {
+        return is_executable(path).then(|| path.to_path_buf());
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(binary))
+            .find(|candidate| is_executable(candidate))
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,75 @@
+use crate::clock;
+use crate::config::HeartbeatMethod;
+use crate::http_client;
+use crate::state::AppState;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// 单次推送的超时
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 定期推送节点与流的心跳
+///
+/// 节点心跳只要网关在运行就推送；流心跳只在流处于 `running` 时推送，
+/// 崩溃、停止或降级的流停止推送，由外部监控在超时后告警。
+/// 适用于没有入站连接、无法被抓取监控的站点
+pub async fn start_pusher(state: Arc<AppState>) {
+    loop {
+        let config = state.config();
+        let heartbeat = config.heartbeat.clone();
+        let statuses = state.stream_statuses();
+
+        let mut targets = Vec::new();
+        if let Some(url) = &heartbeat.url {
+            let running = statuses.iter().filter(|s| s.status == "running").count();
+            let body = serde_json::json!({
+                "node": sys_info::hostname().unwrap_or_default(),
+                "time": clock::rfc3339(SystemTime::now()),
+                "streams": statuses.len(),
+                "running": running,
+            });
+            targets.push(("node".to_string(), url.clone(), body));
+        }
+        for status in statuses.iter().filter(|s| s.status == "running") {
+            let Some(url) = config
+                .stream(&status.name)
+                .and_then(|c| c.heartbeat_url.clone())
+            else {
+                continue;
+            };
+            let body = serde_json::json!({
+                "stream": status.name,
+                "time": clock::rfc3339(SystemTime::now()),
+                "uptime_seconds": status.uptime_seconds,
+                "viewers": status.viewers,
+            });
+            targets.push((format!("stream {}", status.name), url, body));
+        }
+
+        for (target, url, body) in targets {
+            tokio::spawn(async move {
+                let result = match heartbeat.method {
+                    HeartbeatMethod::Get => {
+                        http_client::request("GET", &url, &[], None, PUSH_TIMEOUT).await
+                    }
+                    HeartbeatMethod::Post => {
+                        http_client::send_json("POST", &url, &body, None, PUSH_TIMEOUT).await
+                    }
+                };
+                match result {
+                    Ok(res) if !res.is_success() => {
+                        warn!(
+                            "Heartbeat for {} rejected with status {}",
+                            target, res.status
+                        )
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Heartbeat for {} failed: {}", target, e),
+                }
+            });
+        }
+
+        tokio::time::sleep(Duration::from_secs(heartbeat.interval_sec.max(1))).await;
+    }
+}
@@ -0,0 +1,364 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 响应体大小上限，防止异常上游耗尽内存
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// HTTP 响应
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// 按名称查找响应头 (不区分大小写)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// 主机名是否指向本机：`localhost` 或环回地址 (IPv6 地址不带方括号)
+///
+/// 客户端不支持 TLS，携带凭据的明文请求只发往本机的中继或 TLS 转发代理
+pub fn is_loopback_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// 解析后的 URL
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    /// 路径与查询串，至少为 "/"
+    pub path: String,
+}
+
+impl Url {
+    /// 解析 `http://host[:port][/path]`，不支持 https
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unsupported URL scheme (only http:// is supported): {}",
+                url
+            )
+        })?;
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+
+        // 去除 userinfo
+        let authority = authority.rsplit('@').next().unwrap_or(authority);
+
+        let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+            let end = v6
+                .find(']')
+                .ok_or_else(|| anyhow::anyhow!("Invalid IPv6 host in URL: {}", url))?;
+            let port = v6[end + 1..]
+                .strip_prefix(':')
+                .map(|p| p.parse())
+                .transpose()?;
+            (v6[..end].to_string(), port.unwrap_or(80))
+        } else {
+            match authority.rsplit_once(':') {
+                Some((h, p)) => (h.to_string(), p.parse()?),
+                None => (authority.to_string(), 80),
+            }
+        };
+
+        if host.is_empty() {
+            anyhow::bail!("Missing host in URL: {}", url);
+        }
+
+        Ok(Self { host, port, path })
+    }
+
+    /// 主机是否为本机 (见 `is_loopback_host`)
+    pub fn is_loopback(&self) -> bool {
+        is_loopback_host(&self.host)
+    }
+
+    /// 解析相对于当前 URL 的引用 (绝对 URL、绝对路径或相对路径)
+    pub fn join(&self, reference: &str) -> anyhow::Result<Self> {
+        if reference.contains("://") {
+            return Self::parse(reference);
+        }
+        let path = if reference.starts_with('/') {
+            reference.to_string()
+        } else {
+            let base = self.path.split('?').next().unwrap_or("/");
+            let dir = &base[..base.rfind('/').map(|i| i + 1).unwrap_or(0)];
+            format!("{}{}", dir, reference)
+        };
+        Ok(Self {
+            host: self.host.clone(),
+            port: self.port,
+            path,
+        })
+    }
+
+    pub fn host_header(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        if self.port == 80 {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+}
+
+/// 发送一次 HTTP/1.1 请求 (Connection: close)
+pub async fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+    timeout: Duration,
+) -> anyhow::Result<HttpResponse> {
+    request_url(method, &Url::parse(url)?, headers, body, timeout).await
+}
+
+/// 向已解析的 URL 发送请求
+pub async fn request_url(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+    timeout: Duration,
+) -> anyhow::Result<HttpResponse> {
+    tokio::time::timeout(timeout, send(method, url, headers, body))
+        .await
+        .map_err(|_| anyhow::anyhow!("HTTP request to {} timed out", url.host_header()))?
+}
+
+/// 以 JSON 发送请求体，可选携带 Bearer 令牌
+pub async fn send_json(
+    method: &str,
+    url: &str,
+    payload: &serde_json::Value,
+    bearer: Option<&str>,
+    timeout: Duration,
+) -> anyhow::Result<HttpResponse> {
+    let body = serde_json::to_vec(payload)?;
+    let auth = bearer.map(|t| format!("Bearer {}", t));
+    let mut headers = vec![("Content-Type", "application/json")];
+    if let Some(auth) = &auth {
+        headers.push(("Authorization", auth.as_str()));
+    }
+    request(method, url, &headers, Some(&body), timeout).await
+}
+
+async fn send(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+) -> anyhow::Result<HttpResponse> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: vtx-link/{}\r\n",
+        method,
+        url.path,
+        url.host_header(),
+        env!("CARGO_PKG_VERSION")
+    );
+    for (k, v) in headers {
+        head.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    if let Some(body) = body {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    if let Some(body) = body {
+        stream.write_all(body).await?;
+    }
+
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        raw.extend_from_slice(&buf[..n]);
+        if raw.len() > MAX_BODY_BYTES {
+            anyhow::bail!("HTTP response exceeds {} bytes", MAX_BODY_BYTES);
+        }
+    }
+
+    parse_response(&raw, method == "HEAD")
+}
+
+fn parse_response(raw: &[u8], head_only: bool) -> anyhow::Result<HttpResponse> {
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("Malformed HTTP response"))?;
+    let head = std::str::from_utf8(&raw[..split])?;
+    let mut payload = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("Malformed HTTP status line: {}", status_line))?
+        .parse()?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+
+    let mut response = HttpResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    if head_only {
+        return Ok(response);
+    }
+
+    let chunked = response
+        .header("Transfer-Encoding")
+        .map(|v| v.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false);
+
+    if chunked {
+        // 解码 chunked 传输编码
+        let mut body = Vec::new();
+        loop {
+            let line_end = payload
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .ok_or_else(|| anyhow::anyhow!("Truncated chunked body"))?;
+            let size_str = std::str::from_utf8(&payload[..line_end])?;
+            let size = usize::from_str_radix(size_str.split(';').next().unwrap_or("").trim(), 16)?;
+            payload = &payload[line_end + 2..];
+            if size == 0 {
+                break;
+            }
+            if payload.len() < size {
+                anyhow::bail!("Truncated chunked body");
+            }
+            body.extend_from_slice(&payload[..size]);
+            payload = payload.get(size + 2..).unwrap_or_default();
+        }
+        response.body = body;
+    } else if let Some(len) = response
+        .header("Content-Length")
+        .and_then(|v| v.parse().ok())
+    {
+        if payload.len() < len {
+            anyhow::bail!("Truncated HTTP body");
+        }
+        response.body = payload[..len].to_vec();
+    } else {
+        response.body = payload.to_vec();
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls() {
+        let url = Url::parse("http://user:pw@example.com:8080/api/nodes?wait=30").unwrap();
+        assert_eq!(url.host, "example.com");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.path, "/api/nodes?wait=30");
+
+        let url = Url::parse("http://example.com").unwrap();
+        assert_eq!((url.port, url.path.as_str()), (80, "/"));
+
+        let url = Url::parse("http://[::1]:9000/x").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 9000));
+        assert!(url.is_loopback());
+        assert_eq!(url.host_header(), "[::1]:9000");
+    }
+
+    #[test]
+    fn rejects_unsupported_urls() {
+        for bad in [
+            "https://example.com/",
+            "ftp://example.com/",
+            "example.com/",
+            "http://example.com:99999/",
+            "http://example.com:port/",
+            "http://:8080/",
+            "http:///path",
+            "http://[::1/",
+        ] {
+            assert!(Url::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn joins_references() {
+        let base = Url::parse("http://example.com/live/index.m3u8?token=a").unwrap();
+        assert_eq!(base.join("seg1.ts").unwrap().path, "/live/seg1.ts");
+        assert_eq!(base.join("/other/x.ts").unwrap().path, "/other/x.ts");
+        let other = base.join("http://cdn.example.com/y.ts").unwrap();
+        assert_eq!(
+            (other.host.as_str(), other.path.as_str()),
+            ("cdn.example.com", "/y.ts")
+        );
+    }
+
+    #[test]
+    fn loopback_hosts() {
+        assert!(is_loopback_host("localhost"));
+        assert!(is_loopback_host("LOCALHOST"));
+        assert!(is_loopback_host("127.0.0.53"));
+        assert!(is_loopback_host("::1"));
+        assert!(!is_loopback_host("10.0.0.1"));
+        assert!(!is_loopback_host("localhost.evil.com"));
+    }
+
+    #[test]
+    fn parses_responses() {
+        let res = parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-A: b\r\n\r\nhello extra",
+            false,
+        )
+        .unwrap();
+        assert_eq!((res.status, res.body.as_slice()), (200, &b"hello"[..]));
+        assert_eq!(res.header("x-a"), Some("b"));
+
+        let res = parse_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(res.body, b"abcde");
+
+        assert!(
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nshort", false).is_err()
+        );
+        assert!(parse_response(b"garbage", false).is_err());
+    }
+}
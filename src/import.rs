@@ -0,0 +1,784 @@
+use crate::config::StreamConfig;
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// 按需拉流在最后一个观看者离开后保留的时间 (秒)，缺省时与 MediaMTX 的
+/// `sourceOnDemandCloseAfter` 默认值相同
+const DEFAULT_CLOSE_AFTER_SEC: u64 = 10;
+
+/// nginx-rtmp 的 `hls_fragment` 与 `hls_playlist_length` 默认值 (秒)
+const NGINX_HLS_FRAGMENT_SEC: f64 = 5.0;
+const NGINX_HLS_PLAYLIST_SEC: f64 = 30.0;
+
+/// FFmpeg 可直接拉取的源协议
+const PULL_SCHEMES: &[&str] = &[
+    "rtsp", "rtsps", "rtmp", "rtmps", "http", "https", "srt", "udp", "rtp",
+];
+
+/// 不影响拉流转发、转换时直接忽略的 nginx-rtmp 指令 (HLS 封装由网关按流生成)
+const NGINX_IGNORED: &[&str] = &[
+    "live",
+    "meta",
+    "hls",
+    "hls_path",
+    "hls_cleanup",
+    "hls_nested",
+    "hls_continuous",
+    "hls_sync",
+    "hls_type",
+    "hls_fragment_naming",
+    "hls_fragment_naming_granularity",
+    "hls_fragment_slicing",
+    "hls_base_url",
+    "wait_key",
+    "wait_video",
+    "interleave",
+    "sync",
+    "idle_streams",
+    "drop_idle_publisher",
+    "publish_notify",
+    "play_restart",
+    "buflen",
+];
+
+/// 待转换配置的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SourceFormat {
+    /// MediaMTX (含旧名 rtsp-simple-server) 的 YAML 配置
+    Mediamtx,
+    /// nginx 配置中的 `rtmp { ... }` 部分
+    #[value(name = "nginx-rtmp")]
+    NginxRtmp,
+}
+
+/// `import` 子命令的参数
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// 待转换的配置文件
+    #[arg(long)]
+    pub from: PathBuf,
+    /// 配置格式，缺省按扩展名判断 (`.yml`/`.yaml` 为 MediaMTX，其余为 nginx-rtmp)
+    #[arg(long, value_enum)]
+    pub format: Option<SourceFormat>,
+}
+
+/// 转换结果
+#[derive(Debug, Default, Serialize)]
+pub struct Import {
+    /// 可并入配置文件 `streams` 的流 (只含非默认值)
+    pub streams: Vec<Value>,
+    /// 未能转换、需要人工处理的选项
+    pub warnings: Vec<String>,
+}
+
+/// 由源配置得到的一路流
+struct Draft {
+    name: String,
+    /// 出处，用于提示 (如 `path [cam1]`)
+    origin: String,
+    source: String,
+    rtsp_transport: Option<String>,
+    /// 按需拉流时为最后一个观看者离开后保留的秒数，None 表示随网关启动并常驻
+    on_demand: Option<u64>,
+    /// 按需启动时等待播放列表生成的时长 (毫秒)
+    start_timeout_ms: Option<u64>,
+    hls: Mapping,
+}
+
+impl Import {
+    fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+
+    /// 生成流配置：名称中不支持的字符替换为 `_`，重名与未通过校验的流只记录提示
+    fn add(&mut self, draft: Draft) {
+        if draft.source.contains('$') {
+            self.warn(format!(
+                "{}: source {:?} contains a variable; add this stream by hand",
+                draft.origin, draft.source
+            ));
+            return;
+        }
+        let scheme = draft.source.split("://").next().unwrap_or_default();
+        if !draft.source.contains("://") || !PULL_SCHEMES.contains(&scheme) {
+            self.warn(format!(
+                "{}: source {:?} cannot be pulled by FFmpeg; add this stream by hand",
+                draft.origin, draft.source
+            ));
+            return;
+        }
+        let name: String = draft
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let name = name.trim_start_matches('.').to_string();
+        if name.is_empty() {
+            self.warn(format!("{}: has no usable stream name", draft.origin));
+            return;
+        }
+        if name != draft.name {
+            self.warn(format!(
+                "{}: renamed to [{}] (stream names are used in playback URLs)",
+                draft.origin, name
+            ));
+        }
+        if self
+            .streams
+            .iter()
+            .any(|s| s["name"].as_str() == Some(&name))
+        {
+            self.warn(format!(
+                "{}: stream [{}] is already defined; skipped",
+                draft.origin, name
+            ));
+            return;
+        }
+
+        let mut stream = Mapping::new();
+        stream.insert("name".into(), name.clone().into());
+        stream.insert("source".into(), draft.source.clone().into());
+        stream.insert("mode".into(), "relay".into());
+        if scheme == "http" || scheme == "https" {
+            stream.insert("reconnect".into(), true.into());
+        }
+        if let Some(transport) = &draft.rtsp_transport {
+            stream.insert("rtsp_transport".into(), transport.clone().into());
+        }
+        match draft.on_demand {
+            None => {
+                stream.insert("auto_start".into(), true.into());
+            }
+            Some(close_after) => {
+                stream.insert("idle_timeout".into(), close_after.into());
+                if let Some(ms) = draft.start_timeout_ms {
+                    stream.insert("cold_start_wait_ms".into(), ms.into());
+                }
+            }
+        }
+        if !draft.hls.is_empty() {
+            stream.insert("hls".into(), Value::Mapping(draft.hls));
+        }
+        let stream = Value::Mapping(stream);
+        if let Err(e) = serde_yaml::from_value::<StreamConfig>(stream.clone()) {
+            self.warn(format!(
+                "{}: converted stream is invalid: {}",
+                draft.origin, e
+            ));
+            return;
+        }
+        self.streams.push(stream);
+    }
+
+    /// 输出为 YAML：提示以注释列在开头，`streams` 可直接并入配置文件
+    pub fn to_yaml(&self, from: &Path) -> anyhow::Result<String> {
+        let mut out = format!("# Converted from {} by `vtx-link import`\n", from.display());
+        if !self.warnings.is_empty() {
+            out.push_str("# Review before use:\n");
+            for warning in &self.warnings {
+                out.push_str(&format!("#   - {}\n", warning));
+            }
+        }
+        let mut root = Mapping::new();
+        root.insert("streams".into(), Value::Sequence(self.streams.clone()));
+        out.push_str(&serde_yaml::to_string(&root)?);
+        Ok(out)
+    }
+}
+
+/// 读取并转换配置文件
+pub fn convert(args: &ImportArgs) -> anyhow::Result<Import> {
+    let text = std::fs::read_to_string(&args.from)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", args.from.display(), e))?;
+    let format =
+        args.format
+            .unwrap_or_else(|| match args.from.extension().and_then(|e| e.to_str()) {
+                Some("yml" | "yaml") => SourceFormat::Mediamtx,
+                _ => SourceFormat::NginxRtmp,
+            });
+    match format {
+        SourceFormat::Mediamtx => mediamtx(&text),
+        SourceFormat::NginxRtmp => nginx_rtmp(&text),
+    }
+}
+
+/// 转换 MediaMTX 配置：`paths` 中拉取外部源 (或以 `runOnInit`/`runOnDemand`
+/// 运行 FFmpeg 拉流) 的路径转换为 relay 流，`pathDefaults` 作为每个路径的缺省值
+pub fn mediamtx(text: &str) -> anyhow::Result<Import> {
+    let root: Value = serde_yaml::from_str(text)?;
+    let root = root
+        .as_mapping()
+        .ok_or_else(|| anyhow::anyhow!("MediaMTX configuration must be a YAML mapping"))?;
+    let mut import = Import::default();
+
+    // 全局 HLS 参数作用于每一路流
+    let mut hls = Mapping::new();
+    if let Some(sec) = root.get("hlsSegmentDuration").and_then(seconds) {
+        hls.insert(
+            "segment_duration_sec".into(),
+            (sec.round().max(1.0) as u32).into(),
+        );
+    }
+    if let Some(count) = root.get("hlsSegmentCount").and_then(Value::as_u64) {
+        hls.insert("list_size".into(), count.max(1).into());
+    }
+    if let Some(variant) = root.get("hlsVariant").and_then(Value::as_str) {
+        if variant != "mpegts" {
+            import.warn(format!(
+                "hlsVariant {:?} is not supported; streams are served as MPEG-TS HLS",
+                variant
+            ));
+        }
+    }
+
+    let empty = Mapping::new();
+    let defaults = root
+        .get("pathDefaults")
+        .and_then(Value::as_mapping)
+        .unwrap_or(&empty);
+    let paths = root
+        .get("paths")
+        .and_then(Value::as_mapping)
+        .unwrap_or(&empty);
+    for (name, path) in paths {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let origin = format!("path [{}]", name);
+        let mut merged = defaults.clone();
+        if let Some(path) = path.as_mapping() {
+            for (k, v) in path {
+                merged.insert(k.clone(), v.clone());
+            }
+        }
+        if name.starts_with('~') || name == "all" || name == "all_others" {
+            import.warn(format!(
+                "{}: pattern paths cannot be converted; configure each stream by hand",
+                origin
+            ));
+            continue;
+        }
+        if let Some(draft) = mediamtx_path(&mut import, name, origin, &merged, &hls) {
+            import.add(draft);
+        }
+    }
+    Ok(import)
+}
+
+fn mediamtx_path(
+    import: &mut Import,
+    name: &str,
+    origin: String,
+    path: &Mapping,
+    hls: &Mapping,
+) -> Option<Draft> {
+    let get = |key: &str| path.get(key).filter(|v| active(v));
+    let mut draft = Draft {
+        name: name.to_string(),
+        origin: origin.clone(),
+        source: String::new(),
+        rtsp_transport: None,
+        on_demand: None,
+        start_timeout_ms: None,
+        hls: hls.clone(),
+    };
+    // 以 FFmpeg 拉流转发的路径使用的命令
+    let mut command_key = None;
+    match get("source").and_then(Value::as_str).unwrap_or("publisher") {
+        "publisher" => {
+            let (key, close_after) = match (get("runOnInit"), get("runOnDemand")) {
+                (Some(_), _) => ("runOnInit", None),
+                (None, Some(_)) => ("runOnDemand", Some("runOnDemandCloseAfter")),
+                (None, None) => {
+                    import.warn(format!(
+                        "{}: receives a published stream; add its source URL by hand",
+                        origin
+                    ));
+                    return None;
+                }
+            };
+            let command = get(key).and_then(Value::as_str).unwrap_or_default();
+            let Some(pull) = ffmpeg_pull(command) else {
+                import.warn(format!(
+                    "{}: {} is not an FFmpeg pull command; add this stream by hand",
+                    origin, key
+                ));
+                return None;
+            };
+            if !pull.dropped.is_empty() {
+                import.warn(format!(
+                    "{}: FFmpeg arguments not imported (the stream is relayed with -c copy): {}",
+                    origin,
+                    pull.dropped.join(" ")
+                ));
+            }
+            draft.source = pull.source;
+            draft.rtsp_transport = pull.rtsp_transport;
+            if let Some(close_after) = close_after {
+                draft.on_demand = Some(close_after_sec(get(close_after)));
+                draft.start_timeout_ms = get("runOnDemandStartTimeout")
+                    .and_then(seconds)
+                    .map(|s| (s * 1000.0) as u64);
+            }
+            command_key = Some(key);
+        }
+        "redirect" | "rpiCamera" => {
+            import.warn(format!(
+                "{}: source {:?} is not supported",
+                origin,
+                get("source").and_then(Value::as_str).unwrap_or_default()
+            ));
+            return None;
+        }
+        source => {
+            draft.source = source.to_string();
+            if get("sourceOnDemand").is_some_and(|v| truthy(v) == Some(true)) {
+                draft.on_demand = Some(close_after_sec(get("sourceOnDemandCloseAfter")));
+                draft.start_timeout_ms = get("sourceOnDemandStartTimeout")
+                    .and_then(seconds)
+                    .map(|s| (s * 1000.0) as u64);
+            }
+        }
+    }
+
+    // 旧版本使用 sourceProtocol
+    let transport = get("rtspTransport").or_else(|| get("sourceProtocol"));
+    match transport.and_then(Value::as_str) {
+        Some("tcp") => draft.rtsp_transport = Some("tcp".to_string()),
+        Some("udp") => draft.rtsp_transport = Some("udp".to_string()),
+        Some("automatic") | None => {}
+        Some(other) => import.warn(format!(
+            "{}: RTSP transport {:?} is not supported",
+            origin, other
+        )),
+    }
+
+    for (key, value) in path {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let handled = matches!(
+            key,
+            "source"
+                | "sourceOnDemand"
+                | "sourceOnDemandCloseAfter"
+                | "sourceOnDemandStartTimeout"
+                | "rtspTransport"
+                | "sourceProtocol"
+                | "sourceRedirect"
+        ) || Some(key) == command_key
+            // 仅对发布者生效，转换后的流都由网关拉取
+            || key.starts_with("publish")
+            || key.starts_with("rpiCamera")
+            || matches!(
+                key,
+                "overridePublisher" | "disablePublisherOverride" | "srtPublishPassphrase"
+            )
+            // 附属于其他选项，随其一同提示
+            || (key.starts_with("record") && key != "record")
+            || (key.starts_with("runOn")
+                && ["Restart", "StartTimeout", "CloseAfter"]
+                    .iter()
+                    .any(|suffix| key.ends_with(suffix)));
+        if handled || !active(value) {
+            continue;
+        }
+        import.warn(format!("{}: {} is not supported", origin, key));
+    }
+    Some(draft)
+}
+
+/// 转换 nginx-rtmp 配置：各 `application` 中的 `pull ... name=...` 与
+/// 以 FFmpeg 拉流转发的 `exec_static`/`exec_pull` 转换为 relay 流
+pub fn nginx_rtmp(text: &str) -> anyhow::Result<Import> {
+    let directives = parse_nginx(text)?;
+    let mut import = Import::default();
+    let applications = directives
+        .iter()
+        .filter(|d| d.name == "rtmp")
+        .flat_map(|rtmp| &rtmp.block)
+        .filter(|d| d.name == "server")
+        .flat_map(|server| &server.block)
+        .filter(|d| d.name == "application");
+    for app in applications {
+        let app_name = app.args.first().map(String::as_str).unwrap_or_default();
+        let value = |name: &str| {
+            app.block
+                .iter()
+                .rev()
+                .find(|d| d.name == name)
+                .and_then(|d| d.args.first())
+                .and_then(|v| parse_duration(v))
+        };
+        let mut hls = Mapping::new();
+        let (fragment, playlist) = (value("hls_fragment"), value("hls_playlist_length"));
+        if fragment.is_some() || playlist.is_some() {
+            let fragment = fragment.unwrap_or(NGINX_HLS_FRAGMENT_SEC).max(1.0);
+            let playlist = playlist.unwrap_or(NGINX_HLS_PLAYLIST_SEC);
+            hls.insert(
+                "segment_duration_sec".into(),
+                (fragment.round() as u32).into(),
+            );
+            hls.insert(
+                "list_size".into(),
+                ((playlist / fragment).ceil().max(1.0) as u32).into(),
+            );
+        }
+
+        for d in &app.block {
+            let origin = format!("line {}: application [{}]", d.line, app_name);
+            match d.name.as_str() {
+                "pull" => {
+                    let Some(url) = d.args.first() else {
+                        continue;
+                    };
+                    let options = &d.args[1..];
+                    let Some(name) = options.iter().find_map(|o| o.strip_prefix("name=")) else {
+                        import.warn(format!(
+                            "{}: pull without name= relays every requested stream; add the streams by hand",
+                            origin
+                        ));
+                        continue;
+                    };
+                    let on_demand = !options.iter().any(|o| o == "static");
+                    import.add(Draft {
+                        name: name.to_string(),
+                        origin: format!("{} pull", origin),
+                        source: url.clone(),
+                        rtsp_transport: None,
+                        on_demand: on_demand.then_some(DEFAULT_CLOSE_AFTER_SEC),
+                        start_timeout_ms: None,
+                        hls: hls.clone(),
+                    });
+                }
+                "exec_static" | "exec_pull" => {
+                    let origin = format!("{} {}", origin, d.name);
+                    let Some(pull) = ffmpeg_pull(&d.args.join(" ")) else {
+                        import.warn(format!(
+                            "{}: not an FFmpeg pull command; add this stream by hand",
+                            origin
+                        ));
+                        continue;
+                    };
+                    // 推送回本机的地址的最后一段为流名称
+                    let name = pull
+                        .output
+                        .as_deref()
+                        .and_then(|url| url.split(['?', '#']).next())
+                        .and_then(|url| url.trim_end_matches('/').rsplit('/').next())
+                        .unwrap_or_default();
+                    if name.is_empty() || name.contains('$') {
+                        import.warn(format!(
+                            "{}: stream name cannot be determined from the output; add this stream by hand",
+                            origin
+                        ));
+                        continue;
+                    }
+                    if !pull.dropped.is_empty() {
+                        import.warn(format!(
+                            "{}: FFmpeg arguments not imported (the stream is relayed with -c copy): {}",
+                            origin,
+                            pull.dropped.join(" ")
+                        ));
+                    }
+                    import.add(Draft {
+                        name: name.to_string(),
+                        origin,
+                        source: pull.source,
+                        rtsp_transport: pull.rtsp_transport,
+                        on_demand: (d.name == "exec_pull").then_some(DEFAULT_CLOSE_AFTER_SEC),
+                        start_timeout_ms: None,
+                        hls: hls.clone(),
+                    });
+                }
+                "hls_fragment" | "hls_playlist_length" => {}
+                "record" if d.args.first().map(String::as_str) == Some("off") => {}
+                name if NGINX_IGNORED.contains(&name) => {}
+                name => import.warn(format!("{}: `{}` is not supported", origin, name)),
+            }
+        }
+    }
+    Ok(import)
+}
+
+/// 从 FFmpeg 拉流转发命令中提取的输入
+struct FfmpegPull {
+    source: String,
+    rtsp_transport: Option<String>,
+    /// 推送的目标地址 (最后一个位置参数)
+    output: Option<String>,
+    /// 除转发 (`-c copy`) 以外的参数，转换后不再生效
+    dropped: Vec<String>,
+}
+
+/// 解析 `ffmpeg ... -i <source> ... <output>` 形式的命令，不是 FFmpeg 命令或没有输入时为 None
+fn ffmpeg_pull(command: &str) -> Option<FfmpegPull> {
+    let mut args = split_command(command);
+    let program = args.first()?.rsplit(['/', '\\']).next()?;
+    if program != "ffmpeg" && program != "ffmpeg.exe" {
+        return None;
+    }
+    args.remove(0);
+    let output = match args.as_slice() {
+        [.., prev, last] if !last.starts_with('-') && prev != "-i" => args.pop(),
+        _ => None,
+    };
+
+    let mut source = None;
+    let mut rtsp_transport = None;
+    let mut dropped = Vec::new();
+    let mut iter = args.into_iter().peekable();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-i" if source.is_none() => source = iter.next(),
+            "-rtsp_transport" => {
+                let value = iter.next();
+                // 输出端的 -rtsp_transport 只影响推送回本机
+                if source.is_none() {
+                    rtsp_transport = value.filter(|v| v == "tcp" || v == "udp");
+                }
+            }
+            "-re" | "-hide_banner" | "-nostdin" | "-y" | "-n" => {}
+            "-loglevel" | "-v" | "-f" => {
+                iter.next();
+            }
+            "-c" | "-codec" | "-c:v" | "-c:a" | "-codec:v" | "-codec:a" | "-vcodec" | "-acodec"
+                if iter.peek().is_some_and(|v| v == "copy") =>
+            {
+                iter.next();
+            }
+            _ => {
+                dropped.push(arg);
+                if let Some(value) = iter.next_if(|v| !v.starts_with('-')) {
+                    dropped.push(value);
+                }
+            }
+        }
+    }
+    Some(FfmpegPull {
+        source: source?,
+        rtsp_transport,
+        output,
+        dropped,
+    })
+}
+
+/// 按 Shell 规则拆分命令行 (支持单双引号与反斜杠转义)
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_arg = true;
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+/// nginx 配置中的一条指令
+struct Directive {
+    name: String,
+    args: Vec<String>,
+    /// `{ ... }` 中的子指令
+    block: Vec<Directive>,
+    line: usize,
+}
+
+enum Token {
+    Word(String),
+    Semicolon,
+    Open,
+    Close,
+}
+
+/// 解析 nginx 配置的指令树
+fn parse_nginx(text: &str) -> anyhow::Result<Vec<Directive>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            ';' => tokens.push((Token::Semicolon, line)),
+            '{' => tokens.push((Token::Open, line)),
+            '}' => tokens.push((Token::Close, line)),
+            '"' | '\'' => {
+                let start = line;
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(ch) => {
+                            if ch == '\n' {
+                                line += 1;
+                            }
+                            word.push(ch);
+                        }
+                        None => anyhow::bail!("line {}: unterminated quoted string", start),
+                    }
+                }
+                tokens.push((Token::Word(word), start));
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(ch) =
+                    chars.next_if(|ch| !ch.is_whitespace() && !matches!(ch, ';' | '{' | '}'))
+                {
+                    word.push(ch);
+                }
+                tokens.push((Token::Word(word), line));
+            }
+        }
+    }
+    let mut tokens = tokens.into_iter();
+    parse_block(&mut tokens, false)
+}
+
+fn parse_block(
+    tokens: &mut impl Iterator<Item = (Token, usize)>,
+    nested: bool,
+) -> anyhow::Result<Vec<Directive>> {
+    let mut directives = Vec::new();
+    loop {
+        let (name, line) = match tokens.next() {
+            None if nested => anyhow::bail!("Unexpected end of file (missing `}}`)"),
+            None => return Ok(directives),
+            Some((Token::Close, _)) if nested => return Ok(directives),
+            Some((Token::Close, line)) => anyhow::bail!("line {}: unexpected `}}`", line),
+            Some((Token::Open, line)) => anyhow::bail!("line {}: unexpected `{{`", line),
+            Some((Token::Semicolon, _)) => continue,
+            Some((Token::Word(name), line)) => (name, line),
+        };
+        let mut args = Vec::new();
+        let block = loop {
+            match tokens.next() {
+                Some((Token::Word(arg), _)) => args.push(arg),
+                Some((Token::Semicolon, _)) => break Vec::new(),
+                Some((Token::Open, _)) => break parse_block(tokens, true)?,
+                Some((Token::Close, line)) => {
+                    anyhow::bail!("line {}: missing `;` after `{}`", line, name)
+                }
+                None => anyhow::bail!("line {}: missing `;` after `{}`", line, name),
+            }
+        };
+        directives.push(Directive {
+            name,
+            args,
+            block,
+            line,
+        });
+    }
+}
+
+/// 选项是否为非缺省值 (未设置、关闭、空字符串、0 与空列表视为缺省)
+fn active(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty() && truthy(value) != Some(false),
+        Value::Sequence(seq) => !seq.is_empty(),
+        Value::Mapping(map) => !map.is_empty(),
+        Value::Tagged(tagged) => active(&tagged.value),
+    }
+}
+
+/// 布尔选项的值 (MediaMTX 的示例配置使用 YAML 1.1 的 `yes`/`no`)
+fn truthy(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => match s.as_str() {
+            "yes" | "true" | "on" => Some(true),
+            "no" | "false" | "off" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn close_after_sec(value: Option<&Value>) -> u64 {
+    value
+        .and_then(seconds)
+        .map(|s| s.ceil() as u64)
+        .unwrap_or(DEFAULT_CLOSE_AFTER_SEC)
+}
+
+/// Go 时长字符串 (如 `10s`、`1m30s`、`500ms`) 或秒数
+fn seconds(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => parse_duration(s),
+        _ => None,
+    }
+}
+
+/// 解析由数字与单位 (ns、us、ms、s、m、h、d) 组成的时长 (Go 与 nginx 的写法)，不带单位时为秒
+fn parse_duration(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(sec) = text.parse::<f64>() {
+        return Some(sec);
+    }
+    let mut total = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return None,
+        };
+        total += number * scale;
+        rest = &rest[unit_len..];
+    }
+    Some(total)
+}
@@ -0,0 +1,54 @@
+use crate::config::{RtspTransport, StreamConfig};
+
+/// 源地址的输入选项 (位于 `-i` 之前)：超时、重连与探测参数
+///
+/// 各协议的选项名称不同，按源地址的协议选择；不适用于该协议的设置被忽略，
+/// 因此同一份配置对主源与不同协议的备用源都有效
+pub fn args(cfg: &StreamConfig, source: &str) -> Vec<String> {
+    let scheme = source
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase())
+        .unwrap_or_default();
+    let rtsp = matches!(scheme.as_str(), "rtsp" | "rtsps");
+    let http = matches!(scheme.as_str(), "http" | "https");
+    // 本地文件 (无协议前缀或 file://) 不需要超时
+    let network = !scheme.is_empty() && scheme != "file";
+    let mut args = Vec::new();
+
+    if rtsp {
+        if let Some(transport) = cfg.rtsp_transport {
+            let transport = match transport {
+                RtspTransport::Tcp => "tcp",
+                RtspTransport::Udp => "udp",
+            };
+            args.extend(["-rtsp_transport".to_string(), transport.to_string()]);
+        }
+    }
+    if let Some(timeout) = cfg.input_timeout_sec.filter(|_| network) {
+        // RTSP 解复用器使用自己的套接字超时，其余网络协议使用通用的读写超时 (均为微秒)
+        let flag = if rtsp { "-timeout" } else { "-rw_timeout" };
+        args.extend([flag.to_string(), (timeout * 1_000_000).to_string()]);
+    }
+    if cfg.reconnect && http {
+        args.extend(
+            [
+                "-reconnect",
+                "1",
+                "-reconnect_streamed",
+                "1",
+                "-reconnect_on_network_error",
+                "1",
+                "-reconnect_delay_max",
+                "10",
+            ]
+            .map(String::from),
+        );
+    }
+    if let Some(duration) = cfg.analyzeduration {
+        args.extend(["-analyzeduration".to_string(), duration.to_string()]);
+    }
+    if let Some(size) = cfg.probesize {
+        args.extend(["-probesize".to_string(), size.to_string()]);
+    }
+    args
+}
@@ -0,0 +1,662 @@
+use crate::auth;
+use crate::config::{JwtConfig, StreamConfig};
+use crate::hash;
+use crate::http_client;
+use crate::pattern::Pattern;
+use crate::state::{AppState, LockExt};
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// 获取 JWKS 的超时
+const JWKS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 两次获取 JWKS 的最小间隔，避免携带未知 kid 的令牌触发频繁请求
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 接受的最小 RSA 模数长度 (位)
+const MIN_RSA_BITS: usize = 2048;
+
+/// SHA-256 的 DigestInfo 前缀 (PKCS#1 v1.5 签名中摘要之前的 DER 编码)
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+/// 从 JWKS 地址获取的 RS256 公钥
+#[derive(Debug, Default)]
+pub struct KeyCache {
+    /// 公钥所属的 JWKS 地址 (配置变化后重新获取)
+    url: String,
+    keys: Vec<RsaKey>,
+    /// 最近一次成功获取的时间
+    fetched: Option<Instant>,
+    /// 最近一次尝试获取的时间
+    attempted: Option<Instant>,
+}
+
+/// 拒绝时的说明
+type Rejection = (StatusCode, String);
+
+fn unauthorized(message: &str) -> Rejection {
+    (StatusCode::UNAUTHORIZED, message.to_string())
+}
+
+fn keys_unavailable() -> Rejection {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Playback token keys unavailable".to_string(),
+    )
+}
+
+/// 配置了 jwt 且流在其范围内时校验播放令牌，拒绝时返回应答给播放器的状态与说明
+///
+/// 可访问该流所属租户的管理 API 令牌同样被接受 (便于运维直接查看)
+pub async fn check(
+    state: &AppState,
+    cfg: &StreamConfig,
+    token: Option<&str>,
+) -> Result<(), Rejection> {
+    let config = state.config();
+    let Some(jwt) = &config.jwt else {
+        return Ok(());
+    };
+    if !applies(jwt, &cfg.name) {
+        return Ok(());
+    }
+    let token = token.ok_or_else(|| unauthorized("Playback token required"))?;
+    if token.split('.').count() != 3 {
+        return match auth::authenticate(state, Some(token)) {
+            Some(principal) if principal.can_access(cfg.tenant.as_deref()) => Ok(()),
+            _ => Err(unauthorized("Invalid playback token")),
+        };
+    }
+    verify(state, jwt, token, &cfg.name).await
+}
+
+/// 校验 JWT 的签名、有效期、签发者与受众，并确认令牌可访问指定的流
+pub async fn verify(
+    state: &AppState,
+    jwt: &JwtConfig,
+    token: &str,
+    stream: &str,
+) -> Result<(), Rejection> {
+    let claims = verify_signature(state, jwt, token).await?;
+    validate_claims(jwt, &claims)?;
+
+    match claims.get(&jwt.streams_claim) {
+        None => Ok(()),
+        Some(Value::String(name)) if name == stream => Ok(()),
+        Some(Value::Array(names)) if names.iter().any(|n| n.as_str() == Some(stream)) => Ok(()),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            "Playback token does not grant this stream".to_string(),
+        )),
+    }
+}
+
+fn applies(jwt: &JwtConfig, stream: &str) -> bool {
+    jwt.streams.is_empty()
+        || jwt
+            .streams
+            .iter()
+            .filter_map(|p| Pattern::new(p).ok())
+            .any(|p| p.is_match(stream))
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// 校验签名并返回声明；只接受 HS256 (配置了 secret) 与 RS256 (配置了 jwks_url)
+async fn verify_signature(
+    state: &AppState,
+    jwt: &JwtConfig,
+    token: &str,
+) -> Result<serde_json::Map<String, Value>, Rejection> {
+    let invalid = || unauthorized("Invalid playback token");
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    let signing_input = &token[..header.len() + 1 + payload.len()];
+    let signature = hash::base64_decode(signature).ok_or_else(invalid)?;
+    let header: Header = hash::base64_decode(header)
+        .and_then(|h| serde_json::from_slice(&h).ok())
+        .ok_or_else(invalid)?;
+
+    let valid = match (header.alg.as_str(), &jwt.secret, &jwt.jwks_url) {
+        ("HS256", Some(secret), _) => {
+            hs256_valid(secret.as_bytes(), signing_input.as_bytes(), &signature)
+        }
+        ("RS256", _, Some(url)) => {
+            let digest = hash::sha256(signing_input.as_bytes());
+            rsa_verify(state, jwt, url, header.kid.as_deref(), &digest, &signature).await?
+        }
+        _ => false,
+    };
+    if !valid {
+        return Err(invalid());
+    }
+    hash::base64_decode(payload)
+        .and_then(|p| serde_json::from_slice(&p).ok())
+        .ok_or_else(invalid)
+}
+
+/// HMAC-SHA256 签名校验 (常数时间比较)
+fn hs256_valid(key: &[u8], signing_input: &[u8], signature: &[u8]) -> bool {
+    let mac = hash::hmac_sha256(key, signing_input);
+    signature.len() == mac.len()
+        && mac
+            .iter()
+            .zip(signature)
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// 检查 `exp` (必需)、`nbf`、`iss` 与 `aud`
+fn validate_claims(
+    jwt: &JwtConfig,
+    claims: &serde_json::Map<String, Value>,
+) -> Result<(), Rejection> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let leeway = jwt.leeway_sec as f64;
+    let time = |name: &str| claims.get(name).and_then(Value::as_f64);
+
+    match time("exp") {
+        None => return Err(unauthorized("Playback token has no expiry")),
+        Some(exp) if exp + leeway <= now => return Err(unauthorized("Playback token expired")),
+        Some(_) => {}
+    }
+    if time("nbf").is_some_and(|nbf| nbf - leeway > now) {
+        return Err(unauthorized("Playback token not yet valid"));
+    }
+    if let Some(issuer) = &jwt.issuer {
+        if claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str()) {
+            return Err(unauthorized("Playback token issuer not accepted"));
+        }
+    }
+    if let Some(audience) = &jwt.audience {
+        let accepted = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !accepted {
+            return Err(unauthorized("Playback token audience not accepted"));
+        }
+    }
+    Ok(())
+}
+
+/// 以 JWKS 中的公钥校验 RS256 签名；令牌指定 kid 时只使用该公钥
+async fn rsa_verify(
+    state: &AppState,
+    jwt: &JwtConfig,
+    url: &str,
+    kid: Option<&str>,
+    digest: &[u8; 32],
+    signature: &[u8],
+) -> Result<bool, Rejection> {
+    let verify_with = |cache: &KeyCache| -> Option<bool> {
+        let mut candidates = cache
+            .keys
+            .iter()
+            .filter(|k| kid.is_none() || k.kid.as_deref() == kid)
+            .peekable();
+        candidates.peek()?;
+        Some(candidates.any(|k| k.verify(digest, signature)))
+    };
+
+    let now = Instant::now();
+    let (result, refetch, unavailable) = {
+        let mut cache = state.jwks.lock_or_recover();
+        if cache.url != url {
+            *cache = KeyCache {
+                url: url.to_string(),
+                ..Default::default()
+            };
+        }
+        let stale = cache
+            .fetched
+            .is_none_or(|at| now.duration_since(at) >= Duration::from_secs(jwt.jwks_refresh_sec));
+        let result = verify_with(&cache);
+        // 公钥过期或找不到令牌指定的 kid (签发方可能已轮换公钥) 时重新获取
+        let refetch = (stale || result.is_none())
+            && cache
+                .attempted
+                .is_none_or(|at| now.duration_since(at) >= MIN_REFETCH_INTERVAL);
+        if refetch {
+            cache.attempted = Some(now);
+        }
+        (result, refetch, cache.fetched.is_none())
+    };
+    if !refetch {
+        return match result {
+            Some(valid) => Ok(valid),
+            None if unavailable => Err(keys_unavailable()),
+            None => Err(unauthorized("Invalid playback token")),
+        };
+    }
+
+    match fetch_jwks(url).await {
+        Ok(keys) => {
+            let mut cache = state.jwks.lock_or_recover();
+            if cache.url == url {
+                info!("Loaded {} RS256 key(s) from {}", keys.len(), url);
+                cache.keys = keys;
+                cache.fetched = Some(now);
+            }
+        }
+        // 继续使用之前获取的公钥
+        Err(e) => warn!("Failed to fetch JWKS from {}: {}", url, e),
+    }
+    let cache = state.jwks.lock_or_recover();
+    match verify_with(&cache) {
+        Some(valid) => Ok(valid),
+        None if cache.fetched.is_none() => Err(keys_unavailable()),
+        None => Err(unauthorized("Invalid playback token")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default, rename = "use")]
+    usage: Option<String>,
+    #[serde(default)]
+    alg: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+}
+
+/// 获取 JWKS，只保留可用于 RS256 签名校验的 RSA 公钥
+async fn fetch_jwks(url: &str) -> anyhow::Result<Vec<RsaKey>> {
+    let res = http_client::request("GET", url, &[], None, JWKS_TIMEOUT).await?;
+    if !res.is_success() {
+        anyhow::bail!("JWKS endpoint returned status {}", res.status);
+    }
+    #[derive(Deserialize)]
+    struct Jwks {
+        keys: Vec<Jwk>,
+    }
+    let jwks: Jwks = serde_json::from_slice(&res.body)?;
+    let keys: Vec<RsaKey> = jwks
+        .keys
+        .into_iter()
+        .filter(|k| k.kty == "RSA")
+        .filter(|k| k.usage.as_deref().is_none_or(|u| u == "sig"))
+        .filter(|k| k.alg.as_deref().is_none_or(|a| a == "RS256"))
+        .filter_map(|k| {
+            let n = hash::base64_decode(k.n.as_deref()?)?;
+            let e = hash::base64_decode(k.e.as_deref()?)?;
+            let key = RsaKey::new(k.kid.clone(), &n, &e);
+            if key.is_none() {
+                warn!("Ignoring unusable RSA key {:?} from {}", k.kid, url);
+            }
+            key
+        })
+        .collect();
+    if keys.is_empty() {
+        anyhow::bail!("JWKS contains no RS256 keys");
+    }
+    Ok(keys)
+}
+
+/// RSA 公钥，模数以小端 64 位字表示，并预先计算 Montgomery 乘法参数
+#[derive(Debug)]
+struct RsaKey {
+    kid: Option<String>,
+    n: Vec<u64>,
+    /// 公钥指数 (大端字节)
+    e: Vec<u8>,
+    /// 模数字节数
+    len: usize,
+    /// -n⁻¹ mod 2⁶⁴
+    n0_inv: u64,
+    /// R² mod n，R = 2^(64 × 字数)
+    r2: Vec<u64>,
+}
+
+impl RsaKey {
+    fn new(kid: Option<String>, n: &[u8], e: &[u8]) -> Option<Self> {
+        let n = strip_zeros(n);
+        let e = strip_zeros(e).to_vec();
+        // 模数必须为奇数 (Montgomery 乘法的前提)
+        if n.len() * 8 < MIN_RSA_BITS || n.last()? & 1 == 0 || e.is_empty() {
+            return None;
+        }
+        let len = n.len();
+        let n = to_limbs(n, len.div_ceil(8));
+
+        // 牛顿迭代求 n[0] 在 2⁶⁴ 下的逆元，每次迭代精度翻倍
+        let mut inv: u64 = 1;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(n[0].wrapping_mul(inv)));
+        }
+
+        // 由 1 反复倍加得到 R² mod n
+        let mut r2 = vec![0u64; n.len()];
+        r2[0] = 1;
+        for _ in 0..2 * 64 * n.len() {
+            let carry = r2[r2.len() - 1] >> 63;
+            for i in (0..r2.len()).rev() {
+                r2[i] = (r2[i] << 1) | if i > 0 { r2[i - 1] >> 63 } else { 0 };
+            }
+            if carry == 1 || !less_than(&r2, &n) {
+                sub_assign(&mut r2, &n);
+            }
+        }
+        Some(Self {
+            kid,
+            n,
+            e,
+            len,
+            n0_inv: inv.wrapping_neg(),
+            r2,
+        })
+    }
+
+    /// PKCS#1 v1.5 签名校验：签名的 e 次幂须等于
+    /// `00 01 FF..FF 00 || DigestInfo || SHA-256 摘要`
+    fn verify(&self, digest: &[u8; 32], signature: &[u8]) -> bool {
+        let signature = strip_zeros(signature);
+        if signature.len() > self.len || self.len < SHA256_DIGEST_INFO.len() + 32 + 11 {
+            return false;
+        }
+        let s = to_limbs(signature, self.n.len());
+        if !less_than(&s, &self.n) {
+            return false;
+        }
+        let m = from_limbs(&self.pow(&s), self.len);
+
+        let mut expected = vec![0xffu8; self.len];
+        expected[0] = 0x00;
+        expected[1] = 0x01;
+        let t = self.len - SHA256_DIGEST_INFO.len() - 32;
+        expected[t - 1] = 0x00;
+        expected[t..t + SHA256_DIGEST_INFO.len()].copy_from_slice(&SHA256_DIGEST_INFO);
+        expected[self.len - 32..].copy_from_slice(digest);
+        m == expected
+    }
+
+    /// base^e mod n (从高位开始的平方-乘)
+    fn pow(&self, base: &[u64]) -> Vec<u64> {
+        let base = self.mont_mul(base, &self.r2);
+        let mut acc: Option<Vec<u64>> = None;
+        for byte in &self.e {
+            for bit in (0..8).rev() {
+                if let Some(x) = &acc {
+                    acc = Some(self.mont_mul(x, x));
+                }
+                if byte >> bit & 1 == 1 {
+                    acc = Some(match &acc {
+                        Some(x) => self.mont_mul(x, &base),
+                        None => base.clone(),
+                    });
+                }
+            }
+        }
+        let mut one = vec![0u64; self.n.len()];
+        one[0] = 1;
+        // e 非零，acc 必有值
+        self.mont_mul(acc.as_ref().unwrap_or(&one), &one)
+    }
+
+    /// Montgomery 乘法 a × b × R⁻¹ mod n (CIOS)，要求 a、b < n
+    fn mont_mul(&self, a: &[u64], b: &[u64]) -> Vec<u64> {
+        let s = self.n.len();
+        let mut t = vec![0u64; s + 2];
+        for &bi in b {
+            let mut carry = 0u128;
+            for j in 0..s {
+                let uv = t[j] as u128 + a[j] as u128 * bi as u128 + carry;
+                t[j] = uv as u64;
+                carry = uv >> 64;
+            }
+            let uv = t[s] as u128 + carry;
+            t[s] = uv as u64;
+            t[s + 1] = (uv >> 64) as u64;
+
+            let m = t[0].wrapping_mul(self.n0_inv);
+            let mut carry = (t[0] as u128 + m as u128 * self.n[0] as u128) >> 64;
+            for j in 1..s {
+                let uv = t[j] as u128 + m as u128 * self.n[j] as u128 + carry;
+                t[j - 1] = uv as u64;
+                carry = uv >> 64;
+            }
+            let uv = t[s] as u128 + carry;
+            t[s - 1] = uv as u64;
+            t[s] = t[s + 1] + (uv >> 64) as u64;
+        }
+        let overflow = t[s] != 0;
+        t.truncate(s);
+        if overflow || !less_than(&t, &self.n) {
+            sub_assign(&mut t, &self.n);
+        }
+        t
+    }
+}
+
+fn strip_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// 大端字节转为指定字数的小端 64 位字
+fn to_limbs(bytes: &[u8], words: usize) -> Vec<u64> {
+    let mut limbs = vec![0u64; words];
+    for (i, &b) in bytes.iter().rev().enumerate() {
+        limbs[i / 8] |= (b as u64) << (8 * (i % 8));
+    }
+    limbs
+}
+
+/// 小端 64 位字转为指定长度的大端字节
+fn from_limbs(limbs: &[u64], len: usize) -> Vec<u8> {
+    (0..len)
+        .rev()
+        .map(|i| (limbs[i / 8] >> (8 * (i % 8))) as u8)
+        .collect()
+}
+
+fn less_than(a: &[u64], b: &[u64]) -> bool {
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        if x != y {
+            return x < y;
+        }
+    }
+    false
+}
+
+/// a -= b (按 2^(64 × 字数) 取模)
+fn sub_assign(a: &mut [u64], b: &[u64]) {
+    let mut borrow = false;
+    for (x, &y) in a.iter_mut().zip(b) {
+        let (d, b1) = x.overflowing_sub(y);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        *x = d;
+        borrow = b1 || b2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use std::sync::Arc;
+
+    /// RFC 7515 附录 A.1 / A.2 的载荷 (`exp` 为 2011 年，已过期)
+    const RFC_PAYLOAD: &str = "eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ";
+
+    fn b64url(data: &[u8]) -> String {
+        hash::base64_encode(data)
+            .trim_end_matches('=')
+            .replace('+', "-")
+            .replace('/', "_")
+    }
+
+    fn test_state() -> (Arc<AppState>, JwtConfig) {
+        let yaml = r#"
+server:
+  listen: 127.0.0.1:0
+  ffmpeg_binary: ffmpeg
+  supervisor_interval_ms: 1000
+streams: []
+jwt:
+  secret: s3cret
+  issuer: vtx
+"#;
+        let config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+        let jwt = config.jwt.clone().unwrap();
+        let state = AppState::new(config, "config.yaml".into()).unwrap();
+        (Arc::new(state), jwt)
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// 以 `s3cret` 签名的 HS256 令牌
+    fn sign(header: &str, claims: Value) -> String {
+        let input = format!(
+            "{}.{}",
+            b64url(header.as_bytes()),
+            b64url(claims.to_string().as_bytes())
+        );
+        let mac = hash::hmac_sha256(b"s3cret", input.as_bytes());
+        format!("{}.{}", input, b64url(&mac))
+    }
+
+    #[test]
+    fn rfc7515_a1_hs256() {
+        let key = hash::base64_decode(
+            "AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow",
+        )
+        .unwrap();
+        let input = format!("eyJ0eXAiOiJKV1QiLA0KICJhbGciOiJIUzI1NiJ9.{}", RFC_PAYLOAD);
+        let mut signature =
+            hash::base64_decode("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").unwrap();
+        assert!(hs256_valid(&key, input.as_bytes(), &signature));
+        signature[0] ^= 1;
+        assert!(!hs256_valid(&key, input.as_bytes(), &signature));
+        assert!(!hs256_valid(&key, input.as_bytes(), &signature[..31]));
+    }
+
+    #[test]
+    fn rfc7515_a2_rs256() {
+        let n = hash::base64_decode(
+            "ofgWCuLjybRlzo0tZWJjNiuSfb4p4fAkd_wWJcyQoTbji9k0l8W26mPddxHmfHQp-Vaw-4qPCJrcS2mJPMEzP1Pt0Bm4d4QlL-yRT-SFd2lZS-pCgNMsD1W_YpRPEwOWvG6b32690r2jZ47soMZo9wGzjb_7OMg0LOL-bSf63kpaSHSXndS5z5rexMdbBYUsLA9e-KXBdQOS-UTo7WTBEMa2R2CapHg665xsmtdVMTBQY4uDZlxvb3qCo5ZwKh9kG4LT6_I5IhlJH7aGhyxXFvUK-DWNmoudF8NAco9_h9iaGNj8q2ethFkMLs91kzk2PAcDTW9gb54h4FRWyuXpoQ",
+        )
+        .unwrap();
+        let e = hash::base64_decode("AQAB").unwrap();
+        let key = RsaKey::new(None, &n, &e).unwrap();
+        let input = format!("eyJhbGciOiJSUzI1NiJ9.{}", RFC_PAYLOAD);
+        let digest = hash::sha256(input.as_bytes());
+        let mut signature = hash::base64_decode(
+            "cC4hiUPoj9Eetdgtv3hF80EGrhuB__dzERat0XF9g2VtQgr9PJbu3XOiZj5RZmh7AAuHIm4Bh-0Qc_lF5YKt_O8W2Fp5jujGbds9uJdbF9CUAr7t1dnZcAcQjbKBYNX4BAynRFdiuB--f_nZLgrnbyTyWzO75vRK5h6xBArLIARNPvkSjtQBMHlb1L07Qe7K0GarZRmB_eSN9383LcOLn6_dO--xi12jzDwusC-eOkHWEsqtFZESc6BfI7noOPqvhJ1phCnvWh6IeYI2w9QOYEUipUTI8np6LbgGY9Fs98rqVt5AXLIhWkWywlVmtVrBp0igcN_IoypGlUPQGe77Rw",
+        )
+        .unwrap();
+        assert!(key.verify(&digest, &signature));
+        assert!(!key.verify(&hash::sha256(b"tampered"), &signature));
+        signature[100] ^= 1;
+        assert!(!key.verify(&digest, &signature));
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_hs256_token() {
+        let (state, jwt) = test_state();
+        let token = sign(
+            r#"{"alg":"HS256"}"#,
+            serde_json::json!({"exp": now() + 600, "iss": "vtx", "streams": ["cam"]}),
+        );
+        assert!(verify(&state, &jwt, &token, "cam").await.is_ok());
+        let (status, _) = verify(&state, &jwt, &token, "other").await.unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signature_and_alg_none() {
+        let (state, jwt) = test_state();
+        let claims = serde_json::json!({"exp": now() + 600, "iss": "vtx"});
+        let token = sign(r#"{"alg":"HS256"}"#, claims.clone());
+        // 改动签名开头的一个字符 (末尾字符含不参与解码的填充位)
+        let at = token.rfind('.').unwrap() + 1;
+        let flipped = if &token[at..at + 1] == "A" { "B" } else { "A" };
+        let forged = format!("{}{}{}", &token[..at], flipped, &token[at + 1..]);
+        assert!(verify(&state, &jwt, &forged, "cam").await.is_err());
+
+        // 无签名的令牌
+        let unsigned = format!(
+            "{}.{}.",
+            b64url(br#"{"alg":"none"}"#),
+            b64url(claims.to_string().as_bytes())
+        );
+        assert!(verify(&state, &jwt, &unsigned, "cam").await.is_err());
+        // 声明 none 却附带了 HS256 签名
+        let mislabeled = sign(r#"{"alg":"none"}"#, claims);
+        assert!(verify(&state, &jwt, &mislabeled, "cam").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn enforces_time_and_issuer_claims() {
+        let (state, jwt) = test_state();
+        let t = now();
+        for (claims, message) in [
+            (
+                serde_json::json!({"iss": "vtx"}),
+                "Playback token has no expiry",
+            ),
+            (
+                serde_json::json!({"exp": t - 3600, "iss": "vtx"}),
+                "Playback token expired",
+            ),
+            (
+                serde_json::json!({"exp": t + 600, "nbf": t + 300, "iss": "vtx"}),
+                "Playback token not yet valid",
+            ),
+            (
+                serde_json::json!({"exp": t + 600, "iss": "someone"}),
+                "Playback token issuer not accepted",
+            ),
+        ] {
+            let token = sign(r#"{"alg":"HS256"}"#, claims);
+            let rejection = verify(&state, &jwt, &token, "cam").await.unwrap_err();
+            assert_eq!(rejection, (StatusCode::UNAUTHORIZED, message.to_string()));
+        }
+    }
+
+    #[test]
+    fn jwks_must_be_local() {
+        let config = |url: &str| {
+            format!(
+                "server:\n  listen: 127.0.0.1:0\n  ffmpeg_binary: ffmpeg\n  supervisor_interval_ms: 1000\nstreams: []\njwt:\n  jwks_url: {}\n",
+                url
+            )
+        };
+        let validate = |url: &str| {
+            serde_yaml::from_str::<AppConfig>(&config(url))
+                .unwrap()
+                .validate()
+        };
+        assert!(validate("http://127.0.0.1:9000/jwks.json").is_ok());
+        assert!(validate("http://idp.example.com/jwks.json").is_err());
+    }
+}
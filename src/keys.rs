@@ -0,0 +1,159 @@
+use crate::privilege;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::fs;
+
+/// 保留的历史密钥数量 (播放器可能仍在请求旧切片)
+const RETAINED_KEYS: usize = 16;
+
+/// 单个流的 AES-128 密钥环
+pub struct StreamKeyring {
+    /// 已发布的密钥 (id, key)，按 id 递增
+    keys: VecDeque<(u64, [u8; 16])>,
+    /// 最近一次轮换时间
+    pub rotated_at: Instant,
+}
+
+impl StreamKeyring {
+    pub fn new() -> std::io::Result<Self> {
+        let mut keys = VecDeque::new();
+        keys.push_back((1, random_key()?));
+        Ok(Self {
+            keys,
+            rotated_at: Instant::now(),
+        })
+    }
+
+    /// 当前用于加密新切片的密钥
+    pub fn current(&self) -> (u64, [u8; 16]) {
+        *self.keys.back().expect("keyring is never empty")
+    }
+
+    /// 生成新密钥并淘汰过旧的密钥
+    pub fn rotate(&mut self) -> std::io::Result<(u64, [u8; 16])> {
+        let next = (self.current().0 + 1, random_key()?);
+        self.keys.push_back(next);
+        while self.keys.len() > RETAINED_KEYS {
+            self.keys.pop_front();
+        }
+        self.rotated_at = Instant::now();
+        Ok(next)
+    }
+
+    /// 按 id 查找密钥，未指定时返回当前密钥
+    pub fn get(&self, id: Option<u64>) -> Option<[u8; 16]> {
+        match id {
+            Some(id) => self.keys.iter().find(|(k, _)| *k == id).map(|(_, v)| *v),
+            None => Some(self.current().1),
+        }
+    }
+}
+
+/// 写入密钥文件与 FFmpeg 所需的 key info 文件
+///
+/// key info 通过临时文件 + rename 原子替换，
+/// 以便 FFmpeg 在 `periodic_rekey` 模式下读取到完整内容
+pub async fn write_key_files(dir: &Path, id: u64, key: &[u8; 16]) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir).await?;
+    privilege::chown_media(dir)?;
+
+    let key_path = dir.join(format!("key-{}.bin", id));
+    fs::write(&key_path, key).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    // FFmpeg 以独立用户运行时需能读取密钥
+    privilege::chown_media(&key_path)?;
+
+    // 旧密钥文件已被 FFmpeg 读取过，可直接清理
+    if id > RETAINED_KEYS as u64 {
+        let stale = dir.join(format!("key-{}.bin", id - RETAINED_KEYS as u64));
+        let _ = fs::remove_file(stale).await;
+    }
+
+    let info_path = dir.join("keyinfo");
+    let tmp_path = dir.join("keyinfo.tmp");
+    let content = format!("key?id={}\n{}\n", id, key_path.to_string_lossy());
+    fs::write(&tmp_path, content).await?;
+    fs::rename(&tmp_path, &info_path).await?;
+
+    Ok(info_path)
+}
+
+/// 生成随机 AES-128 密钥
+fn random_key() -> std::io::Result<[u8; 16]> {
+    let mut key = [0u8; 16];
+    random_bytes(&mut key)?;
+    Ok(key)
+}
+
+/// 以操作系统的密码学安全随机源填充缓冲区
+///
+/// 密钥、令牌与会话标识均由此生成，随机源不可用时返回错误，不退化为可预测的来源
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        // SAFETY: 写入范围为 rest 自身
+        let n = unsafe { libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0) };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        filled += n as usize;
+    }
+    Ok(())
+}
+
+/// 以操作系统的密码学安全随机源填充缓冲区
+///
+/// 密钥、令牌与会话标识均由此生成，随机源不可用时返回错误，不退化为可预测的来源
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+pub fn random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+    // getentropy 每次最多 256 字节
+    for chunk in buf.chunks_mut(256) {
+        // SAFETY: 写入范围为 chunk 自身
+        if unsafe { libc::getentropy(chunk.as_mut_ptr().cast(), chunk.len()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// 以操作系统的密码学安全随机源填充缓冲区
+///
+/// 密钥、令牌与会话标识均由此生成，随机源不可用时返回错误，不退化为可预测的来源
+#[cfg(windows)]
+pub fn random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+    use std::ffi::c_void;
+    const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 0x0000_0002;
+    #[link(name = "bcrypt")]
+    extern "system" {
+        fn BCryptGenRandom(alg: *mut c_void, buf: *mut u8, len: u32, flags: u32) -> i32;
+    }
+    for chunk in buf.chunks_mut(u32::MAX as usize) {
+        // SAFETY: 写入范围为 chunk 自身，系统首选的 RNG 不需要算法句柄
+        let status = unsafe {
+            BCryptGenRandom(
+                std::ptr::null_mut(),
+                chunk.as_mut_ptr(),
+                chunk.len() as u32,
+                BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+            )
+        };
+        if status != 0 {
+            return Err(std::io::Error::other(format!(
+                "BCryptGenRandom failed (NTSTATUS {:#x})",
+                status
+            )));
+        }
+    }
+    Ok(())
+}
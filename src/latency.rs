@@ -0,0 +1,138 @@
+use crate::clock;
+use crate::segment_index::DirIndex;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 未声明 `HOLD-BACK` 时播放器起播点距直播边缘的目标时长倍数 (RFC 8216 6.3.3)
+const HOLD_BACK_TARGETS: f64 = 3.0;
+
+/// 流的端到端延迟估算 (随进程重启清零，在返回主播放列表时更新)
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct LatencyStats {
+    /// 最新切片的结束时间距返回播放列表时的时长 (毫秒)：编码到可下发的延迟
+    pub live_edge_age_ms: Option<u64>,
+    /// 播放器在距直播边缘 hold-back 处起播时，画面采集到播放的估算延迟 (毫秒)
+    pub estimated_ms: Option<u64>,
+    /// 估算所用的 hold-back (毫秒)：播放列表的 `HOLD-BACK`，缺省为 3 倍目标时长
+    pub hold_back_ms: Option<u64>,
+    /// 切片时间的来源：`program_date_time` (FFmpeg 写入的挂钟时间) 或 `segment_mtime`
+    /// (切片写完的时间减去切片时长)
+    pub source: Option<&'static str>,
+    /// 参与估算的播放列表请求数
+    pub samples: u64,
+}
+
+/// 按返回给观看者的主播放列表更新延迟估算
+///
+/// 优先使用播放列表中最新切片的 `#EXT-X-PROGRAM-DATE-TIME`，没有时由切片文件的修改时间推算
+pub fn observe(state: &AppState, name: &str, playlist: &str, index: &DirIndex) {
+    let Some(edge) = live_edge(playlist, index) else {
+        return;
+    };
+    let now = SystemTime::now();
+    let age = now.duration_since(edge.end).unwrap_or_default();
+    let mut streams = state.active_streams.lock_or_recover();
+    let Some(running) = streams.get_mut(name) else {
+        return;
+    };
+    let stats = &mut running.latency;
+    stats.live_edge_age_ms = Some(age.as_millis() as u64);
+    stats.estimated_ms = Some((age + edge.hold_back).as_millis() as u64);
+    stats.hold_back_ms = Some(edge.hold_back.as_millis() as u64);
+    stats.source = Some(edge.source);
+    stats.samples += 1;
+}
+
+/// 播放列表的直播边缘
+struct Edge {
+    /// 最新切片的结束时间
+    end: SystemTime,
+    hold_back: Duration,
+    source: &'static str,
+}
+
+fn live_edge(playlist: &str, index: &DirIndex) -> Option<Edge> {
+    let mut target = None;
+    let mut hold_back = None;
+    let mut pdt = None;
+    let mut duration = None;
+    // (开始时间, 时长, 来源)
+    let mut newest = None;
+    for line in playlist.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            target = value.parse::<f64>().ok();
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-SERVER-CONTROL:") {
+            hold_back = attrs
+                .split(',')
+                .find_map(|a| a.strip_prefix("HOLD-BACK="))
+                .and_then(|v| v.parse::<f64>().ok());
+        } else if let Some(value) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            pdt = parse_date_time(value);
+        } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+            duration = info.split(',').next().and_then(|d| d.parse::<f64>().ok());
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let Some(secs) = duration.take() else {
+                continue;
+            };
+            let Ok(length) = Duration::try_from_secs_f64(secs) else {
+                continue;
+            };
+            // 同一标签只描述紧随其后的切片，之后的切片按时长顺延
+            newest = match pdt.take() {
+                Some(start) => Some((start, length, "program_date_time")),
+                None => match newest {
+                    Some((start, prev, "program_date_time")) => {
+                        Some((start + prev, length, "program_date_time"))
+                    }
+                    _ => segment_start(index, line, length)
+                        .map(|start| (start, length, "segment_mtime")),
+                },
+            };
+        }
+    }
+    let (start, length, source) = newest?;
+    let hold_back = hold_back.or(target.map(|t| t * HOLD_BACK_TARGETS))?;
+    Some(Edge {
+        end: start + length,
+        hold_back: Duration::try_from_secs_f64(hold_back).ok()?,
+        source,
+    })
+}
+
+/// 由切片写完的时间推算其开始时间
+fn segment_start(index: &DirIndex, uri: &str, length: Duration) -> Option<SystemTime> {
+    let name = uri.split('?').next()?;
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        return None;
+    }
+    index.modified(name)?.checked_sub(length)
+}
+
+/// 解析 `#EXT-X-PROGRAM-DATE-TIME` 的值，保留毫秒
+///
+/// FFmpeg 写出的时区偏移不带冒号 (如 `+0800`)，先补齐再按 RFC 3339 解析
+fn parse_date_time(value: &str) -> Option<SystemTime> {
+    let mut text = value.trim().to_string();
+    let tz = text.rfind(['+', '-']).filter(|&at| at > 10);
+    if let Some(at) = tz {
+        if text.len() - at == 5 && !text[at..].contains(':') {
+            text.insert(at + 3, ':');
+        }
+    }
+    let secs = clock::parse_rfc3339(&text)?;
+    // parse_rfc3339 忽略小数秒
+    let millis = text
+        .split_once('.')
+        .map(|(_, rest)| {
+            let digits: String = rest
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .chain("000".chars())
+                .take(3)
+                .collect();
+            digits.parse::<u64>().unwrap_or(0)
+        })
+        .unwrap_or(0);
+    Some(UNIX_EPOCH + Duration::from_millis(secs * 1000 + millis))
+}
@@ -0,0 +1,101 @@
+//! VTX Link 核心库：配置、状态、进程引擎与 Supervisor
+//!
+//! `vtx-link` 可执行文件只是本库的一层薄封装。嵌入到其他程序时：
+//! 用 [`config::AppConfig::load`] 读取配置，[`state::AppState::new`] 创建状态，
+//! [`app::spawn_tasks`] 启动 Supervisor 等后台任务，再通过 [`engine::Engine`] 启停流；
+//! 需要 HTTP 接口时将 [`app::router`] 挂载到自己的服务上，退出前调用 [`app::shutdown`]。
+
+pub mod abr;
+pub mod agent;
+pub mod alerts;
+pub mod analytics;
+/// 网关的组装：HTTP 路由、后台任务与关闭流程
+pub mod app;
+pub mod audit;
+pub mod auth;
+pub mod av_sync;
+pub mod availability;
+pub mod bandwidth;
+pub mod cli;
+pub mod clock;
+pub mod completion;
+/// 配置文件的结构、默认值与校验
+pub mod config;
+pub mod converge;
+pub mod counters;
+pub mod credentials;
+pub mod dependency;
+pub mod discovery;
+pub mod drain;
+pub mod ed25519;
+/// FFmpeg 进程的启动与停止
+pub mod engine;
+/// 流操作的类型化错误及其 HTTP 状态码映射
+pub mod error;
+pub mod failure;
+pub mod file_loop;
+pub mod forward_auth;
+pub mod gc;
+pub mod geoip;
+pub mod gpu;
+pub mod hash;
+pub mod health;
+pub mod heartbeat;
+pub mod http_client;
+pub mod import;
+pub mod input;
+pub mod jwt;
+pub mod keys;
+pub mod latency;
+pub mod listener;
+pub mod loadshed;
+pub mod maintenance;
+pub mod markers;
+pub mod matchers;
+pub mod metrics;
+pub mod migrate;
+pub mod mock;
+pub mod mosaic;
+pub mod motion;
+pub mod nettest;
+pub mod notify;
+pub mod ntp;
+pub mod origin_push;
+pub mod overlay;
+pub mod pattern;
+pub mod platform;
+pub mod playback;
+pub mod playlist;
+pub mod playout;
+pub mod privilege;
+pub mod proxy;
+pub mod quality;
+pub mod referer;
+pub mod rollout;
+pub mod rtsp;
+pub mod runtime_state;
+pub mod sandbox;
+pub mod segment_index;
+pub mod sessions;
+pub mod share;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod start_tasks;
+/// 全局状态 (AppState) 与流运行状态
+pub mod state;
+pub mod store;
+/// 崩溃重启、空闲回收等后台托管
+pub mod supervisor;
+pub mod system;
+pub mod templates;
+pub mod tenant;
+pub mod test_source;
+pub mod timelapse;
+pub mod tokens;
+pub mod transfer;
+pub mod ts;
+pub mod tuning;
+pub mod updater;
+pub mod watermark;
+pub mod web;
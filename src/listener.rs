@@ -0,0 +1,86 @@
+use crate::config::ServerConfig;
+use axum::extract::ConnectInfo;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::Service;
+use hyper::Request;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::service::TowerToHyperService;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+/// 接受连接失败 (如文件描述符耗尽) 后的等待时间，避免空转
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// 在媒体监听端口上提供 HTTP/1.1 服务，连接参数取自 `server` 配置
+///
+/// 与 `axum::serve` 相同，每个请求带有对端地址 (`ConnectInfo<SocketAddr>`)；
+/// 连接数达到 `server.max_connections` 时暂停接受新连接，返回前不等待已建立的连接结束。
+///
+/// 只支持 HTTP/1.1：构建依赖中没有 TLS 与 HTTP/2 实现 (rustls、h2)，
+/// 不支持 HTTP/2、TLS 与每连接并发流上限，需要时由前置的反向代理终结
+pub async fn serve(listener: TcpListener, router: Router, server: &ServerConfig) {
+    let mut builder = http1::Builder::new();
+    builder
+        .timer(TokioTimer::new())
+        .keep_alive(server.keep_alive);
+    // 超时从等待请求头开始计时，长连接上即为两次请求之间的空闲时间
+    let timeout = (server.keep_alive_timeout_sec > 0)
+        .then(|| Duration::from_secs(server.keep_alive_timeout_sec));
+    builder.header_read_timeout(timeout);
+
+    let slots =
+        (server.max_connections > 0).then(|| Arc::new(Semaphore::new(server.max_connections)));
+    // 仅在进入饱和时记录一次
+    let mut saturated = false;
+    loop {
+        // 先占用名额再接受连接，超出上限的连接留在监听队列中
+        let permit = match &slots {
+            Some(slots) => Some(match slots.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    saturated = false;
+                    permit
+                }
+                Err(_) => {
+                    if !saturated {
+                        warn!(
+                            "HTTP connection limit ({}) reached, new connections wait in the backlog",
+                            server.max_connections
+                        );
+                        saturated = true;
+                    }
+                    let Ok(permit) = slots.clone().acquire_owned().await else {
+                        return;
+                    };
+                    permit
+                }
+            }),
+            None => None,
+        };
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Failed to accept HTTP connection: {}", e);
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let service = TowerToHyperService::new(router.clone());
+        let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+            req.extensions_mut().insert(ConnectInfo(peer));
+            service.call(req)
+        });
+        let conn = builder.serve_connection(TokioIo::new(stream), service);
+        tokio::spawn(async move {
+            let _permit = permit;
+            // 客户端未发送请求即断开、或空闲超时时也会返回错误
+            if let Err(e) = conn.await {
+                debug!("HTTP connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,219 @@
+use crate::clock;
+use crate::config::{AppConfig, LoadSheddingConfig};
+use crate::engine::Engine;
+use crate::http_client;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// 事件回调的超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 被卸载的流
+#[derive(Debug, Clone, Serialize)]
+pub struct ShedStream {
+    /// 卸载时间 (RFC 3339)
+    pub since: String,
+    /// 触发卸载的压力 (如 `memory 38 MB available < 64 MB`)
+    pub reason: String,
+    pub priority: i32,
+}
+
+/// 负载卸载状态
+#[derive(Debug, Default)]
+pub struct ShedState {
+    /// 被卸载的流 (Name -> 记录)，期间不自动重启也不按需启动
+    pub streams: BTreeMap<String, ShedStream>,
+    /// 最近一次卸载或恢复的时间
+    last_action: Option<Instant>,
+    /// 压力解除的起始时间
+    calm_since: Option<Instant>,
+}
+
+/// 系统压力
+enum Pressure {
+    /// 超过阈值，附带说明
+    Critical(String),
+    /// 介于阈值与解除余量之间
+    Elevated,
+    Normal,
+}
+
+/// 按阈值判断当前的内存与 CPU 压力
+fn pressure(cfg: &LoadSheddingConfig) -> Pressure {
+    let margin = cfg.resume_margin_percent as f64 / 100.0;
+    let mut elevated = false;
+
+    if cfg.min_mem_avail_mb > 0 {
+        if let Ok(mem) = sys_info::mem_info() {
+            let avail_mb = mem.avail / 1024;
+            if avail_mb < cfg.min_mem_avail_mb {
+                return Pressure::Critical(format!(
+                    "memory {} MB available < {} MB",
+                    avail_mb, cfg.min_mem_avail_mb
+                ));
+            }
+            elevated |= (avail_mb as f64) < cfg.min_mem_avail_mb as f64 * (1.0 + margin);
+        }
+    }
+
+    if cfg.max_load_per_cpu > 0.0 {
+        if let (Ok(load), Ok(cpus)) = (sys_info::loadavg(), sys_info::cpu_num()) {
+            let per_cpu = load.one / cpus.max(1) as f64;
+            if per_cpu > cfg.max_load_per_cpu {
+                return Pressure::Critical(format!(
+                    "load {:.2} per CPU > {:.2}",
+                    per_cpu, cfg.max_load_per_cpu
+                ));
+            }
+            elevated |= per_cpu > cfg.max_load_per_cpu * (1.0 - margin);
+        }
+    }
+
+    if elevated {
+        Pressure::Elevated
+    } else {
+        Pressure::Normal
+    }
+}
+
+/// 流是否因负载卸载而暂停
+pub fn is_shed(state: &AppState, name: &str) -> bool {
+    state
+        .load_shedding
+        .lock_or_recover()
+        .streams
+        .contains_key(name)
+}
+
+/// 流被卸载的原因
+pub fn shed_reason(state: &AppState, name: &str) -> Option<String> {
+    state
+        .load_shedding
+        .lock_or_recover()
+        .streams
+        .get(name)
+        .map(|s| s.reason.clone())
+}
+
+/// 选择下一个卸载的流：优先级最低者，同优先级时最近启动的先停 (受保护的流除外)
+fn pick_victim(state: &AppState, config: &AppConfig, protect: Option<i32>) -> Option<String> {
+    let streams = state.active_streams.lock_or_recover();
+    streams
+        .iter()
+        .filter_map(|(name, runtime)| {
+            let priority = config.stream(name)?.priority;
+            (protect.is_none_or(|p| priority < p)).then_some((priority, runtime.started_at, name))
+        })
+        .min_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map(|(_, _, name)| name.clone())
+}
+
+/// Supervisor 每轮调用：压力过高时停止一个优先级最低的运行中流，
+/// 压力解除并保持 `recover_sec` 后按优先级由高到低逐个放行被卸载的流
+///
+/// 每 `interval_sec` 最多卸载或恢复一个流；放行后的 auto_start 流由 Supervisor 重启，
+/// 按需启动的流等待下一个观看者
+pub async fn tick(state: &Arc<AppState>) {
+    let config = state.config();
+    let Some(cfg) = &config.load_shedding else {
+        // 关闭卸载时放行所有流
+        let released = std::mem::take(&mut state.load_shedding.lock_or_recover().streams);
+        for name in released.into_keys() {
+            info!("Stream [{}] released from load shedding (disabled).", name);
+        }
+        return;
+    };
+    let now = Instant::now();
+    let pressure = pressure(cfg);
+
+    let victim = {
+        let mut shed = state.load_shedding.lock_or_recover();
+        // 已从配置中移除的流不再保留卸载记录
+        shed.streams.retain(|name, _| config.stream(name).is_some());
+        let ready = shed
+            .last_action
+            .is_none_or(|t| now.duration_since(t) >= Duration::from_secs(cfg.interval_sec));
+        match &pressure {
+            Pressure::Critical(reason) => {
+                shed.calm_since = None;
+                if !ready {
+                    return;
+                }
+                let Some(name) = pick_victim(state, &config, cfg.protect_priority) else {
+                    return;
+                };
+                let record = ShedStream {
+                    since: clock::rfc3339(SystemTime::now()),
+                    reason: reason.clone(),
+                    priority: config.stream(&name).map_or(0, |s| s.priority),
+                };
+                shed.streams.insert(name.clone(), record.clone());
+                shed.last_action = Some(now);
+                Some((name, record))
+            }
+            Pressure::Elevated => {
+                shed.calm_since = None;
+                return;
+            }
+            Pressure::Normal => {
+                let since = *shed.calm_since.get_or_insert(now);
+                if !ready || now.duration_since(since) < Duration::from_secs(cfg.recover_sec) {
+                    return;
+                }
+                let Some(name) = shed
+                    .streams
+                    .iter()
+                    .max_by_key(|(_, s)| s.priority)
+                    .map(|(name, _)| name.clone())
+                else {
+                    return;
+                };
+                let record = shed.streams.remove(&name);
+                shed.last_action = Some(now);
+                if let Some(record) = record {
+                    info!(
+                        "Stream [{}] released from load shedding (pressure subsided).",
+                        name
+                    );
+                    emit(cfg, "stream_restored", &name, &record);
+                }
+                return;
+            }
+        }
+    };
+
+    if let Some((name, record)) = victim {
+        warn!(
+            "Load shedding: stopping stream [{}] (priority {}): {}",
+            name, record.priority, record.reason
+        );
+        if let Err(e) = Engine::stop_stream(state, &name).await {
+            warn!("Load shedding failed to stop [{}]: {}", name, e);
+        }
+        emit(cfg, "stream_shed", &name, &record);
+    }
+}
+
+/// 向 webhook 发送卸载 / 恢复事件 (后台执行，失败仅记录日志)
+fn emit(cfg: &LoadSheddingConfig, event: &'static str, name: &str, record: &ShedStream) {
+    let Some(url) = cfg.webhook.clone() else {
+        return;
+    };
+    let body = serde_json::json!({
+        "stream": name,
+        "event": event,
+        "priority": record.priority,
+        "reason": record.reason,
+        "time": clock::rfc3339(SystemTime::now()),
+    });
+    let name = name.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = http_client::send_json("POST", &url, &body, None, WEBHOOK_TIMEOUT).await {
+            warn!("Load shedding webhook failed [{}]: {}", name, e);
+        }
+    });
+}
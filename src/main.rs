@@ -1,21 +1,9 @@
-mod config;
-mod engine;
-mod state;
-mod supervisor;
-mod web;
-
-use axum::{
-    routing::{get, post},
-    Router,
-};
-use clap::Parser;
-use config::AppConfig;
-use state::AppState;
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use clap::{CommandFactory, Parser};
+use std::sync::Arc;
 use tracing::info;
+use vtx_link_core::config::AppConfig;
+use vtx_link_core::state::AppState;
+use vtx_link_core::{app, cli, listener, platform, privilege, rtsp};
 
 /// VTX Link - Edge Media Gateway
 /// 解析命令行参数，初始化服务，加载配置文件，并启动HTTP服务及后台监控
@@ -23,53 +11,74 @@ use tracing::info;
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// 配置文件路径
-    #[arg(short, long, default_value = "vtx-link.yaml")]
+    #[arg(short, long, default_value = "vtx-link.yaml", global = true)]
     config: String,
+
+    /// 以 Windows 服务方式运行 (由服务控制管理器启动，见 README)
+    #[arg(long)]
+    service: bool,
+
+    #[command(flatten)]
+    client: cli::ClientArgs,
+
+    /// 管理子命令，省略时启动网关
+    #[command(subcommand)]
+    command: Option<cli::Command>,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
+    // 解析命令行参数，获取配置文件路径
+    let args = Args::parse();
+
+    // 管理子命令连接运行中的实例，不启动网关
+    if let Some(command) = args.command {
+        return tokio::runtime::Runtime::new()?.block_on(cli::run(
+            command,
+            &args.config,
+            args.client,
+            Args::command(),
+        ));
+    }
+
     // 初始化日志系统，设置格式
     tracing_subscriber::fmt::init();
 
-    // 解析命令行参数，获取配置文件路径
-    let args = Args::parse();
+    // 以服务方式运行时先向服务控制管理器报告运行中，停止服务时关闭网关
+    if args.service {
+        platform::start_service()?;
+    }
 
-    // 加载配置文件
+    // 加载配置文件，按其中的线程配置构建运行时
     let config = AppConfig::load(&args.config)?;
     info!("VTX Link initialized. HLS Root: {}", config.server.hls_root);
+    app::runtime(&config.server)?.block_on(run(config, args.config))
+}
 
-    // 初始化全局状态，包含配置信息和活动流状态
-    let state = Arc::new(AppState {
-        config: config.clone(),
-        active_streams: Mutex::new(HashMap::new()),
-        recovery_states: Mutex::new(HashMap::new()),
-    });
-
-    // 启动后台监控程序
-    let supervisor_interval = config.server.supervisor_interval_ms;
-    tokio::spawn(supervisor::start_supervisor(
-        state.clone(),
-        supervisor_interval,
-    ));
+/// 监听端口、启动后台任务并提供 HTTP 服务，直到收到关闭请求
+async fn run(config: AppConfig, config_path: String) -> anyhow::Result<()> {
+    // 先以当前 (root) 权限监听端口并调整目录属主，再切换到配置的运行用户
+    let run_as = privilege::prepare(&config)?;
+    let listener = tokio::net::TcpListener::bind(&config.server.listen).await?;
+    let rtsp_listeners = rtsp::bind_servers(&config).await;
+    if let Some(owner) = run_as {
+        privilege::drop_to(owner)?;
+    }
 
-    // 注册HTTP路由
-    let app = Router::new()
-        .route("/", get(web::admin::index_handler)) // 首页
-        .route("/sys/status", get(web::admin::sys_status)) // 系统状态
-        .route("/streams", get(web::admin::list_streams)) // 获取流列表
-        .route("/streams/:name/start", post(web::admin::handle_start)) // 启动流
-        .route("/streams/:name/stop", post(web::admin::handle_stop)) // 停止流
-        .route(
-            "/hls/:stream_name/:file_name",
-            get(web::hls::serve_hls_file), // 获取HLS文件
-        )
-        .with_state(state.clone());
+    // 初始化全局状态，并启动 Supervisor 等后台任务
+    let server = config.server.clone();
+    let state = Arc::new(AppState::new(config, config_path.into())?);
+    app::spawn_tasks(&state, rtsp_listeners);
 
     // 启动HTTP服务，监听指定的地址和端口
-    info!("Listening on {}", config.server.listen);
-    let listener = tokio::net::TcpListener::bind(&config.server.listen).await?;
-    axum::serve(listener, app).await?;
+    info!("Listening on {}", server.listen);
+    // 不等待连接关闭 (TS 与长轮询连接可能一直打开)，收到关闭请求后立即进入清理
+    tokio::select! {
+        _ = listener::serve(listener, app::router(state.clone()), &server) => {}
+        _ = platform::shutdown_signal() => {}
+    }
 
+    // 收到关闭请求：先停止所有 FFmpeg 进程，避免留下无人管理的子进程
+    app::shutdown(&state).await;
+    platform::service_stopped();
     Ok(())
 }
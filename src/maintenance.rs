@@ -0,0 +1,125 @@
+use crate::clock;
+use crate::engine::Engine;
+use crate::runtime_state;
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// 运维禁用记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisabledStream {
+    /// 禁用时间 (RFC 3339)
+    pub since: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// 通知静默记录：静默期间流照常运行与监控，只是不发送告警通知
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mute {
+    /// 开始时间 (RFC 3339)
+    pub since: String,
+    /// 结束时间 (RFC 3339)
+    pub mute_until: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// 禁用流：停止运行中的进程，禁用期间不自动重启也不按需启动
+pub async fn disable(
+    state: &Arc<AppState>,
+    name: &str,
+    reason: Option<String>,
+) -> anyhow::Result<DisabledStream> {
+    let record = DisabledStream {
+        since: clock::rfc3339(SystemTime::now()),
+        reason,
+    };
+    let previous = state.disabled_streams.insert(name, record.clone())?;
+    if let Err(e) = runtime_state::save(state) {
+        let _ = match previous {
+            Some(previous) => state.disabled_streams.insert(name, previous),
+            None => state.disabled_streams.remove(name),
+        };
+        return Err(e);
+    }
+    Engine::stop_stream(state, name).await?;
+    info!(
+        "Stream [{}] disabled for maintenance{}",
+        name,
+        record
+            .reason
+            .as_deref()
+            .map(|r| format!(": {}", r))
+            .unwrap_or_default()
+    );
+    Ok(record)
+}
+
+/// 恢复流：清除禁用记录与崩溃计数，auto_start 流由 Supervisor 重新拉起
+pub fn enable(state: &AppState, name: &str) -> anyhow::Result<bool> {
+    let was_disabled = state.disabled_streams.remove(name)?.is_some();
+    if was_disabled {
+        state.recovery_states.lock_or_recover().remove(name);
+        runtime_state::save(state)?;
+        info!("Stream [{}] enabled", name);
+    }
+    Ok(was_disabled)
+}
+
+/// 流是否被运维禁用
+pub fn is_disabled(state: &AppState, name: &str) -> bool {
+    state.disabled_streams.contains(name)
+}
+
+/// 静默流的告警通知 (崩溃、离线等) 直到 `until` (Unix 秒数)，重复调用会替换原有的静默
+pub fn mute(
+    state: &AppState,
+    name: &str,
+    until: u64,
+    reason: Option<String>,
+) -> anyhow::Result<Mute> {
+    let record = Mute {
+        since: clock::rfc3339(SystemTime::now()),
+        mute_until: clock::rfc3339(UNIX_EPOCH + Duration::from_secs(until)),
+        reason,
+    };
+    let previous = state.stream_mutes.insert(name, record.clone())?;
+    if let Err(e) = runtime_state::save(state) {
+        let _ = match previous {
+            Some(previous) => state.stream_mutes.insert(name, previous),
+            None => state.stream_mutes.remove(name),
+        };
+        return Err(e);
+    }
+    info!(
+        "Notifications for stream [{}] muted until {}",
+        name, record.mute_until
+    );
+    Ok(record)
+}
+
+/// 提前结束静默，返回流此前是否处于静默
+pub fn unmute(state: &AppState, name: &str) -> anyhow::Result<bool> {
+    let was_muted = muted(state, name).is_some();
+    if state.stream_mutes.remove(name)?.is_some() {
+        runtime_state::save(state)?;
+    }
+    if was_muted {
+        info!("Notifications for stream [{}] unmuted", name);
+    }
+    Ok(was_muted)
+}
+
+/// 流当前生效的静默 (已过期的记录视为未静默，下次保存运行状态时清除)
+pub fn muted(state: &AppState, name: &str) -> Option<Mute> {
+    let mute = state.stream_mutes.get(name)?;
+    let until = clock::parse_rfc3339(&mute.mute_until)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    (until > now).then_some(mute)
+}
@@ -0,0 +1,205 @@
+use crate::clock;
+use crate::config::{CueFormat, StreamConfig};
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 每个流保留的标记数量上限
+const MAX_MARKERS: usize = 64;
+
+/// 广告标记类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CueKind {
+    /// 广告开始 (离开节目)
+    CueOut,
+    /// 广告结束 (回到节目)
+    CueIn,
+}
+
+/// 插入到下发播放列表中的广告标记
+#[derive(Debug, Clone, Serialize)]
+pub struct CueMarker {
+    pub id: String,
+    pub kind: CueKind,
+    /// 计划的广告时长 (秒，仅 cue_out)
+    pub duration: Option<f64>,
+    /// 调用方提供的 SCTE-35 splice_info_section (十六进制)，DATERANGE 格式下原样传递
+    pub scte35: Option<String>,
+    /// 插入时间 (RFC 3339)
+    pub created_at: String,
+    /// 标记所在切片的媒体序号，在下一次下发播放列表时确定为其后的第一个新切片
+    pub sequence: Option<u64>,
+    /// 被本标记结束的 cue_out (仅 cue_in)
+    pub closes: Option<String>,
+    /// 被结束的 cue_out 的开始时间 (DATERANGE 需要)
+    #[serde(skip)]
+    closes_started_at: Option<String>,
+}
+
+/// 为运行中的流登记一个广告标记
+pub fn insert(
+    state: &AppState,
+    name: &str,
+    kind: CueKind,
+    duration: Option<f64>,
+    id: Option<String>,
+    scte35: Option<String>,
+) -> anyhow::Result<CueMarker> {
+    if duration.is_some_and(|d| !(d > 0.0 && d.is_finite())) {
+        anyhow::bail!("Marker duration must be a positive number");
+    }
+    if kind == CueKind::CueIn && duration.is_some() {
+        anyhow::bail!("Only cue_out markers take a duration");
+    }
+    if let Some(hex) = &scte35 {
+        let digits = hex
+            .strip_prefix("0x")
+            .or_else(|| hex.strip_prefix("0X"))
+            .unwrap_or_default();
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("scte35 must be a hexadecimal string starting with 0x");
+        }
+    }
+    if let Some(id) = &id {
+        if id.is_empty() || id.contains(['"', '\n', '\r']) {
+            anyhow::bail!("Invalid marker id");
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut map = state.cue_markers.lock_or_recover();
+    let markers = map.entry(name.to_string()).or_default();
+    let id = id.unwrap_or_else(|| {
+        let millis = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        format!("vtx-{}", millis)
+    });
+    if markers.iter().any(|m| m.id == id) {
+        anyhow::bail!("Marker [{}] already exists", id);
+    }
+
+    // cue_in 结束最近一个尚未结束的 cue_out
+    let open = (kind == CueKind::CueIn)
+        .then(|| {
+            markers.iter().rev().find(|m| {
+                m.kind == CueKind::CueOut
+                    && !markers.iter().any(|c| c.closes.as_deref() == Some(&m.id))
+            })
+        })
+        .flatten();
+    let marker = CueMarker {
+        closes: open.map(|m| m.id.clone()),
+        closes_started_at: open.map(|m| m.created_at.clone()),
+        id,
+        kind,
+        duration,
+        scte35,
+        created_at: clock::rfc3339(now),
+        sequence: None,
+    };
+
+    if markers.len() == MAX_MARKERS {
+        markers.remove(0);
+    }
+    markers.push(marker.clone());
+    Ok(marker)
+}
+
+/// 流重启后媒体序号重新开始，丢弃已登记的标记
+pub fn clear(state: &AppState, name: &str) {
+    state.cue_markers.lock_or_recover().remove(name);
+}
+
+/// 将已登记的标记插入媒体播放列表 (位于对应切片的 `#EXTINF` 之前)
+///
+/// 尚未定位的标记绑定到本播放列表之后的第一个新切片；已滑出窗口的标记被丢弃
+pub fn apply(state: &AppState, cfg: &StreamConfig, text: &str) -> String {
+    if !text.contains("#EXTINF") {
+        return text.to_string();
+    }
+    let mut map = state.cue_markers.lock_or_recover();
+    let Some(markers) = map.get_mut(&cfg.name) else {
+        return text.to_string();
+    };
+
+    let first = media_sequence(text);
+    let count = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .count() as u64;
+    for marker in markers.iter_mut().filter(|m| m.sequence.is_none()) {
+        marker.sequence = Some(first + count);
+    }
+    markers.retain(|m| m.sequence.is_some_and(|s| s >= first));
+    if markers.is_empty() {
+        map.remove(&cfg.name);
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut sequence = first;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("#EXTINF") {
+            for marker in markers.iter().filter(|m| m.sequence == Some(sequence)) {
+                out.push_str(&tag(marker, cfg.cue_format));
+                out.push('\n');
+            }
+        } else if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            sequence += 1;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn media_sequence(text: &str) -> u64 {
+    text.lines()
+        .find_map(|l| l.trim().strip_prefix("#EXT-X-MEDIA-SEQUENCE:"))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn tag(marker: &CueMarker, format: CueFormat) -> String {
+    match (format, marker.kind) {
+        (CueFormat::CueOut, CueKind::CueOut) => match marker.duration {
+            Some(d) => format!("#EXT-X-CUE-OUT:DURATION={:.3}", d),
+            None => "#EXT-X-CUE-OUT".to_string(),
+        },
+        (CueFormat::CueOut, CueKind::CueIn) => "#EXT-X-CUE-IN".to_string(),
+        (CueFormat::DateRange, CueKind::CueOut) => {
+            let mut tag = format!(
+                "#EXT-X-DATERANGE:ID=\"{}\",START-DATE=\"{}\"",
+                marker.id, marker.created_at
+            );
+            if let Some(d) = marker.duration {
+                tag.push_str(&format!(",PLANNED-DURATION={:.3}", d));
+            }
+            if let Some(hex) = &marker.scte35 {
+                tag.push_str(&format!(",SCTE35-OUT={}", hex));
+            }
+            tag
+        }
+        (CueFormat::DateRange, CueKind::CueIn) => {
+            // 结束对应的 cue_out；没有时以本标记自身作为一个瞬时区间
+            let id = marker.closes.as_deref().unwrap_or(&marker.id);
+            let start = marker
+                .closes_started_at
+                .as_deref()
+                .unwrap_or(&marker.created_at);
+            let mut tag = format!(
+                "#EXT-X-DATERANGE:ID=\"{}\",START-DATE=\"{}\",END-DATE=\"{}\"",
+                id, start, marker.created_at
+            );
+            if let Some(hex) = &marker.scte35 {
+                tag.push_str(&format!(",SCTE35-IN={}", hex));
+            }
+            tag
+        }
+    }
+}
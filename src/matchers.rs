@@ -0,0 +1,144 @@
+use crate::clock;
+use crate::config::{AppConfig, MatchAction, StderrMatcher, StreamConfig};
+use crate::engine::Engine;
+use crate::http_client;
+use crate::maintenance;
+use crate::pattern::Pattern;
+use crate::state::{AppState, LockExt};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+/// 告警回调超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 运行中的日志匹配规则，每个 FFmpeg 进程持有一份独立的计数
+pub struct ActiveMatcher {
+    cfg: StderrMatcher,
+    pattern: Pattern,
+    /// 窗口内的匹配时间
+    hits: VecDeque<Instant>,
+    last_fired: Option<Instant>,
+}
+
+/// 编译流适用的全部规则 (全局规则在前)
+pub fn compile(config: &AppConfig, cfg: &StreamConfig) -> Vec<ActiveMatcher> {
+    config
+        .stderr_matchers
+        .iter()
+        .chain(&cfg.stderr_matchers)
+        .filter_map(|m| {
+            // 配置已通过校验，编译失败只可能来自未校验的配置
+            let pattern = Pattern::new(&m.pattern)
+                .map_err(|e| warn!("Skipping stderr matcher [{}]: {}", cfg.name, e))
+                .ok()?;
+            Some(ActiveMatcher {
+                cfg: m.clone(),
+                pattern,
+                hits: VecDeque::new(),
+                last_fired: None,
+            })
+        })
+        .collect()
+}
+
+/// 检查一行日志，达到触发条件的规则执行对应动作
+pub fn check(state: &Arc<AppState>, name: &str, matchers: &mut [ActiveMatcher], line: &str) {
+    let now = Instant::now();
+    for m in matchers.iter_mut() {
+        if !m.pattern.is_match(line) {
+            continue;
+        }
+        let window = Duration::from_secs(m.cfg.window_sec);
+        m.hits.push_back(now);
+        while m
+            .hits
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            m.hits.pop_front();
+        }
+
+        let cooling = m
+            .last_fired
+            .is_some_and(|t| now.duration_since(t) < Duration::from_secs(m.cfg.cooldown_sec));
+        if cooling || m.hits.len() < m.cfg.count as usize {
+            continue;
+        }
+        m.last_fired = Some(now);
+        m.hits.clear();
+        fire(state, name, &m.cfg, line);
+    }
+}
+
+fn fire(state: &Arc<AppState>, name: &str, cfg: &StderrMatcher, line: &str) {
+    match cfg.action {
+        MatchAction::Restart | MatchAction::SwitchSource => {
+            info!(
+                "Stream [{}] matched stderr rule {:?} ({:?}): {}",
+                name, cfg.pattern, cfg.action, line
+            );
+            let switch = cfg.action == MatchAction::SwitchSource;
+            tokio::spawn(restart(state.clone(), name.to_string(), switch));
+        }
+        MatchAction::MarkDegraded => {
+            warn!(
+                "Stream [{}] marked degraded by stderr rule {:?}: {}",
+                name, cfg.pattern, line
+            );
+            if let Some(running) = state.active_streams.lock_or_recover().get_mut(name) {
+                running.degraded_until =
+                    Some(Instant::now() + Duration::from_secs(cfg.cooldown_sec));
+            }
+        }
+        MatchAction::Notify => {
+            warn!(
+                "Stream [{}] matched stderr rule {:?}: {}",
+                name, cfg.pattern, line
+            );
+            if let Some(url) = cfg
+                .webhook
+                .clone()
+                .filter(|_| maintenance::muted(state, name).is_none())
+            {
+                let body = serde_json::json!({
+                    "stream": name,
+                    "event": "stderr_match",
+                    "pattern": cfg.pattern,
+                    "line": line,
+                    "time": clock::rfc3339(SystemTime::now()),
+                });
+                let name = name.to_string();
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        http_client::send_json("POST", &url, &body, None, WEBHOOK_TIMEOUT).await
+                    {
+                        warn!("Stderr matcher webhook failed [{}]: {}", name, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// 重启流，`switch` 时先切换到下一个源 (无备用源时仅重启)
+async fn restart(state: Arc<AppState>, name: String, switch: bool) {
+    let has_fallback = state
+        .config()
+        .stream(&name)
+        .is_some_and(|c| !c.fallback_sources.is_empty());
+    if switch && has_fallback {
+        state
+            .recovery_states
+            .lock_or_recover()
+            .entry(name.clone())
+            .or_default()
+            .source_index += 1;
+    }
+
+    let _ = Engine::stop_stream(&state, &name).await;
+    if let Err(e) = Engine::start_stream(&state, &name).await {
+        error!("Restart failed [{}]: {}", name, e);
+    }
+}
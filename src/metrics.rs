@@ -0,0 +1,378 @@
+use crate::geoip;
+use crate::gpu;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::fmt::Write;
+use std::time::Duration;
+use tracing::info;
+
+/// 启动耗时直方图的桶上界 (毫秒)
+const BUCKETS_MS: [u64; 9] = [100, 250, 500, 1000, 2000, 3000, 5000, 10000, 20000];
+
+/// 累积直方图 (Prometheus 语义)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Histogram {
+    /// 各桶 (含之前所有桶) 的样本数，与 BUCKETS_MS 一一对应
+    buckets: [u64; BUCKETS_MS.len()],
+    count: u64,
+    sum_ms: u64,
+    /// 最近一次样本 (毫秒)
+    last_ms: Option<u64>,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let ms = value.as_millis() as u64;
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS_MS) {
+            if ms <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum_ms += ms;
+        self.last_ms = Some(ms);
+    }
+
+    fn render(&self, out: &mut String, metric: &str, stream: &str) {
+        for (count, le) in self.buckets.iter().zip(BUCKETS_MS) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{stream=\"{}\",le=\"{}\"}} {}",
+                metric,
+                stream,
+                le as f64 / 1000.0,
+                count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{stream=\"{}\",le=\"+Inf\"}} {}",
+            metric, stream, self.count
+        );
+        let _ = writeln!(
+            out,
+            "{}_sum{{stream=\"{}\"}} {}",
+            metric,
+            stream,
+            self.sum_ms as f64 / 1000.0
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{stream=\"{}\"}} {}",
+            metric, stream, self.count
+        );
+    }
+}
+
+/// 单个流的冷启动耗时统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StartupMetrics {
+    /// 从启动请求到首个切片写出 (首次生成播放列表)
+    pub first_segment: Histogram,
+    /// 从启动请求到首次向观看者返回播放列表
+    pub first_playlist: Histogram,
+}
+
+/// 启动阶段的里程碑
+#[derive(Debug, Clone, Copy)]
+pub enum Milestone {
+    FirstSegment,
+    FirstPlaylist,
+}
+
+/// 记录运行中的流到达某个里程碑的耗时，每次启动只记录一次
+pub fn record(state: &AppState, name: &str, milestone: Milestone) {
+    let elapsed = {
+        let mut streams = state.active_streams.lock_or_recover();
+        let Some(running) = streams.get_mut(name) else {
+            return;
+        };
+        let slot = match milestone {
+            Milestone::FirstSegment => &mut running.first_segment,
+            Milestone::FirstPlaylist => &mut running.first_playlist,
+        };
+        if slot.is_some() {
+            return;
+        }
+        let elapsed = running.requested_at.elapsed();
+        *slot = Some(elapsed);
+        elapsed
+    };
+
+    let mut metrics = state.startup_metrics.lock_or_recover();
+    let entry = metrics.entry(name.to_string()).or_default();
+    match milestone {
+        Milestone::FirstSegment => {
+            info!(
+                "Stream [{}] first segment after {} ms",
+                name,
+                elapsed.as_millis()
+            );
+            entry.first_segment.observe(elapsed);
+        }
+        Milestone::FirstPlaylist => entry.first_playlist.observe(elapsed),
+    }
+}
+
+/// 流的启动耗时统计快照
+pub fn startup(state: &AppState, name: &str) -> StartupMetrics {
+    state
+        .startup_metrics
+        .lock_or_recover()
+        .get(name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// 以 Prometheus 文本格式导出指标，仅包含 `visible` 返回 true 的流
+pub fn render(state: &AppState, visible: impl Fn(Option<&str>) -> bool) -> String {
+    let statuses: Vec<_> = state
+        .stream_statuses()
+        .into_iter()
+        .filter(|s| visible(s.tenant.as_deref()))
+        .collect();
+    let metrics = state.startup_metrics.lock_or_recover().clone();
+    let mut out = String::new();
+
+    out.push_str(
+        "# HELP vtx_stream_up Whether the stream process is running (including standby).\n",
+    );
+    out.push_str("# TYPE vtx_stream_up gauge\n");
+    for s in &statuses {
+        let up = matches!(s.status, "running" | "standby" | "degraded") as u8;
+        let _ = writeln!(out, "vtx_stream_up{{stream=\"{}\"}} {}", s.name, up);
+    }
+
+    out.push_str("# HELP vtx_stream_viewers Active viewer sessions.\n");
+    out.push_str("# TYPE vtx_stream_viewers gauge\n");
+    for s in &statuses {
+        let _ = writeln!(
+            out,
+            "vtx_stream_viewers{{stream=\"{}\"}} {}",
+            s.name, s.viewers
+        );
+    }
+
+    if state.config().server.geoip_db.is_some() {
+        out.push_str(
+            "# HELP vtx_stream_viewers_by_country Active viewer sessions by GeoIP country.\n",
+        );
+        out.push_str("# TYPE vtx_stream_viewers_by_country gauge\n");
+        for s in &statuses {
+            for (country, viewers) in geoip::viewer_countries(state, &s.name).unwrap_or_default() {
+                let _ = writeln!(
+                    out,
+                    "vtx_stream_viewers_by_country{{stream=\"{}\",country=\"{}\"}} {}",
+                    s.name, country, viewers
+                );
+            }
+        }
+    }
+
+    out.push_str("# HELP vtx_stream_crashes Consecutive crashes of the stream process.\n");
+    out.push_str("# TYPE vtx_stream_crashes gauge\n");
+    for s in &statuses {
+        let _ = writeln!(
+            out,
+            "vtx_stream_crashes{{stream=\"{}\"}} {}",
+            s.name, s.crash_count
+        );
+    }
+
+    out.push_str(
+        "# HELP vtx_process_resident_memory_bytes Resident memory of the FFmpeg process.\n",
+    );
+    out.push_str("# TYPE vtx_process_resident_memory_bytes gauge\n");
+    for s in &statuses {
+        if let Some(p) = &s.process {
+            let _ = writeln!(
+                out,
+                "vtx_process_resident_memory_bytes{{stream=\"{}\",pid=\"{}\"}} {}",
+                s.name,
+                p.pid,
+                p.rss_kb * 1024
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_process_cpu_percent CPU usage of the FFmpeg process (100 = one core).\n",
+    );
+    out.push_str("# TYPE vtx_process_cpu_percent gauge\n");
+    for s in &statuses {
+        if let Some(p) = &s.process {
+            let _ = writeln!(
+                out,
+                "vtx_process_cpu_percent{{stream=\"{}\",pid=\"{}\"}} {}",
+                s.name, p.pid, p.cpu_percent
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_stream_av_drift_ms Change of the audio/video offset since the process started.\n",
+    );
+    out.push_str("# TYPE vtx_stream_av_drift_ms gauge\n");
+    for s in &statuses {
+        if let Some(drift) = s.sync.and_then(|sync| sync.drift_ms) {
+            let _ = writeln!(
+                out,
+                "vtx_stream_av_drift_ms{{stream=\"{}\"}} {}",
+                s.name, drift
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_stream_timestamp_discontinuities_total Timestamp discontinuities of the running process.\n",
+    );
+    out.push_str("# TYPE vtx_stream_timestamp_discontinuities_total counter\n");
+    for s in &statuses {
+        if let Some(sync) = &s.sync {
+            let _ = writeln!(
+                out,
+                "vtx_stream_timestamp_discontinuities_total{{stream=\"{}\"}} {}",
+                s.name, sync.discontinuities
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_stream_live_edge_age_ms Age of the newest segment's end when the playlist was last served.\n",
+    );
+    out.push_str("# TYPE vtx_stream_live_edge_age_ms gauge\n");
+    for s in &statuses {
+        if let Some(age) = s.latency.and_then(|l| l.live_edge_age_ms) {
+            let _ = writeln!(
+                out,
+                "vtx_stream_live_edge_age_ms{{stream=\"{}\"}} {}",
+                s.name, age
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_stream_latency_estimate_ms Estimated glass-to-player latency for a player starting at the playlist's hold-back.\n",
+    );
+    out.push_str("# TYPE vtx_stream_latency_estimate_ms gauge\n");
+    for s in &statuses {
+        if let Some(estimate) = s.latency.and_then(|l| l.estimated_ms) {
+            let _ = writeln!(
+                out,
+                "vtx_stream_latency_estimate_ms{{stream=\"{}\"}} {}",
+                s.name, estimate
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_stream_quality_score Share of recent HLS segments without duration, discontinuity or sequence anomalies (0-100).\n",
+    );
+    out.push_str("# TYPE vtx_stream_quality_score gauge\n");
+    for s in &statuses {
+        if let Some(score) = s.quality.as_ref().and_then(|q| q.score) {
+            let _ = writeln!(
+                out,
+                "vtx_stream_quality_score{{stream=\"{}\"}} {}",
+                s.name, score
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP vtx_stream_quality_anomalies_total HLS segment anomalies of the running process.\n",
+    );
+    out.push_str("# TYPE vtx_stream_quality_anomalies_total counter\n");
+    for s in &statuses {
+        if let Some(q) = &s.quality {
+            for (kind, count) in [
+                ("short_segment", q.short_segments),
+                ("long_segment", q.long_segments),
+                ("discontinuity", q.discontinuities),
+                ("sequence_jump", q.sequence_jumps),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "vtx_stream_quality_anomalies_total{{stream=\"{}\",kind=\"{}\"}} {}",
+                    s.name, kind, count
+                );
+            }
+        }
+    }
+
+    if let Some(check) = state.clock_check.lock_or_recover().as_ref() {
+        out.push_str(
+            "# HELP vtx_clock_offset_seconds Offset of the system clock from the NTP server.\n",
+        );
+        out.push_str("# TYPE vtx_clock_offset_seconds gauge\n");
+        let _ = writeln!(
+            out,
+            "vtx_clock_offset_seconds{{server=\"{}\"}} {}",
+            check.server,
+            check.offset_ms as f64 / 1000.0
+        );
+    }
+
+    let transfers = state.transfers.snapshot();
+    out.push_str("# HELP vtx_segment_transfers_total Segment transfers by outcome.\n");
+    out.push_str("# TYPE vtx_segment_transfers_total counter\n");
+    for (outcome, count) in [
+        ("completed", transfers.completed),
+        ("aborted", transfers.aborted),
+        ("stalled", transfers.stalled),
+    ] {
+        let _ = writeln!(
+            out,
+            "vtx_segment_transfers_total{{outcome=\"{}\"}} {}",
+            outcome, count
+        );
+    }
+
+    out.push_str(
+        "# HELP vtx_hw_encode_sessions Hardware encode sessions used by running streams.\n",
+    );
+    out.push_str("# TYPE vtx_hw_encode_sessions gauge\n");
+    let _ = writeln!(
+        out,
+        "vtx_hw_encode_sessions {}",
+        gpu::active_sessions(state)
+    );
+
+    let devices = state.gpu_devices.lock_or_recover().clone();
+    if !devices.is_empty() {
+        out.push_str("# HELP vtx_gpu_utilization_percent Utilization of the GPU/VPU.\n");
+        out.push_str("# TYPE vtx_gpu_utilization_percent gauge\n");
+        for d in &devices {
+            if let Some(percent) = d.utilization_percent {
+                let _ = writeln!(
+                    out,
+                    "vtx_gpu_utilization_percent{{device=\"{}\"}} {}",
+                    d.id, percent
+                );
+            }
+        }
+    }
+
+    for (metric, help, pick) in [
+        (
+            "vtx_startup_first_segment_seconds",
+            "Time from start request to the first segment being written.",
+            (|m: &StartupMetrics| &m.first_segment) as fn(&StartupMetrics) -> &Histogram,
+        ),
+        (
+            "vtx_startup_first_playlist_seconds",
+            "Time from start request to the first playlist being served.",
+            |m: &StartupMetrics| &m.first_playlist,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {} {}", metric, help);
+        let _ = writeln!(out, "# TYPE {} histogram", metric);
+        for s in &statuses {
+            if let Some(m) = metrics.get(&s.name) {
+                pick(m).render(&mut out, metric, &s.name);
+            }
+        }
+    }
+
+    out
+}
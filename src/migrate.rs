@@ -0,0 +1,191 @@
+use crate::config::AppConfig;
+use crate::dependency;
+use crate::engine::{Engine, Launched};
+use crate::platform;
+use crate::snapshot;
+use crate::state::{AppState, LockExt};
+use crate::templates;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// 检查暂存输出的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 迁移结果
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrateResult {
+    /// 配置是否发生变化；无变化时不写配置文件、不启动新进程
+    pub changed: bool,
+    /// 是否由新进程接替了运行中的旧进程 (流未运行时只更新配置)
+    pub switched: bool,
+    /// 切换后流的输出目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+    /// 新进程从启动到产出有效播放列表的耗时 (毫秒)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ready_ms: Option<u64>,
+}
+
+/// 与输出目录交替使用的暂存目录 (`{dir}.next`)
+pub fn staging_dir(dir: &Path) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".next");
+    dir.with_file_name(name)
+}
+
+/// 将覆盖字段合并到流配置并无中断地应用
+///
+/// 流在运行时，按新配置启动第二个 FFmpeg 写入暂存目录，待其产出引用了已存在切片的播放列表后
+/// 写回配置文件，将对外服务的目录切换到暂存目录，再结束旧进程并删除旧目录；
+/// 新进程在 `timeout` 内未就绪或提前退出时丢弃暂存输出，旧进程与配置均保持不变。
+/// 流未运行 (或为代理中继) 时与声明式更新相同，只写回配置
+pub async fn apply_profile(
+    state: &Arc<AppState>,
+    name: &str,
+    overrides: &Value,
+    timeout: Duration,
+) -> anyhow::Result<MigrateResult> {
+    let _guard = state.config_lock.lock().await;
+    let current = state.config();
+    let cfg = current
+        .stream(name)
+        .ok_or_else(|| anyhow::anyhow!("Stream [{}] not found", name))?;
+    let stream = templates::update_stream(cfg, overrides)?;
+    if &stream == cfg {
+        return Ok(MigrateResult {
+            changed: false,
+            switched: false,
+            output_dir: None,
+            ready_ms: None,
+        });
+    }
+    let mut next: AppConfig = (*current).clone();
+    if let Some(slot) = next.streams.iter_mut().find(|s| s.name == name) {
+        *slot = stream.clone();
+    }
+    next.validate()?;
+
+    let running = state.active_streams.lock_or_recover().contains_key(name);
+    if !running || stream.is_proxied() || cfg.is_proxied() {
+        snapshot::apply(state, &state.config_path, next).await?;
+        info!("Stream [{}] profile updated (not running)", name);
+        return Ok(MigrateResult {
+            changed: true,
+            switched: false,
+            output_dir: None,
+            ready_ms: None,
+        });
+    }
+
+    // 在当前目录与暂存目录之间交替，新进程不触碰正在对外服务的目录
+    let active_dir = Engine::output_dir(state, cfg);
+    let base_dir = next.output_dir(&stream);
+    let new_dir = if active_dir == base_dir {
+        staging_dir(&base_dir)
+    } else {
+        base_dir.clone()
+    };
+
+    let started = Instant::now();
+    let mut launched = Engine::launch(state, &next, &stream, &new_dir).await?;
+    if let Err(e) = wait_ready(&mut launched, &new_dir, timeout).await {
+        discard(launched, &new_dir).await;
+        anyhow::bail!("Stream [{}] new profile did not become ready: {}", name, e);
+    }
+    let ready_ms = started.elapsed().as_millis() as u64;
+    if let Err(e) = snapshot::persist(&state.config_path, &next).await {
+        discard(launched, &new_dir).await;
+        return Err(e);
+    }
+
+    // 先切换目录再替换配置，请求在任何时刻都能读到完整的输出
+    {
+        let mut dirs = state.output_dirs.lock_or_recover();
+        if new_dir == base_dir {
+            dirs.remove(name);
+        } else {
+            dirs.insert(name.to_string(), new_dir.clone());
+        }
+    }
+    state.replace_config(next);
+    Engine::serve_output(state, &stream, &new_dir);
+    let old = {
+        let mut streams = state.active_streams.lock_or_recover();
+        let old = streams.remove(name);
+        let mut runtime = launched.into_runtime(started);
+        if let Some(old) = &old {
+            // 观看状态随流保留，空闲回收与热备不因迁移重新计时
+            runtime.last_accessed = old.last_accessed;
+            runtime.standby_since = old.standby_since;
+        }
+        streams.insert(name.to_string(), runtime);
+        old
+    };
+    if let Some(mut old) = old {
+        platform::terminate(&mut old.process).await;
+        drop(old.group.take());
+    }
+    if active_dir != new_dir {
+        let _ = tokio::fs::remove_dir_all(&active_dir).await;
+    }
+
+    // 下游流读取的是旧目录，随之重启
+    tokio::spawn(dependency::restart_dependents(state, name));
+    info!(
+        "Stream [{}] switched to new profile in {} ms. HLS Output: {:?}",
+        name, ready_ms, new_dir
+    );
+    Ok(MigrateResult {
+        changed: true,
+        switched: true,
+        output_dir: Some(new_dir),
+        ready_ms: Some(ready_ms),
+    })
+}
+
+/// 等待新进程写出有效的主播放列表
+async fn wait_ready(launched: &mut Launched, dir: &Path, timeout: Duration) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = launched.child.try_wait()? {
+            anyhow::bail!("FFmpeg exited ({})", status);
+        }
+        if playlist_ready(dir).await {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("no playlist within {} s", timeout.as_secs());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// 输出目录中的主播放列表是否有效：至少一个切片，且最新的切片文件已存在
+pub async fn playlist_ready(dir: &Path) -> bool {
+    let Ok(text) = tokio::fs::read_to_string(dir.join("index.m3u8")).await else {
+        return false;
+    };
+    let newest = text
+        .lines()
+        .map(str::trim)
+        .rfind(|l| !l.is_empty() && !l.starts_with('#'));
+    match newest {
+        Some(segment) if text.contains("#EXTINF:") => tokio::fs::try_exists(dir.join(segment))
+            .await
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// 结束未被采用的新进程并删除暂存输出
+async fn discard(mut launched: Launched, dir: &Path) {
+    platform::terminate(&mut launched.child).await;
+    drop(launched.group.take());
+    if let Err(e) = tokio::fs::remove_dir_all(dir).await {
+        warn!("Failed to remove staging directory {:?}: {}", dir, e);
+    }
+}
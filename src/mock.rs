@@ -0,0 +1,319 @@
+use crate::clock;
+use crate::config::MockScript;
+use clap::Args;
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::time::Instant;
+
+/// 未指定 `-hls_time` 时的切片时长 (秒，同 FFmpeg)
+const DEFAULT_SEGMENT_SEC: u64 = 2;
+
+/// 未指定 `-hls_list_size` 时播放列表的切片数 (同 FFmpeg)
+const DEFAULT_LIST_SIZE: usize = 5;
+
+/// 移出播放列表后仍保留在磁盘上的切片数，正在下载旧列表的观看者不会遇到 404
+const DELETE_THRESHOLD: usize = 1;
+
+/// 每个合成切片包含的 TS 包数
+const PACKETS_PER_SEGMENT: usize = 64;
+
+/// 模拟进程的命令行参数 (由 `command` 生成，`--` 之后为原本传给 FFmpeg 的参数)
+#[derive(Args, Debug)]
+pub struct MockArgs {
+    #[arg(long, default_value_t = 0)]
+    startup_delay_ms: u64,
+    #[arg(long)]
+    crash_after_sec: Option<u64>,
+    #[arg(long, default_value_t = 1, allow_negative_numbers = true)]
+    exit_code: i32,
+    #[arg(long, allow_hyphen_values = true)]
+    error: Option<String>,
+    #[arg(long)]
+    stall_after_sec: Option<u64>,
+    #[arg(last = true)]
+    ffmpeg_args: Vec<String>,
+}
+
+/// 启动模拟进程的命令：网关自身的可执行文件加隐藏的 `mock-engine` 子命令，
+/// 调用方随后追加的 FFmpeg 参数位于 `--` 之后 (嵌入本库的程序需自行将该子命令交给 `cli::run`)
+pub fn command(script: &MockScript) -> anyhow::Result<Command> {
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.arg("mock-engine")
+        .arg(format!("--startup-delay-ms={}", script.startup_delay_ms));
+    if let Some(sec) = script.crash_after_sec {
+        cmd.arg(format!("--crash-after-sec={}", sec));
+    }
+    if let Some(code) = script.exit_code {
+        cmd.arg(format!("--exit-code={}", code));
+    }
+    if let Some(error) = &script.error {
+        cmd.arg(format!("--error={}", error));
+    }
+    if let Some(sec) = script.stall_after_sec {
+        cmd.arg(format!("--stall-after-sec={}", sec));
+    }
+    cmd.arg("--");
+    Ok(cmd)
+}
+
+/// 一路 HLS 输出
+struct Output {
+    playlist: PathBuf,
+    /// 切片路径模板 (`%d` 或 `%0Nd` 替换为序号)
+    pattern: String,
+    /// `-strftime 1`：模板先按时间展开
+    strftime: bool,
+    duration: u64,
+    /// 0 表示保留全部切片
+    list_size: usize,
+    date_time: bool,
+    sequence: u64,
+    /// 磁盘上的切片 (序号, 文件名, 写出时间)，由旧到新
+    segments: VecDeque<(u64, String, SystemTime)>,
+}
+
+/// 从 FFmpeg 参数中找出 HLS 输出：每个以 `.m3u8` 结尾且不是 `-i` 参数值的路径为一路输出，
+/// 之前出现的 `-hls_*` 选项作用于该输出 (与 FFmpeg 的按输出生效一致)
+fn parse_outputs(args: &[String]) -> Vec<Output> {
+    let mut outputs = Vec::new();
+    let mut duration = DEFAULT_SEGMENT_SEC;
+    let mut list_size = DEFAULT_LIST_SIZE;
+    let mut pattern = None;
+    let mut strftime = false;
+    let mut date_time = false;
+    for (i, arg) in args.iter().enumerate() {
+        let value = args.get(i + 1);
+        match arg.as_str() {
+            "-hls_time" => {
+                duration = value
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .map_or(DEFAULT_SEGMENT_SEC, |v| (v.ceil() as u64).max(1));
+            }
+            "-hls_list_size" => {
+                list_size = value
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_LIST_SIZE);
+            }
+            "-hls_segment_filename" => pattern = value.cloned(),
+            "-strftime" => strftime = value.is_some_and(|v| v == "1"),
+            "-hls_flags" => {
+                date_time = value.is_some_and(|v| v.split('+').any(|f| f == "program_date_time"));
+            }
+            _ if arg.ends_with(".m3u8") && (i == 0 || !args[i - 1].starts_with('-')) => {
+                let playlist = PathBuf::from(arg);
+                let pattern = pattern.take().unwrap_or_else(|| {
+                    let stem = arg.trim_end_matches(".m3u8");
+                    format!("{}%d.ts", stem)
+                });
+                outputs.push(Output {
+                    playlist,
+                    pattern,
+                    strftime,
+                    duration,
+                    list_size,
+                    date_time,
+                    sequence: 0,
+                    segments: VecDeque::new(),
+                });
+                duration = DEFAULT_SEGMENT_SEC;
+                list_size = DEFAULT_LIST_SIZE;
+                strftime = false;
+                date_time = false;
+            }
+            _ => {}
+        }
+    }
+    outputs
+}
+
+impl Output {
+    /// 写出下一个切片并更新播放列表
+    fn write_segment(&mut self, data: &[u8]) -> io::Result<()> {
+        let path = PathBuf::from(segment_path(
+            &self.pattern,
+            self.strftime,
+            self.sequence,
+            SystemTime::now(),
+        ));
+        write_atomic(&path, data)?;
+        eprintln!("[hls @ mock] Opening '{}' for writing", path.display());
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.segments
+            .push_back((self.sequence, name, SystemTime::now()));
+        self.sequence += 1;
+        if self.list_size > 0 {
+            while self.segments.len() > self.list_size + DELETE_THRESHOLD {
+                if let Some((_, old, _)) = self.segments.pop_front() {
+                    let _ = std::fs::remove_file(path.with_file_name(old));
+                }
+            }
+        }
+
+        let listed = match self.list_size {
+            0 => 0,
+            n => self.segments.len().saturating_sub(n),
+        };
+        let mut playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            self.duration,
+            self.segments.get(listed).map_or(0, |s| s.0)
+        );
+        for (_, name, written) in self.segments.iter().skip(listed) {
+            if self.date_time {
+                playlist.push_str(&format!(
+                    "#EXT-X-PROGRAM-DATE-TIME:{}\n",
+                    clock::rfc3339_millis(*written)
+                ));
+            }
+            playlist.push_str(&format!("#EXTINF:{}.000000,\n{}\n", self.duration, name));
+        }
+        write_atomic(&self.playlist, playlist.as_bytes())
+    }
+}
+
+/// 展开切片路径：strftime 模式下先展开时间字段 (按 UTC，FFmpeg 使用本地时间)，
+/// 之后 `%d` / `%0Nd` 替换为序号 (对应 FFmpeg 的 `second_level_segment_index`)
+fn segment_path(pattern: &str, strftime: bool, sequence: u64, now: SystemTime) -> String {
+    let pattern = if strftime {
+        strftime_utc(pattern, now)
+    } else {
+        pattern.to_string()
+    };
+    let mut out = String::new();
+    let mut rest = pattern.as_str();
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        let spec = &rest[at + 1..];
+        let digits = spec.len() - spec.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if spec[digits..].starts_with('d') {
+            let width = spec[..digits].parse::<usize>().unwrap_or(0);
+            out.push_str(&format!("{:0width$}", sequence, width = width));
+            rest = &spec[digits + 1..];
+        } else {
+            out.push('%');
+            rest = spec;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 只支持 `%Y %m %d %H %M %S %T %s %%`
+fn strftime_utc(pattern: &str, now: SystemTime) -> String {
+    // 2024-01-31T08:00:00Z
+    let stamp = clock::rfc3339(now);
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&stamp[0..4]),
+            Some('m') => out.push_str(&stamp[5..7]),
+            Some('d') => out.push_str(&stamp[8..10]),
+            Some('H') => out.push_str(&stamp[11..13]),
+            Some('M') => out.push_str(&stamp[14..16]),
+            Some('S') => out.push_str(&stamp[17..19]),
+            Some('T') => out.push_str(&stamp[11..19]),
+            Some('s') => out.push_str(
+                &now.duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+                    .to_string(),
+            ),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// 先写临时文件再重命名，读取方不会看到写了一半的文件
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// 合成切片：只含空包 (PID 0x1FFF) 的 MPEG-TS，大小固定，不可播放
+fn segment_bytes() -> Vec<u8> {
+    let mut packet = [0xFFu8; 188];
+    packet[..4].copy_from_slice(&[0x47, 0x1F, 0xFF, 0x10]);
+    packet.repeat(PACKETS_PER_SEGMENT)
+}
+
+/// 模拟进程主循环：按切片时长写出切片，按脚本卡住或退出
+///
+/// 除 HLS 播放列表与切片外，`pipe:1` 输出 (TS 直播) 写入标准输出；加密、字幕等其他输出不模拟
+pub async fn run(args: MockArgs) -> anyhow::Result<()> {
+    let mut outputs = parse_outputs(&args.ffmpeg_args);
+    let to_stdout = args.ffmpeg_args.iter().any(|a| a == "pipe:1");
+    if outputs.is_empty() && !to_stdout {
+        anyhow::bail!("Mock engine: no HLS or pipe output in the arguments");
+    }
+
+    // Windows 上网关通过标准输入发送 `q` 请求退出 (见 platform::terminate)
+    tokio::spawn(async {
+        let mut stdin = tokio::io::stdin();
+        let mut buf = [0u8; 1];
+        while let Ok(1) = stdin.read(&mut buf).await {
+            if buf[0] == b'q' {
+                std::process::exit(0);
+            }
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(args.startup_delay_ms)).await;
+    let started = Instant::now();
+    let crash_at = args
+        .crash_after_sec
+        .map(|s| started + Duration::from_secs(s));
+    let stall_at = args
+        .stall_after_sec
+        .map(|s| started + Duration::from_secs(s));
+    let interval = Duration::from_secs(
+        outputs
+            .iter()
+            .map(|o| o.duration)
+            .min()
+            .unwrap_or(DEFAULT_SEGMENT_SEC),
+    );
+    let segment = segment_bytes();
+    let mut stdout = tokio::io::stdout();
+
+    loop {
+        let now = Instant::now();
+        if crash_at.is_some_and(|t| now >= t) {
+            if let Some(error) = &args.error {
+                eprintln!("{}", error);
+            }
+            std::process::exit(args.exit_code);
+        }
+        if stall_at.is_none_or(|t| now < t) {
+            for output in &mut outputs {
+                output.write_segment(&segment)?;
+            }
+            if to_stdout {
+                stdout.write_all(&segment).await?;
+                stdout.flush().await?;
+            }
+        }
+        let next = now + interval;
+        tokio::time::sleep_until(crash_at.map_or(next, |t| t.min(next))).await;
+    }
+}
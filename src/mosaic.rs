@@ -0,0 +1,216 @@
+use crate::config::{MosaicConfig, StreamConfig};
+use crate::engine::Engine;
+use crate::state::AppState;
+use std::path::Path;
+
+/// 画面网格的列数与行数 (未指定列数时尽量接近正方形)
+pub fn grid(cfg: &MosaicConfig) -> (u32, u32) {
+    let n = cfg.members.len().max(1) as u32;
+    let columns = cfg
+        .columns
+        .unwrap_or_else(|| (n as f64).sqrt().ceil() as u32)
+        .clamp(1, n);
+    (columns, n.div_ceil(columns))
+}
+
+/// 每个成员流的本地播放列表作为一路输入，从最新的切片开始读取
+pub fn input_args(state: &AppState, cfg: &MosaicConfig) -> Vec<String> {
+    let config = state.config();
+    let mut args = Vec::new();
+    for member in &cfg.members {
+        let Some(member_cfg) = config.stream(member) else {
+            continue;
+        };
+        let playlist = Engine::output_dir(state, member_cfg).join("index.m3u8");
+        args.extend([
+            "-live_start_index".to_string(),
+            "-1".to_string(),
+            "-i".to_string(),
+            playlist.to_string_lossy().to_string(),
+        ]);
+    }
+    args
+}
+
+/// 生成 filter_complex：各路缩放并补边到统一格子大小后用 xstack 拼接，输出标签为 `[mosaic]`
+pub fn filter_graph(cfg: &MosaicConfig) -> String {
+    let (w, h) = (cfg.tile_width, cfg.tile_height);
+    let (columns, rows) = grid(cfg);
+    let n = cfg.members.len();
+
+    let mut chains: Vec<String> = (0..n)
+        .map(|i| {
+            format!(
+                "[{i}:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps}[t{i}]",
+                fps = cfg.fps
+            )
+        })
+        .collect();
+
+    let tiles: String = (0..n).map(|i| format!("[t{}]", i)).collect();
+    if n == 1 {
+        chains.push(format!("{}null[mosaic]", tiles));
+    } else {
+        let layout: Vec<String> = (0..n as u32)
+            .map(|i| format!("{}_{}", i % columns * w, i / columns * h))
+            .collect();
+        // 最后一行未排满时空格以黑色填充
+        let fill = if (n as u32) < columns * rows {
+            ":fill=black"
+        } else {
+            ""
+        };
+        chains.push(format!(
+            "{}xstack=inputs={}:layout={}{}[mosaic]",
+            tiles,
+            n,
+            layout.join("|"),
+            fill
+        ));
+    }
+    chains.join(";")
+}
+
+/// 合成画面的编码与 HLS 封装参数 (无音频)
+pub fn output_args(
+    stream: &StreamConfig,
+    cfg: &MosaicConfig,
+    output_dir: &Path,
+    key_args: &[String],
+    hls_flags: &[&str],
+) -> Vec<String> {
+    let bitrate = format!("{}k", cfg.bitrate_kbps);
+    let mut args = vec![
+        "-filter_complex".to_string(),
+        filter_graph(cfg),
+        "-map".to_string(),
+        "[mosaic]".to_string(),
+        "-an".to_string(),
+        "-c:v".to_string(),
+        cfg.encoder.clone(),
+        "-b:v".to_string(),
+        bitrate.clone(),
+        "-maxrate".to_string(),
+        bitrate,
+        "-bufsize".to_string(),
+        format!("{}k", cfg.bitrate_kbps * 2),
+        // 每个切片以关键帧开始
+        "-g".to_string(),
+        (cfg.fps * stream.hls.segment_duration_sec).to_string(),
+    ];
+    if cfg.encoder == "libx264" {
+        args.extend(["-preset".to_string(), "veryfast".to_string()]);
+    }
+    args.extend(key_args.iter().cloned());
+
+    let mut flags = hls_flags.to_vec();
+    flags.extend(["temp_file", "delete_segments"]);
+    if stream.program_date_time {
+        flags.push("program_date_time");
+    }
+    args.extend([
+        "-f".to_string(),
+        "hls".to_string(),
+        "-hls_time".to_string(),
+        stream.hls.segment_duration_sec.to_string(),
+        "-hls_list_size".to_string(),
+        stream.hls.list_size.to_string(),
+    ]);
+    if let Some(naming) = stream.hls.segment_naming() {
+        flags.extend(naming.flags);
+        if naming.strftime {
+            args.extend(["-strftime".to_string(), "1".to_string()]);
+        }
+        args.extend([
+            "-hls_segment_filename".to_string(),
+            output_dir
+                .join(&naming.pattern)
+                .to_string_lossy()
+                .to_string(),
+        ]);
+    }
+    args.extend([
+        "-hls_flags".to_string(),
+        flags.join("+"),
+        output_dir.join("index.m3u8").to_string_lossy().to_string(),
+    ]);
+    args
+}
+
+/// 校验合成流：成员必须是存在的本地进程流，且不使用合成流不支持的输出功能
+pub fn validate(stream: &StreamConfig, streams: &[StreamConfig]) -> anyhow::Result<()> {
+    let Some(cfg) = &stream.mosaic else {
+        anyhow::bail!(
+            "Stream [{}] uses mosaic mode without a mosaic block",
+            stream.name
+        );
+    };
+    if cfg.members.is_empty() || cfg.members.len() > MAX_MEMBERS {
+        anyhow::bail!(
+            "Stream [{}] must combine between 1 and {} members",
+            stream.name,
+            MAX_MEMBERS
+        );
+    }
+    for member in &cfg.members {
+        let Some(member_cfg) = streams.iter().find(|s| &s.name == member) else {
+            anyhow::bail!(
+                "Mosaic [{}] references unknown stream [{}]",
+                stream.name,
+                member
+            );
+        };
+        if member_cfg.is_proxied() || member_cfg.mosaic.is_some() {
+            anyhow::bail!(
+                "Mosaic [{}] member [{}] must be a local relay or transcode stream",
+                stream.name,
+                member
+            );
+        }
+    }
+    if cfg.tile_width == 0 || cfg.tile_height == 0 || cfg.tile_width % 2 + cfg.tile_height % 2 != 0
+    {
+        anyhow::bail!(
+            "Mosaic [{}] tile size must be even and non-zero",
+            stream.name
+        );
+    }
+    if cfg.columns == Some(0) || cfg.fps == 0 || cfg.bitrate_kbps == 0 {
+        anyhow::bail!(
+            "Mosaic [{}] has a zero columns, fps or bitrate_kbps",
+            stream.name
+        );
+    }
+    if stream.hls.segment_duration_sec == 0 {
+        anyhow::bail!(
+            "Stream [{}] has a zero hls.segment_duration_sec",
+            stream.name
+        );
+    }
+    let unsupported = [
+        ("output_args", !stream.output_args.is_empty()),
+        ("rtsp_output", stream.rtsp_output.is_some()),
+        ("ts_output", stream.ts_output),
+        ("variants", !stream.variants.is_empty()),
+        ("abr", stream.abr.is_some()),
+        ("audio_tracks", !stream.audio_tracks.is_empty()),
+        ("subtitles", !stream.subtitles.is_empty()),
+        ("audio.normalize", stream.audio.normalize.is_some()),
+        ("overlay", stream.overlay.is_some()),
+        ("motion", stream.motion.is_some()),
+        ("watermark", stream.watermark.is_some()),
+        ("timelapse", stream.timelapse.is_some()),
+        ("fallback_sources", !stream.fallback_sources.is_empty()),
+    ];
+    if let Some((option, _)) = unsupported.iter().find(|(_, used)| *used) {
+        anyhow::bail!(
+            "Stream [{}] uses mosaic mode, which does not support {}",
+            stream.name,
+            option
+        );
+    }
+    Ok(())
+}
+
+/// 单个合成流最多的成员数
+const MAX_MEMBERS: usize = 16;
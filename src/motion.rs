@@ -0,0 +1,244 @@
+use crate::clock;
+use crate::config::{MotionConfig, StreamConfig};
+use crate::engine::Engine;
+use crate::http_client;
+use crate::state::{AppState, LockExt};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// 检测输出中场景变化分数的日志键 (由 metadata=print 输出)
+const SCENE_SCORE_KEY: &str = "lavfi.scene_score=";
+
+/// 告警回调超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 进行中的运动事件
+pub struct MotionEvent {
+    /// 最近一次检测到运动的时间
+    last_motion: Instant,
+    /// 事件期间的最高分数
+    peak_score: f64,
+    /// 录像文件 (首次写入时创建)
+    file: Option<PathBuf>,
+    /// 最后写入录像的切片名
+    last_segment: Option<String>,
+}
+
+/// 生成运动检测输出的 FFmpeg 参数
+///
+/// 降帧降分辨率后计算场景变化分数，超过阈值的帧由 metadata 滤镜打印到 stderr
+pub fn detection_args(cfg: &MotionConfig) -> Vec<String> {
+    vec![
+        "-map".to_string(),
+        "0:v:0".to_string(),
+        "-an".to_string(),
+        "-vf".to_string(),
+        format!(
+            "fps=2,scale=320:-2,select='gt(scene,{})',metadata=print",
+            cfg.threshold
+        ),
+        "-f".to_string(),
+        "null".to_string(),
+        "-".to_string(),
+    ]
+}
+
+/// 从 FFmpeg 日志行中解析场景变化分数
+pub fn scene_score(line: &str) -> Option<f64> {
+    let start = line.find(SCENE_SCORE_KEY)? + SCENE_SCORE_KEY.len();
+    line[start..].split_whitespace().next()?.parse().ok()
+}
+
+/// 记录一次运动，必要时开启新事件并发送告警
+pub fn on_motion(state: &AppState, name: &str, score: f64) {
+    let is_new = {
+        let mut events = state.motion_events.lock_or_recover();
+        match events.get_mut(name) {
+            Some(event) => {
+                event.last_motion = Instant::now();
+                event.peak_score = event.peak_score.max(score);
+                false
+            }
+            None => {
+                events.insert(
+                    name.to_string(),
+                    MotionEvent {
+                        last_motion: Instant::now(),
+                        peak_score: score,
+                        file: None,
+                        last_segment: None,
+                    },
+                );
+                true
+            }
+        }
+    };
+
+    if is_new {
+        info!("Motion detected on stream [{}] (score {:.2})", name, score);
+        notify(state, name, "motion_start", score);
+    }
+}
+
+/// 推进所有运动事件：追加新切片到录像文件，结束超过 post_roll 的事件
+///
+/// 预录 (pre_roll) 取自 HLS 窗口中已有的切片，因此 pre_roll_sec 不应超过
+/// `hls.list_size * hls.segment_duration_sec`
+pub async fn tick(state: &Arc<AppState>) {
+    let config = state.config();
+    let names: Vec<String> = state
+        .motion_events
+        .lock_or_recover()
+        .keys()
+        .cloned()
+        .collect();
+
+    for name in names {
+        let cfg = config.stream(&name);
+        let motion = cfg.and_then(|c| c.motion.as_ref());
+        let running = state.active_streams.lock_or_recover().contains_key(&name);
+        let (Some(cfg), Some(motion), true) = (cfg, motion, running) else {
+            finish(state, &name).await;
+            continue;
+        };
+
+        if let Err(e) = record(state, cfg, motion).await {
+            warn!("Motion recording failed [{}]: {}", name, e);
+        }
+
+        let expired = state
+            .motion_events
+            .lock_or_recover()
+            .get(&name)
+            .is_some_and(|e| e.last_motion.elapsed() > Duration::from_secs(motion.post_roll_sec));
+        if expired {
+            finish(state, &name).await;
+        }
+    }
+}
+
+/// 将播放列表中尚未写入的切片追加到录像文件
+async fn record(state: &AppState, cfg: &StreamConfig, motion: &MotionConfig) -> anyhow::Result<()> {
+    let output_dir = Engine::output_dir(state, cfg);
+    let Ok(playlist) = fs::read_to_string(output_dir.join("index.m3u8")).await else {
+        return Ok(());
+    };
+    let segments = parse_segments(&playlist);
+
+    let (file, last_segment) = {
+        let events = state.motion_events.lock_or_recover();
+        let Some(event) = events.get(&cfg.name) else {
+            return Ok(());
+        };
+        (event.file.clone(), event.last_segment.clone())
+    };
+
+    // 首次写入从覆盖 pre_roll 的切片开始，之后从上次写入的位置继续
+    let start = match &last_segment {
+        Some(last) => segments
+            .iter()
+            .position(|(_, s)| s == last)
+            .map(|i| i + 1)
+            .unwrap_or(0),
+        None => {
+            let mut covered = 0.0;
+            let mut i = segments.len();
+            while i > 0 && covered < motion.pre_roll_sec as f64 {
+                i -= 1;
+                covered += segments[i].0;
+            }
+            i
+        }
+    };
+    if start >= segments.len() {
+        return Ok(());
+    }
+
+    let file = match file {
+        Some(f) => f,
+        None => {
+            let dir = PathBuf::from(&state.config().server.record_root).join(&cfg.name);
+            fs::create_dir_all(&dir).await?;
+            dir.join(format!("{}.ts", clock::compact(SystemTime::now())))
+        }
+    };
+    let mut out = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file)
+        .await?;
+
+    let mut written = last_segment;
+    for (_, segment) in &segments[start..] {
+        // 切片可能已被 FFmpeg 淘汰，跳过即可
+        if let Ok(data) = fs::read(output_dir.join(segment)).await {
+            out.write_all(&data).await?;
+        }
+        written = Some(segment.clone());
+    }
+    out.flush().await?;
+
+    if let Some(event) = state.motion_events.lock_or_recover().get_mut(&cfg.name) {
+        event.file = Some(file);
+        event.last_segment = written;
+    }
+    Ok(())
+}
+
+/// 结束运动事件并发送告警
+async fn finish(state: &AppState, name: &str) {
+    let Some(event) = state.motion_events.lock_or_recover().remove(name) else {
+        return;
+    };
+    match &event.file {
+        Some(file) => info!("Motion ended on stream [{}]. Recording: {:?}", name, file),
+        None => info!("Motion ended on stream [{}]", name),
+    }
+    notify(state, name, "motion_end", event.peak_score);
+}
+
+/// 向配置的告警地址发送事件 (后台执行，失败仅记录日志)
+fn notify(state: &AppState, name: &str, event: &'static str, score: f64) {
+    let config = state.config();
+    let Some(url) = config
+        .stream(name)
+        .and_then(|c| c.motion.as_ref())
+        .and_then(|m| m.webhook.clone())
+    else {
+        return;
+    };
+    let body = serde_json::json!({
+        "stream": name,
+        "event": event,
+        "score": score,
+        "time": clock::rfc3339(SystemTime::now()),
+    });
+    let name = name.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = http_client::send_json("POST", &url, &body, None, WEBHOOK_TIMEOUT).await {
+            warn!("Motion webhook failed [{}]: {}", name, e);
+        }
+    });
+}
+
+/// 解析媒体播放列表，返回 (时长, 切片名) 列表
+fn parse_segments(playlist: &str) -> Vec<(f64, String)> {
+    let mut out = Vec::new();
+    let mut duration = 0.0;
+    for line in playlist.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            duration = info
+                .split(',')
+                .next()
+                .and_then(|d| d.parse().ok())
+                .unwrap_or(0.0);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            out.push((duration, line.to_string()));
+        }
+    }
+    out
+}
@@ -0,0 +1,360 @@
+use crate::config::NetTestConfig;
+use crate::http_client::Url;
+use axum::body::{Body, Bytes};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout_at;
+
+/// 单个方向允许传输的最大字节数
+pub const MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// 生成数据的块大小
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// 响应头大小上限
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// 同一时间只允许一个测速任务，避免多个测速互相挤占带宽
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// 下行：从对端下载
+    Down,
+    /// 上行：向对端上传
+    Up,
+    #[default]
+    Both,
+}
+
+/// 单个方向的测速结果
+#[derive(Debug, Serialize, Default)]
+pub struct Throughput {
+    /// 对端地址 (接收上传时为空)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    /// 实际传输的字节数
+    pub bytes: u64,
+    /// 传输耗时 (秒，不含建立连接)
+    pub seconds: f64,
+    /// 平均速率 (Mbit/s)
+    pub mbps: f64,
+    /// 建立连接并收到首个响应的耗时 (毫秒)
+    pub latency_ms: u64,
+    /// 是否在超时前传输完全部数据
+    pub complete: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Throughput {
+    fn measured(
+        url: &str,
+        bytes: u64,
+        elapsed: Duration,
+        latency: Duration,
+        complete: bool,
+    ) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        Self {
+            url: url.to_string(),
+            bytes,
+            seconds,
+            mbps: if seconds > 0.0 {
+                bytes as f64 * 8.0 / seconds / 1_000_000.0
+            } else {
+                0.0
+            },
+            latency_ms: latency.as_millis() as u64,
+            complete,
+            error: None,
+        }
+    }
+
+    fn failed(url: &str, error: anyhow::Error) -> Self {
+        Self {
+            url: url.to_string(),
+            error: Some(error.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// 测速报告，未测试的方向为 None
+#[derive(Debug, Serialize)]
+pub struct NetTestReport {
+    pub download: Option<Throughput>,
+    pub upload: Option<Throughput>,
+}
+
+/// 正在运行的测速任务，释放时允许下一次测速
+pub struct RunGuard(());
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+/// 开始一次测速，已有测速进行中时返回 None
+pub fn try_begin() -> Option<RunGuard> {
+    RUNNING
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .ok()
+        .map(|_| RunGuard(()))
+}
+
+/// 对配置的对端执行测速，各方向依次进行以免互相影响
+pub async fn run(
+    cfg: &NetTestConfig,
+    direction: Direction,
+    bytes: u64,
+) -> anyhow::Result<NetTestReport> {
+    let want_down = direction != Direction::Up;
+    let want_up = direction != Direction::Down;
+    let download_url = cfg.download_url.as_deref().filter(|_| want_down);
+    let upload_url = cfg.upload_url.as_deref().filter(|_| want_up);
+    if download_url.is_none() && upload_url.is_none() {
+        anyhow::bail!("nettest has no endpoint configured for this direction");
+    }
+    let timeout = Duration::from_secs(cfg.timeout_sec);
+    let token = cfg.token.as_deref();
+
+    let mut report = NetTestReport {
+        download: None,
+        upload: None,
+    };
+    if let Some(url) = download_url {
+        report.download = Some(
+            download(url, token, bytes, timeout)
+                .await
+                .unwrap_or_else(|e| Throughput::failed(url, e)),
+        );
+    }
+    if let Some(url) = upload_url {
+        report.upload = Some(
+            upload(url, token, bytes, timeout)
+                .await
+                .unwrap_or_else(|e| Throughput::failed(url, e)),
+        );
+    }
+    Ok(report)
+}
+
+async fn download(
+    url: &str,
+    token: Option<&str>,
+    bytes: u64,
+    timeout: Duration,
+) -> anyhow::Result<Throughput> {
+    let parsed = Url::parse(url)?;
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut stream = timeout_at(deadline, connect("GET", &parsed, token, None))
+        .await
+        .map_err(|_| anyhow::anyhow!("Connecting to {} timed out", url))??;
+    let (status, mut received) = timeout_at(deadline, read_head(&mut stream))
+        .await
+        .map_err(|_| anyhow::anyhow!("Waiting for a response from {} timed out", url))??;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("{} returned HTTP {}", url, status);
+    }
+    let latency = started.elapsed();
+
+    let body_started = Instant::now();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut complete = true;
+    while received < bytes {
+        match timeout_at(deadline, stream.read(&mut buf)).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => received += n as u64,
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {
+                complete = false;
+                break;
+            }
+        }
+    }
+    Ok(Throughput::measured(
+        url,
+        received.min(bytes),
+        body_started.elapsed(),
+        latency,
+        complete,
+    ))
+}
+
+async fn upload(
+    url: &str,
+    token: Option<&str>,
+    bytes: u64,
+    timeout: Duration,
+) -> anyhow::Result<Throughput> {
+    let parsed = Url::parse(url)?;
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut stream = timeout_at(deadline, connect("POST", &parsed, token, Some(bytes)))
+        .await
+        .map_err(|_| anyhow::anyhow!("Connecting to {} timed out", url))??;
+    let latency = started.elapsed();
+
+    let chunk = junk_chunk();
+    let body_started = Instant::now();
+    let mut sent = 0u64;
+    while sent < bytes {
+        let n = (bytes - sent).min(CHUNK_SIZE as u64) as usize;
+        match timeout_at(deadline, stream.write_all(&chunk[..n])).await {
+            Ok(Ok(())) => sent += n as u64,
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {
+                return Ok(Throughput::measured(
+                    url,
+                    sent,
+                    body_started.elapsed(),
+                    latency,
+                    false,
+                ))
+            }
+        }
+    }
+    // 等待对端读完请求体后的响应，避免只测到本机发送缓冲区
+    let (status, _) = match timeout_at(deadline, read_head(&mut stream)).await {
+        Ok(head) => head?,
+        Err(_) => {
+            return Ok(Throughput::measured(
+                url,
+                sent,
+                body_started.elapsed(),
+                latency,
+                false,
+            ))
+        }
+    };
+    if !(200..300).contains(&status) {
+        anyhow::bail!("{} returned HTTP {}", url, status);
+    }
+    Ok(Throughput::measured(
+        url,
+        sent,
+        body_started.elapsed(),
+        latency,
+        true,
+    ))
+}
+
+/// 建立连接并发送请求头
+async fn connect(
+    method: &str,
+    url: &Url,
+    token: Option<&str>,
+    content_length: Option<u64>,
+) -> anyhow::Result<TcpStream> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: vtx-link/{}\r\nCache-Control: no-store\r\n",
+        method,
+        url.path,
+        url.host_header(),
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(token) = token {
+        head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    if let Some(len) = content_length {
+        head.push_str(&format!(
+            "Content-Type: application/octet-stream\r\nContent-Length: {}\r\n",
+            len
+        ));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    Ok(stream)
+}
+
+/// 读取响应头，返回状态码与已读入的响应体字节数
+async fn read_head(stream: &mut TcpStream) -> anyhow::Result<(u16, u64)> {
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            anyhow::bail!("Connection closed before the response headers");
+        }
+        raw.extend_from_slice(&buf[..n]);
+        if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+            let status = std::str::from_utf8(&raw[..end])
+                .ok()
+                .and_then(|head| head.split_whitespace().nth(1))
+                .and_then(|code| code.parse().ok())
+                .ok_or_else(|| anyhow::anyhow!("Malformed HTTP response"))?;
+            return Ok((status, (raw.len() - end - 4) as u64));
+        }
+        if raw.len() > MAX_HEAD_BYTES {
+            anyhow::bail!("HTTP response headers exceed {} bytes", MAX_HEAD_BYTES);
+        }
+    }
+}
+
+/// 生成数据块：伪随机内容，避免链路上的压缩影响结果
+fn junk_chunk() -> Bytes {
+    static CHUNK: OnceLock<Bytes> = OnceLock::new();
+    CHUNK
+        .get_or_init(|| {
+            let mut x: u64 = 0x9E37_79B9_7F4A_7C15;
+            let data: Vec<u8> = (0..CHUNK_SIZE)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    (x >> 32) as u8
+                })
+                .collect();
+            Bytes::from(data)
+        })
+        .clone()
+}
+
+/// 供对端或浏览器下载测速的生成数据
+pub fn junk_body(bytes: u64) -> Body {
+    let chunk = junk_chunk();
+    let stream = futures_util::stream::unfold(bytes, move |remaining| {
+        let chunk = chunk.clone();
+        async move {
+            if remaining == 0 {
+                return None;
+            }
+            let n = remaining.min(CHUNK_SIZE as u64);
+            Some((
+                Ok::<_, std::io::Error>(chunk.slice(..n as usize)),
+                remaining - n,
+            ))
+        }
+    });
+    Body::from_stream(stream)
+}
+
+/// 接收对端或浏览器上传的数据并计算速率，超过上限的部分不再计入
+pub async fn receive(body: Body) -> anyhow::Result<Throughput> {
+    let started = Instant::now();
+    let mut received = 0u64;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        received += chunk?.len() as u64;
+        if received >= MAX_BYTES {
+            break;
+        }
+    }
+    Ok(Throughput::measured(
+        "",
+        received.min(MAX_BYTES),
+        started.elapsed(),
+        Duration::ZERO,
+        true,
+    ))
+}
@@ -0,0 +1,388 @@
+use crate::clock;
+use crate::config::{
+    AlertRule, NotificationChannel, NotificationsConfig, SmtpChannel, TelegramChannel,
+};
+use crate::hash;
+use crate::http_client;
+use crate::pattern::Pattern;
+use crate::state::{AppState, LockExt};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// 单次发送 (连接、对话与响应) 的超时
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 去重键：(渠道, 规则, 标签, 事件)
+type DedupKey = (String, String, BTreeMap<String, String>, &'static str);
+
+/// 去重与限流记录
+#[derive(Debug, Default)]
+pub struct NotifyHistory {
+    /// 去重键 -> 最近一次发送时间
+    sent: HashMap<DedupKey, Instant>,
+    /// 渠道 -> 最近一小时内的发送时间
+    hourly: HashMap<String, VecDeque<Instant>>,
+    /// 渠道 -> 因限流丢弃的通知数 (下一条成功发送时附带说明)
+    dropped: HashMap<String, u64>,
+}
+
+/// 一条告警通知
+struct Message {
+    subject: String,
+    body: String,
+}
+
+/// 将告警事件发往所有匹配的通知渠道 (后台执行，失败仅记录日志)
+///
+/// 渠道按告警级别与 `stream` 标签过滤；同一告警在 `dedup_window_sec` 内只发送一次，
+/// 每个渠道每小时最多发送 `max_per_hour` 条，超出部分被丢弃并在下一条通知中注明
+pub fn dispatch(
+    state: &AppState,
+    rule: &AlertRule,
+    labels: &BTreeMap<String, String>,
+    value: f64,
+    event: &'static str,
+) {
+    let config = state.config();
+    let settings = &config.notifications;
+    for channel in settings
+        .channels
+        .iter()
+        .filter(|c| accepts(c, rule, labels))
+    {
+        let Some(dropped) = admit(state, settings, channel, rule, labels, event) else {
+            continue;
+        };
+        let mut message = render(rule, labels, value, event);
+        if dropped > 0 {
+            message.body.push_str(&format!(
+                "\n({} earlier notification(s) on this channel were dropped by the hourly limit)\n",
+                dropped
+            ));
+        }
+        let channel = channel.clone();
+        tokio::spawn(async move {
+            let result = if let Some(smtp) = &channel.smtp {
+                send_smtp(smtp, &message).await
+            } else if let Some(telegram) = &channel.telegram {
+                send_telegram(telegram, &message).await
+            } else {
+                Ok(())
+            };
+            if let Err(e) = result {
+                warn!("Notification via [{}] failed: {}", channel.name, e);
+            }
+        });
+    }
+}
+
+fn accepts(
+    channel: &NotificationChannel,
+    rule: &AlertRule,
+    labels: &BTreeMap<String, String>,
+) -> bool {
+    if rule.severity < channel.min_severity {
+        return false;
+    }
+    if channel.streams.is_empty() {
+        return true;
+    }
+    let Some(stream) = labels.get("stream") else {
+        return false;
+    };
+    channel
+        .streams
+        .iter()
+        .filter_map(|p| Pattern::new(p).ok())
+        .any(|p| p.is_match(stream))
+}
+
+/// 检查去重与限流，允许发送时返回此前被限流丢弃的条数
+fn admit(
+    state: &AppState,
+    settings: &NotificationsConfig,
+    channel: &NotificationChannel,
+    rule: &AlertRule,
+    labels: &BTreeMap<String, String>,
+    event: &'static str,
+) -> Option<u64> {
+    let now = Instant::now();
+    let window = Duration::from_secs(settings.dedup_window_sec);
+    let mut history = state.notifications.lock_or_recover();
+    history
+        .sent
+        .retain(|_, at| now.duration_since(*at) < window);
+
+    let key = (
+        channel.name.clone(),
+        rule.name.clone(),
+        labels.clone(),
+        event,
+    );
+    if history.sent.contains_key(&key) {
+        info!(
+            "Suppressed duplicate {} for [{}] via [{}]",
+            event, rule.name, channel.name
+        );
+        return None;
+    }
+
+    let hour = Duration::from_secs(3600);
+    let sent = history.hourly.entry(channel.name.clone()).or_default();
+    while sent
+        .front()
+        .is_some_and(|at| now.duration_since(*at) >= hour)
+    {
+        sent.pop_front();
+    }
+    if settings.max_per_hour > 0 && sent.len() >= settings.max_per_hour as usize {
+        warn!(
+            "Notification channel [{}] reached {} per hour, dropping {} for [{}]",
+            channel.name, settings.max_per_hour, event, rule.name
+        );
+        *history.dropped.entry(channel.name.clone()).or_default() += 1;
+        return None;
+    }
+    sent.push_back(now);
+    history.sent.insert(key, now);
+    Some(history.dropped.remove(&channel.name).unwrap_or(0))
+}
+
+fn render(
+    rule: &AlertRule,
+    labels: &BTreeMap<String, String>,
+    value: f64,
+    event: &'static str,
+) -> Message {
+    let state = if event == "alert_resolved" {
+        "RESOLVED"
+    } else {
+        "FIRING"
+    };
+    let target = labels
+        .get("stream")
+        .map(|s| format!(" [{}]", s))
+        .unwrap_or_default();
+    let node = sys_info::hostname().unwrap_or_default();
+    let subject = format!(
+        "[{}] {:?} alert {}{} on {}",
+        state, rule.severity, rule.name, target, node
+    );
+    let mut body = format!(
+        "Rule: {}\nSeverity: {:?}\nMetric: {} = {} ({:?} {})\nAction: {:?}\nNode: {}\nTime: {}\n",
+        rule.name,
+        rule.severity,
+        rule.metric,
+        value,
+        rule.condition,
+        rule.threshold,
+        rule.action,
+        node,
+        clock::rfc3339(SystemTime::now())
+    );
+    for (key, value) in labels {
+        body.push_str(&format!("Label {}: {}\n", key, value));
+    }
+    Message { subject, body }
+}
+
+/// Bot 令牌位于请求路径中，只发往本机的 Bot API 服务或 HTTPS 转发代理 (见配置校验)
+async fn send_telegram(channel: &TelegramChannel, message: &Message) -> anyhow::Result<()> {
+    let url = format!(
+        "{}/bot{}/sendMessage",
+        channel.api_url.trim_end_matches('/'),
+        channel.bot_token
+    );
+    let body = serde_json::json!({
+        "chat_id": channel.chat_id,
+        "text": format!("{}\n\n{}", message.subject, message.body),
+    });
+    let res = http_client::send_json("POST", &url, &body, None, SEND_TIMEOUT).await?;
+    if !res.is_success() {
+        anyhow::bail!("Telegram API returned status {}", res.status);
+    }
+    Ok(())
+}
+
+async fn send_smtp(channel: &SmtpChannel, message: &Message) -> anyhow::Result<()> {
+    tokio::time::timeout(SEND_TIMEOUT, smtp_session(channel, message))
+        .await
+        .map_err(|_| anyhow::anyhow!("SMTP session to {} timed out", channel.server))?
+}
+
+/// 最小的 SMTP 客户端 (RFC 5321)：EHLO、可选的 AUTH PLAIN、单封邮件
+///
+/// 不支持 STARTTLS，AUTH PLAIN 只在连接到环回地址 (本机中继) 时发送
+async fn smtp_session(channel: &SmtpChannel, message: &Message) -> anyhow::Result<()> {
+    let server = if channel.server.contains(':') {
+        channel.server.clone()
+    } else {
+        format!("{}:25", channel.server)
+    };
+    let stream = TcpStream::connect(&server).await?;
+    let peer = stream.peer_addr()?;
+    if channel.username.is_some() && !peer.ip().is_loopback() {
+        anyhow::bail!(
+            "Refusing to send SMTP credentials to {} over an unencrypted session; use a local relay",
+            peer
+        );
+    }
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    expect(&mut reader, 220).await?;
+    let host = sys_info::hostname().unwrap_or_else(|_| "localhost".to_string());
+    command(&mut writer, &mut reader, &format!("EHLO {}", host), 250).await?;
+    if let (Some(user), Some(pass)) = (&channel.username, &channel.password) {
+        let token = hash::base64_encode(format!("\0{}\0{}", user, pass).as_bytes());
+        command(
+            &mut writer,
+            &mut reader,
+            &format!("AUTH PLAIN {}", token),
+            235,
+        )
+        .await?;
+    }
+    command(
+        &mut writer,
+        &mut reader,
+        &format!("MAIL FROM:<{}>", channel.from),
+        250,
+    )
+    .await?;
+    for to in &channel.to {
+        command(&mut writer, &mut reader, &format!("RCPT TO:<{}>", to), 250).await?;
+    }
+    command(&mut writer, &mut reader, "DATA", 354).await?;
+
+    let mut data = format!(
+        "From: <{}>\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        channel.from,
+        channel
+            .to
+            .iter()
+            .map(|t| format!("<{}>", t))
+            .collect::<Vec<_>>()
+            .join(", "),
+        encode_header(&message.subject),
+        clock::rfc2822(SystemTime::now())
+    );
+    for line in message.body.lines() {
+        // 以点开头的行需要转义，否则单独的 "." 会提前结束邮件
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".\r\n");
+    writer.write_all(data.as_bytes()).await?;
+    expect(&mut reader, 250).await?;
+
+    let _ = command(&mut writer, &mut reader, "QUIT", 221).await;
+    Ok(())
+}
+
+/// 非 ASCII 的邮件头按 RFC 2047 编码
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", hash::base64_encode(value.as_bytes()))
+    }
+}
+
+async fn command<W, R>(writer: &mut W, reader: &mut R, line: &str, code: u16) -> anyhow::Result<()>
+where
+    W: AsyncWriteExt + Unpin,
+    R: AsyncBufReadExt + Unpin,
+{
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    expect(reader, code).await.map_err(|e| {
+        // 不在错误中泄露认证信息
+        let verb = line.split(' ').next().unwrap_or(line);
+        anyhow::anyhow!("{} rejected: {}", verb, e)
+    })
+}
+
+/// 读取一个 (可能多行的) 响应并检查状态码
+async fn expect<R: AsyncBufReadExt + Unpin>(reader: &mut R, code: u16) -> anyhow::Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            anyhow::bail!("connection closed by server");
+        }
+        let line = line.trim_end();
+        let status: u16 = line.get(..3).and_then(|s| s.parse().ok()).unwrap_or(0);
+        // "250-..." 为多行响应的中间行
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if status != code {
+            anyhow::bail!("{}", line);
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::AppConfig;
+
+    fn validate(channel: &str) -> anyhow::Result<()> {
+        let yaml = format!(
+            r#"
+server:
+  listen: 127.0.0.1:8080
+  ffmpeg_binary: ffmpeg
+  supervisor_interval_ms: 1000
+streams: []
+notifications:
+  channels:
+    - name: ops
+{}
+"#,
+            channel
+        );
+        serde_yaml::from_str::<AppConfig>(&yaml)?.validate()
+    }
+
+    #[test]
+    fn smtp_credentials_only_go_to_a_local_relay() {
+        let smtp = |server: &str, auth: bool| {
+            let auth = if auth {
+                "\n        username: gw\n        password: pw"
+            } else {
+                ""
+            };
+            format!(
+                "      smtp:\n        server: \"{}\"\n        from: gw@example.com\n        to: [ops@example.com]{}",
+                server, auth
+            )
+        };
+        assert!(validate(&smtp("127.0.0.1:25", true)).is_ok());
+        assert!(validate(&smtp("localhost", true)).is_ok());
+        assert!(validate(&smtp("[::1]:587", true)).is_ok());
+        assert!(validate(&smtp("smtp.example.com:587", true)).is_err());
+        assert!(validate(&smtp("10.0.0.5", true)).is_err());
+        // 不带凭据时可直接发往远端服务器
+        assert!(validate(&smtp("smtp.example.com:25", false)).is_ok());
+    }
+
+    #[test]
+    fn telegram_token_stays_on_loopback() {
+        let telegram = |url: &str| {
+            format!(
+                "      telegram:\n        api_url: {}\n        bot_token: t\n        chat_id: \"1\"",
+                url
+            )
+        };
+        assert!(validate(&telegram("http://127.0.0.1:8081")).is_ok());
+        assert!(validate(&telegram("http://localhost:8081")).is_ok());
+        assert!(validate(&telegram("http://api.telegram.org")).is_err());
+    }
+}
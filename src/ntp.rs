@@ -0,0 +1,114 @@
+use crate::clock;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+/// 时钟核对间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// 查询失败后的重试间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 单次 SNTP 查询超时
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// NTP 纪元 (1900-01-01) 与 Unix 纪元之间的秒数
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+
+/// 最近一次时钟核对结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockCheck {
+    pub server: String,
+    /// 本机时钟相对 NTP 服务器的偏差 (毫秒，正数表示本机落后)
+    pub offset_ms: i64,
+    /// 偏差是否在 server.max_clock_skew_ms 之内
+    pub within_tolerance: bool,
+    /// 核对时间 (RFC 3339)
+    pub checked_at: String,
+}
+
+/// 定期向 NTP 服务器核对系统时钟，偏差过大时告警
+///
+/// 网关不调整系统时钟 (应由 chrony / systemd-timesyncd 负责)，
+/// 只确保 PROGRAM-DATE-TIME 所依赖的时钟可信
+pub async fn start_monitor(state: Arc<AppState>, server: String) {
+    loop {
+        let max_skew = state.config().server.max_clock_skew_ms;
+        match query(&server).await {
+            Ok(offset_ms) => {
+                let within_tolerance = offset_ms.unsigned_abs() <= max_skew;
+                if within_tolerance {
+                    info!("System clock offset from {}: {} ms", server, offset_ms);
+                } else {
+                    warn!(
+                        "System clock is off by {} ms from {} (tolerance {} ms). PROGRAM-DATE-TIME tags will be inaccurate.",
+                        offset_ms, server, max_skew
+                    );
+                }
+                *state.clock_check.lock_or_recover() = Some(ClockCheck {
+                    server: server.clone(),
+                    offset_ms,
+                    within_tolerance,
+                    checked_at: clock::rfc3339(SystemTime::now()),
+                });
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+            Err(e) => {
+                warn!("NTP query to {} failed: {}", server, e);
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// 发送一次 SNTP 请求，返回本机时钟偏差 (毫秒)
+async fn query(server: &str) -> anyhow::Result<i64> {
+    let addr = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:123", server)
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(&addr).await?;
+
+    // LI = 0, VN = 4, Mode = 3 (client)
+    let mut packet = [0u8; 48];
+    packet[0] = 0x23;
+    let t1 = unix_now();
+    socket.send(&packet).await?;
+    let n = tokio::time::timeout(QUERY_TIMEOUT, socket.recv(&mut packet))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out"))??;
+    let t4 = unix_now();
+    if n < 48 || packet[0] & 0x07 != 4 {
+        anyhow::bail!("Invalid NTP response");
+    }
+
+    let t2 = timestamp(&packet[32..40]);
+    let t3 = timestamp(&packet[40..48]);
+    if t3 <= 0.0 {
+        anyhow::bail!("NTP server is not synchronized");
+    }
+    let offset = ((t2 - t1) + (t3 - t4)) / 2.0;
+    Ok((offset * 1000.0).round() as i64)
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// 解析 64 位 NTP 时间戳为 Unix 秒数
+fn timestamp(bytes: &[u8]) -> f64 {
+    let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let frac = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if secs == 0 {
+        return 0.0;
+    }
+    f64::from(secs) - NTP_UNIX_OFFSET + f64::from(frac) / 4_294_967_296.0
+}
@@ -0,0 +1,281 @@
+use crate::clock;
+use crate::config::OriginPushConfig;
+use crate::http_client::{self, Url};
+use crate::segment_index::DirIndex;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// 检查输出目录变化的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 首次重试前的等待，此后每次翻倍
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// 主播放列表引用其他播放列表，最后推送
+const MASTER_PLAYLIST: &str = "master.m3u8";
+
+/// 一个流的推送统计 (随进程重启清零)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PushStats {
+    /// 远端目录地址
+    pub url: String,
+    /// 已上传的文件数与字节数 (含播放列表)
+    pub uploaded: u64,
+    pub bytes: u64,
+    /// 已在远端删除的切片数
+    pub deleted: u64,
+    /// 失败的请求数 (含重试)
+    pub failures: u64,
+    /// 最近一次失败的说明
+    pub last_error: Option<String>,
+    /// 最近一次成功推送播放列表的时间 (RFC 3339)
+    pub last_push: Option<String>,
+}
+
+/// 推送任务的本地状态
+struct Pusher {
+    name: String,
+    base: Url,
+    cfg: OriginPushConfig,
+    /// 已推送的播放列表内容 (文件名 -> 文本)
+    playlists: HashMap<String, String>,
+    /// 已上传到远端的切片
+    uploaded: BTreeSet<String>,
+}
+
+/// 流启动后开始推送其输出目录，直到该流的目录索引被替换或移除 (流停止或重启)
+///
+/// 切片先于引用它的播放列表上传，远端的播放列表不会引用尚未到达的切片；
+/// 切片上传失败时该播放列表留到下一轮，切片移出所有播放列表后才在远端删除
+pub fn spawn(state: &Arc<AppState>, name: &str, cfg: &OriginPushConfig, base_url: String) {
+    let base = match Url::parse(&base_url) {
+        Ok(url) => url,
+        Err(e) => {
+            warn!("Origin push disabled [{}]: {}", name, e);
+            return;
+        }
+    };
+    let Some(index) = state.segment_indexes.lock_or_recover().get(name).cloned() else {
+        return;
+    };
+    state.origin_pushes.lock_or_recover().insert(
+        name.to_string(),
+        PushStats {
+            url: base_url.clone(),
+            ..PushStats::default()
+        },
+    );
+    let mut pusher = Pusher {
+        name: name.to_string(),
+        base,
+        cfg: cfg.clone(),
+        playlists: HashMap::new(),
+        uploaded: BTreeSet::new(),
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        info!(
+            "Stream [{}] pushing HLS output to {}",
+            pusher.name, base_url
+        );
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let current = state
+                .segment_indexes
+                .lock_or_recover()
+                .get(&pusher.name)
+                .is_some_and(|i| Arc::ptr_eq(i, &index));
+            if !current {
+                break;
+            }
+            pusher.sync(&state, &index).await;
+        }
+        info!("Stream [{}] stopped pushing HLS output", pusher.name);
+    });
+}
+
+impl Pusher {
+    /// 推送内容有变化的播放列表及其新切片，再删除不再被引用的切片
+    async fn sync(&mut self, state: &AppState, index: &DirIndex) {
+        let mut names: Vec<String> = index
+            .files()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.ends_with(".m3u8"))
+            .collect();
+        // 媒体播放列表在前，主播放列表最后
+        names.sort_by_key(|name| (name == MASTER_PLAYLIST, name.clone()));
+
+        let mut referenced = BTreeSet::new();
+        let mut complete = true;
+        for name in names {
+            let text = match index.playlist(&name) {
+                Some(text) => text,
+                None => match tokio::fs::read_to_string(index.dir().join(&name)).await {
+                    Ok(text) => text,
+                    Err(_) => {
+                        complete = false;
+                        continue;
+                    }
+                },
+            };
+            let uris = segment_uris(&text);
+            referenced.extend(uris.iter().cloned());
+            if self.playlists.get(&name) == Some(&text) {
+                continue;
+            }
+
+            let mut ready = true;
+            for uri in &uris {
+                if self.uploaded.contains(uri) {
+                    continue;
+                }
+                let Ok(data) = tokio::fs::read(index.dir().join(uri)).await else {
+                    // 切片已被 FFmpeg 删除，等待播放列表更新
+                    ready = false;
+                    break;
+                };
+                if self.put(state, uri, &data).await {
+                    self.uploaded.insert(uri.clone());
+                } else {
+                    ready = false;
+                    break;
+                }
+            }
+            if !ready {
+                complete = false;
+                continue;
+            }
+            if self.put(state, &name, text.as_bytes()).await {
+                self.playlists.insert(name, text);
+                if let Some(stats) = state.origin_pushes.lock_or_recover().get_mut(&self.name) {
+                    stats.last_push = Some(clock::rfc3339(SystemTime::now()));
+                }
+            } else {
+                complete = false;
+            }
+        }
+
+        // 有播放列表未推送时远端可能仍引用旧切片，暂不删除
+        if !complete {
+            return;
+        }
+        let stale: Vec<String> = self.uploaded.difference(&referenced).cloned().collect();
+        for uri in stale {
+            if self.cfg.delete && !self.delete(state, &uri).await {
+                continue;
+            }
+            self.uploaded.remove(&uri);
+        }
+    }
+
+    /// 上传一个文件，失败时按退避重试 `retries` 次
+    async fn put(&self, state: &AppState, file: &str, data: &[u8]) -> bool {
+        let content_type = if file.ends_with(".m3u8") {
+            "application/vnd.apple.mpegurl".to_string()
+        } else {
+            mime_guess::from_path(file)
+                .first_or_octet_stream()
+                .to_string()
+        };
+        let mut headers: Vec<(&str, &str)> = self
+            .cfg
+            .headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        headers.push(("Content-Type", &content_type));
+
+        let ok = self.send(state, "PUT", file, &headers, Some(data)).await;
+        if ok {
+            if let Some(stats) = state.origin_pushes.lock_or_recover().get_mut(&self.name) {
+                stats.uploaded += 1;
+                stats.bytes += data.len() as u64;
+            }
+        }
+        ok
+    }
+
+    async fn delete(&self, state: &AppState, file: &str) -> bool {
+        let headers: Vec<(&str, &str)> = self
+            .cfg
+            .headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let ok = self.send(state, "DELETE", file, &headers, None).await;
+        if ok {
+            if let Some(stats) = state.origin_pushes.lock_or_recover().get_mut(&self.name) {
+                stats.deleted += 1;
+            }
+        }
+        ok
+    }
+
+    /// 发送请求并重试；删除时远端返回 404 也算成功
+    async fn send(
+        &self,
+        state: &AppState,
+        method: &str,
+        file: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> bool {
+        let Ok(url) = self.base.join(file) else {
+            return false;
+        };
+        let timeout = Duration::from_secs(self.cfg.timeout_sec);
+        let mut delay = RETRY_DELAY;
+        for attempt in 0..=self.cfg.retries {
+            if attempt > 0 {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            let error = match http_client::request_url(method, &url, headers, body, timeout).await {
+                Ok(resp) if resp.is_success() || (method == "DELETE" && resp.status == 404) => {
+                    return true;
+                }
+                Ok(resp) => format!("{} {} returned HTTP {}", method, file, resp.status),
+                Err(e) => format!("{} {} failed: {}", method, file, e),
+            };
+            warn!("Origin push [{}]: {}", self.name, error);
+            if let Some(stats) = state.origin_pushes.lock_or_recover().get_mut(&self.name) {
+                stats.failures += 1;
+                stats.last_error = Some(error);
+            }
+        }
+        false
+    }
+}
+
+/// 播放列表引用的本地文件 (媒体行与 `#EXT-X-MAP` 的 URI)，子播放列表与绝对地址除外
+fn segment_uris(text: &str) -> Vec<String> {
+    let mut uris = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let uri = if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+            attrs
+                .split_once("URI=\"")
+                .and_then(|(_, rest)| rest.split('"').next())
+        } else if !line.is_empty() && !line.starts_with('#') {
+            Some(line)
+        } else {
+            None
+        };
+        // 只接受输出目录内的相对地址
+        if let Some(uri) = uri
+            .filter(|u| !u.ends_with(".m3u8") && !u.contains(['/', '\\', '?']) && !u.contains(".."))
+        {
+            uris.push(uri.to_string());
+        }
+    }
+    uris
+}
+
+/// 推送统计 (未配置推送或流未启动过时为 None)
+pub fn stats(state: &AppState, name: &str) -> Option<PushStats> {
+    state.origin_pushes.lock_or_recover().get(name).cloned()
+}
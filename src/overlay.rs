@@ -0,0 +1,93 @@
+use crate::config::{OverlayConfig, OverlayPosition};
+use std::path::Path;
+
+/// 叠加文字文件名 (位于流输出目录，由 drawtext 的 textfile 读取)
+pub const TEXT_FILE: &str = ".overlay.txt";
+
+/// 元素与画面边缘的间距 (像素)
+const MARGIN: u32 = 10;
+
+/// 生成叠加的文字内容 (drawtext 文本展开语法)
+///
+/// 自定义文字、流名称与时间戳各占一行
+pub fn text(cfg: &OverlayConfig, stream_name: &str) -> String {
+    let mut lines = Vec::new();
+    if let Some(text) = &cfg.text {
+        lines.push(escape_expansion(text));
+    }
+    if cfg.show_name {
+        lines.push(escape_expansion(stream_name));
+    }
+    if let Some(format) = &cfg.timestamp {
+        // 函数参数以 ':' 和 '}' 分隔，需要转义
+        lines.push(format!("%{{localtime:{}}}", escape(format, "\\:}")));
+    }
+    lines.join("\n")
+}
+
+/// 生成 `-vf` 滤镜链：可选的 logo 叠加 (movie + overlay) 与 drawtext
+pub fn filter(cfg: &OverlayConfig, text_file: &Path) -> String {
+    let mut chain = Vec::new();
+
+    if cfg.has_text() {
+        let (x, y) = coordinates(cfg.position, "w-tw", "h-th");
+        let mut drawtext = format!(
+            "drawtext=textfile={}:x={}:y={}:fontsize={}:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=4",
+            escape_value(&text_file.to_string_lossy()),
+            x,
+            y,
+            cfg.font_size
+        );
+        if let Some(font) = &cfg.font_file {
+            drawtext.push_str(&format!(":fontfile={}", escape_value(font)));
+        }
+        chain.push(drawtext);
+    }
+
+    let Some(logo) = &cfg.logo else {
+        return chain.join(",");
+    };
+    let (x, y) = coordinates(cfg.logo_position, "main_w-overlay_w", "main_h-overlay_h");
+    chain.insert(0, format!("[in][logo]overlay=x={}:y={}", x, y));
+    format!(
+        "movie={}[logo];{}[out]",
+        escape_value(logo),
+        chain.join(",")
+    )
+}
+
+/// 按位置计算 x/y 表达式
+///
+/// `free_w`/`free_h` 为画面与元素尺寸之差 (drawtext 用 `w-tw`，overlay 用 `main_w-overlay_w`)
+fn coordinates(position: OverlayPosition, free_w: &str, free_h: &str) -> (String, String) {
+    let near = MARGIN.to_string();
+    let far_x = format!("{}-{}", free_w, MARGIN);
+    let far_y = format!("{}-{}", free_h, MARGIN);
+    match position {
+        OverlayPosition::TopLeft => (near.clone(), near),
+        OverlayPosition::TopRight => (far_x, near),
+        OverlayPosition::BottomLeft => (near, far_y),
+        OverlayPosition::BottomRight => (far_x, far_y),
+    }
+}
+
+/// 转义 drawtext 文本展开中的特殊字符
+fn escape_expansion(s: &str) -> String {
+    escape(s, "\\%")
+}
+
+/// 转义滤镜选项值：先按选项解析转义，再按滤镜图解析转义
+fn escape_value(s: &str) -> String {
+    escape(&escape(s, "\\':"), "\\'[],;")
+}
+
+fn escape(s: &str, special: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,567 @@
+/// 编译后的程序指令数上限 (次数区间按副本展开)
+const MAX_PROGRAM: usize = 10_000;
+
+/// 编译后的正则表达式 (轻量实现，用于匹配 FFmpeg 日志)
+///
+/// 支持常用子集：字面量、`.`、字符类 `[a-z]` / `[^…]`、`\d \w \s` (及大写取反)、
+/// 锚点 `^ $`、分组 `(…)` / `(?:…)`、选择 `|`、量词 `* + ? {n} {n,} {n,m}`，
+/// 以及开头的 `(?i)` 忽略大小写。只判断是否匹配，不提取分组。
+///
+/// 编译为 NFA 后逐字符同时推进全部状态 (Thompson 构造)，不回溯、不递归，
+/// 耗时与文本长度成线性，任意长的输入都不会耗尽栈
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(self, c: char, ignore_case: bool) -> bool {
+        match self {
+            ClassItem::Range(lo, hi) => {
+                (lo..=hi).contains(&c)
+                    || (ignore_case
+                        && ((lo..=hi).contains(&c.to_ascii_lowercase())
+                            || (lo..=hi).contains(&c.to_ascii_uppercase())))
+            }
+            ClassItem::Digit(neg) => c.is_ascii_digit() != neg,
+            ClassItem::Word(neg) => (c.is_alphanumeric() || c == '_') != neg,
+            ClassItem::Space(neg) => c.is_whitespace() != neg,
+        }
+    }
+}
+
+/// NFA 指令，跳转目标为指令下标
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    /// 同时尝试两个分支
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Pattern {
+    /// 编译模式，语法错误时返回错误
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        let (ignore_case, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+        };
+        let program = parser
+            .alternation()
+            .and_then(|node| {
+                if parser.pos < parser.chars.len() {
+                    anyhow::bail!("Unmatched ')'");
+                }
+                let mut program = Vec::new();
+                emit(&node, &mut program)?;
+                program.push(Inst::Match);
+                Ok(program)
+            })
+            .map_err(|e| anyhow::anyhow!("Invalid pattern {:?}: {}", pattern, e))?;
+        Ok(Self {
+            source: pattern.to_string(),
+            program,
+            ignore_case,
+        })
+    }
+
+    /// 文本中是否存在匹配
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        // 每个位置各指令只加入一次 (值为最后加入时的位置 + 1)
+        let mut seen = vec![0usize; self.program.len()];
+        let mut stack = Vec::new();
+        for i in 0..=chars.len() {
+            // 每个位置都可以开始一次匹配 (未锚定搜索)
+            current.push(0);
+            if self.advance(&chars, i, &mut current, &mut seen, &mut stack) {
+                return true;
+            }
+            let Some(&c) = chars.get(i) else {
+                break;
+            };
+            for &pc in &current {
+                let consumed = match &self.program[pc] {
+                    Inst::Char(expected) => self.char_eq(c, *expected),
+                    Inst::Any => true,
+                    Inst::Class(items, negated) => {
+                        items.iter().any(|it| it.matches(c, self.ignore_case)) != *negated
+                    }
+                    _ => false,
+                };
+                if consumed {
+                    next.push(pc + 1);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+
+    /// 沿不消耗字符的指令 (跳转、分支与锚点) 展开位置 `i` 的状态，只保留消耗字符的指令；
+    /// 到达 Match 时返回 true
+    fn advance(
+        &self,
+        s: &[char],
+        i: usize,
+        threads: &mut Vec<usize>,
+        seen: &mut [usize],
+        stack: &mut Vec<usize>,
+    ) -> bool {
+        stack.extend(threads.drain(..).rev());
+        while let Some(pc) = stack.pop() {
+            if seen[pc] == i + 1 {
+                continue;
+            }
+            seen[pc] = i + 1;
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(a, b) => {
+                    stack.push(b);
+                    stack.push(a);
+                }
+                Inst::Start if i == 0 => stack.push(pc + 1),
+                Inst::End if i == s.len() => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.push(pc),
+            }
+        }
+        false
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+/// 配置中以原文保存，加载时编译，语法错误使配置加载失败
+impl serde::Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+/// 将语法树编译为指令
+fn emit(node: &Node, out: &mut Vec<Inst>) -> anyhow::Result<()> {
+    if out.len() > MAX_PROGRAM {
+        anyhow::bail!("Pattern is too large");
+    }
+    match node {
+        Node::Char(c) => out.push(Inst::Char(*c)),
+        Node::Any => out.push(Inst::Any),
+        Node::Class(items, negated) => out.push(Inst::Class(items.clone(), *negated)),
+        Node::Start => out.push(Inst::Start),
+        Node::End => out.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                emit(node, out)?;
+            }
+        }
+        Node::Alt(alts) => {
+            // Split(a, Split(b, c))，每个分支结束后跳到末尾
+            let mut jumps = Vec::new();
+            for (n, alt) in alts.iter().enumerate() {
+                let split = out.len();
+                if n + 1 < alts.len() {
+                    out.push(Inst::Split(split + 1, 0));
+                }
+                emit(alt, out)?;
+                if n + 1 < alts.len() {
+                    jumps.push(out.len());
+                    out.push(Inst::Jump(0));
+                    let after = out.len();
+                    out[split] = Inst::Split(split + 1, after);
+                }
+            }
+            let end = out.len();
+            for at in jumps {
+                out[at] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat(inner, min, max) => {
+            for _ in 0..*min {
+                emit(inner, out)?;
+            }
+            match max {
+                // L: Split(body, end); body; Jump(L)
+                None => {
+                    let split = out.len();
+                    out.push(Inst::Split(split + 1, 0));
+                    emit(inner, out)?;
+                    out.push(Inst::Jump(split));
+                    let end = out.len();
+                    out[split] = Inst::Split(split + 1, end);
+                }
+                // 每个可选副本前一个 Split，不匹配时直接跳到末尾
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(out.len());
+                        out.push(Inst::Split(0, 0));
+                        emit(inner, out)?;
+                    }
+                    let end = out.len();
+                    for at in splits {
+                        out[at] = Inst::Split(at + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    if out.len() > MAX_PROGRAM {
+        anyhow::bail!("Pattern is too large");
+    }
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += c.is_some() as usize;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        self.pos += matched as usize;
+        matched
+    }
+
+    fn alternation(&mut self) -> anyhow::Result<Node> {
+        let mut alts = vec![self.concat()?];
+        while self.eat('|') {
+            alts.push(self.concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn concat(&mut self) -> anyhow::Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Node> {
+        let c = self.next().unwrap_or_default();
+        Ok(match c {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    anyhow::bail!("Unsupported group syntax at {}", self.pos);
+                }
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    anyhow::bail!("Unclosed group");
+                }
+                inner
+            }
+            '[' => self.class()?,
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => match self.escape()? {
+                Some(item) => Node::Class(vec![item], false),
+                None => Node::Char(self.chars[self.pos - 1]),
+            },
+            '*' | '+' | '?' => anyhow::bail!("Nothing to repeat at {}", self.pos),
+            c => Node::Char(c),
+        })
+    }
+
+    /// 解析 `\` 之后的字符，返回预定义字符类；普通转义返回 None (字面量为上一个字符)
+    fn escape(&mut self) -> anyhow::Result<Option<ClassItem>> {
+        let c = self
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Trailing backslash"))?;
+        Ok(match c {
+            'd' | 'D' => Some(ClassItem::Digit(c == 'D')),
+            'w' | 'W' => Some(ClassItem::Word(c == 'W')),
+            's' | 'S' => Some(ClassItem::Space(c == 'S')),
+            c if c.is_ascii_alphanumeric() => anyhow::bail!("Unsupported escape \\{}", c),
+            _ => None,
+        })
+    }
+
+    fn class(&mut self) -> anyhow::Result<Node> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Unclosed character class"))?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                match self.escape()? {
+                    Some(item) => {
+                        items.push(item);
+                        continue;
+                    }
+                    None => self.chars[self.pos - 1],
+                }
+            } else {
+                c
+            };
+            // `a-z` 区间；末尾的 `-` 视为字面量
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let mut hi = self.next().unwrap_or_default();
+                if hi == '\\' {
+                    if self.escape()?.is_some() {
+                        anyhow::bail!("Invalid range in character class");
+                    }
+                    hi = self.chars[self.pos - 1];
+                }
+                if hi < lo {
+                    anyhow::bail!("Invalid range {}-{}", lo, hi);
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    fn quantifier(&mut self, atom: Node) -> anyhow::Result<Node> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.pos;
+                self.pos += 1;
+                match self.bounds() {
+                    Some(b) => {
+                        self.pos -= 1;
+                        b
+                    }
+                    // 不是合法的次数区间时按字面量 `{` 处理
+                    None => {
+                        self.pos = start;
+                        return Ok(atom);
+                    }
+                }
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        // 只判断是否匹配，非贪婪修饰不影响结果
+        self.eat('?');
+        if max.is_some_and(|m| m < min) {
+            anyhow::bail!("Invalid repetition bounds");
+        }
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    /// 解析 `n}`、`n,}` 或 `n,m}`，结束时位于 `}` 之后
+    fn bounds(&mut self) -> Option<(usize, Option<usize>)> {
+        let min = self.number()?;
+        let max = if self.eat(',') {
+            if self.peek() == Some('}') {
+                None
+            } else {
+                Some(self.number()?)
+            }
+        } else {
+            Some(min)
+        };
+        self.eat('}').then_some((min, max))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert!(matches(
+            "Connection refused",
+            "tcp: Connection refused (111)"
+        ));
+        assert!(!matches("Connection refused", "Connection reset"));
+        assert!(matches(r"\d+ fps", "speed 25 fps"));
+        assert!(!matches(r"\d+ fps", "speed fps"));
+        assert!(matches("[a-c]x[^0-9]", "bx_"));
+        assert!(!matches("[a-c]x[^0-9]", "bx5"));
+        assert!(matches(r"\w\s\W", "a ."));
+        assert!(matches(r"a\.b", "a.b"));
+        assert!(!matches(r"a\.b", "axb"));
+        assert!(matches("[-a]", "-"));
+        assert!(matches("a{b", "a{b"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^error", "error: x"));
+        assert!(!matches("^error", "an error"));
+        assert!(matches("done$", "all done"));
+        assert!(!matches("done$", "done."));
+        assert!(matches("^$", ""));
+        assert!(!matches("^$", "x"));
+        assert!(matches("^(www\\.)?partner\\.com$", "partner.com"));
+        assert!(matches("^(www\\.)?partner\\.com$", "www.partner.com"));
+        assert!(!matches("^(www\\.)?partner\\.com$", "evilpartner.com"));
+        assert!(!matches("^(www\\.)?partner\\.com$", "partner.com.evil"));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert!(matches("^(cat|dog)s?$", "dogs"));
+        assert!(matches("^(cat|dog)s?$", "cat"));
+        assert!(!matches("^(cat|dog)s?$", "cow"));
+        assert!(matches("^(?:a|b|c)+$", "abcabc"));
+        assert!(!matches("^(?:a|b|c)+$", "abd"));
+        assert!(matches("^(a|)b$", "b"));
+        assert!(matches("x|^y", "zzx"));
+    }
+
+    #[test]
+    fn repetition() {
+        assert!(matches("^a*$", ""));
+        assert!(matches("^a+b$", "aaab"));
+        assert!(!matches("^a+b$", "b"));
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aaaa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^a{1,3}b$", "aab"));
+        assert!(!matches("^a{1,3}b$", "aaaab"));
+        assert!(matches("^(ab){2}$", "abab"));
+        assert!(matches("^a*?b$", "aab"));
+        // 可匹配空串的循环不会死循环
+        assert!(matches("^(a*)*b$", "aaab"));
+        assert!(!matches("^(a*)*b$", "aaac"));
+        assert!(matches("^(a?){3}$", "a"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        assert!(matches("(?i)^partner\\.COM$", "Partner.com"));
+        assert!(matches("(?i)[a-c]", "B"));
+        assert!(!matches("^partner$", "PARTNER"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for bad in [
+            "(a", "a)", "[a", "*a", "a\\", "\\q", "[z-a]", "a{3,1}", "(?=a)",
+        ] {
+            assert!(Pattern::new(bad).is_err(), "{}", bad);
+        }
+        assert!(Pattern::new("a{100000}").is_err());
+    }
+
+    /// 长输入曾使递归回溯实现栈溢出 (每个字符一层递归)，整个进程随之退出
+    #[test]
+    fn long_input_does_not_overflow_stack() {
+        let pattern = Pattern::new(r"(?i)^(.*\.)?partner\.example\.com$").unwrap();
+        let host = format!("{}partner.example.com", "a.".repeat(100_000));
+        let missing = "a".repeat(200_000);
+        // 在 64 KiB 栈上运行，原实现数百字符即溢出
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                assert!(pattern.is_match(&host));
+                assert!(!pattern.is_match(&missing));
+                assert!(!Pattern::new("(a*)*b").unwrap().is_match(&missing[..50_000]));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn serde_keeps_source() {
+        let pattern: Pattern = serde_json::from_str(r#""^a+$""#).unwrap();
+        assert!(pattern.is_match("aa"));
+        assert_eq!(serde_json::to_string(&pattern).unwrap(), r#""^a+$""#);
+        assert!(serde_json::from_str::<Pattern>(r#""(a""#).is_err());
+    }
+}
@@ -0,0 +1,562 @@
+use std::process::ExitStatus;
+use tokio::process::{Child, Command};
+
+/// 平台相关的子进程设置 (在 spawn 之前调用)
+///
+/// Unix 上让 FFmpeg 成为独立进程组的组长 (不再从终端读取输入)；
+/// Windows 上为 FFmpeg 提供标准输入 (用于请求退出) 且不创建控制台窗口
+pub fn prepare_child(cmd: &mut Command) {
+    imp::prepare_child(cmd)
+}
+
+/// FFmpeg 及其派生的辅助进程 (协议、管道调用的外部程序)：Unix 为进程组，Windows 为作业对象
+///
+/// 释放时结束组内剩余的进程，FFmpeg 被停止或崩溃后不会留下孤儿进程
+pub struct ProcessGroup(imp::Group);
+
+impl ProcessGroup {
+    /// 将刚启动的子进程纳入独立的组 (子进程需经 prepare_child 设置)，失败时返回 None
+    pub fn adopt(child: &Child) -> Option<Self> {
+        imp::Group::adopt(child).map(Self)
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.0.kill();
+    }
+}
+
+/// 停止 FFmpeg 子进程
+///
+/// Unix 上直接结束进程；Windows 没有 SIGTERM，先通过标准输入发送 `q` 让 FFmpeg 正常收尾，
+/// 3 秒内仍未退出时再调用 TerminateProcess。
+/// 返回时进程已被回收 (不会成为僵尸进程)，并返回其退出状态
+pub async fn terminate(child: &mut Child) -> Option<ExitStatus> {
+    imp::terminate(child).await
+}
+
+/// 结束进程的信号 (Windows 上没有信号，始终为 None)
+pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    imp::exit_signal(status)
+}
+
+/// 暂停或恢复进程 (Unix 上为 SIGSTOP/SIGCONT)，用于故障演练；Windows 上不支持
+pub fn set_paused(pid: u32, paused: bool) -> std::io::Result<()> {
+    imp::set_paused(pid, paused)
+}
+
+/// 以 Windows 服务方式运行：在后台线程连接服务控制管理器并报告运行中
+///
+/// 必须在服务启动后 30 秒内调用；其他平台返回错误
+pub fn start_service() -> anyhow::Result<()> {
+    imp::start_service()
+}
+
+/// 等待关闭请求：Unix 上为 SIGINT/SIGTERM，Windows 上为服务停止或 Ctrl+C
+///
+/// FFmpeg 运行在独立的进程组中，不会随网关一同收到终端信号，因此网关必须自行停止所有流
+pub async fn shutdown_signal() {
+    imp::shutdown_signal().await
+}
+
+/// 网关已关闭，以服务方式运行时向服务控制管理器报告已停止
+pub fn service_stopped() {
+    imp::service_stopped()
+}
+
+/// 进程累计 CPU 时间 (秒) 与常驻内存 (KB)，进程已退出或平台不支持时返回 None
+pub fn process_usage(pid: u32) -> Option<(f64, u64)> {
+    imp::process_usage(pid)
+}
+
+/// 路径所在文件系统的总容量与可用空间 (字节)，路径不存在时返回 None
+pub fn disk_space(path: &std::path::Path) -> Option<(u64, u64)> {
+    imp::disk_space(path)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Stdio};
+    use tokio::process::{Child, Command};
+
+    /// /proc 中 CPU 时间的单位 (Linux USER_HZ，各常见平台均为 100)
+    const CLOCK_TICKS_PER_SEC: f64 = 100.0;
+
+    pub fn prepare_child(cmd: &mut Command) {
+        // 后台进程组读取终端会被 SIGTTIN 挂起
+        cmd.stdin(Stdio::null()).process_group(0);
+    }
+
+    pub struct Group {
+        pgid: libc::pid_t,
+    }
+
+    impl Group {
+        pub fn adopt(child: &Child) -> Option<Self> {
+            let pgid = child.id()? as libc::pid_t;
+            (pgid > 0).then_some(Self { pgid })
+        }
+
+        pub fn kill(&self) {
+            // SAFETY: killpg 没有内存方面的前置条件，组已不存在时返回 ESRCH
+            unsafe {
+                libc::killpg(self.pgid, libc::SIGKILL);
+            }
+        }
+    }
+
+    pub async fn terminate(child: &mut Child) -> Option<ExitStatus> {
+        // 进程可能已自行退出，此时 kill 失败但 wait 仍会回收并返回退出状态
+        let _ = child.kill().await;
+        child.wait().await.ok()
+    }
+
+    pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+        status.signal()
+    }
+
+    pub fn set_paused(pid: u32, paused: bool) -> std::io::Result<()> {
+        let signal = if paused { libc::SIGSTOP } else { libc::SIGCONT };
+        // SAFETY: kill 没有内存方面的前置条件，进程已不存在时返回 ESRCH
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+
+    pub fn start_service() -> anyhow::Result<()> {
+        anyhow::bail!("--service is only supported on Windows")
+    }
+
+    pub async fn shutdown_signal() {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut term) = signal(SignalKind::terminate()) else {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        };
+        tokio::select! {
+            _ = term.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+
+    pub fn service_stopped() {}
+
+    pub fn process_usage(pid: u32) -> Option<(f64, u64)> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // 进程名可能包含空格，字段从最后一个 ')' 之后开始 (state 为第 3 个字段)
+        let fields: Vec<&str> = stat
+            .get(stat.rfind(')')? + 1..)?
+            .split_whitespace()
+            .collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let rss_kb = status
+            .lines()
+            .find_map(|l| l.strip_prefix("VmRSS:"))
+            .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+            .unwrap_or(0);
+        Some(((utime + stime) as f64 / CLOCK_TICKS_PER_SEC, rss_kb))
+    }
+
+    pub fn disk_space(path: &std::path::Path) -> Option<(u64, u64)> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: path 以 NUL 结尾，stat 为本地输出参数
+        unsafe {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+                return None;
+            }
+            let block = stat.f_frsize as u64;
+            Some((stat.f_blocks as u64 * block, stat.f_bavail as u64 * block))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::ffi::c_void;
+    use std::process::{ExitStatus, Stdio};
+    use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+    use std::sync::OnceLock;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use tokio::process::{Child, Command};
+    use tokio::sync::Notify;
+    use tracing::error;
+
+    /// 请求 FFmpeg 自行退出后等待的时间，超时后强制结束
+    const STOP_GRACE: Duration = Duration::from_secs(3);
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+    const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+
+    const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+    const SERVICE_STOPPED: u32 = 1;
+    const SERVICE_STOP_PENDING: u32 = 3;
+    const SERVICE_RUNNING: u32 = 4;
+    const SERVICE_ACCEPT_STOP: u32 = 0x1;
+    const SERVICE_ACCEPT_SHUTDOWN: u32 = 0x4;
+    const SERVICE_CONTROL_STOP: u32 = 1;
+    const SERVICE_CONTROL_INTERROGATE: u32 = 4;
+    const SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+    const NO_ERROR: u32 = 0;
+    const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    impl FileTime {
+        /// 100 纳秒为单位
+        fn seconds(&self) -> f64 {
+            (((self.high as u64) << 32) | self.low as u64) as f64 / 10_000_000.0
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ProcessMemoryCounters {
+        cb: u32,
+        page_fault_count: u32,
+        peak_working_set_size: usize,
+        working_set_size: usize,
+        quota_peak_paged_pool_usage: usize,
+        quota_paged_pool_usage: usize,
+        quota_peak_non_paged_pool_usage: usize,
+        quota_non_paged_pool_usage: usize,
+        pagefile_usage: usize,
+        peak_pagefile_usage: usize,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct JobBasicLimits {
+        per_process_user_time_limit: i64,
+        per_job_user_time_limit: i64,
+        limit_flags: u32,
+        minimum_working_set_size: usize,
+        maximum_working_set_size: usize,
+        active_process_limit: u32,
+        affinity: usize,
+        priority_class: u32,
+        scheduling_class: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct JobExtendedLimits {
+        basic: JobBasicLimits,
+        io_counters: [u64; 6],
+        process_memory_limit: usize,
+        job_memory_limit: usize,
+        peak_process_memory_used: usize,
+        peak_job_memory_used: usize,
+    }
+
+    #[repr(C)]
+    struct ServiceStatus {
+        service_type: u32,
+        current_state: u32,
+        controls_accepted: u32,
+        win32_exit_code: u32,
+        service_specific_exit_code: u32,
+        check_point: u32,
+        wait_hint: u32,
+    }
+
+    #[repr(C)]
+    struct ServiceTableEntry {
+        name: *mut u16,
+        proc_: Option<unsafe extern "system" fn(u32, *mut *mut u16)>,
+    }
+
+    type HandlerEx = unsafe extern "system" fn(u32, u32, *mut c_void, *mut c_void) -> u32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+        fn GetProcessTimes(
+            process: *mut c_void,
+            creation: *mut FileTime,
+            exit: *mut FileTime,
+            kernel: *mut FileTime,
+            user: *mut FileTime,
+        ) -> i32;
+        fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> *mut c_void;
+        fn SetInformationJobObject(
+            job: *mut c_void,
+            class: i32,
+            info: *const c_void,
+            length: u32,
+        ) -> i32;
+        fn AssignProcessToJobObject(job: *mut c_void, process: *mut c_void) -> i32;
+        fn TerminateJobObject(job: *mut c_void, exit_code: u32) -> i32;
+        fn K32GetProcessMemoryInfo(
+            process: *mut c_void,
+            counters: *mut ProcessMemoryCounters,
+            cb: u32,
+        ) -> i32;
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn StartServiceCtrlDispatcherW(table: *const ServiceTableEntry) -> i32;
+        fn RegisterServiceCtrlHandlerExW(
+            name: *const u16,
+            handler: HandlerEx,
+            context: *mut c_void,
+        ) -> isize;
+        fn SetServiceStatus(handle: isize, status: *const ServiceStatus) -> i32;
+    }
+
+    /// 服务状态句柄 (0 表示未以服务方式运行)
+    static STATUS_HANDLE: AtomicIsize = AtomicIsize::new(0);
+    static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    fn shutdown() -> &'static Notify {
+        static SHUTDOWN: OnceLock<Notify> = OnceLock::new();
+        SHUTDOWN.get_or_init(Notify::new)
+    }
+
+    fn service_name() -> Vec<u16> {
+        "vtx-link\0".encode_utf16().collect()
+    }
+
+    fn report(state: u32) {
+        let handle = STATUS_HANDLE.load(Ordering::Acquire);
+        if handle == 0 {
+            return;
+        }
+        let status = ServiceStatus {
+            service_type: SERVICE_WIN32_OWN_PROCESS,
+            current_state: state,
+            controls_accepted: if state == SERVICE_RUNNING {
+                SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+            } else {
+                0
+            },
+            win32_exit_code: NO_ERROR,
+            service_specific_exit_code: 0,
+            check_point: 0,
+            wait_hint: if state == SERVICE_STOP_PENDING {
+                30_000
+            } else {
+                0
+            },
+        };
+        // SAFETY: 句柄来自 RegisterServiceCtrlHandlerExW，status 在调用期间有效
+        unsafe {
+            SetServiceStatus(handle, &status);
+        }
+    }
+
+    unsafe extern "system" fn control_handler(
+        control: u32,
+        _event_type: u32,
+        _event_data: *mut c_void,
+        _context: *mut c_void,
+    ) -> u32 {
+        match control {
+            SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                report(SERVICE_STOP_PENDING);
+                STOP_REQUESTED.store(true, Ordering::Release);
+                shutdown().notify_waiters();
+                NO_ERROR
+            }
+            SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+            _ => ERROR_CALL_NOT_IMPLEMENTED,
+        }
+    }
+
+    unsafe extern "system" fn service_main(_argc: u32, _argv: *mut *mut u16) {
+        let name = service_name();
+        let handle =
+            RegisterServiceCtrlHandlerExW(name.as_ptr(), control_handler, std::ptr::null_mut());
+        if handle == 0 {
+            error!(
+                "RegisterServiceCtrlHandlerExW failed: {}",
+                std::io::Error::last_os_error()
+            );
+            return;
+        }
+        STATUS_HANDLE.store(handle, Ordering::Release);
+        report(SERVICE_RUNNING);
+        // 网关在主线程运行；本函数返回后服务线程结束，状态由 service_stopped 报告
+    }
+
+    pub fn prepare_child(cmd: &mut Command) {
+        cmd.stdin(Stdio::piped()).creation_flags(CREATE_NO_WINDOW);
+    }
+
+    /// 作业对象句柄 (以整数保存以便跨线程传递)
+    pub struct Group {
+        job: isize,
+    }
+
+    impl Group {
+        /// 作业对象关闭时结束其中的全部进程，网关自身异常退出时同样生效
+        pub fn adopt(child: &Child) -> Option<Self> {
+            let process = child.raw_handle()?;
+            // SAFETY: 句柄由本函数创建或来自仍在运行的子进程，limits 在调用期间有效
+            unsafe {
+                let job = CreateJobObjectW(std::ptr::null_mut(), std::ptr::null());
+                if job.is_null() {
+                    return None;
+                }
+                let mut limits = JobExtendedLimits::default();
+                limits.basic.limit_flags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                let configured = SetInformationJobObject(
+                    job,
+                    JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+                    &limits as *const JobExtendedLimits as *const c_void,
+                    std::mem::size_of::<JobExtendedLimits>() as u32,
+                ) != 0;
+                if !configured || AssignProcessToJobObject(job, process as *mut c_void) == 0 {
+                    CloseHandle(job);
+                    return None;
+                }
+                Some(Self { job: job as isize })
+            }
+        }
+
+        pub fn kill(&self) {
+            // SAFETY: 句柄在 Group 存活期间有效
+            unsafe {
+                TerminateJobObject(self.job as *mut c_void, 1);
+            }
+        }
+    }
+
+    impl Drop for Group {
+        fn drop(&mut self) {
+            // SAFETY: 句柄只在此处关闭一次
+            unsafe {
+                CloseHandle(self.job as *mut c_void);
+            }
+        }
+    }
+
+    pub async fn terminate(child: &mut Child) -> Option<ExitStatus> {
+        if let Some(mut stdin) = child.stdin.take() {
+            if stdin.write_all(b"q").await.is_ok() {
+                drop(stdin);
+                if let Ok(status) = tokio::time::timeout(STOP_GRACE, child.wait()).await {
+                    return status.ok();
+                }
+            }
+        }
+        let _ = child.kill().await;
+        child.wait().await.ok()
+    }
+
+    pub fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+        None
+    }
+
+    pub fn set_paused(_pid: u32, _paused: bool) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Pausing processes is not supported on Windows",
+        ))
+    }
+
+    pub fn start_service() -> anyhow::Result<()> {
+        std::thread::Builder::new()
+            .name("service-dispatcher".to_string())
+            .spawn(|| {
+                let mut name = service_name();
+                let table = [
+                    ServiceTableEntry {
+                        name: name.as_mut_ptr(),
+                        proc_: Some(service_main),
+                    },
+                    ServiceTableEntry {
+                        name: std::ptr::null_mut(),
+                        proc_: None,
+                    },
+                ];
+                // SAFETY: 服务表以空项结尾，且在分发期间 (本线程阻塞) 保持有效
+                if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+                    error!(
+                        "Could not connect to the service control manager: {}",
+                        std::io::Error::last_os_error()
+                    );
+                }
+            })?;
+        Ok(())
+    }
+
+    pub async fn shutdown_signal() {
+        let notified = shutdown().notified();
+        if STOP_REQUESTED.load(Ordering::Acquire) {
+            return;
+        }
+        tokio::select! {
+            _ = notified => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+
+    pub fn service_stopped() {
+        report(SERVICE_STOPPED);
+    }
+
+    pub fn process_usage(pid: u32) -> Option<(f64, u64)> {
+        // SAFETY: 句柄在本函数内打开并关闭，输出结构体均为本地变量
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return None;
+            }
+            let mut creation = FileTime::default();
+            let mut exit = FileTime::default();
+            let mut kernel = FileTime::default();
+            let mut user = FileTime::default();
+            let times = GetProcessTimes(process, &mut creation, &mut exit, &mut kernel, &mut user);
+            let mut counters = ProcessMemoryCounters {
+                cb: std::mem::size_of::<ProcessMemoryCounters>() as u32,
+                ..Default::default()
+            };
+            let memory = K32GetProcessMemoryInfo(process, &mut counters, counters.cb);
+            CloseHandle(process);
+            if times == 0 {
+                return None;
+            }
+            let rss_kb = if memory != 0 {
+                counters.working_set_size as u64 / 1024
+            } else {
+                0
+            };
+            Some((kernel.seconds() + user.seconds(), rss_kb))
+        }
+    }
+
+    pub fn disk_space(path: &std::path::Path) -> Option<(u64, u64)> {
+        use std::os::windows::ffi::OsStrExt;
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let (mut available, mut total, mut free) = (0u64, 0u64, 0u64);
+        // SAFETY: path 以 NUL 结尾，输出参数均为本地变量
+        let ok =
+            unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, &mut total, &mut free) };
+        (ok != 0).then_some((total, available))
+    }
+}
@@ -0,0 +1,37 @@
+use crate::config::StreamConfig;
+use crate::forward_auth::{self, PlaybackRequest};
+use crate::geoip;
+use crate::jwt;
+use crate::maintenance;
+use crate::referer;
+use crate::state::AppState;
+use axum::http::{HeaderMap, StatusCode};
+
+/// 拉流的准入检查 (HLS、MPEG-TS 与 RTSP 共用)：维护模式、来源页面、地区限制、
+/// JWT 与外部鉴权服务，拒绝时返回应答给播放器的状态与说明
+///
+/// 排空 (drain) 由各协议自行处理：HLS 只拒绝播放列表，已在播放的观看者继续拉取切片
+pub async fn admit(
+    state: &AppState,
+    cfg: &StreamConfig,
+    headers: &HeaderMap,
+    req: PlaybackRequest<'_>,
+) -> Result<(), (StatusCode, String)> {
+    // 运维停用的流在重新启用前不提供任何内容
+    if maintenance::is_disabled(state, &cfg.name) {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Stream is disabled for maintenance".to_string(),
+        ));
+    }
+
+    // 防盗链与地区限制：只允许指定的嵌入站点与国家/地区
+    referer::check(cfg, headers)?;
+    geoip::check(state, cfg, req.client_ip)?;
+
+    // 签名的播放令牌 (JWT)
+    jwt::check(state, cfg, req.token).await?;
+
+    // 外部服务持有的授权在启动或下发任何内容之前检查
+    forward_auth::check(state, cfg, req).await
+}
@@ -0,0 +1,212 @@
+use crate::abr;
+use crate::clock;
+use crate::config::StreamConfig;
+use crate::discovery::percent_encode;
+use crate::segment_index::DirIndex;
+use std::time::{Duration, SystemTime};
+
+/// 下发前改写播放列表中的切片地址 (媒体行与 `#EXT-X-MAP` 的 URI)
+///
+/// - 相对地址拼接 `segment_base_url` 成为绝对地址，已是绝对地址的保持不变
+/// - 追加 `segment_query`，其中 `{token}` 替换为观看者的令牌 (无令牌时为空)
+///
+/// 子播放列表与解密密钥 (`#EXT-X-KEY`) 不改写：前者需要经过网关维持观看会话，
+/// 后者不允许被 CDN 缓存
+pub fn rewrite(text: &str, cfg: &StreamConfig, token: Option<&str>) -> String {
+    let rules = &cfg.playlist;
+    if rules.is_empty() {
+        return text.to_string();
+    }
+    let base = rules.segment_base_url.as_deref().map(|base| {
+        let base = base
+            .replace("{tenant}", cfg.tenant.as_deref().unwrap_or_default())
+            .replace("{name}", &cfg.name)
+            .replace("{id}", cfg.id.as_deref().unwrap_or(&cfg.name));
+        if base.ends_with('/') {
+            base
+        } else {
+            format!("{}/", base)
+        }
+    });
+    let query = rules.segment_query.as_deref().map(|q| {
+        q.trim_start_matches(['?', '&'])
+            .replace("{token}", &percent_encode(token.unwrap_or_default()))
+    });
+    let rewrite_uri = |uri: &str| {
+        let mut out = match &base {
+            Some(base) if !uri.contains("://") => {
+                format!("{}{}", base, uri.trim_start_matches('/'))
+            }
+            _ => uri.to_string(),
+        };
+        if let Some(query) = &query {
+            out.push(if out.contains('?') { '&' } else { '?' });
+            out.push_str(query);
+        }
+        out
+    };
+
+    let mut out = String::with_capacity(text.len());
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("#EXT-X-MAP:") {
+            out.push_str(&rewrite_attribute(trimmed, &rewrite_uri));
+        } else if trimmed.is_empty() || trimmed.starts_with('#') || is_playlist_uri(trimmed) {
+            out.push_str(line);
+        } else {
+            out.push_str(&rewrite_uri(trimmed));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn is_playlist_uri(uri: &str) -> bool {
+    uri.split('?').next().unwrap_or_default().ends_with(".m3u8")
+}
+
+/// 改写标签中的 `URI="..."` 属性
+fn rewrite_attribute(line: &str, rewrite_uri: &impl Fn(&str) -> String) -> String {
+    let Some(start) = line.find("URI=\"") else {
+        return line.to_string();
+    };
+    let value_start = start + 5;
+    let Some(len) = line[value_start..].find('"') else {
+        return line.to_string();
+    };
+    format!(
+        "{}{}{}",
+        &line[..value_start],
+        rewrite_uri(&line[value_start..value_start + len]),
+        &line[value_start + len..]
+    )
+}
+
+/// 为缺少 `#EXT-X-PROGRAM-DATE-TIME` 的播放列表补充墙上时间
+///
+/// 切片文件在写完时落盘，其修改时间 (取自输出目录索引) 减去切片时长即为切片开始时间。
+/// FFmpeg 已输出该标签 (relay 模式的 program_date_time) 时原样返回
+pub fn add_program_date_time(text: &str, index: &DirIndex) -> String {
+    if text.contains("#EXT-X-PROGRAM-DATE-TIME") {
+        return text.to_string();
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = String::with_capacity(text.len() + lines.len() * 40);
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(info) = line.trim().strip_prefix("#EXTINF:") {
+            let duration: f64 = info
+                .split(',')
+                .next()
+                .unwrap_or_default()
+                .parse()
+                .unwrap_or(0.0);
+            let uri = lines[i + 1..]
+                .iter()
+                .map(|l| l.trim())
+                .find(|l| !l.is_empty() && !l.starts_with('#'));
+            if let Some(start) = uri.and_then(|uri| segment_start(index, uri, duration)) {
+                out.push_str("#EXT-X-PROGRAM-DATE-TIME:");
+                out.push_str(&clock::rfc3339_millis(start));
+                out.push('\n');
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// 由本地切片的修改时间推算其开始时间
+fn segment_start(index: &DirIndex, uri: &str, duration: f64) -> Option<SystemTime> {
+    let name = uri.split('?').next()?;
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        return None;
+    }
+    let modified = index.modified(name)?;
+    modified.checked_sub(Duration::try_from_secs_f64(duration).ok()?)
+}
+
+/// 多音轨/字幕流由网关生成的 master 播放列表
+pub const MASTER_PLAYLIST: &str = "master.m3u8";
+
+/// 未配置带宽上限时在 master 播放列表中声明的码率 (bps)
+///
+/// 仅供播放器初始选择，网关不测量实际码率
+const NOMINAL_BANDWIDTH: u64 = 2_000_000;
+
+/// 第 `i` 条音轨的播放列表文件名
+pub fn audio_playlist(i: usize) -> String {
+    format!("audio_{}.m3u8", i)
+}
+
+/// 第 `i` 条字幕的播放列表文件名
+pub fn subtitle_playlist(i: usize) -> String {
+    format!("subs_{}.m3u8", i)
+}
+
+/// 生成登记音轨 (`EXT-X-MEDIA TYPE=AUDIO`) 与字幕 (`TYPE=SUBTITLES`) 的 master 播放列表，
+/// 每组第一条为默认选项；配置了 ABR 时在源画质的主输出之后登记各档
+pub fn master(cfg: &StreamConfig) -> String {
+    let mut out = String::from("#EXTM3U\n#EXT-X-VERSION:4\n");
+    let flag = |first: bool| if first { "YES" } else { "NO" };
+    for (i, track) in cfg.audio_tracks.iter().enumerate() {
+        out.push_str(&format!(
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",LANGUAGE=\"{}\",NAME=\"{}\",DEFAULT={},AUTOSELECT=YES,URI=\"{}\"\n",
+            track.language,
+            track.name.as_deref().unwrap_or(&track.language),
+            flag(i == 0),
+            audio_playlist(i)
+        ));
+    }
+    for (i, sub) in cfg.subtitles.iter().enumerate() {
+        out.push_str(&format!(
+            "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",LANGUAGE=\"{}\",NAME=\"{}\",DEFAULT={},AUTOSELECT=YES,URI=\"{}\"\n",
+            sub.language,
+            sub.name.as_deref().unwrap_or(&sub.language),
+            flag(i == 0),
+            subtitle_playlist(i)
+        ));
+    }
+
+    let bandwidth = match cfg.max_bandwidth_kbps {
+        0 => NOMINAL_BANDWIDTH,
+        kbps => kbps * 1000,
+    };
+    out.push_str(&format!("#EXT-X-STREAM-INF:BANDWIDTH={}", bandwidth));
+    let groups = format!(
+        "{}{}",
+        if cfg.audio_tracks.is_empty() {
+            ""
+        } else {
+            ",AUDIO=\"audio\""
+        },
+        if cfg.subtitles.is_empty() {
+            ""
+        } else {
+            ",SUBTITLES=\"subs\""
+        }
+    );
+    out.push_str(&groups);
+    out.push_str("\nindex.m3u8\n");
+    // ABR 各档按声明的视频与音频码率登记
+    if let Some(abr) = &cfg.abr {
+        for rendition in &abr.renditions {
+            let kbps = rendition.bitrate_kbps as u64 + abr.audio_bitrate_kbps as u64;
+            out.push_str(&format!(
+                "#EXT-X-STREAM-INF:BANDWIDTH={}{}\n{}\n",
+                kbps * 1000,
+                groups,
+                abr::playlist(rendition)
+            ));
+        }
+    }
+    out
+}
+
+/// 冷启动时下发的占位播放列表 (`cold_start: priming`)
+///
+/// 没有切片也没有 `#EXT-X-ENDLIST`，目标时长取 1 秒让播放器尽快重新请求；
+/// `#EXT-X-START` (负偏移) 让播放器在真实切片出现后从直播点附近开始播放
+pub fn priming() -> String {
+    "#EXTM3U\n#EXT-X-VERSION:6\n#EXT-X-TARGETDURATION:1\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-START:TIME-OFFSET=-1.0\n".to_string()
+}
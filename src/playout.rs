@@ -0,0 +1,240 @@
+use crate::clock;
+use crate::config::{AppConfig, FileLoopConfig, PlayoutSlot, StreamConfig, Weekday};
+use crate::engine::Engine;
+use crate::snapshot;
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+/// 节目表检查间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 当前应播放的节目
+#[derive(Debug, Clone, Copy)]
+pub struct Program<'a> {
+    /// 生效的节目时段序号，None 表示播放垫片
+    pub slot: Option<usize>,
+    pub files: &'a [String],
+    /// 播放位置的起点 (Unix 秒)：时段为本次开始时间，垫片为 epoch
+    pub origin: u64,
+}
+
+/// 计算指定时间应播放的节目
+pub fn current(fl: &FileLoopConfig, now: u64) -> Program<'_> {
+    let offset = fl.utc_offset_min as i64 * 60;
+    let local = now as i64 + offset;
+    let today = local.div_euclid(86400);
+    for (i, slot) in fl.schedule.iter().enumerate() {
+        let (Some(start), Some(end)) = (parse_time(&slot.start), parse_time(&slot.end)) else {
+            continue;
+        };
+        let length = if end > start {
+            end - start
+        } else {
+            end + 1440 - start
+        } as i64
+            * 60;
+        // 跨越午夜的时段可能从前一天开始
+        for day in [today, today - 1] {
+            let begin = day * 86400 + start as i64 * 60;
+            let on_day = slot.days.is_empty() || slot.days.contains(&weekday(day));
+            if on_day && (begin..begin + length).contains(&local) {
+                return Program {
+                    slot: Some(i),
+                    files: &slot.files,
+                    origin: (begin - offset).max(0) as u64,
+                };
+            }
+        }
+    }
+    Program {
+        slot: None,
+        files: &fl.files,
+        origin: fl.epoch,
+    }
+}
+
+/// 1970-01-01 为星期四
+fn weekday(day: i64) -> Weekday {
+    const DAYS: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+    DAYS[(day + 3).rem_euclid(7) as usize]
+}
+
+/// 解析 `HH:MM`，返回当天的分钟数
+fn parse_time(s: &str) -> Option<u32> {
+    let (h, m) = s.split_once(':')?;
+    let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+fn valid_files(files: &[String]) -> bool {
+    !files.is_empty()
+        && files
+            .iter()
+            .all(|f| !f.is_empty() && !f.contains(['\n', '\r']))
+}
+
+/// 校验循环文件与节目表
+pub fn validate(stream: &StreamConfig, fl: &FileLoopConfig) -> anyhow::Result<()> {
+    if !valid_files(&fl.files) {
+        anyhow::bail!(
+            "Stream [{}] needs non-empty file_loop.files without line breaks",
+            stream.name
+        );
+    }
+    if !(-720..=840).contains(&fl.utc_offset_min) {
+        anyhow::bail!(
+            "Stream [{}] file_loop.utc_offset_min must be between -720 and 840",
+            stream.name
+        );
+    }
+    for (i, slot) in fl.schedule.iter().enumerate() {
+        if parse_time(&slot.start).is_none() || parse_time(&slot.end).is_none() {
+            anyhow::bail!(
+                "Stream [{}] schedule slot {} must use HH:MM start and end times",
+                stream.name,
+                i
+            );
+        }
+        if !valid_files(&slot.files) {
+            anyhow::bail!(
+                "Stream [{}] schedule slot {} needs files without line breaks",
+                stream.name,
+                i
+            );
+        }
+    }
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// 节目表调度：运行中的循环文件流在节目时段切换时重启，改为播放新时段的文件
+pub async fn start_scheduler(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let config = state.config();
+        let now = now_secs();
+        let due: Vec<(String, Option<usize>)> = {
+            let active = state.active_streams.lock_or_recover();
+            let playing = state.playout_slots.lock_or_recover();
+            config
+                .streams
+                .iter()
+                .filter(|s| active.contains_key(&s.name))
+                .filter_map(|s| {
+                    let fl = s.file_loop.as_ref().filter(|fl| !fl.schedule.is_empty())?;
+                    let slot = current(fl, now).slot;
+                    (playing.get(&s.name)? != &slot).then(|| (s.name.clone(), slot))
+                })
+                .collect()
+        };
+        for (name, slot) in due {
+            match slot {
+                Some(i) => info!("Stream [{}] switching to schedule slot {}", name, i),
+                None => info!("Stream [{}] switching to filler content", name),
+            }
+            restart(&state, &name).await;
+        }
+    }
+}
+
+async fn restart(state: &Arc<AppState>, name: &str) {
+    let _ = Engine::stop_stream(state, name).await;
+    if let Err(e) = Engine::start_stream(state, name).await {
+        error!("Restart failed [{}]: {}", name, e);
+    }
+}
+
+/// 节目表查询结果
+#[derive(Debug, Serialize)]
+pub struct ScheduleView {
+    pub now: String,
+    pub utc_offset_min: i32,
+    /// 当前生效的节目时段序号，None 表示播放垫片
+    pub active_slot: Option<usize>,
+    /// 当前应播放的文件
+    pub playing: Vec<String>,
+    pub filler: Vec<String>,
+    pub schedule: Vec<PlayoutSlot>,
+}
+
+/// 流的节目表与当前节目，非循环文件流返回 None
+pub fn view(cfg: &StreamConfig) -> Option<ScheduleView> {
+    let fl = cfg.file_loop.as_ref()?;
+    let now = SystemTime::now();
+    let program = current(fl, now_secs());
+    Some(ScheduleView {
+        now: clock::rfc3339(now),
+        utc_offset_min: fl.utc_offset_min,
+        active_slot: program.slot,
+        playing: program.files.to_vec(),
+        filler: fl.files.clone(),
+        schedule: fl.schedule.clone(),
+    })
+}
+
+/// 节目表修改请求，省略的字段保持不变
+#[derive(Debug, Deserialize)]
+pub struct ScheduleUpdate {
+    pub schedule: Vec<PlayoutSlot>,
+    #[serde(default)]
+    pub filler: Option<Vec<String>>,
+    #[serde(default)]
+    pub utc_offset_min: Option<i32>,
+    /// 是否写回配置文件
+    #[serde(default)]
+    pub persist: bool,
+}
+
+/// 替换流的节目表，运行中的流随即按新节目表重启
+pub async fn update(state: &Arc<AppState>, name: &str, req: ScheduleUpdate) -> anyhow::Result<()> {
+    let guard = state.config_lock.lock().await;
+    let mut next: AppConfig = (*state.config()).clone();
+    let fl = next
+        .streams
+        .iter_mut()
+        .find(|s| s.name == name)
+        .and_then(|s| s.file_loop.as_mut())
+        .ok_or_else(|| anyhow::anyhow!("Stream [{}] is not a file_loop channel", name))?;
+    fl.schedule = req.schedule;
+    if let Some(filler) = req.filler {
+        fl.files = filler;
+    }
+    if let Some(offset) = req.utc_offset_min {
+        fl.utc_offset_min = offset;
+    }
+    next.validate()?;
+
+    let running = state.active_streams.lock_or_recover().contains_key(name);
+    if req.persist {
+        snapshot::apply(state, &state.config_path, next).await?;
+    } else {
+        state.replace_config(next);
+    }
+    drop(guard);
+    info!(
+        "Schedule of stream [{}] updated (persisted: {})",
+        name, req.persist
+    );
+    if running {
+        restart(state, name).await;
+    }
+    Ok(())
+}
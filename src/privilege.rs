@@ -0,0 +1,224 @@
+use crate::config::AppConfig;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::info;
+
+/// FFmpeg 子进程使用的用户 (仅当与网关进程不同时设置)
+static MEDIA_OWNER: OnceLock<Owner> = OnceLock::new();
+
+/// 用户与组 ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// 切换用户前的准备：解析用户、调整目录属主并校验 hls_root 的属主
+///
+/// 返回网关进程需要切换到的用户；监听端口后调用 `drop_to` 完成切换
+pub fn prepare(config: &AppConfig) -> anyhow::Result<Option<Owner>> {
+    let Some(privileges) = &config.privileges else {
+        return Ok(None);
+    };
+    if !cfg!(unix) {
+        anyhow::bail!("privileges are only supported on Unix");
+    }
+    let process = privileges
+        .user
+        .as_deref()
+        .map(|user| resolve(user, privileges.group.as_deref()))
+        .transpose()?;
+    let media = privileges
+        .ffmpeg_user
+        .as_deref()
+        .map(|user| resolve(user, privileges.ffmpeg_group.as_deref()))
+        .transpose()?
+        .filter(|media| Some(*media) != process);
+
+    // 媒体目录由 FFmpeg 写入，归属 FFmpeg 用户；运行状态目录归属网关进程
+    let server = &config.server;
+    let mut dirs = Vec::new();
+    if let Some(owner) = media.or(process) {
+        for dir in [&server.hls_root, &server.key_root, &server.record_root] {
+            dirs.push((dir, owner));
+        }
+    }
+    if let Some(owner) = process {
+        dirs.push((&server.state_root, owner));
+    }
+
+    let euid = sys::current_uid();
+    if euid != 0 {
+        if let Some(owner) = media.or(process).filter(|o| o.uid != euid) {
+            anyhow::bail!(
+                "privileges: switching to uid {} requires starting as root (current uid {})",
+                owner.uid,
+                euid
+            );
+        }
+        return Ok(None);
+    }
+
+    for (dir, owner) in &dirs {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dir, e))?;
+        if privileges.chown_dirs {
+            chown_recursive(Path::new(dir), *owner)
+                .map_err(|e| anyhow::anyhow!("Failed to chown {}: {}", dir, e))?;
+        }
+    }
+    if let Some((dir, owner)) = dirs.first() {
+        let uid = sys::owner_uid(Path::new(dir))
+            .map_err(|e| anyhow::anyhow!("Failed to stat {}: {}", dir, e))?;
+        if uid != owner.uid {
+            anyhow::bail!(
+                "server.hls_root {} is owned by uid {} instead of {}; fix its ownership or enable privileges.chown_dirs",
+                dir,
+                uid,
+                owner.uid
+            );
+        }
+    }
+
+    if let Some(media) = media {
+        info!(
+            "FFmpeg processes will run as uid {} gid {}",
+            media.uid, media.gid
+        );
+        let _ = MEDIA_OWNER.set(media);
+    }
+    Ok(process)
+}
+
+/// 放弃 root 权限 (须在监听特权端口之后调用)
+pub fn drop_to(owner: Owner) -> anyhow::Result<()> {
+    sys::drop_to(owner)?;
+    if sys::current_uid() == 0 {
+        anyhow::bail!("privileges: still running as root after switching user");
+    }
+    info!("Dropped privileges to uid {} gid {}", owner.uid, owner.gid);
+    Ok(())
+}
+
+/// 让 FFmpeg 子进程以配置的用户运行
+pub fn apply_to_child(cmd: &mut tokio::process::Command) {
+    if let Some(owner) = MEDIA_OWNER.get() {
+        sys::set_child_owner(cmd, *owner);
+    }
+}
+
+/// 将网关 (root) 创建、需由 FFmpeg 读写的文件或目录交给 FFmpeg 用户
+pub fn chown_media(path: &Path) -> std::io::Result<()> {
+    match MEDIA_OWNER.get() {
+        Some(owner) => sys::chown(path, *owner),
+        None => Ok(()),
+    }
+}
+
+/// 用户可写为用户名或数字 ID；组缺省取用户的主组
+fn resolve(user: &str, group: Option<&str>) -> anyhow::Result<Owner> {
+    let passwd = std::fs::read_to_string("/etc/passwd").unwrap_or_default();
+    let entry = passwd
+        .lines()
+        .map(|l| l.split(':').collect::<Vec<_>>())
+        .find(|f| f.len() > 3 && (f[0] == user || f[2] == user));
+    let (uid, primary_gid) = match (&entry, user.parse::<u32>()) {
+        (Some(f), _) => (f[2].parse()?, f[3].parse()?),
+        (None, Ok(uid)) => (uid, uid),
+        (None, Err(_)) => anyhow::bail!("privileges: unknown user {}", user),
+    };
+    let gid = match group {
+        None => primary_gid,
+        Some(group) => {
+            let groups = std::fs::read_to_string("/etc/group").unwrap_or_default();
+            let entry = groups
+                .lines()
+                .map(|l| l.split(':').collect::<Vec<_>>())
+                .find(|f| f.len() > 2 && (f[0] == group || f[2] == group));
+            match (entry, group.parse::<u32>()) {
+                (Some(f), _) => f[2].parse()?,
+                (None, Ok(gid)) => gid,
+                (None, Err(_)) => anyhow::bail!("privileges: unknown group {}", group),
+            }
+        }
+    };
+    if uid == 0 {
+        anyhow::bail!("privileges: user {} is root", user);
+    }
+    Ok(Owner { uid, gid })
+}
+
+fn chown_recursive(path: &Path, owner: Owner) -> std::io::Result<()> {
+    sys::chown(path, owner)?;
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_recursive(&entry?.path(), owner)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::Owner;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    pub fn drop_to(owner: Owner) -> anyhow::Result<()> {
+        // 顺序不可颠倒：放弃 uid 后无权再修改组
+        // SAFETY: 仅传入本地数组的指针与长度，不涉及其他内存
+        unsafe {
+            if libc::setgroups(1, &owner.gid) != 0 {
+                anyhow::bail!("setgroups failed: {}", std::io::Error::last_os_error());
+            }
+            if libc::setgid(owner.gid) != 0 {
+                anyhow::bail!("setgid failed: {}", std::io::Error::last_os_error());
+            }
+            if libc::setuid(owner.uid) != 0 {
+                anyhow::bail!("setuid failed: {}", std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_child_owner(cmd: &mut tokio::process::Command, owner: Owner) {
+        cmd.uid(owner.uid).gid(owner.gid);
+    }
+
+    pub fn chown(path: &Path, owner: Owner) -> std::io::Result<()> {
+        std::os::unix::fs::lchown(path, Some(owner.uid), Some(owner.gid))
+    }
+
+    pub fn current_uid() -> u32 {
+        // SAFETY: geteuid 没有前置条件且不会失败
+        unsafe { libc::geteuid() }
+    }
+
+    pub fn owner_uid(path: &Path) -> std::io::Result<u32> {
+        Ok(std::fs::metadata(path)?.uid())
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use super::Owner;
+    use std::path::Path;
+
+    pub fn drop_to(_owner: Owner) -> anyhow::Result<()> {
+        anyhow::bail!("privileges are only supported on Unix")
+    }
+
+    pub fn set_child_owner(_cmd: &mut tokio::process::Command, _owner: Owner) {}
+
+    pub fn chown(_path: &Path, _owner: Owner) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    pub fn current_uid() -> u32 {
+        0
+    }
+
+    pub fn owner_uid(_path: &Path) -> std::io::Result<u32> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,276 @@
+use crate::config::StreamConfig;
+use crate::credentials;
+use crate::engine::Engine;
+use crate::http_client::{self, Url};
+use crate::state::{AppState, LockExt};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tracing::{info, warn};
+
+/// 上游请求超时
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 入口播放列表的本地文件名
+const ENTRY_PLAYLIST: &str = "index.m3u8";
+
+/// 代理流的运行时会话
+pub struct ProxySession {
+    /// 本地文件名 -> 上游地址
+    uri_map: HashMap<String, Url>,
+    /// 各播放列表当前引用的本地文件 (用于淘汰过期缓存)
+    referenced: HashMap<String, HashSet<String>>,
+    /// 最后一次有观看会话活跃的时间 (用于空闲回收)
+    pub last_accessed: Instant,
+    /// 会话建立时间
+    pub started_at: Instant,
+}
+
+/// 代理响应
+pub struct ProxyResponse {
+    pub body: Vec<u8>,
+    pub content_type: String,
+}
+
+/// 处理代理流的文件请求
+///
+/// # 流程
+/// - 播放列表：每次从上游拉取，改写其中的 URI 为本地文件名
+/// - 切片：优先读取 RAMDisk 缓存，未命中时从上游拉取并写入缓存
+pub async fn serve(
+    state: &AppState,
+    cfg: &StreamConfig,
+    file_name: &str,
+) -> anyhow::Result<ProxyResponse> {
+    let output_dir = Engine::output_dir(state, cfg);
+    let source = Url::parse(&credentials::expand(&state.config(), &cfg.source)?)?;
+
+    // 1. 建立或刷新会话，首次建立时清理残留缓存
+    let (upstream, is_new) = {
+        let mut sessions = state.proxy_sessions.lock_or_recover();
+        let is_new = !sessions.contains_key(&cfg.name);
+        let session = sessions
+            .entry(cfg.name.clone())
+            .or_insert_with(|| ProxySession {
+                uri_map: HashMap::new(),
+                referenced: HashMap::new(),
+                last_accessed: Instant::now(),
+                started_at: Instant::now(),
+            });
+        if is_new {
+            info!("Proxy session opened for stream [{}]", cfg.name);
+        }
+
+        let upstream = match session.uri_map.get(file_name) {
+            Some(url) => url.clone(),
+            None if file_name == ENTRY_PLAYLIST => source.clone(),
+            // 未登记的文件名按源播放列表所在目录解析 (对端 vtx-link 节点的约定)
+            None => source.join(file_name)?,
+        };
+        (upstream, is_new)
+    };
+    if is_new {
+        let _ = fs::remove_dir_all(&output_dir).await;
+    }
+
+    if is_playlist(file_name) {
+        let res = fetch(&upstream).await?;
+        let text = String::from_utf8_lossy(&res.body);
+        let (rewritten, referenced) = rewrite_playlist(state, cfg, &upstream, &text);
+        prune_cache(state, cfg, file_name, referenced).await;
+
+        return Ok(ProxyResponse {
+            body: rewritten.into_bytes(),
+            content_type: "application/vnd.apple.mpegurl".to_string(),
+        });
+    }
+
+    let content_type = mime_guess::from_path(file_name)
+        .first_or_octet_stream()
+        .to_string();
+
+    // 2. 命中缓存直接返回
+    let cache_path = output_dir.join(file_name);
+    if let Ok(body) = fs::read(&cache_path).await {
+        return Ok(ProxyResponse { body, content_type });
+    }
+
+    // 3. 回源并写入缓存 (临时文件 + rename，避免并发读到半个切片)
+    let res = fetch(&upstream).await?;
+    fs::create_dir_all(&output_dir).await?;
+    let tmp_path = output_dir.join(format!(".{}.tmp", file_name));
+    if let Err(e) = write_cache(&tmp_path, &cache_path, &res.body).await {
+        warn!(
+            "Proxy cache write failed [{}/{}]: {}",
+            cfg.name, file_name, e
+        );
+    }
+
+    Ok(ProxyResponse {
+        body: res.body,
+        content_type,
+    })
+}
+
+/// 关闭代理会话并清理缓存目录
+pub async fn close(state: &AppState, cfg: &StreamConfig) {
+    let removed = state.proxy_sessions.lock_or_recover().remove(&cfg.name);
+    if removed.is_some() {
+        let _ = fs::remove_dir_all(Engine::output_dir(state, cfg)).await;
+        info!("Proxy session closed for stream [{}]", cfg.name);
+    }
+}
+
+async fn fetch(url: &Url) -> anyhow::Result<http_client::HttpResponse> {
+    let res = http_client::request_url("GET", url, &[], None, UPSTREAM_TIMEOUT).await?;
+    if !res.is_success() {
+        anyhow::bail!("Upstream responded with status {}", res.status);
+    }
+    Ok(res)
+}
+
+async fn write_cache(
+    tmp: &std::path::Path,
+    dst: &std::path::Path,
+    body: &[u8],
+) -> std::io::Result<()> {
+    fs::write(tmp, body).await?;
+    fs::rename(tmp, dst).await
+}
+
+fn is_playlist(file_name: &str) -> bool {
+    file_name.ends_with(".m3u8")
+}
+
+/// 改写播放列表中的 URI (媒体行以及 `URI="..."` 属性) 为本地文件名，并登记映射
+///
+/// 返回改写后的内容和本播放列表引用的本地文件集合
+fn rewrite_playlist(
+    state: &AppState,
+    cfg: &StreamConfig,
+    base: &Url,
+    text: &str,
+) -> (String, HashSet<String>) {
+    let mut mapped = Vec::new();
+    let mut out = String::with_capacity(text.len());
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            out.push_str(line);
+        } else if trimmed.starts_with('#') {
+            out.push_str(&rewrite_uri_attribute(trimmed, base, &mut mapped));
+        } else {
+            match map_uri(base, trimmed) {
+                Some((local, url)) => {
+                    out.push_str(&local);
+                    mapped.push((local, url));
+                }
+                None => out.push_str(trimmed),
+            }
+        }
+        out.push('\n');
+    }
+
+    let referenced: HashSet<String> = mapped.iter().map(|(local, _)| local.clone()).collect();
+    if let Some(session) = state.proxy_sessions.lock_or_recover().get_mut(&cfg.name) {
+        session.uri_map.extend(mapped);
+    }
+
+    (out, referenced)
+}
+
+fn rewrite_uri_attribute(line: &str, base: &Url, mapped: &mut Vec<(String, Url)>) -> String {
+    let Some(start) = line.find("URI=\"") else {
+        return line.to_string();
+    };
+    let value_start = start + 5;
+    let Some(len) = line[value_start..].find('"') else {
+        return line.to_string();
+    };
+    let uri = &line[value_start..value_start + len];
+
+    match map_uri(base, uri) {
+        Some((local, url)) => {
+            let rewritten = format!(
+                "{}{}{}",
+                &line[..value_start],
+                local,
+                &line[value_start + len..]
+            );
+            mapped.push((local, url));
+            rewritten
+        }
+        None => line.to_string(),
+    }
+}
+
+/// 将上游引用映射为本地文件名
+///
+/// 与播放列表同目录的简单文件名保持不变，其余引用以地址哈希作前缀避免冲突
+fn map_uri(base: &Url, reference: &str) -> Option<(String, Url)> {
+    let url = base.join(reference).ok()?;
+    let path = url.path.split('?').next().unwrap_or_default();
+    let basename: String = path
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let basename = basename.trim_start_matches('.');
+    let basename = if basename.is_empty() {
+        "media"
+    } else {
+        basename
+    };
+
+    let local = match base.join(basename) {
+        Ok(simple) if simple == url => basename.to_string(),
+        _ => {
+            let mut hasher = DefaultHasher::new();
+            url.hash(&mut hasher);
+            format!("{:016x}-{}", hasher.finish(), basename)
+        }
+    };
+    Some((local, url))
+}
+
+/// 淘汰不再被任何播放列表引用的缓存文件
+async fn prune_cache(
+    state: &AppState,
+    cfg: &StreamConfig,
+    playlist: &str,
+    referenced: HashSet<String>,
+) {
+    let keep: HashSet<String> = {
+        let mut sessions = state.proxy_sessions.lock_or_recover();
+        let Some(session) = sessions.get_mut(&cfg.name) else {
+            return;
+        };
+        session.referenced.insert(playlist.to_string(), referenced);
+        let keep: HashSet<String> = session.referenced.values().flatten().cloned().collect();
+        session
+            .uri_map
+            .retain(|local, _| keep.contains(local) || is_playlist(local));
+        keep
+    };
+
+    let Ok(mut entries) = fs::read_dir(Engine::output_dir(state, cfg)).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !keep.contains(&name) && !name.starts_with('.') {
+            let _ = fs::remove_file(entry.path()).await;
+        }
+    }
+}
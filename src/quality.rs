@@ -0,0 +1,261 @@
+use crate::config::{QualityWatchConfig, StreamConfig, StreamMode};
+use crate::engine::Engine;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// 流的 HLS 输出质量统计 (随进程重启清零)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QualityStats {
+    /// 评分窗口内正常切片的比例 (0~100)，尚未分析切片时为 None
+    pub score: Option<f64>,
+    /// 判断切片过短 / 过长所用的期望时长 (秒)
+    pub target_duration_sec: Option<f64>,
+    /// 已分析的切片数
+    pub segments: u64,
+    pub short_segments: u64,
+    pub long_segments: u64,
+    /// 播放列表中的 `#EXT-X-DISCONTINUITY` 数量
+    pub discontinuities: u64,
+    /// 媒体序号跳跃 (切片未出现在播放列表中即被移除) 或回退的次数
+    pub sequence_jumps: u64,
+    /// 最近一次异常的说明
+    pub last_anomaly: Option<String>,
+}
+
+/// 评分窗口中的一个切片
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    duration: f64,
+    /// 切片前有不连续标签或序号跳跃
+    discontinuity: bool,
+    /// 时长超出容差
+    out_of_range: bool,
+}
+
+/// 运行中流的质量监测状态
+#[derive(Debug, Default)]
+pub struct QualityTracker {
+    pub stats: QualityStats,
+    /// 最近已分析切片的媒体序号
+    last_sequence: Option<u64>,
+    /// 最近的切片 (由旧到新，最多 `window_segments` 个)
+    window: VecDeque<Sample>,
+    /// 已对当前的低评分处理过，评分恢复后才再次处理
+    alarmed: bool,
+}
+
+/// 播放列表中的一个切片
+struct Entry {
+    sequence: u64,
+    duration: f64,
+    discontinuity: bool,
+}
+
+/// 主播放列表写出后分析新出现的切片
+///
+/// 与音画同步监测一样，FFmpeg 先输出 `Opening` 日志再写入播放列表，稍作等待后读取
+pub fn on_playlist_write(state: &Arc<AppState>, name: &str, line: &str, cfg: &QualityWatchConfig) {
+    if !line.contains("index.m3u8") {
+        return;
+    }
+    let state = state.clone();
+    let name = name.to_string();
+    let cfg = cfg.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        if let Err(e) = analyze(&state, &name, &cfg).await {
+            warn!("HLS quality check failed [{}]: {}", name, e);
+        }
+    });
+}
+
+async fn analyze(
+    state: &Arc<AppState>,
+    name: &str,
+    cfg: &QualityWatchConfig,
+) -> anyhow::Result<()> {
+    let Some(stream) = state.config().stream(name).cloned() else {
+        return Ok(());
+    };
+    let output_dir = Engine::output_dir(state, &stream);
+    let playlist = tokio::fs::read_to_string(output_dir.join("index.m3u8")).await?;
+    let entries = parse(&playlist);
+
+    let restart = {
+        let mut streams = state.active_streams.lock_or_recover();
+        let Some(running) = streams.get_mut(name) else {
+            return Ok(());
+        };
+        let tracker = &mut running.quality;
+        let mut jumped = false;
+        if let (Some(last), Some(first)) = (tracker.last_sequence, entries.first()) {
+            let newest = entries.last().map_or(first.sequence, |e| e.sequence);
+            if first.sequence > last + 1 || newest < last {
+                tracker.stats.sequence_jumps += 1;
+                tracker.stats.last_anomaly = Some(format!(
+                    "media sequence jumped from {} to {}",
+                    last,
+                    if newest < last {
+                        newest
+                    } else {
+                        first.sequence
+                    }
+                ));
+                if newest < last {
+                    // 序号重新开始，之后的切片按新序号分析
+                    tracker.last_sequence = None;
+                }
+                jumped = true;
+            }
+        }
+
+        let fresh: Vec<&Entry> = entries
+            .iter()
+            .filter(|e| tracker.last_sequence.is_none_or(|last| e.sequence > last))
+            .collect();
+        if fresh.is_empty() {
+            return Ok(());
+        }
+        for (i, entry) in fresh.iter().enumerate() {
+            if tracker.window.len() == cfg.window_segments as usize {
+                tracker.window.pop_front();
+            }
+            tracker.window.push_back(Sample {
+                duration: entry.duration,
+                discontinuity: entry.discontinuity || (jumped && i == 0),
+                out_of_range: false,
+            });
+            tracker.stats.segments += 1;
+            if entry.discontinuity {
+                tracker.stats.discontinuities += 1;
+                tracker.stats.last_anomaly =
+                    Some(format!("discontinuity before segment {}", entry.sequence));
+            }
+        }
+
+        // 期望时长需在加入新切片后确定 (transcode 模式取中位数)
+        let target = target_duration(&stream, cfg, &tracker.window);
+        let tolerance = cfg.tolerance_percent as f64 / 100.0;
+        // 新切片多于窗口时只有末尾的部分仍在窗口中
+        let kept = fresh.len().min(tracker.window.len());
+        let new_from = tracker.window.len() - kept;
+        for (sample, entry) in tracker
+            .window
+            .iter_mut()
+            .skip(new_from)
+            .zip(&fresh[fresh.len() - kept..])
+        {
+            if sample.duration < target * (1.0 - tolerance) {
+                tracker.stats.short_segments += 1;
+            } else if sample.duration > target * (1.0 + tolerance) {
+                tracker.stats.long_segments += 1;
+            } else {
+                continue;
+            }
+            sample.out_of_range = true;
+            tracker.stats.last_anomaly = Some(format!(
+                "segment {} lasted {:.2}s (expected {:.2}s)",
+                entry.sequence, sample.duration, target
+            ));
+        }
+        tracker.last_sequence = fresh.last().map(|e| e.sequence);
+
+        let clean = tracker
+            .window
+            .iter()
+            .filter(|s| !s.discontinuity && !s.out_of_range)
+            .count();
+        let score = (clean as f64 * 1000.0 / tracker.window.len() as f64).round() / 10.0;
+        tracker.stats.score = Some(score);
+        tracker.stats.target_duration_sec = Some(target);
+
+        // 窗口填满后才判断，避免启动初期的少量切片左右评分
+        let discontinuities = tracker.window.iter().filter(|s| s.discontinuity).count();
+        let full = tracker.window.len() == cfg.window_segments as usize;
+        let flood = discontinuities > cfg.max_discontinuities as usize;
+        if full && (score < cfg.min_score || flood) {
+            let fire = !tracker.alarmed;
+            tracker.alarmed = true;
+            if fire {
+                warn!(
+                    "Stream [{}] HLS output quality degraded: score {:.1} (min {:.1}), {} discontinuities in the last {} segments{}",
+                    name,
+                    score,
+                    cfg.min_score,
+                    discontinuities,
+                    tracker.window.len(),
+                    tracker
+                        .stats
+                        .last_anomaly
+                        .as_deref()
+                        .map(|a| format!(", last: {}", a))
+                        .unwrap_or_default()
+                );
+            }
+            fire && cfg.restart
+        } else {
+            tracker.alarmed = false;
+            false
+        }
+    };
+
+    if restart {
+        warn!("Restarting stream [{}] to recover HLS output quality", name);
+        let _ = Engine::stop_stream(state, name).await;
+        if let Err(e) = Engine::start_stream(state, name).await {
+            error!("Restart failed [{}]: {}", name, e);
+        }
+    }
+    Ok(())
+}
+
+/// 判断切片过短 / 过长所用的期望时长
+fn target_duration(
+    stream: &StreamConfig,
+    cfg: &QualityWatchConfig,
+    window: &VecDeque<Sample>,
+) -> f64 {
+    if let Some(target) = cfg.target_duration_sec {
+        return target;
+    }
+    if matches!(stream.mode, StreamMode::Relay | StreamMode::Mosaic) {
+        return stream.hls.segment_duration_sec as f64;
+    }
+    let mut durations: Vec<f64> = window.iter().map(|s| s.duration).collect();
+    durations.sort_by(f64::total_cmp);
+    durations.get(durations.len() / 2).copied().unwrap_or(0.0)
+}
+
+/// 解析播放列表中的切片序号、时长与之前的不连续标签
+fn parse(playlist: &str) -> Vec<Entry> {
+    let mut sequence = playlist
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("#EXT-X-MEDIA-SEQUENCE:"))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0);
+    let mut entries = Vec::new();
+    let mut duration = None;
+    let mut discontinuity = false;
+    for line in playlist.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            duration = rest.split(',').next().and_then(|d| d.parse::<f64>().ok());
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            discontinuity = true;
+        } else if !line.is_empty() && !line.starts_with('#') {
+            if let Some(duration) = duration.take() {
+                entries.push(Entry {
+                    sequence,
+                    duration,
+                    discontinuity,
+                });
+            }
+            discontinuity = false;
+            sequence += 1;
+        }
+    }
+    entries
+}
@@ -0,0 +1,88 @@
+use crate::config::StreamConfig;
+use axum::http::{header, HeaderMap, StatusCode};
+
+/// 主机名的最大长度 (RFC 1035)，更长的来源不做匹配直接拒绝
+const MAX_HOST_LEN: usize = 253;
+
+/// 按流的 `referer` 规则检查媒体请求的来源页面，拒绝时返回应答给播放器的状态与说明
+///
+/// Origin 优先于 Referer；`Origin: null` (沙箱 iframe、本地文件) 视为不匹配的来源
+pub fn check(cfg: &StreamConfig, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(policy) = &cfg.referer else {
+        return Ok(());
+    };
+    let source = [header::ORIGIN, header::REFERER]
+        .iter()
+        .find_map(|name| headers.get(name))
+        .map(|v| v.to_str().unwrap_or_default());
+    let allowed = match source {
+        None => policy.allow_empty,
+        Some(source) => host(source)
+            .filter(|host| host.len() <= MAX_HOST_LEN)
+            .is_some_and(|host| policy.allow.iter().any(|p| p.is_match(&host))),
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::FORBIDDEN,
+            "Embedding this stream is not allowed from this site".to_string(),
+        ))
+    }
+}
+
+/// `scheme://[userinfo@]host[:port]/...` 中的主机名 (小写)
+fn host(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    let host = match authority.strip_prefix('[') {
+        // IPv6 字面量
+        Some(v6) => v6.split(']').next()?,
+        None => authority.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn stream(allow: &str) -> anyhow::Result<StreamConfig> {
+        Ok(serde_yaml::from_str(&format!(
+            "name: cam\nsource: rtsp://10.0.0.9/live\nreferer:\n  allow: ['{}']\n",
+            allow
+        ))?)
+    }
+
+    fn origin(url: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ORIGIN, HeaderValue::from_str(url).unwrap());
+        headers
+    }
+
+    #[test]
+    fn allows_only_matching_hosts() {
+        let cfg = stream(r"^(.*\.)?partner\.example\.com$").unwrap();
+        assert!(check(&cfg, &origin("https://www.partner.example.com")).is_ok());
+        assert!(check(&cfg, &origin("https://user@PARTNER.example.com:8443/x")).is_ok());
+        assert!(check(&cfg, &origin("https://partner.example.com.evil")).is_err());
+        assert!(check(&cfg, &origin("null")).is_err());
+        assert!(check(&cfg, &HeaderMap::new()).is_err());
+    }
+
+    /// 超长的主机名不做匹配直接拒绝
+    #[test]
+    fn rejects_oversized_hosts() {
+        let cfg = stream(r"^(.*\.)?partner\.example\.com$").unwrap();
+        let host = format!("{}partner.example.com", "a.".repeat(8_000));
+        let (status, _) = check(&cfg, &origin(&format!("https://{}", host))).unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn invalid_patterns_fail_to_load() {
+        assert!(stream("^(partner").is_err());
+    }
+}
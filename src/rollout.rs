@@ -0,0 +1,174 @@
+use crate::config::{AppConfig, RolloutConfig};
+use crate::engine::Engine;
+use crate::migrate;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// 检查流是否就绪的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 分批应用的结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RolloutReport {
+    /// 以新配置重启并就绪的流
+    pub applied: Vec<String>,
+    /// 未能就绪的流
+    pub failed: Vec<FailedStream>,
+    /// 因回滚而未重启的流 (仍在以原配置运行)
+    pub skipped: Vec<String>,
+    /// 是否已回滚到原配置 (配置文件与运行配置)
+    pub rolled_back: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedStream {
+    pub name: String,
+    pub reason: String,
+}
+
+/// 按批重启配置已改动的运行中流：每批停止后以新配置启动，等待 `health_window_sec` 内产出
+/// 有效的播放列表；累计未就绪的流超过 `max_failure_ratio` 时停止后续批次，恢复原运行配置
+/// 并以原配置重启已处理的流 (配置文件由调用方从备份恢复)
+///
+/// 调用前运行配置须已替换为新配置，`targets` 中尚未处理的流在此期间继续运行旧进程
+pub async fn run(
+    state: &Arc<AppState>,
+    previous: &AppConfig,
+    targets: &[String],
+    cfg: &RolloutConfig,
+) -> RolloutReport {
+    let mut report = RolloutReport::default();
+    let allowed = cfg.max_failure_ratio * targets.len() as f64;
+    let window = Duration::from_secs(cfg.health_window_sec);
+
+    for (i, batch) in targets.chunks(cfg.batch_size).enumerate() {
+        info!(
+            "Config rollout: batch {} restarting {}",
+            i + 1,
+            batch.join(", ")
+        );
+        let mut pending = Vec::new();
+        for name in batch {
+            let _ = Engine::stop_stream(state, name).await;
+            match Engine::start_stream(state, name).await {
+                Ok(_) => pending.push(name.clone()),
+                Err(e) => report.failed.push(FailedStream {
+                    name: name.clone(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        let (ready, failed) = wait_healthy(state, pending, window).await;
+        report.applied.extend(ready);
+        report.failed.extend(failed);
+
+        if report.failed.len() as f64 > allowed {
+            let done = ((i + 1) * cfg.batch_size).min(targets.len());
+            report.skipped = targets[done..].to_vec();
+            rollback(state, previous, &report).await;
+            report.rolled_back = true;
+            return report;
+        }
+    }
+    info!(
+        "Config rollout finished: {} applied, {} failed",
+        report.applied.len(),
+        report.failed.len()
+    );
+    report
+}
+
+/// 等待流产出有效的播放列表，返回 (就绪的流, 崩溃或超时未就绪的流)
+async fn wait_healthy(
+    state: &Arc<AppState>,
+    mut pending: Vec<String>,
+    window: Duration,
+) -> (Vec<String>, Vec<FailedStream>) {
+    let deadline = Instant::now() + window;
+    let mut ready = Vec::new();
+    let mut failed = Vec::new();
+    loop {
+        let mut waiting = Vec::new();
+        for name in pending {
+            if is_healthy(state, &name).await {
+                ready.push(name);
+            } else if let Some(reason) = last_crash(state, &name) {
+                failed.push(FailedStream { name, reason });
+            } else {
+                waiting.push(name);
+            }
+        }
+        pending = waiting;
+        if pending.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    failed.extend(pending.into_iter().map(|name| FailedStream {
+        name,
+        reason: format!("no playlist within {} s", window.as_secs()),
+    }));
+    (ready, failed)
+}
+
+/// 进程在运行且输出目录中有有效的播放列表 (启动时目录已清空，只会是新进程的输出)
+async fn is_healthy(state: &AppState, name: &str) -> bool {
+    if !state.active_streams.lock_or_recover().contains_key(name) {
+        return false;
+    }
+    let Some(cfg) = state.config().stream(name).cloned() else {
+        return false;
+    };
+    migrate::playlist_ready(&Engine::output_dir(state, &cfg)).await
+}
+
+/// 最近一次崩溃的原因 (重启前的停止记录之后只会有新进程的退出记录)
+fn last_crash(state: &AppState, name: &str) -> Option<String> {
+    let recovery = state.recovery_states.lock_or_recover();
+    let exit = recovery.get(name)?.exits.back()?;
+    (exit.cause == "crashed").then(|| {
+        exit.reason.clone().unwrap_or_else(|| match exit.code {
+            Some(code) => format!("exited with code {}", code),
+            None => "crashed".to_string(),
+        })
+    })
+}
+
+/// 恢复原运行配置，已以新配置重启的流 (含失败的) 停止后以原配置重新启动
+async fn rollback(state: &Arc<AppState>, previous: &AppConfig, report: &RolloutReport) {
+    error!(
+        "Config rollout: {} stream(s) failed to become healthy ({}), rolling back",
+        report.failed.len(),
+        report
+            .failed
+            .iter()
+            .map(|f| format!("{}: {}", f.name, f.reason))
+            .collect::<Vec<_>>()
+            .join("; ")
+    );
+    state.replace_config(previous.clone());
+    let touched = report
+        .applied
+        .iter()
+        .chain(report.failed.iter().map(|f| &f.name));
+    for name in touched {
+        let _ = Engine::stop_stream(state, name).await;
+        // 新配置导致的崩溃退避与隔离不应阻止以原配置恢复
+        if let Some(rec) = state
+            .recovery_states
+            .lock_or_recover()
+            .get_mut(name.as_str())
+        {
+            rec.crash_count = 0;
+            rec.next_retry_at = None;
+            rec.quarantined = None;
+            rec.last_start = None;
+        }
+        if let Err(e) = Engine::start_stream(state, name).await {
+            warn!("Config rollout: failed to restart [{}]: {}", name, e);
+        }
+    }
+}
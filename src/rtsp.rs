@@ -0,0 +1,693 @@
+use crate::auth;
+use crate::config::{AppConfig, StreamConfig};
+use crate::drain;
+use crate::engine::Engine;
+use crate::forward_auth::PlaybackRequest;
+use crate::keys;
+use crate::playback;
+use crate::sessions;
+use crate::state::{LockExt, SharedState};
+use axum::extract::Query;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Uri};
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// 等待 FFmpeg 推流就绪的最长时间
+const PUBLISH_WAIT: Duration = Duration::from_secs(15);
+
+/// 拉流期间刷新流活跃时间的间隔
+const TOUCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 每个发布的包缓冲数量，慢速客户端超出后丢包
+const PACKET_BUFFER: usize = 1024;
+
+/// 请求头部的最大行数
+const MAX_HEADER_LINES: usize = 64;
+
+/// 请求行与单个头部行的最大长度，超出时断开连接
+const MAX_LINE: usize = 8 * 1024;
+
+/// 请求体上限 (SDP)
+const MAX_BODY: usize = 64 * 1024;
+
+static PUBLICATION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 交织帧 (通道号, RTP/RTCP 包)
+type Packet = Arc<(u8, Vec<u8>)>;
+
+/// 本地 FFmpeg 推送的 RTSP 发布
+pub struct RtspPublication {
+    id: u64,
+    sdp: String,
+    /// 轨道控制名 -> 推流端的 RTP 交织通道
+    tracks: HashMap<String, u8>,
+    packets: broadcast::Sender<Packet>,
+}
+
+/// 为配置了 rtsp_output 的端口启动内置 RTSP 服务
+///
+/// FFmpeg 以 TCP 交织方式推流 (ANNOUNCE/RECORD)，客户端以 TCP 交织方式拉流 (DESCRIBE/PLAY)。
+/// 端口在启动时确定 (在切换运行用户之前监听，见 `bind_servers`)，运行时新增的端口需重启生效
+pub fn start_servers(state: SharedState, listeners: Vec<(u16, TcpListener)>) {
+    for (port, listener) in listeners {
+        tokio::spawn(serve(state.clone(), port, listener));
+    }
+}
+
+/// 监听配置中所有 rtsp_output 端口，监听失败的端口记录日志后跳过
+pub async fn bind_servers(config: &AppConfig) -> Vec<(u16, TcpListener)> {
+    let ports: BTreeSet<u16> = config
+        .streams
+        .iter()
+        .filter_map(|s| s.rtsp_output.as_ref().map(|o| o.port))
+        .collect();
+
+    let mut listeners = Vec::new();
+    for port in ports {
+        match TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(listener) => listeners.push((port, listener)),
+            Err(e) => error!("Failed to bind RTSP server on port {}: {}", port, e),
+        }
+    }
+    listeners
+}
+
+async fn serve(state: SharedState, port: u16, listener: TcpListener) {
+    info!("RTSP server listening on port {}", port);
+
+    loop {
+        match listener.accept().await {
+            Ok((socket, peer)) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(state, port, socket, peer).await {
+                        warn!("RTSP connection from {} closed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => warn!("RTSP accept failed on port {}: {}", port, e),
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+enum Message {
+    Request(Request),
+    Interleaved(u8, Vec<u8>),
+}
+
+/// 连接上的会话状态 (推流端与拉流端共用)
+#[derive(Default)]
+struct Session {
+    id: String,
+    /// 推流端: ANNOUNCE 的流名与 SDP
+    announced: Option<(String, String)>,
+    /// 推流端: SETUP 登记的轨道
+    record_tracks: HashMap<String, u8>,
+    /// 推流端: RECORD 之后的发布 (流名, 发布 ID, 发送端)
+    publishing: Option<(String, u64, broadcast::Sender<Packet>)>,
+    /// 拉流端: 通过准入检查的令牌，供同一连接上不带令牌的后续请求使用
+    token: Option<String>,
+    /// 拉流端: 目标流
+    stream: Option<String>,
+    /// 拉流端: 推流端通道 -> 客户端通道
+    channels: HashMap<u8, u8>,
+    player: Option<JoinHandle<()>>,
+}
+
+async fn handle_connection(
+    state: SharedState,
+    port: u16,
+    socket: TcpStream,
+    peer: SocketAddr,
+) -> anyhow::Result<()> {
+    let (read_half, write_half) = socket.into_split();
+    let mut reader = BufReader::new(read_half);
+    let writer = Arc::new(Mutex::new(write_half));
+    let mut session = Session::default();
+
+    let result = loop {
+        let message = match read_message(&mut reader).await {
+            Ok(Some(m)) => m,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e.into()),
+        };
+
+        let req = match message {
+            Message::Interleaved(channel, payload) => {
+                // 拉流端的 RTCP 接收报告直接丢弃
+                if let Some((_, _, tx)) = &session.publishing {
+                    let _ = tx.send(Arc::new((channel, payload)));
+                }
+                continue;
+            }
+            Message::Request(req) => req,
+        };
+
+        let cseq = req.header("CSeq").unwrap_or("0").to_string();
+        let (status, headers, body, close) =
+            handle_request(&state, port, peer, &mut session, &writer, &req).await;
+        let response = format_response(status, &cseq, &session.id, headers, &body);
+        writer.lock().await.write_all(&response).await?;
+        if close {
+            break Ok(());
+        }
+    };
+
+    // 清理：结束转发任务，撤销本连接的发布 (若未被新的推流替换)
+    if let Some(player) = session.player.take() {
+        player.abort();
+    }
+    if let Some((name, id, _)) = session.publishing.take() {
+        let mut publications = state.rtsp_publications.lock_or_recover();
+        if publications.get(&name).is_some_and(|p| p.id == id) {
+            publications.remove(&name);
+            info!("RTSP publication ended for stream [{}]", name);
+        }
+    }
+
+    result
+}
+
+type Reply = (u16, Vec<(&'static str, String)>, Vec<u8>, bool);
+
+async fn handle_request(
+    state: &SharedState,
+    port: u16,
+    peer: SocketAddr,
+    session: &mut Session,
+    writer: &Arc<Mutex<OwnedWriteHalf>>,
+    req: &Request,
+) -> Reply {
+    let ok = |headers| (200, headers, Vec::new(), false);
+
+    match req.method.as_str() {
+        "OPTIONS" => ok(vec![(
+            "Public",
+            "OPTIONS, DESCRIBE, ANNOUNCE, SETUP, PLAY, RECORD, TEARDOWN, GET_PARAMETER".to_string(),
+        )]),
+        "GET_PARAMETER" | "SET_PARAMETER" => ok(Vec::new()),
+        "TEARDOWN" => (200, Vec::new(), Vec::new(), true),
+
+        "DESCRIBE" => {
+            let Some((cfg, _)) = resolve(state, port, &req.uri) else {
+                return (404, Vec::new(), Vec::new(), false);
+            };
+            if let Err(status) = admit(state, &cfg, peer, session, req).await {
+                return (status, Vec::new(), Vec::new(), false);
+            }
+            // 轨道地址由客户端在 Content-Base 后追加控制名得到，不能带查询参数
+            let base = req.uri.split('?').next().unwrap_or_default();
+            match wait_publication(state, &cfg).await {
+                Some(sdp) => (
+                    200,
+                    vec![
+                        ("Content-Base", format!("{}/", base.trim_end_matches('/'))),
+                        ("Content-Type", "application/sdp".to_string()),
+                    ],
+                    sdp.into_bytes(),
+                    false,
+                ),
+                None => (503, Vec::new(), Vec::new(), false),
+            }
+        }
+
+        // 仅接受本机 FFmpeg 推流
+        "ANNOUNCE" => {
+            if !peer.ip().is_loopback() {
+                return (403, Vec::new(), Vec::new(), true);
+            }
+            let Some((cfg, _)) = resolve(state, port, &req.uri) else {
+                return (404, Vec::new(), Vec::new(), true);
+            };
+            let sdp = String::from_utf8_lossy(&req.body).to_string();
+            let Ok(id) = new_session_id() else {
+                return (500, Vec::new(), Vec::new(), true);
+            };
+            session.announced = Some((cfg.name, sdp));
+            session.id = id;
+            ok(Vec::new())
+        }
+
+        "SETUP" => {
+            let Some((cfg, track)) = resolve(state, port, &req.uri) else {
+                return (404, Vec::new(), Vec::new(), false);
+            };
+            let transport = req.header("Transport").unwrap_or_default();
+            // 仅支持 TCP 交织传输，客户端收到 461 后通常会回退到 TCP
+            if !transport.contains("RTP/AVP/TCP") {
+                return (461, Vec::new(), Vec::new(), false);
+            }
+            if session.id.is_empty() {
+                let Ok(id) = new_session_id() else {
+                    return (500, Vec::new(), Vec::new(), true);
+                };
+                session.id = id;
+            }
+
+            if session.announced.is_some() {
+                let fallback = 2 * session.record_tracks.len();
+                let Some((channel, _)) = channel_pair(transport, fallback) else {
+                    return (461, Vec::new(), Vec::new(), false);
+                };
+                session.record_tracks.insert(track, channel);
+                return ok(vec![("Transport", transport_header(channel, true))]);
+            }
+
+            // PLAY 只作用于本连接上通过检查的 SETUP
+            if let Err(status) = admit(state, &cfg, peer, session, req).await {
+                return (status, Vec::new(), Vec::new(), false);
+            }
+            let published = state
+                .rtsp_publications
+                .lock_or_recover()
+                .get(&cfg.name)
+                .and_then(|p| p.tracks.get(&track).copied());
+            let Some(source_channel) = published else {
+                return (404, Vec::new(), Vec::new(), false);
+            };
+            // 每条轨道占用 RTP/RTCP 两个通道
+            let pair =
+                channel_pair(transport, session.channels.len()).zip(source_channel.checked_add(1));
+            let Some(((channel, rtcp), source_rtcp)) = pair else {
+                return (461, Vec::new(), Vec::new(), false);
+            };
+            session.channels.insert(source_channel, channel);
+            session.channels.insert(source_rtcp, rtcp);
+            session.stream = Some(cfg.name);
+            ok(vec![("Transport", transport_header(channel, false))])
+        }
+
+        "RECORD" => {
+            let Some((name, sdp)) = session.announced.take() else {
+                return (455, Vec::new(), Vec::new(), false);
+            };
+            let (tx, _) = broadcast::channel(PACKET_BUFFER);
+            let id = PUBLICATION_ID.fetch_add(1, Ordering::Relaxed);
+            state.rtsp_publications.lock_or_recover().insert(
+                name.clone(),
+                RtspPublication {
+                    id,
+                    sdp,
+                    tracks: std::mem::take(&mut session.record_tracks),
+                    packets: tx.clone(),
+                },
+            );
+            info!("RTSP publication started for stream [{}]", name);
+            session.publishing = Some((name, id, tx));
+            ok(Vec::new())
+        }
+
+        "PLAY" => {
+            let Some(name) = session.stream.clone() else {
+                return (455, Vec::new(), Vec::new(), false);
+            };
+            let rx = state
+                .rtsp_publications
+                .lock_or_recover()
+                .get(&name)
+                .map(|p| p.packets.subscribe());
+            let Some(rx) = rx else {
+                return (503, Vec::new(), Vec::new(), false);
+            };
+            if session.player.is_none() {
+                session.player = Some(tokio::spawn(forward(
+                    state.clone(),
+                    name,
+                    format!("rtsp:{}:{}", peer, session.id),
+                    rx,
+                    session.channels.clone(),
+                    writer.clone(),
+                )));
+            }
+            ok(vec![("Range", "npt=0.000-".to_string())])
+        }
+
+        _ => (501, Vec::new(), Vec::new(), false),
+    }
+}
+
+/// 拉流请求的准入检查 (与 HLS、MPEG-TS 相同，见 `playback::admit`)，拒绝时返回 RTSP 状态码
+///
+/// 令牌取自 `Authorization: Bearer` 头或地址中的 `?token=`，都没有时沿用本连接上已通过检查的令牌；
+/// 客户端地址为连接的对端地址
+async fn admit(
+    state: &SharedState,
+    cfg: &StreamConfig,
+    peer: SocketAddr,
+    session: &mut Session,
+    req: &Request,
+) -> Result<(), u16> {
+    // 排空期间拒绝新的拉流，已在播放的连接继续转发
+    if drain::is_draining(state) {
+        return Err(503);
+    }
+
+    let mut headers = HeaderMap::new();
+    for (k, v) in &req.headers {
+        if let (Ok(k), Ok(v)) = (
+            HeaderName::from_bytes(k.as_bytes()),
+            HeaderValue::from_str(v),
+        ) {
+            headers.append(k, v);
+        }
+    }
+    let uri = req.uri.parse::<Uri>().ok();
+    let query = uri
+        .as_ref()
+        .and_then(|u| Query::<HashMap<String, String>>::try_from_uri(u).ok());
+    let token = auth::extract_token(
+        &headers,
+        query
+            .as_ref()
+            .and_then(|q| q.get("token"))
+            .map(String::as_str),
+    )
+    .or_else(|| session.token.clone());
+    let client_ip = peer.ip().to_string();
+
+    let result = playback::admit(
+        state,
+        cfg,
+        &headers,
+        PlaybackRequest {
+            client_ip: &client_ip,
+            token: token.as_deref(),
+            path: uri.as_ref().map_or("/", |u| u.path()),
+            protocol: "rtsp",
+        },
+    )
+    .await;
+    match result {
+        Ok(()) => {
+            session.token = token;
+            Ok(())
+        }
+        Err((status, message)) => {
+            warn!(
+                "RTSP request from {} for stream [{}] rejected: {}",
+                peer, cfg.name, message
+            );
+            Err(match status.as_u16() {
+                code @ (401 | 403 | 503) => code,
+                _ => 403,
+            })
+        }
+    }
+}
+
+/// 将发布的包转发给拉流客户端，播放期间作为一个观看会话保持流活跃
+async fn forward(
+    state: SharedState,
+    name: String,
+    viewer: String,
+    mut rx: broadcast::Receiver<Packet>,
+    channels: HashMap<u8, u8>,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+) {
+    let mut touch = tokio::time::interval(TOUCH_INTERVAL);
+    loop {
+        tokio::select! {
+            packet = rx.recv() => match packet {
+                Ok(packet) => {
+                    let (channel, payload) = &*packet;
+                    let Some(&target) = channels.get(channel) else {
+                        continue;
+                    };
+                    let mut frame = Vec::with_capacity(payload.len() + 4);
+                    frame.push(b'$');
+                    frame.push(target);
+                    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+                    frame.extend_from_slice(payload);
+                    if writer.lock().await.write_all(&frame).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("RTSP client of stream [{}] dropped {} packets", name, n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = touch.tick() => sessions::touch(&state, &name, &viewer),
+        }
+    }
+
+    // 发布结束时关闭连接，由客户端自行重连
+    let _ = writer.lock().await.shutdown().await;
+}
+
+/// 按需启动流并等待 FFmpeg 推流，返回发布的 SDP
+async fn wait_publication(state: &SharedState, cfg: &StreamConfig) -> Option<String> {
+    if let Err(e) = Engine::start_stream(state, &cfg.name).await {
+        warn!("RTSP request failed to start stream [{}]: {}", cfg.name, e);
+        return None;
+    }
+
+    let deadline = Instant::now() + PUBLISH_WAIT;
+    loop {
+        if let Some(p) = state.rtsp_publications.lock_or_recover().get(&cfg.name) {
+            return Some(p.sdp.clone());
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// 根据请求地址查找流配置及轨道控制名
+fn resolve(state: &SharedState, port: u16, uri: &str) -> Option<(StreamConfig, String)> {
+    let after_scheme = uri.split_once("://").map(|(_, r)| r).unwrap_or(uri);
+    let path = after_scheme
+        .find('/')
+        .map(|i| &after_scheme[i..])
+        .unwrap_or("/");
+    let path = path.split('?').next().unwrap_or_default();
+
+    let config = state.config();
+    config.streams.iter().find_map(|s| {
+        let output = s.rtsp_output.as_ref().filter(|o| o.port == port)?;
+        let rest = path.strip_prefix(output.path(&s.name).as_str())?;
+        let track = match rest.trim_end_matches('/') {
+            "" => "",
+            r => r.strip_prefix('/')?,
+        };
+        Some((s.clone(), track.to_string()))
+    })
+}
+
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Message>> {
+    let first = match reader.fill_buf().await? {
+        [] => return Ok(None),
+        buf => buf[0],
+    };
+
+    if first == b'$' {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).await?;
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload).await?;
+        return Ok(Some(Message::Interleaved(header[1], payload)));
+    }
+
+    let mut line = String::new();
+    if read_line(reader, &mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(uri)) = (parts.next(), parts.next()) else {
+        return Err(std::io::Error::other("Malformed RTSP request line"));
+    };
+    let (method, uri) = (method.to_string(), uri.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if read_line(reader, &mut line).await? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADER_LINES {
+            return Err(std::io::Error::other("Too many RTSP headers"));
+        }
+        if let Some((k, v)) = trimmed.split_once(':') {
+            headers.push((k.trim().to_string(), v.trim().to_string()));
+        }
+    }
+
+    let len = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    if len > MAX_BODY {
+        return Err(std::io::Error::other("RTSP request body too large"));
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(Message::Request(Request {
+        method,
+        uri,
+        headers,
+        body,
+    })))
+}
+
+/// 读取一行，长度不超过 `MAX_LINE`
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+) -> std::io::Result<usize> {
+    let n = reader.take(MAX_LINE as u64).read_line(line).await?;
+    if n >= MAX_LINE && !line.ends_with('\n') {
+        return Err(std::io::Error::other("RTSP request line too long"));
+    }
+    Ok(n)
+}
+
+fn format_response(
+    status: u16,
+    cseq: &str,
+    session: &str,
+    headers: Vec<(&'static str, String)>,
+    body: &[u8],
+) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        455 => "Method Not Valid in This State",
+        461 => "Unsupported Transport",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _ => "Service Unavailable",
+    };
+    let mut out = format!("RTSP/1.0 {} {}\r\nCSeq: {}\r\n", status, reason, cseq);
+    if !session.is_empty() {
+        out.push_str(&format!("Session: {}\r\n", session));
+    }
+    for (k, v) in headers {
+        out.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    if !body.is_empty() {
+        out.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    out.push_str("\r\n");
+
+    let mut bytes = out.into_bytes();
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// 解析 Transport 头中的 `interleaved=a-b`，未指定时返回 None，通道号无效时返回 Some(None)
+fn interleaved_channel(transport: &str) -> Option<Option<u8>> {
+    transport
+        .split(';')
+        .find_map(|p| p.trim().strip_prefix("interleaved="))
+        .map(|v| v.split('-').next().and_then(|v| v.parse().ok()))
+}
+
+/// 确定轨道的 RTP/RTCP 通道对，Transport 头未指定时使用 `fallback`
+///
+/// RTCP 占用 RTP 的下一个通道，通道号无效或超出 0-255 时返回 None (应答 461)
+fn channel_pair(transport: &str, fallback: usize) -> Option<(u8, u8)> {
+    let channel = match interleaved_channel(transport) {
+        Some(c) => c?,
+        None => u8::try_from(fallback).ok()?,
+    };
+    Some((channel, channel.checked_add(1)?))
+}
+
+/// 调用方保证 `channel` 小于 255 (见 `channel_pair`)
+fn transport_header(channel: u8, record: bool) -> String {
+    format!(
+        "RTP/AVP/TCP;unicast;interleaved={}-{}{}",
+        channel,
+        u16::from(channel) + 1,
+        if record { ";mode=record" } else { "" }
+    )
+}
+
+fn new_session_id() -> std::io::Result<String> {
+    let mut id = [0u8; 8];
+    keys::random_bytes(&mut id)?;
+    Ok(id.iter().map(|b| format!("{:02X}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn overlong_request_line_is_rejected() {
+        let mut data = b"DESCRIBE rtsp://h/".to_vec();
+        data.extend(std::iter::repeat_n(b'a', MAX_LINE));
+        data.extend_from_slice(b" RTSP/1.0\r\n\r\n");
+        let mut reader = &data[..];
+        assert!(read_message(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn overlong_header_is_rejected() {
+        let mut data = b"OPTIONS * RTSP/1.0\r\nX: ".to_vec();
+        data.extend(std::iter::repeat_n(b'a', MAX_LINE));
+        let mut reader = &data[..];
+        assert!(read_message(&mut reader).await.is_err());
+    }
+
+    #[test]
+    fn channel_pairs_stay_in_range() {
+        let tcp = "RTP/AVP/TCP;unicast";
+        assert_eq!(channel_pair("RTP/AVP/TCP;interleaved=0-1", 9), Some((0, 1)));
+        assert_eq!(channel_pair(tcp, 4), Some((4, 5)));
+        assert_eq!(
+            channel_pair("RTP/AVP/TCP;interleaved=254-255", 0),
+            Some((254, 255))
+        );
+        assert_eq!(channel_pair("RTP/AVP/TCP;interleaved=255", 0), None);
+        assert_eq!(channel_pair("RTP/AVP/TCP;interleaved=256-257", 0), None);
+        assert_eq!(channel_pair(tcp, 255), None);
+        assert_eq!(channel_pair(tcp, 300), None);
+    }
+
+    #[tokio::test]
+    async fn request_is_parsed() {
+        let data = b"OPTIONS rtsp://h/s RTSP/1.0\r\nCSeq: 2\r\n\r\n".to_vec();
+        let mut reader = &data[..];
+        let Ok(Some(Message::Request(req))) = read_message(&mut reader).await else {
+            panic!("request not parsed");
+        };
+        assert_eq!(req.method, "OPTIONS");
+        assert_eq!(req.header("cseq"), Some("2"));
+    }
+}
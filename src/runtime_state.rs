@@ -0,0 +1,321 @@
+use crate::config::{AppConfig, StateStore};
+use crate::engine::Engine;
+use crate::maintenance::{self, DisabledStream, Mute};
+use crate::state::{AppState, LockExt, StreamRecoveryState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{error, info, warn};
+
+/// 运行状态文件 (位于 server.state_root)
+const FILE_NAME: &str = "runtime_state.json";
+
+/// 运行状态数据库 (server.state_store: sqlite，位于 server.state_root)
+#[cfg(feature = "sqlite")]
+const DB_FILE_NAME: &str = "runtime_state.db";
+
+/// 串行化写入，保证后一次写入的内容不早于前一次
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 运维人员对流的手动操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Intent {
+    /// 手动启动：网关重启后重新启动，空闲回收后清除
+    Started,
+    /// 手动停止：Supervisor 不再自动启动 (auto_start)，直到再次手动启动
+    Stopped,
+}
+
+/// 需跨重启保留的故障恢复状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedRecovery {
+    pub crash_count: u32,
+    #[serde(default)]
+    pub quarantined: Option<String>,
+    /// 已达到最大重试次数，不再自动重启
+    #[serde(default)]
+    pub exhausted: bool,
+}
+
+/// 运行状态文件内容
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RuntimeState {
+    #[serde(default)]
+    pub disabled: HashMap<String, DisabledStream>,
+    #[serde(default)]
+    pub intents: HashMap<String, Intent>,
+    #[serde(default)]
+    pub recovery: HashMap<String, SavedRecovery>,
+    #[serde(default)]
+    pub mutes: HashMap<String, Mute>,
+}
+
+impl RuntimeState {
+    pub fn is_empty(&self) -> bool {
+        self.disabled.is_empty()
+            && self.intents.is_empty()
+            && self.recovery.is_empty()
+            && self.mutes.is_empty()
+    }
+
+    /// 还原为运行时的故障恢复状态 (未耗尽重试的流允许立即重试)
+    pub fn recovery_states(&self) -> HashMap<String, StreamRecoveryState> {
+        let now = Instant::now();
+        self.recovery
+            .iter()
+            .map(|(name, saved)| {
+                let rec = StreamRecoveryState {
+                    crash_count: saved.crash_count,
+                    next_retry_at: (!saved.exhausted && saved.crash_count > 0).then_some(now),
+                    quarantined: saved.quarantined.clone(),
+                    ..Default::default()
+                };
+                (name.clone(), rec)
+            })
+            .collect()
+    }
+}
+
+/// 读取上次运行保存的状态 (不存在或无法读取时为空)
+pub fn load(config: &AppConfig) -> RuntimeState {
+    let saved = match config.server.state_store {
+        StateStore::Json => load_json(config),
+        StateStore::Sqlite => match sqlite_backend::load(config) {
+            // 首次改用 SQLite 时沿用 JSON 文件中的状态，下次写入时存入数据库
+            Ok(saved) if saved.is_empty() => load_json(config),
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                warn!("Failed to read the runtime state database: {}", e);
+                None
+            }
+        },
+    };
+    let Some(saved) = saved else {
+        return RuntimeState::default();
+    };
+    info!(
+        "Restored runtime state: {} disabled, {} manual, {} recovering, {} muted stream(s)",
+        saved.disabled.len(),
+        saved.intents.len(),
+        saved.recovery.len(),
+        saved.mutes.len()
+    );
+    saved
+}
+
+fn load_json(config: &AppConfig) -> Option<RuntimeState> {
+    let path = file_path(config);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Failed to read {:?}: {}", path, e);
+            return None;
+        }
+    };
+    match serde_json::from_slice::<RuntimeState>(&data) {
+        Ok(saved) => Some(saved),
+        Err(e) => {
+            warn!("Ignoring invalid {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// 写入当前运行状态：先写临时文件再重命名，只保留配置中仍存在的流与未过期的静默
+pub fn save(state: &AppState) -> anyhow::Result<()> {
+    let _guard = SAVE_LOCK.lock_or_recover();
+    let config = state.config();
+    let known = |name: &String| config.stream(name).is_some();
+
+    let disabled = state.disabled_streams.entries();
+    let intents = state.stream_intents.entries();
+    let recovery = state
+        .recovery_states
+        .lock_or_recover()
+        .iter()
+        .filter(|(_, r)| r.crash_count > 0 || r.quarantined.is_some())
+        .map(|(name, r)| {
+            let saved = SavedRecovery {
+                crash_count: r.crash_count,
+                quarantined: r.quarantined.clone(),
+                exhausted: r.next_retry_at.is_none() && r.crash_count > 0,
+            };
+            (name.clone(), saved)
+        })
+        .collect::<HashMap<_, _>>();
+    let mutes = state
+        .stream_mutes
+        .entries()
+        .into_iter()
+        .filter(|(n, _)| known(n) && maintenance::muted(state, n).is_some());
+    let snapshot = RuntimeState {
+        disabled: disabled.into_iter().filter(|(n, _)| known(n)).collect(),
+        intents: intents.into_iter().filter(|(n, _)| known(n)).collect(),
+        recovery: recovery.into_iter().filter(|(n, _)| known(n)).collect(),
+        mutes: mutes.collect(),
+    };
+
+    match config.server.state_store {
+        StateStore::Json => save_json(&config, &snapshot),
+        StateStore::Sqlite => sqlite_backend::save(&config, snapshot),
+    }
+}
+
+fn save_json(config: &AppConfig, snapshot: &RuntimeState) -> anyhow::Result<()> {
+    let path = file_path(config);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(snapshot)?)?;
+    std::fs::rename(&tmp, &path).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))
+}
+
+/// 写入运行状态，失败时仅记录日志 (用于不影响请求结果的状态变化)
+pub fn save_or_warn(state: &AppState) {
+    if let Err(e) = save(state) {
+        warn!("Failed to save runtime state: {}", e);
+    }
+}
+
+/// 记录 (或清除) 运维人员的手动操作
+pub fn set_intent(state: &AppState, name: &str, intent: Option<Intent>) {
+    let changed = match intent {
+        Some(intent) => state
+            .stream_intents
+            .insert(name, intent)
+            .map(|previous| previous != Some(intent)),
+        None => state
+            .stream_intents
+            .remove(name)
+            .map(|previous| previous.is_some()),
+    };
+    match changed {
+        Ok(true) => save_or_warn(state),
+        Ok(false) => {}
+        Err(e) => warn!("Failed to record the manual action on [{}]: {}", name, e),
+    }
+}
+
+/// 流被回收 (空闲超时) 后清除手动启动记录，手动停止记录保留
+pub fn clear_started(state: &AppState, name: &str) {
+    if state.stream_intents.get(name) != Some(Intent::Started) {
+        return;
+    }
+    match state.stream_intents.remove(name) {
+        Ok(_) => save_or_warn(state),
+        Err(e) => warn!("Failed to clear the manual start of [{}]: {}", name, e),
+    }
+}
+
+/// 流是否被手动停止
+pub fn is_stopped(state: &AppState, name: &str) -> bool {
+    state.stream_intents.get(name) == Some(Intent::Stopped)
+}
+
+/// 启动时恢复上次手动启动的流 (auto_start 流由 Supervisor 负责)
+pub async fn resume_started(state: Arc<AppState>) {
+    let config = state.config();
+    let started: Vec<String> = state
+        .stream_intents
+        .entries()
+        .into_iter()
+        .filter(|(_, intent)| *intent == Intent::Started)
+        .map(|(name, _)| name)
+        .filter(|name| config.stream(name).is_some_and(|s| !s.auto_start))
+        .collect();
+    for name in started {
+        info!("Resuming manually started stream [{}]", name);
+        if let Err(e) = Engine::start_stream(&state, &name).await {
+            error!("Failed to resume stream [{}]: {}", name, e);
+        }
+    }
+}
+
+fn file_path(config: &AppConfig) -> PathBuf {
+    PathBuf::from(&config.server.state_root).join(FILE_NAME)
+}
+
+/// 运行状态数据库：禁用记录、手动操作、故障恢复状态与静默各占一张表
+#[cfg(feature = "sqlite")]
+mod sqlite_backend {
+    use super::{DisabledStream, Intent, Mute, RuntimeState, SavedRecovery, DB_FILE_NAME};
+    use crate::config::AppConfig;
+    use crate::sqlite::{Database, SqliteStore};
+    use crate::store::StreamStore;
+    use std::path::PathBuf;
+    use std::sync::{Arc, OnceLock};
+
+    struct Tables {
+        disabled: SqliteStore<DisabledStream>,
+        intents: SqliteStore<Intent>,
+        recovery: SqliteStore<SavedRecovery>,
+        mutes: SqliteStore<Mute>,
+    }
+
+    /// 首次使用时按当时的 server.state_root 打开
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+
+    fn tables(config: &AppConfig) -> anyhow::Result<&'static Tables> {
+        if let Some(tables) = TABLES.get() {
+            return Ok(tables);
+        }
+        let path = PathBuf::from(&config.server.state_root).join(DB_FILE_NAME);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = Arc::new(Database::open(&path)?);
+        let tables = Tables {
+            disabled: SqliteStore::open(db.clone(), "disabled")?,
+            intents: SqliteStore::open(db.clone(), "intents")?,
+            recovery: SqliteStore::open(db.clone(), "recovery")?,
+            mutes: SqliteStore::open(db, "mutes")?,
+        };
+        Ok(TABLES.get_or_init(|| tables))
+    }
+
+    pub fn load(config: &AppConfig) -> anyhow::Result<RuntimeState> {
+        let tables = tables(config)?;
+        Ok(RuntimeState {
+            disabled: tables.disabled.entries().into_iter().collect(),
+            intents: tables.intents.entries().into_iter().collect(),
+            recovery: tables.recovery.entries().into_iter().collect(),
+            mutes: tables.mutes.entries().into_iter().collect(),
+        })
+    }
+
+    pub fn save(config: &AppConfig, snapshot: RuntimeState) -> anyhow::Result<()> {
+        let tables = tables(config)?;
+        tables
+            .disabled
+            .replace_all(snapshot.disabled.into_iter().collect())?;
+        tables
+            .intents
+            .replace_all(snapshot.intents.into_iter().collect())?;
+        tables
+            .recovery
+            .replace_all(snapshot.recovery.into_iter().collect())?;
+        tables
+            .mutes
+            .replace_all(snapshot.mutes.into_iter().collect())
+    }
+}
+
+/// 未以 sqlite 特性编译 (配置校验已拒绝 server.state_store: sqlite)
+#[cfg(not(feature = "sqlite"))]
+mod sqlite_backend {
+    use super::RuntimeState;
+    use crate::config::AppConfig;
+
+    pub fn load(_config: &AppConfig) -> anyhow::Result<RuntimeState> {
+        anyhow::bail!("vtx-link was built without the sqlite feature")
+    }
+
+    pub fn save(_config: &AppConfig, _snapshot: RuntimeState) -> anyhow::Result<()> {
+        anyhow::bail!("vtx-link was built without the sqlite feature")
+    }
+}
@@ -0,0 +1,315 @@
+use crate::config::SandboxConfig;
+use std::path::Path;
+
+/// 为 FFmpeg 子进程启用沙箱：no_new_privs、Landlock 写入限制与可选的 seccomp 过滤
+///
+/// `writable` 为该进程允许写入的目录 (输出目录等)，其余文件系统只读。
+/// 内核不支持 Landlock 时仅记录一次告警，其余限制照常生效
+pub fn apply(
+    cmd: &mut tokio::process::Command,
+    config: &SandboxConfig,
+    writable: &[&Path],
+) -> anyhow::Result<()> {
+    imp::apply(cmd, config, writable)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::SandboxConfig;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::path::Path;
+    use std::sync::OnceLock;
+    use tracing::warn;
+
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    /// ABI v2
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// ABI v3
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// 拦截的系统调用：挂载、调试其他进程、内核模块、重启、命名空间与密钥管理等
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_kexec_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_acct,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "x86")]
+    const AUDIT_ARCH: Option<u32> = Some(0x4000_0003);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(target_arch = "arm")]
+    const AUDIT_ARCH: Option<u32> = Some(0x4000_0028);
+    #[cfg(target_arch = "riscv64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00F3);
+    #[cfg(all(target_arch = "mips", target_endian = "big"))]
+    const AUDIT_ARCH: Option<u32> = Some(0x0000_0008);
+    #[cfg(all(target_arch = "mips", target_endian = "little"))]
+    const AUDIT_ARCH: Option<u32> = Some(0x4000_0008);
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "riscv64",
+        target_arch = "mips"
+    )))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// 内核支持的 Landlock ABI 版本 (0 表示不支持)
+    fn landlock_abi() -> i64 {
+        static ABI: OnceLock<i64> = OnceLock::new();
+        *ABI.get_or_init(|| {
+            // SAFETY: 以空属性查询版本号，不创建规则集
+            let abi = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    std::ptr::null::<RulesetAttr>(),
+                    0usize,
+                    LANDLOCK_CREATE_RULESET_VERSION,
+                )
+            };
+            if abi < 0 {
+                warn!(
+                    "Landlock is not supported by this kernel ({}); FFmpeg writes are not restricted",
+                    io::Error::last_os_error()
+                );
+            }
+            abi.max(0)
+        })
+    }
+
+    pub fn apply(
+        cmd: &mut tokio::process::Command,
+        config: &SandboxConfig,
+        writable: &[&Path],
+    ) -> anyhow::Result<()> {
+        // 目录在父进程中打开 (O_CLOEXEC)，子进程只做系统调用，避免 fork 后分配内存
+        let mut rules: Vec<(File, u64)> = Vec::new();
+        let mut handled = 0;
+        let abi = if config.landlock { landlock_abi() } else { 0 };
+        if abi > 0 {
+            let create = ACCESS_FS_MAKE_CHAR
+                | ACCESS_FS_MAKE_DIR
+                | ACCESS_FS_MAKE_REG
+                | ACCESS_FS_MAKE_SOCK
+                | ACCESS_FS_MAKE_FIFO
+                | ACCESS_FS_MAKE_BLOCK
+                | ACCESS_FS_MAKE_SYM;
+            handled = ACCESS_FS_WRITE_FILE | ACCESS_FS_REMOVE_DIR | ACCESS_FS_REMOVE_FILE | create;
+            if abi >= 2 {
+                handled |= ACCESS_FS_REFER;
+            }
+            let truncate = if abi >= 3 { ACCESS_FS_TRUNCATE } else { 0 };
+            handled |= truncate;
+
+            let extra = config.writable_paths.iter().map(Path::new);
+            for dir in writable.iter().copied().chain(extra) {
+                let file = File::open(dir)
+                    .map_err(|e| anyhow::anyhow!("Sandbox: failed to open {:?}: {}", dir, e))?;
+                rules.push((file, handled));
+            }
+            // 设备节点保持可写 (/dev/null 与硬件编解码设备)，但不允许创建或删除
+            if let Ok(dev) = File::open("/dev") {
+                rules.push((dev, ACCESS_FS_WRITE_FILE | truncate));
+            }
+        }
+
+        let filter = match (config.seccomp, AUDIT_ARCH) {
+            (true, Some(arch)) => seccomp_filter(arch),
+            (true, None) => anyhow::bail!("Sandbox: seccomp is not supported on this architecture"),
+            (false, _) => Vec::new(),
+        };
+
+        // SAFETY: 闭包在 fork 后、exec 前运行，只调用不分配内存的系统调用
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if handled != 0 {
+                    restrict_writes(handled, &rules)?;
+                }
+                if !filter.is_empty() {
+                    let prog = libc::sock_fprog {
+                        len: filter.len() as libc::c_ushort,
+                        filter: filter.as_ptr() as *mut libc::sock_filter,
+                    };
+                    if libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER,
+                        &prog as *const libc::sock_fprog,
+                    ) != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// 创建 Landlock 规则集：仅规则中的目录保留写入权限
+    unsafe fn restrict_writes(handled: u64, rules: &[(File, u64)]) -> io::Result<()> {
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let ruleset = libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        );
+        if ruleset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ruleset = ruleset as RawFd;
+        for (dir, access) in rules {
+            let rule = PathBeneathAttr {
+                allowed_access: *access,
+                parent_fd: dir.as_raw_fd(),
+            };
+            if libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset,
+                LANDLOCK_RULE_PATH_BENEATH,
+                &rule as *const PathBeneathAttr,
+                0u32,
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let res = libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0u32);
+        libc::close(ruleset);
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// 生成 seccomp BPF 程序：架构不符时终止进程，被拦截的系统调用返回 EPERM
+    fn seccomp_filter(arch: u32) -> Vec<libc::sock_filter> {
+        const ARCH_OFFSET: u32 = 4;
+        const NR_OFFSET: u32 = 0;
+        let load = |offset| bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+        let deny = bpf_stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+        );
+
+        let mut filter = vec![
+            load(ARCH_OFFSET),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, arch, 1, 0),
+            bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            load(NR_OFFSET),
+        ];
+        // x32 系统调用号带有 0x40000000 标志位，一律拒绝以免绕过过滤
+        #[cfg(target_arch = "x86_64")]
+        {
+            const BPF_JGE: u32 = 0x30;
+            filter.push(bpf_jump(
+                libc::BPF_JMP | BPF_JGE | libc::BPF_K,
+                0x4000_0000,
+                0,
+                1,
+            ));
+            filter.push(deny);
+        }
+        for nr in DENIED_SYSCALLS {
+            filter.push(bpf_jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                *nr as u32,
+                0,
+                1,
+            ));
+            filter.push(deny);
+        }
+        filter.push(bpf_stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ALLOW,
+        ));
+        filter
+    }
+
+    fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::SandboxConfig;
+    use std::path::Path;
+
+    pub fn apply(
+        _cmd: &mut tokio::process::Command,
+        _config: &SandboxConfig,
+        _writable: &[&Path],
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("sandbox is only supported on Linux")
+    }
+}
@@ -0,0 +1,626 @@
+use crate::state::{AppState, LockExt};
+use axum::body::Bytes;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tracing::warn;
+
+/// 所有输出目录共享的切片缓存总字节数上限，超出时丢弃最久未被请求的切片
+pub const SEGMENT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// 输出目录中的一个文件
+#[derive(Debug, Clone, Copy)]
+pub struct FileInfo {
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    files: HashMap<String, FileInfo>,
+    /// 已完整写出的播放列表内容 (文件名 -> 文本)
+    playlists: HashMap<String, String>,
+}
+
+/// 待下发的切片
+pub enum Segment {
+    /// 缓存中的完整内容，由所有观看者共享
+    Cached(Bytes),
+    /// 未缓存的切片 (未被监视的目录、原地写入中或超出单个切片上限)，由调用方从文件流式发送
+    File(File),
+}
+
+/// 已写完切片的内存缓存，所有输出目录共享一个总预算 (`SEGMENT_CACHE_BYTES`)
+///
+/// 热门的直播切片每个只读取一次，所有观看者共享同一份内容，不再逐个观看者读取与缓冲；
+/// 之后文件被截断、改写或删除都不影响已开始的传输
+#[derive(Debug)]
+pub struct SegmentCache {
+    max_bytes: usize,
+    inner: Mutex<CacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    /// 切片路径 -> (内容, 最近一次请求的时间)
+    segments: HashMap<PathBuf, (Bytes, Instant)>,
+    bytes: usize,
+}
+
+impl SegmentCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            inner: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    /// 单个切片最多占用总预算的四分之一，更大的切片直接从文件发送
+    fn admits(&self, size: u64) -> bool {
+        size <= (self.max_bytes / 4) as u64
+    }
+
+    fn get(&self, path: &Path) -> Option<Bytes> {
+        let mut inner = self.inner.lock_or_recover();
+        let (data, used) = inner.segments.get_mut(path)?;
+        *used = Instant::now();
+        Some(data.clone())
+    }
+
+    /// 加入切片，超出总预算时按最近请求时间从早到晚丢弃
+    fn insert(&self, path: PathBuf, data: Bytes) {
+        let mut inner = self.inner.lock_or_recover();
+        inner.bytes += data.len();
+        if let Some((old, _)) = inner.segments.insert(path, (data, Instant::now())) {
+            inner.bytes -= old.len();
+        }
+        while inner.bytes > self.max_bytes {
+            let Some(oldest) = inner
+                .segments
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            if let Some((data, _)) = inner.segments.remove(&oldest) {
+                inner.bytes -= data.len();
+            }
+        }
+    }
+
+    fn remove(&self, path: &Path) {
+        let mut inner = self.inner.lock_or_recover();
+        if let Some((data, _)) = inner.segments.remove(path) {
+            inner.bytes -= data.len();
+        }
+    }
+
+    /// 丢弃目录中的全部切片 (目录不再被索引或需要重新读取时)
+    fn remove_dir(&self, dir: &Path) {
+        let mut inner = self.inner.lock_or_recover();
+        let CacheEntries { segments, bytes } = &mut *inner;
+        segments.retain(|path, (data, _)| {
+            let keep = path.parent() != Some(dir);
+            if !keep {
+                *bytes -= data.len();
+            }
+            keep
+        });
+    }
+
+    /// 缓存中切片的总字节数
+    pub fn bytes(&self) -> usize {
+        self.inner.lock_or_recover().bytes
+    }
+}
+
+/// 一个流输出目录的内存索引 (切片名称、大小、修改时间与当前播放列表内容)
+///
+/// 由文件系统监视 (Linux inotify) 维护，HLS 下发、热备清理与存储统计据此读取输出状态，
+/// 热路径上不再反复 stat / readdir。未被监视时 (非 Linux、监视失败或目录已被删除)
+/// 各方法直接访问磁盘，调用方无需区分
+#[derive(Debug)]
+pub struct DirIndex {
+    dir: PathBuf,
+    live: AtomicBool,
+    /// inotify 监视描述符
+    watch: Mutex<Option<i32>>,
+    entries: Mutex<Entries>,
+    cache: Arc<SegmentCache>,
+}
+
+impl DirIndex {
+    fn new(dir: PathBuf, cache: Arc<SegmentCache>) -> Self {
+        Self {
+            dir,
+            live: AtomicBool::new(false),
+            watch: Mutex::new(None),
+            entries: Mutex::new(Entries::default()),
+            cache,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 索引是否由文件系统监视维护
+    pub fn is_live(&self) -> bool {
+        self.live.load(Ordering::Acquire)
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        if self.is_live() {
+            self.entries.lock_or_recover().files.contains_key(name)
+        } else {
+            self.dir.join(name).exists()
+        }
+    }
+
+    pub fn modified(&self, name: &str) -> Option<SystemTime> {
+        if self.is_live() {
+            self.entries
+                .lock_or_recover()
+                .files
+                .get(name)
+                .map(|f| f.modified)
+        } else {
+            std::fs::metadata(self.dir.join(name))
+                .and_then(|m| m.modified())
+                .ok()
+        }
+    }
+
+    /// 索引中已完整写出的播放列表；未被监视时为 None，由调用方从磁盘读取
+    pub fn playlist(&self, name: &str) -> Option<String> {
+        if !self.is_live() {
+            return None;
+        }
+        self.entries.lock_or_recover().playlists.get(name).cloned()
+    }
+
+    /// 打开切片供下发：被监视时已写完的切片只读取一次，放入共享缓存 (见 `SegmentCache`)
+    ///
+    /// 只缓存索引中已写完 (IN_CLOSE_WRITE / IN_MOVED_TO) 且读取前后记录不变的切片；
+    /// 未被监视的目录、FFmpeg 原地写入中的切片与超出单个切片上限的切片以文件返回，由调用方流式发送
+    pub async fn segment(&self, name: &str) -> std::io::Result<Segment> {
+        let path = self.dir.join(name);
+        let written = if self.is_live() {
+            if let Some(data) = self.cache.get(&path) {
+                return Ok(Segment::Cached(data));
+            }
+            self.entries.lock_or_recover().files.get(name).copied()
+        } else {
+            None
+        };
+        let mut file = File::open(&path).await?;
+        let Some(written) = written.filter(|w| self.cache.admits(w.size)) else {
+            return Ok(Segment::File(file));
+        };
+        // 文件已不同于索引记录 (仍在写入或已被改写) 时不读入内存
+        let meta = file.metadata().await?;
+        if meta.len() != written.size || meta.modified().ok() != Some(written.modified) {
+            return Ok(Segment::File(file));
+        }
+        let mut data = Vec::with_capacity(written.size as usize);
+        file.read_to_end(&mut data).await?;
+        let data = Bytes::from(data);
+        // 与文件事件的处理 (update / remove) 在同一把锁下，不会缓存已过期的内容
+        let entries = self.entries.lock_or_recover();
+        let unchanged = entries
+            .files
+            .get(name)
+            .is_some_and(|f| f.size == written.size && f.modified == written.modified);
+        if unchanged && data.len() as u64 == written.size {
+            self.cache.insert(path, data.clone());
+        }
+        Ok(Segment::Cached(data))
+    }
+
+    /// 目录中的全部文件 (不含子目录)
+    pub fn files(&self) -> Vec<(String, FileInfo)> {
+        if self.is_live() {
+            let entries = self.entries.lock_or_recover();
+            entries
+                .files
+                .iter()
+                .map(|(name, info)| (name.clone(), *info))
+                .collect()
+        } else {
+            scan(&self.dir)
+        }
+    }
+
+    /// 目录中文件的总字节数
+    pub fn total_bytes(&self) -> u64 {
+        self.files().iter().map(|(_, f)| f.size).sum()
+    }
+
+    /// 重新读取整个目录 (开始监视时与事件队列溢出后)
+    fn rescan(&self) {
+        let files = scan(&self.dir);
+        let playlists = files
+            .iter()
+            .filter(|(name, _)| is_playlist(name))
+            .filter_map(|(name, _)| {
+                let text = std::fs::read_to_string(self.dir.join(name)).ok()?;
+                Some((name.clone(), text))
+            })
+            .collect();
+        let mut entries = self.entries.lock_or_recover();
+        *entries = Entries {
+            files: files.into_iter().collect(),
+            playlists,
+        };
+        self.cache.remove_dir(&self.dir);
+    }
+
+    /// 文件写完或被移入后更新其记录
+    fn update(&self, name: &str) {
+        let path = self.dir.join(name);
+        let Ok(meta) = std::fs::metadata(&path) else {
+            self.remove(name);
+            return;
+        };
+        if !meta.is_file() {
+            return;
+        }
+        let info = FileInfo {
+            size: meta.len(),
+            modified: meta.modified().unwrap_or_else(|_| SystemTime::now()),
+        };
+        let text = is_playlist(name)
+            .then(|| std::fs::read_to_string(&path).ok())
+            .flatten();
+        let mut entries = self.entries.lock_or_recover();
+        entries.files.insert(name.to_string(), info);
+        self.cache.remove(&path);
+        if let Some(text) = text {
+            entries.playlists.insert(name.to_string(), text);
+        }
+    }
+
+    fn remove(&self, name: &str) {
+        let mut entries = self.entries.lock_or_recover();
+        entries.files.remove(name);
+        entries.playlists.remove(name);
+        self.cache.remove(&self.dir.join(name));
+    }
+}
+
+/// 播放列表 (FFmpeg 先写 `.m3u8.tmp` 再重命名，临时文件不算)
+fn is_playlist(name: &str) -> bool {
+    name.ends_with(".m3u8")
+}
+
+fn scan(dir: &Path) -> Vec<(String, FileInfo)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            let info = FileInfo {
+                size: meta.len(),
+                modified: meta.modified().ok()?,
+            };
+            Some((entry.file_name().to_string_lossy().to_string(), info))
+        })
+        .collect()
+}
+
+/// 开始索引流的输出目录 (在目录重新创建之后调用)，替换该流原有的索引
+pub fn watch(state: &AppState, name: &str, dir: &Path) {
+    let index = Arc::new(DirIndex::new(
+        dir.to_path_buf(),
+        state.segment_cache.clone(),
+    ));
+    // 先建立监视再读取目录，读取期间的变化不会遗漏
+    inotify::add(&index);
+    if index.is_live() {
+        index.rescan();
+    }
+    let previous = state
+        .segment_indexes
+        .lock_or_recover()
+        .insert(name.to_string(), index.clone());
+    if let Some(previous) = previous {
+        inotify::remove(&previous);
+        if previous.dir != index.dir {
+            state.segment_cache.remove_dir(&previous.dir);
+        }
+    }
+}
+
+/// 停止索引流的输出目录
+pub fn unwatch(state: &AppState, name: &str) {
+    if let Some(index) = state.segment_indexes.lock_or_recover().remove(name) {
+        inotify::remove(&index);
+        state.segment_cache.remove_dir(&index.dir);
+    }
+}
+
+/// 流输出目录的索引；流未被索引时返回直接访问磁盘的临时索引
+pub fn get(state: &AppState, name: &str, dir: &Path) -> Arc<DirIndex> {
+    state
+        .segment_indexes
+        .lock_or_recover()
+        .get(name)
+        .filter(|index| index.dir == dir)
+        .cloned()
+        .unwrap_or_else(|| {
+            Arc::new(DirIndex::new(
+                dir.to_path_buf(),
+                state.segment_cache.clone(),
+            ))
+        })
+}
+
+/// Linux：所有输出目录共用一个 inotify 实例，由一个后台线程读取事件
+#[cfg(target_os = "linux")]
+mod inotify {
+    use super::{warn, DirIndex, LockExt};
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex, OnceLock};
+
+    /// 写完、移入、删除与移出；目录自身被删除时内核发送 IN_IGNORED
+    const MASK: u32 =
+        libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE | libc::IN_MOVED_FROM;
+
+    struct Watcher {
+        fd: i32,
+        /// 监视描述符 -> 索引
+        watches: Mutex<HashMap<i32, Arc<DirIndex>>>,
+    }
+
+    static WATCHER: OnceLock<Option<Arc<Watcher>>> = OnceLock::new();
+
+    fn watcher() -> Option<&'static Arc<Watcher>> {
+        WATCHER
+            .get_or_init(|| {
+                // SAFETY: 无指针参数
+                let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+                if fd < 0 {
+                    warn!(
+                        "inotify is unavailable ({}); output directories are read from disk",
+                        std::io::Error::last_os_error()
+                    );
+                    return None;
+                }
+                let watcher = Arc::new(Watcher {
+                    fd,
+                    watches: Mutex::new(HashMap::new()),
+                });
+                let reader = watcher.clone();
+                let spawned = std::thread::Builder::new()
+                    .name("segment-index".to_string())
+                    .spawn(move || read_events(&reader));
+                if let Err(e) = spawned {
+                    warn!("Failed to start the segment index thread: {}", e);
+                    return None;
+                }
+                Some(watcher)
+            })
+            .as_ref()
+    }
+
+    pub fn add(index: &Arc<DirIndex>) {
+        let Some(watcher) = watcher() else {
+            return;
+        };
+        let Ok(path) = CString::new(index.dir.as_os_str().as_bytes()) else {
+            return;
+        };
+        // 持有索引表的锁，事件线程在登记完成前不会处理该描述符的事件
+        let mut watches = watcher.watches.lock_or_recover();
+        // SAFETY: fd 有效，path 以 NUL 结尾
+        let wd = unsafe { libc::inotify_add_watch(watcher.fd, path.as_ptr(), MASK) };
+        if wd < 0 {
+            warn!(
+                "Failed to watch {:?}: {}",
+                index.dir,
+                std::io::Error::last_os_error()
+            );
+            return;
+        }
+        watches.insert(wd, index.clone());
+        *index.watch.lock_or_recover() = Some(wd);
+        index.live.store(true, Ordering::Release);
+    }
+
+    pub fn remove(index: &DirIndex) {
+        index.live.store(false, Ordering::Release);
+        let (Some(watcher), Some(wd)) = (watcher(), index.watch.lock_or_recover().take()) else {
+            return;
+        };
+        let mut watches = watcher.watches.lock_or_recover();
+        // 同一目录可能已被新的索引重新监视 (内核返回相同的描述符)
+        if watches
+            .get(&wd)
+            .is_some_and(|i| std::ptr::eq(i.as_ref(), index))
+        {
+            watches.remove(&wd);
+            // SAFETY: fd 与 wd 有效；目录已被删除时调用失败，无副作用
+            unsafe {
+                libc::inotify_rm_watch(watcher.fd, wd);
+            }
+        }
+    }
+
+    fn read_events(watcher: &Watcher) {
+        let mut buf = vec![0u8; 64 * 1024];
+        let header = std::mem::size_of::<libc::inotify_event>();
+        loop {
+            // SAFETY: buf 在读取期间有效，长度正确
+            let n = unsafe { libc::read(watcher.fd, buf.as_mut_ptr().cast(), buf.len()) };
+            if n < 0 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                warn!("Segment index stopped: {}", e);
+                for index in watcher.watches.lock_or_recover().values() {
+                    index.live.store(false, Ordering::Release);
+                }
+                return;
+            }
+            let mut offset = 0;
+            while offset + header <= n as usize {
+                // SAFETY: 内核写入的是完整的 inotify_event 序列，可能未对齐，按值读取
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+                let name_bytes = &buf[offset + header..offset + header + event.len as usize];
+                let name_len = name_bytes
+                    .iter()
+                    .position(|b| *b == 0)
+                    .unwrap_or(name_bytes.len());
+                let name = OsStr::from_bytes(&name_bytes[..name_len]).to_string_lossy();
+                offset += header + event.len as usize;
+                handle(watcher, event.wd, event.mask, &name);
+            }
+        }
+    }
+
+    fn handle(watcher: &Watcher, wd: i32, mask: u32, name: &str) {
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            warn!("Segment index event queue overflowed, rescanning output directories");
+            let indexes: Vec<_> = watcher
+                .watches
+                .lock_or_recover()
+                .values()
+                .cloned()
+                .collect();
+            for index in indexes {
+                index.rescan();
+            }
+            return;
+        }
+        let Some(index) = watcher.watches.lock_or_recover().get(&wd).cloned() else {
+            return;
+        };
+        if mask & libc::IN_IGNORED != 0 {
+            // 目录被删除或卸载，此后回到直接读取磁盘
+            index.live.store(false, Ordering::Release);
+            watcher.watches.lock_or_recover().remove(&wd);
+            index.watch.lock_or_recover().take();
+        } else if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+            index.remove(name);
+        } else if !name.is_empty() {
+            index.update(name);
+        }
+    }
+}
+
+/// 其他平台没有监视，索引始终直接访问磁盘
+#[cfg(not(target_os = "linux"))]
+mod inotify {
+    use super::DirIndex;
+    use std::sync::Arc;
+
+    pub fn add(_index: &Arc<DirIndex>) {}
+
+    pub fn remove(_index: &DirIndex) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn live_index(tag: &str, cache: &Arc<SegmentCache>) -> DirIndex {
+        let dir = std::env::temp_dir().join(format!("vtx-index-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = DirIndex::new(dir, cache.clone());
+        index.live.store(true, Ordering::Release);
+        index
+    }
+
+    fn write(index: &DirIndex, name: &str, len: usize) {
+        std::fs::write(index.dir().join(name), vec![7u8; len]).unwrap();
+        index.update(name);
+    }
+
+    async fn cached(index: &DirIndex, name: &str) -> Option<usize> {
+        match index.segment(name).await.unwrap() {
+            Segment::Cached(data) => Some(data.len()),
+            Segment::File(_) => None,
+        }
+    }
+
+    /// 已缓存的切片不受之后文件截断影响，文件事件到达后重新读取
+    #[tokio::test]
+    async fn cached_segment_survives_truncation() {
+        let cache = Arc::new(SegmentCache::new(1 << 20));
+        let index = live_index("truncate", &cache);
+        write(&index, "seg0.ts", 4096);
+
+        assert_eq!(cached(&index, "seg0.ts").await, Some(4096));
+        std::fs::write(index.dir().join("seg0.ts"), b"").unwrap();
+        assert_eq!(cached(&index, "seg0.ts").await, Some(4096));
+
+        index.update("seg0.ts");
+        assert_eq!(cache.bytes(), 0);
+        assert_eq!(cached(&index, "seg0.ts").await, Some(0));
+        let _ = std::fs::remove_dir_all(index.dir());
+    }
+
+    /// 与索引记录不一致的切片 (仍在原位写入)、过大的切片与未被监视的目录从文件发送
+    #[tokio::test]
+    async fn uncacheable_segments_are_streamed() {
+        let cache = Arc::new(SegmentCache::new(4000));
+        let index = live_index("stream", &cache);
+        write(&index, "seg1.ts", 100);
+        std::fs::write(index.dir().join("seg1.ts"), vec![1u8; 300]).unwrap();
+        assert_eq!(cached(&index, "seg1.ts").await, None);
+
+        write(&index, "big.ts", 1001);
+        assert_eq!(cached(&index, "big.ts").await, None);
+
+        let unwatched = DirIndex::new(index.dir().to_path_buf(), cache.clone());
+        write(&index, "seg2.ts", 100);
+        assert_eq!(cached(&unwatched, "seg2.ts").await, None);
+        assert_eq!(cache.bytes(), 0);
+        let _ = std::fs::remove_dir_all(index.dir());
+    }
+
+    /// 预算由所有目录共享，超出时丢弃最久未被请求的切片
+    #[tokio::test]
+    async fn budget_is_shared_across_directories() {
+        let cache = Arc::new(SegmentCache::new(2000));
+        let a = live_index("budget-a", &cache);
+        let b = live_index("budget-b", &cache);
+        for name in ["0.ts", "1.ts", "2.ts"] {
+            write(&a, name, 450);
+            write(&b, name, 450);
+        }
+        let requests = [
+            (&a, "0.ts"),
+            (&b, "0.ts"),
+            (&a, "1.ts"),
+            (&b, "1.ts"),
+            (&a, "2.ts"),
+        ];
+        for (index, name) in requests {
+            assert_eq!(cached(index, name).await, Some(450));
+        }
+        assert_eq!(cache.bytes(), 1800);
+        let kept = |index: &DirIndex, name: &str| cache.get(&index.dir().join(name)).is_some();
+        assert!(!kept(&a, "0.ts"));
+        assert!(kept(&b, "0.ts") && kept(&a, "1.ts") && kept(&b, "1.ts") && kept(&a, "2.ts"));
+
+        cache.remove_dir(a.dir());
+        assert_eq!(cache.bytes(), 900);
+        for index in [a, b] {
+            let _ = std::fs::remove_dir_all(index.dir());
+        }
+    }
+}
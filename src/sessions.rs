@@ -0,0 +1,296 @@
+use crate::clock;
+use crate::http_client;
+use crate::state::{AppState, LockExt};
+use axum::http::{header, HeaderMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// 观看事件回调超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 观看会话表 (Viewer ID -> 最后活跃时间)
+pub type ViewerSessions = std::collections::HashMap<String, Instant>;
+
+/// 流的观看状态 (用于观看事件)
+#[derive(Debug, Default)]
+pub struct Presence {
+    /// 已通知的状态：是否有人观看
+    watching: bool,
+    /// 观察到与已通知状态不同的时间，保持满防抖时间后才通知
+    changed_at: Option<Instant>,
+}
+
+/// 客户端地址：对端为 `server.trusted_proxies` 中的代理时按 X-Forwarded-For 逐跳回溯，
+/// 否则为对端地址 (客户端自带的 X-Forwarded-For 不可信，不能用来绕过地区限制或伪造地址)
+pub fn client_ip(state: &AppState, headers: &HeaderMap, peer: SocketAddr) -> String {
+    forwarded_client(&state.config().server.trusted_proxies, headers, peer).to_string()
+}
+
+/// 自右向左遍历 X-Forwarded-For：每一跳由其右侧的可信代理追加，遇到第一个非可信地址即为客户端；
+/// 无法解析的条目之前的内容不可信，停在已确认的最后一跳
+fn forwarded_client(trusted: &[String], headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+    let is_trusted = |ip: IpAddr| {
+        trusted
+            .iter()
+            .filter_map(|net| parse_net(net))
+            .any(|net| contains(net, ip))
+    };
+    let mut client = peer.ip();
+    if !is_trusted(client) {
+        return client;
+    }
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    for hop in hops.iter().rev() {
+        let ip = hop
+            .parse::<IpAddr>()
+            .or_else(|_| hop.parse::<SocketAddr>().map(|s| s.ip()));
+        let Ok(ip) = ip else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// 解析 IP 地址或 CIDR 网段，返回 (网络地址, 前缀长度)
+pub fn parse_net(net: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match net.split_once('/') {
+        Some((addr, prefix)) => (
+            addr.parse::<IpAddr>().ok()?,
+            Some(prefix.parse::<u8>().ok()?),
+        ),
+        None => (net.parse::<IpAddr>().ok()?, None),
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    (prefix <= max).then_some((addr, prefix))
+}
+
+fn contains((net, prefix): (IpAddr, u8), ip: IpAddr) -> bool {
+    // IPv4 映射的 IPv6 地址 (双栈监听) 按 IPv4 比较
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    };
+    match (net, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// 由客户端地址与 User-Agent 生成观看者标识
+pub fn viewer_id(state: &AppState, kind: &str, headers: &HeaderMap, peer: SocketAddr) -> String {
+    let addr = client_ip(state, headers, peer);
+    let agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    let mut hasher = DefaultHasher::new();
+    agent.hash(&mut hasher);
+    format!("{}:{}:{:08x}", kind, addr, hasher.finish() as u32)
+}
+
+/// 刷新观看会话，同时刷新流的活跃时间
+pub fn touch(state: &AppState, stream: &str, viewer: &str) {
+    let now = Instant::now();
+    state
+        .viewer_sessions
+        .lock_or_recover()
+        .entry(stream.to_string())
+        .or_default()
+        .insert(viewer.to_string(), now);
+
+    if let Some(running) = state.active_streams.lock_or_recover().get_mut(stream) {
+        running.last_accessed = now;
+        if running.standby_since.take().is_some() {
+            info!("Stream [{}] resumed from standby.", stream);
+        }
+    }
+    if let Some(session) = state.proxy_sessions.lock_or_recover().get_mut(stream) {
+        session.last_accessed = now;
+    }
+}
+
+/// 当前在线的观看者数量
+pub fn active_count(state: &AppState, stream: &str) -> usize {
+    let timeout = timeout(state);
+    state
+        .viewer_sessions
+        .lock_or_recover()
+        .get(stream)
+        .map(|s| s.values().filter(|t| t.elapsed() < timeout).count())
+        .unwrap_or(0)
+}
+
+/// 当前在线观看者的客户端地址 (取自观看者标识 `kind:addr:hash`)
+pub fn active_addresses(state: &AppState, stream: &str) -> Vec<String> {
+    let timeout = timeout(state);
+    state
+        .viewer_sessions
+        .lock_or_recover()
+        .get(stream)
+        .map(|s| {
+            s.iter()
+                .filter(|(_, t)| t.elapsed() < timeout)
+                .filter_map(|(id, _)| {
+                    let (_, rest) = id.split_once(':')?;
+                    Some(rest.rsplit_once(':')?.0.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 移除过期的观看会话
+pub fn prune(state: &AppState) {
+    let timeout = timeout(state);
+    let mut sessions = state.viewer_sessions.lock_or_recover();
+    for viewers in sessions.values_mut() {
+        viewers.retain(|_, t| t.elapsed() < timeout);
+    }
+    sessions.retain(|_, viewers| !viewers.is_empty());
+}
+
+/// 会话超时：超过该时间没有请求播放列表 (或保持连接) 的观看者视为离开
+pub fn timeout(state: &AppState) -> Duration {
+    Duration::from_secs(state.config().server.session_timeout_sec)
+}
+
+/// 检查配置了观看事件的流，在首个观看者加入与最后一个观看者离开时通知
+///
+/// 状态变化需保持 `debounce_sec` 才会通知；离开本身已按会话超时判定
+pub fn notify_presence(state: &AppState) {
+    let config = state.config();
+    let now = Instant::now();
+    let mut events = Vec::new();
+    {
+        let mut presence = state.viewer_presence.lock_or_recover();
+        presence.retain(|name, _| {
+            config
+                .stream(name)
+                .is_some_and(|c| c.viewer_events.is_some())
+        });
+        for cfg in &config.streams {
+            let Some(ev) = &cfg.viewer_events else {
+                continue;
+            };
+            let viewers = active_count(state, &cfg.name);
+            let entry = presence.entry(cfg.name.clone()).or_default();
+            if (viewers > 0) == entry.watching {
+                entry.changed_at = None;
+                continue;
+            }
+            let since = *entry.changed_at.get_or_insert(now);
+            if now.duration_since(since) >= Duration::from_secs(ev.debounce_sec) {
+                entry.watching = viewers > 0;
+                entry.changed_at = None;
+                let event = if entry.watching {
+                    "viewer_join"
+                } else {
+                    "viewer_leave"
+                };
+                events.push((cfg.name.clone(), event, viewers, ev.webhook.clone()));
+            }
+        }
+    }
+
+    for (name, event, viewers, webhook) in events {
+        info!("Stream [{}] {} ({} viewers)", name, event, viewers);
+        let Some(url) = webhook else {
+            continue;
+        };
+        let body = serde_json::json!({
+            "stream": name,
+            "event": event,
+            "viewers": viewers,
+            "time": clock::rfc3339(SystemTime::now()),
+        });
+        tokio::spawn(async move {
+            if let Err(e) = http_client::send_json("POST", &url, &body, None, WEBHOOK_TIMEOUT).await
+            {
+                warn!("Viewer event webhook failed [{}]: {}", name, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn xff(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn peer(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 40000)
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peers() {
+        let headers = xff("203.0.113.9");
+        let trusted = vec!["10.0.0.0/8".to_string()];
+        let ip = forwarded_client(&trusted, &headers, peer("198.51.100.7"));
+        assert_eq!(ip.to_string(), "198.51.100.7");
+        let ip = forwarded_client(&[], &headers, peer("10.0.0.2"));
+        assert_eq!(ip.to_string(), "10.0.0.2");
+    }
+
+    #[test]
+    fn takes_rightmost_untrusted_hop() {
+        // 客户端伪造的首个地址被忽略，取可信代理之前的最后一跳
+        let headers = xff("1.2.3.4, 198.51.100.7, 10.0.0.5");
+        let trusted = vec!["10.0.0.0/8".to_string()];
+        let ip = forwarded_client(&trusted, &headers, peer("10.0.0.1"));
+        assert_eq!(ip.to_string(), "198.51.100.7");
+    }
+
+    #[test]
+    fn stops_at_malformed_hop() {
+        let headers = xff("198.51.100.7, garbage");
+        let trusted = vec!["10.0.0.1".to_string()];
+        let ip = forwarded_client(&trusted, &headers, peer("10.0.0.1"));
+        assert_eq!(ip.to_string(), "10.0.0.1");
+    }
+
+    #[test]
+    fn matches_cidr_and_mapped_addresses() {
+        let net = parse_net("192.168.0.0/16").unwrap();
+        assert!(contains(net, "192.168.4.2".parse().unwrap()));
+        assert!(contains(net, "::ffff:192.168.4.2".parse().unwrap()));
+        assert!(!contains(net, "192.169.0.1".parse().unwrap()));
+        assert!(contains(
+            parse_net("::/0").unwrap(),
+            "2001:db8::1".parse().unwrap()
+        ));
+        assert!(contains(
+            parse_net("0.0.0.0/0").unwrap(),
+            "8.8.8.8".parse().unwrap()
+        ));
+        assert!(parse_net("10.0.0.0/33").is_none());
+        assert!(parse_net("not-an-ip").is_none());
+    }
+}
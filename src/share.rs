@@ -0,0 +1,99 @@
+use crate::config::{AppConfig, StreamConfig};
+use crate::state::{LockExt, SharedState};
+use crate::test_source;
+use crate::ts;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// 能否与其他流共享拉取：由本地 FFmpeg 直接拉取外部源的流
+fn is_shareable(stream: &StreamConfig) -> bool {
+    !stream.is_proxied()
+        && stream.mosaic.is_none()
+        && stream.file_loop.is_none()
+        && !stream.source.is_empty()
+        && !test_source::is_test(&stream.source)
+}
+
+/// 为该流拉取源的流：同一源地址的第一个流 (配置顺序)，流自身即为拉取者或未开启共享时为 None
+///
+/// 读取共享源的流依赖拉取者 (见 `dependency`)：拉取者随其启动，在仍有读取者时不因空闲被回收，
+/// 重启后读取者随之重启
+pub fn owner<'a>(config: &'a AppConfig, stream: &StreamConfig) -> Option<&'a StreamConfig> {
+    if !config.server.share_sources || !is_shareable(stream) {
+        return None;
+    }
+    config
+        .streams
+        .iter()
+        .find(|s| is_shareable(s) && s.source == stream.source)
+        .filter(|s| s.name != stream.name)
+}
+
+/// 流是否为其他流拉取源 (其 FFmpeg 需额外输出源的 MPEG-TS 副本)
+pub fn is_owner(config: &AppConfig, stream: &StreamConfig) -> bool {
+    config
+        .streams
+        .iter()
+        .any(|s| owner(config, s).is_some_and(|o| o.name == stream.name))
+}
+
+/// 读取共享源的输入地址 (`tcp://127.0.0.1:{port}`)，首次使用时监听本地回环端口
+///
+/// 每个连接从连接时起接收拉取者的 MPEG-TS 副本，拉取者退出时连接随之关闭
+pub async fn input_url(state: &SharedState, owner: &str) -> anyhow::Result<String> {
+    let port = state.share_ports.lock_or_recover().get(owner).copied();
+    let port = match port {
+        Some(port) => port,
+        None => {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let port = listener.local_addr()?.port();
+            let existing = *state
+                .share_ports
+                .lock_or_recover()
+                .entry(owner.to_string())
+                .or_insert(port);
+            // 并发的首次使用中先登记者生效，多余的监听随之关闭
+            if existing == port {
+                info!("Stream [{}] shares its source on 127.0.0.1:{}", owner, port);
+                tokio::spawn(serve(state.clone(), owner.to_string(), listener));
+            }
+            existing
+        }
+    };
+    Ok(format!("tcp://127.0.0.1:{}", port))
+}
+
+async fn serve(state: SharedState, owner: String, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, _)) => {
+                tokio::spawn(forward(state.clone(), owner.clone(), socket));
+            }
+            Err(e) => warn!("Shared source [{}] accept failed: {}", owner, e),
+        }
+    }
+}
+
+/// 向一个读取者转发拉取者的输出，读取者跟不上时跳过积压的数据
+async fn forward(state: SharedState, owner: String, mut socket: TcpStream) {
+    let Some(mut rx) = ts::subscribe(&state, &owner) else {
+        warn!(
+            "Shared source [{}] is not running; closing reader connection",
+            owner
+        );
+        return;
+    };
+    loop {
+        match rx.recv().await {
+            Ok(chunk) => {
+                if socket.write_all(&chunk).await.is_err() {
+                    return;
+                }
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::state::LockExt;
+use crate::store::{MemoryStore, StreamStore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+/// 由 SQLite 复制绑定的字符串
+const SQLITE_TRANSIENT: isize = -1;
+
+/// 其他连接持有写锁时的等待时间 (毫秒)
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut c_void,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut c_void) -> c_int;
+    fn sqlite3_busy_timeout(db: *mut c_void, ms: c_int) -> c_int;
+    fn sqlite3_errmsg(db: *mut c_void) -> *const c_char;
+    fn sqlite3_prepare_v2(
+        db: *mut c_void,
+        sql: *const c_char,
+        len: c_int,
+        stmt: *mut *mut c_void,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut c_void,
+        index: c_int,
+        text: *const c_char,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_step(stmt: *mut c_void) -> c_int;
+    fn sqlite3_column_count(stmt: *mut c_void) -> c_int;
+    fn sqlite3_column_text(stmt: *mut c_void, col: c_int) -> *const c_char;
+    fn sqlite3_finalize(stmt: *mut c_void) -> c_int;
+}
+
+struct Handle(*mut c_void);
+
+// SAFETY: 连接只在 Database 的互斥锁内使用，不会被多个线程同时访问
+unsafe impl Send for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: 句柄由 sqlite3_open_v2 打开，语句均已 finalize
+        unsafe {
+            sqlite3_close(self.0);
+        }
+    }
+}
+
+/// SQLite 数据库连接 (通过系统的 libsqlite3)
+pub struct Database {
+    handle: Mutex<Handle>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let filename = CString::new(path.to_string_lossy().as_bytes())?;
+        let mut db = std::ptr::null_mut();
+        // SAFETY: filename 以 NUL 结尾，db 为输出参数；失败时仍需关闭返回的句柄
+        let rc = unsafe {
+            sqlite3_open_v2(
+                filename.as_ptr(),
+                &mut db,
+                SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE,
+                std::ptr::null(),
+            )
+        };
+        let handle = Handle(db);
+        if rc != SQLITE_OK {
+            anyhow::bail!("Failed to open {:?}: {}", path, error_message(&handle));
+        }
+        // SAFETY: 句柄有效
+        unsafe {
+            sqlite3_busy_timeout(handle.0, BUSY_TIMEOUT_MS);
+        }
+        Ok(Self {
+            handle: Mutex::new(handle),
+        })
+    }
+
+    /// 执行语句，返回结果行 (各列按文本读取)
+    pub fn query(&self, sql: &str, params: &[&str]) -> anyhow::Result<Vec<Vec<String>>> {
+        let handle = self.handle.lock_or_recover();
+        run(&handle, sql, params)
+    }
+
+    /// 在一个事务中依次执行多条语句，任一失败时回滚
+    pub fn transaction(&self, statements: &[(String, Vec<String>)]) -> anyhow::Result<()> {
+        let handle = self.handle.lock_or_recover();
+        run(&handle, "BEGIN IMMEDIATE", &[])?;
+        for (sql, params) in statements {
+            let params: Vec<&str> = params.iter().map(String::as_str).collect();
+            if let Err(e) = run(&handle, sql, &params) {
+                let _ = run(&handle, "ROLLBACK", &[]);
+                return Err(e);
+            }
+        }
+        if let Err(e) = run(&handle, "COMMIT", &[]) {
+            let _ = run(&handle, "ROLLBACK", &[]);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+fn error_message(handle: &Handle) -> String {
+    // SAFETY: sqlite3_errmsg 返回由连接持有的 NUL 结尾字符串
+    unsafe { CStr::from_ptr(sqlite3_errmsg(handle.0)) }
+        .to_string_lossy()
+        .to_string()
+}
+
+fn run(handle: &Handle, sql: &str, params: &[&str]) -> anyhow::Result<Vec<Vec<String>>> {
+    let sql_c = CString::new(sql)?;
+    let params = params
+        .iter()
+        .map(|p| CString::new(*p))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = std::ptr::null_mut();
+    // SAFETY: 句柄有效，sql 以 NUL 结尾 (长度 -1 表示读到 NUL)
+    let rc = unsafe {
+        sqlite3_prepare_v2(
+            handle.0,
+            sql_c.as_ptr(),
+            -1,
+            &mut stmt,
+            std::ptr::null_mut(),
+        )
+    };
+    if rc != SQLITE_OK {
+        anyhow::bail!("SQLite: {} ({})", error_message(handle), sql);
+    }
+
+    let mut rows = Vec::new();
+    let result = (|| {
+        for (i, param) in params.iter().enumerate() {
+            // SAFETY: stmt 有效，SQLITE_TRANSIENT 让 SQLite 复制字符串
+            let rc = unsafe {
+                sqlite3_bind_text(stmt, i as c_int + 1, param.as_ptr(), -1, SQLITE_TRANSIENT)
+            };
+            if rc != SQLITE_OK {
+                anyhow::bail!("SQLite: {} ({})", error_message(handle), sql);
+            }
+        }
+        loop {
+            // SAFETY: stmt 有效
+            match unsafe { sqlite3_step(stmt) } {
+                SQLITE_ROW => {
+                    let mut row = Vec::new();
+                    // SAFETY: stmt 有效且指向一行结果
+                    let columns = unsafe { sqlite3_column_count(stmt) };
+                    for col in 0..columns {
+                        // SAFETY: 列序号在范围内，NULL 值返回空指针
+                        let text = unsafe { sqlite3_column_text(stmt, col) };
+                        if text.is_null() {
+                            row.push(String::new());
+                            continue;
+                        }
+                        // SAFETY: 返回的文本以 NUL 结尾，在下一次 step 之前有效
+                        row.push(
+                            unsafe { CStr::from_ptr(text) }
+                                .to_string_lossy()
+                                .to_string(),
+                        );
+                    }
+                    rows.push(row);
+                }
+                SQLITE_DONE => return Ok(()),
+                _ => anyhow::bail!("SQLite: {} ({})", error_message(handle), sql),
+            }
+        }
+    })();
+    // SAFETY: stmt 由 prepare 创建，只释放一次
+    unsafe {
+        sqlite3_finalize(stmt);
+    }
+    result.map(|_| rows)
+}
+
+/// 以 SQLite 表持久化的状态表 (每行为流名称与 JSON 记录)
+///
+/// 读取使用内存中的副本，写入先落盘成功再更新副本
+pub struct SqliteStore<V> {
+    db: Arc<Database>,
+    table: String,
+    cache: MemoryStore<V>,
+}
+
+impl<V: Clone + Send + Serialize + DeserializeOwned> SqliteStore<V> {
+    /// 打开 (必要时创建) 表并读入全部记录，无法解析的记录被跳过
+    pub fn open(db: Arc<Database>, table: &str) -> anyhow::Result<Self> {
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("Invalid table name {:?}", table);
+        }
+        db.query(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (name TEXT PRIMARY KEY, value TEXT NOT NULL)",
+                table
+            ),
+            &[],
+        )?;
+        let cache = MemoryStore::default();
+        for row in db.query(&format!("SELECT name, value FROM {}", table), &[])? {
+            let [name, value] = &row[..] else {
+                continue;
+            };
+            match serde_json::from_str(value) {
+                Ok(value) => {
+                    cache.insert(name, value)?;
+                }
+                Err(e) => warn!("Ignoring invalid record [{}] in {}: {}", name, table, e),
+            }
+        }
+        Ok(Self {
+            db,
+            table: table.to_string(),
+            cache,
+        })
+    }
+
+    fn upsert(&self, name: &str, value: &V) -> (String, Vec<String>) {
+        (
+            format!(
+                "INSERT OR REPLACE INTO {} (name, value) VALUES (?1, ?2)",
+                self.table
+            ),
+            vec![
+                name.to_string(),
+                serde_json::to_string(value).unwrap_or_default(),
+            ],
+        )
+    }
+}
+
+impl<V: Clone + Send + Serialize + DeserializeOwned> StreamStore<V> for SqliteStore<V> {
+    fn get(&self, name: &str) -> Option<V> {
+        self.cache.get(name)
+    }
+
+    fn insert(&self, name: &str, value: V) -> anyhow::Result<Option<V>> {
+        self.db.transaction(&[self.upsert(name, &value)])?;
+        self.cache.insert(name, value)
+    }
+
+    fn remove(&self, name: &str) -> anyhow::Result<Option<V>> {
+        self.db.query(
+            &format!("DELETE FROM {} WHERE name = ?1", self.table),
+            &[name],
+        )?;
+        self.cache.remove(name)
+    }
+
+    fn entries(&self) -> Vec<(String, V)> {
+        self.cache.entries()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.cache.contains(name)
+    }
+
+    fn replace_all(&self, entries: Vec<(String, V)>) -> anyhow::Result<()> {
+        let mut statements = vec![(format!("DELETE FROM {}", self.table), Vec::new())];
+        statements.extend(entries.iter().map(|(name, value)| self.upsert(name, value)));
+        self.db.transaction(&statements)?;
+        self.cache.replace_all(entries)
+    }
+}
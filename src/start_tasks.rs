@@ -0,0 +1,140 @@
+use crate::clock;
+use crate::engine::Engine;
+use crate::segment_index;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// 等待首个播放列表的最长时间，慢速源 (卫星、RTSP) 需要 10–20 秒
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 检查启动进度的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 异步启动任务的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    /// 正在启动，尚未写出播放列表
+    Starting,
+    /// 已写出播放列表，可以播放
+    Healthy,
+    /// 启动被拒绝、进程在就绪前退出或等待超时
+    Failed,
+}
+
+/// 一次异步启动 (每个流只保留最近一次)
+#[derive(Debug, Clone, Serialize)]
+pub struct StartTask {
+    pub id: String,
+    pub state: TaskState,
+    /// 请求时间 (RFC 3339)
+    pub requested_at: String,
+    /// 进入 healthy 或 failed 的时间 (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// 失败时的错误码 (同 `VtxError::code`，另有 `exited` 与 `startup_timeout`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// 流最近一次异步启动的状态
+pub fn get(state: &AppState, name: &str) -> Option<StartTask> {
+    state.start_tasks.lock_or_recover().get(name).cloned()
+}
+
+/// 在后台启动流并跟踪其进入 healthy 或 failed，立即返回新任务
+///
+/// 同一个流已有进行中的任务时返回该任务，不会重复启动
+pub fn spawn(state: &Arc<AppState>, name: &str) -> StartTask {
+    let task = {
+        let mut tasks = state.start_tasks.lock_or_recover();
+        if let Some(task) = tasks.get(name).filter(|t| t.state == TaskState::Starting) {
+            return task.clone();
+        }
+        let task = StartTask {
+            id: format!("start-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            state: TaskState::Starting,
+            requested_at: clock::rfc3339(SystemTime::now()),
+            finished_at: None,
+            error: None,
+            message: None,
+        };
+        tasks.insert(name.to_string(), task.clone());
+        task
+    };
+    tokio::spawn(run(state.clone(), name.to_string(), task.id.clone()));
+    task
+}
+
+async fn run(state: Arc<AppState>, name: String, id: String) {
+    let outcome = match Engine::start_stream(&state, &name).await {
+        Ok(_) => wait_ready(&state, &name).await,
+        Err(e) => Err((e.code().to_string(), e.to_string())),
+    };
+    match &outcome {
+        Ok(()) => info!("Stream [{}] is healthy (task {})", name, id),
+        Err((_, message)) => warn!("Start task {} for [{}] failed: {}", id, name, message),
+    }
+
+    let mut tasks = state.start_tasks.lock_or_recover();
+    // 任务可能已被同一个流的新任务取代
+    let Some(task) = tasks.get_mut(&name).filter(|t| t.id == id) else {
+        return;
+    };
+    task.finished_at = Some(clock::rfc3339(SystemTime::now()));
+    match outcome {
+        Ok(()) => task.state = TaskState::Healthy,
+        Err((code, message)) => {
+            task.state = TaskState::Failed;
+            task.error = Some(code);
+            task.message = Some(message);
+        }
+    }
+}
+
+/// 等待流写出播放列表；进程在此之前退出或超时视为失败 (超时时进程保持运行)
+async fn wait_ready(state: &AppState, name: &str) -> Result<(), (String, String)> {
+    let config = state.config();
+    let Some(cfg) = config.stream(name) else {
+        return Err(("config_not_found".to_string(), "Stream removed".to_string()));
+    };
+    let index = segment_index::get(state, name, &Engine::output_dir(state, cfg));
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+        if !state.active_streams.lock_or_recover().contains_key(name) {
+            let reason = state
+                .recovery_states
+                .lock_or_recover()
+                .get(name)
+                .and_then(|r| r.exits.back().and_then(|e| e.reason.clone()));
+            let message = match reason {
+                Some(reason) => format!("Stream [{}] exited during startup: {}", name, reason),
+                None => format!("Stream [{}] exited during startup", name),
+            };
+            return Err(("exited".to_string(), message));
+        }
+        if index.exists("index.m3u8") {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err((
+                "startup_timeout".to_string(),
+                format!(
+                    "Stream [{}] wrote no playlist within {}s",
+                    name,
+                    READY_TIMEOUT.as_secs()
+                ),
+            ));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -1,34 +1,427 @@
+use crate::alerts::{AlertAck, FiringAlert};
+use crate::analytics::Analytics;
+use crate::av_sync::{SyncStats, SyncTracker};
+use crate::availability;
+use crate::bandwidth::{EgressMeter, RateLimiter};
 use crate::config::AppConfig;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use crate::counters::{self, LifetimeCounters};
+use crate::credentials;
+use crate::drain::DrainState;
+use crate::failure::{self, ExitRecord, StderrTail};
+use crate::forward_auth::AuthCache;
+use crate::geoip::LoadedDb;
+use crate::gpu::GpuDevice;
+use crate::jwt::KeyCache;
+use crate::keys::{self, StreamKeyring};
+use crate::latency::LatencyStats;
+use crate::loadshed;
+use crate::loadshed::ShedState;
+use crate::maintenance::{DisabledStream, Mute};
+use crate::markers::CueMarker;
+use crate::metrics::StartupMetrics;
+use crate::motion::MotionEvent;
+use crate::notify::NotifyHistory;
+use crate::ntp::ClockCheck;
+use crate::origin_push::PushStats;
+use crate::platform::ProcessGroup;
+use crate::proxy::ProxySession;
+use crate::quality::{QualityStats, QualityTracker};
+use crate::rtsp::RtspPublication;
+use crate::runtime_state::{self, Intent};
+use crate::segment_index::{self, DirIndex, SegmentCache};
+use crate::sessions::{self, Presence, ViewerSessions};
+use crate::start_tasks::StartTask;
+use crate::store::{MemoryStore, StreamStore};
+use crate::supervisor::SupervisorHealth;
+use crate::system::{CpuSample, ProcessUsage};
+use crate::tokens::{self, TokenStore};
+use crate::transfer::TransferStats;
+use crate::watermark::WatermarkCode;
+use axum::body::Bytes;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 use tokio::process::Child;
+use tokio::sync::broadcast;
 
 /// 运行时的流实例状态
 pub struct StreamRuntime {
     /// FFmpeg 子进程句柄
     pub process: Child,
-    /// 最后一次活跃时间 (用于空闲回收)
+    /// FFmpeg 所在的进程组 / 作业对象，随运行状态一同释放时结束剩余的辅助进程
+    pub group: Option<ProcessGroup>,
+    /// 最后一次有观看会话活跃的时间 (用于空闲回收)
     pub last_accessed: Instant,
     /// 进程启动时间 (用于计算运行时长)
     pub started_at: Instant,
+    /// 进入热备状态的时间 (空闲后保留进程，等待下一个观看者)
+    pub standby_since: Option<Instant>,
+    /// 收到启动请求的时间 (用于统计冷启动耗时)
+    pub requested_at: Instant,
+    /// 首个切片写出的耗时
+    pub first_segment: Option<Duration>,
+    /// 首次返回播放列表的耗时
+    pub first_playlist: Option<Duration>,
+    /// FFmpeg stderr 的末尾若干行 (用于判断退出原因)
+    pub stderr_tail: StderrTail,
+    /// 被日志匹配规则标记为降级的截止时间
+    pub degraded_until: Option<Instant>,
+    /// 最近一次采集的进程资源占用 (由 Supervisor 定期刷新)
+    pub usage: Option<ProcessUsage>,
+    /// 上一次的 CPU 时间采样 (用于计算占用率)
+    pub cpu_sample: Option<CpuSample>,
+    /// 音画同步与时间戳跳变统计
+    pub sync: SyncTracker,
+    /// HLS 输出质量统计
+    pub quality: QualityTracker,
+    /// 端到端延迟估算
+    pub latency: LatencyStats,
 }
 
 /// 故障恢复状态
+#[derive(Default)]
 pub struct StreamRecoveryState {
     /// 连续崩溃次数
     pub crash_count: u32,
     /// 下次允许尝试重启的最早时间点
     pub next_retry_at: Option<Instant>,
+    /// 因无法恢复的错误被隔离的原因，隔离期间不再自动或按需启动
+    pub quarantined: Option<String>,
+    /// 当前使用的源序号 (0 为主源，见 `StreamConfig::source_at`)
+    pub source_index: usize,
+    /// 崩溃检测窗口内的崩溃时间点 (由旧到新)
+    pub recent_crashes: VecDeque<Instant>,
+    /// 最近的进程退出记录 (由旧到新，最多 `failure::EXIT_HISTORY` 条)
+    pub exits: VecDeque<ExitRecord>,
+    /// 最近一次成功启动进程的时间点
+    pub last_start: Option<Instant>,
+}
+
+impl StreamRecoveryState {
+    /// 距离 `min_start_interval_sec` 允许的下一次启动还需等待的时间
+    pub fn start_wait(&self, min_interval_sec: u64) -> Option<Duration> {
+        let allowed_at = self.last_start? + Duration::from_secs(min_interval_sec);
+        allowed_at
+            .checked_duration_since(Instant::now())
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// 记录一次进程退出，超出保留条数时丢弃最早的记录
+    pub fn record_exit(&mut self, record: ExitRecord) {
+        if self.exits.len() == failure::EXIT_HISTORY {
+            self.exits.pop_front();
+        }
+        self.exits.push_back(record);
+    }
 }
 
 /// 全局应用上下文
 pub struct AppState {
-    pub config: AppConfig,
+    /// 当前生效的配置，可在运行时被整体替换 (如控制器下发)
+    pub config: RwLock<Arc<AppConfig>>,
+    /// 配置文件路径 (配置导入时写回)
+    pub config_path: PathBuf,
     /// 活跃流表 (Stream Name -> Runtime)
     pub active_streams: Mutex<HashMap<String, StreamRuntime>>,
     /// 恢复状态表 (Stream Name -> Recovery State)
     pub recovery_states: Mutex<HashMap<String, StreamRecoveryState>>,
+    /// 加密密钥环 (Stream Name -> Keyring)
+    pub stream_keys: Mutex<HashMap<String, StreamKeyring>>,
+    /// 租户带宽限速器 (Tenant Name -> Limiter)，仅包含配置了带宽配额的租户
+    pub tenant_limiters: HashMap<String, Arc<RateLimiter>>,
+    /// 流带宽限速器 (Stream Name -> (Kbps, Limiter))，按需创建，配置的速率变化时重建
+    pub stream_limiters: Mutex<HashMap<String, (u64, Arc<RateLimiter>)>>,
+    /// 网关总带宽限速器 (server.max_egress_mbps)
+    pub egress_limiter: Option<Arc<RateLimiter>>,
+    /// 流出口流量计 (Stream Name -> Meter)，首次下发时创建
+    pub egress_meters: Mutex<HashMap<String, Arc<EgressMeter>>>,
+    /// 跨重启保留的累计计数 (不含运行中进程与本次运行的流量，见 counters::snapshot)
+    pub lifetime_counters: Mutex<HashMap<String, LifetimeCounters>>,
+    /// 签发的令牌、吊销记录与令牌使用统计
+    pub tokens: Mutex<TokenStore>,
+    /// 运行中流的输出目录索引 (Stream Name -> Index)
+    pub segment_indexes: Mutex<HashMap<String, Arc<DirIndex>>>,
+    /// 所有输出目录共享的切片缓存
+    pub segment_cache: Arc<SegmentCache>,
+    /// 代理流会话 (Stream Name -> Session)
+    pub proxy_sessions: Mutex<HashMap<String, ProxySession>>,
+    /// RTSP 发布 (Stream Name -> Publication)
+    pub rtsp_publications: Mutex<HashMap<String, RtspPublication>>,
+    /// MPEG-TS 输出 (Stream Name -> Broadcaster)
+    pub ts_feeds: Mutex<HashMap<String, broadcast::Sender<Bytes>>>,
+    /// 观看会话分析的进行中会话与待导出记录
+    pub analytics: Mutex<Analytics>,
+    /// 共享源的本地回环端口 (拉取源的流 -> 127.0.0.1 上的端口)，首次使用时监听
+    pub share_ports: Mutex<HashMap<String, u16>>,
+    /// 观看会话 (Stream Name -> Sessions)
+    pub viewer_sessions: Mutex<HashMap<String, ViewerSessions>>,
+    /// 已通知的观看状态 (Stream Name -> Presence)
+    pub viewer_presence: Mutex<HashMap<String, Presence>>,
+    /// 进行中的运动事件 (Stream Name -> Event)
+    pub motion_events: Mutex<HashMap<String, MotionEvent>>,
+    /// 冷启动耗时统计 (Stream Name -> Metrics)，跨重启累积
+    pub startup_metrics: Mutex<HashMap<String, StartupMetrics>>,
+    /// 待插入播放列表的广告标记 (Stream Name -> Markers)
+    pub cue_markers: Mutex<HashMap<String, Vec<CueMarker>>>,
+    /// 最近一次系统时钟核对结果 (server.ntp_server)
+    pub clock_check: Mutex<Option<ClockCheck>>,
+    /// 生成水印码的进程级随机密钥 (水印码不可由观看者自行推算)
+    pub watermark_secret: [u8; 16],
+    /// 已分配的水印码 (Code -> Assignment)
+    pub watermark_codes: Mutex<HashMap<String, WatermarkCode>>,
+    /// 最近一次采集的 GPU/VPU 状态
+    pub gpu_devices: Mutex<Vec<GpuDevice>>,
+    /// 切片传输计数 (完成 / 断开 / 停滞)
+    pub transfers: Arc<TransferStats>,
+    /// 维护排空状态 (None 表示正常服务)
+    pub drain: Mutex<Option<DrainState>>,
+    /// 运维禁用的流 (Name -> 禁用记录)
+    pub disabled_streams: Box<dyn StreamStore<DisabledStream>>,
+    /// 运维人员的手动启动 / 停止记录，持久化于 server.state_root
+    pub stream_intents: Box<dyn StreamStore<Intent>>,
+    /// 告警通知静默 (Name -> 静默记录)，持久化于 server.state_root
+    pub stream_mutes: Box<dyn StreamStore<Mute>>,
+    /// Supervisor 的最近一轮检查时间与重启记录
+    pub supervisor_health: Mutex<SupervisorHealth>,
+    /// 已触发的本地告警
+    pub alerts: Mutex<Vec<FiringAlert>>,
+    /// 已确认的告警 (Alert ID -> 确认记录)，告警解除时清除
+    pub alert_acks: Mutex<HashMap<String, AlertAck>>,
+    /// 配置迁移后流实际写入的输出目录 (Stream Name -> Dir)，仅在与配置的目录不同时记录
+    pub output_dirs: Mutex<HashMap<String, PathBuf>>,
+    /// 源站推送统计 (Stream Name -> Stats)
+    pub origin_pushes: Mutex<HashMap<String, PushStats>>,
+    /// 负载卸载状态 (被卸载的流等)
+    pub load_shedding: Mutex<ShedState>,
+    /// 循环文件流启动时所处的节目时段 (Stream Name -> Slot，None 为垫片)
+    pub playout_slots: Mutex<HashMap<String, Option<usize>>>,
+    /// 最近一次异步启动 (Stream Name -> Task)
+    pub start_tasks: Mutex<HashMap<String, StartTask>>,
+    /// 流的启动锁 (Stream Name -> Lock)，同一流的启动检查与登记串行执行
+    pub start_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// 配置修改锁：读取当前配置、修改、写回配置文件并替换的整个过程持有，
+    /// 避免并发修改基于同一份旧配置而互相覆盖，或配置文件与内存中的配置不一致
+    pub config_lock: tokio::sync::Mutex<()>,
+    /// 告警通知的去重与限流记录
+    pub notifications: Mutex<NotifyHistory>,
+    /// 外部鉴权的决定缓存
+    pub forward_auth_cache: Mutex<AuthCache>,
+    /// 从 JWKS 获取的 JWT 公钥
+    pub jwks: Mutex<KeyCache>,
+    /// GeoIP 数据库 (首次使用时按 server.geoip_db 加载)
+    pub geoip: Mutex<Option<LoadedDb>>,
+    /// 进程启动时间 (就绪检查的宽限期由此起算)
+    pub started_at: Instant,
+}
+
+/// 流状态快照 (供管理 API 与控制器心跳使用)
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStatus {
+    pub name: String,
+    /// 稳定的外部标识
+    pub id: Option<String>,
+    pub aliases: Vec<String>,
+    pub tenant: Option<String>,
+    pub source: String,
+    pub status: &'static str,
+    pub idle_seconds: u64,
+    pub uptime_seconds: u64,
+    pub config_idle_timeout: u64,
+    pub crash_count: u32,
+    /// 最近一次进程退出
+    pub last_exit: Option<ExitRecord>,
+    /// 当前在线的观看者数量
+    pub viewers: usize,
+    /// FFmpeg 进程的资源占用 (仅本地进程运行时)
+    pub process: Option<ProcessUsage>,
+    /// 音画同步统计 (仅本地进程运行时)
+    pub sync: Option<SyncStats>,
+    /// HLS 输出质量 (仅配置了 quality_watch 且本地进程运行时)
+    pub quality: Option<QualityStats>,
+    /// 端到端延迟估算 (仅本地进程运行时)
+    pub latency: Option<LatencyStats>,
+}
+
+impl AppState {
+    /// 按配置创建状态，并恢复上次运行保存的禁用、手动操作与故障恢复状态 (见 runtime_state)；
+    /// 同时裁剪可用性事件日志 (见 availability)
+    /// 系统随机源不可用 (无法生成水印密钥) 时返回错误
+    pub fn new(config: AppConfig, config_path: PathBuf) -> anyhow::Result<Self> {
+        availability::prune(&config);
+
+        // 为配置了带宽配额的租户创建限速器
+        let tenant_limiters = config
+            .tenants
+            .iter()
+            .filter_map(|t| {
+                RateLimiter::from_kbps(t.quota.max_bandwidth_kbps).map(|l| (t.name.clone(), l))
+            })
+            .collect();
+
+        // 网关总带宽上限
+        let egress_limiter =
+            RateLimiter::from_kbps((config.server.max_egress_mbps * 1000.0) as u64);
+
+        let mut watermark_secret = [0u8; 16];
+        keys::random_bytes(&mut watermark_secret)?;
+
+        let saved = runtime_state::load(&config);
+        let lifetime_counters = counters::load(&config);
+        let tokens = tokens::load(&config);
+        Ok(Self {
+            config: RwLock::new(Arc::new(config)),
+            config_path,
+            active_streams: Mutex::new(HashMap::new()),
+            recovery_states: Mutex::new(saved.recovery_states()),
+            stream_keys: Mutex::new(HashMap::new()),
+            tenant_limiters,
+            stream_limiters: Mutex::new(HashMap::new()),
+            egress_limiter,
+            egress_meters: Mutex::new(HashMap::new()),
+            lifetime_counters: Mutex::new(lifetime_counters),
+            tokens: Mutex::new(tokens),
+            segment_indexes: Mutex::new(HashMap::new()),
+            segment_cache: Arc::new(SegmentCache::new(segment_index::SEGMENT_CACHE_BYTES)),
+            proxy_sessions: Mutex::new(HashMap::new()),
+            rtsp_publications: Mutex::new(HashMap::new()),
+            ts_feeds: Mutex::new(HashMap::new()),
+            share_ports: Mutex::new(HashMap::new()),
+            analytics: Mutex::new(Analytics::default()),
+            viewer_sessions: Mutex::new(HashMap::new()),
+            viewer_presence: Mutex::new(HashMap::new()),
+            motion_events: Mutex::new(HashMap::new()),
+            startup_metrics: Mutex::new(HashMap::new()),
+            cue_markers: Mutex::new(HashMap::new()),
+            clock_check: Mutex::new(None),
+            watermark_secret,
+            watermark_codes: Mutex::new(HashMap::new()),
+            gpu_devices: Mutex::new(Vec::new()),
+            transfers: Arc::new(TransferStats::default()),
+            drain: Mutex::new(None),
+            disabled_streams: Box::new(MemoryStore::from(saved.disabled)),
+            stream_intents: Box::new(MemoryStore::from(saved.intents)),
+            stream_mutes: Box::new(MemoryStore::from(saved.mutes)),
+            supervisor_health: Mutex::new(SupervisorHealth::default()),
+            alerts: Mutex::new(Vec::new()),
+            alert_acks: Mutex::new(HashMap::new()),
+            output_dirs: Mutex::new(HashMap::new()),
+            origin_pushes: Mutex::new(HashMap::new()),
+            load_shedding: Mutex::new(ShedState::default()),
+            playout_slots: Mutex::new(HashMap::new()),
+            start_tasks: Mutex::new(HashMap::new()),
+            start_locks: Mutex::new(HashMap::new()),
+            config_lock: tokio::sync::Mutex::new(()),
+            notifications: Mutex::new(NotifyHistory::default()),
+            forward_auth_cache: Mutex::new(AuthCache::default()),
+            jwks: Mutex::new(KeyCache::default()),
+            geoip: Mutex::new(None),
+            started_at: Instant::now(),
+        })
+    }
+
+    /// 获取当前配置快照
+    pub fn config(&self) -> Arc<AppConfig> {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 生成所有已配置流的状态快照，包括运行时长和闲置时间
+    pub fn stream_statuses(&self) -> Vec<StreamStatus> {
+        let config = self.config();
+        let streams_map = self.active_streams.lock_or_recover();
+        let recovery_map = self.recovery_states.lock_or_recover();
+        let proxy_map = self.proxy_sessions.lock_or_recover();
+        let now = Instant::now();
+
+        config
+            .streams
+            .iter()
+            .map(|cfg| {
+                // 获取流的状态、闲置时间和运行时长
+                let quarantined = recovery_map
+                    .get(&cfg.name)
+                    .is_some_and(|r| r.quarantined.is_some());
+                let (status, idle, uptime) = if let Some(running) = streams_map.get(&cfg.name) {
+                    let idle_sec = now.duration_since(running.last_accessed).as_secs();
+                    let uptime_sec = now.duration_since(running.started_at).as_secs();
+                    let status = if running.standby_since.is_some() {
+                        "standby"
+                    } else if running.degraded_until.is_some_and(|t| t > now) {
+                        "degraded"
+                    } else {
+                        "running"
+                    };
+                    (status, idle_sec, uptime_sec)
+                } else if let Some(session) = proxy_map.get(&cfg.name) {
+                    let idle_sec = now.duration_since(session.last_accessed).as_secs();
+                    let uptime_sec = now.duration_since(session.started_at).as_secs();
+                    ("proxy", idle_sec, uptime_sec)
+                } else if self.disabled_streams.contains(&cfg.name) {
+                    ("disabled", 0, 0)
+                } else if cfg.is_proxied() {
+                    ("proxy", 0, 0)
+                } else if quarantined {
+                    ("quarantined", 0, 0)
+                } else if loadshed::is_shed(self, &cfg.name) {
+                    ("shed", 0, 0)
+                } else {
+                    ("stopped", 0, 0)
+                };
+
+                // 获取流的崩溃次数（如果有）
+                let crash_count = recovery_map
+                    .get(&cfg.name)
+                    .map(|r| r.crash_count)
+                    .unwrap_or(0);
+
+                StreamStatus {
+                    name: cfg.name.clone(),
+                    id: cfg.id.clone(),
+                    aliases: cfg.aliases.clone(),
+                    tenant: cfg.tenant.clone(),
+                    source: credentials::mask_urls(&cfg.source),
+                    status,
+                    idle_seconds: idle,
+                    uptime_seconds: uptime,
+                    config_idle_timeout: cfg.idle_timeout,
+                    crash_count,
+                    last_exit: recovery_map
+                        .get(&cfg.name)
+                        .and_then(|r| r.exits.back().cloned()),
+                    viewers: sessions::active_count(self, &cfg.name),
+                    process: streams_map.get(&cfg.name).and_then(|r| r.usage),
+                    sync: streams_map.get(&cfg.name).map(|r| r.sync.stats),
+                    quality: streams_map
+                        .get(&cfg.name)
+                        .filter(|_| cfg.quality_watch.is_some())
+                        .map(|r| r.quality.stats.clone()),
+                    latency: streams_map.get(&cfg.name).map(|r| r.latency),
+                }
+            })
+            .collect()
+    }
+
+    /// 替换当前配置
+    pub fn replace_config(&self, config: AppConfig) {
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+    }
 }
 
 pub type SharedState = Arc<AppState>;
+
+/// 获取互斥锁，锁中毒 (持锁的任务 panic) 时继续使用其中的数据
+///
+/// 状态表在每次修改后都保持一致，单个处理函数 panic 不应让之后的所有访问者 (包括 Supervisor) 一并失败
+pub trait LockExt<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
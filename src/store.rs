@@ -0,0 +1,88 @@
+use crate::state::LockExt;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// 按流名称存取的状态表
+///
+/// 读取返回副本、遍历返回快照，调用方不会持有锁，可以安全地跨越 `.await`。
+/// 实现可以是纯内存 (`MemoryStore`)，也可以写穿到持久化存储 (`sqlite` 特性的 `SqliteStore`)，
+/// 测试时也可替换为自定义的实现；写入持久化存储可能失败，因此写操作返回 Result
+pub trait StreamStore<V: Clone>: Send + Sync {
+    fn get(&self, name: &str) -> Option<V>;
+
+    /// 写入记录，返回被替换的旧记录
+    fn insert(&self, name: &str, value: V) -> anyhow::Result<Option<V>>;
+
+    /// 删除记录，返回被删除的记录
+    fn remove(&self, name: &str) -> anyhow::Result<Option<V>>;
+
+    /// 全部记录的快照
+    fn entries(&self) -> Vec<(String, V)>;
+
+    fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// 以给定内容替换全部记录
+    fn replace_all(&self, entries: Vec<(String, V)>) -> anyhow::Result<()> {
+        for (name, _) in self.entries() {
+            self.remove(&name)?;
+        }
+        for (name, value) in entries {
+            self.insert(&name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// 内存中的状态表，写操作不会失败
+pub struct MemoryStore<V> {
+    map: Mutex<HashMap<String, V>>,
+}
+
+impl<V> Default for MemoryStore<V> {
+    fn default() -> Self {
+        Self {
+            map: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<V> From<HashMap<String, V>> for MemoryStore<V> {
+    fn from(map: HashMap<String, V>) -> Self {
+        Self {
+            map: Mutex::new(map),
+        }
+    }
+}
+
+impl<V: Clone + Send> StreamStore<V> for MemoryStore<V> {
+    fn get(&self, name: &str) -> Option<V> {
+        self.map.lock_or_recover().get(name).cloned()
+    }
+
+    fn insert(&self, name: &str, value: V) -> anyhow::Result<Option<V>> {
+        Ok(self.map.lock_or_recover().insert(name.to_string(), value))
+    }
+
+    fn remove(&self, name: &str) -> anyhow::Result<Option<V>> {
+        Ok(self.map.lock_or_recover().remove(name))
+    }
+
+    fn entries(&self) -> Vec<(String, V)> {
+        self.map
+            .lock_or_recover()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.map.lock_or_recover().contains_key(name)
+    }
+
+    fn replace_all(&self, entries: Vec<(String, V)>) -> anyhow::Result<()> {
+        *self.map.lock_or_recover() = entries.into_iter().collect();
+        Ok(())
+    }
+}
@@ -1,53 +1,234 @@
+use crate::analytics;
+use crate::availability::{self, Transition};
+use crate::clock;
+use crate::config::IdleAction;
+use crate::counters;
+use crate::dependency;
 use crate::engine::Engine;
-use crate::state::{AppState, StreamRecoveryState};
+use crate::failure::{self, ExitRecord, FailureKind};
+use crate::loadshed;
+use crate::maintenance;
+use crate::motion;
+use crate::runtime_state;
+use crate::segment_index;
+use crate::sessions;
+use crate::state::{AppState, LockExt};
+use crate::system::ProcessUsage;
+use crate::tenant;
+use crate::timelapse;
+use serde::Serialize;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{error, info, warn};
 
+/// 进程连续运行超过该时长后视为恢复正常，重置崩溃计数
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// 延时截帧的清理间隔
+const TIMELAPSE_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Supervisor panic 后重新拉起前的等待
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// 判定 Supervisor 停止检查的最短时间
+const STALL_MIN: Duration = Duration::from_secs(10);
+
+/// Supervisor 自身的运行状态 (供 /healthz 使用)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SupervisorHealth {
+    /// 最近一轮检查的时间
+    #[serde(serialize_with = "serialize_time")]
+    pub last_tick: Option<SystemTime>,
+    /// 因 panic 被重新拉起的次数
+    pub restarts: u32,
+    /// 最近一次 panic 的信息
+    pub last_panic: Option<String>,
+}
+
+impl SupervisorHealth {
+    /// 距最近一轮检查的时间 (尚未检查过时为 None)
+    pub fn age(&self) -> Option<Duration> {
+        self.last_tick
+            .map(|t| SystemTime::now().duration_since(t).unwrap_or_default())
+    }
+
+    /// 是否已停止检查：超过三个检查间隔 (至少 10 秒) 没有新一轮检查
+    pub fn is_stalled(&self, interval_ms: u64) -> bool {
+        let limit = Duration::from_millis(interval_ms * 3).max(STALL_MIN);
+        self.age().is_none_or(|age| age > limit)
+    }
+}
+
+fn serialize_time<S: serde::Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(t) => serializer.serialize_some(&clock::rfc3339_millis(*t)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// 看门狗：在独立任务中运行 Supervisor，任务 panic 时记录原因并重新拉起
+///
+/// 锁中毒会被恢复 (见 `LockExt`)，重新拉起的 Supervisor 可以继续使用原有状态
+pub async fn start_watchdog(state: Arc<AppState>, interval_ms: u64) {
+    loop {
+        let task = tokio::spawn(start_supervisor(state.clone(), interval_ms));
+        let err = match task.await {
+            Ok(()) => return,
+            Err(e) if e.is_panic() => e,
+            Err(e) => {
+                error!("Supervisor task ended: {}", e);
+                return;
+            }
+        };
+        let payload = err.into_panic();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        error!("Supervisor panicked: {}. Restarting.", message);
+        {
+            let mut health = state.supervisor_health.lock_or_recover();
+            health.restarts += 1;
+            health.last_panic = Some(message);
+        }
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
 /// 启动后台监控任务，定期检查流的状态并进行故障恢复和重启
 ///
 /// # 任务流程：
 /// - 每隔指定的时间间隔检查一次流的状态
 /// - 检查流是否正常运行，如果流意外退出，记录并尝试重启
-/// - 如果流超时空闲，则安排停止
+/// - 如果流在最后一个观看会话结束后超时空闲 (且未配置 keep_warm 或 supervise.idle_stop: false)，则安排停止
 /// - 在流崩溃后根据配置进行回退和重试
 /// - 如果流自动重启配置为启用，尝试重启失败的流
 pub async fn start_supervisor(state: Arc<AppState>, interval_ms: u64) {
     let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+    let mut last_prune: Option<Instant> = None;
 
     loop {
         interval.tick().await; // 等待指定的时间间隔
+        state.supervisor_health.lock_or_recover().last_tick = Some(SystemTime::now());
         let now = Instant::now();
+        let config = state.config(); // 本轮使用的配置快照
+        let session_timeout = Duration::from_secs(config.server.session_timeout_sec);
+        sessions::prune(&state); // 清理过期的观看会话
+        sessions::notify_presence(&state); // 观看者加入 / 离开事件
+        analytics::tick(&state, session_timeout); // 结束并导出观看会话记录
         let mut streams_to_kill = Vec::new(); // 用于存储待停止的流
         let mut streams_crashed = Vec::new(); // 用于存储崩溃的流
+        let mut streams_standby = Vec::new(); // 处于热备状态的流的输出目录索引
+        let mut recovery_changed = false; // 故障恢复状态是否需要写入运行状态文件
 
         // --- 阶段 1: 检查流状态 ---
         {
-            let mut streams = state.active_streams.lock().unwrap();
+            let mut streams = state.active_streams.lock_or_recover();
+            // 运行中的流所依赖的流不因空闲回收
+            let upstreams =
+                dependency::running_upstreams(&config, &streams.keys().collect::<Vec<_>>());
 
             for (name, runtime) in streams.iter_mut() {
                 match runtime.process.try_wait() {
                     Ok(Some(status)) => {
-                        // 流异常退出，记录警告并加入崩溃列表
-                        warn!("Stream [{}] exited unexpectedly with: {}", name, status);
-                        streams_crashed.push(name.clone());
+                        // 流异常退出，根据退出状态与日志判断失败类型后加入崩溃列表
+                        let (kind, reason) = failure::classify(status, &runtime.stderr_tail);
+                        warn!(
+                            "Stream [{}] exited unexpectedly with: {} ({:?}{})",
+                            name,
+                            status,
+                            kind,
+                            reason
+                                .as_deref()
+                                .map(|r| format!(": {}", r))
+                                .unwrap_or_default()
+                        );
+                        let exit = ExitRecord {
+                            kind: Some(kind),
+                            reason: reason.clone(),
+                            ..ExitRecord::new(
+                                "crashed",
+                                Some(status),
+                                now.duration_since(runtime.started_at).as_secs(),
+                            )
+                        };
+                        streams_crashed.push((name.clone(), kind, reason, exit));
                         continue;
                     }
-                    Ok(None) => {} // 流还在运行
+                    Ok(None) => {
+                        // 流还在运行，刷新进程资源占用
+                        if let Some(pid) = runtime.process.id() {
+                            if let Some((usage, sample)) =
+                                ProcessUsage::collect(pid, runtime.cpu_sample)
+                            {
+                                runtime.usage = Some(usage);
+                                runtime.cpu_sample = Some(sample);
+                            }
+                        }
+
+                        // 稳定运行一段时间后重置崩溃计数
+                        if now.duration_since(runtime.started_at) >= STABLE_UPTIME {
+                            if let Some(rec) = state.recovery_states.lock_or_recover().get_mut(name)
+                            {
+                                recovery_changed |= rec.crash_count > 0;
+                                rec.crash_count = 0;
+                                rec.next_retry_at = None;
+                            }
+                        }
+                    }
                     Err(e) => error!("Process monitor error [{}]: {}", name, e), // 监控进程出错
                 }
 
-                // 检查流是否超时空闲
-                if let Some(cfg) = state.config.streams.iter().find(|s| s.name == *name) {
-                    if cfg.idle_timeout > 0 {
-                        let idle_dur = now.duration_since(runtime.last_accessed);
+                // 检查流是否超时空闲 (最后一个观看会话结束后超过 idle_timeout)
+                if let Some(cfg) = config.stream(name) {
+                    if cfg.idle_timeout > 0
+                        && !cfg.keep_warm
+                        && cfg.supervise.idle_stop
+                        && !upstreams.contains(name)
+                    {
+                        let idle_dur = now
+                            .duration_since(runtime.last_accessed)
+                            .saturating_sub(session_timeout);
                         if idle_dur.as_secs() > cfg.idle_timeout {
-                            // 如果空闲超过配置的超时，安排停止流
-                            info!(
-                                "Stream [{}] idle for {}s. Scheduling stop.",
-                                name,
-                                idle_dur.as_secs()
-                            );
+                            if cfg.idle_action == IdleAction::Standby {
+                                // 热备：保留进程与源连接，直到超过 standby_timeout
+                                let since = match runtime.standby_since {
+                                    Some(t) => t,
+                                    None => {
+                                        info!(
+                                            "Stream [{}] idle for {}s. Entering standby.",
+                                            name,
+                                            idle_dur.as_secs()
+                                        );
+                                        runtime.standby_since = Some(now);
+                                        now
+                                    }
+                                };
+                                let standby_secs = now.duration_since(since).as_secs();
+                                if cfg.standby_timeout == 0 || standby_secs <= cfg.standby_timeout {
+                                    streams_standby.push(segment_index::get(
+                                        &state,
+                                        name,
+                                        &Engine::output_dir(&state, cfg),
+                                    ));
+                                    continue;
+                                }
+                                info!(
+                                    "Stream [{}] in standby for {}s. Scheduling stop.",
+                                    name, standby_secs
+                                );
+                            } else {
+                                // 如果空闲超过配置的超时，安排停止流
+                                info!(
+                                    "Stream [{}] idle for {}s. Scheduling stop.",
+                                    name,
+                                    idle_dur.as_secs()
+                                );
+                            }
                             streams_to_kill.push(name.clone());
                         }
                     }
@@ -55,27 +236,163 @@ pub async fn start_supervisor(state: Arc<AppState>, interval_ms: u64) {
             }
 
             // 从活动流中移除崩溃的流
-            for name in &streams_crashed {
+            for (name, _, _, _) in &streams_crashed {
                 streams.remove(name);
             }
         }
 
+        // 代理流会话同样按空闲超时回收
+        {
+            let sessions = state.proxy_sessions.lock_or_recover();
+            for (name, session) in sessions.iter() {
+                if let Some(cfg) = config.stream(name) {
+                    let idle_dur = now
+                        .duration_since(session.last_accessed)
+                        .saturating_sub(session_timeout);
+                    if cfg.idle_timeout > 0
+                        && !cfg.keep_warm
+                        && cfg.supervise.idle_stop
+                        && idle_dur.as_secs() > cfg.idle_timeout
+                    {
+                        info!(
+                            "Proxy stream [{}] idle for {}s. Scheduling stop.",
+                            name,
+                            idle_dur.as_secs()
+                        );
+                        streams_to_kill.push(name.clone());
+                    }
+                } else {
+                    // 配置已被移除
+                    streams_to_kill.push(name.clone());
+                }
+            }
+        }
+
         // --- 阶段 2: 执行停止流任务 ---
         for name in streams_to_kill {
             let _ = Engine::stop_stream(&state, &name).await;
+            runtime_state::clear_started(&state, &name);
+        }
+
+        // --- 阶段 2.1: 热备流只保留播放列表当前引用的切片 ---
+        for index in streams_standby {
+            Engine::trim_segments(&index).await;
         }
 
+        // --- 阶段 2.2: 租户存储配额 ---
+        for t in &config.tenants {
+            if t.quota.max_storage_mb == 0 {
+                continue;
+            }
+            let used = tenant::storage_usage(&state, &t.name);
+            if used > t.quota.max_storage_mb * 1024 * 1024 {
+                // 超出配额时停止该租户最近启动的流
+                if let Some(name) = tenant::running_streams(&state, &t.name).pop() {
+                    warn!(
+                        "Tenant [{}] exceeds storage quota ({} MB). Stopping stream [{}].",
+                        t.name, t.quota.max_storage_mb, name
+                    );
+                    let _ = Engine::stop_stream(&state, &name).await;
+                }
+            }
+        }
+
+        // --- 阶段 2.5: 密钥轮换 ---
+        let streams_to_rekey: Vec<String> = {
+            let streams = state.active_streams.lock_or_recover();
+            let key_map = state.stream_keys.lock_or_recover();
+            key_map
+                .iter()
+                .filter(|(name, _)| streams.contains_key(*name))
+                .filter(|(name, keyring)| {
+                    config
+                        .stream(name)
+                        .map(|cfg| {
+                            cfg.key_rotation_sec > 0
+                                && now.duration_since(keyring.rotated_at).as_secs()
+                                    >= cfg.key_rotation_sec
+                        })
+                        .unwrap_or(false)
+                })
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+        for name in streams_to_rekey {
+            if let Err(e) = Engine::rotate_key(&state, &name).await {
+                error!("Key rotation failed [{}]: {}", name, e);
+            }
+        }
+
+        // --- 阶段 2.6: 运动录像 ---
+        motion::tick(&state).await;
+
+        // --- 阶段 2.7: 清理过期的延时截帧 (每小时一次) ---
+        if last_prune.is_none_or(|t| now.duration_since(t) >= TIMELAPSE_PRUNE_INTERVAL) {
+            timelapse::prune(&state).await;
+            last_prune = Some(now);
+        }
+
+        // --- 阶段 2.8: 负载卸载 ---
+        loadshed::tick(&state).await;
+
         // --- 阶段 3: 故障恢复 (Backoff) ---
-        for name in streams_crashed {
-            let mut recovery_map = state.recovery_states.lock().unwrap();
-            let recovery = recovery_map
-                .entry(name.clone())
-                .or_insert(StreamRecoveryState {
-                    crash_count: 0,
-                    next_retry_at: None,
-                });
-
-            if let Some(cfg) = state.config.streams.iter().find(|s| s.name == name) {
+        recovery_changed |= !streams_crashed.is_empty();
+        for (name, kind, reason, exit) in streams_crashed {
+            availability::record(&state, &name, Transition::Down, exit.reason.clone());
+            counters::record_exit(&state, &name, exit.uptime_seconds, true);
+            let mut recovery_map = state.recovery_states.lock_or_recover();
+            let recovery = recovery_map.entry(name.clone()).or_default();
+            recovery.record_exit(exit);
+
+            if let Some(cfg) = config.stream(&name) {
+                // 不自动重启的流只记录崩溃，等待手动启动
+                if !cfg.supervise.auto_restart {
+                    warn!(
+                        "Stream [{}] crashed. Not restarting (supervise.auto_restart is off).",
+                        name
+                    );
+                    recovery.crash_count += 1;
+                    recovery.next_retry_at = None;
+                    continue;
+                }
+
+                // 无法自行恢复的错误直接隔离，不再浪费重试
+                if kind == FailureKind::Fatal {
+                    let reason = reason.unwrap_or_else(|| "unrecoverable error".to_string());
+                    error!(
+                        "Stream [{}] failed with an unrecoverable error: {}. Quarantined.",
+                        name, reason
+                    );
+                    recovery.quarantined = Some(reason);
+                    recovery.next_retry_at = None;
+                    continue;
+                }
+
+                // 滚动窗口内崩溃过于频繁，视为崩溃循环直接隔离
+                let window = Duration::from_secs(cfg.retry.window_sec);
+                recovery.recent_crashes.push_back(now);
+                while recovery
+                    .recent_crashes
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) > window)
+                {
+                    recovery.recent_crashes.pop_front();
+                }
+                let crashes = recovery.recent_crashes.len() as u32;
+                if cfg.retry.max_crashes_per_window > 0
+                    && crashes > cfg.retry.max_crashes_per_window
+                {
+                    let reason = format!(
+                        "crash loop ({} crashes within {}s)",
+                        crashes, cfg.retry.window_sec
+                    );
+                    error!("Stream [{}] is in a {}. Quarantined.", name, reason);
+                    recovery.quarantined = Some(reason);
+                    recovery.next_retry_at = None;
+                    recovery.recent_crashes.clear();
+                    continue;
+                }
+
                 // 检查最大重试次数
                 if cfg.retry.max_attempts > 0 && recovery.crash_count >= cfg.retry.max_attempts {
                     // 如果达到最大重试次数，则放弃重试
@@ -83,14 +400,19 @@ pub async fn start_supervisor(state: Arc<AppState>, interval_ms: u64) {
                         "Stream [{}] reached max retry attempts ({}). Giving up.",
                         name, cfg.retry.max_attempts
                     );
+                    recovery.next_retry_at = None;
                     continue;
                 }
 
-                // 计算回退时间（基于指数退避算法）
-                let backoff_sec = std::cmp::min(
-                    cfg.retry.max_backoff_sec,
-                    cfg.retry.initial_backoff_sec * 2u64.pow(recovery.crash_count),
-                );
+                // 计算回退时间：网络抖动首次立即重试，配置错误长时间退避，其余指数退避
+                let backoff_sec = match kind {
+                    FailureKind::Transient if recovery.crash_count == 0 => 0,
+                    FailureKind::Config => cfg.retry.config_backoff_sec,
+                    _ => std::cmp::min(
+                        cfg.retry.max_backoff_sec,
+                        cfg.retry.initial_backoff_sec * 2u64.pow(recovery.crash_count),
+                    ),
+                };
 
                 recovery.crash_count += 1;
                 recovery.next_retry_at = Some(now + Duration::from_secs(backoff_sec));
@@ -103,30 +425,53 @@ pub async fn start_supervisor(state: Arc<AppState>, interval_ms: u64) {
             }
         }
 
+        if recovery_changed {
+            runtime_state::save_or_warn(&state);
+        }
+
         // --- 阶段 4: 尝试重启流任务 ---
-        for cfg in &state.config.streams {
-            if !cfg.auto_start {
+        // 运行中的流所依赖的流与 auto_start 流一样自动重启
+        let upstreams = {
+            let streams = state.active_streams.lock_or_recover();
+            dependency::running_upstreams(&config, &streams.keys().collect::<Vec<_>>())
+        };
+        for cfg in &config.streams {
+            if !(cfg.auto_start || upstreams.contains(&cfg.name))
+                || cfg.is_proxied()
+                || maintenance::is_disabled(&state, &cfg.name)
+                || runtime_state::is_stopped(&state, &cfg.name)
+                || loadshed::is_shed(&state, &cfg.name)
+            {
                 continue;
-            } // 如果配置中不允许自动启动，跳过
+            } // 如果配置中不允许自动启动 (且不被运行中的流依赖)、流为代理中继、被运维禁用、被手动停止或被负载卸载，跳过
 
             // 检查流是否已在运行
-            let is_running = state.active_streams.lock().unwrap().contains_key(&cfg.name);
+            let is_running = state
+                .active_streams
+                .lock_or_recover()
+                .contains_key(&cfg.name);
             if is_running {
                 continue;
             }
 
             let mut should_start = true;
             {
-                let recovery_map = state.recovery_states.lock().unwrap();
+                let recovery_map = state.recovery_states.lock_or_recover();
                 if let Some(rec) = recovery_map.get(&cfg.name) {
-                    // 如果已达到最大重试次数或还在冷却中，则不重启流
-                    if rec.next_retry_at.is_none() && rec.crash_count > 0 {
+                    // 如果已被隔离、已达到最大重试次数或还在冷却中，则不重启流
+                    if rec.quarantined.is_some()
+                        || (rec.next_retry_at.is_none() && rec.crash_count > 0)
+                    {
                         should_start = false;
                     } else if let Some(next_retry) = rec.next_retry_at {
                         if now < next_retry {
                             should_start = false;
                         }
                     }
+                    // 距上次启动过近时等待，避免每个周期都被 Engine 拒绝
+                    if rec.start_wait(cfg.min_start_interval_sec).is_some() {
+                        should_start = false;
+                    }
                 }
             }
 
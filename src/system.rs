@@ -0,0 +1,83 @@
+use crate::platform;
+use serde::Serialize;
+use std::time::Instant;
+
+/// 系统资源快照
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemStats {
+    /// 总内存 (MB)
+    pub mem_total: u64,
+    /// 可用内存 (MB)
+    pub mem_avail: u64,
+    /// 1 分钟平均负载
+    pub load_avg: f64,
+}
+
+impl SystemStats {
+    /// 采集当前内存和负载信息，获取失败时对应字段为 0
+    pub fn collect() -> Self {
+        let mem = sys_info::mem_info()
+            .map(|m| (m.total, m.avail))
+            .unwrap_or((0, 0));
+        let load = sys_info::loadavg().map(|l| l.one).unwrap_or(0.0);
+
+        Self {
+            mem_total: mem.0 / 1024, // 转换为MB
+            mem_avail: mem.1 / 1024, // 转换为MB
+            load_avg: load,
+        }
+    }
+}
+
+/// 子进程资源占用
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    /// 常驻内存 (KB)
+    pub rss_kb: u64,
+    /// 最近一个采样周期的 CPU 占用 (%，占满一个核心为 100)
+    pub cpu_percent: f64,
+}
+
+/// 进程累计 CPU 时间的采样点
+#[derive(Debug, Clone, Copy)]
+pub struct CpuSample {
+    at: Instant,
+    /// 累计 CPU 时间 (秒)
+    cpu_secs: f64,
+}
+
+impl ProcessUsage {
+    /// 读取进程资源占用 (Linux 为 /proc，Windows 为进程计时与工作集)，
+    /// `prev` 为上一次的 CPU 采样 (首次采样时 CPU 占用为 0)
+    ///
+    /// 平台不支持或进程已退出时返回 None
+    pub fn collect(pid: u32, prev: Option<CpuSample>) -> Option<(Self, CpuSample)> {
+        let (cpu_secs, rss_kb) = platform::process_usage(pid)?;
+        let sample = CpuSample {
+            at: Instant::now(),
+            cpu_secs,
+        };
+
+        let cpu_percent = prev
+            .map(|p| {
+                let secs = sample.at.duration_since(p.at).as_secs_f64();
+                let used = (sample.cpu_secs - p.cpu_secs).max(0.0);
+                if secs > 0.0 {
+                    used / secs * 100.0
+                } else {
+                    0.0
+                }
+            })
+            .unwrap_or(0.0);
+
+        Some((
+            Self {
+                pid,
+                rss_kb,
+                cpu_percent: (cpu_percent * 10.0).round() / 10.0,
+            },
+            sample,
+        ))
+    }
+}
@@ -0,0 +1,130 @@
+use crate::config::{AppConfig, StreamConfig, StreamTemplate};
+use crate::snapshot;
+use crate::state::AppState;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+
+/// 复制流配置：以现有流为基础合并覆盖字段并使用新名称
+///
+/// 稳定标识与别名必须全局唯一，不随复制继承 (可在覆盖字段中重新指定)
+pub fn clone_stream(
+    source: &StreamConfig,
+    name: &str,
+    overrides: Option<&Value>,
+) -> anyhow::Result<StreamConfig> {
+    let mut spec = serde_json::to_value(source)?;
+    let obj = spec
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("Stream [{}] is not a mapping", source.name))?;
+    obj.remove("id");
+    obj.remove("aliases");
+    build(spec, name, overrides)
+}
+
+/// 修改流配置：在现有配置上合并覆盖字段，名称不变
+pub fn update_stream(source: &StreamConfig, overrides: &Value) -> anyhow::Result<StreamConfig> {
+    build(serde_json::to_value(source)?, &source.name, Some(overrides))
+}
+
+/// 实例化流模板：替换模板中的 `{参数}` 占位符后合并覆盖字段
+///
+/// 模板声明的参数均为必填，未声明的参数视为错误；未声明的占位符
+/// (如 output_args 中 FFmpeg 自身的模式) 原样保留
+pub fn instantiate(
+    template: &StreamTemplate,
+    name: &str,
+    params: &HashMap<String, String>,
+    overrides: Option<&Value>,
+) -> anyhow::Result<StreamConfig> {
+    if let Some(missing) = template.params.iter().find(|p| !params.contains_key(*p)) {
+        anyhow::bail!(
+            "Template [{}] requires parameter [{}]",
+            template.name,
+            missing
+        );
+    }
+    if let Some(unknown) = params.keys().find(|p| !template.params.contains(p)) {
+        anyhow::bail!(
+            "Template [{}] has no parameter [{}]",
+            template.name,
+            unknown
+        );
+    }
+    let mut spec = template.stream.clone();
+    substitute(&mut spec, params);
+    build(spec, name, overrides)
+}
+
+/// 将新流加入运行配置，`persist` 为 true 时同时写回配置文件
+pub async fn add(state: &Arc<AppState>, stream: StreamConfig, persist: bool) -> anyhow::Result<()> {
+    let _guard = state.config_lock.lock().await;
+    let current = state.config();
+    if current.lookup(&stream.name).is_some() {
+        anyhow::bail!("Stream [{}] already exists", stream.name);
+    }
+    let mut next: AppConfig = (*current).clone();
+    let name = stream.name.clone();
+    next.streams.push(stream);
+    next.validate()?;
+
+    if persist {
+        snapshot::apply(state, &state.config_path, next).await?;
+    } else {
+        state.replace_config(next);
+    }
+    info!("Stream [{}] added (persisted: {})", name, persist);
+    Ok(())
+}
+
+fn build(mut spec: Value, name: &str, overrides: Option<&Value>) -> anyhow::Result<StreamConfig> {
+    if let Some(overrides) = overrides {
+        if !overrides.is_object() {
+            anyhow::bail!("overrides must be a mapping");
+        }
+        merge(&mut spec, overrides);
+    }
+    let obj = spec
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("Stream template must be a mapping"))?;
+    obj.insert("name".to_string(), name.into());
+    Ok(serde_json::from_value(spec)?)
+}
+
+/// 深度合并：映射逐键合并，其余值 (含数组) 整体替换
+fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (k, v) in overrides {
+                merge(base.entry(k.clone()).or_insert(Value::Null), v);
+            }
+        }
+        (base, v) => *base = v.clone(),
+    }
+}
+
+fn substitute(value: &mut Value, params: &HashMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            for (k, v) in params {
+                *s = s.replace(&format!("{{{}}}", k), v);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| substitute(v, params)),
+        Value::Object(map) => map.values_mut().for_each(|v| substitute(v, params)),
+        _ => {}
+    }
+}
+
+/// 校验模板：以占位值实例化一次，确保模板能生成合法的流配置字段
+pub fn validate_template(template: &StreamTemplate) -> anyhow::Result<()> {
+    let params = template
+        .params
+        .iter()
+        .map(|p| (p.clone(), "x".to_string()))
+        .collect();
+    instantiate(template, "probe", &params, None)
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("Stream template [{}] is invalid: {}", template.name, e))
+}
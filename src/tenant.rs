@@ -0,0 +1,67 @@
+use crate::config::StreamConfig;
+use crate::engine::Engine;
+use crate::segment_index;
+use crate::state::{AppState, LockExt};
+
+/// 启动前检查租户的流数量与存储配额
+pub fn check_start_quota(state: &AppState, cfg: &StreamConfig) -> anyhow::Result<()> {
+    let Some(tenant_name) = &cfg.tenant else {
+        return Ok(());
+    };
+    let config = state.config();
+    let Some(tenant) = config.tenant(tenant_name) else {
+        return Ok(());
+    };
+
+    if tenant.quota.max_streams > 0 {
+        let running = running_streams(state, tenant_name).len();
+        if running >= tenant.quota.max_streams {
+            anyhow::bail!(
+                "Tenant [{}] reached its stream quota ({})",
+                tenant_name,
+                tenant.quota.max_streams
+            );
+        }
+    }
+
+    if tenant.quota.max_storage_mb > 0 {
+        let used = storage_usage(state, tenant_name);
+        if used >= tenant.quota.max_storage_mb * 1024 * 1024 {
+            anyhow::bail!(
+                "Tenant [{}] reached its storage quota ({} MB)",
+                tenant_name,
+                tenant.quota.max_storage_mb
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// 租户当前运行中的流 (按启动时间从早到晚排序)
+pub fn running_streams(state: &AppState, tenant: &str) -> Vec<String> {
+    let config = state.config();
+    let streams = state.active_streams.lock_or_recover();
+    let mut running: Vec<_> = config
+        .streams
+        .iter()
+        .filter(|s| s.tenant.as_deref() == Some(tenant))
+        .filter_map(|s| streams.get(&s.name).map(|r| (r.started_at, s.name.clone())))
+        .collect();
+    running.sort();
+    running.into_iter().map(|(_, name)| name).collect()
+}
+
+/// 统计租户运行中流的输出目录占用的字节数
+///
+/// 已停止流的残留切片会在下次启动时被清理，不计入用量
+pub fn storage_usage(state: &AppState, tenant: &str) -> u64 {
+    let config = state.config();
+    running_streams(state, tenant)
+        .iter()
+        .filter_map(|name| config.stream(name))
+        .map(|cfg| {
+            segment_index::get(state, &cfg.name, &Engine::output_dir(state, cfg)).total_bytes()
+        })
+        .sum()
+}
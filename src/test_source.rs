@@ -0,0 +1,126 @@
+use crate::config::StreamConfig;
+
+/// 内置测试源的地址前缀，如 `test://smpte?size=1280x720&rate=25&tone=1000`
+const SCHEME: &str = "test://";
+
+/// 解析后的测试源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestSource {
+    /// lavfi 视频源 (含参数)
+    video: String,
+    /// 帧率
+    rate: u32,
+    /// 测试音频率 (Hz)，0 表示静音
+    tone: u32,
+}
+
+/// 是否为内置测试源
+pub fn is_test(source: &str) -> bool {
+    source.starts_with(SCHEME)
+}
+
+/// 解析测试源地址
+///
+/// 图案：`smpte` (SMPTE HD 彩条)、`smptesd` (SMPTE 彩条)、`testsrc` (带计数器的动态图案)、`black`；
+/// 参数：`size` (默认 1280x720)、`rate` (默认 25)、`tone` (测试音频率，默认 1000，0 为静音)。
+/// 参数直接拼入滤镜图，因此只接受数字
+pub fn parse(source: &str) -> anyhow::Result<TestSource> {
+    let rest = source
+        .strip_prefix(SCHEME)
+        .ok_or_else(|| anyhow::anyhow!("Not a test source: {}", source))?;
+    let (pattern, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let (mut width, mut height, mut rate, mut tone) = (1280u32, 720u32, 25u32, 1000u32);
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let number = |v: &str| {
+            v.parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("Test source {}: invalid {} {:?}", source, key, v))
+        };
+        match key {
+            "size" => {
+                let (w, h) = value.split_once('x').ok_or_else(|| {
+                    anyhow::anyhow!("Test source {}: size must be WIDTHxHEIGHT", source)
+                })?;
+                (width, height) = (number(w)?, number(h)?);
+            }
+            "rate" => rate = number(value)?,
+            "tone" => tone = number(value)?,
+            _ => anyhow::bail!("Test source {}: unknown parameter {}", source, key),
+        }
+    }
+    // 编码为 yuv420p 要求宽高均为偶数
+    if !(16..=7680).contains(&width)
+        || !(16..=4320).contains(&height)
+        || width % 2 + height % 2 != 0
+    {
+        anyhow::bail!(
+            "Test source {}: size must be even and between 16x16 and 7680x4320",
+            source
+        );
+    }
+    if !(1..=120).contains(&rate) || tone > 20_000 {
+        anyhow::bail!(
+            "Test source {}: rate must be 1-120 and tone at most 20000",
+            source
+        );
+    }
+
+    let size = format!("{}x{}", width, height);
+    let video = match pattern {
+        "smpte" => format!("smptehdbars=size={}:rate={}", size, rate),
+        "smptesd" => format!("smptebars=size={}:rate={}", size, rate),
+        "testsrc" => format!("testsrc2=size={}:rate={}", size, rate),
+        "black" => format!("color=c=black:size={}:rate={}", size, rate),
+        _ => anyhow::bail!(
+            "Unknown test pattern {:?} (expected smpte, smptesd, testsrc or black)",
+            pattern
+        ),
+    };
+    Ok(TestSource { video, rate, tone })
+}
+
+/// 测试源的输入参数：一个 lavfi 输入同时产生视频 (0:v:0) 与音频 (0:a:0)，按实时速率读取
+pub fn input_args(source: &TestSource) -> Vec<String> {
+    let audio = if source.tone > 0 {
+        format!("sine=frequency={}:sample_rate=48000", source.tone)
+    } else {
+        "anullsrc=channel_layout=stereo:sample_rate=48000".to_string()
+    };
+    vec![
+        "-re".to_string(),
+        "-f".to_string(),
+        "lavfi".to_string(),
+        "-i".to_string(),
+        format!("{}[out0];{}[out1]", source.video, audio),
+    ]
+}
+
+/// 替代 `-c copy` 的编码参数：lavfi 产生的是未压缩数据，无法直接复制到 HLS/RTSP/TS 输出
+///
+/// 每个切片以关键帧开始，切片时长与配置一致
+pub fn encode_args(cfg: &StreamConfig, source: &TestSource) -> Vec<String> {
+    let gop = (source.rate * cfg.hls.segment_duration_sec.max(1)).to_string();
+    [
+        "-c:v",
+        "libx264",
+        "-preset",
+        "veryfast",
+        "-tune",
+        "zerolatency",
+        "-pix_fmt",
+        "yuv420p",
+        "-g",
+        &gop,
+        "-keyint_min",
+        &gop,
+        "-sc_threshold",
+        "0",
+        "-c:a",
+        "aac",
+        "-b:a",
+        "128k",
+    ]
+    .map(String::from)
+    .to_vec()
+}
@@ -0,0 +1,190 @@
+use crate::config::{StreamConfig, TimelapseConfig};
+use crate::keys;
+use crate::privilege;
+use crate::sandbox;
+use crate::state::AppState;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// 生成视频的最高帧率，超出时按间隔抽帧
+const MAX_FPS: f64 = 30.0;
+
+/// 单次生成的最长耗时
+const RENDER_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 流的截帧目录 `{record_root}/{name}/timelapse`
+pub fn frame_dir(state: &AppState, name: &str) -> PathBuf {
+    Path::new(&state.config().server.record_root)
+        .join(name)
+        .join("timelapse")
+}
+
+/// 生成定时截帧输出的 FFmpeg 参数
+///
+/// 文件名为截帧时的 Unix 时间戳 (strftime `%s`)，便于按时间范围筛选
+pub fn capture_args(cfg: &TimelapseConfig, dir: &Path) -> Vec<String> {
+    vec![
+        "-map".to_string(),
+        "0:v:0".to_string(),
+        "-an".to_string(),
+        "-vf".to_string(),
+        format!("fps=1/{}", cfg.interval_sec),
+        "-q:v".to_string(),
+        "4".to_string(),
+        "-f".to_string(),
+        "image2".to_string(),
+        "-strftime".to_string(),
+        "1".to_string(),
+        dir.join("%s.jpg").to_string_lossy().to_string(),
+    ]
+}
+
+/// 删除超过保留时长的截帧
+pub async fn prune(state: &AppState) {
+    let config = state.config();
+    let now = unix_now();
+    for cfg in &config.streams {
+        let Some(tl) = cfg.timelapse.as_ref().filter(|t| t.retention_hours > 0) else {
+            continue;
+        };
+        let cutoff = now.saturating_sub(tl.retention_hours * 3600);
+        let mut removed = 0;
+        for (ts, path) in list_frames(&frame_dir(state, &cfg.name)).await {
+            if ts < cutoff && fs::remove_file(&path).await.is_ok() {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            info!(
+                "Pruned {} timelapse frames of stream [{}]",
+                removed, cfg.name
+            );
+        }
+    }
+}
+
+/// 将时间范围内的截帧合成为 MP4
+///
+/// `speed` 为相对真实时间的倍速，即每帧显示 `interval_sec / speed` 秒
+pub async fn render(
+    state: &AppState,
+    cfg: &StreamConfig,
+    from: u64,
+    to: u64,
+    speed: f64,
+) -> anyhow::Result<PathBuf> {
+    let tl = cfg
+        .timelapse
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Timelapse is not enabled for this stream"))?;
+    if from >= to || speed.is_nan() || speed <= 0.0 {
+        anyhow::bail!("Invalid time range or speed");
+    }
+
+    let dir = frame_dir(state, &cfg.name);
+    let frames: Vec<PathBuf> = list_frames(&dir)
+        .await
+        .into_iter()
+        .filter(|(ts, _)| (from..=to).contains(ts))
+        .map(|(_, p)| p)
+        .collect();
+    if frames.is_empty() {
+        anyhow::bail!("No frames captured in the requested range");
+    }
+
+    // 帧率过高时每 step 帧取一帧
+    let fps = speed / tl.interval_sec as f64;
+    let step = (fps / MAX_FPS).ceil().max(1.0) as usize;
+    let fps = fps / step as f64;
+
+    let mut list = String::from("ffconcat version 1.0\n");
+    for frame in frames.iter().step_by(step) {
+        let path = frame.to_string_lossy().replace('\'', "'\\''");
+        list.push_str(&format!("file '{}'\nduration {:.6}\n", path, 1.0 / fps));
+    }
+
+    let mut tag = [0u8; 6];
+    keys::random_bytes(&mut tag)?;
+    let tag: String = tag.iter().map(|b| format!("{:02x}", b)).collect();
+    let list_path = dir.join(format!(".render-{}.txt", tag));
+    let out_path = dir.join(format!(".render-{}.mp4", tag));
+    fs::write(&list_path, list).await?;
+
+    let mut cmd = Command::new(&state.config().server.ffmpeg_binary);
+    cmd.args(["-hide_banner", "-y", "-f", "concat", "-safe", "0", "-i"])
+        .arg(&list_path)
+        .args(["-r", &format!("{:.3}", fps.max(1.0))])
+        .args([
+            "-c:v",
+            "libx264",
+            "-pix_fmt",
+            "yuv420p",
+            "-movflags",
+            "+faststart",
+        ])
+        .arg(&out_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    privilege::apply_to_child(&mut cmd);
+    if let Some(sandbox) = &state.config().sandbox {
+        sandbox::apply(&mut cmd, sandbox, &[&dir])?;
+    }
+
+    let status = tokio::time::timeout(RENDER_TIMEOUT, cmd.status()).await;
+    let _ = fs::remove_file(&list_path).await;
+    match status {
+        Ok(Ok(s)) if s.success() => {
+            info!(
+                "Rendered timelapse of stream [{}] from {} frames",
+                cfg.name,
+                frames.len().div_ceil(step)
+            );
+            Ok(out_path)
+        }
+        Ok(Ok(s)) => {
+            let _ = fs::remove_file(&out_path).await;
+            anyhow::bail!("FFmpeg exited with {}", s)
+        }
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => {
+            let _ = fs::remove_file(&out_path).await;
+            warn!("Timelapse rendering timed out [{}]", cfg.name);
+            anyhow::bail!("Timelapse rendering timed out")
+        }
+    }
+}
+
+/// 列出截帧 (时间戳, 路径)，按时间排序
+async fn list_frames(dir: &Path) -> Vec<(u64, PathBuf)> {
+    let mut frames = Vec::new();
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return frames;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_some_and(|e| e == "jpg") {
+            if let Some(ts) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+            {
+                frames.push((ts, path));
+            }
+        }
+    }
+    frames.sort();
+    frames
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
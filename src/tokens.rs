@@ -0,0 +1,314 @@
+use crate::auth::constant_time_eq;
+use crate::clock;
+use crate::config::AppConfig;
+use crate::hash;
+use crate::keys;
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// 令牌文件 (位于 server.state_root)：签发的令牌、吊销记录与使用统计
+const FILE_NAME: &str = "tokens.json";
+
+/// 使用统计的写入间隔；签发与吊销立即写入
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 通过 API 签发的令牌 ID 前缀
+pub const ISSUED_PREFIX: &str = "tok-";
+
+/// 签发的令牌本身的前缀，便于在日志与代码仓库中识别泄露的令牌
+const SECRET_PREFIX: &str = "vtx_";
+
+/// 串行化写入
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 跨重启保留的令牌状态
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenStore {
+    /// 通过 API 签发的令牌 (只保存摘要)
+    #[serde(default)]
+    issued: Vec<IssuedToken>,
+    /// 已吊销的配置令牌：ID -> 吊销时间 (RFC 3339)；签发的令牌吊销时直接删除
+    #[serde(default)]
+    revoked: BTreeMap<String, String>,
+    /// 令牌 ID -> 使用统计
+    #[serde(default)]
+    usage: HashMap<String, TokenUsage>,
+    /// 使用统计是否有未写入的变化
+    #[serde(skip)]
+    dirty: bool,
+}
+
+/// 通过 API 签发的令牌
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub id: String,
+    /// 令牌的 SHA-512 摘要 (十六进制)
+    digest: String,
+    /// 令牌所属租户，缺省表示管理员令牌
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// 备注 (如持有者或用途)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// 签发时间 (RFC 3339)
+    pub created_at: String,
+    /// 过期时间 (Unix 秒)，缺省表示永不过期
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl IssuedToken {
+    fn expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// 令牌的使用统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// 最近一次使用的时间 (RFC 3339)
+    pub last_used: Option<String>,
+    /// 累计认证通过的请求数
+    pub requests: u64,
+}
+
+/// 令牌列表中的一项 (不含令牌本身)
+#[derive(Debug, Serialize)]
+pub struct TokenInfo {
+    pub id: String,
+    /// `config` (配置文件中的令牌) 或 `api` (通过 API 签发)
+    pub source: &'static str,
+    pub tenant: Option<String>,
+    pub label: Option<String>,
+    pub created_at: Option<String>,
+    /// 过期时间 (RFC 3339)
+    pub expires_at: Option<String>,
+    /// 吊销时间 (RFC 3339)，仅配置令牌会保留吊销记录
+    pub revoked_at: Option<String>,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+}
+
+/// 令牌的摘要 (SHA-512 十六进制)，用于保存签发的令牌与生成配置令牌的 ID
+pub fn digest(token: &str) -> String {
+    hex(&hash::sha512(token.as_bytes()))
+}
+
+/// 读取上次运行保存的令牌状态 (不存在或无法读取时为空)
+pub fn load(config: &AppConfig) -> TokenStore {
+    let path = file_path(config);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return TokenStore::default(),
+        Err(e) => {
+            warn!("Failed to read {:?}: {}", path, e);
+            return TokenStore::default();
+        }
+    };
+    match serde_json::from_slice(&data) {
+        Ok(store) => store,
+        Err(e) => {
+            warn!("Ignoring invalid {:?}: {}", path, e);
+            TokenStore::default()
+        }
+    }
+}
+
+/// 查找未过期的签发令牌，返回其 ID 与所属租户
+pub fn lookup(state: &AppState, token: &str) -> Option<(String, Option<String>)> {
+    if !token.starts_with(SECRET_PREFIX) {
+        return None;
+    }
+    let digest = digest(token);
+    let now = unix_now();
+    state
+        .tokens
+        .lock_or_recover()
+        .issued
+        .iter()
+        .find(|t| constant_time_eq(&t.digest, &digest))
+        .filter(|t| !t.expired(now))
+        .map(|t| (t.id.clone(), t.tenant.clone()))
+}
+
+/// 记录一次认证通过的请求；令牌已吊销时返回 false
+pub fn record_use(state: &AppState, id: &str) -> bool {
+    let mut store = state.tokens.lock_or_recover();
+    if store.revoked.contains_key(id) {
+        return false;
+    }
+    let usage = store.usage.entry(id.to_string()).or_default();
+    usage.last_used = Some(clock::rfc3339(SystemTime::now()));
+    usage.requests += 1;
+    store.dirty = true;
+    true
+}
+
+/// 签发新令牌并立即写入，返回令牌信息与令牌本身 (只在此时返回一次)
+pub fn issue(
+    state: &AppState,
+    tenant: Option<String>,
+    label: Option<String>,
+    ttl_sec: Option<u64>,
+) -> anyhow::Result<(IssuedToken, String)> {
+    let mut secret = [0u8; 24];
+    keys::random_bytes(&mut secret)?;
+    let secret = format!("{}{}", SECRET_PREFIX, hex(&secret));
+    let mut id = [0u8; 6];
+    keys::random_bytes(&mut id)?;
+
+    let token = IssuedToken {
+        id: format!("{}{}", ISSUED_PREFIX, hex(&id)),
+        digest: digest(&secret),
+        tenant,
+        label,
+        created_at: clock::rfc3339(SystemTime::now()),
+        expires_at: ttl_sec.map(|ttl| unix_now() + ttl),
+    };
+    state.tokens.lock_or_recover().issued.push(token.clone());
+    save(state)?;
+    info!(
+        "Issued token [{}]{}",
+        token.id,
+        token
+            .tenant
+            .as_deref()
+            .map(|t| format!(" for tenant [{}]", t))
+            .unwrap_or_default()
+    );
+    Ok((token, secret))
+}
+
+/// 立即吊销令牌并写入；签发的令牌被删除，配置令牌记入吊销列表 (直到从配置中移除)。
+/// 令牌不存在时返回 Ok(false)
+pub fn revoke(state: &AppState, id: &str) -> anyhow::Result<bool> {
+    let in_config = state.config().auth.tokens.iter().any(|t| t.id() == id);
+    {
+        let mut store = state.tokens.lock_or_recover();
+        let issued = store.issued.len();
+        store.issued.retain(|t| t.id != id);
+        if store.issued.len() < issued {
+            store.usage.remove(id);
+        } else if in_config {
+            store
+                .revoked
+                .entry(id.to_string())
+                .or_insert_with(|| clock::rfc3339(SystemTime::now()));
+        } else {
+            return Ok(false);
+        }
+    }
+    save(state)?;
+    info!("Revoked token [{}]", id);
+    Ok(true)
+}
+
+/// 全部令牌及其使用统计：先列出配置令牌，再按签发时间列出签发的令牌
+pub fn list(state: &AppState) -> Vec<TokenInfo> {
+    let config = state.config();
+    let store = state.tokens.lock_or_recover();
+    let usage = |id: &str| store.usage.get(id).cloned().unwrap_or_default();
+
+    let configured = config.auth.tokens.iter().map(|t| {
+        let id = t.id();
+        TokenInfo {
+            source: "config",
+            tenant: t.tenant().map(str::to_string),
+            label: None,
+            created_at: None,
+            expires_at: None,
+            revoked_at: store.revoked.get(&id).cloned(),
+            usage: usage(&id),
+            id,
+        }
+    });
+    let issued = store.issued.iter().map(|t| TokenInfo {
+        id: t.id.clone(),
+        source: "api",
+        tenant: t.tenant.clone(),
+        label: t.label.clone(),
+        created_at: Some(t.created_at.clone()),
+        expires_at: t
+            .expires_at
+            .map(|at| clock::rfc3339(UNIX_EPOCH + Duration::from_secs(at))),
+        revoked_at: None,
+        usage: usage(&t.id),
+    });
+    configured.chain(issued).collect()
+}
+
+/// 写入令牌状态：先写临时文件再重命名；过期的签发令牌与
+/// 已从配置中移除的令牌的统计、吊销记录在此时清除
+pub fn save(state: &AppState) -> anyhow::Result<()> {
+    let _guard = SAVE_LOCK.lock_or_recover();
+    let config = state.config();
+    let data = {
+        let mut store = state.tokens.lock_or_recover();
+        let now = unix_now();
+        store.issued.retain(|t| !t.expired(now));
+        let known: HashSet<String> = config
+            .auth
+            .tokens
+            .iter()
+            .map(|t| t.id())
+            .chain(store.issued.iter().map(|t| t.id.clone()))
+            .collect();
+        store.usage.retain(|id, _| known.contains(id));
+        store.revoked.retain(|id, _| known.contains(id));
+        store.dirty = false;
+        serde_json::to_vec_pretty(&*store)?
+    };
+
+    let path = file_path(&config);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, &path).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))
+}
+
+/// 定期写入使用统计
+pub async fn start_flusher(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if !state.tokens.lock_or_recover().dirty {
+            continue;
+        }
+        if let Err(e) = save(&state) {
+            warn!("Failed to save token usage: {}", e);
+        }
+    }
+}
+
+/// 网关关闭时写入最终的使用统计
+pub fn flush_on_shutdown(state: &AppState) {
+    if !state.tokens.lock_or_recover().dirty {
+        return;
+    }
+    if let Err(e) = save(state) {
+        warn!("Failed to save token usage: {}", e);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn file_path(config: &AppConfig) -> PathBuf {
+    PathBuf::from(&config.server.state_root).join(FILE_NAME)
+}
@@ -0,0 +1,124 @@
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::sync::mpsc;
+use tokio_util::io::ReaderStream;
+use tracing::debug;
+
+/// 观看者在该时长内未取走下一块数据时视为连接已失效，中止传输
+const STALL_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// 每次从文件读取的块大小
+const READ_CHUNK: usize = 16 * 1024;
+
+/// 缓存的切片每次交给连接的块大小 (仅切分引用，不复制)
+const CACHED_CHUNK: usize = 64 * 1024;
+
+/// 切片传输计数
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    /// 完整发送的切片数
+    completed: AtomicU64,
+    /// 观看者中途断开而中止的传输数
+    aborted: AtomicU64,
+    /// 观看者长时间不读取 (半开连接) 而中止的传输数
+    stalled: AtomicU64,
+}
+
+/// 传输计数快照
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TransferCounts {
+    pub completed: u64,
+    pub aborted: u64,
+    pub stalled: u64,
+}
+
+impl TransferStats {
+    pub fn snapshot(&self) -> TransferCounts {
+        TransferCounts {
+            completed: self.completed.load(Ordering::Relaxed),
+            aborted: self.aborted.load(Ordering::Relaxed),
+            stalled: self.stalled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 以受监护的方式发送文件：由独立任务读取文件并经容量为 1 的通道交给连接
+///
+/// - 观看者断开时响应体被丢弃，读取任务立即停止并关闭文件
+/// - 观看者超过 STALL_TIMEOUT 未读取时放弃传输，释放文件句柄与缓冲，
+///   连接再次被轮询时以错误结束 (不会被播放器当作完整切片)
+///
+/// 任一时刻每个传输最多缓冲一块数据
+pub fn guarded_file(
+    file: File,
+    stats: Arc<TransferStats>,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static {
+    guarded(ReaderStream::with_capacity(file, READ_CHUNK), stats)
+}
+
+/// 以受监护的方式发送缓存的切片 (见 `segment_index::SegmentCache`)：按块切分引用，
+/// 没有读取与复制，断开与停滞的处理同 `guarded_file`
+pub fn guarded_bytes(
+    data: Bytes,
+    stats: Arc<TransferStats>,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static {
+    let chunks: Vec<io::Result<Bytes>> = (0..data.len())
+        .step_by(CACHED_CHUNK)
+        .map(|i| Ok(data.slice(i..(i + CACHED_CHUNK).min(data.len()))))
+        .collect();
+    guarded(futures_util::stream::iter(chunks), stats)
+}
+
+fn guarded<S>(
+    mut reader: S,
+    stats: Arc<TransferStats>,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(1);
+    let stalled = Arc::new(AtomicBool::new(false));
+
+    let flag = stalled.clone();
+    tokio::spawn(async move {
+        while let Some(chunk) = reader.next().await {
+            let failed = chunk.is_err();
+            match tokio::time::timeout(STALL_TIMEOUT, tx.send(chunk)).await {
+                Ok(Ok(())) if failed => return,
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => {
+                    stats.aborted.fetch_add(1, Ordering::Relaxed);
+                    debug!("Segment transfer aborted: client disconnected");
+                    return;
+                }
+                Err(_) => {
+                    flag.store(true, Ordering::Relaxed);
+                    stats.stalled.fetch_add(1, Ordering::Relaxed);
+                    debug!("Segment transfer aborted: client stopped reading");
+                    return;
+                }
+            }
+        }
+        stats.completed.fetch_add(1, Ordering::Relaxed);
+    });
+
+    Box::pin(futures_util::stream::unfold(
+        (rx, stalled),
+        |(mut rx, stalled)| async move {
+            match rx.recv().await {
+                Some(chunk) => Some((chunk, (rx, stalled))),
+                None if stalled.swap(false, Ordering::Relaxed) => Some((
+                    Err(io::Error::new(io::ErrorKind::TimedOut, "Client stalled")),
+                    (rx, stalled),
+                )),
+                None => None,
+            }
+        },
+    ))
+}
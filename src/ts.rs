@@ -0,0 +1,57 @@
+use crate::state::{LockExt, SharedState};
+use axum::body::Bytes;
+use tokio::io::AsyncReadExt;
+use tokio::process::ChildStdout;
+use tokio::sync::broadcast;
+use tracing::info;
+
+/// MPEG-TS 包长度
+const TS_PACKET_SIZE: usize = 188;
+
+/// 每路输出缓冲的数据块数量，慢速客户端超出后丢弃
+const FEED_BUFFER: usize = 256;
+
+/// 将 FFmpeg 标准输出的 MPEG-TS 分发给所有订阅者
+///
+/// 无论是否有订阅者都持续读取，避免管道写满后阻塞 FFmpeg；
+/// 数据块按 TS 包边界切分，新订阅者可以直接从包头开始解析
+pub fn spawn_feed(state: SharedState, name: String, mut stdout: ChildStdout) {
+    let (tx, _) = broadcast::channel(FEED_BUFFER);
+    state
+        .ts_feeds
+        .lock_or_recover()
+        .insert(name.clone(), tx.clone());
+
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 512 * TS_PACKET_SIZE];
+        let mut filled = 0;
+        loop {
+            match stdout.read(&mut buf[filled..]).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => filled += n,
+            }
+            let whole = filled / TS_PACKET_SIZE * TS_PACKET_SIZE;
+            if whole > 0 {
+                let _ = tx.send(Bytes::copy_from_slice(&buf[..whole]));
+                buf.copy_within(whole..filled, 0);
+                filled -= whole;
+            }
+        }
+
+        // 进程退出后移除输出 (若未被新进程替换)，订阅者随之结束
+        let mut feeds = state.ts_feeds.lock_or_recover();
+        if feeds.get(&name).is_some_and(|f| f.same_channel(&tx)) {
+            feeds.remove(&name);
+            info!("MPEG-TS feed closed for stream [{}]", name);
+        }
+    });
+}
+
+/// 订阅指定流的 MPEG-TS 输出
+pub fn subscribe(state: &SharedState, name: &str) -> Option<broadcast::Receiver<Bytes>> {
+    state
+        .ts_feeds
+        .lock_or_recover()
+        .get(name)
+        .map(|f| f.subscribe())
+}
@@ -0,0 +1,131 @@
+use crate::config::{AppConfig, HlsConfig};
+use crate::migrate::{self, MigrateResult};
+use crate::snapshot;
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+/// HLS 参数修改请求，省略的字段保持不变
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HlsTuning {
+    #[serde(default)]
+    pub segment_duration_sec: Option<u32>,
+    #[serde(default)]
+    pub list_size: Option<u32>,
+    /// LL-HLS 部分切片时长：网关不输出 LL-HLS，提供时拒绝请求
+    #[serde(default)]
+    pub part_duration_sec: Option<f64>,
+    #[serde(default)]
+    pub apply: ApplyMode,
+    /// 是否写回配置文件 (`switch` 总是写回)
+    #[serde(default)]
+    pub persist: bool,
+}
+
+/// 新参数的生效方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyMode {
+    /// 只更新配置，运行中的进程在下次重启时使用新参数
+    #[default]
+    NextRestart,
+    /// 按新参数启动第二个进程，就绪后无中断地接替 (见 `migrate`)
+    Switch,
+}
+
+/// 修改结果
+#[derive(Debug, Clone, Serialize)]
+pub struct TuningResult {
+    /// 修改后的 HLS 参数
+    pub hls: HlsConfig,
+    pub changed: bool,
+    pub apply: ApplyMode,
+    /// 运行中的进程仍在使用修改前的参数，下次重启时生效
+    pub pending_restart: bool,
+    /// `switch` 的迁移结果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub migrate: Option<MigrateResult>,
+}
+
+/// 修改流的切片时长与播放列表长度
+pub async fn update(
+    state: &Arc<AppState>,
+    name: &str,
+    req: HlsTuning,
+    timeout: Duration,
+) -> anyhow::Result<TuningResult> {
+    if req.part_duration_sec.is_some() {
+        anyhow::bail!("LL-HLS partial segments are not supported; part_duration_sec cannot be set");
+    }
+    if req.segment_duration_sec == Some(0) {
+        anyhow::bail!("segment_duration_sec must be at least 1");
+    }
+    let current = state.config();
+    let cfg = current
+        .stream(name)
+        .ok_or_else(|| anyhow::anyhow!("Stream [{}] not found", name))?;
+    if cfg.is_proxied() {
+        anyhow::bail!(
+            "Stream [{}] is a proxy relay; its segments come from upstream",
+            name
+        );
+    }
+    let tune = |current: &HlsConfig| {
+        let mut hls = current.clone();
+        if let Some(duration) = req.segment_duration_sec {
+            hls.segment_duration_sec = duration;
+        }
+        if let Some(size) = req.list_size {
+            hls.list_size = size;
+        }
+        hls
+    };
+    let hls = tune(&cfg.hls);
+    drop(current);
+
+    if req.apply == ApplyMode::Switch {
+        let overrides = serde_json::json!({ "hls": &hls });
+        let result = migrate::apply_profile(state, name, &overrides, timeout).await?;
+        return Ok(TuningResult {
+            hls,
+            changed: result.changed,
+            apply: req.apply,
+            pending_restart: false,
+            migrate: Some(result),
+        });
+    }
+
+    let _guard = state.config_lock.lock().await;
+    let mut next: AppConfig = (*state.config()).clone();
+    let stream = next
+        .streams
+        .iter_mut()
+        .find(|s| s.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Stream [{}] not found", name))?;
+    // 按持锁后读取的配置重新计算，不覆盖等锁期间的其他修改
+    let hls = tune(&stream.hls);
+    let changed = stream.hls != hls;
+    stream.hls = hls.clone();
+    if changed {
+        next.validate()?;
+        if req.persist {
+            snapshot::persist(&state.config_path, &next).await?;
+        }
+        state.replace_config(next);
+        info!(
+            "Stream [{}] HLS tuning updated: {} s segments, {} in list (persisted: {})",
+            name, hls.segment_duration_sec, hls.list_size, req.persist
+        );
+    }
+    let running = state.active_streams.lock_or_recover().contains_key(name);
+    Ok(TuningResult {
+        hls,
+        changed,
+        apply: req.apply,
+        pending_restart: changed && running,
+        migrate: None,
+    })
+}
@@ -0,0 +1,372 @@
+use crate::ed25519;
+use crate::engine::Engine;
+use crate::hash;
+use crate::http_client;
+use crate::state::{AppState, LockExt};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering as CmpOrdering;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// 下载超时 (二进制约数 MB，低速链路上留足时间)
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 返回响应后到重新执行之间的等待，保证调用方收到结果
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// 同一时间只允许一次更新
+static UPDATING: AtomicBool = AtomicBool::new(false);
+
+/// 发布清单 (JSON)，发布者的 Ed25519 签名覆盖清单的原始字节
+///
+/// 签名绑定版本、目标平台与二进制摘要：旧版本或其他平台的已签名二进制不能被重放
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// 新版本号 (`主.次.修订`，可带 `-预发布` 后缀)，必须高于当前版本
+    pub version: String,
+    /// 目标平台 `{arch}-{os}` (如 `x86_64-linux`)，必须与当前进程一致
+    pub target: String,
+    /// 二进制的 SHA-256 (十六进制)
+    pub sha256: String,
+}
+
+/// 当前进程的目标平台 (与清单的 `target` 比较)
+pub fn current_target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// 更新结果
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateResult {
+    /// 安装的版本
+    pub version: String,
+    /// 安装的二进制路径
+    pub installed: PathBuf,
+    /// 旧版本备份路径 (可手动回滚)
+    pub backup: PathBuf,
+    pub size: usize,
+    pub restarting: bool,
+}
+
+/// 更新来源，省略的地址取 update 配置
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Source {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub manifest_url: Option<String>,
+    #[serde(default)]
+    pub signature_url: Option<String>,
+}
+
+/// 下载并校验签名的发布清单与新版本二进制，替换当前可执行文件后重新执行
+///
+/// 地址可覆盖配置 (清单始终使用配置中的公钥校验，二进制按清单中的摘要校验)。
+/// 配置了 `privileges` 时拒绝更新：降权后的网关不应能替换以 root 启动的可执行文件。
+/// 网关通过管道读取 FFmpeg 的输出，管道在 exec 后不再可用，
+/// 因此重新执行前会停止所有流；按需流由下一个观看者拉起，auto_start 流由 Supervisor 恢复
+pub async fn update(state: &Arc<AppState>, source: Source) -> anyhow::Result<UpdateResult> {
+    if !cfg!(unix) {
+        anyhow::bail!("Self-update is only supported on Unix");
+    }
+    let config = state.config();
+    let cfg = config
+        .update
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Self-update is not configured"))?;
+    if config.privileges.is_some() {
+        anyhow::bail!(
+            "Self-update is disabled when privileges is configured; install new binaries with the system package manager"
+        );
+    }
+    let public_key = parse_public_key(&cfg.public_key)?;
+    let url = source.url.unwrap_or_else(|| cfg.url.clone());
+    let manifest_url = source
+        .manifest_url
+        .or_else(|| cfg.manifest_url.clone())
+        .unwrap_or_else(|| format!("{}.manifest", url));
+    let signature_url = source
+        .signature_url
+        .or_else(|| cfg.signature_url.clone())
+        .unwrap_or_else(|| format!("{}.sig", manifest_url));
+
+    if UPDATING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("An update is already in progress");
+    }
+    let result = install(state, &url, &manifest_url, &signature_url, &public_key).await;
+    if result.is_err() {
+        UPDATING.store(false, Ordering::SeqCst);
+    }
+    result
+}
+
+/// 解析十六进制 Ed25519 公钥
+pub fn parse_public_key(text: &str) -> anyhow::Result<[u8; 32]> {
+    decode_hex(text.trim())
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("update.public_key must be 64 hex characters"))
+}
+
+/// 校验清单的签名、目标平台与版本 (必须高于 `current_version`)
+pub fn verify_manifest(
+    public_key: &[u8; 32],
+    data: &[u8],
+    signature: &[u8; 64],
+    target: &str,
+    current_version: &str,
+) -> anyhow::Result<Manifest> {
+    if !ed25519::verify(public_key, data, signature) {
+        anyhow::bail!("Signature verification failed for the release manifest");
+    }
+    let manifest: Manifest = serde_json::from_slice(data)
+        .map_err(|e| anyhow::anyhow!("Invalid release manifest: {}", e))?;
+    if manifest.target != target {
+        anyhow::bail!(
+            "Release is built for {}, this gateway runs on {}",
+            manifest.target,
+            target
+        );
+    }
+    match compare_versions(&manifest.version, current_version) {
+        Some(CmpOrdering::Greater) => Ok(manifest),
+        Some(_) => anyhow::bail!(
+            "Release {} is not newer than the running version {}; downgrades are refused",
+            manifest.version,
+            current_version
+        ),
+        None => anyhow::bail!("Invalid version in release manifest: {}", manifest.version),
+    }
+}
+
+/// 比较 `主.次.修订[-预发布]` 形式的版本号，预发布版本低于同号的正式版本
+fn compare_versions(a: &str, b: &str) -> Option<CmpOrdering> {
+    fn parse(v: &str) -> Option<([u64; 3], Option<&str>)> {
+        let (core, pre) = match v.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (v, None),
+        };
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let numbers = [parts.next()??, parts.next()??, parts.next()??];
+        parts.next().is_none().then_some((numbers, pre))
+    }
+    let (a_num, a_pre) = parse(a)?;
+    let (b_num, b_pre) = parse(b)?;
+    Some(a_num.cmp(&b_num).then(match (a_pre, b_pre) {
+        (None, None) => CmpOrdering::Equal,
+        (None, Some(_)) => CmpOrdering::Greater,
+        (Some(_), None) => CmpOrdering::Less,
+        (Some(x), Some(y)) => x.cmp(y),
+    }))
+}
+
+async fn install(
+    state: &Arc<AppState>,
+    url: &str,
+    manifest_url: &str,
+    signature_url: &str,
+    public_key: &[u8; 32],
+) -> anyhow::Result<UpdateResult> {
+    info!("Self-update: checking {}", manifest_url);
+    let manifest = download(manifest_url).await?;
+    let signature = parse_signature(&download(signature_url).await?)?;
+    let manifest = verify_manifest(
+        public_key,
+        &manifest,
+        &signature,
+        &current_target(),
+        env!("CARGO_PKG_VERSION"),
+    )?;
+
+    info!(
+        "Self-update: downloading {} (version {})",
+        url, manifest.version
+    );
+    let binary = download(url).await?;
+    let expected = decode_hex(manifest.sha256.trim())
+        .ok_or_else(|| anyhow::anyhow!("Invalid sha256 in release manifest"))?;
+    if hash::sha256(&binary)[..] != expected[..] {
+        anyhow::bail!("{} does not match the digest in the release manifest", url);
+    }
+
+    let exe = std::env::current_exe()?;
+    let staged = sibling(&exe, "new");
+    let backup = sibling(&exe, "old");
+    tokio::fs::write(&staged, &binary).await?;
+    set_executable(&staged).await?;
+    tokio::fs::copy(&exe, &backup).await?;
+    tokio::fs::rename(&staged, &exe).await?;
+    info!(
+        "Self-update: installed version {} ({} bytes) to {:?} (previous binary kept at {:?})",
+        manifest.version,
+        binary.len(),
+        exe,
+        backup
+    );
+
+    tokio::spawn(restart(state.clone(), exe.clone()));
+    Ok(UpdateResult {
+        version: manifest.version,
+        installed: exe,
+        backup,
+        size: binary.len(),
+        restarting: true,
+    })
+}
+
+async fn download(url: &str) -> anyhow::Result<Vec<u8>> {
+    let res = http_client::request("GET", url, &[], None, DOWNLOAD_TIMEOUT).await?;
+    if !res.is_success() {
+        anyhow::bail!("Download of {} failed with status {}", url, res.status);
+    }
+    if res.body.is_empty() {
+        anyhow::bail!("Download of {} is empty", url);
+    }
+    Ok(res.body)
+}
+
+/// 签名为 64 字节原始数据或 128 位十六进制文本
+fn parse_signature(data: &[u8]) -> anyhow::Result<[u8; 64]> {
+    if let Ok(raw) = <[u8; 64]>::try_from(data) {
+        return Ok(raw);
+    }
+    std::str::from_utf8(data)
+        .ok()
+        .and_then(|text| decode_hex(text.trim()))
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid signature file"))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// 与可执行文件同目录的 `{name}.{suffix}`，保证 rename 在同一文件系统内完成
+fn sibling(exe: &Path, suffix: &str) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    exe.with_file_name(name)
+}
+
+#[cfg(unix)]
+async fn set_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).await
+}
+
+#[cfg(not(unix))]
+async fn set_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// 停止所有流后以相同参数重新执行新版本 (进程号不变)
+async fn restart(state: Arc<AppState>, exe: PathBuf) {
+    tokio::time::sleep(RESTART_DELAY).await;
+    let running: Vec<String> = state
+        .active_streams
+        .lock_or_recover()
+        .keys()
+        .cloned()
+        .collect();
+    for name in running {
+        if let Err(e) = Engine::stop_stream(&state, &name).await {
+            warn!("Self-update: failed to stop stream [{}]: {}", name, e);
+        }
+    }
+    info!("Self-update: restarting {:?}", exe);
+    let err = exec(&exe);
+    error!("Self-update: exec failed: {}", err);
+    UPDATING.store(false, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn exec(exe: &Path) -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+    std::process::Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .exec()
+}
+
+#[cfg(not(unix))]
+fn exec(_exe: &Path) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "exec is not supported")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 8032 TEST 1 的密钥对签名的清单，`sha256` 为 "new binary" 的摘要
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const MANIFEST: &str = r#"{"version":"0.3.0","target":"x86_64-linux","sha256":"2f17c9ffb972a6c5da72c2b3df01f7e2ccf52dad2c0059dac631232a15126d2e"}"#;
+    const SIGNATURE: &str = "feaffd084c46140f48eac05712fa8eb01493e352c25bff88d75051ae5419f288cfaba45dd7cdd93fbcd5fd496973ae3d1aae503c069e0457f450a71491027e0b";
+
+    fn check(manifest: &str, target: &str, current: &str) -> anyhow::Result<Manifest> {
+        let public_key = parse_public_key(PUBLIC_KEY).unwrap();
+        let signature = parse_signature(SIGNATURE.as_bytes()).unwrap();
+        verify_manifest(
+            &public_key,
+            manifest.as_bytes(),
+            &signature,
+            target,
+            current,
+        )
+    }
+
+    #[test]
+    fn accepts_signed_upgrade() {
+        let manifest = check(MANIFEST, "x86_64-linux", "0.2.0").unwrap();
+        assert_eq!(manifest.version, "0.3.0");
+        let digest = decode_hex(&manifest.sha256).unwrap();
+        assert_eq!(hash::sha256(b"new binary")[..], digest[..]);
+    }
+
+    #[test]
+    fn refuses_replay_and_tampering() {
+        // 重新安装当前版本或降级
+        assert!(check(MANIFEST, "x86_64-linux", "0.3.0").is_err());
+        assert!(check(MANIFEST, "x86_64-linux", "1.0.0").is_err());
+        // 其他平台
+        assert!(check(MANIFEST, "aarch64-linux", "0.2.0").is_err());
+        // 改动清单内容后签名不再有效
+        let tampered = MANIFEST.replace("0.3.0", "9.9.9");
+        assert!(check(&tampered, "x86_64-linux", "0.2.0").is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_under_privilege_separation() {
+        let yaml = format!(
+            "server:\n  listen: 127.0.0.1:0\n  ffmpeg_binary: ffmpeg\n  supervisor_interval_ms: 1000\nstreams: []\nupdate:\n  url: http://127.0.0.1:1/vtx-link\n  public_key: {}\nprivileges:\n  ffmpeg_user: nobody\n",
+            PUBLIC_KEY
+        );
+        let config: crate::config::AppConfig = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+        let state = Arc::new(AppState::new(config, "config.yaml".into()).unwrap());
+        let err = update(&state, Source::default()).await.unwrap_err();
+        assert!(err.to_string().contains("privileges"), "{}", err);
+    }
+
+    #[test]
+    fn orders_versions() {
+        use CmpOrdering::*;
+        assert_eq!(compare_versions("0.3.0", "0.2.9"), Some(Greater));
+        assert_eq!(compare_versions("0.10.0", "0.9.0"), Some(Greater));
+        assert_eq!(compare_versions("1.0.0", "1.0.0"), Some(Equal));
+        assert_eq!(compare_versions("1.0.0-rc1", "1.0.0"), Some(Less));
+        assert_eq!(compare_versions("1.0.0", "1.0.0-rc1"), Some(Greater));
+        assert_eq!(compare_versions("1.0.0-rc2", "1.0.0-rc1"), Some(Greater));
+        assert_eq!(compare_versions("1.0", "0.9.0"), None);
+        assert_eq!(compare_versions("1.0.0.1", "0.9.0"), None);
+        assert_eq!(compare_versions("v1.0.0", "0.9.0"), None);
+    }
+}
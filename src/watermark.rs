@@ -0,0 +1,257 @@
+use crate::clock;
+use crate::config::{AppConfig, StreamConfig, WatermarkConfig};
+use crate::hash;
+use crate::state::{AppState, LockExt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// 对外提供的个性化播放列表
+pub const SECURE_PLAYLIST: &str = "secure.m3u8";
+
+/// 两个水印版本的播放列表 (FFmpeg 输出，不直接对外提供)
+const VARIANT_PLAYLISTS: [&str; 2] = ["wm_a.m3u8", "wm_b.m3u8"];
+
+/// 超过该时长未活跃的水印码从内存中淘汰 (分配日志仍保留)
+const CODE_RETENTION: Duration = Duration::from_secs(6 * 3600);
+
+/// 已分配的水印码
+#[derive(Debug, Clone, Serialize)]
+pub struct WatermarkCode {
+    /// 64 位水印码 (十六进制)，第 n 号切片使用第 `n % 64` 位选择 A/B 版本
+    pub code: String,
+    pub stream: String,
+    /// 观看者身份：令牌摘要或观看会话标识 (地址 + User-Agent 摘要)
+    pub viewer: String,
+    /// 分配时间 (RFC 3339)
+    pub assigned_at: String,
+    #[serde(skip)]
+    last_seen: Instant,
+}
+
+/// 生成两路画面标记位置不同的转码输出 (`wm_a` / `wm_b`)
+///
+/// 两路强制在相同时间点插入关键帧，保证切片边界与编号一致，可逐片混排
+pub fn output_args(
+    cfg: &StreamConfig,
+    wm: &WatermarkConfig,
+    output_dir: &Path,
+    key_args: &[String],
+    hls_flags: &str,
+) -> Vec<String> {
+    let duration = cfg.hls.segment_duration_sec;
+    let mut args = Vec::new();
+    for (variant, x) in [("a", "iw*0.02"), ("b", "iw*0.97")] {
+        args.extend(key_args.iter().cloned());
+        args.extend([
+            "-map".to_string(),
+            "0:v:0".to_string(),
+            "-map".to_string(),
+            "0:a:0?".to_string(),
+            "-vf".to_string(),
+            format!(
+                "drawbox=x={}:y=ih*0.02:w=iw*0.01:h=iw*0.01:color=white@{}:t=fill",
+                x, wm.opacity
+            ),
+            "-c:v".to_string(),
+            "libx264".to_string(),
+            "-preset".to_string(),
+            "veryfast".to_string(),
+            "-b:v".to_string(),
+            format!("{}k", wm.bitrate_kbps),
+            "-force_key_frames".to_string(),
+            format!("expr:gte(t,n_forced*{})", duration),
+            "-c:a".to_string(),
+            "aac".to_string(),
+            "-f".to_string(),
+            "hls".to_string(),
+            "-hls_time".to_string(),
+            duration.to_string(),
+            "-hls_list_size".to_string(),
+            cfg.hls.list_size.to_string(),
+            "-hls_flags".to_string(),
+            hls_flags.to_string(),
+            "-hls_segment_filename".to_string(),
+            output_dir
+                .join(format!("wm_{}_%d.ts", variant))
+                .to_string_lossy()
+                .to_string(),
+            output_dir
+                .join(format!("wm_{}.m3u8", variant))
+                .to_string_lossy()
+                .to_string(),
+        ]);
+    }
+    args
+}
+
+/// 个性化播放列表的来源 (A 版本，切片编号与 B 版本一致)
+pub fn source_playlist() -> &'static str {
+    VARIANT_PLAYLISTS[0]
+}
+
+/// 是否为不直接对外提供的水印版本播放列表
+pub fn is_variant_playlist(file_name: &str) -> bool {
+    VARIANT_PLAYLISTS.contains(&file_name)
+}
+
+/// 观看者的水印身份：优先使用令牌 (取摘要，不记录原文)，否则使用观看会话标识
+pub fn identity(token: Option<&str>, viewer_id: &str) -> String {
+    match token {
+        Some(token) => {
+            let digest: String = hash::sha1(token.as_bytes())[..8]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            format!("token:{}", digest)
+        }
+        None => viewer_id.to_string(),
+    }
+}
+
+/// 取得 (必要时分配) 观看者的水印码，首次分配时写入分配日志
+pub async fn assign(state: &AppState, cfg: &StreamConfig, viewer: &str) -> u64 {
+    let mut input = state.watermark_secret.to_vec();
+    input.extend_from_slice(cfg.name.as_bytes());
+    input.push(0);
+    input.extend_from_slice(viewer.as_bytes());
+    let digest = hash::sha1(&input);
+    let code = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    let key = format!("{:016x}", code);
+
+    let assigned = {
+        let mut codes = state.watermark_codes.lock_or_recover();
+        let now = Instant::now();
+        codes.retain(|_, c| now.duration_since(c.last_seen) < CODE_RETENTION);
+        match codes.get_mut(&key) {
+            Some(existing) => {
+                existing.last_seen = now;
+                None
+            }
+            None => {
+                let entry = WatermarkCode {
+                    code: key.clone(),
+                    stream: cfg.name.clone(),
+                    viewer: viewer.to_string(),
+                    assigned_at: clock::rfc3339(SystemTime::now()),
+                    last_seen: now,
+                };
+                codes.insert(key, entry.clone());
+                Some(entry)
+            }
+        }
+    };
+
+    if let Some(entry) = assigned {
+        info!(
+            "Stream [{}] watermark code {} assigned to {}",
+            entry.stream, entry.code, entry.viewer
+        );
+        if let Err(e) = append_log(&state.config(), &entry).await {
+            warn!("Failed to write watermark log [{}]: {}", entry.stream, e);
+        }
+    }
+    code
+}
+
+/// 改写 A 版本播放列表：第 n 号切片按水印码第 `n % 64` 位选择 A 或 B，并附带水印码
+pub fn personalize(text: &str, code: u64) -> String {
+    let mut out = String::with_capacity(text.len() + 256);
+    for line in text.lines() {
+        match segment_number(line.trim(), "a") {
+            Some(n) => out.push_str(&format!(
+                "wm_{}_{}.ts?wm={:016x}",
+                variant_for(code, n),
+                n,
+                code
+            )),
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// 校验水印切片请求：水印码必须已分配给该流，且版本与水印码对应的位一致
+pub fn check_segment(state: &AppState, stream: &str, file_name: &str, code: Option<&str>) -> bool {
+    let Some(code) = code else {
+        return false;
+    };
+    let Ok(value) = u64::from_str_radix(code, 16) else {
+        return false;
+    };
+    let issued = state
+        .watermark_codes
+        .lock_or_recover()
+        .get(code)
+        .is_some_and(|c| c.stream == stream);
+    if !issued {
+        return false;
+    }
+    ["a", "b"].iter().any(|variant| {
+        segment_number(file_name, variant).is_some_and(|n| variant_for(value, n) == *variant)
+    })
+}
+
+/// 是否为水印版本的切片
+pub fn is_variant_segment(file_name: &str) -> bool {
+    segment_number(file_name, "a").is_some() || segment_number(file_name, "b").is_some()
+}
+
+/// 流的水印码分配记录 (内存中仍保留的部分)
+pub fn codes(state: &AppState, stream: &str) -> Vec<WatermarkCode> {
+    let mut codes: Vec<_> = state
+        .watermark_codes
+        .lock_or_recover()
+        .values()
+        .filter(|c| c.stream == stream)
+        .cloned()
+        .collect();
+    codes.sort_by(|a, b| a.assigned_at.cmp(&b.assigned_at));
+    codes
+}
+
+/// 水印码分配日志路径 (位于录像目录，跨重启保留)
+pub fn log_path(config: &AppConfig, stream: &str) -> PathBuf {
+    Path::new(&config.server.record_root)
+        .join("watermarks")
+        .join(format!("{}.log", stream))
+}
+
+async fn append_log(config: &AppConfig, entry: &WatermarkCode) -> std::io::Result<()> {
+    let path = log_path(config, &entry.stream);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    let line = format!(
+        "{} code={} viewer={}\n",
+        entry.assigned_at, entry.code, entry.viewer
+    );
+    file.write_all(line.as_bytes()).await
+}
+
+fn variant_for(code: u64, segment: u64) -> &'static str {
+    if (code >> (segment % 64)) & 1 == 1 {
+        "b"
+    } else {
+        "a"
+    }
+}
+
+/// 解析 `wm_{variant}_{n}.ts` 中的切片编号
+fn segment_number(file_name: &str, variant: &str) -> Option<u64> {
+    file_name
+        .strip_prefix("wm_")?
+        .strip_prefix(variant)?
+        .strip_prefix('_')?
+        .strip_suffix(".ts")?
+        .parse()
+        .ok()
+}
@@ -1,10 +1,55 @@
-use crate::engine::Engine;
-use crate::state::SharedState;
+use crate::abr;
+use crate::alerts::{self, FiringAlert};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{ApiPrincipal, Principal};
+use crate::availability;
+use crate::clock;
+use crate::config::StreamConfig;
+use crate::converge;
+use crate::counters;
+use crate::discovery::{self, Credentials};
+use crate::drain;
+use crate::engine::{Engine, StartOutcome};
+use crate::error::VtxError;
+use crate::failure;
+use crate::gc;
+use crate::geoip;
+use crate::gpu;
+use crate::health::{self, Readiness};
+use crate::maintenance::{self, DisabledStream};
+use crate::markers::{self, CueKind, CueMarker};
+use crate::metrics;
+use crate::migrate;
+use crate::nettest;
+use crate::origin_push;
+use crate::platform;
+use crate::playout;
+use crate::runtime_state::{self, Intent};
+use crate::share;
+use crate::snapshot;
+use crate::start_tasks;
+use crate::state::{LockExt, SharedState};
+use crate::system::SystemStats;
+use crate::templates;
+use crate::tenant;
+use crate::timelapse;
+use crate::tokens;
+use crate::tuning;
+use crate::updater;
+use crate::watermark;
 use axum::{
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
     Json,
 };
-use std::time::Instant;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+use tracing::{error, info};
 
 /// 提供内嵌的管理后台页面
 /// 该处理函数返回嵌入的 HTML 页面，用于管理界面
@@ -12,84 +57,1475 @@ pub async fn index_handler() -> axum::response::Html<&'static str> {
     axum::response::Html(include_str!("../../static/index.html"))
 }
 
+/// 存活探测 API (无需令牌)
+/// Supervisor 持续检查时返回 200，停止检查时返回 503，附带最近一轮检查的时间
+pub async fn healthz(State(state): State<SharedState>) -> (StatusCode, Json<serde_json::Value>) {
+    let health = state.supervisor_health.lock_or_recover().clone();
+    let stalled = health.is_stalled(state.config().server.supervisor_interval_ms);
+    let status = if stalled {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    let mut supervisor = serde_json::to_value(&health).unwrap_or_default();
+    supervisor["age_ms"] = serde_json::json!(health.age().map(|a| a.as_millis() as u64));
+    (
+        status,
+        Json(serde_json::json!({
+            "status": if stalled { "stalled" } else { "ok" },
+            "supervisor": supervisor,
+        })),
+    )
+}
+
+/// 就绪探测 API (无需令牌)
+/// 所有依赖检查通过时返回 200，否则返回 503，响应体列出每一项检查结果
+pub async fn readyz(State(state): State<SharedState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = health::readiness(&state).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
 /// 获取系统状态 API
-/// 该处理函数返回系统的内存和负载信息，作为 JSON 响应
-pub async fn sys_status() -> Json<serde_json::Value> {
-    // 获取内存信息，默认值为 0
-    let mem = sys_info::mem_info()
-        .map(|m| (m.total, m.avail))
-        .unwrap_or((0, 0));
-    // 获取负载信息，默认值为 0.0
-    let load = sys_info::loadavg().map(|l| l.one).unwrap_or(0.0);
-
-    // 返回系统的内存和负载状态
+/// 该处理函数返回系统的内存和负载信息，以及最近一次时钟核对结果，作为 JSON 响应
+pub async fn sys_status(
+    State(state): State<SharedState>,
+    _principal: ApiPrincipal,
+) -> Json<serde_json::Value> {
+    let mut stats = serde_json::to_value(SystemStats::collect()).unwrap_or_default();
+    stats["clock"] = serde_json::json!(*state.clock_check.lock_or_recover());
+    stats["drain"] = serde_json::json!(*state.drain.lock_or_recover());
+    stats["transfers"] = serde_json::json!(state.transfers.snapshot());
+    stats["alerts"] = serde_json::json!(*state.alerts.lock_or_recover());
+    stats["shed_streams"] = serde_json::json!(state.load_shedding.lock_or_recover().streams);
+    stats["gpu"] = serde_json::json!({
+        "encode_sessions": gpu::active_sessions(&state),
+        "max_encode_sessions": state.config().hwaccel.max_encode_sessions,
+        "devices": *state.gpu_devices.lock_or_recover(),
+    });
+    Json(stats)
+}
+
+/// 排行榜中列出的流数
+const TOP_STREAMS: usize = 5;
+
+/// 统计概览 API
+/// 汇总调用方可见流的运行数、观看人数、出口带宽与最近一小时的崩溃次数，
+/// 附带切片与录像目录的磁盘用量和带宽最高的流，供大屏直接展示
+pub async fn stats_summary(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Json<serde_json::Value> {
+    let statuses: Vec<_> = state
+        .stream_statuses()
+        .into_iter()
+        .filter(|s| principal.can_access(s.tenant.as_deref()))
+        .collect();
+    let running = statuses
+        .iter()
+        .filter(|s| matches!(s.status, "running" | "degraded" | "standby"))
+        .count();
+    let viewers: usize = statuses.iter().map(|s| s.viewers).sum();
+
+    let mut bandwidth: Vec<(String, u64, usize)> = {
+        let meters = state.egress_meters.lock_or_recover();
+        statuses
+            .iter()
+            .map(|s| {
+                let bps = meters.get(&s.name).map_or(0, |m| m.rate_bps());
+                (s.name.clone(), bps, s.viewers)
+            })
+            .collect()
+    };
+    let egress_bps: u64 = bandwidth.iter().map(|(_, bps, _)| bps).sum();
+    bandwidth.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let top: Vec<_> = bandwidth
+        .into_iter()
+        .filter(|(_, bps, _)| *bps > 0)
+        .take(TOP_STREAMS)
+        .map(|(name, bps, viewers)| {
+            serde_json::json!({ "stream": name, "egress_bps": bps, "viewers": viewers })
+        })
+        .collect();
+
+    // 退出记录按流保留最近 failure::EXIT_HISTORY 条，频繁崩溃的流可能少计
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        .saturating_sub(3600);
+    let crashes = {
+        let recovery = state.recovery_states.lock_or_recover();
+        statuses
+            .iter()
+            .filter_map(|s| recovery.get(&s.name))
+            .flat_map(|r| r.exits.iter())
+            .filter(|e| e.cause == "crashed")
+            .filter(|e| clock::parse_rfc3339(&e.time).is_some_and(|t| t >= since))
+            .count()
+    };
+
+    let config = state.config();
+    let storage: Vec<_> = [
+        ("hls", &config.server.hls_root),
+        ("record", &config.server.record_root),
+    ]
+    .into_iter()
+    .filter_map(|(kind, path)| {
+        let (total, available) = platform::disk_space(std::path::Path::new(path))?;
+        let used = total.saturating_sub(available);
+        Some(serde_json::json!({
+            "kind": kind,
+            "path": path,
+            "total_bytes": total,
+            "available_bytes": available,
+            "used_percent": match total {
+                0 => 0.0,
+                t => (used as f64 * 1000.0 / t as f64).round() / 10.0,
+            },
+        }))
+    })
+    .collect();
+
     Json(serde_json::json!({
-        "mem_total": mem.0 / 1024, // 转换为MB
-        "mem_avail": mem.1 / 1024, // 转换为MB
-        "load_avg": load,
+        "streams": { "configured": statuses.len(), "running": running },
+        "viewers": viewers,
+        "egress_bps": egress_bps,
+        "crashes_last_hour": crashes,
+        "storage": storage,
+        "top_bandwidth": top,
     }))
 }
 
+/// 测速请求参数
+#[derive(Debug, Deserialize)]
+pub struct NetTestQuery {
+    /// `junk` 时直接返回生成的数据供对方测速，缺省向配置的对端测速
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub direction: nettest::Direction,
+    /// 传输字节数，缺省取 nettest.bytes
+    pub bytes: Option<u64>,
+}
+
+/// 网络测速 API
+/// 缺省向 nettest 配置的对端依次测试下行与上行并返回结果；
+/// `mode=junk` 时返回指定字节数的生成数据，供另一台网关或浏览器测量到本机的下行速率
+pub async fn nettest(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Query(query): Query<NetTestQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let config = state.config();
+    let default_bytes = config
+        .nettest
+        .as_ref()
+        .map_or(10 * 1024 * 1024, |n| n.bytes);
+    let bytes = query.bytes.unwrap_or(default_bytes);
+    if bytes == 0 || bytes > nettest::MAX_BYTES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("bytes must be between 1 and {}", nettest::MAX_BYTES),
+        ));
+    }
+
+    match query.mode.as_deref() {
+        Some("junk") => {
+            return Ok(Response::builder()
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_LENGTH, bytes)
+                .header(header::CACHE_CONTROL, "no-store")
+                .body(nettest::junk_body(bytes))
+                .unwrap());
+        }
+        None | Some("endpoint") => {}
+        Some(other) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown nettest mode: {}", other),
+            ))
+        }
+    }
+
+    let Some(cfg) = &config.nettest else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "nettest is not configured; use mode=junk to test against this gateway".to_string(),
+        ));
+    };
+    let Some(_guard) = nettest::try_begin() else {
+        return Err((
+            StatusCode::CONFLICT,
+            "A network test is already running".to_string(),
+        ));
+    };
+    info!(
+        "Running network test ({:?}, {} bytes)",
+        query.direction, bytes
+    );
+    let report = nettest::run(cfg, query.direction, bytes)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(Json(report).into_response())
+}
+
+/// 网络测速上传 API
+/// 读取并丢弃请求体，返回上行速率 (由发送方的网关或浏览器发起)
+pub async fn nettest_upload(
+    ApiPrincipal(principal): ApiPrincipal,
+    body: Body,
+) -> Result<Json<nettest::Throughput>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    nettest::receive(body)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// 排空请求参数 (均可省略，默认取 server.drain_* 配置)
+#[derive(Debug, Deserialize, Default)]
+pub struct DrainRequest {
+    pub alternate_url: Option<String>,
+    pub retry_after_sec: Option<u64>,
+}
+
+/// 进入维护排空 API
+/// 拒绝新的播放列表与 MPEG-TS 请求 (503 + Retry-After)，观看者播完缓冲后自然离开
+pub async fn handle_drain(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    body: Option<Json<DrainRequest>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    if let Some(url) = &req.alternate_url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "alternate_url must be an http(s) URL".to_string(),
+            ));
+        }
+    }
+    let drain = drain::start(&state, req.alternate_url, req.retry_after_sec);
+    Ok(Json(serde_json::json!({ "drain": drain })))
+}
+
+/// 恢复服务 API
+pub async fn handle_undrain(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let was_draining = drain::stop(&state);
+    Ok(Json(serde_json::json!({ "was_draining": was_draining })))
+}
+
+/// 清理请求参数
+#[derive(Debug, Deserialize)]
+pub struct GcQuery {
+    /// 只列出将被清理的目录
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// 清理残留目录 API
+/// 立即执行一次 hls_root 清理并返回删除的目录与回收的字节数
+pub async fn handle_gc(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Query(query): Query<GcQuery>,
+) -> Result<Json<gc::GcReport>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    gc::run(&state, query.dry_run)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// 配置导入导出与自更新可读取全部凭据、改写配置文件 (如 `server.ffmpeg_binary`) 或替换可执行文件，
+/// 只接受开启了 `auth.protect_api` 时的管理员令牌；未开启保护时所有调用方都被视为管理员，一律拒绝
+fn require_protected_admin(
+    state: &SharedState,
+    principal: &Principal,
+) -> Result<(), (StatusCode, String)> {
+    if !state.config().auth.protect_api {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "This endpoint requires auth.protect_api to be enabled".to_string(),
+        ));
+    }
+    if *principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    Ok(())
+}
+
+/// 自更新 API
+/// 下载并校验签名后替换可执行文件，返回结果后重新执行
+pub async fn handle_update(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    body: Option<Json<updater::Source>>,
+) -> Result<Json<updater::UpdateResult>, (StatusCode, String)> {
+    require_protected_admin(&state, &principal)?;
+    let source = body.map(|Json(r)| r).unwrap_or_default();
+    updater::update(&state, source)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Self-update failed: {}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string())
+        })
+}
+
+/// 配置导出参数
+#[derive(Debug, Deserialize, Default)]
+pub struct ExportQuery {
+    /// `include` 时原样导出令牌与密码，默认屏蔽
+    pub secrets: Option<String>,
+    /// `yaml` 或 `json` (默认)
+    pub format: Option<String>,
+}
+
+/// 导出配置 API
+/// 返回当前生效的完整配置 (含运行中由控制器或导入下发的变更)
+pub async fn export_config(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    require_protected_admin(&state, &principal)?;
+    let include_secrets = query.secrets.as_deref() == Some("include");
+    let value = snapshot::export(&state.config(), include_secrets);
+    let (content_type, body) = match query.format.as_deref() {
+        Some("yaml") => (
+            "application/yaml",
+            serde_yaml::to_string(&value)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        ),
+        _ => (
+            "application/json",
+            serde_json::to_string_pretty(&value)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        ),
+    };
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap())
+}
+
+/// 导入配置 API
+/// 请求体为完整配置 (JSON 或 YAML)，屏蔽的敏感值保留当前值；校验通过后写回配置文件并替换
+pub async fn import_config(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    body: Bytes,
+) -> Result<Json<snapshot::ImportResult>, (StatusCode, String)> {
+    require_protected_admin(&state, &principal)?;
+    let _guard = state.config_lock.lock().await;
+    let next = snapshot::parse(&state.config(), &body).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid configuration: {}", e),
+        )
+    })?;
+    snapshot::apply(&state, &state.config_path, next)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
 /// 获取流列表 API
 /// 返回所有流的状态信息，包括每个流的运行时长和闲置时间
-pub async fn list_streams(State(state): State<SharedState>) -> Json<serde_json::Value> {
-    // 获取当前活跃流和恢复状态
-    let streams_map = state.active_streams.lock().unwrap();
-    let recovery_map = state.recovery_states.lock().unwrap();
-    let now = Instant::now();
+pub async fn list_streams(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Json<serde_json::Value> {
+    // 仅返回调用方可见的流
+    let result: Vec<_> = state
+        .stream_statuses()
+        .into_iter()
+        .filter(|s| principal.can_access(s.tenant.as_deref()))
+        .collect();
+
+    // 返回所有流的信息
+    Json(serde_json::json!({ "streams": result }))
+}
 
-    // 遍历配置文件中的流，生成每个流的状态信息
+/// 获取流详情 API
+/// 返回流的状态快照、最近的进程退出记录与冷启动耗时统计
+pub async fn stream_detail(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    let status = state
+        .stream_statuses()
+        .into_iter()
+        .find(|s| s.name == name && principal.can_access(s.tenant.as_deref()))
+        .ok_or((StatusCode::NOT_FOUND, "Stream not found".to_string()))?;
+    let (first_segment_ms, first_playlist_ms) = state
+        .active_streams
+        .lock_or_recover()
+        .get(&name)
+        .map(|r| {
+            (
+                r.first_segment.map(|d| d.as_millis() as u64),
+                r.first_playlist.map(|d| d.as_millis() as u64),
+            )
+        })
+        .unwrap_or_default();
+    let exits: Vec<_> = state
+        .recovery_states
+        .lock_or_recover()
+        .get(&name)
+        .map(|r| r.exits.iter().cloned().collect())
+        .unwrap_or_default();
+    let config = state.config();
+
+    Ok(Json(serde_json::json!({
+        "stream": status,
+        "start_task": start_tasks::get(&state, &name),
+        "mute": maintenance::muted(&state, &name),
+        "lifetime": counters::snapshot(&state, &name),
+        "viewers_by_country": geoip::viewer_countries(&state, &name),
+        "origin_push": origin_push::stats(&state, &name),
+        "abr": config.stream(&name).and_then(abr::layout),
+        "shared_source_owner": config
+            .stream(&name)
+            .and_then(|s| share::owner(&config, s))
+            .map(|o| &o.name),
+        "exits": exits,
+        "startup": {
+            "current": {
+                "first_segment_ms": first_segment_ms,
+                "first_playlist_ms": first_playlist_ms,
+            },
+            "history": metrics::startup(&state, &name),
+        },
+    })))
+}
+
+/// Prometheus 指标 API
+/// 以文本格式导出调用方可见流的状态与冷启动耗时直方图
+pub async fn metrics(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Response<Body> {
+    let body = metrics::render(&state, |tenant| principal.can_access(tenant));
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// 获取租户列表 API
+/// 返回调用方可见租户的配额与当前用量
+pub async fn list_tenants(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Json<serde_json::Value> {
     let result: Vec<_> = state
-        .config
-        .streams
+        .config()
+        .tenants
         .iter()
-        .map(|cfg| {
-            // 获取流的状态、闲置时间和运行时长
-            let (status, idle, uptime) = if let Some(running) = streams_map.get(&cfg.name) {
-                let idle_sec = now.duration_since(running.last_accessed).as_secs();
-                let uptime_sec = now.duration_since(running.started_at).as_secs();
-                ("running", idle_sec, uptime_sec)
-            } else {
-                ("stopped", 0, 0)
-            };
-
-            // 获取流的崩溃次数（如果有）
-            let crash_count = recovery_map
-                .get(&cfg.name)
-                .map(|r| r.crash_count)
-                .unwrap_or(0);
-
-            // 返回每个流的状态信息
+        .filter(|t| principal.can_access(Some(&t.name)))
+        .map(|t| {
             serde_json::json!({
-                "name": cfg.name,
-                "source": cfg.source,
-                "status": status,
-                "idle_seconds": idle,
-                "uptime_seconds": uptime,
-                "config_idle_timeout": cfg.idle_timeout,
-                "crash_count": crash_count
+                "name": t.name,
+                "running_streams": tenant::running_streams(&state, &t.name).len(),
+                "storage_mb": tenant::storage_usage(&state, &t.name) / 1024 / 1024,
+                "quota": {
+                    "max_streams": t.quota.max_streams,
+                    "max_bandwidth_kbps": t.quota.max_bandwidth_kbps,
+                    "max_storage_mb": t.quota.max_storage_mb,
+                },
             })
         })
         .collect();
 
-    // 返回所有流的信息
-    Json(serde_json::json!({ "streams": result }))
+    Json(serde_json::json!({ "tenants": result }))
+}
+
+/// 启动参数
+#[derive(Debug, Deserialize)]
+pub struct StartQuery {
+    /// 释放隔离并跳过崩溃退避，立即启动
+    #[serde(default)]
+    force: bool,
+    /// 后台启动，立即返回任务 ID (通过 GET /streams/:name 的 `start_task` 查询进度)
+    #[serde(default, rename = "async")]
+    background: bool,
 }
 
 /// 手动启动流 API
-/// 启动指定名称的流，并返回操作结果信息
-pub async fn handle_start(State(state): State<SharedState>, Path(name): Path<String>) -> String {
-    match Engine::start_stream(&state, &name).await {
-        Ok(_) => format!("Stream [{}] is active (started or refreshed)", name),
-        Err(e) => format!("Error: {}", e),
+///
+/// 新启动返回 201 `started`，已在运行返回 200 `already_running`；
+/// 被隔离 (409)、资源不足 (429) 或仍在退避期 (503，附下次重试时间) 时拒绝，`?force=true` 可强制启动。
+/// `?async=true` 时未运行的流在后台启动，立即返回 202 `starting` 与任务 ID
+pub async fn handle_start(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<StartQuery>,
+) -> Result<(StatusCode, Json<serde_json::Value>), VtxError> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    // 强制启动视为已处理隔离原因，并放弃等待退避
+    if query.force {
+        let released = state
+            .recovery_states
+            .lock_or_recover()
+            .get_mut(&name)
+            .is_some_and(|rec| {
+                rec.next_retry_at = None;
+                rec.quarantined.take().is_some()
+            });
+        if released {
+            info!("Stream [{}] released from quarantine", name);
+            runtime_state::save_or_warn(&state);
+        }
+    }
+    // 记录手动启动，网关重启后恢复
+    if state.config().stream(&name).is_some() {
+        runtime_state::set_intent(&state, &name, Some(Intent::Started));
     }
+    if query.background && !state.active_streams.lock_or_recover().contains_key(&name) {
+        if state.config().stream(&name).is_none() {
+            return Err(VtxError::ConfigNotFound(name));
+        }
+        let task = start_tasks::spawn(&state, &name);
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({
+                "stream": name,
+                "status": "starting",
+                "task_id": task.id,
+            })),
+        ));
+    }
+    let (status, outcome) = match Engine::start_stream(&state, &name).await? {
+        StartOutcome::Started => (StatusCode::CREATED, "started"),
+        StartOutcome::AlreadyRunning => (StatusCode::OK, "already_running"),
+    };
+    Ok((
+        status,
+        Json(serde_json::json!({ "stream": name, "status": outcome })),
+    ))
 }
 
 /// 手动停止流 API
 /// 停止指定名称的流，并返回操作结果信息
-pub async fn handle_stop(State(state): State<SharedState>, Path(name): Path<String>) -> String {
-    match Engine::stop_stream(&state, &name).await {
-        Ok(_) => format!("Stream [{}] stopped", name),
-        Err(e) => format!("Error: {}", e),
+pub async fn handle_stop(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<String, VtxError> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    // 记录手动停止，auto_start 流在再次手动启动前不会被自动拉起 (包括网关重启后)
+    if state.config().stream(&name).is_some() {
+        runtime_state::set_intent(&state, &name, Some(Intent::Stopped));
+    }
+    Engine::stop_stream(&state, &name).await?;
+    Ok(format!("Stream [{}] stopped", name))
+}
+
+/// 可用性查询参数
+#[derive(Debug, Deserialize)]
+pub struct AvailabilityQuery {
+    /// 统计区间，如 `24h`、`7d` (缺省 7d，最长 90d)
+    period: Option<String>,
+}
+
+/// 流可用性 API
+/// 根据启停事件日志统计最近一段时间的可用率与各次停机 (意外退出到下一次启动之间)
+pub async fn stream_availability(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<AvailabilityQuery>,
+) -> Result<Json<availability::Report>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    let config = state.config();
+    if config.stream(&name).is_none() {
+        return Err(VtxError::ConfigNotFound(name).into());
+    }
+    let period = query.period.as_deref().unwrap_or("7d");
+    let period_sec = availability::parse_period(period).ok_or((
+        StatusCode::BAD_REQUEST,
+        format!(
+            "Invalid period {:?} (expected e.g. 24h or 7d, at most 90d)",
+            period
+        ),
+    ))?;
+    Ok(Json(availability::report(&config, &name, period_sec)))
+}
+
+/// 日志查询参数
+#[derive(Debug, Deserialize)]
+pub struct LogsQuery {
+    /// 上次返回的 `next`，只返回其后的新日志
+    #[serde(default)]
+    after: u64,
+}
+
+/// 获取流日志 API
+/// 返回运行中 FFmpeg 进程 stderr 的最近若干行，配合 `after` 增量读取 (`vtx-link logs -f`)
+pub async fn stream_logs(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<LogsQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    if state.config().stream(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Stream not found".to_string()));
+    }
+    let tail = state
+        .active_streams
+        .lock_or_recover()
+        .get(&name)
+        .map(|r| r.stderr_tail.clone())
+        .ok_or((StatusCode::CONFLICT, "Stream not running".to_string()))?;
+    let (lines, next, dropped) = failure::lines_after(&tail, query.after);
+    Ok(Json(serde_json::json!({
+        "lines": lines,
+        "next": next,
+        "dropped": dropped,
+    })))
+}
+
+/// 运维禁用请求参数
+#[derive(Debug, Deserialize, Default)]
+pub struct DisableRequest {
+    /// 禁用原因 (如维护工单号)
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// 运维禁用流 API
+/// 停止流并标记为禁用：Supervisor 不再自动重启，观看请求返回 503，网关重启后仍保持禁用
+pub async fn handle_disable(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    body: Option<Json<DisableRequest>>,
+) -> Result<Json<DisabledStream>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    if state.config().stream(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Stream not found".to_string()));
+    }
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    maintenance::disable(&state, &name, req.reason)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// 恢复运维禁用的流 API
+pub async fn handle_enable(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    match maintenance::enable(&state, &name) {
+        Ok(true) => Ok(format!("Stream [{}] enabled", name)),
+        Ok(false) => Ok(format!("Stream [{}] was not disabled", name)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// 通知静默请求参数，`until` 与 `duration_sec` 二选一
+#[derive(Debug, Deserialize)]
+pub struct MuteRequest {
+    #[serde(default)]
+    until: Option<TimeArg>,
+    #[serde(default)]
+    duration_sec: Option<u64>,
+    /// 静默原因 (如维护工单号)
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// 静默流的告警通知 API
+/// 用于计划内维护：流照常运行与监控，告警照常触发与执行动作，只是不发送通知
+pub async fn handle_mute(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Json(req): Json<MuteRequest>,
+) -> Result<Json<maintenance::Mute>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    if state.config().stream(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Stream not found".to_string()));
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let until = match (&req.until, req.duration_sec) {
+        (Some(until), None) => until.to_unix(),
+        (None, Some(sec)) => now.checked_add(sec),
+        _ => None,
+    };
+    let Some(until) = until.filter(|t| *t > now) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Specify a future `until` or a positive `duration_sec`".to_string(),
+        ));
+    };
+    maintenance::mute(&state, &name, until, req.reason)
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// 结束通知静默 API
+pub async fn handle_unmute(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    match maintenance::unmute(&state, &name) {
+        Ok(true) => Ok(format!("Stream [{}] unmuted", name)),
+        Ok(false) => Ok(format!("Stream [{}] was not muted", name)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// 告警是否对调用方可见：租户只能看到其流的告警，没有 `stream` 标签的告警仅限管理员
+fn can_see_alert(state: &SharedState, principal: &Principal, alert: &FiringAlert) -> bool {
+    if *principal == Principal::Admin {
+        return true;
+    }
+    let config = state.config();
+    alert
+        .labels
+        .get("stream")
+        .and_then(|s| config.lookup(s))
+        .is_some_and(|cfg| principal.can_access(cfg.tenant.as_deref()))
+}
+
+/// 已触发的告警列表 API
+pub async fn list_alerts(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Json<serde_json::Value> {
+    let alerts: Vec<FiringAlert> = state
+        .alerts
+        .lock_or_recover()
+        .iter()
+        .filter(|a| can_see_alert(&state, &principal, a))
+        .cloned()
+        .collect();
+    Json(serde_json::json!({ "alerts": alerts }))
+}
+
+/// 告警确认请求参数
+#[derive(Debug, Deserialize, Default)]
+pub struct AckRequest {
+    /// 确认人
+    #[serde(default)]
+    by: Option<String>,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+/// 确认告警 API
+/// 确认后该告警直到解除 (包括解除时) 都不再发送通知，再次触发时重新通知
+pub async fn handle_ack(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(id): Path<String>,
+    body: Option<Json<AckRequest>>,
+) -> Result<Json<FiringAlert>, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, "Alert not found".to_string());
+    let visible = state
+        .alerts
+        .lock_or_recover()
+        .iter()
+        .any(|a| a.id == id && can_see_alert(&state, &principal, a));
+    if !visible {
+        return Err(not_found());
+    }
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    alerts::acknowledge(&state, &id, req.by, req.comment)
+        .map(Json)
+        .ok_or_else(not_found)
+}
+
+/// 审计日志查询参数
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    stream: Option<String>,
+    /// 令牌 ID 或自报的操作人
+    actor: Option<String>,
+    /// 起始时间 (含)，Unix 秒数或 RFC 3339
+    since: Option<String>,
+    /// 结束时间 (不含)
+    until: Option<String>,
+    /// 返回最近的条数，缺省 100 (导出时缺省全部)
+    limit: Option<usize>,
+    /// `jsonl` 时以 JSON Lines 导出
+    format: Option<String>,
+}
+
+/// 审计日志 API (仅管理员)
+/// 按时间顺序列出改变状态的 API 调用：调用方、接口与参数、时间和结果
+pub async fn list_audit(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Query(query): Query<AuditQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let time = |arg: &Option<String>, name: &str| -> Result<Option<u64>, (StatusCode, String)> {
+        arg.as_deref()
+            .map(|s| {
+                s.parse::<u64>()
+                    .ok()
+                    .or_else(|| clock::parse_rfc3339(s))
+                    .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Invalid {}: {}", name, s)))
+            })
+            .transpose()
+    };
+    let filter = audit::Filter {
+        since: time(&query.since, "since")?,
+        until: time(&query.until, "until")?,
+        stream: query.stream,
+        actor: query.actor,
+    };
+    let export = query.format.as_deref() == Some("jsonl");
+    let limit = query.limit.unwrap_or(if export { usize::MAX } else { 100 });
+    let entries: Vec<AuditEntry> = audit::query(&state.config(), &filter, limit)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if export {
+        let mut body = String::new();
+        for entry in &entries {
+            body.push_str(&serde_json::to_string(entry).unwrap_or_default());
+            body.push('\n');
+        }
+        return Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "application/x-ndjson")
+            .header(
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"audit.jsonl\"",
+            )
+            .body(Body::from(body))
+            .unwrap());
+    }
+    Ok(Json(serde_json::json!({ "entries": entries })).into_response())
+}
+
+/// 令牌列表 API (仅管理员)
+/// 列出配置中的与通过 API 签发的令牌及其使用统计，不返回令牌本身
+pub async fn list_tokens(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "tokens": tokens::list(&state) })))
+}
+
+/// 令牌签发请求参数
+#[derive(Debug, Deserialize, Default)]
+pub struct IssueTokenRequest {
+    /// 令牌所属租户，缺省为管理员令牌
+    #[serde(default)]
+    tenant: Option<String>,
+    /// 备注 (如持有者或用途)
+    #[serde(default)]
+    label: Option<String>,
+    /// 有效期 (秒)，缺省永不过期
+    #[serde(default)]
+    ttl_sec: Option<u64>,
+}
+
+/// 签发令牌 API (仅管理员)
+/// 立即生效，无需修改配置；令牌本身只在应答中返回这一次
+pub async fn issue_token(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    body: Option<Json<IssueTokenRequest>>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    if let Some(tenant) = &req.tenant {
+        if state.config().tenant(tenant).is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown tenant [{}]", tenant),
+            ));
+        }
+    }
+    if req.ttl_sec == Some(0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ttl_sec must be greater than 0".to_string(),
+        ));
+    }
+    let (issued, token) = tokens::issue(&state, req.tenant, req.label, req.ttl_sec)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let info = tokens::list(&state).into_iter().find(|t| t.id == issued.id);
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "token": token, "info": info })),
+    ))
+}
+
+/// 吊销令牌 API (仅管理员)
+/// 立即生效：之后携带该令牌的请求均被拒绝
+pub async fn revoke_token(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    match tokens::revoke(&state, &id) {
+        Ok(true) => Ok(Json(serde_json::json!({ "id": id, "revoked": true }))),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Token not found".to_string())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// 时间参数，可为 Unix 秒数或 RFC 3339 字符串
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TimeArg {
+    Unix(u64),
+    Text(String),
+}
+
+impl TimeArg {
+    fn to_unix(&self) -> Option<u64> {
+        match self {
+            TimeArg::Unix(t) => Some(*t),
+            TimeArg::Text(s) => clock::parse_rfc3339(s),
+        }
+    }
+}
+
+/// 延时视频请求参数
+#[derive(Debug, Deserialize)]
+pub struct TimelapseRequest {
+    from: TimeArg,
+    to: TimeArg,
+    /// 相对真实时间的倍速
+    speed: f64,
+}
+
+/// 生成延时视频 API
+/// 将时间范围内的截帧按倍速合成为 MP4 并直接返回
+pub async fn handle_timelapse(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Json(req): Json<TimelapseRequest>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    let cfg = state
+        .config()
+        .stream(&name)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, "Stream not found".to_string()))?;
+    let (Some(from), Some(to)) = (req.from.to_unix(), req.to.to_unix()) else {
+        return Err((StatusCode::BAD_REQUEST, "Invalid time".to_string()));
+    };
+
+    let path = timelapse::render(&state, &cfg, from, to, req.speed)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let file = File::open(&path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // 打开后即删除临时文件，响应结束时释放空间
+    let _ = tokio::fs::remove_file(&path).await;
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "video/mp4")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}-timelapse.mp4\"", name),
+        )
+        .body(Body::from_stream(ReaderStream::new(file)))
+        .unwrap())
+}
+
+/// 广告标记请求参数
+#[derive(Debug, Deserialize)]
+pub struct MarkerRequest {
+    #[serde(rename = "type")]
+    kind: CueKind,
+    /// 计划的广告时长 (秒，仅 cue_out)
+    #[serde(default)]
+    duration: Option<f64>,
+    /// 标记标识，缺省自动生成
+    #[serde(default)]
+    id: Option<String>,
+    /// SCTE-35 splice_info_section (十六进制，`0x` 开头)
+    #[serde(default)]
+    scte35: Option<String>,
+}
+
+/// 插入广告标记 API
+/// 在运行中流的下一个新切片前插入 CUE-OUT / CUE-IN (或 DATERANGE) 标记
+pub async fn handle_marker(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Json(req): Json<MarkerRequest>,
+) -> Result<Json<CueMarker>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    if state.config().stream(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Stream not found".to_string()));
+    }
+    let running = state.active_streams.lock_or_recover().contains_key(&name)
+        || state.proxy_sessions.lock_or_recover().contains_key(&name);
+    if !running {
+        return Err((StatusCode::CONFLICT, "Stream not running".to_string()));
+    }
+
+    let marker = markers::insert(&state, &name, req.kind, req.duration, req.id, req.scte35)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!(
+        "Stream [{}] marker {:?} [{}] queued",
+        name, marker.kind, marker.id
+    );
+    Ok(Json(marker))
+}
+
+/// 查询水印码分配 API
+/// 返回流近期分配的水印码及完整分配日志的位置，用于追溯泄露的录屏
+pub async fn list_watermarks(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    let config = state.config();
+    if config.stream(&name).is_none_or(|s| s.watermark.is_none()) {
+        return Err((StatusCode::NOT_FOUND, "Stream has no watermark".to_string()));
+    }
+    Ok(Json(serde_json::json!({
+        "codes": watermark::codes(&state, &name),
+        "log": watermark::log_path(&config, &name),
+    })))
+}
+
+/// 复制流请求参数
+#[derive(Debug, Deserialize)]
+pub struct CloneRequest {
+    /// 新流名称
+    name: String,
+    /// 覆盖的字段 (部分流配置，映射逐键合并)
+    #[serde(default)]
+    overrides: Option<serde_json::Value>,
+    /// 是否写回配置文件 (仅管理员)，默认只加入运行配置
+    #[serde(default)]
+    persist: bool,
+}
+
+/// 复制流 API
+/// 以现有流为基础生成新流，返回新流的配置
+pub async fn clone_stream(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Json(req): Json<CloneRequest>,
+) -> Result<Json<StreamConfig>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    let source = state
+        .config()
+        .stream(&name)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Stream not found".to_string()))?;
+    let stream = templates::clone_stream(&source, &req.name, req.overrides.as_ref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    add_stream(&state, &principal, stream, req.persist).await
+}
+
+/// 声明式配置请求参数
+#[derive(Debug, Deserialize)]
+pub struct ConvergeQuery {
+    /// 只计算差异，不应用 (如 Ansible 的 check 模式)
+    #[serde(default)]
+    dry_run: bool,
+    /// 只收敛该租户的流 (`PUT /config/streams`)，其他流不受影响
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+/// 声明式更新流 API
+/// 请求体 (JSON 或 YAML) 为流的完整期望配置，与当前配置一致时不做任何改动 (`changed: false`)；
+/// 有变化时写回配置文件并停止运行中的旧进程，新建时返回 201
+pub async fn put_stream(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<ConvergeQuery>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<converge::ConvergeResult>), (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let stream = converge::parse_stream(&name, &body).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid stream spec: {}", e),
+        )
+    })?;
+    let result = converge::converge(&state, vec![stream], |s| s.name == name, query.dry_run)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let status = if result.created.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((status, Json(result)))
+}
+
+/// 配置迁移请求参数
+#[derive(Debug, Deserialize)]
+pub struct ApplyProfileQuery {
+    /// 等待新进程产出有效播放列表的时间，超时后放弃迁移，旧进程继续运行
+    #[serde(default = "default_apply_timeout")]
+    timeout_sec: u64,
+}
+
+fn default_apply_timeout() -> u64 {
+    30
+}
+
+/// 无中断修改流配置 API
+/// 请求体 (JSON 或 YAML) 为覆盖的字段 (部分流配置，映射逐键合并)；运行中的流由按新配置启动的
+/// 进程在暂存目录中就绪后接替，期间旧进程持续提供服务，新进程未能就绪时返回 422 且配置不变
+pub async fn apply_profile(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<ApplyProfileQuery>,
+    body: Bytes,
+) -> Result<Json<migrate::MigrateResult>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let name = state
+        .config()
+        .lookup(&name)
+        .map(|s| s.name.clone())
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Stream not found".to_string()))?;
+    let overrides = converge::parse_body(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid profile: {}", e)))?;
+    if !overrides.is_object() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Profile must be a mapping".to_string(),
+        ));
+    }
+    let timeout = Duration::from_secs(query.timeout_sec.clamp(1, 300));
+    migrate::apply_profile(&state, &name, &overrides, timeout)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
+
+/// 修改 HLS 参数 API
+/// 请求体为 `segment_duration_sec` / `list_size`；`apply: next_restart` (默认) 只更新配置，运行中的进程
+/// 下次重启时生效，`apply: switch` 按新参数无中断地切换进程 (同 apply-profile)；写回配置文件需管理员令牌
+pub async fn patch_hls(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<ApplyProfileQuery>,
+    Json(req): Json<tuning::HlsTuning>,
+) -> Result<Json<tuning::TuningResult>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    if state.config().stream(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Stream not found".to_string()));
+    }
+    if (req.persist || req.apply == tuning::ApplyMode::Switch) && principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let timeout = Duration::from_secs(query.timeout_sec.clamp(1, 300));
+    tuning::update(&state, &name, req, timeout)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
+
+/// 删除流 API
+/// 从配置中移除流 (写回配置文件) 并停止其进程；流不存在时视为已删除 (`changed: false`)
+pub async fn delete_stream(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<ConvergeQuery>,
+) -> Result<Json<converge::ConvergeResult>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let name = state
+        .config()
+        .lookup(&name)
+        .map_or(name, |s| s.name.clone());
+    converge::converge(&state, Vec::new(), |s| s.name == name, query.dry_run)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// 声明式替换流集合 API
+/// 请求体为完整的期望流列表 (`{"streams": [...]}` 或列表)，服务端计算差异后新建、更新与删除流；
+/// 指定 `tenant` 时只收敛该租户的流，列表中未指定租户的流归入该租户
+pub async fn put_streams(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Query(query): Query<ConvergeQuery>,
+    body: Bytes,
+) -> Result<Json<converge::ConvergeResult>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let mut streams = converge::parse_streams(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid streams: {}", e)))?;
+    if let Some(tenant) = &query.tenant {
+        for stream in &mut streams {
+            match &stream.tenant {
+                None => stream.tenant = Some(tenant.clone()),
+                Some(other) if other != tenant => {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        format!(
+                            "Stream [{}] belongs to tenant [{}], not [{}]",
+                            stream.name, other, tenant
+                        ),
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let tenant = query.tenant;
+    converge::converge(
+        &state,
+        streams,
+        |s| tenant.is_none() || s.tenant == tenant,
+        query.dry_run,
+    )
+    .await
+    .map(Json)
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// 查询节目表 API
+/// 返回循环文件流的节目时段、垫片内容与当前应播放的节目
+pub async fn get_schedule(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<Json<playout::ScheduleView>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    let config = state.config();
+    let cfg = config
+        .stream(&name)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Stream not found".to_string()))?;
+    playout::view(cfg).map(Json).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Stream is not a file_loop channel".to_string(),
+        )
+    })
+}
+
+/// 修改节目表 API
+/// 替换节目时段 (可同时替换垫片与时区)，运行中的流按新节目表重启；`persist` 需管理员令牌
+pub async fn put_schedule(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Json(req): Json<playout::ScheduleUpdate>,
+) -> Result<Json<playout::ScheduleView>, (StatusCode, String)> {
+    let name = check_stream_access(&state, &principal, &name)?;
+    if state.config().stream(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Stream not found".to_string()));
+    }
+    if req.persist && principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    playout::update(&state, &name, req)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    get_schedule(State(state), ApiPrincipal(principal), Path(name)).await
+}
+
+/// 获取流模板列表 API
+pub async fn list_templates(
+    State(state): State<SharedState>,
+    _principal: ApiPrincipal,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "templates": state.config().stream_templates }))
+}
+
+/// 模板实例化请求参数
+#[derive(Debug, Deserialize)]
+pub struct InstantiateRequest {
+    /// 新流名称
+    name: String,
+    /// 模板参数 (如摄像机地址、标签)
+    #[serde(default)]
+    params: HashMap<String, String>,
+    #[serde(default)]
+    overrides: Option<serde_json::Value>,
+    #[serde(default)]
+    persist: bool,
+}
+
+/// 模板实例化 API
+/// 按参数填充流模板生成新流，返回新流的配置
+pub async fn instantiate_template(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(template): Path<String>,
+    Json(req): Json<InstantiateRequest>,
+) -> Result<Json<StreamConfig>, (StatusCode, String)> {
+    let config = state.config();
+    let template = config
+        .stream_templates
+        .iter()
+        .find(|t| t.name == template)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Template not found".to_string()))?;
+    let stream = templates::instantiate(template, &req.name, &req.params, req.overrides.as_ref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    add_stream(&state, &principal, stream, req.persist).await
+}
+
+/// 将复制或实例化生成的流加入配置
+///
+/// 租户令牌只能在本租户下添加流 (未指定租户时归入本租户)，且不能写回配置文件
+async fn add_stream(
+    state: &SharedState,
+    principal: &Principal,
+    mut stream: StreamConfig,
+    persist: bool,
+) -> Result<Json<StreamConfig>, (StatusCode, String)> {
+    if let (Principal::Tenant(own), None) = (principal, &stream.tenant) {
+        stream.tenant = Some(own.clone());
+    }
+    if !principal.can_access(stream.tenant.as_deref()) {
+        return Err((StatusCode::FORBIDDEN, "Tenant not accessible".to_string()));
+    }
+    if persist && *principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    if state.config().lookup(&stream.name).is_some() {
+        return Err((StatusCode::CONFLICT, "Stream already exists".to_string()));
+    }
+    templates::add(state, stream.clone(), persist)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(Json(stream))
+}
+
+/// 设备发现请求参数
+#[derive(Debug, Deserialize, Default)]
+pub struct ScanRequest {
+    /// 等待探测应答的时间 (毫秒)
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    /// 覆盖配置中的默认账号
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    /// 是否将候选流加入运行配置并写回配置文件
+    #[serde(default)]
+    auto_add: bool,
+    #[serde(default)]
+    name_prefix: Option<String>,
+}
+
+/// ONVIF 设备发现 API
+/// 扫描本地网段的摄像机并返回候选流配置，可选自动加入运行配置并写回配置文件
+pub async fn discovery_scan(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    body: Option<Json<ScanRequest>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let config = state.config();
+
+    let creds = Credentials {
+        username: req.username.or_else(|| config.discovery.username.clone()),
+        password: req.password.or_else(|| config.discovery.password.clone()),
+    };
+    let timeout = Duration::from_millis(req.timeout_ms.unwrap_or(3000).min(30_000));
+    let prefix = req.name_prefix.as_deref().unwrap_or("onvif_");
+
+    let devices = discovery::scan(timeout, &creds)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let candidates =
+        discovery::candidates(&devices, config.discovery.template.as_ref(), &creds, prefix)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    // 扫描耗时数秒，合并时以最新配置为准
+    let added = if req.auto_add {
+        discovery::add(&state, &candidates)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(serde_json::json!({
+        "devices": devices,
+        "candidates": candidates,
+        "added": added,
+    })))
+}
+
+/// 租户令牌只能操作本租户的流，对其他租户的流按不存在处理
+///
+/// 路径中可使用名称、稳定标识或别名，返回流的规范名称 (未找到时原样返回)
+fn check_stream_access(
+    state: &SharedState,
+    principal: &Principal,
+    name: &str,
+) -> Result<String, VtxError> {
+    match state.config().lookup(name) {
+        Some(cfg) if !principal.can_access(cfg.tenant.as_deref()) => {
+            Err(VtxError::ConfigNotFound(name.to_string()))
+        }
+        Some(cfg) => Ok(cfg.name.clone()),
+        None => Ok(name.to_string()),
     }
 }
@@ -0,0 +1,347 @@
+//! 故障演练接口 (仅在启用 `debug` 特性构建时提供)：结束或暂停 FFmpeg 进程、
+//! 为 HLS 应答注入延迟与错误，用于在测试环境验证 Supervisor 与播放器的故障处理
+
+use crate::auth::{ApiPrincipal, Principal};
+use crate::keys;
+use crate::platform;
+use crate::state::{LockExt, SharedState};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// 暂停进程的默认时长 (秒)
+const DEFAULT_STALL_SEC: u64 = 60;
+
+/// 暂停进程的最长时长 (秒)，超时后自动恢复，避免遗忘的演练一直占用流
+const MAX_STALL_SEC: u64 = 3600;
+
+/// 当前生效的 HLS 故障注入
+static FAULTS: Mutex<Option<ActiveFaults>> = Mutex::new(None);
+
+/// 流名称 -> 最近一次暂停的序号，自动恢复只作用于同一次暂停
+static STALLS: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+/// HLS 故障注入规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsFaults {
+    /// 只作用于该流，缺省作用于全部流
+    #[serde(default)]
+    pub stream: Option<String>,
+    /// 作用于播放列表、切片或全部 HLS 文件
+    #[serde(default)]
+    pub target: FaultTarget,
+    /// 每个应答附加的延迟 (毫秒)
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// 在延迟之上附加的随机抖动上限 (毫秒)
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// 以该概率 (0 ~ 1) 直接返回错误
+    #[serde(default)]
+    pub error_rate: f64,
+    /// 注入的错误状态码
+    #[serde(default = "default_error_status")]
+    pub error_status: u16,
+    /// 自动解除的时长 (秒)，缺省一直生效直到删除
+    #[serde(default)]
+    pub duration_sec: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FaultTarget {
+    #[default]
+    All,
+    Playlist,
+    Segment,
+}
+
+struct ActiveFaults {
+    rules: HlsFaults,
+    until: Option<Instant>,
+}
+
+fn default_error_status() -> u16 {
+    503
+}
+
+/// 在路由上挂载故障演练接口与 HLS 故障注入中间件
+pub fn routes(router: Router<SharedState>) -> Router<SharedState> {
+    warn!("Debug endpoints enabled (built with the `debug` feature); do not use in production");
+    router
+        .route("/debug/streams/:name/kill", post(kill_stream))
+        .route("/debug/streams/:name/stall", post(stall_stream))
+        .route("/debug/streams/:name/resume", post(resume_stream))
+        .route(
+            "/debug/hls/faults",
+            get(get_faults).put(put_faults).delete(clear_faults),
+        )
+        .layer(middleware::from_fn(inject))
+}
+
+fn require_admin(principal: &Principal) -> Result<(), (StatusCode, String)> {
+    if *principal != Principal::Admin {
+        return Err((StatusCode::FORBIDDEN, "Admin token required".to_string()));
+    }
+    Ok(())
+}
+
+/// 运行中流的 FFmpeg 进程号
+fn running_pid(state: &SharedState, name: &str) -> Result<(String, u32), (StatusCode, String)> {
+    let name = state
+        .config()
+        .lookup(name)
+        .map_or_else(|| name.to_string(), |s| s.name.clone());
+    let pid = state
+        .active_streams
+        .lock_or_recover()
+        .get(&name)
+        .and_then(|r| r.process.id());
+    match pid {
+        Some(pid) => Ok((name, pid)),
+        None => Err((
+            StatusCode::CONFLICT,
+            format!("Stream [{}] is not running", name),
+        )),
+    }
+}
+
+/// 结束进程 API
+/// 以 SIGKILL 结束流的 FFmpeg 进程 (不经正常停止流程)，由 Supervisor 按崩溃处理
+pub async fn kill_stream(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    require_admin(&principal)?;
+    let (name, pid) = running_pid(&state, &name)?;
+    let killed = state
+        .active_streams
+        .lock_or_recover()
+        .get_mut(&name)
+        .map(|r| r.process.start_kill());
+    match killed {
+        Some(Ok(())) => {
+            warn!("Debug: killed FFmpeg of stream [{}] (pid {})", name, pid);
+            Ok(format!("Stream [{}] FFmpeg (pid {}) killed", name, pid))
+        }
+        Some(Err(e)) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        None => Err((
+            StatusCode::CONFLICT,
+            format!("Stream [{}] is not running", name),
+        )),
+    }
+}
+
+/// 暂停参数
+#[derive(Debug, Deserialize)]
+pub struct StallQuery {
+    /// 暂停时长 (秒)，到期后自动恢复
+    duration_sec: Option<u64>,
+}
+
+/// 暂停进程 API
+/// 暂停流的 FFmpeg 进程 (SIGSTOP)，停止输出但保留进程，用于验证卡死检测；到期后自动恢复
+pub async fn stall_stream(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+    Query(query): Query<StallQuery>,
+) -> Result<String, (StatusCode, String)> {
+    require_admin(&principal)?;
+    let (name, pid) = running_pid(&state, &name)?;
+    let duration = query
+        .duration_sec
+        .unwrap_or(DEFAULT_STALL_SEC)
+        .clamp(1, MAX_STALL_SEC);
+    platform::set_paused(pid, true).map_err(|e| (StatusCode::NOT_IMPLEMENTED, e.to_string()))?;
+    let generation = {
+        let mut stalls = STALLS.lock_or_recover();
+        let generation = stalls
+            .get_or_insert_with(HashMap::new)
+            .entry(name.clone())
+            .or_insert(0);
+        *generation += 1;
+        *generation
+    };
+    warn!(
+        "Debug: paused FFmpeg of stream [{}] (pid {}) for {}s",
+        name, pid, duration
+    );
+    let message = format!(
+        "Stream [{}] FFmpeg (pid {}) paused for {}s",
+        name, pid, duration
+    );
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(duration)).await;
+        let current = STALLS
+            .lock_or_recover()
+            .as_ref()
+            .and_then(|s| s.get(&name).copied());
+        // 期间被手动恢复或再次暂停时由后者负责
+        if current == Some(generation) {
+            resume(&state, &name, pid);
+        }
+    });
+    Ok(message)
+}
+
+/// 恢复进程 API
+/// 提前恢复被暂停的 FFmpeg 进程 (SIGCONT)
+pub async fn resume_stream(
+    State(state): State<SharedState>,
+    ApiPrincipal(principal): ApiPrincipal,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    require_admin(&principal)?;
+    let (name, pid) = running_pid(&state, &name)?;
+    if let Some(stalls) = STALLS.lock_or_recover().as_mut() {
+        stalls.remove(&name);
+    }
+    platform::set_paused(pid, false).map_err(|e| (StatusCode::NOT_IMPLEMENTED, e.to_string()))?;
+    info!("Debug: resumed FFmpeg of stream [{}] (pid {})", name, pid);
+    Ok(format!("Stream [{}] FFmpeg (pid {}) resumed", name, pid))
+}
+
+/// 恢复暂停的进程 (进程已被重启时不做处理)
+fn resume(state: &SharedState, name: &str, pid: u32) {
+    if let Some(stalls) = STALLS.lock_or_recover().as_mut() {
+        stalls.remove(name);
+    }
+    let running = state
+        .active_streams
+        .lock_or_recover()
+        .get(name)
+        .and_then(|r| r.process.id());
+    if running != Some(pid) {
+        return;
+    }
+    match platform::set_paused(pid, false) {
+        Ok(()) => info!("Debug: resumed FFmpeg of stream [{}] (pid {})", name, pid),
+        Err(e) => warn!("Debug: failed to resume stream [{}]: {}", name, e),
+    }
+}
+
+/// 查询 HLS 故障注入 API
+pub async fn get_faults(
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Result<Json<Option<HlsFaults>>, (StatusCode, String)> {
+    require_admin(&principal)?;
+    Ok(Json(current_faults()))
+}
+
+/// 设置 HLS 故障注入 API
+/// 替换当前规则：按概率返回错误状态码，其余应答附加延迟
+pub async fn put_faults(
+    ApiPrincipal(principal): ApiPrincipal,
+    Json(rules): Json<HlsFaults>,
+) -> Result<Json<HlsFaults>, (StatusCode, String)> {
+    require_admin(&principal)?;
+    if !(0.0..=1.0).contains(&rules.error_rate) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "error_rate must be between 0 and 1".to_string(),
+        ));
+    }
+    if StatusCode::from_u16(rules.error_status).map_or(true, |s| s.as_u16() < 400) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "error_status must be a 4xx or 5xx status".to_string(),
+        ));
+    }
+    warn!(
+        "Debug: HLS fault injection set (stream: {}, latency {}ms, error rate {})",
+        rules.stream.as_deref().unwrap_or("all"),
+        rules.latency_ms,
+        rules.error_rate
+    );
+    *FAULTS.lock_or_recover() = Some(ActiveFaults {
+        until: rules
+            .duration_sec
+            .map(|sec| Instant::now() + Duration::from_secs(sec)),
+        rules: rules.clone(),
+    });
+    Ok(Json(rules))
+}
+
+/// 解除 HLS 故障注入 API
+pub async fn clear_faults(
+    ApiPrincipal(principal): ApiPrincipal,
+) -> Result<String, (StatusCode, String)> {
+    require_admin(&principal)?;
+    FAULTS.lock_or_recover().take();
+    info!("Debug: HLS fault injection cleared");
+    Ok("HLS fault injection cleared".to_string())
+}
+
+/// 当前生效的规则，到期的规则在此时清除
+fn current_faults() -> Option<HlsFaults> {
+    let mut faults = FAULTS.lock_or_recover();
+    if faults
+        .as_ref()
+        .is_some_and(|f| f.until.is_some_and(|until| Instant::now() >= until))
+    {
+        *faults = None;
+        info!("Debug: HLS fault injection expired");
+    }
+    faults.as_ref().map(|f| f.rules.clone())
+}
+
+/// 路由中间件：对匹配规则的 HLS 请求注入延迟或错误
+async fn inject(req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    let Some(rest) = path.strip_prefix("/hls/") else {
+        return next.run(req).await;
+    };
+    let Some(rules) = current_faults() else {
+        return next.run(req).await;
+    };
+    // `/hls/:stream/:file` 或 `/hls/:tenant/:stream/:file`
+    let segments: Vec<&str> = rest.split('/').collect();
+    let (dirs, file) = segments.split_at(segments.len().saturating_sub(1));
+    let file = file.first().copied().unwrap_or_default();
+    let stream_matches = rules.stream.as_deref().is_none_or(|s| dirs.contains(&s));
+    let target_matches = match rules.target {
+        FaultTarget::All => true,
+        FaultTarget::Playlist => file.ends_with(".m3u8"),
+        FaultTarget::Segment => !file.ends_with(".m3u8") && file != "key",
+    };
+    if !stream_matches || !target_matches {
+        return next.run(req).await;
+    }
+
+    let delay = rules.latency_ms + random_below(rules.jitter_ms + 1);
+    if delay > 0 {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+    if rules.error_rate > 0.0 && (random_below(1_000_000) as f64) < rules.error_rate * 1e6 {
+        let status =
+            StatusCode::from_u16(rules.error_status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+        return (status, "Injected fault").into_response();
+    }
+    next.run(req).await
+}
+
+/// [0, bound) 内的随机数 (bound 为 0 时为 0)
+fn random_below(bound: u64) -> u64 {
+    if bound <= 1 {
+        return 0;
+    }
+    // 只用于延迟抖动与错误注入，随机源不可用时不注入
+    let mut bytes = [0u8; 8];
+    if keys::random_bytes(&mut bytes).is_err() {
+        return 0;
+    }
+    u64::from_le_bytes(bytes) % bound
+}
@@ -1,21 +1,211 @@
+use crate::analytics::{self, Fetch};
+use crate::auth;
+use crate::bandwidth::{self, EgressMeter, RateLimiter};
+use crate::config::{ColdStart, StreamConfig};
+use crate::drain;
 use crate::engine::Engine;
-use crate::state::SharedState;
+use crate::forward_auth::PlaybackRequest;
+use crate::jwt;
+use crate::latency;
+use crate::markers;
+use crate::metrics::{self, Milestone};
+use crate::playback;
+use crate::playlist;
+use crate::proxy;
+use crate::segment_index::{self, Segment};
+use crate::sessions;
+use crate::state::{LockExt, SharedState};
+use crate::transfer;
+use crate::watermark;
 use axum::{
-    body::Body,
-    extract::{Path, State},
-    http::{header, Response, StatusCode},
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Uri},
 };
-use std::path::PathBuf;
-use std::time::Duration;
-use tokio::fs::File;
-use tokio_util::io::ReaderStream;
-use tracing::{error, info};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Delay before re-reading a playlist that looked truncated
+const PLAYLIST_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Interval between checks for the playlist while a cold stream starts up
+const COLD_START_POLL: Duration = Duration::from_millis(200);
+
+/// Chunk size for in-memory bodies (playlists, proxied responses)
+const MEMORY_CHUNK: usize = 16 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct FileQuery {
+    /// Viewer token, forwarded into rewritten segment URIs (`playlist.segment_query`)
+    pub token: Option<String>,
+    /// Watermark code carried by personalized segment URIs
+    pub wm: Option<String>,
+}
+
+/// Per-request viewer context
+struct Viewer {
+    id: String,
+    ip: String,
+    agent: String,
+    token: Option<String>,
+    wm: Option<String>,
+}
+
+impl Viewer {
+    fn new(state: &SharedState, headers: &HeaderMap, peer: SocketAddr, query: FileQuery) -> Self {
+        Self {
+            id: sessions::viewer_id(state, "hls", headers, peer),
+            ip: sessions::client_ip(state, headers, peer),
+            agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            token: auth::extract_token(headers, query.token.as_deref()),
+            wm: query.wm,
+        }
+    }
+}
 
 pub async fn serve_hls_file(
     State(state): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     Path((stream_name, file_name)): Path<(String, String)>,
+    Query(query): Query<FileQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let viewer = Viewer::new(&state, &headers, peer, query);
+    serve_file(state, None, stream_name, file_name, viewer, &uri, &headers).await
+}
+
+pub async fn serve_tenant_hls_file(
+    State(state): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path((tenant, stream_name, file_name)): Path<(String, String, String)>,
+    Query(query): Query<FileQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let viewer = Viewer::new(&state, &headers, peer, query);
+    serve_file(
+        state,
+        Some(tenant),
+        stream_name,
+        file_name,
+        viewer,
+        &uri,
+        &headers,
+    )
+    .await
+}
+
+/// Resolve a stream (by name, stable id or alias) inside the requested namespace.
+/// Streams of one tenant are invisible under another tenant's (or the default) path.
+pub(super) fn resolve_stream(
+    state: &SharedState,
+    tenant: Option<&str>,
+    stream_name: &str,
+) -> Result<StreamConfig, (StatusCode, String)> {
+    state
+        .config()
+        .lookup(stream_name)
+        .filter(|cfg| cfg.tenant.as_deref() == tenant)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Stream not found".to_string()))
+}
+
+async fn serve_file(
+    state: SharedState,
+    tenant: Option<String>,
+    stream_name: String,
+    file_name: String,
+    viewer: Viewer,
+    uri: &Uri,
+    headers: &HeaderMap,
 ) -> Result<Response<Body>, (StatusCode, String)> {
-    // 1. Trigger stream startup logic for .m3u8 or keep-alive logic for .ts
+    let cfg = resolve_stream(&state, tenant.as_deref(), &stream_name)?;
+    let stream_name = cfg.name.clone();
+
+    // Reject anything that could escape the stream's output directory
+    if file_name.contains(['/', '\\']) || file_name.contains("..") {
+        return Err((StatusCode::BAD_REQUEST, "Invalid file name".to_string()));
+    }
+
+    // While draining, playlists are refused so players move away once their buffer runs out;
+    // segments they already know about are still served
+    if file_name.ends_with(".m3u8") {
+        if let Some(res) = drain::rejection(&state, uri) {
+            return Ok(res);
+        }
+    }
+
+    // Maintenance, hotlinking and geo restrictions, signed playback tokens (forwarded to
+    // segments through the rewritten playlist) and external entitlements are checked before
+    // anything is started or served
+    playback::admit(
+        &state,
+        &cfg,
+        headers,
+        PlaybackRequest {
+            client_ip: &viewer.ip,
+            token: viewer.token.as_deref(),
+            path: uri.path(),
+            protocol: "hls",
+        },
+    )
+    .await?;
+
+    let limiters = bandwidth::limiters_for(&state, &cfg);
+    let meter = bandwidth::meter_for(&state, &cfg.name);
+    // Playback analytics: the transfer is counted into the viewer's session once the body ends
+    let fetch = || {
+        analytics::begin(
+            &state,
+            analytics::Request {
+                stream: &stream_name,
+                tenant: cfg.tenant.as_deref(),
+                protocol: "hls",
+                viewer: &viewer.id,
+                client_ip: &viewer.ip,
+                user_agent: &viewer.agent,
+            },
+            !file_name.ends_with(".m3u8"),
+        )
+    };
+
+    // Proxied streams have no local process: serve from the upstream origin
+    if cfg.is_proxied() {
+        let res = serve_proxied(&state, &cfg, &file_name, &viewer, limiters, meter, fetch()).await;
+        if file_name.ends_with(".m3u8") {
+            sessions::touch(&state, &stream_name, &viewer.id);
+        }
+        return res.map(|res| with_configured_headers(&state, &cfg, res));
+    }
+
+    // Watermarked streams only expose the personalized playlist and verified A/B segments
+    let secure = cfg.watermark.is_some() && file_name == watermark::SECURE_PLAYLIST;
+    if let Some(wm) = &cfg.watermark {
+        if watermark::is_variant_playlist(&file_name) {
+            return Err((StatusCode::NOT_FOUND, "File not found".to_string()));
+        }
+        if wm.exclusive && file_name.ends_with(".m3u8") && !secure {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("Stream is only available as {}", watermark::SECURE_PLAYLIST),
+            ));
+        }
+        if watermark::is_variant_segment(&file_name)
+            && !watermark::check_segment(&state, &stream_name, &file_name, viewer.wm.as_deref())
+        {
+            return Err((StatusCode::FORBIDDEN, "Invalid watermark code".to_string()));
+        }
+    }
+
+    // 1. Trigger stream startup for .m3u8; .ts files are only served while running
     if file_name.ends_with(".m3u8") {
         // Start stream if it's a .m3u8 file
         Engine::start_stream(&state, &stream_name)
@@ -23,58 +213,315 @@ pub async fn serve_hls_file(
             .map_err(|e| {
                 // Log error if stream startup fails
                 error!("Failed to auto-start stream: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                e
             })?;
-    } else {
-        // For .ts files, refresh the stream's last access time
-        let mut streams = state.active_streams.lock().unwrap();
-        if let Some(running) = streams.get_mut(&stream_name) {
-            running.last_accessed = std::time::Instant::now();
-        } else {
-            // Return an error if the stream is not running
-            return Err((StatusCode::NOT_FOUND, "Stream not running".to_string()));
-        }
+        // Playlist polling is what keeps a viewer session alive
+        sessions::touch(&state, &stream_name, &viewer.id);
+    } else if !state
+        .active_streams
+        .lock_or_recover()
+        .contains_key(&stream_name)
+    {
+        // Segment requests alone never keep a stream alive
+        return Err((StatusCode::NOT_FOUND, "Stream not running".to_string()));
     }
 
     // 2. Construct the file path (reading from the configured HLS Root directory, supports RAMDisk)
-    let mut file_path = PathBuf::from(&state.config.server.hls_root);
-    file_path.push(&stream_name);
-    file_path.push(&file_name);
+    let source_name = if secure {
+        watermark::source_playlist()
+    } else {
+        &file_name
+    };
+    let output_dir = Engine::output_dir(&state, &cfg);
+    let file_path = output_dir.join(source_name);
+    // The output directory index answers existence, mtime and playlist lookups from memory
+    let index = segment_index::get(&state, &cfg.name, &output_dir);
 
-    // 3. Smartly wait for the .m3u8 file to be generated (only applicable for .m3u8)
-    if file_name.ends_with(".m3u8") {
-        for i in 0..15 {
-            // Break if the file exists
-            if file_path.exists() {
-                break;
-            }
-            if i == 0 {
-                // Log that we're waiting for HLS generation
+    // 3. Cold start: either wait for the .m3u8 file to be generated (up to cold_start_wait_ms),
+    //    or answer at once with a segment-less priming playlist so the player keeps polling
+    if file_name.ends_with(".m3u8") && !index.exists(source_name) {
+        match cfg.cold_start {
+            ColdStart::Wait => {
                 info!("Waiting for HLS generation: {:?}", file_path);
+                let deadline = Instant::now() + Duration::from_millis(cfg.cold_start_wait_ms);
+                while !index.exists(source_name) && Instant::now() < deadline {
+                    tokio::time::sleep(COLD_START_POLL).await;
+                }
+            }
+            ColdStart::Priming => {
+                let res = Response::builder()
+                    .header(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")
+                    .header(header::CACHE_CONTROL, "no-cache")
+                    .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                    .body(Body::from(playlist::priming()))
+                    .unwrap();
+                return Ok(with_configured_headers(&state, &cfg, res));
             }
-            // Wait before retrying
-            tokio::time::sleep(Duration::from_millis(200)).await;
         }
     }
 
-    // 4. Open the file for reading
-    let file = File::open(&file_path)
-        .await
-        .map_err(|_| (StatusCode::NOT_FOUND, "File not found".to_string()))?;
-
-    // 5. Determine the Content-Type based on the file extension
+    // 4. Determine the Content-Type based on the file extension
     let content_type = mime_guess::from_path(&file_path)
         .first_or_octet_stream()
         .to_string();
 
-    // Create a stream from the file
-    let stream = ReaderStream::new(file);
-    let body = Body::from_stream(stream);
+    // 5. Playlists are rewritten in place by FFmpeg, so they are read fully and checked;
+    //    segments are sent by a guarded sender that stops as soon as the client
+    //    disconnects or stalls. Both are shaped by the configured bandwidth limits
+    let mut content_length = None;
+    let body = if file_name.ends_with(".m3u8") {
+        let mut playlist = match index.playlist(source_name) {
+            Some(text) => text,
+            None => read_playlist(&file_path)
+                .await
+                .map_err(|_| (StatusCode::NOT_FOUND, "File not found".to_string()))?,
+        };
+        // The main playlist as FFmpeg wrote it dates the live edge for the latency estimate
+        if source_name == "index.m3u8" || secure {
+            latency::observe(&state, &stream_name, &playlist, &index);
+        }
+        if cfg.program_date_time {
+            playlist = playlist::add_program_date_time(&playlist, &index);
+        }
+        if secure {
+            let identity = watermark::identity(viewer.token.as_deref(), &viewer.id);
+            let code = watermark::assign(&state, &cfg, &identity).await;
+            playlist = watermark::personalize(&playlist, code);
+        }
+
+        // A served playlist implies the first segment exists, even if FFmpeg logs were quiet
+        metrics::record(&state, &stream_name, Milestone::FirstSegment);
+        metrics::record(&state, &stream_name, Milestone::FirstPlaylist);
+        let playlist = markers::apply(&state, &cfg, &playlist);
+        let playlist = playlist::rewrite(&playlist, &cfg, viewer.token.as_deref());
+        memory_body(Bytes::from(playlist), limiters, meter, fetch())
+    } else {
+        // Finished segments come from the shared cache: read once, no per-viewer reads or
+        // buffers. Others are streamed from the file
+        let segment = index
+            .segment(source_name)
+            .await
+            .map_err(|_| (StatusCode::NOT_FOUND, "File not found".to_string()))?;
+        match segment {
+            Segment::Cached(data) => {
+                content_length = Some(data.len());
+                shaped_body(
+                    transfer::guarded_bytes(data, state.transfers.clone()),
+                    limiters,
+                    meter,
+                    fetch(),
+                )
+            }
+            Segment::File(file) => shaped_body(
+                transfer::guarded_file(file, state.transfers.clone()),
+                limiters,
+                meter,
+                fetch(),
+            ),
+        }
+    };
 
     // Return the response with appropriate headers and the file content
-    Ok(Response::builder()
+    let mut res = Response::builder()
         .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    if let Some(len) = content_length {
+        res = res.header(header::CONTENT_LENGTH, len);
+    }
+    Ok(with_configured_headers(
+        &state,
+        &cfg,
+        res.body(body).unwrap(),
+    ))
+}
+
+/// Add the headers from `server.hls_headers` and the stream's `hls_headers` (the stream wins).
+/// Configured headers replace the built-in ones of the same name, e.g. a stricter CORS origin
+fn with_configured_headers(
+    state: &SharedState,
+    cfg: &StreamConfig,
+    mut res: Response<Body>,
+) -> Response<Body> {
+    let config = state.config();
+    let headers = res.headers_mut();
+    for (name, value) in config.server.hls_headers.iter().chain(&cfg.hls_headers) {
+        // Both were checked when the config was loaded
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+    res
+}
+
+/// Serve a proxied stream: playlists are rewritten from upstream, segments come from the RAMDisk cache
+async fn serve_proxied(
+    state: &SharedState,
+    cfg: &StreamConfig,
+    file_name: &str,
+    viewer: &Viewer,
+    limiters: Vec<Arc<RateLimiter>>,
+    meter: Arc<EgressMeter>,
+    fetch: Option<Fetch>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let mut res = proxy::serve(state, cfg, file_name).await.map_err(|e| {
+        error!("Proxy fetch failed [{}/{}]: {}", cfg.name, file_name, e);
+        (StatusCode::BAD_GATEWAY, e.to_string())
+    })?;
+    if file_name.ends_with(".m3u8") {
+        let text = markers::apply(state, cfg, &String::from_utf8_lossy(&res.body));
+        res.body = playlist::rewrite(&text, cfg, viewer.token.as_deref()).into_bytes();
+    }
+
+    let body = memory_body(Bytes::from(res.body), limiters, meter, fetch);
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, res.content_type)
         .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
         .body(body)
         .unwrap())
 }
+
+/// Read a playlist fully, retrying once if it looks truncated (FFmpeg rewrote it mid-read)
+async fn read_playlist(path: &std::path::Path) -> std::io::Result<String> {
+    let text = tokio::fs::read_to_string(path).await?;
+    if playlist_complete(&text) {
+        return Ok(text);
+    }
+    tokio::time::sleep(PLAYLIST_RETRY_DELAY).await;
+    let text = tokio::fs::read_to_string(path).await?;
+    if !playlist_complete(&text) {
+        warn!("Serving a possibly truncated playlist: {:?}", path);
+    }
+    Ok(text)
+}
+
+/// A complete playlist starts with `#EXTM3U`, ends with a newline
+/// and has a URI line after every `#EXTINF`
+fn playlist_complete(text: &str) -> bool {
+    if !text.starts_with("#EXTM3U") || !text.ends_with('\n') {
+        return false;
+    }
+    let mut expect_uri = false;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line.starts_with("#EXTINF") {
+            if expect_uri {
+                return false;
+            }
+            expect_uri = true;
+        } else if !line.starts_with('#') {
+            expect_uri = false;
+        }
+    }
+    !expect_uri
+}
+
+/// Wrap an in-memory payload into a response body, chunked so bandwidth limits still apply
+fn memory_body(
+    data: Bytes,
+    limiters: Vec<Arc<RateLimiter>>,
+    meter: Arc<EgressMeter>,
+    fetch: Option<Fetch>,
+) -> Body {
+    let chunks: Vec<Result<Bytes, std::io::Error>> = (0..data.len())
+        .step_by(MEMORY_CHUNK)
+        .map(|i| Ok(data.slice(i..(i + MEMORY_CHUNK).min(data.len()))))
+        .collect();
+    shaped_body(futures_util::stream::iter(chunks), limiters, meter, fetch)
+}
+
+/// Wrap a byte stream into a response body, counting egress (and the viewer session's bytes
+/// when analytics is enabled) and applying bandwidth limits when configured
+pub(super) fn shaped_body<S>(
+    stream: S,
+    limiters: Vec<Arc<RateLimiter>>,
+    meter: Arc<EgressMeter>,
+    fetch: Option<Fetch>,
+) -> Body
+where
+    S: futures_util::Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin + 'static,
+{
+    let stream = bandwidth::metered(analytics::tracked(stream, fetch), meter);
+    if limiters.is_empty() {
+        Body::from_stream(stream)
+    } else {
+        Body::from_stream(bandwidth::throttle(stream, limiters))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyQuery {
+    /// Key id referenced by the playlist's EXT-X-KEY URI
+    pub id: Option<u64>,
+    /// Access token for players that cannot set an Authorization header
+    pub token: Option<String>,
+}
+
+/// Serve the AES-128 key of an encrypted stream to authorized players
+pub async fn serve_hls_key(
+    State(state): State<SharedState>,
+    Path(stream_name): Path<String>,
+    Query(query): Query<KeyQuery>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    serve_key(state, None, stream_name, query, headers).await
+}
+
+pub async fn serve_tenant_hls_key(
+    State(state): State<SharedState>,
+    Path((tenant, stream_name)): Path<(String, String)>,
+    Query(query): Query<KeyQuery>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    serve_key(state, Some(tenant), stream_name, query, headers).await
+}
+
+async fn serve_key(
+    state: SharedState,
+    tenant: Option<String>,
+    stream_name: String,
+    query: KeyQuery,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    // 1. Only authorized players of the owning tenant may fetch keys
+    let token = auth::extract_token(&headers, query.token.as_deref());
+    let principal = auth::authenticate(&state, token.as_deref());
+    if principal
+        .as_ref()
+        .is_some_and(|p| !p.can_access(tenant.as_deref()))
+    {
+        return Err((StatusCode::FORBIDDEN, "Forbidden".to_string()));
+    }
+    let stream_name = match principal {
+        Some(_) => resolve_stream(&state, tenant.as_deref(), &stream_name)?.name,
+        // A JWT playback token granting this stream also unlocks its keys
+        None => {
+            let config = state.config();
+            let (Some(jwt), Some(token)) = (&config.jwt, token.as_deref()) else {
+                return Err((StatusCode::UNAUTHORIZED, "Unauthorized".to_string()));
+            };
+            let name = resolve_stream(&state, tenant.as_deref(), &stream_name)?.name;
+            jwt::verify(&state, jwt, token, &name).await?;
+            name
+        }
+    };
+
+    // 2. Look up the requested key in the stream's keyring
+    let key = {
+        let key_map = state.stream_keys.lock_or_recover();
+        key_map
+            .get(&stream_name)
+            .and_then(|keyring| keyring.get(query.id))
+    }
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Key not found".to_string()))?;
+
+    // Keys must never be cached by intermediaries
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(key.to_vec()))
+        .unwrap())
+}
@@ -1,2 +1,5 @@
 pub mod admin;
+#[cfg(feature = "debug")]
+pub mod debug;
 pub mod hls;
+pub mod ts;
@@ -0,0 +1,158 @@
+use super::hls::{resolve_stream, shaped_body};
+use crate::analytics;
+use crate::auth;
+use crate::bandwidth;
+use crate::drain;
+use crate::engine::Engine;
+use crate::forward_auth::PlaybackRequest;
+use crate::playback;
+use crate::sessions;
+use crate::state::SharedState;
+use crate::ts;
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, Response, StatusCode, Uri},
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::error;
+
+/// How often an open connection refreshes its viewer session
+const TOUCH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+pub struct TsQuery {
+    /// Viewer token for players that cannot set an Authorization header
+    pub token: Option<String>,
+}
+
+pub async fn serve_ts(
+    State(state): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(stream_name): Path<String>,
+    Query(query): Query<TsQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    serve_stream(state, None, stream_name, peer, headers, query, &uri).await
+}
+
+pub async fn serve_tenant_ts(
+    State(state): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path((tenant, stream_name)): Path<(String, String)>,
+    Query(query): Query<TsQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    serve_stream(state, Some(tenant), stream_name, peer, headers, query, &uri).await
+}
+
+/// Serve the live feed as one continuous MPEG-TS response (chunked transfer)
+async fn serve_stream(
+    state: SharedState,
+    tenant: Option<String>,
+    stream_name: String,
+    peer: SocketAddr,
+    headers: HeaderMap,
+    query: TsQuery,
+    uri: &Uri,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let cfg = resolve_stream(&state, tenant.as_deref(), &stream_name)?;
+    let stream_name = cfg.name.clone();
+    if !cfg.ts_output {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "MPEG-TS output is not enabled for this stream".to_string(),
+        ));
+    }
+
+    // New connections are refused while draining; open ones keep running
+    if let Some(res) = drain::rejection(&state, uri) {
+        return Ok(res);
+    }
+
+    // Maintenance, embedding site, geo restrictions, playback tokens and external
+    // entitlements are checked once per connection
+    let client_ip = sessions::client_ip(&state, &headers, peer);
+    let token = auth::extract_token(&headers, query.token.as_deref());
+    playback::admit(
+        &state,
+        &cfg,
+        &headers,
+        PlaybackRequest {
+            client_ip: &client_ip,
+            token: token.as_deref(),
+            path: uri.path(),
+            protocol: "ts",
+        },
+    )
+    .await?;
+
+    // Start the stream on demand, exactly like a playlist request
+    Engine::start_stream(&state, &stream_name)
+        .await
+        .map_err(|e| {
+            error!("Failed to auto-start stream: {}", e);
+            e
+        })?;
+    let rx = ts::subscribe(&state, &stream_name).ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Stream output not available".to_string(),
+    ))?;
+
+    let limiters = bandwidth::limiters_for(&state, &cfg);
+    let meter = bandwidth::meter_for(&state, &stream_name);
+
+    // The open connection counts as one viewer session
+    let viewer = sessions::viewer_id(&state, "ts", &headers, peer);
+    sessions::touch(&state, &stream_name, &viewer);
+    let fetch = analytics::begin(
+        &state,
+        analytics::Request {
+            stream: &stream_name,
+            tenant: cfg.tenant.as_deref(),
+            protocol: "ts",
+            viewer: &viewer,
+            client_ip: &client_ip,
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default(),
+        },
+        false,
+    );
+
+    // Lagging viewers skip ahead; the connection ends when the process exits
+    let feed = futures_util::stream::unfold(
+        (rx, state, stream_name, viewer, Instant::now()),
+        |(mut rx, state, name, viewer, mut touched)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(chunk) => {
+                        if touched.elapsed() >= TOUCH_INTERVAL {
+                            sessions::touch(&state, &name, &viewer);
+                            touched = Instant::now();
+                        }
+                        return Some((
+                            Ok::<Bytes, std::io::Error>(chunk),
+                            (rx, state, name, viewer, touched),
+                        ));
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "video/mp2t")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(shaped_body(Box::pin(feed), limiters, meter, fetch))
+        .unwrap())
+}